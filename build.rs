@@ -34,8 +34,8 @@ fn get_naga_bin_path() -> Option<PathBuf> {
         println!("Naga not founded! Auto install, auto install...`");
         println!("cargo install naga-cli --root . --no-track");
         let _ = Command::new("cargo")
-            .args(&["install", "naga-cli"])
-            .args(&["--root", ".", "--no-track"])
+            .args(["install", "naga-cli"])
+            .args(["--root", ".", "--no-track"])
             .exec();
         println!("Naga installed! {}", naga_bin_path.to_str().unwrap());
     }
@@ -50,6 +50,29 @@ fn get_naga_bin_path() -> Option<PathBuf> {
     Some(naga_bin_path)
 }
 
+fn compile_wgsl(input: &str, output: &str) {
+    let naga_bin_path = get_naga_bin_path().unwrap();
+    let status = Command::new(&naga_bin_path)
+        .args([input, output, "--keep-coordinate-space"])
+        .status()
+        .expect("failed to run naga!");
+    assert!(status.success(), "failed to compile shader {}", input);
+}
+
+fn get_glslc_bin_path() -> Option<PathBuf> {
+    // glslc ships with the Vulkan SDK, naga can't compile tessellation or geometry stages.
+    let sdk_bin = env::var_os("VULKAN_SDK").map(|sdk| Path::new(&sdk).join("bin").join("glslc"));
+    if let Some(path) = sdk_bin.filter(|path| path.is_file()) {
+        return Some(path);
+    }
+
+    env::var_os("PATH").and_then(|paths| {
+        env::split_paths(&paths)
+            .map(|dir| dir.join("glslc"))
+            .find(|path| path.is_file())
+    })
+}
+
 fn main() {
     println!("build.rs is running!");
 
@@ -58,24 +81,59 @@ fn main() {
     let root_path = Path::new(&root_dir);
     let out_path = Path::new(&out_dir);
     let wgsl_ext_reg = Regex::new(r"\.wgsl$").unwrap();
+    let glsl_ext_reg = Regex::new(r"\.(vert|tesc|tese|geom|frag|comp)$").unwrap();
 
     let shader_dir = root_path.join("src").join("shaders");
     let shader_out_dir = out_path.join("shaders");
     fs::create_dir_all(&shader_out_dir).unwrap();
     visit_files(&shader_dir, &|path| {
         let relative = path.strip_prefix(&shader_dir).unwrap().to_str().unwrap();
-        let result = wgsl_ext_reg.replace(&relative, ".spv");
-        let output_path = shader_out_dir.join(result.as_ref());
-
         let input = path.to_str().unwrap();
-        let output = output_path.to_str().unwrap();
 
-        let naga_bin_path = get_naga_bin_path().unwrap();
-        let _ = Command::new(&naga_bin_path)
-            .args(&[input, output, "--keep-coordinate-space"])
-            .exec();
+        if wgsl_ext_reg.is_match(relative) {
+            let result = wgsl_ext_reg.replace(relative, ".spv");
+            let output_path = shader_out_dir.join(result.as_ref());
+            let output = output_path.to_str().unwrap();
+
+            compile_wgsl(input, output);
+            println!("Shader Output: {}", output);
 
-        println!("Shader Output: {}", output);
+            // Passes reading the multisampled depth also get a variant for MSAA off, where the
+            // depth has a single sample: decal.wgsl -> decal_single_sample.spv
+            let source = fs::read_to_string(path).unwrap();
+            if source.contains("texture_depth_multisampled_2d") {
+                let variant = wgsl_ext_reg.replace(relative, "_single_sample");
+                let variant_input_path = out_path.join(format!("{}.wgsl", variant));
+                fs::create_dir_all(variant_input_path.parent().unwrap()).unwrap();
+                fs::write(
+                    &variant_input_path,
+                    source.replace("texture_depth_multisampled_2d", "texture_depth_2d"),
+                )
+                .unwrap();
+                let variant_output_path = shader_out_dir.join(format!("{}.spv", variant));
+                let variant_output = variant_output_path.to_str().unwrap();
+                compile_wgsl(variant_input_path.to_str().unwrap(), variant_output);
+
+                println!("Shader Output: {}", variant_output);
+            }
+        } else if glsl_ext_reg.is_match(relative) {
+            // terrain.tesc -> terrain.tesc.spv
+            let output_path = shader_out_dir.join(format!("{}.spv", relative));
+            let output = output_path.to_str().unwrap();
+
+            let Some(glslc_bin_path) = get_glslc_bin_path() else {
+                // the stages are left out, see `Shading::is_available`
+                println!("cargo:warning=glslc not found, skip shader {}", relative);
+                return;
+            };
+            let status = Command::new(&glslc_bin_path)
+                .args([input, "-o", output])
+                .status()
+                .expect("failed to run glslc!");
+            assert!(status.success(), "failed to compile shader {}", input);
+
+            println!("Shader Output: {}", output);
+        }
     })
     .unwrap();
 
@@ -0,0 +1,95 @@
+// The math hot paths of a frame, run with and without the `simd` feature to compare:
+//   cargo bench -p mirage-core
+//   cargo bench -p mirage-core --features simd
+use mirage_core::math::{Aabb, Frustum, Mat4, Quat, Vec3};
+use std::hint::black_box;
+use std::time::Instant;
+
+const ITERATIONS: u32 = 1_000_000;
+// objects per frame in the transform and culling passes
+const OBJECTS: usize = 10_000;
+
+fn bench(name: &str, iterations: u32, mut run: impl FnMut()) {
+    for _ in 0..iterations / 10 {
+        run();
+    }
+    let start = Instant::now();
+    for _ in 0..iterations {
+        run();
+    }
+    let nanos = start.elapsed().as_nanos() as f64 / iterations as f64;
+    println!("{:<28} {:>12.2} ns/iter", name, nanos);
+}
+
+fn main() {
+    println!(
+        "simd: {}",
+        if cfg!(feature = "simd") { "on" } else { "off" }
+    );
+
+    let a = Mat4::compose(
+        Vec3::new(1.0, 2.0, 3.0),
+        Quat::from_axis_angle(Vec3::new(0.0, 1.0, 0.0), 0.7),
+        Vec3::new(2.0, 2.0, 2.0),
+    );
+    let b = Mat4::perspective_reversed_z_infinite_rh(1.0, 1.5, 0.1) * a.invert();
+    bench("mat4 * mat4", ITERATIONS, || {
+        black_box(black_box(a) * black_box(b));
+    });
+    bench("mat4 invert", ITERATIONS, || {
+        black_box(black_box(b).invert());
+    });
+    bench("mat4 transform_point", ITERATIONS, || {
+        black_box(black_box(a).transform_point(black_box(Vec3::new(1.0, 2.0, 3.0))));
+    });
+
+    // a parent matrix times the composed local one of every object
+    let locals = (0..OBJECTS)
+        .map(|index| {
+            let t = index as f32;
+            (
+                Vec3::new(t.sin() * 50.0, 0.0, t.cos() * 50.0),
+                Quat::from_axis_angle(Vec3::new(0.0, 1.0, 0.0), t),
+                Vec3::new(1.0, 1.0, 1.0),
+            )
+        })
+        .collect::<Vec<_>>();
+    let mut worlds = vec![Mat4::identity(); OBJECTS];
+    bench("transform propagation", 200, || {
+        for ((location, rotation, scale), world) in locals.iter().zip(worlds.iter_mut()) {
+            *world = a * Mat4::compose(*location, *rotation, *scale);
+        }
+        black_box(&worlds);
+    });
+
+    // the world bounds of every object against the camera frustum, as in the forward pass
+    let view = Mat4::look_at_rh(
+        Vec3::new(0.0, 10.0, 60.0),
+        Vec3::new(0.0, 0.0, 0.0),
+        Vec3::new(0.0, 1.0, 0.0),
+    );
+    let frustum = Frustum::from_matrix(&(Mat4::perspective_rh(1.0, 1.5, 0.1, 500.0) * view));
+    let bounds = Aabb::new(Vec3::new(-0.5, -0.5, -0.5), Vec3::new(0.5, 0.5, 0.5));
+    bench("aabb transform", 200, || {
+        for world in &worlds {
+            black_box(bounds.transform(world));
+        }
+    });
+    // the frustum test is scalar in both, a lane per plane measured no faster
+    let boxes = worlds
+        .iter()
+        .map(|world| bounds.transform(world))
+        .collect::<Vec<_>>();
+    bench("frustum test", 200, || {
+        for aabb in &boxes {
+            black_box(frustum.intersects_aabb(aabb));
+        }
+    });
+    bench("culling", 200, || {
+        let visible = worlds
+            .iter()
+            .filter(|world| frustum.intersects_aabb(&bounds.transform(world)))
+            .count();
+        black_box(visible);
+    });
+}
@@ -0,0 +1,180 @@
+use std::cell::RefCell;
+use std::fmt::Write;
+use std::path::PathBuf;
+use std::time::Instant;
+
+struct Event {
+    name: &'static str,
+    category: &'static str,
+    frame: u64,
+    start: Instant,
+    end: Instant,
+}
+
+struct Capture {
+    path: PathBuf,
+    frames_left: u32,
+    // false until the first frame begins, scopes opened before it would be cut in half
+    active: bool,
+    frame: u64,
+    started: Instant,
+    // scopes begun and not ended yet, innermost last
+    open: Vec<(&'static str, &'static str, Instant)>,
+    events: Vec<Event>,
+}
+
+thread_local! {
+    static CAPTURE: RefCell<Option<Capture>> = const { RefCell::new(None) };
+}
+
+// Records the nested CPU scopes of the next `frames` frames and writes them to `path` as a Chrome
+// trace, which about://tracing, Perfetto or speedscope show as a flame graph. Frames are marked by
+// `frame`, systems and passes open scopes of their own, anything else can with `scope`. Outside a
+// capture a scope costs a thread local lookup.
+pub fn start_capture(frames: u32, path: impl Into<PathBuf>) {
+    CAPTURE.with_borrow_mut(|capture| {
+        *capture = (frames > 0).then(|| Capture {
+            path: path.into(),
+            frames_left: frames,
+            active: false,
+            frame: 0,
+            started: Instant::now(),
+            open: vec![],
+            events: vec![],
+        });
+    });
+}
+
+pub fn is_capturing() -> bool {
+    CAPTURE.with_borrow(|capture| capture.is_some())
+}
+
+// Opens a scope that ends where the returned guard is dropped.
+pub fn scope(name: &'static str) -> Scope {
+    begin_scope(name, "scope");
+    Scope
+}
+
+// Wraps a whole frame, the capture is written once its last frame ends.
+pub fn frame() -> Frame {
+    CAPTURE.with_borrow_mut(|capture| {
+        let Some(capture) = capture else {
+            return;
+        };
+        if !capture.active {
+            capture.active = true;
+            capture.started = Instant::now();
+        }
+        capture.frame += 1;
+        capture.open.clear();
+    });
+    begin_scope("frame", "frame");
+    Frame
+}
+
+// For scopes that don't fit a guard, e.g. a pass begun and ended by different calls. Every begin
+// needs its end on the same thread.
+pub fn begin_scope(name: &'static str, category: &'static str) {
+    CAPTURE.with_borrow_mut(|capture| {
+        if let Some(capture) = capture.as_mut().filter(|capture| capture.active) {
+            capture.open.push((name, category, Instant::now()));
+        }
+    });
+}
+
+pub fn end_scope() {
+    CAPTURE.with_borrow_mut(|capture| {
+        let Some(capture) = capture.as_mut().filter(|capture| capture.active) else {
+            return;
+        };
+        let Some((name, category, start)) = capture.open.pop() else {
+            return;
+        };
+        capture.events.push(Event {
+            name,
+            category,
+            frame: capture.frame,
+            start,
+            end: Instant::now(),
+        });
+    });
+}
+
+pub struct Scope;
+
+impl Drop for Scope {
+    fn drop(&mut self) {
+        end_scope();
+    }
+}
+
+pub struct Frame;
+
+impl Drop for Frame {
+    fn drop(&mut self) {
+        end_scope();
+        let finished = CAPTURE.with_borrow_mut(|capture| {
+            let current = capture.as_mut()?;
+            if !current.active {
+                return None;
+            }
+            current.frames_left -= 1;
+            match current.frames_left {
+                0 => capture.take(),
+                _ => None,
+            }
+        });
+        if let Some(capture) = finished {
+            write_trace(&capture);
+        }
+    }
+}
+
+fn write_trace(capture: &Capture) {
+    let micros = |instant: Instant| instant.duration_since(capture.started).as_secs_f64() * 1e6;
+    let mut json = String::from("{\"displayTimeUnit\":\"ms\",\"traceEvents\":[\n");
+    for (index, event) in capture.events.iter().enumerate() {
+        if index > 0 {
+            json.push_str(",\n");
+        }
+        // complete events, the viewer nests them by their time ranges
+        let _ = write!(
+            json,
+            "{{\"name\":\"{}\",\"cat\":\"{}\",\"ph\":\"X\",\"ts\":{:.3},\"dur\":{:.3},\"pid\":1,\"tid\":1,\"args\":{{\"frame\":{}}}}}",
+            escape(event.name),
+            escape(event.category),
+            micros(event.start),
+            micros(event.end) - micros(event.start),
+            event.frame
+        );
+    }
+    json.push_str("\n]}\n");
+
+    match std::fs::write(&capture.path, json) {
+        Ok(()) => log::info!(
+            "wrote a trace of {} frames to {}",
+            capture.frame,
+            capture.path.display()
+        ),
+        Err(error) => log::error!(
+            "failed to write trace to {}: {}",
+            capture.path.display(),
+            error
+        ),
+    }
+}
+
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for char in text.chars() {
+        match char {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            char if char.is_control() => {
+                let _ = write!(escaped, "\\u{:04x}", char as u32);
+            }
+            char => escaped.push(char),
+        }
+    }
+    escaped
+}
@@ -0,0 +1,10 @@
+// Top level mode of the application. The scheduler runs the systems registered for the
+// current state only, plus the enter/exit hooks when it changes.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq, Hash)]
+pub enum AppState {
+    #[default]
+    Loading,
+    Menu,
+    Play,
+    Editor,
+}
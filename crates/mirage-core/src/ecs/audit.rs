@@ -0,0 +1,150 @@
+use crate::ecs::{Comp, Query, World};
+use crate::serialize::{SerializeComp, Value};
+use std::hash::Hasher;
+
+type CompHasher = Box<dyn Fn(&mut World, &mut StableHasher)>;
+
+// FNV-1a, unlike the std and ahash hashers it gives the same value in every process and on every
+// platform, so hashes of separate runs or machines can be compared.
+#[derive(Debug, Clone)]
+pub struct StableHasher(u64);
+
+impl StableHasher {
+    pub fn new() -> Self {
+        Self(0xcbf29ce484222325)
+    }
+}
+
+impl Default for StableHasher {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Hasher for StableHasher {
+    fn finish(&self) -> u64 {
+        self.0
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.0 ^= byte as u64;
+            self.0 = self.0.wrapping_mul(0x100000001b3);
+        }
+    }
+}
+
+// Checks that the simulation is deterministic, which replays and lockstep networking rely on. Set
+// on the scheduler, it hashes the tracked comps after every tick in the order queries visit them.
+// Two runs fed the same input must produce the same hashes, `first_divergence` finds the tick they
+// part at. The world warns about every place iterating it in hash order meanwhile, see
+// `World::entities`.
+pub struct DeterminismAudit {
+    hashers: Vec<(&'static str, CompHasher)>,
+    // one per tick since the audit was set
+    hashes: Vec<u64>,
+    // of an earlier run, a tick hashing differently is reported
+    reference: Option<Vec<u64>>,
+}
+
+impl DeterminismAudit {
+    pub fn new() -> Self {
+        Self {
+            hashers: vec![],
+            hashes: vec![],
+            reference: None,
+        }
+    }
+
+    // Compared against while running, the first tick that doesn't match is logged.
+    pub fn with_reference(mut self, hashes: Vec<u64>) -> Self {
+        self.reference = Some(hashes);
+        self
+    }
+
+    // Hashes the serialized fields of the comp, floats by their bits.
+    pub fn track<T: SerializeComp>(&mut self) {
+        self.track_with::<T, _>(|comp, hasher| {
+            for (name, value) in comp.serialize() {
+                hasher.write(name.as_bytes());
+                hash_value(&value, hasher);
+            }
+        });
+    }
+
+    // For comps without serialization, `hash` feeds whatever defines their state to the hasher.
+    pub fn track_with<T: Comp, F>(&mut self, hash: F)
+    where
+        F: Fn(&T, &mut StableHasher) + 'static,
+    {
+        self.hashers.push((
+            std::any::type_name::<T>(),
+            Box::new(move |world, hasher| {
+                for comp in Query::<&T>::new(world) {
+                    hash(comp, hasher);
+                }
+            }),
+        ));
+    }
+
+    pub fn hashes(&self) -> &[u64] {
+        &self.hashes
+    }
+
+    // Index of the first tick hashing differently from `reference`, None while they agree as far
+    // as both go.
+    pub fn first_divergence(&self, reference: &[u64]) -> Option<usize> {
+        self.hashes
+            .iter()
+            .zip(reference)
+            .position(|(hash, reference)| hash != reference)
+    }
+
+    // After the commands of the tick's last batch were applied.
+    pub(super) fn record(&mut self, world: &mut World) {
+        let mut hasher = StableHasher::new();
+        hasher.write(&(world.entity_count() as u64).to_le_bytes());
+        for (name, hash) in &self.hashers {
+            hasher.write(name.as_bytes());
+            hash(world, &mut hasher);
+        }
+        let tick = self.hashes.len();
+        let hash = hasher.finish();
+        self.hashes.push(hash);
+
+        let expected = self
+            .reference
+            .as_ref()
+            .and_then(|reference| reference.get(tick));
+        if let Some(&expected) = expected.filter(|&&expected| expected != hash) {
+            log::error!(
+                "tick {} diverged from the reference run, hash {:016x} instead of {:016x}",
+                tick,
+                hash,
+                expected
+            );
+            // the ticks after it differ too, only the first is of interest
+            self.reference = None;
+        }
+    }
+}
+
+impl Default for DeterminismAudit {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn hash_value(value: &Value, hasher: &mut StableHasher) {
+    match value {
+        // little endian, the default integer writes are in native byte order
+        Value::Bool(value) => hasher.write(&[*value as u8]),
+        Value::Int(value) => hasher.write(&value.to_le_bytes()),
+        Value::Float(value) => hasher.write(&value.to_bits().to_le_bytes()),
+        Value::String(value) => hasher.write(value.as_bytes()),
+        Value::List(values) => {
+            hasher.write(&(values.len() as u64).to_le_bytes());
+            values.iter().for_each(|value| hash_value(value, hasher));
+        }
+    }
+}
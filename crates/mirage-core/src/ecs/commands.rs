@@ -0,0 +1,59 @@
+use crate::ecs::{Comp, Entity, World};
+use std::cell::RefCell;
+
+type Command = Box<dyn FnOnce(&mut World)>;
+
+// World changes recorded while a system iterates its queries, applied by the scheduler once the
+// system (or its whole batch) is done. Spawned entities get their id right away.
+pub struct Commands {
+    queue: RefCell<Vec<Command>>,
+}
+
+impl Commands {
+    pub fn new() -> Self {
+        Self {
+            queue: RefCell::new(vec![]),
+        }
+    }
+
+    pub fn spawn(&self) -> Entity {
+        let entity = World::reserve_entity();
+        self.add(move |world| world.insert_entity(entity));
+        entity
+    }
+
+    pub fn insert<T: Comp>(&self, entity: Entity, comp: T) {
+        self.add(move |world| world.add_entity_comp(entity, comp));
+    }
+
+    pub fn insert_resource<T: 'static>(&self, resource: T) {
+        self.add(move |world| world.insert_resource(resource));
+    }
+
+    pub fn despawn(&self, entity: Entity) {
+        self.add(move |world| world.remove_entity(entity));
+    }
+
+    pub fn add<F>(&self, command: F)
+    where
+        F: FnOnce(&mut World) + 'static,
+    {
+        self.queue.borrow_mut().push(Box::new(command));
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.queue.borrow().is_empty()
+    }
+
+    // In recording order.
+    pub fn apply(&self, world: &mut World) {
+        let queue = std::mem::take(&mut *self.queue.borrow_mut());
+        queue.into_iter().for_each(|command| command(world));
+    }
+}
+
+impl Default for Commands {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,22 @@
+use crate::ecs::Storage;
+use std::any::TypeId;
+
+pub trait Comp
+where
+    Self: 'static,
+{
+    fn id() -> TypeId
+    where
+        Self: Sized,
+    {
+        TypeId::of::<Self>()
+    }
+
+    // Override with `Storage::Sparse` for comps only a few entities have.
+    fn storage() -> Storage
+    where
+        Self: Sized,
+    {
+        Storage::Dense
+    }
+}
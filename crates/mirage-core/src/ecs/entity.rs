@@ -1,4 +1,4 @@
-use std::hash::{Hash};
+use std::hash::Hash;
 
 #[derive(Debug, Copy, Clone, Hash, Eq, PartialEq)]
 pub struct Entity {
@@ -9,5 +9,4 @@ impl Entity {
     pub fn new(id: u32) -> Self {
         Self { id }
     }
-
 }
@@ -0,0 +1,134 @@
+use crate::ecs::SystemState;
+use std::any::{Any, TypeId};
+use std::cell::{Ref, RefCell};
+use std::collections::HashMap;
+use std::marker::PhantomData;
+use std::rc::Rc;
+
+// Messages of one type between systems, double buffered per tick. What is sent during a tick is
+// read during the next one by every reader, then dropped, so readers see the same events whatever
+// order the systems of a batch ran in.
+pub struct Events<T> {
+    // sent during the previous tick
+    read: RefCell<Vec<T>>,
+    // sent during this tick, readable once the scheduler swaps the buffers
+    sent: RefCell<Vec<T>>,
+}
+
+impl<T> Events<T> {
+    pub fn new() -> Self {
+        Self {
+            read: RefCell::new(vec![]),
+            sent: RefCell::new(vec![]),
+        }
+    }
+
+    pub fn send(&self, event: T) {
+        self.sent.borrow_mut().push(event);
+    }
+
+    pub fn read(&self) -> Ref<'_, [T]> {
+        Ref::map(self.read.borrow(), |events| events.as_slice())
+    }
+
+    // Start of a tick, the sent events become the read ones.
+    pub fn update(&self) {
+        let sent = std::mem::take(&mut *self.sent.borrow_mut());
+        *self.read.borrow_mut() = sent;
+    }
+}
+
+impl<T> Default for Events<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+trait AnyEvents: Any {
+    fn update(&self);
+}
+
+impl<T: 'static> AnyEvents for Events<T> {
+    fn update(&self) {
+        Events::update(self);
+    }
+}
+
+// The `Events` of every type sent or read so far, owned by the scheduler.
+#[derive(Default)]
+pub struct EventRegistry {
+    events: RefCell<HashMap<TypeId, Rc<dyn AnyEvents>>>,
+}
+
+impl EventRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Added on first use.
+    pub fn get<T: 'static>(&self) -> Rc<Events<T>> {
+        let events = self
+            .events
+            .borrow_mut()
+            .entry(TypeId::of::<T>())
+            .or_insert_with(|| Rc::new(Events::<T>::new()))
+            .clone();
+        let events: Rc<dyn Any> = events;
+        events.downcast::<Events<T>>().unwrap()
+    }
+
+    pub fn send<T: 'static>(&self, event: T) {
+        self.get::<T>().send(event);
+    }
+
+    pub fn update(&self) {
+        self.events
+            .borrow()
+            .values()
+            .for_each(|events| events.update());
+    }
+}
+
+// Sends from a system. The events are queued in its state and handed over with its commands, in
+// the order the systems were added.
+pub struct EventWriter<'a, T> {
+    state: &'a SystemState,
+    marker: PhantomData<T>,
+}
+
+impl<'a, T: 'static> EventWriter<'a, T> {
+    pub fn new(state: &'a SystemState) -> Self {
+        Self {
+            state,
+            marker: PhantomData,
+        }
+    }
+
+    pub fn send(&self, event: T) {
+        self.state
+            .sent_events
+            .borrow_mut()
+            .push(Box::new(move |registry| registry.send(event)));
+    }
+}
+
+// The events sent during the previous tick.
+pub struct EventReader<T> {
+    events: Rc<Events<T>>,
+}
+
+impl<T: 'static> EventReader<T> {
+    pub fn new(state: &SystemState) -> Self {
+        Self {
+            events: state.events.get::<T>(),
+        }
+    }
+
+    pub fn read(&self) -> Ref<'_, [T]> {
+        self.events.read()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.read().is_empty()
+    }
+}
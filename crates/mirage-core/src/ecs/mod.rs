@@ -0,0 +1,25 @@
+mod app_state;
+mod audit;
+mod commands;
+mod comp;
+mod entity;
+mod events;
+mod query;
+mod resource;
+mod scheduler;
+mod storage;
+mod system;
+mod world;
+
+pub use app_state::AppState;
+pub use audit::{DeterminismAudit, StableHasher};
+pub use commands::Commands;
+pub use comp::Comp;
+pub use entity::Entity;
+pub use events::{EventReader, EventRegistry, EventWriter, Events};
+pub use query::{Added, Changed, Query, QueryFilter, With, Without};
+pub use resource::{Res, ResMut};
+pub use scheduler::Scheduler;
+pub use storage::{CompColumn, CompTicks, Storage};
+pub use system::{SystemAccess, SystemState};
+pub use world::World;
@@ -0,0 +1,690 @@
+use crate::ecs::{Comp, CompColumn, Storage, World};
+use std::any::Any;
+use std::marker::PhantomData;
+
+trait QueryComp<'a> {
+    type Item: Comp;
+    // yielding it counts as a change of the comp
+    const MUTABLE: bool = false;
+    fn parse(item: &'a mut Option<Box<dyn Any>>) -> Option<Self>
+    where
+        Self: Sized;
+    // what entities of a world that never had the comp get
+    fn missing() -> Option<Self>
+    where
+        Self: Sized,
+    {
+        None
+    }
+}
+
+impl<'a, C: Comp> QueryComp<'a> for &'a C {
+    type Item = C;
+
+    fn parse(item: &'a mut Option<Box<dyn Any>>) -> Option<Self> {
+        match item {
+            None => None,
+            Some(v) => v.downcast_ref::<C>(),
+        }
+    }
+}
+
+impl<'a, C: Comp> QueryComp<'a> for &'a mut C {
+    type Item = C;
+    const MUTABLE: bool = true;
+
+    fn parse(item: &'a mut Option<Box<dyn Any>>) -> Option<Self> {
+        match item {
+            None => None,
+            Some(v) => v.downcast_mut::<C>(),
+        }
+    }
+}
+impl<'a, C: Comp> QueryComp<'a> for Option<&'a C> {
+    type Item = C;
+    fn parse(item: &'a mut Option<Box<dyn Any>>) -> Option<Self> {
+        match item {
+            None => Some(None),
+            Some(v) => Some(v.downcast_ref::<C>()),
+        }
+    }
+    fn missing() -> Option<Self> {
+        Some(None)
+    }
+}
+impl<'a, C: Comp> QueryComp<'a> for Option<&'a mut C> {
+    type Item = C;
+    const MUTABLE: bool = true;
+    fn parse(item: &'a mut Option<Box<dyn Any>>) -> Option<Self> {
+        match item {
+            None => Some(None),
+            Some(v) => Some(v.downcast_mut::<C>()),
+        }
+    }
+    fn missing() -> Option<Self> {
+        Some(None)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct QueryItemGetInvalid;
+type QueryItemResult<T> = Result<T, QueryItemGetInvalid>;
+type QueryData = Vec<QueryColumn>;
+
+pub struct QueryColumn {
+    column: *mut CompColumn,
+    // entities without the comp are skipped, false for optional comps
+    required: bool,
+    mutable: bool,
+}
+
+// Null when the world has no such comp at all, only optional comps can do without.
+fn fetch_column<'a, T: QueryComp<'a>>(world: &mut World) -> Option<QueryColumn> {
+    let required = T::missing().is_none();
+    match world.get_comps_mut::<T::Item>() {
+        Some(column) => Some(QueryColumn {
+            column: column as *mut CompColumn,
+            required,
+            mutable: T::MUTABLE,
+        }),
+        None => T::missing().map(|_| QueryColumn {
+            column: std::ptr::null_mut(),
+            required,
+            mutable: T::MUTABLE,
+        }),
+    }
+}
+
+unsafe fn get_comp<'a, T: QueryComp<'a>>(column: &QueryColumn, index: usize) -> QueryItemResult<T> {
+    if column.column.is_null() {
+        return T::missing().ok_or(QueryItemGetInvalid);
+    }
+    match (*column.column).get_mut(index) {
+        Some(slot) => T::parse(slot),
+        // sparse columns only have the entities with the comp
+        None => T::missing(),
+    }
+    .ok_or(QueryItemGetInvalid)
+}
+
+// The entity slots of the smallest sparse comp the query requires, the only ones that can match.
+fn sparse_indices(data: &QueryData) -> Option<Vec<usize>> {
+    data.iter()
+        .filter(|column| column.required && !column.column.is_null())
+        .map(|column| unsafe { &*column.column })
+        .filter(|column| column.storage() == Storage::Sparse)
+        .min_by_key(|column| column.len())
+        .and_then(|column| column.indices())
+}
+
+pub trait QueryItem {
+    fn fetch(world: &mut World) -> Option<QueryData>;
+    fn try_get(data: &mut QueryData, index: usize) -> QueryItemResult<Self>
+    where
+        Self: Sized;
+}
+
+impl<'a, T1: QueryComp<'a>> QueryItem for T1 {
+    fn fetch(world: &mut World) -> Option<QueryData> {
+        let item1 = fetch_column::<T1>(world)?;
+        Some(vec![item1])
+    }
+
+    fn try_get(data: &mut QueryData, index: usize) -> QueryItemResult<Self> {
+        unsafe {
+            let item1 = get_comp::<T1>(&data[0], index)?;
+            Ok(item1)
+        }
+    }
+}
+
+impl<'a, T1: QueryComp<'a>, T2: QueryComp<'a>> QueryItem for (T1, T2) {
+    fn fetch(world: &mut World) -> Option<QueryData> {
+        let item1 = fetch_column::<T1>(world)?;
+        let item2 = fetch_column::<T2>(world)?;
+
+        Some(vec![item1, item2])
+    }
+
+    fn try_get(data: &mut QueryData, index: usize) -> QueryItemResult<Self> {
+        unsafe {
+            let item1 = get_comp::<T1>(&data[0], index)?;
+            let item2 = get_comp::<T2>(&data[1], index)?;
+
+            Ok((item1, item2))
+        }
+    }
+}
+
+impl<'a, T1: QueryComp<'a>, T2: QueryComp<'a>, T3: QueryComp<'a>> QueryItem for (T1, T2, T3) {
+    fn fetch(world: &mut World) -> Option<QueryData> {
+        let item1 = fetch_column::<T1>(world)?;
+        let item2 = fetch_column::<T2>(world)?;
+        let item3 = fetch_column::<T3>(world)?;
+
+        Some(vec![item1, item2, item3])
+    }
+
+    fn try_get(data: &mut QueryData, index: usize) -> QueryItemResult<Self> {
+        unsafe {
+            let item1 = get_comp::<T1>(&data[0], index)?;
+            let item2 = get_comp::<T2>(&data[1], index)?;
+            let item3 = get_comp::<T3>(&data[2], index)?;
+
+            Ok((item1, item2, item3))
+        }
+    }
+}
+
+impl<'a, T1: QueryComp<'a>, T2: QueryComp<'a>, T3: QueryComp<'a>, T4: QueryComp<'a>> QueryItem
+    for (T1, T2, T3, T4)
+{
+    fn fetch(world: &mut World) -> Option<QueryData> {
+        let item1 = fetch_column::<T1>(world)?;
+        let item2 = fetch_column::<T2>(world)?;
+        let item3 = fetch_column::<T3>(world)?;
+        let item4 = fetch_column::<T4>(world)?;
+
+        Some(vec![item1, item2, item3, item4])
+    }
+
+    fn try_get(data: &mut QueryData, index: usize) -> QueryItemResult<Self> {
+        unsafe {
+            let item1 = get_comp::<T1>(&data[0], index)?;
+            let item2 = get_comp::<T2>(&data[1], index)?;
+            let item3 = get_comp::<T3>(&data[2], index)?;
+            let item4 = get_comp::<T4>(&data[3], index)?;
+
+            Ok((item1, item2, item3, item4))
+        }
+    }
+}
+
+impl<
+        'a,
+        T1: QueryComp<'a>,
+        T2: QueryComp<'a>,
+        T3: QueryComp<'a>,
+        T4: QueryComp<'a>,
+        T5: QueryComp<'a>,
+    > QueryItem for (T1, T2, T3, T4, T5)
+{
+    fn fetch(world: &mut World) -> Option<QueryData> {
+        let item1 = fetch_column::<T1>(world)?;
+        let item2 = fetch_column::<T2>(world)?;
+        let item3 = fetch_column::<T3>(world)?;
+        let item4 = fetch_column::<T4>(world)?;
+        let item5 = fetch_column::<T5>(world)?;
+
+        Some(vec![item1, item2, item3, item4, item5])
+    }
+
+    fn try_get(data: &mut QueryData, index: usize) -> QueryItemResult<Self> {
+        unsafe {
+            let item1 = get_comp::<T1>(&data[0], index)?;
+            let item2 = get_comp::<T2>(&data[1], index)?;
+            let item3 = get_comp::<T3>(&data[2], index)?;
+            let item4 = get_comp::<T4>(&data[3], index)?;
+            let item5 = get_comp::<T5>(&data[4], index)?;
+
+            Ok((item1, item2, item3, item4, item5))
+        }
+    }
+}
+
+// Narrows a query down by comps it doesn't fetch, the second parameter of `Query`. Tuples of
+// filters must all pass.
+pub trait QueryFilter {
+    type State;
+    // None when no entity can pass, e.g. `With` a comp the world never had
+    fn fetch(world: &World) -> Option<Self::State>;
+    fn matches(state: &Self::State, index: usize) -> bool;
+}
+
+// Entities that have the comp, without fetching it.
+pub struct With<C>(PhantomData<C>);
+
+// Entities that don't have the comp.
+pub struct Without<C>(PhantomData<C>);
+
+// Entities whose comp was inserted or written since the system last ran, see
+// `World::clear_trackers` outside of systems. Fetching it mutably counts as a write, whether or not
+// anything was assigned.
+pub struct Changed<C>(PhantomData<C>);
+
+// Entities whose comp was inserted since the system last ran, replacing it doesn't count.
+pub struct Added<C>(PhantomData<C>);
+
+impl QueryFilter for () {
+    type State = ();
+
+    fn fetch(_: &World) -> Option<()> {
+        Some(())
+    }
+
+    fn matches(_: &(), _: usize) -> bool {
+        true
+    }
+}
+
+impl<C: Comp> QueryFilter for With<C> {
+    type State = *const CompColumn;
+
+    fn fetch(world: &World) -> Option<Self::State> {
+        world
+            .get_comps::<C>()
+            .map(|column| column as *const CompColumn)
+    }
+
+    fn matches(column: &Self::State, index: usize) -> bool {
+        unsafe { (**column).ticks(index).is_some() }
+    }
+}
+
+impl<C: Comp> QueryFilter for Without<C> {
+    // null when the world never had the comp
+    type State = *const CompColumn;
+
+    fn fetch(world: &World) -> Option<Self::State> {
+        Some(
+            world
+                .get_comps::<C>()
+                .map_or(std::ptr::null(), |column| column as *const CompColumn),
+        )
+    }
+
+    fn matches(column: &Self::State, index: usize) -> bool {
+        column.is_null() || unsafe { (**column).ticks(index).is_none() }
+    }
+}
+
+impl<C: Comp> QueryFilter for Changed<C> {
+    type State = (*const CompColumn, u64);
+
+    fn fetch(world: &World) -> Option<Self::State> {
+        let column = world.get_comps::<C>()?;
+        Some((column as *const CompColumn, world.last_change_tick()))
+    }
+
+    fn matches((column, last_change_tick): &Self::State, index: usize) -> bool {
+        unsafe { (**column).ticks(index) }.is_some_and(|ticks| ticks.changed > *last_change_tick)
+    }
+}
+
+impl<C: Comp> QueryFilter for Added<C> {
+    type State = (*const CompColumn, u64);
+
+    fn fetch(world: &World) -> Option<Self::State> {
+        let column = world.get_comps::<C>()?;
+        Some((column as *const CompColumn, world.last_change_tick()))
+    }
+
+    fn matches((column, last_change_tick): &Self::State, index: usize) -> bool {
+        unsafe { (**column).ticks(index) }.is_some_and(|ticks| ticks.added > *last_change_tick)
+    }
+}
+
+impl<F1: QueryFilter, F2: QueryFilter> QueryFilter for (F1, F2) {
+    type State = (F1::State, F2::State);
+
+    fn fetch(world: &World) -> Option<Self::State> {
+        Some((F1::fetch(world)?, F2::fetch(world)?))
+    }
+
+    fn matches((state1, state2): &Self::State, index: usize) -> bool {
+        F1::matches(state1, index) && F2::matches(state2, index)
+    }
+}
+
+impl<F1: QueryFilter, F2: QueryFilter, F3: QueryFilter> QueryFilter for (F1, F2, F3) {
+    type State = (F1::State, F2::State, F3::State);
+
+    fn fetch(world: &World) -> Option<Self::State> {
+        Some((F1::fetch(world)?, F2::fetch(world)?, F3::fetch(world)?))
+    }
+
+    fn matches((state1, state2, state3): &Self::State, index: usize) -> bool {
+        F1::matches(state1, index) && F2::matches(state2, index) && F3::matches(state3, index)
+    }
+}
+
+pub struct Query<T, F: QueryFilter = ()> {
+    data: Option<QueryData>,
+    filter: Option<F::State>,
+    // slots to visit when a sparse comp narrows them down, otherwise all up to `count`
+    indices: Option<Vec<usize>>,
+    count: usize,
+    curr: usize,
+    // what the mutable comps it yields are stamped with
+    change_tick: u64,
+    phantom: PhantomData<T>,
+}
+
+impl<T, F> Query<T, F>
+where
+    T: QueryItem,
+    F: QueryFilter,
+{
+    pub fn new(world: &mut World) -> Query<T, F> {
+        let filter = F::fetch(world);
+        let data = T::fetch(world);
+        let indices = data.as_ref().and_then(sparse_indices);
+        let count = indices
+            .as_ref()
+            .map_or(world.index_count(), |indices| indices.len());
+        Self {
+            data,
+            filter,
+            indices,
+            count,
+            curr: 0,
+            change_tick: world.change_tick(),
+            phantom: PhantomData,
+        }
+    }
+}
+
+impl<T, F> Iterator for Query<T, F>
+where
+    T: QueryItem,
+    F: QueryFilter,
+{
+    type Item = T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.data.is_none() || self.filter.is_none() {
+            return None;
+        }
+
+        while self.curr < self.count {
+            let index = self
+                .indices
+                .as_ref()
+                .map_or(self.curr, |indices| indices[self.curr]);
+            self.curr += 1;
+            if !F::matches(self.filter.as_ref().unwrap(), index) {
+                continue;
+            }
+            let result = T::try_get(self.data.as_mut().unwrap(), index);
+
+            if let Ok(v) = result {
+                for column in self.data.as_ref().unwrap() {
+                    if column.mutable && !column.column.is_null() {
+                        unsafe { (*column.column).set_changed(index, self.change_tick) };
+                    }
+                }
+                return Some(v);
+            }
+        }
+
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ecs::{Added, Changed, Comp, Entity, Query, Storage, With, Without, World};
+
+    struct Position(i32);
+    struct Velocity(i32);
+    struct Health(i32);
+    struct Name(&'static str);
+    struct Tag;
+    struct Selected;
+
+    impl Comp for Position {}
+    impl Comp for Velocity {}
+    impl Comp for Health {}
+    impl Comp for Name {}
+    impl Comp for Tag {}
+    impl Comp for Selected {
+        fn storage() -> Storage {
+            Storage::Sparse
+        }
+    }
+
+    // Positions 0..count, velocities on every second entity and health on every third.
+    fn world(count: i32) -> (World, Vec<Entity>) {
+        let mut world = World::new();
+        let entities = (0..count)
+            .map(|i| {
+                let entity = world.add_entity();
+                world.add_entity_comp(entity, Position(i));
+                if i % 2 == 0 {
+                    world.add_entity_comp(entity, Velocity(i * 10));
+                }
+                if i % 3 == 0 {
+                    world.add_entity_comp(entity, Health(i * 100));
+                }
+                entity
+            })
+            .collect();
+        (world, entities)
+    }
+
+    #[test]
+    fn single_comp_in_entity_order() {
+        let (mut world, _) = world(10);
+        let positions = Query::<&Position>::new(&mut world)
+            .map(|position| position.0)
+            .collect::<Vec<_>>();
+        assert_eq!(positions, (0..10).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn tuple_skips_entities_missing_a_comp() {
+        let (mut world, _) = world(10);
+        let pairs = Query::<(&Position, &Velocity)>::new(&mut world)
+            .map(|(position, velocity)| (position.0, velocity.0))
+            .collect::<Vec<_>>();
+        assert_eq!(pairs, vec![(0, 0), (2, 20), (4, 40), (6, 60), (8, 80)]);
+
+        let triples = Query::<(&Health, &Position, &Velocity)>::new(&mut world)
+            .map(|(health, position, velocity)| (health.0, position.0, velocity.0))
+            .collect::<Vec<_>>();
+        assert_eq!(triples, vec![(0, 0, 0), (600, 6, 60)]);
+    }
+
+    #[test]
+    fn optional_comps_visit_every_entity() {
+        let (mut world, _) = world(6);
+        let items = Query::<(&Position, Option<&Velocity>, Option<&Health>)>::new(&mut world)
+            .map(|(position, velocity, health)| {
+                (position.0, velocity.map(|v| v.0), health.map(|h| h.0))
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            items,
+            vec![
+                (0, Some(0), Some(0)),
+                (1, None, None),
+                (2, Some(20), None),
+                (3, None, Some(300)),
+                (4, Some(40), None),
+                (5, None, None),
+            ]
+        );
+
+        // a comp no entity ever had is None for everyone instead of emptying the query
+        let names = Query::<(&Position, Option<&Name>)>::new(&mut world)
+            .filter(|(_, name)| name.is_none())
+            .count();
+        assert_eq!(names, 6);
+    }
+
+    #[test]
+    fn comp_no_entity_has_gives_nothing() {
+        let (mut world, _) = world(4);
+        assert_eq!(Query::<&Name>::new(&mut world).count(), 0);
+        assert_eq!(Query::<(&Position, &Name)>::new(&mut world).count(), 0);
+        assert_eq!(Query::<&Name>::new(&mut World::new()).count(), 0);
+    }
+
+    #[test]
+    fn writes_through_mutable_comps() {
+        let (mut world, entities) = world(6);
+        for (position, velocity) in Query::<(&mut Position, &Velocity)>::new(&mut world) {
+            position.0 += velocity.0;
+        }
+        for (position, health) in Query::<(&Position, Option<&mut Health>)>::new(&mut world) {
+            if let Some(health) = health {
+                health.0 -= position.0;
+            }
+        }
+
+        let position = |i: usize| world.get_entity_comp::<Position>(entities[i]).unwrap().0;
+        assert_eq!(
+            (0..6).map(position).collect::<Vec<_>>(),
+            vec![0, 1, 22, 3, 44, 5]
+        );
+        assert_eq!(world.get_entity_comp::<Health>(entities[3]).unwrap().0, 297);
+    }
+
+    #[test]
+    fn five_comps() {
+        let (mut world, entities) = world(13);
+        world.add_entity_comp(entities[6], Name("six"));
+        world.add_entity_comp(entities[12], Name("twelve"));
+        world.add_entity_comp(entities[12], Tag);
+        world.add_entity_comp(entities[0], Tag);
+
+        let items = Query::<(&Position, &Velocity, &Health, &Name, Option<&Tag>)>::new(&mut world)
+            .map(|(position, _, health, name, tag)| (position.0, health.0, name.0, tag.is_some()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            items,
+            vec![(6, 600, "six", false), (12, 1200, "twelve", true)]
+        );
+    }
+
+    #[test]
+    fn removed_entities_and_comps_are_skipped() {
+        let (mut world, entities) = world(6);
+        world.remove_entity(entities[2]);
+        world.remove_entity_comp::<Velocity>(entities[4]);
+
+        let pairs = Query::<(&Position, &Velocity)>::new(&mut world)
+            .map(|(position, _)| position.0)
+            .collect::<Vec<_>>();
+        assert_eq!(pairs, vec![0]);
+        assert_eq!(Query::<&Position>::new(&mut world).count(), 5);
+
+        // the new entity reuses the freed slot and is visited there, without the old comps
+        let entity = world.add_entity();
+        world.add_entity_comp(entity, Position(42));
+        let positions = Query::<(&Position, Option<&Velocity>)>::new(&mut world)
+            .map(|(position, velocity)| (position.0, velocity.is_some()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            positions,
+            vec![
+                (0, true),
+                (1, false),
+                (42, false),
+                (3, false),
+                (4, false),
+                (5, false)
+            ]
+        );
+    }
+
+    #[test]
+    fn sparse_comp_narrows_the_query() {
+        let (mut world, entities) = world(100);
+        for i in [97, 3, 50] {
+            world.add_entity_comp(entities[i], Selected);
+        }
+        let selected = Query::<(&Selected, &Position, Option<&Velocity>)>::new(&mut world)
+            .map(|(_, position, velocity)| (position.0, velocity.is_some()))
+            .collect::<Vec<_>>();
+        assert_eq!(selected.len(), 3);
+        assert!(selected.contains(&(3, false)));
+        assert!(selected.contains(&(50, true)));
+        assert!(selected.contains(&(97, false)));
+    }
+
+    #[test]
+    fn query_past_the_initial_capacity() {
+        let (mut world, _) = world(1000);
+        assert_eq!(Query::<&Position>::new(&mut world).count(), 1000);
+        assert_eq!(
+            Query::<(&Position, &Velocity)>::new(&mut world).count(),
+            500
+        );
+        assert_eq!(Query::<(&Velocity, &Health)>::new(&mut world).count(), 167);
+    }
+
+    #[test]
+    fn with_and_without_filter_by_comps_not_fetched() {
+        let (mut world, entities) = world(10);
+        world.add_entity_comp(entities[4], Selected);
+        world.add_entity_comp(entities[6], Selected);
+
+        let with = Query::<&Position, With<Velocity>>::new(&mut world)
+            .map(|position| position.0)
+            .collect::<Vec<_>>();
+        assert_eq!(with, vec![0, 2, 4, 6, 8]);
+
+        let without = Query::<&Position, (Without<Velocity>, Without<Health>)>::new(&mut world)
+            .map(|position| position.0)
+            .collect::<Vec<_>>();
+        assert_eq!(without, vec![1, 5, 7]);
+
+        let selected = Query::<&Position, (With<Selected>, Without<Health>)>::new(&mut world)
+            .map(|position| position.0)
+            .collect::<Vec<_>>();
+        assert_eq!(selected, vec![4]);
+
+        // a comp the world never had
+        assert_eq!(Query::<&Position, With<Name>>::new(&mut world).count(), 0);
+        assert_eq!(
+            Query::<&Position, Without<Name>>::new(&mut world).count(),
+            10
+        );
+    }
+
+    #[test]
+    fn changed_and_added_since_the_trackers_were_cleared() {
+        let (mut world, entities) = world(6);
+        assert_eq!(
+            Query::<&Position, Added<Position>>::new(&mut world).count(),
+            6
+        );
+        world.clear_trackers();
+        assert_eq!(
+            Query::<&Position, Changed<Position>>::new(&mut world).count(),
+            0
+        );
+
+        // reading doesn't count, fetching mutably does
+        for _ in Query::<(&Position, &mut Velocity)>::new(&mut world) {}
+        world
+            .get_entity_comp_mut::<Position>(entities[5])
+            .unwrap()
+            .0 = 50;
+        world.add_entity_comp(entities[1], Position(10));
+        world.add_entity_comp(entities[1], Velocity(10));
+
+        let changed = Query::<&Position, Changed<Position>>::new(&mut world)
+            .map(|position| position.0)
+            .collect::<Vec<_>>();
+        assert_eq!(changed, vec![10, 50]);
+        let velocities = Query::<&Position, Changed<Velocity>>::new(&mut world)
+            .map(|position| position.0)
+            .collect::<Vec<_>>();
+        assert_eq!(velocities, vec![0, 10, 2, 4]);
+        let added = Query::<&Position, Added<Velocity>>::new(&mut world)
+            .map(|position| position.0)
+            .collect::<Vec<_>>();
+        assert_eq!(added, vec![10]);
+
+        // a removed comp added back is new again
+        world.clear_trackers();
+        world.remove_entity_comp::<Velocity>(entities[2]);
+        world.add_entity_comp(entities[2], Velocity(0));
+        let added = Query::<&Position, (Added<Velocity>, Changed<Velocity>)>::new(&mut world)
+            .map(|position| position.0)
+            .collect::<Vec<_>>();
+        assert_eq!(added, vec![2]);
+    }
+}
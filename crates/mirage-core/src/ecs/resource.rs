@@ -0,0 +1,55 @@
+use crate::ecs::World;
+use std::ops::{Deref, DerefMut};
+
+// A world singleton read by a system, e.g. the time or the active camera, next to its queries.
+// Like the comps of a query it points into the world, so it must not outlive the system run nor
+// the resource being removed or replaced.
+pub struct Res<T> {
+    value: *const T,
+}
+
+impl<T: 'static> Res<T> {
+    // None when the world has no such resource.
+    pub fn new(world: &World) -> Option<Self> {
+        let value = world.get_resource::<T>()?;
+        Some(Self {
+            value: value as *const T,
+        })
+    }
+}
+
+impl<T> Deref for Res<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        unsafe { &*self.value }
+    }
+}
+
+// A world singleton written by a system, declared with `SystemAccess::write_resource`.
+pub struct ResMut<T> {
+    value: *mut T,
+}
+
+impl<T: 'static> ResMut<T> {
+    pub fn new(world: &mut World) -> Option<Self> {
+        let value = world.get_resource_mut::<T>()?;
+        Some(Self {
+            value: value as *mut T,
+        })
+    }
+}
+
+impl<T> Deref for ResMut<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        unsafe { &*self.value }
+    }
+}
+
+impl<T> DerefMut for ResMut<T> {
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe { &mut *self.value }
+    }
+}
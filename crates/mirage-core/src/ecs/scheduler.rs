@@ -0,0 +1,621 @@
+use crate::cpu_profiler;
+use crate::ecs::{AppState, DeterminismAudit, EventRegistry, SystemAccess, SystemState, World};
+use std::cell::Cell;
+use std::ops::Range;
+use std::rc::Rc;
+
+type System = Box<dyn Fn(&mut World, &SystemState)>;
+
+struct SystemEntry {
+    // the type name of the system, its scope in a captured trace
+    name: &'static str,
+    // None runs in every state
+    app_state: Option<AppState>,
+    access: SystemAccess,
+    system: System,
+    // the world change tick of its last run, its `Changed` and `Added` queries see what came after
+    last_run: Cell<u64>,
+}
+
+pub struct Scheduler {
+    systems: Vec<SystemEntry>,
+    enter_hooks: Vec<(AppState, System)>,
+    exit_hooks: Vec<(AppState, System)>,
+
+    app_state: Option<AppState>,
+    next_app_state: Option<AppState>,
+    // shuffles the systems of each batch, see `set_batch_order_seed`
+    batch_order: Option<u64>,
+    events: Rc<EventRegistry>,
+    // seconds of all ticks so far, what systems see as elapsed time
+    elapsed_time: f32,
+    audit: Option<DeterminismAudit>,
+}
+
+impl Scheduler {
+    pub fn new() -> Scheduler {
+        Scheduler {
+            systems: vec![],
+            enter_hooks: vec![],
+            exit_hooks: vec![],
+            app_state: None,
+            // the enter hooks of the initial state run on the first tick
+            next_app_state: Some(AppState::default()),
+            batch_order: None,
+            events: Rc::new(EventRegistry::new()),
+            elapsed_time: 0.0,
+            audit: None,
+        }
+    }
+
+    pub fn add_system<F>(&mut self, system: F)
+    where
+        F: Fn(&mut World, &SystemState) + 'static,
+    {
+        self.add_system_with_access(SystemAccess::exclusive(), system);
+    }
+
+    // Consecutive systems with non conflicting access form a batch, their commands are applied
+    // together once the whole batch ran.
+    pub fn add_system_with_access<F>(&mut self, access: SystemAccess, system: F)
+    where
+        F: Fn(&mut World, &SystemState) + 'static,
+    {
+        self.systems.push(SystemEntry {
+            name: std::any::type_name::<F>(),
+            app_state: None,
+            access,
+            system: Box::new(system),
+            last_run: Cell::new(0),
+        });
+    }
+
+    pub fn add_state_system<F>(&mut self, app_state: AppState, system: F)
+    where
+        F: Fn(&mut World, &SystemState) + 'static,
+    {
+        self.systems.push(SystemEntry {
+            name: std::any::type_name::<F>(),
+            app_state: Some(app_state),
+            access: SystemAccess::exclusive(),
+            system: Box::new(system),
+            last_run: Cell::new(0),
+        });
+    }
+
+    // Runs the systems of each batch in an order picked by the seed instead of the order they were
+    // added, like a thread pool might. A batch whose result changes with it has wrong access.
+    pub fn set_batch_order_seed(&mut self, seed: Option<u64>) {
+        // xorshift gets stuck at zero
+        self.batch_order = seed.map(|seed| seed | 1);
+    }
+
+    pub fn on_enter<F>(&mut self, app_state: AppState, hook: F)
+    where
+        F: Fn(&mut World, &SystemState) + 'static,
+    {
+        self.enter_hooks.push((app_state, Box::new(hook)));
+    }
+
+    pub fn on_exit<F>(&mut self, app_state: AppState, hook: F)
+    where
+        F: Fn(&mut World, &SystemState) + 'static,
+    {
+        self.exit_hooks.push((app_state, Box::new(hook)));
+    }
+
+    pub fn get_app_state(&self) -> AppState {
+        self.app_state.or(self.next_app_state).unwrap_or_default()
+    }
+
+    // Takes effect at the start of the next tick, like a system calling `SystemState::set_app_state`.
+    pub fn set_app_state(&mut self, app_state: AppState) {
+        self.next_app_state = Some(app_state);
+    }
+
+    // From outside the systems, e.g. input. Systems read it during the next tick.
+    pub fn send_event<T: 'static>(&self, event: T) {
+        self.events.send(event);
+    }
+
+    // Hashes the world after every tick and reports hash order iteration, None turns it off. The
+    // world is audited while it is ticked with an audit set.
+    pub fn set_determinism_audit(&mut self, audit: Option<DeterminismAudit>) {
+        self.audit = audit;
+    }
+
+    pub fn get_determinism_audit(&self) -> Option<&DeterminismAudit> {
+        self.audit.as_ref()
+    }
+
+    pub fn tick(&mut self, world: &mut World, delta_time: f32) {
+        // per scheduler, a static would be shared by every world of the process
+        self.elapsed_time += delta_time;
+        let elapsed_time = self.elapsed_time;
+        world.set_iteration_audit(self.audit.is_some());
+
+        self.events.update();
+        self.apply_transition(world, delta_time, elapsed_time);
+
+        let app_state = self.get_app_state();
+        let systems = self
+            .systems
+            .iter()
+            .filter(|entry| entry.app_state.is_none_or(|s| s == app_state))
+            .collect::<Vec<_>>();
+
+        for batch in Self::batches(&systems) {
+            let states = batch
+                .clone()
+                .map(|_| SystemState::new(delta_time, elapsed_time, app_state, self.events.clone()))
+                .collect::<Vec<_>>();
+            let mut order = (0..states.len()).collect::<Vec<_>>();
+            if let Some(seed) = &mut self.batch_order {
+                Self::shuffle(&mut order, seed);
+            }
+            for index in order {
+                let entry = systems[batch.start + index];
+                cpu_profiler::begin_scope(entry.name, "system");
+                let last_run = world.run_system(entry.last_run.get(), |world| {
+                    (entry.system)(world, &states[index])
+                });
+                entry.last_run.set(last_run);
+                cpu_profiler::end_scope();
+            }
+
+            // in the order the systems were added, whatever order they ran in
+            for state in states {
+                state.apply(world);
+                if let Some(next_app_state) = state.next_app_state.get() {
+                    self.next_app_state = Some(next_app_state);
+                }
+            }
+        }
+
+        if let Some(audit) = &mut self.audit {
+            audit.record(world);
+        }
+    }
+
+    // Greedy, a system joins the current batch unless it conflicts with a system already in it.
+    fn batches(systems: &[&SystemEntry]) -> Vec<Range<usize>> {
+        let mut batches = vec![];
+        let mut start = 0;
+        for index in 0..systems.len() {
+            let conflicts = systems[start..index]
+                .iter()
+                .any(|other| other.access.conflicts_with(&systems[index].access));
+            if conflicts {
+                batches.push(start..index);
+                start = index;
+            }
+        }
+        if start < systems.len() {
+            batches.push(start..systems.len());
+        }
+        batches
+    }
+
+    fn shuffle(order: &mut [usize], seed: &mut u64) {
+        for index in (1..order.len()).rev() {
+            *seed ^= *seed << 13;
+            *seed ^= *seed >> 7;
+            *seed ^= *seed << 17;
+            order.swap(index, (*seed % (index as u64 + 1)) as usize);
+        }
+    }
+
+    // Exit hooks see the old state and enter hooks the new one. Hooks may chain another transition,
+    // it is applied on the following tick.
+    fn apply_transition(&mut self, world: &mut World, delta_time: f32, elapsed_time: f32) {
+        let Some(next_app_state) = self.next_app_state.take() else {
+            return;
+        };
+        if self.app_state == Some(next_app_state) {
+            return;
+        }
+
+        let events = self.events.clone();
+        let state = SystemState::new(delta_time, elapsed_time, next_app_state, events.clone());
+        if let Some(app_state) = self.app_state {
+            let exit_state = SystemState::new(delta_time, elapsed_time, app_state, events);
+            self.exit_hooks
+                .iter()
+                .filter(|(hook_app_state, _)| *hook_app_state == app_state)
+                .for_each(|(_, hook)| {
+                    hook(world, &exit_state);
+                    exit_state.apply(world);
+                });
+            if let Some(chained) = exit_state.next_app_state.get() {
+                state.next_app_state.set(Some(chained));
+            }
+        }
+
+        self.app_state = Some(next_app_state);
+        self.enter_hooks
+            .iter()
+            .filter(|(hook_app_state, _)| *hook_app_state == next_app_state)
+            .for_each(|(_, hook)| {
+                hook(world, &state);
+                state.apply(world);
+            });
+        self.next_app_state = state.next_app_state.get();
+    }
+}
+
+impl Default for Scheduler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ecs::{Added, Changed, Comp, Entity, Query, ResMut};
+    use std::cell::{Cell, RefCell};
+    use std::hash::Hasher;
+    use std::rc::Rc;
+
+    // integers, so serial and shuffled runs can be compared exactly
+    struct Position(i64);
+    struct Velocity(i64);
+    struct Health(i64);
+    // a resource, the whole world has one
+    struct Score(i64);
+    // the entity itself, queries don't hand out entities
+    struct Owner(Entity);
+
+    impl Comp for Position {}
+    impl Comp for Velocity {}
+    impl Comp for Health {}
+    impl Comp for Owner {}
+
+    fn next_random(value: &mut u64) -> u64 {
+        *value = value
+            .wrapping_mul(6364136223846793005)
+            .wrapping_add(1442695040888963407);
+        *value >> 33
+    }
+
+    fn spawn_world(seed: u64, count: usize) -> World {
+        let mut world = World::new();
+        let mut random = seed;
+        for _ in 0..count {
+            let entity = world.add_entity();
+            world.add_entity_comp(entity, Owner(entity));
+            world.add_entity_comp(entity, Position(next_random(&mut random) as i64 % 100));
+            world.add_entity_comp(entity, Velocity(next_random(&mut random) as i64 % 7 - 3));
+            world.add_entity_comp(entity, Health(next_random(&mut random) as i64 % 20));
+        }
+        world.insert_resource(Score(0));
+        world
+    }
+
+    // Entity ids depend on the order spawns were recorded in, the comps don't.
+    fn snapshot(world: &World) -> (Vec<(i64, i64, i64)>, i64) {
+        let mut items = vec![];
+        for entity in world.entities() {
+            items.push((
+                world.get_entity_comp::<Position>(entity).unwrap().0,
+                world.get_entity_comp::<Velocity>(entity).unwrap().0,
+                world.get_entity_comp::<Health>(entity).unwrap().0,
+            ));
+        }
+        items.sort();
+        (items, world.get_resource::<Score>().unwrap().0)
+    }
+
+    // Three batches: [integrate, regenerate] [drag, damage] [reap, tally]
+    fn add_systems(scheduler: &mut Scheduler) {
+        scheduler.add_system_with_access(
+            SystemAccess::new().read::<Velocity>().write::<Position>(),
+            |world, _| {
+                for (position, velocity) in Query::<(&mut Position, &Velocity)>::new(world) {
+                    position.0 += velocity.0;
+                }
+            },
+        );
+        scheduler.add_system_with_access(SystemAccess::new().write::<Health>(), |world, _| {
+            for health in Query::<&mut Health>::new(world) {
+                health.0 += 1;
+            }
+        });
+        scheduler.add_system_with_access(SystemAccess::new().write::<Velocity>(), |world, _| {
+            for velocity in Query::<&mut Velocity>::new(world) {
+                velocity.0 -= velocity.0.signum();
+            }
+        });
+        scheduler.add_system_with_access(
+            SystemAccess::new().read::<Position>().write::<Health>(),
+            |world, _| {
+                for (position, health) in Query::<(&Position, &mut Health)>::new(world) {
+                    health.0 -= position.0.abs() % 5;
+                }
+            },
+        );
+        // dead entities leave a newborn behind, moving the other way
+        scheduler.add_system_with_access(
+            SystemAccess::new()
+                .read::<Owner>()
+                .read::<Position>()
+                .read::<Health>(),
+            |world, state| {
+                let query = Query::<(&Owner, &Position, &Health)>::new(world);
+                for (owner, position, health) in query {
+                    if health.0 > 0 {
+                        continue;
+                    }
+                    state.commands.despawn(owner.0);
+                    let child = state.commands.spawn();
+                    state.commands.insert(child, Owner(child));
+                    state.commands.insert(child, Position(position.0));
+                    state
+                        .commands
+                        .insert(child, Velocity(-position.0.signum() * 2));
+                    state.commands.insert(child, Health(10));
+                }
+            },
+        );
+        scheduler.add_system_with_access(
+            SystemAccess::new()
+                .read::<Position>()
+                .write_resource::<Score>(),
+            |world, _| {
+                let mut score = ResMut::<Score>::new(world).unwrap();
+                let total = Query::<&Position>::new(world).map(|p| p.0).sum::<i64>();
+                score.0 = score.0.wrapping_mul(31).wrapping_add(total);
+            },
+        );
+    }
+
+    fn run(world_seed: u64, batch_order_seed: Option<u64>, add: fn(&mut Scheduler)) -> World {
+        let mut world = spawn_world(world_seed, 128);
+        let mut scheduler = Scheduler::new();
+        add(&mut scheduler);
+        scheduler.set_batch_order_seed(batch_order_seed);
+        for _ in 0..32 {
+            scheduler.tick(&mut world, 1.0 / 60.0);
+        }
+        world
+    }
+
+    #[test]
+    fn batches_split_at_conflicts() {
+        let mut scheduler = Scheduler::new();
+        add_systems(&mut scheduler);
+        scheduler.add_system(|_, _| {});
+        scheduler.add_system_with_access(SystemAccess::new().read::<Position>(), |_, _| {});
+
+        let systems = scheduler.systems.iter().collect::<Vec<_>>();
+        assert_eq!(
+            Scheduler::batches(&systems),
+            vec![0..2, 2..4, 4..6, 6..7, 7..8]
+        );
+    }
+
+    #[test]
+    fn shuffled_batches_match_serial_run() {
+        for world_seed in 0..8 {
+            let serial = snapshot(&run(world_seed, None, add_systems));
+            assert!(!serial.0.is_empty());
+            for batch_order_seed in 0..32 {
+                let shuffled = snapshot(&run(world_seed, Some(batch_order_seed), add_systems));
+                assert_eq!(
+                    serial, shuffled,
+                    "world seed {}, batch order seed {}",
+                    world_seed, batch_order_seed
+                );
+            }
+        }
+    }
+
+    // The harness has to catch a system writing more than it declared.
+    #[test]
+    fn undeclared_write_changes_with_order() {
+        fn add_wrong_systems(scheduler: &mut Scheduler) {
+            scheduler.add_system_with_access(SystemAccess::new().read::<Position>(), |world, _| {
+                for position in Query::<&mut Position>::new(world) {
+                    position.0 *= 2;
+                }
+            });
+            scheduler.add_system_with_access(
+                SystemAccess::new().write::<Velocity>(),
+                |world, _| {
+                    for position in Query::<&mut Position>::new(world) {
+                        position.0 += 1;
+                    }
+                },
+            );
+        }
+
+        let serial = snapshot(&run(0, None, add_wrong_systems));
+        let differs =
+            (0..32).any(|seed| snapshot(&run(0, Some(seed), add_wrong_systems)) != serial);
+        assert!(differs);
+    }
+
+    fn run_audited(world_seed: u64, batch_order_seed: Option<u64>) -> Vec<u64> {
+        let mut world = spawn_world(world_seed, 128);
+        let mut scheduler = Scheduler::new();
+        add_systems(&mut scheduler);
+        scheduler.set_batch_order_seed(batch_order_seed);
+        let mut audit = DeterminismAudit::new();
+        audit.track_with::<Position, _>(|position, hasher| hasher.write_i64(position.0));
+        audit.track_with::<Health, _>(|health, hasher| hasher.write_i64(health.0));
+        scheduler.set_determinism_audit(Some(audit));
+        for _ in 0..32 {
+            scheduler.tick(&mut world, 1.0 / 60.0);
+        }
+        scheduler.get_determinism_audit().unwrap().hashes().to_vec()
+    }
+
+    #[test]
+    fn audited_runs_hash_the_same() {
+        let serial = run_audited(3, None);
+        assert_eq!(serial.len(), 32);
+        assert_eq!(run_audited(3, None), serial);
+        assert_eq!(run_audited(3, Some(7)), serial);
+        // another world has to hash differently from the first tick on
+        assert_ne!(run_audited(4, None)[0], serial[0]);
+    }
+
+    #[derive(Debug, Clone, PartialEq)]
+    struct Hit(i64);
+
+    // Events flow between systems with a tick of delay, whatever order the senders ran in.
+    fn run_events(batch_order_seed: Option<u64>) -> Vec<Vec<i64>> {
+        let received = Rc::new(std::cell::RefCell::new(vec![]));
+        let mut scheduler = Scheduler::new();
+        scheduler.set_batch_order_seed(batch_order_seed);
+        for sender in 0..4 {
+            scheduler.add_system_with_access(SystemAccess::new(), move |_, state| {
+                let writer = state.event_writer::<Hit>();
+                writer.send(Hit(sender));
+                writer.send(Hit(sender * 10));
+            });
+        }
+        let log = received.clone();
+        scheduler.add_system(move |_, state| {
+            let reader = state.event_reader::<Hit>();
+            log.borrow_mut()
+                .push(reader.read().iter().map(|hit| hit.0).collect());
+        });
+
+        let mut world = World::new();
+        scheduler.send_event(Hit(-1));
+        for _ in 0..4 {
+            scheduler.tick(&mut world, 1.0 / 60.0);
+        }
+        received.take()
+    }
+
+    #[test]
+    fn events_are_read_the_tick_after_they_were_sent() {
+        let received = run_events(None);
+        assert_eq!(received.len(), 4);
+        // sent from outside before the first tick
+        assert_eq!(received[0], vec![-1]);
+        for events in &received[1..] {
+            assert_eq!(events.len(), 8);
+            assert_eq!(events, &vec![0, 0, 1, 10, 2, 20, 3, 30]);
+        }
+    }
+
+    #[test]
+    fn shuffled_senders_keep_event_order() {
+        let serial = run_events(None);
+        for seed in 0..16 {
+            assert_eq!(run_events(Some(seed)), serial, "batch order seed {}", seed);
+        }
+    }
+
+    #[test]
+    fn spawn_and_despawn_from_commands_while_iterating() {
+        let mut world = World::new();
+        for index in 0..400 {
+            let entity = world.add_entity();
+            world.add_entity_comp(entity, Owner(entity));
+            world.add_entity_comp(entity, Health(index));
+        }
+
+        let spawned = Rc::new(Cell::new(0));
+        let mut scheduler = Scheduler::new();
+        let counter = spawned.clone();
+        scheduler.add_system_with_access(
+            SystemAccess::new().read::<Owner>().write::<Health>(),
+            move |world, state| {
+                // grows past the initial column capacity, then hovers around 700
+                let growing = world.entity_count() < 700;
+                for (owner, health) in Query::<(&Owner, &mut Health)>::new(world) {
+                    let mut random = health.0 as u64;
+                    let roll = next_random(&mut random) % 3;
+                    health.0 = random as i64;
+                    if roll == 0 || (!growing && roll == 1) {
+                        state.commands.despawn(owner.0);
+                    } else if growing {
+                        let child = state.commands.spawn();
+                        state.commands.insert(child, Owner(child));
+                        state.commands.insert(child, Health(health.0 ^ 1));
+                        counter.set(counter.get() + 1);
+                    }
+                }
+            },
+        );
+
+        let mut peak = world.entity_count();
+        for _ in 0..200 {
+            let before = world.entity_count();
+            spawned.set(0);
+            scheduler.tick(&mut world, 1.0 / 60.0);
+            peak = peak.max(before + spawned.get());
+
+            let owners = Query::<&Owner>::new(&mut world)
+                .map(|owner| owner.0)
+                .collect::<Vec<_>>();
+            assert_eq!(owners.len(), world.entity_count());
+            for entity in owners {
+                assert!(world.contains_entity(entity));
+                assert_eq!(world.get_entity_comp::<Owner>(entity).unwrap().0, entity);
+            }
+            // slots of despawned entities are reused
+            assert!(world.index_count() <= peak);
+        }
+        assert!(world.index_count() > 512);
+    }
+
+    #[test]
+    fn systems_see_the_changes_since_they_last_ran() {
+        let mut world = World::new();
+        let entities = (0..4)
+            .map(|index| {
+                let entity = world.add_entity();
+                world.add_entity_comp(entity, Position(index));
+                world.add_entity_comp(entity, Velocity(0));
+                entity
+            })
+            .collect::<Vec<_>>();
+
+        // what the second system saw changed and added on each tick
+        let seen = Rc::new(RefCell::new(vec![]));
+        let mut scheduler = Scheduler::new();
+        scheduler.add_system_with_access(
+            SystemAccess::new().read::<Velocity>().write::<Position>(),
+            |world, _| {
+                for (position, velocity) in
+                    Query::<(&mut Position, &Velocity), Changed<Velocity>>::new(world)
+                {
+                    position.0 += velocity.0;
+                }
+            },
+        );
+        let recorder = seen.clone();
+        scheduler.add_system_with_access(
+            SystemAccess::new().read::<Position>(),
+            move |world, _| {
+                let changed = Query::<&Position, Changed<Position>>::new(world)
+                    .map(|position| position.0)
+                    .collect::<Vec<_>>();
+                let added = Query::<&Position, Added<Position>>::new(world).count();
+                recorder.borrow_mut().push((changed, added));
+            },
+        );
+
+        scheduler.tick(&mut world, 1.0);
+        scheduler.tick(&mut world, 1.0);
+        let velocity = world.get_entity_comp_mut::<Velocity>(entities[2]).unwrap();
+        velocity.0 = 10;
+        scheduler.tick(&mut world, 1.0);
+        scheduler.tick(&mut world, 1.0);
+
+        assert_eq!(
+            *seen.borrow(),
+            vec![
+                (vec![0, 1, 2, 3], 4),
+                (vec![], 0),
+                (vec![12], 0),
+                (vec![], 0)
+            ]
+        );
+    }
+}
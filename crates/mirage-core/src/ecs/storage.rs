@@ -0,0 +1,263 @@
+use ahash::{HashMap, HashMapExt};
+use std::any::Any;
+
+pub type CompSlot = Option<Box<dyn Any>>;
+
+// How the comps of one type are laid out, picked by `Comp::storage`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Storage {
+    // a slot for every entity, for comps most entities have like `Transform`
+    Dense,
+    // only the entities that have the comp, for the few cameras, lights and effects
+    Sparse,
+}
+
+// The world change ticks a comp was inserted and last written at, see `World::change_tick`.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub struct CompTicks {
+    pub added: u64,
+    pub changed: u64,
+}
+
+// The comps of one type, indexed by the entity slot in the world.
+pub enum CompColumn {
+    Dense {
+        comps: Vec<CompSlot>,
+        ticks: Vec<CompTicks>,
+    },
+    Sparse {
+        // entity slot to position in `indices`, `comps` and `ticks`
+        positions: HashMap<usize, usize>,
+        indices: Vec<usize>,
+        comps: Vec<CompSlot>,
+        ticks: Vec<CompTicks>,
+    },
+}
+
+impl CompColumn {
+    pub fn new(storage: Storage, capacity: usize) -> Self {
+        match storage {
+            Storage::Dense => {
+                let mut comps = Vec::new();
+                comps.resize_with(capacity, || None);
+                Self::Dense {
+                    comps,
+                    ticks: vec![CompTicks::default(); capacity],
+                }
+            }
+            Storage::Sparse => Self::Sparse {
+                positions: HashMap::new(),
+                indices: vec![],
+                comps: vec![],
+                ticks: vec![],
+            },
+        }
+    }
+
+    pub fn storage(&self) -> Storage {
+        match self {
+            Self::Dense { .. } => Storage::Dense,
+            Self::Sparse { .. } => Storage::Sparse,
+        }
+    }
+
+    // The world grew, sparse columns don't care.
+    pub fn resize(&mut self, capacity: usize) {
+        if let Self::Dense { comps, ticks } = self {
+            comps.resize_with(capacity, || None);
+            ticks.resize(capacity, CompTicks::default());
+        }
+    }
+
+    pub fn get(&self, index: usize) -> Option<&CompSlot> {
+        match self {
+            Self::Dense { comps, .. } => comps.get(index),
+            Self::Sparse {
+                positions, comps, ..
+            } => positions.get(&index).map(|&position| &comps[position]),
+        }
+    }
+
+    pub fn get_mut(&mut self, index: usize) -> Option<&mut CompSlot> {
+        match self {
+            Self::Dense { comps, .. } => comps.get_mut(index),
+            Self::Sparse {
+                positions, comps, ..
+            } => positions.get(&index).map(|&position| &mut comps[position]),
+        }
+    }
+
+    // None when the entity doesn't have the comp.
+    pub fn ticks(&self, index: usize) -> Option<CompTicks> {
+        let (slot, ticks) = match self {
+            Self::Dense { comps, ticks } => (comps.get(index)?, ticks[index]),
+            Self::Sparse {
+                positions,
+                comps,
+                ticks,
+                ..
+            } => {
+                let position = *positions.get(&index)?;
+                (&comps[position], ticks[position])
+            }
+        };
+        slot.is_some().then_some(ticks)
+    }
+
+    // Leaves the comps alone, queries stamp the ones they handed out. The ticks of an empty slot
+    // are never read, inserting overwrites them.
+    pub fn set_changed(&mut self, index: usize, tick: u64) {
+        let ticks = match self {
+            Self::Dense { ticks, .. } => ticks.get_mut(index),
+            Self::Sparse {
+                positions, ticks, ..
+            } => positions.get(&index).map(|&position| &mut ticks[position]),
+        };
+        if let Some(ticks) = ticks {
+            ticks.changed = tick;
+        }
+    }
+
+    // Replacing a comp counts as a change, not as adding it.
+    pub fn insert(&mut self, index: usize, comp: Box<dyn Any>, tick: u64) {
+        let added = |previous: &CompSlot, ticks: &CompTicks| match previous {
+            Some(_) => CompTicks {
+                changed: tick,
+                ..*ticks
+            },
+            None => CompTicks {
+                added: tick,
+                changed: tick,
+            },
+        };
+        match self {
+            Self::Dense { comps, ticks } => {
+                ticks[index] = added(&comps[index], &ticks[index]);
+                comps[index] = Some(comp);
+            }
+            Self::Sparse {
+                positions,
+                indices,
+                comps,
+                ticks,
+            } => match positions.get(&index) {
+                Some(&position) => {
+                    ticks[position] = added(&comps[position], &ticks[position]);
+                    comps[position] = Some(comp);
+                }
+                None => {
+                    positions.insert(index, indices.len());
+                    indices.push(index);
+                    comps.push(Some(comp));
+                    ticks.push(CompTicks {
+                        added: tick,
+                        changed: tick,
+                    });
+                }
+            },
+        }
+    }
+
+    pub fn remove(&mut self, index: usize) {
+        match self {
+            Self::Dense { comps, .. } => {
+                if let Some(slot) = comps.get_mut(index) {
+                    *slot = None;
+                }
+            }
+            Self::Sparse {
+                positions,
+                indices,
+                comps,
+                ticks,
+            } => {
+                // the last comp moves into the hole
+                if let Some(position) = positions.remove(&index) {
+                    indices.swap_remove(position);
+                    comps.swap_remove(position);
+                    ticks.swap_remove(position);
+                    if let Some(&moved) = indices.get(position) {
+                        positions.insert(moved, position);
+                    }
+                }
+            }
+        }
+    }
+
+    // Entity slots with the comp in ascending order, None for dense columns where every slot
+    // has to be looked at.
+    pub fn indices(&self) -> Option<Vec<usize>> {
+        match self {
+            Self::Dense { .. } => None,
+            Self::Sparse { indices, .. } => {
+                let mut indices = indices.clone();
+                indices.sort_unstable();
+                Some(indices)
+            }
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        match self {
+            Self::Dense { comps, .. } => comps.iter().filter(|slot| slot.is_some()).count(),
+            Self::Sparse { indices, .. } => indices.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ecs::{Comp, Query, Storage, World};
+
+    struct Position(i32);
+    struct Marker(i32);
+
+    impl Comp for Position {}
+    impl Comp for Marker {
+        fn storage() -> Storage {
+            Storage::Sparse
+        }
+    }
+
+    #[test]
+    fn sparse_and_dense_query_together() {
+        let mut world = World::new();
+        let entities = (0..10)
+            .map(|i| {
+                let entity = world.add_entity();
+                world.add_entity_comp(entity, Position(i));
+                if i % 3 == 0 {
+                    world.add_entity_comp(entity, Marker(i * 10));
+                }
+                entity
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(world.comp_count::<Marker>(), 4);
+
+        let pairs = Query::<(&Position, &Marker)>::new(&mut world)
+            .map(|(position, marker)| (position.0, marker.0))
+            .collect::<Vec<_>>();
+        assert_eq!(pairs, vec![(0, 0), (3, 30), (6, 60), (9, 90)]);
+
+        let optional = Query::<(&Position, Option<&Marker>)>::new(&mut world)
+            .filter(|(_, marker)| marker.is_some())
+            .count();
+        assert_eq!(optional, 4);
+
+        // removing from the middle moves the last sparse comp, lookups still find it
+        world.remove_entity(entities[3]);
+        assert!(world.get_entity_comp::<Marker>(entities[9]).is_some());
+        assert!(!world.has_entity_comp::<Marker>(entities[1]));
+        for marker in Query::<&mut Marker>::new(&mut world) {
+            marker.0 += 1;
+        }
+        let markers = Query::<&Marker>::new(&mut world)
+            .map(|marker| marker.0)
+            .collect::<Vec<_>>();
+        assert_eq!(markers, vec![1, 61, 91]);
+    }
+}
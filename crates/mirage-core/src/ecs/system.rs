@@ -0,0 +1,121 @@
+use crate::ecs::{AppState, Commands, Comp, EventReader, EventRegistry, EventWriter, World};
+use std::any::TypeId;
+use std::cell::{Cell, RefCell};
+use std::rc::Rc;
+
+type SentEvent = Box<dyn FnOnce(&EventRegistry)>;
+
+pub struct SystemState {
+    pub delta_time: f32,
+    pub elapsed_time: f32,
+    pub app_state: AppState,
+    // applied by the scheduler before the next tick
+    pub(crate) next_app_state: Cell<Option<AppState>>,
+    // applied by the scheduler after the system's batch
+    pub commands: Commands,
+    pub(crate) events: Rc<EventRegistry>,
+    // handed to `events` with the commands
+    pub(crate) sent_events: RefCell<Vec<SentEvent>>,
+}
+
+impl SystemState {
+    pub fn new(
+        delta_time: f32,
+        elapsed_time: f32,
+        app_state: AppState,
+        events: Rc<EventRegistry>,
+    ) -> Self {
+        Self {
+            delta_time,
+            elapsed_time,
+            app_state,
+            next_app_state: Cell::new(None),
+            commands: Commands::new(),
+            events,
+            sent_events: RefCell::new(vec![]),
+        }
+    }
+
+    pub fn set_app_state(&self, app_state: AppState) {
+        self.next_app_state.set(Some(app_state));
+    }
+
+    pub fn event_writer<T: 'static>(&self) -> EventWriter<'_, T> {
+        EventWriter::new(self)
+    }
+
+    pub fn event_reader<T: 'static>(&self) -> EventReader<T> {
+        EventReader::new(self)
+    }
+
+    pub fn send_event<T: 'static>(&self, event: T) {
+        self.event_writer().send(event);
+    }
+
+    // Applies the commands and hands the sent events over, in recording order.
+    pub(crate) fn apply(&self, world: &mut World) {
+        self.commands.apply(world);
+        let sent_events = std::mem::take(&mut *self.sent_events.borrow_mut());
+        sent_events.into_iter().for_each(|send| send(&self.events));
+    }
+}
+
+// The comps and resources a system reads and writes. Systems whose access doesn't conflict may run in any
+// order, or at the same time, and must give the same result.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SystemAccess {
+    reads: Vec<TypeId>,
+    writes: Vec<TypeId>,
+    // touches anything in the world, what systems added without access get
+    exclusive: bool,
+}
+
+impl SystemAccess {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn exclusive() -> Self {
+        Self {
+            exclusive: true,
+            ..Self::default()
+        }
+    }
+
+    pub fn read<T: Comp>(mut self) -> Self {
+        self.reads.push(T::id());
+        self
+    }
+
+    pub fn write<T: Comp>(mut self) -> Self {
+        self.writes.push(T::id());
+        self
+    }
+
+    // Resources share the lists with comps, a type used as both only conflicts more than needed.
+    pub fn read_resource<T: 'static>(mut self) -> Self {
+        self.reads.push(TypeId::of::<T>());
+        self
+    }
+
+    pub fn write_resource<T: 'static>(mut self) -> Self {
+        self.writes.push(TypeId::of::<T>());
+        self
+    }
+
+    pub fn is_exclusive(&self) -> bool {
+        self.exclusive
+    }
+
+    pub fn conflicts_with(&self, other: &SystemAccess) -> bool {
+        if self.exclusive || other.exclusive {
+            return true;
+        }
+        let writes_read = |a: &SystemAccess, b: &SystemAccess| {
+            a.writes
+                .iter()
+                .any(|id| b.writes.contains(id) || b.reads.contains(id))
+        };
+        writes_read(self, other) || writes_read(other, self)
+    }
+}
@@ -0,0 +1,265 @@
+use crate::ecs::*;
+use ahash::{HashMap, HashMapExt};
+use std::any::{Any, TypeId};
+use std::cell::RefCell;
+use std::panic::Location;
+use std::sync::atomic::{AtomicU32, Ordering};
+
+pub struct EntityIndex {
+    pub index: usize,
+}
+
+pub struct World {
+    entity_id_index_map: HashMap<u32, EntityIndex>,
+    components_map: HashMap<TypeId, CompColumn>,
+    // slots of removed entities, reused before new ones
+    free_indices: Vec<usize>,
+    // slots ever used, queries don't look past it
+    index_count: usize,
+    // length of every dense component column, grown together
+    capacity: usize,
+    // singletons by type, see `Res` and `ResMut`
+    resources: HashMap<TypeId, Box<dyn Any>>,
+    // callers of `entities` reported so far, Some while a `DeterminismAudit` runs
+    audited_callers: RefCell<Option<Vec<&'static Location<'static>>>>,
+    // what comp writes are stamped with, advanced after every system run
+    change_tick: u64,
+    // `Changed` and `Added` filters pass comps written after it
+    last_change_tick: u64,
+}
+
+impl World {
+    pub fn new() -> World {
+        World {
+            entity_id_index_map: HashMap::new(),
+            components_map: HashMap::new(),
+            free_indices: vec![],
+            index_count: 0,
+            capacity: 512,
+            resources: HashMap::new(),
+            audited_callers: RefCell::new(None),
+            change_tick: 1,
+            last_change_tick: 0,
+        }
+    }
+
+    // Ids are unique across worlds, so an entity can be handed out before it is inserted,
+    // e.g. by `Commands::spawn`.
+    pub fn reserve_entity() -> Entity {
+        static COUNT: AtomicU32 = AtomicU32::new(0);
+        Entity::new(COUNT.fetch_add(1, Ordering::Relaxed))
+    }
+
+    pub fn add_entity(&mut self) -> Entity {
+        let entity = Self::reserve_entity();
+        self.insert_entity(entity);
+        entity
+    }
+
+    pub fn insert_entity(&mut self, entity: Entity) {
+        if self.entity_id_index_map.contains_key(&entity.id) {
+            return;
+        }
+
+        let index = self.free_indices.pop().unwrap_or_else(|| {
+            self.index_count += 1;
+            self.index_count - 1
+        });
+        if index >= self.capacity {
+            self.capacity *= 2;
+            let capacity = self.capacity;
+            self.components_map
+                .values_mut()
+                .for_each(|components| components.resize(capacity));
+        }
+
+        let index = EntityIndex { index };
+        self.entity_id_index_map.insert(entity.id, index);
+    }
+
+    pub fn remove_entity(&mut self, entity: Entity) {
+        if let Some(index) = self.entity_id_index_map.remove(&entity.id) {
+            self.components_map
+                .values_mut()
+                .for_each(|components| components.remove(index.index));
+            self.free_indices.push(index.index);
+        }
+    }
+
+    pub fn contains_entity(&self, entity: Entity) -> bool {
+        self.entity_id_index_map.contains_key(&entity.id)
+    }
+
+    pub fn add_entity_comp<T: Comp>(&mut self, entity: Entity, comp: T) {
+        if let Some(index) = self.entity_id_index_map.get(&entity.id) {
+            let index = index.index;
+            let id = TypeId::of::<T>();
+            let capacity = self.capacity;
+            let tick = self.change_tick;
+            let comps = self
+                .components_map
+                .entry(id)
+                .or_insert_with(|| CompColumn::new(T::storage(), capacity));
+
+            comps.insert(index, Box::new(comp), tick);
+        }
+    }
+
+    pub fn remove_entity_comp<T: Comp>(&mut self, entity: Entity) {
+        if let Some(index) = self.entity_id_index_map.get(&entity.id) {
+            let index = index.index;
+            if let Some(comps) = self.get_comps_mut::<T>() {
+                comps.remove(index);
+            }
+        }
+    }
+
+    // In hash order, which changes from run to run. Anything the simulation depends on should use
+    // `ordered_entities` or a query instead, an audited world warns about every caller.
+    #[track_caller]
+    pub fn entities(&self) -> Vec<Entity> {
+        if let Some(callers) = self.audited_callers.borrow_mut().as_mut() {
+            let caller = Location::caller();
+            if !callers.contains(&caller) {
+                log::warn!("entities iterated in hash order at {}", caller);
+                callers.push(caller);
+            }
+        }
+        self.entity_id_index_map
+            .keys()
+            .map(|id| Entity::new(*id))
+            .collect()
+    }
+
+    // By component slot, the order queries visit them in. The same in every run that spawned and
+    // removed the same entities in the same order.
+    pub fn ordered_entities(&self) -> Vec<Entity> {
+        let mut entities = self
+            .entity_id_index_map
+            .iter()
+            .map(|(id, index)| (index.index, Entity::new(*id)))
+            .collect::<Vec<_>>();
+        entities.sort_unstable_by_key(|(index, _)| *index);
+        entities.into_iter().map(|(_, entity)| entity).collect()
+    }
+
+    // Reports the callers of `entities`, see `DeterminismAudit`.
+    pub fn set_iteration_audit(&self, enabled: bool) {
+        *self.audited_callers.borrow_mut() = enabled.then(Vec::new);
+    }
+
+    pub fn entity_count(&self) -> usize {
+        self.entity_id_index_map.len()
+    }
+
+    // Component slots in use, removed entities leave holes until their slot is reused.
+    pub fn index_count(&self) -> usize {
+        self.index_count
+    }
+
+    pub fn get_entity_comp<T>(&self, entity: Entity) -> Option<&T>
+    where
+        T: Comp,
+    {
+        let index = self.entity_id_index_map.get(&entity.id)?.index;
+        let comp = self.get_comps::<T>()?.get(index)?.as_ref()?;
+        comp.downcast_ref::<T>()
+    }
+
+    // Counts as a change, whether or not the caller writes to it.
+    pub fn get_entity_comp_mut<T: Comp>(&mut self, entity: Entity) -> Option<&mut T> {
+        let index = self.entity_id_index_map.get(&entity.id)?.index;
+        let tick = self.change_tick;
+        let comps = self.get_comps_mut::<T>()?;
+        comps.set_changed(index, tick);
+        let comp = comps.get_mut(index)?.as_mut()?;
+        comp.downcast_mut::<T>()
+    }
+
+    pub fn has_entity_comp<T: Comp>(&self, entity: Entity) -> bool {
+        if let Some(index) = self.entity_id_index_map.get(&entity.id) {
+            let index = index.index;
+            self.get_comps::<T>()
+                .is_some_and(|comps| comps.get(index).is_some_and(|comp| comp.is_some()))
+        } else {
+            false
+        }
+    }
+
+    // Entities that have the comp.
+    pub fn comp_count<T: Comp>(&self) -> usize {
+        self.get_comps::<T>().map_or(0, |comps| comps.len())
+    }
+
+    pub fn get_entity_comp_ticks<T: Comp>(&self, entity: Entity) -> Option<CompTicks> {
+        let index = self.entity_id_index_map.get(&entity.id)?.index;
+        self.get_comps::<T>()?.ticks(index)
+    }
+
+    pub fn change_tick(&self) -> u64 {
+        self.change_tick
+    }
+
+    pub fn last_change_tick(&self) -> u64 {
+        self.last_change_tick
+    }
+
+    // Outside the scheduler, e.g. while extracting the frame, `Changed` and `Added` queries see the
+    // writes since the last call. Systems see the writes since they last ran instead.
+    pub fn clear_trackers(&mut self) {
+        self.last_change_tick = self.change_tick;
+        self.change_tick += 1;
+    }
+
+    // Runs a system that last ran at `last_run`, returns the tick it runs at.
+    pub(crate) fn run_system(&mut self, last_run: u64, run: impl FnOnce(&mut World)) -> u64 {
+        let outside = std::mem::replace(&mut self.last_change_tick, last_run);
+        run(self);
+        self.last_change_tick = outside;
+        // its own writes are changes for the next run
+        self.change_tick += 1;
+        self.change_tick - 1
+    }
+
+    pub fn get_comps<T: Comp>(&self) -> Option<&CompColumn> {
+        let id = TypeId::of::<T>();
+        self.components_map.get(&id)
+    }
+
+    pub fn get_comps_mut<T: Comp>(&mut self) -> Option<&mut CompColumn> {
+        let id = TypeId::of::<T>();
+        self.components_map.get_mut(&id)
+    }
+
+    // Replaces the one of the same type.
+    pub fn insert_resource<T: 'static>(&mut self, resource: T) {
+        self.resources.insert(TypeId::of::<T>(), Box::new(resource));
+    }
+
+    pub fn remove_resource<T: 'static>(&mut self) -> Option<T> {
+        let resource = self.resources.remove(&TypeId::of::<T>())?;
+        resource.downcast::<T>().ok().map(|resource| *resource)
+    }
+
+    pub fn has_resource<T: 'static>(&self) -> bool {
+        self.resources.contains_key(&TypeId::of::<T>())
+    }
+
+    pub fn get_resource<T: 'static>(&self) -> Option<&T> {
+        self.resources.get(&TypeId::of::<T>())?.downcast_ref::<T>()
+    }
+
+    pub fn get_resource_mut<T: 'static>(&mut self) -> Option<&mut T> {
+        self.resources
+            .get_mut(&TypeId::of::<T>())?
+            .downcast_mut::<T>()
+    }
+
+    pub fn dispose(&mut self) {}
+}
+
+impl Default for World {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,6 @@
+// The parts of the engine that need neither a window nor a GPU. The mirage crate re-exports them
+// under their old paths, `crate::math` and `crate::scene::ecs`.
+pub mod cpu_profiler;
+pub mod ecs;
+pub mod math;
+pub mod serialize;
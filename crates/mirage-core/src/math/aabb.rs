@@ -0,0 +1,153 @@
+#[cfg(feature = "simd")]
+use super::simd::F32x4;
+use super::{Mat4, Ray, Vec3};
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Aabb {
+    pub min: Vec3,
+    pub max: Vec3,
+}
+
+impl Aabb {
+    #[inline]
+    pub fn new(min: Vec3, max: Vec3) -> Self {
+        Self { min, max }
+    }
+
+    // inverted, so that any union with it yields the other box
+    #[inline]
+    pub fn empty() -> Self {
+        Self::new(
+            Vec3::new(f32::MAX, f32::MAX, f32::MAX),
+            Vec3::new(f32::MIN, f32::MIN, f32::MIN),
+        )
+    }
+
+    pub fn from_points(points: impl IntoIterator<Item = Vec3>) -> Self {
+        points
+            .into_iter()
+            .fold(Self::empty(), |aabb, p| aabb.union(Self::new(p, p)))
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.min.x > self.max.x || self.min.y > self.max.y || self.min.z > self.max.z
+    }
+
+    #[inline]
+    pub fn union(&self, other: Self) -> Self {
+        Self::new(
+            Vec3::new(
+                self.min.x.min(other.min.x),
+                self.min.y.min(other.min.y),
+                self.min.z.min(other.min.z),
+            ),
+            Vec3::new(
+                self.max.x.max(other.max.x),
+                self.max.y.max(other.max.y),
+                self.max.z.max(other.max.z),
+            ),
+        )
+    }
+
+    #[inline]
+    pub fn center(&self) -> Vec3 {
+        (self.min + self.max) * 0.5
+    }
+
+    #[inline]
+    pub fn size(&self) -> Vec3 {
+        self.max - self.min
+    }
+
+    #[inline]
+    pub fn surface_area(&self) -> f32 {
+        if self.is_empty() {
+            return 0.0;
+        }
+        let size = self.size();
+        2.0 * (size.x * size.y + size.y * size.z + size.z * size.x)
+    }
+
+    #[inline]
+    pub fn intersects(&self, other: &Self) -> bool {
+        self.min.x <= other.max.x
+            && self.max.x >= other.min.x
+            && self.min.y <= other.max.y
+            && self.max.y >= other.min.y
+            && self.min.z <= other.max.z
+            && self.max.z >= other.min.z
+    }
+
+    // Box enclosing the transformed box, per axis the min/max contribution of each column (Arvo).
+    pub fn transform(&self, matrix: &Mat4) -> Self {
+        #[cfg(feature = "simd")]
+        {
+            // a column at a time, the w lanes are ignored
+            let translation = F32x4::new(matrix[3]);
+            let (mut min, mut max) = (translation, translation);
+            let from_min = [self.min.x, self.min.y, self.min.z];
+            let from_max = [self.max.x, self.max.y, self.max.z];
+            for col in 0..3 {
+                let column = F32x4::new(matrix[col]);
+                let a = column.mul(F32x4::splat(from_min[col]));
+                let b = column.mul(F32x4::splat(from_max[col]));
+                min = min.add(a.min(b));
+                max = max.add(a.max(b));
+            }
+            let ([min_x, min_y, min_z, _], [max_x, max_y, max_z, _]) = (min.lanes(), max.lanes());
+            Self::new(
+                Vec3::new(min_x, min_y, min_z),
+                Vec3::new(max_x, max_y, max_z),
+            )
+        }
+        #[cfg(not(feature = "simd"))]
+        {
+            self.transform_scalar(matrix)
+        }
+    }
+
+    #[cfg_attr(feature = "simd", allow(dead_code))]
+    pub(crate) fn transform_scalar(&self, matrix: &Mat4) -> Self {
+        let mut min = [matrix[3][0], matrix[3][1], matrix[3][2]];
+        let mut max = min;
+        let from_min = [self.min.x, self.min.y, self.min.z];
+        let from_max = [self.max.x, self.max.y, self.max.z];
+        for col in 0..3 {
+            for row in 0..3 {
+                let a = matrix[col][row] * from_min[col];
+                let b = matrix[col][row] * from_max[col];
+                min[row] += a.min(b);
+                max[row] += a.max(b);
+            }
+        }
+        Self::new(Vec3::from(min), Vec3::from(max))
+    }
+
+    // Slab test, distance along the ray to the entry point (0 when it starts inside).
+    pub fn intersect_ray(&self, ray: &Ray, max_distance: f32) -> Option<f32> {
+        let origin = [ray.origin.x, ray.origin.y, ray.origin.z];
+        let direction = [ray.direction.x, ray.direction.y, ray.direction.z];
+        let min = [self.min.x, self.min.y, self.min.z];
+        let max = [self.max.x, self.max.y, self.max.z];
+
+        let mut t_min = 0.0f32;
+        let mut t_max = max_distance;
+        for axis in 0..3 {
+            let inv = 1.0 / direction[axis];
+            let t0 = (min[axis] - origin[axis]) * inv;
+            let t1 = (max[axis] - origin[axis]) * inv;
+            t_min = t_min.max(t0.min(t1));
+            t_max = t_max.min(t0.max(t1));
+        }
+
+        (t_min <= t_max).then_some(t_min)
+    }
+}
+
+impl Default for Aabb {
+    #[inline]
+    fn default() -> Self {
+        Self::empty()
+    }
+}
@@ -39,7 +39,7 @@ impl Default for Euler {
 
 impl From<Quat> for Euler {
     fn from(value: Quat) -> Self {
-        Euler::new(value.x, value.y, value.z)
+        Euler::from(Mat4::from(value))
     }
 }
 
@@ -0,0 +1,49 @@
+use super::{Aabb, Mat4, Vec3, Vec4};
+
+// The six clip planes of a view projection matrix, normals pointing inwards.
+#[derive(Debug, Copy, Clone)]
+pub struct Frustum {
+    pub planes: [Vec4; 6],
+}
+
+impl Frustum {
+    // Gribb/Hartmann plane extraction for Vulkan clip space (0 <= z <= w). With reversed or
+    // infinite depth the near/far planes swap or degenerate, which only makes them pass everything.
+    pub fn from_matrix(view_projection: &Mat4) -> Self {
+        let row = |index: usize| {
+            let row = view_projection.row(index);
+            Vec4::new(row[0], row[1], row[2], row[3])
+        };
+        let (x, y, z, w) = (row(0), row(1), row(2), row(3));
+
+        let planes = [w + x, w - x, w + y, w - y, z, w - z].map(|plane| {
+            let len = (plane.x * plane.x + plane.y * plane.y + plane.z * plane.z).sqrt();
+            if len > 0.0 {
+                plane / len
+            } else {
+                Vec4::new(0.0, 0.0, 0.0, 1.0)
+            }
+        });
+
+        Self { planes }
+    }
+
+    // Conservative, boxes near a frustum corner may pass while being outside.
+    pub fn intersects_aabb(&self, aabb: &Aabb) -> bool {
+        let pick = |normal: f32, min: f32, max: f32| if normal >= 0.0 { max } else { min };
+        self.planes.iter().all(|plane| {
+            // the box corner furthest along the plane normal
+            let x = pick(plane.x, aabb.min.x, aabb.max.x);
+            let y = pick(plane.y, aabb.min.y, aabb.max.y);
+            let z = pick(plane.z, aabb.min.z, aabb.max.z);
+            plane.x * x + plane.y * y + plane.z * z + plane.w >= 0.0
+        })
+    }
+
+    // Conservative the same way, spheres off a frustum corner may pass.
+    pub fn intersects_sphere(&self, center: Vec3, radius: f32) -> bool {
+        self.planes.iter().all(|plane| {
+            plane.x * center.x + plane.y * center.y + plane.z * center.z + plane.w >= -radius
+        })
+    }
+}
@@ -1,4 +1,8 @@
+#[cfg(feature = "simd")]
+use super::Mat4;
 use num_traits::{Num, One, Signed};
+#[cfg(feature = "simd")]
+use std::any::Any;
 use std::mem;
 use std::ops::{Add, Div, Index, IndexMut, Mul, Neg, Sub};
 
@@ -192,10 +196,20 @@ impl<T: Num + Default + Copy, const C: usize, const R: usize> Sub for Mat<T, C,
     }
 }
 
-impl<T: Num + Default + Copy, const C: usize, const R: usize> Mul for Mat<T, C, R> {
+impl<T: Num + Default + Copy + 'static, const C: usize, const R: usize> Mul for Mat<T, C, R> {
     type Output = Self;
 
     fn mul(self, rhs: Self) -> Self::Output {
+        // resolved at compile time, the generic impl can't be specialized for Mat4
+        #[cfg(feature = "simd")]
+        if let (Some(lhs), Some(rhs)) = (
+            (&self as &dyn Any).downcast_ref::<Mat4>(),
+            (&rhs as &dyn Any).downcast_ref::<Mat4>(),
+        ) {
+            let product = super::simd::mul_mat4(lhs, rhs);
+            return *(&product as &dyn Any).downcast_ref::<Self>().unwrap();
+        }
+
         let mut mat = Self::default();
         let count = C.min(R);
         for col in 0..C {
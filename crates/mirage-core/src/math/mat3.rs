@@ -13,6 +13,7 @@ pub struct Mat3 {
 impl Mat3 {
     #[rustfmt::skip]
     #[inline]
+    #[allow(clippy::too_many_arguments)]
     pub fn new(c0r0: f32, c0r1: f32, c0r2: f32, c1r0: f32, c1r1: f32, c1r2: f32, c2r0: f32, c2r1: f32, c2r2: f32) -> Self {
         Self {
             c0: Vec3::new(c0r0, c0r1, c0r2),
@@ -1,3 +1,5 @@
+#[cfg(feature = "simd")]
+use super::simd;
 use super::{Euler, EulerOrder, Mat, Quat, Vec3, Vec4};
 
 pub type Mat4 = Mat<f32, 4, 4>;
@@ -5,6 +7,7 @@ pub type Mat4 = Mat<f32, 4, 4>;
 impl Mat4 {
     #[rustfmt::skip]
     #[inline]
+    #[allow(clippy::too_many_arguments)]
     pub fn new(c0r0: f32, c0r1: f32, c0r2: f32, c0r3: f32, c1r0: f32, c1r1: f32, c1r2: f32, c1r3: f32, c2r0: f32, c2r1: f32, c2r2: f32, c2r3: f32, c3r0: f32, c3r1: f32, c3r2: f32, c3r3: f32) -> Self {
         Self::from([
             [c0r0, c0r1, c0r2, c0r3],
@@ -159,6 +162,17 @@ impl Mat4 {
         ])
     }
 
+    // Regular depth with the far plane at infinity, from 0 at the near plane toward 1.
+    pub fn perspective_infinite_lh(fov_y: f32, aspect: f32, near: f32) -> Self {
+        let tan_theta_recip = 1.0 / (fov_y / 2.0).tan();
+        Self::from([
+            [tan_theta_recip / aspect, 0.0, 0.0, 0.0],
+            [0.0, -tan_theta_recip, 0.0, 0.0],
+            [0.0, 0.0, 1.0, 1.0],
+            [0.0, 0.0, -near, 0.0],
+        ])
+    }
+
     /**
      * RH; Y downward; Z range [0, 1]
      * orthographic matrix
@@ -257,31 +271,87 @@ impl Mat4 {
         ])
     }
 
+    // Regular depth with the far plane at infinity, from 0 at the near plane toward 1.
+    pub fn perspective_infinite_rh(fov_y: f32, aspect: f32, near: f32) -> Self {
+        let tan_theta_recip = 1.0 / (fov_y / 2.0).tan();
+        Self::from([
+            [tan_theta_recip / aspect, 0.0, 0.0, 0.0],
+            [0.0, -tan_theta_recip, 0.0, 0.0],
+            [0.0, 0.0, -1.0, -1.0],
+            [0.0, 0.0, -near, 0.0],
+        ])
+    }
+
     #[inline]
-    pub fn compose(location: Vec3, rotation: Euler, scale: Vec3) -> Self {
-        let mut mat = Self::rotate(rotation) * Self::scale(scale);
+    pub fn compose(location: Vec3, rotation: Quat, scale: Vec3) -> Self {
+        let mut mat = Self::from(rotation) * Self::scale(scale);
         mat[3] = [location.x, location.y, location.z, 1.0];
         mat
     }
 
+    // Inverse of `compose`, shear is lost. A mirrored matrix gets a negative x scale.
     #[inline]
-    pub fn decompose(mat4: Self) -> (Vec3, Euler, Vec3) {
-        (
-            Vec3::new(1.0, 0.0, 1.0),
-            // Quat {
-            //     x: 0.0,
-            //     y: 0.0,
-            //     z: 0.0,
-            //     s: 1.0,
-            // },
-            Euler {
-                x: 0.0,
-                y: 0.0,
-                z: 0.0,
-                order: EulerOrder::ZYX,
-            },
-            Vec3::new(1.0, 0.0, 1.0),
-        )
+    pub fn decompose(mat4: Self) -> (Vec3, Quat, Vec3) {
+        let location = Vec3::new(mat4[3][0], mat4[3][1], mat4[3][2]);
+
+        let axis = |index: usize| Vec3::new(mat4[index][0], mat4[index][1], mat4[index][2]);
+        let mut scale = Vec3::new(axis(0).len(), axis(1).len(), axis(2).len());
+        if axis(0).cross(axis(1)).dot(axis(2)) < 0.0 {
+            scale.x = -scale.x;
+        }
+        if scale.x == 0.0 || scale.y == 0.0 || scale.z == 0.0 {
+            return (location, Quat::identity(), scale);
+        }
+
+        let mut rotation = Self::identity();
+        for (index, scale) in [scale.x, scale.y, scale.z].into_iter().enumerate() {
+            let axis = axis(index) / scale;
+            rotation[index] = [axis.x, axis.y, axis.z, 0.0];
+        }
+        (location, Quat::from(rotation).normalize(), scale)
+    }
+
+    // w = 1, affine matrices only
+    #[inline]
+    pub fn transform_point(&self, point: Vec3) -> Vec3 {
+        #[cfg(feature = "simd")]
+        {
+            let [x, y, z, _] = simd::mul_vec4(self, [point.x, point.y, point.z, 1.0]);
+            Vec3::new(x, y, z)
+        }
+        #[cfg(not(feature = "simd"))]
+        {
+            self.transform_vector(point) + Vec3::new(self[3][0], self[3][1], self[3][2])
+        }
+    }
+
+    // w = 1 with the full matrix, e.g. into clip space by a projection. No divide by w.
+    #[inline]
+    pub fn project_point(&self, point: Vec3) -> Vec4 {
+        #[cfg(feature = "simd")]
+        {
+            Vec4::from(simd::mul_vec4(self, [point.x, point.y, point.z, 1.0]))
+        }
+        #[cfg(not(feature = "simd"))]
+        {
+            let column = |index: usize| Vec4::from(self.col(index));
+            column(0) * point.x + column(1) * point.y + column(2) * point.z + column(3)
+        }
+    }
+
+    // w = 0, the translation is ignored
+    #[inline]
+    pub fn transform_vector(&self, vector: Vec3) -> Vec3 {
+        #[cfg(feature = "simd")]
+        {
+            let [x, y, z, _] = simd::mul_vec4(self, [vector.x, vector.y, vector.z, 0.0]);
+            Vec3::new(x, y, z)
+        }
+        #[cfg(not(feature = "simd"))]
+        {
+            let column = |index: usize| Vec3::new(self[index][0], self[index][1], self[index][2]);
+            column(0) * vector.x + column(1) * vector.y + column(2) * vector.z
+        }
     }
 
     pub fn invert_svd(&self) -> Self {
@@ -290,6 +360,20 @@ impl Mat4 {
 
     #[inline]
     pub fn invert(&self) -> Self {
+        #[cfg(feature = "simd")]
+        {
+            simd::invert_mat4(self)
+        }
+        #[cfg(not(feature = "simd"))]
+        {
+            self.invert_scalar()
+        }
+    }
+
+    // Cofactors over the determinant, what `invert` does without the `simd` feature.
+    #[cfg_attr(feature = "simd", allow(dead_code))]
+    #[inline]
+    fn invert_scalar(&self) -> Self {
         let c0 = Vec4::from(self.col(0));
         let c1 = Vec4::from(self.col(1));
         let c2 = Vec4::from(self.col(2));
@@ -302,9 +386,9 @@ impl Mat4 {
             c1.y * xy_cof + c1.z * xz_cof + c1.w * xw_cof
         };
         let c0r1_cof = {
-            let yz_cof = c2.w * c3.x - c2.x * c3.w;
-            let yw_cof = c2.x * c3.z - c2.z * c3.x;
-            let yx_cof = c2.z * c3.w - c2.w * c3.z;
+            let yz_cof = c2.x * c3.w - c2.w * c3.x;
+            let yw_cof = c2.z * c3.x - c2.x * c3.z;
+            let yx_cof = c2.w * c3.z - c2.z * c3.w;
             c1.z * yz_cof + c1.w * yw_cof + c1.x * yx_cof
         };
         let c0r2_cof = {
@@ -314,9 +398,9 @@ impl Mat4 {
             c1.w * zw_cof + c1.x * zx_cof + c1.y * zy_cof
         };
         let c0r3_cof = {
-            let wx_cof = c2.y * c3.z - c2.z * c3.y;
-            let wy_cof = c2.z * c3.x - c2.x * c3.z;
-            let wz_cof = c2.x * c3.y - c2.y * c3.x;
+            let wx_cof = c2.z * c3.y - c2.y * c3.z;
+            let wy_cof = c2.x * c3.z - c2.z * c3.x;
+            let wz_cof = c2.y * c3.x - c2.x * c3.y;
             c1.x * wx_cof + c1.y * wy_cof + c1.z * wz_cof
         };
 
@@ -326,9 +410,9 @@ impl Mat4 {
         }
 
         let c1r0_cof = {
-            let xy_cof = c3.z * c0.w - c3.w * c0.z;
-            let xz_cof = c3.w * c0.y - c3.y * c0.w;
-            let xw_cof = c3.y * c0.z - c3.z * c0.y;
+            let xy_cof = c3.w * c0.z - c3.z * c0.w;
+            let xz_cof = c3.y * c0.w - c3.w * c0.y;
+            let xw_cof = c3.z * c0.y - c3.y * c0.z;
             c2.y * xy_cof + c2.z * xz_cof + c2.w * xw_cof
         };
         let c1r1_cof = {
@@ -338,15 +422,15 @@ impl Mat4 {
             c2.z * yz_cof + c2.w * yw_cof + c2.x * yx_cof
         };
         let c1r2_cof = {
-            let zw_cof = c3.x * c0.y - c3.y * c0.x;
-            let zx_cof = c3.y * c0.w - c3.w * c0.y;
-            let zy_cof = c3.w * c0.x - c3.x * c0.w;
+            let zw_cof = c3.y * c0.x - c3.x * c0.y;
+            let zx_cof = c3.w * c0.y - c3.y * c0.w;
+            let zy_cof = c3.x * c0.w - c3.w * c0.x;
             c2.w * zw_cof + c2.x * zx_cof + c2.y * zy_cof
         };
         let c1r3_cof = {
-            let wx_cof = c3.y * c0.z - c0.z * c3.y;
-            let wy_cof = c3.z * c0.x - c0.x * c3.z;
-            let wz_cof = c3.x * c0.y - c0.y * c3.x;
+            let wx_cof = c3.y * c0.z - c3.z * c0.y;
+            let wy_cof = c3.z * c0.x - c3.x * c0.z;
+            let wz_cof = c3.x * c0.y - c3.y * c0.x;
             c2.x * wx_cof + c2.y * wy_cof + c2.z * wz_cof
         };
         let c2r0_cof = {
@@ -356,9 +440,9 @@ impl Mat4 {
             c3.y * xy_cof + c3.z * xz_cof + c3.w * xw_cof
         };
         let c2r1_cof = {
-            let yz_cof = c0.w * c1.x - c0.x * c1.w;
-            let yw_cof = c0.x * c1.z - c0.z * c1.x;
-            let yx_cof = c0.z * c1.w - c0.w * c1.z;
+            let yz_cof = c0.x * c1.w - c0.w * c1.x;
+            let yw_cof = c0.z * c1.x - c0.x * c1.z;
+            let yx_cof = c0.w * c1.z - c0.z * c1.w;
             c3.z * yz_cof + c3.w * yw_cof + c3.x * yx_cof
         };
         let c2r2_cof = {
@@ -368,15 +452,15 @@ impl Mat4 {
             c3.w * zw_cof + c3.x * zx_cof + c3.y * zy_cof
         };
         let c2r3_cof = {
-            let wx_cof = c0.y * c1.z - c0.z * c1.y;
-            let wy_cof = c0.z * c1.x - c0.x * c1.z;
-            let wz_cof = c0.x * c1.y - c0.y * c1.x;
+            let wx_cof = c0.z * c1.y - c0.y * c1.z;
+            let wy_cof = c0.x * c1.z - c0.z * c1.x;
+            let wz_cof = c0.y * c1.x - c0.x * c1.y;
             c3.x * wx_cof + c3.y * wy_cof + c3.z * wz_cof
         };
         let c3r0_cof = {
-            let xy_cof = c1.z * c2.w - c1.w * c2.z;
-            let xz_cof = c1.w * c2.y - c1.y * c2.w;
-            let xw_cof = c1.y * c2.z - c1.z * c2.y;
+            let xy_cof = c1.w * c2.z - c1.z * c2.w;
+            let xz_cof = c1.y * c2.w - c1.w * c2.y;
+            let xw_cof = c1.z * c2.y - c1.y * c2.z;
             c0.y * xy_cof + c0.z * xz_cof + c0.w * xw_cof
         };
         let c3r1_cof = {
@@ -386,9 +470,9 @@ impl Mat4 {
             c0.z * yz_cof + c0.w * yw_cof + c0.x * yx_cof
         };
         let c3r2_cof = {
-            let zw_cof = c1.x * c2.y - c1.y * c2.x;
-            let zx_cof = c1.y * c2.w - c1.w * c2.y;
-            let zy_cof = c1.w * c2.x - c1.x * c2.w;
+            let zw_cof = c1.y * c2.x - c1.x * c2.y;
+            let zx_cof = c1.w * c2.y - c1.y * c2.w;
+            let zy_cof = c1.x * c2.w - c1.w * c2.x;
             c0.w * zw_cof + c0.x * zx_cof + c0.y * zy_cof
         };
         let c3r3_cof = {
@@ -495,7 +579,6 @@ impl From<Euler> for Mat4 {
                 ],
                 [0.0, 0.0, 0.0, 1.0],
             ]),
-            _ => unreachable!(),
         }
     }
 }
@@ -503,7 +586,28 @@ impl From<Euler> for Mat4 {
 impl From<Quat> for Mat4 {
     #[inline]
     fn from(value: Quat) -> Self {
-        Self::default()
+        let Quat { x, y, z, s } = value;
+        Self::from([
+            [
+                1.0 - 2.0 * (y * y + z * z),
+                2.0 * (x * y + s * z),
+                2.0 * (x * z - s * y),
+                0.0,
+            ],
+            [
+                2.0 * (x * y - s * z),
+                1.0 - 2.0 * (x * x + z * z),
+                2.0 * (y * z + s * x),
+                0.0,
+            ],
+            [
+                2.0 * (x * z + s * y),
+                2.0 * (y * z - s * x),
+                1.0 - 2.0 * (x * x + y * y),
+                0.0,
+            ],
+            [0.0, 0.0, 0.0, 1.0],
+        ])
     }
 }
 //
@@ -578,3 +682,272 @@ impl From<Quat> for Mat4 {
 //         }
 //     }
 // }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EPSILON: f32 = 1e-4;
+
+    fn assert_mat_eq(a: Mat4, b: Mat4) {
+        for col in 0..4 {
+            for row in 0..4 {
+                assert!(
+                    (a[col][row] - b[col][row]).abs() < EPSILON,
+                    "[{}][{}] differs\n{:?}\n{:?}",
+                    col,
+                    row,
+                    a,
+                    b
+                );
+            }
+        }
+    }
+
+    fn assert_vec_eq(a: Vec3, b: Vec3) {
+        assert!((a - b).len() < EPSILON, "{:?} != {:?}", a, b);
+    }
+
+    // q and -q are the same rotation
+    fn assert_quat_eq(a: Quat, b: Quat) {
+        assert!((a.dot(b).abs() - 1.0).abs() < EPSILON, "{:?} != {:?}", a, b);
+    }
+
+    fn rotations() -> Vec<Quat> {
+        vec![
+            Quat::identity(),
+            Quat::from_axis_angle(Vec3::new(1.0, 0.0, 0.0), 0.5),
+            Quat::from_axis_angle(Vec3::new(0.0, 1.0, 0.0), -2.0),
+            Quat::from_axis_angle(Vec3::new(0.0, 0.0, 1.0), std::f32::consts::PI),
+            Quat::from_axis_angle(Vec3::new(1.0, 2.0, -3.0).normalize(), 1.3),
+        ]
+    }
+
+    fn transforms() -> Vec<Mat4> {
+        let mut transforms = vec![];
+        for rotation in rotations() {
+            for scale in [
+                Vec3::new(1.0, 1.0, 1.0),
+                Vec3::new(2.0, 0.5, 3.0),
+                Vec3::new(0.1, 10.0, 1.0),
+            ] {
+                let location = Vec3::new(3.0, -4.0, 5.5);
+                transforms.push(Mat4::compose(location, rotation, scale));
+            }
+        }
+        transforms
+    }
+
+    #[test]
+    fn invert_undoes_the_matrix() {
+        let mut matrices = transforms();
+        matrices.push(Mat4::perspective_rh(1.0, 16.0 / 9.0, 0.1, 100.0));
+        matrices.push(Mat4::perspective_reversed_z_lh(0.8, 1.5, 0.5, 500.0));
+        matrices.push(Mat4::look_at_rh(
+            Vec3::new(1.0, 2.0, 3.0),
+            Vec3::new(0.0, 0.0, 0.0),
+            Vec3::new(0.0, 1.0, 0.0),
+        ));
+        for matrix in matrices {
+            assert_mat_eq(matrix * matrix.invert(), Mat4::identity());
+            assert_mat_eq(matrix.invert() * matrix, Mat4::identity());
+            assert_mat_eq(matrix.invert().invert(), matrix);
+        }
+    }
+
+    #[test]
+    fn invert_of_simple_transforms() {
+        let location = Vec3::new(1.0, -2.0, 3.0);
+        assert_mat_eq(
+            Mat4::translate(location).invert(),
+            Mat4::translate(location * -1.0),
+        );
+        assert_mat_eq(
+            Mat4::scale(Vec3::new(2.0, 4.0, 0.5)).invert(),
+            Mat4::scale(Vec3::new(0.5, 0.25, 2.0)),
+        );
+        // rotations are orthonormal, their inverse is the transpose
+        for rotation in rotations() {
+            let matrix = Mat4::from(rotation);
+            assert_mat_eq(matrix.invert(), matrix.transpose());
+        }
+    }
+
+    #[test]
+    fn determinant_is_the_volume_scale() {
+        let matrix = Mat4::compose(
+            Vec3::new(7.0, 8.0, 9.0),
+            Quat::from_axis_angle(Vec3::new(0.0, 1.0, 0.0), 0.7),
+            Vec3::new(2.0, 3.0, 4.0),
+        );
+        assert!((matrix.determinant() - 24.0).abs() < EPSILON);
+        assert!((matrix.invert().determinant() - 1.0 / 24.0).abs() < EPSILON);
+        let mirrored = Mat4::scale(Vec3::new(-1.0, 1.0, 1.0));
+        assert!((mirrored.determinant() + 1.0).abs() < EPSILON);
+    }
+
+    #[test]
+    fn compose_matches_translate_rotate_scale() {
+        let location = Vec3::new(-1.0, 2.0, 0.5);
+        let scale = Vec3::new(2.0, 3.0, 4.0);
+        for rotation in rotations() {
+            let expected = Mat4::translate(location) * Mat4::from(rotation) * Mat4::scale(scale);
+            assert_mat_eq(Mat4::compose(location, rotation, scale), expected);
+        }
+    }
+
+    #[test]
+    fn decompose_inverts_compose() {
+        let location = Vec3::new(3.0, -4.0, 5.5);
+        for rotation in rotations() {
+            for scale in [
+                Vec3::new(1.0, 1.0, 1.0),
+                Vec3::new(2.0, 0.5, 3.0),
+                Vec3::new(0.01, 100.0, 1.0),
+            ] {
+                let matrix = Mat4::compose(location, rotation, scale);
+                let (decomposed_location, decomposed_rotation, decomposed_scale) =
+                    Mat4::decompose(matrix);
+                assert_vec_eq(decomposed_location, location);
+                assert_quat_eq(decomposed_rotation, rotation);
+                assert_vec_eq(decomposed_scale, scale);
+                assert_mat_eq(
+                    Mat4::compose(decomposed_location, decomposed_rotation, decomposed_scale),
+                    matrix,
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn decompose_mirrored_matrix() {
+        let rotation = Quat::from_axis_angle(Vec3::new(0.0, 0.0, 1.0), 0.4);
+        let matrix = Mat4::compose(
+            Vec3::new(1.0, 1.0, 1.0),
+            rotation,
+            Vec3::new(1.0, 2.0, -3.0),
+        );
+        let (location, decomposed_rotation, scale) = Mat4::decompose(matrix);
+        // the mirror moves to x, the matrix is still the same
+        assert!(scale.x < 0.0);
+        assert_mat_eq(Mat4::compose(location, decomposed_rotation, scale), matrix);
+    }
+
+    #[test]
+    fn decompose_flattened_matrix() {
+        let matrix = Mat4::compose(
+            Vec3::new(1.0, 2.0, 3.0),
+            rotations()[4],
+            Vec3::new(1.0, 0.0, 1.0),
+        );
+        let (location, rotation, scale) = Mat4::decompose(matrix);
+        assert_vec_eq(location, Vec3::new(1.0, 2.0, 3.0));
+        assert_quat_eq(rotation, Quat::identity());
+        assert_eq!(scale.y, 0.0);
+    }
+
+    #[test]
+    fn transforms_points_and_vectors() {
+        let rotation = Quat::from_axis_angle(Vec3::new(0.0, 1.0, 0.0), std::f32::consts::FRAC_PI_2);
+        let matrix = Mat4::compose(
+            Vec3::new(10.0, 0.0, 0.0),
+            rotation,
+            Vec3::new(2.0, 2.0, 2.0),
+        );
+        let point = Vec3::new(1.0, 2.0, 3.0);
+        assert_vec_eq(
+            matrix.transform_point(point),
+            rotation * (point * 2.0) + Vec3::new(10.0, 0.0, 0.0),
+        );
+        assert_vec_eq(matrix.transform_vector(point), rotation * (point * 2.0));
+        assert_vec_eq(
+            matrix
+                .invert()
+                .transform_point(matrix.transform_point(point)),
+            point,
+        );
+        let projected = matrix.project_point(point);
+        assert!((projected.w - 1.0).abs() < EPSILON);
+    }
+
+    #[test]
+    fn quat_and_matrix_rotate_alike() {
+        let vector = Vec3::new(0.3, -1.0, 2.0);
+        for rotation in rotations() {
+            assert_vec_eq(
+                Mat4::from(rotation).transform_vector(vector),
+                rotation * vector,
+            );
+            assert_quat_eq(Quat::from(Mat4::from(rotation)), rotation);
+        }
+    }
+
+    // the SIMD paths against the scalar ones they stand in for
+    #[cfg(feature = "simd")]
+    #[test]
+    fn simd_matches_scalar() {
+        use crate::math::Aabb;
+
+        let mut matrices = transforms();
+        matrices.push(Mat4::perspective_rh(1.0, 16.0 / 9.0, 0.1, 100.0));
+        matrices.push(Mat4::perspective_reversed_z_infinite_rh(1.0, 1.5, 0.1));
+        let point = Vec3::new(0.3, -1.0, 2.0);
+        for &a in &matrices {
+            assert_mat_eq(a.invert(), a.invert_scalar());
+
+            let column = |index: usize| Vec4::from(a.col(index));
+            let projected =
+                column(0) * point.x + column(1) * point.y + column(2) * point.z + column(3);
+            let simd = a.project_point(point);
+            assert!(
+                (simd - projected).len() < EPSILON,
+                "{:?} != {:?}",
+                simd,
+                projected
+            );
+
+            for &b in &matrices {
+                let product = a * b;
+                for col in 0..4 {
+                    for row in 0..4 {
+                        let expected = (0..4).map(|i| a[i][row] * b[col][i]).sum::<f32>();
+                        assert!(
+                            (product[col][row] - expected).abs()
+                                < EPSILON * expected.abs().max(1.0)
+                        );
+                    }
+                }
+            }
+
+            let bounds = Aabb::new(Vec3::new(-1.0, 0.0, -2.0), Vec3::new(1.0, 3.0, 0.5));
+            assert_eq!(bounds.transform(&a), bounds.transform_scalar(&a));
+        }
+    }
+
+    #[test]
+    fn infinite_perspectives_map_the_near_plane_and_infinity() {
+        let depth = |projection: Mat4, distance: f32| {
+            let clip = projection.project_point(Vec3::new(0.0, 0.0, -distance));
+            clip.z / clip.w
+        };
+        let regular = Mat4::perspective_infinite_rh(1.0, 1.5, 0.1);
+        let reversed = Mat4::perspective_reversed_z_infinite_rh(1.0, 1.5, 0.1);
+        assert!(depth(regular, 0.1).abs() < EPSILON);
+        assert!((depth(reversed, 0.1) - 1.0).abs() < EPSILON);
+        assert!((depth(regular, 1e6) - 1.0).abs() < EPSILON);
+        assert!(depth(reversed, 1e6).abs() < EPSILON);
+        // farther is deeper with regular depth and shallower reversed
+        assert!(depth(regular, 10.0) < depth(regular, 20.0));
+        assert!(depth(reversed, 10.0) > depth(reversed, 20.0));
+
+        // the same view but for the depth
+        let point = Vec3::new(0.3, -0.2, -5.0);
+        let (a, b) = (regular.project_point(point), reversed.project_point(point));
+        assert!((a.x / a.w - b.x / b.w).abs() < EPSILON);
+        assert!((a.y / a.w - b.y / b.w).abs() < EPSILON);
+
+        let left = Mat4::perspective_infinite_lh(1.0, 1.5, 0.1);
+        let clip = left.project_point(Vec3::new(0.0, 0.0, 0.1));
+        assert!((clip.z / clip.w).abs() < EPSILON);
+    }
+}
@@ -1,20 +1,31 @@
+mod aabb;
+mod euler;
+mod frustum;
+mod mat;
 mod mat2;
 mod mat3;
 mod mat4;
+mod quat;
+mod ray;
+mod screen;
+#[cfg(feature = "simd")]
+mod simd;
 mod vec2;
 mod vec3;
 mod vec4;
-mod quat;
-mod euler;
-mod mat;
 
 pub use vec2::Vec2;
 pub use vec3::Vec3;
 pub use vec4::Vec4;
 
-pub use quat::Quat;
 pub use euler::Euler;
 pub use euler::EulerOrder;
+pub use quat::Quat;
+
+pub use aabb::Aabb;
+pub use frustum::Frustum;
+pub use ray::Ray;
+pub use screen::{ndc_to_screen, screen_to_ndc};
 
 pub use mat::Mat;
 pub use mat2::Mat2;
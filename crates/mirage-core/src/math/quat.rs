@@ -0,0 +1,210 @@
+use crate::math::{Euler, EulerOrder, Mat4, Vec3};
+use std::ops::{Mul, Neg};
+
+// Unit quaternion rotation, `s` is the scalar part.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Quat {
+    pub x: f32,
+    pub y: f32,
+    pub z: f32,
+    pub s: f32,
+}
+
+impl Quat {
+    #[inline]
+    pub fn new(x: f32, y: f32, z: f32, s: f32) -> Self {
+        Self { x, y, z, s }
+    }
+
+    #[inline]
+    pub fn identity() -> Self {
+        Self::new(0.0, 0.0, 0.0, 1.0)
+    }
+
+    // Right handed, counter clockwise when looking down the axis.
+    #[inline]
+    pub fn from_axis_angle(axis: Vec3, angle: f32) -> Self {
+        let axis = axis.normalize();
+        let (sin, cos) = (angle * 0.5).sin_cos();
+        Self::new(axis.x * sin, axis.y * sin, axis.z * sin, cos)
+    }
+
+    #[inline]
+    pub fn from_euler(euler: Euler) -> Self {
+        let x = Self::from_axis_angle(Vec3::new(1.0, 0.0, 0.0), euler.x);
+        let y = Self::from_axis_angle(Vec3::new(0.0, 1.0, 0.0), euler.y);
+        let z = Self::from_axis_angle(Vec3::new(0.0, 0.0, 1.0), euler.z);
+        match euler.order {
+            // same as the matrix of the euler, x is applied first
+            EulerOrder::ZYX => z * y * x,
+        }
+    }
+
+    #[inline]
+    pub fn to_euler(&self) -> Euler {
+        Euler::from(self.to_matrix())
+    }
+
+    #[inline]
+    pub fn to_matrix(&self) -> Mat4 {
+        Mat4::from(*self)
+    }
+
+    #[inline]
+    pub fn dot(&self, rhs: Self) -> f32 {
+        self.x * rhs.x + self.y * rhs.y + self.z * rhs.z + self.s * rhs.s
+    }
+
+    #[inline]
+    pub fn len(&self) -> f32 {
+        self.dot(*self).sqrt()
+    }
+
+    #[inline]
+    pub fn normalize(&self) -> Self {
+        let denominator = 1.0 / self.len();
+        Self::new(
+            self.x * denominator,
+            self.y * denominator,
+            self.z * denominator,
+            self.s * denominator,
+        )
+    }
+
+    // The inverse rotation, for unit quaternions.
+    #[inline]
+    pub fn conjugate(&self) -> Self {
+        Self::new(-self.x, -self.y, -self.z, self.s)
+    }
+
+    // Normalized linear interpolation, cheaper than `slerp` but not at constant speed.
+    #[inline]
+    pub fn nlerp(&self, rhs: Self, t: f32) -> Self {
+        let rhs = if self.dot(rhs) < 0.0 { -rhs } else { rhs };
+        Self::new(
+            self.x + (rhs.x - self.x) * t,
+            self.y + (rhs.y - self.y) * t,
+            self.z + (rhs.z - self.z) * t,
+            self.s + (rhs.s - self.s) * t,
+        )
+        .normalize()
+    }
+
+    // Constant angular speed along the shortest arc.
+    pub fn slerp(&self, rhs: Self, t: f32) -> Self {
+        let mut cos_theta = self.dot(rhs);
+        let rhs = if cos_theta < 0.0 {
+            cos_theta = -cos_theta;
+            -rhs
+        } else {
+            rhs
+        };
+        // nearly the same rotation, sin(theta) goes to zero
+        if cos_theta > 0.9995 {
+            return self.nlerp(rhs, t);
+        }
+
+        let theta = cos_theta.acos();
+        let sin_theta_recip = 1.0 / theta.sin();
+        let a = ((1.0 - t) * theta).sin() * sin_theta_recip;
+        let b = (t * theta).sin() * sin_theta_recip;
+        Self::new(
+            self.x * a + rhs.x * b,
+            self.y * a + rhs.y * b,
+            self.z * a + rhs.z * b,
+            self.s * a + rhs.s * b,
+        )
+    }
+}
+
+impl Default for Quat {
+    #[inline]
+    fn default() -> Self {
+        Self::identity()
+    }
+}
+
+impl From<Euler> for Quat {
+    #[inline]
+    fn from(value: Euler) -> Self {
+        Self::from_euler(value)
+    }
+}
+
+// The rotation part of the matrix, which must be free of scale.
+// https://www.euclideanspace.com/maths/geometry/rotations/conversions/matrixToQuaternion/
+impl From<Mat4> for Quat {
+    fn from(m: Mat4) -> Self {
+        // m[col][row]
+        let trace = m[0][0] + m[1][1] + m[2][2];
+        if trace > 0.0 {
+            let s = (trace + 1.0).sqrt() * 2.0;
+            Self::new(
+                (m[1][2] - m[2][1]) / s,
+                (m[2][0] - m[0][2]) / s,
+                (m[0][1] - m[1][0]) / s,
+                0.25 * s,
+            )
+        } else if m[0][0] > m[1][1] && m[0][0] > m[2][2] {
+            let s = (1.0 + m[0][0] - m[1][1] - m[2][2]).sqrt() * 2.0;
+            Self::new(
+                0.25 * s,
+                (m[1][0] + m[0][1]) / s,
+                (m[2][0] + m[0][2]) / s,
+                (m[1][2] - m[2][1]) / s,
+            )
+        } else if m[1][1] > m[2][2] {
+            let s = (1.0 + m[1][1] - m[0][0] - m[2][2]).sqrt() * 2.0;
+            Self::new(
+                (m[1][0] + m[0][1]) / s,
+                0.25 * s,
+                (m[2][1] + m[1][2]) / s,
+                (m[2][0] - m[0][2]) / s,
+            )
+        } else {
+            let s = (1.0 + m[2][2] - m[0][0] - m[1][1]).sqrt() * 2.0;
+            Self::new(
+                (m[2][0] + m[0][2]) / s,
+                (m[2][1] + m[1][2]) / s,
+                0.25 * s,
+                (m[0][1] - m[1][0]) / s,
+            )
+        }
+    }
+}
+
+// Hamilton product, `a * b` rotates by b first, then by a.
+impl Mul<Quat> for Quat {
+    type Output = Quat;
+
+    #[inline]
+    fn mul(self, rhs: Quat) -> Self::Output {
+        Self::new(
+            self.s * rhs.x + self.x * rhs.s + self.y * rhs.z - self.z * rhs.y,
+            self.s * rhs.y - self.x * rhs.z + self.y * rhs.s + self.z * rhs.x,
+            self.s * rhs.z + self.x * rhs.y - self.y * rhs.x + self.z * rhs.s,
+            self.s * rhs.s - self.x * rhs.x - self.y * rhs.y - self.z * rhs.z,
+        )
+    }
+}
+
+impl Mul<Vec3> for Quat {
+    type Output = Vec3;
+
+    #[inline]
+    fn mul(self, rhs: Vec3) -> Self::Output {
+        let axis = Vec3::new(self.x, self.y, self.z);
+        let t = axis.cross(rhs) * 2.0;
+        rhs + t * self.s + axis.cross(t)
+    }
+}
+
+// Same rotation, the other hemisphere.
+impl Neg for Quat {
+    type Output = Quat;
+
+    #[inline]
+    fn neg(self) -> Self::Output {
+        Self::new(-self.x, -self.y, -self.z, -self.s)
+    }
+}
@@ -0,0 +1,73 @@
+use super::{screen_to_ndc, Mat4, Vec2, Vec3};
+
+#[derive(Debug, Copy, Clone)]
+pub struct Ray {
+    pub origin: Vec3,
+    pub direction: Vec3,
+}
+
+impl Ray {
+    #[inline]
+    pub fn new(origin: Vec3, direction: Vec3) -> Self {
+        Self {
+            origin,
+            direction: direction.normalize(),
+        }
+    }
+
+    #[inline]
+    pub fn at(&self, distance: f32) -> Vec3 {
+        self.origin + self.direction * distance
+    }
+
+    // Ray through a pixel of the viewport, for picking. `screen` is in pixels from the top left.
+    // Starts on the near plane at `near_depth`, 0 for regular and 1 for reversed depth, and goes
+    // through the depth halfway, which is in front of the camera for finite and infinite far planes.
+    pub fn from_screen(
+        screen: Vec2,
+        size: Vec2,
+        view: &Mat4,
+        projection: &Mat4,
+        near_depth: f32,
+    ) -> Self {
+        let inverse = (*projection * *view).invert();
+        let ndc = screen_to_ndc(screen, size);
+        let unproject = |z: f32| {
+            let point = [ndc.x, ndc.y, z, 1.0];
+            let mut result = [0.0; 4];
+            for (row, value) in result.iter_mut().enumerate() {
+                *value = (0..4).map(|col| inverse[col][row] * point[col]).sum();
+            }
+            Vec3::new(result[0], result[1], result[2]) / result[3]
+        };
+
+        let near = unproject(near_depth);
+        let far = unproject(0.5);
+        Self::new(near, far - near)
+    }
+
+    // Möller-Trumbore, both faces count. Distance along the ray to the hit.
+    pub fn intersect_triangle(&self, a: Vec3, b: Vec3, c: Vec3) -> Option<f32> {
+        let edge1 = b - a;
+        let edge2 = c - a;
+        let p = self.direction.cross(edge2);
+        let determinant = edge1.dot(p);
+        if determinant.abs() < 1e-8 {
+            return None;
+        }
+
+        let inverse = 1.0 / determinant;
+        let t = self.origin - a;
+        let u = t.dot(p) * inverse;
+        if !(0.0..=1.0).contains(&u) {
+            return None;
+        }
+        let q = t.cross(edge1);
+        let v = self.direction.dot(q) * inverse;
+        if v < 0.0 || u + v > 1.0 {
+            return None;
+        }
+        let distance = edge2.dot(q) * inverse;
+        (distance >= 0.0).then_some(distance)
+    }
+}
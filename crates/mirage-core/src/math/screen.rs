@@ -0,0 +1,16 @@
+use super::Vec2;
+
+// Screen positions are in pixels from the top left of the viewport. The projection matrices flip
+// Y (Vulkan clip space points down), so NDC y = -1 is the top row, the same way up as the screen.
+
+// NDC x, y in [-1, 1] of a pixel position.
+#[inline]
+pub fn screen_to_ndc(screen: Vec2, size: Vec2) -> Vec2 {
+    Vec2::new(screen.x / size.x * 2.0 - 1.0, screen.y / size.y * 2.0 - 1.0)
+}
+
+// Pixel position of NDC x, y, what `screen_to_ndc` undoes.
+#[inline]
+pub fn ndc_to_screen(ndc: Vec2, size: Vec2) -> Vec2 {
+    Vec2::new((ndc.x + 1.0) * 0.5 * size.x, (ndc.y + 1.0) * 0.5 * size.y)
+}
@@ -0,0 +1,280 @@
+// Four f32 lanes in one register, behind the `simd` feature: SSE2 on x86_64 and NEON on aarch64,
+// which both targets always have, plain arrays anywhere else. Backs the `Mat4` multiply, inverse
+// and transforms and the `Vec4` arithmetic. `Vec3` stays scalar, loading three floats into a
+// register costs about what the math on them does, it gets there through the matrix transforms.
+use super::{Mat4, Vec4};
+
+#[cfg(target_arch = "x86_64")]
+use std::arch::x86_64::*;
+
+#[cfg(target_arch = "aarch64")]
+use std::arch::aarch64::*;
+
+#[derive(Copy, Clone)]
+pub(crate) struct F32x4(
+    #[cfg(target_arch = "x86_64")] __m128,
+    #[cfg(target_arch = "aarch64")] float32x4_t,
+    #[cfg(not(any(target_arch = "x86_64", target_arch = "aarch64")))] [f32; 4],
+);
+
+// The lanes `F32x4::shuffle` picks, like _MM_SHUFFLE but in lane order: x and y from the first
+// vector, z and w from the second.
+pub(crate) const fn shuffle_mask(x: u32, y: u32, z: u32, w: u32) -> i32 {
+    (x | y << 2 | z << 4 | w << 6) as i32
+}
+
+impl F32x4 {
+    #[inline]
+    pub fn new(lanes: [f32; 4]) -> Self {
+        #[cfg(target_arch = "x86_64")]
+        unsafe {
+            Self(_mm_loadu_ps(lanes.as_ptr()))
+        }
+        #[cfg(target_arch = "aarch64")]
+        unsafe {
+            Self(vld1q_f32(lanes.as_ptr()))
+        }
+        #[cfg(not(any(target_arch = "x86_64", target_arch = "aarch64")))]
+        Self(lanes)
+    }
+
+    #[inline]
+    pub fn splat(value: f32) -> Self {
+        #[cfg(target_arch = "x86_64")]
+        unsafe {
+            Self(_mm_set1_ps(value))
+        }
+        #[cfg(target_arch = "aarch64")]
+        unsafe {
+            Self(vdupq_n_f32(value))
+        }
+        #[cfg(not(any(target_arch = "x86_64", target_arch = "aarch64")))]
+        Self([value; 4])
+    }
+
+    #[inline]
+    pub fn lanes(self) -> [f32; 4] {
+        #[cfg(target_arch = "x86_64")]
+        unsafe {
+            let mut lanes = [0.0; 4];
+            _mm_storeu_ps(lanes.as_mut_ptr(), self.0);
+            lanes
+        }
+        #[cfg(target_arch = "aarch64")]
+        unsafe {
+            let mut lanes = [0.0; 4];
+            vst1q_f32(lanes.as_mut_ptr(), self.0);
+            lanes
+        }
+        #[cfg(not(any(target_arch = "x86_64", target_arch = "aarch64")))]
+        self.0
+    }
+
+    #[inline]
+    pub fn add(self, rhs: Self) -> Self {
+        #[cfg(target_arch = "x86_64")]
+        unsafe {
+            Self(_mm_add_ps(self.0, rhs.0))
+        }
+        #[cfg(target_arch = "aarch64")]
+        unsafe {
+            Self(vaddq_f32(self.0, rhs.0))
+        }
+        #[cfg(not(any(target_arch = "x86_64", target_arch = "aarch64")))]
+        Self(std::array::from_fn(|lane| self.0[lane] + rhs.0[lane]))
+    }
+
+    #[inline]
+    pub fn sub(self, rhs: Self) -> Self {
+        #[cfg(target_arch = "x86_64")]
+        unsafe {
+            Self(_mm_sub_ps(self.0, rhs.0))
+        }
+        #[cfg(target_arch = "aarch64")]
+        unsafe {
+            Self(vsubq_f32(self.0, rhs.0))
+        }
+        #[cfg(not(any(target_arch = "x86_64", target_arch = "aarch64")))]
+        Self(std::array::from_fn(|lane| self.0[lane] - rhs.0[lane]))
+    }
+
+    #[inline]
+    pub fn mul(self, rhs: Self) -> Self {
+        #[cfg(target_arch = "x86_64")]
+        unsafe {
+            Self(_mm_mul_ps(self.0, rhs.0))
+        }
+        #[cfg(target_arch = "aarch64")]
+        unsafe {
+            Self(vmulq_f32(self.0, rhs.0))
+        }
+        #[cfg(not(any(target_arch = "x86_64", target_arch = "aarch64")))]
+        Self(std::array::from_fn(|lane| self.0[lane] * rhs.0[lane]))
+    }
+
+    #[inline]
+    pub fn min(self, rhs: Self) -> Self {
+        #[cfg(target_arch = "x86_64")]
+        unsafe {
+            Self(_mm_min_ps(self.0, rhs.0))
+        }
+        #[cfg(target_arch = "aarch64")]
+        unsafe {
+            Self(vminq_f32(self.0, rhs.0))
+        }
+        #[cfg(not(any(target_arch = "x86_64", target_arch = "aarch64")))]
+        Self(std::array::from_fn(|lane| self.0[lane].min(rhs.0[lane])))
+    }
+
+    #[inline]
+    pub fn max(self, rhs: Self) -> Self {
+        #[cfg(target_arch = "x86_64")]
+        unsafe {
+            Self(_mm_max_ps(self.0, rhs.0))
+        }
+        #[cfg(target_arch = "aarch64")]
+        unsafe {
+            Self(vmaxq_f32(self.0, rhs.0))
+        }
+        #[cfg(not(any(target_arch = "x86_64", target_arch = "aarch64")))]
+        Self(std::array::from_fn(|lane| self.0[lane].max(rhs.0[lane])))
+    }
+
+    #[inline]
+    pub fn dot(self, rhs: Self) -> f32 {
+        let product = self.mul(rhs);
+        #[cfg(target_arch = "x86_64")]
+        unsafe {
+            // (x + z, y + w) then their sum, SSE2 has no horizontal add
+            let pairs = _mm_add_ps(product.0, _mm_movehl_ps(product.0, product.0));
+            let sum = _mm_add_ss(pairs, _mm_shuffle_ps::<0b01>(pairs, pairs));
+            _mm_cvtss_f32(sum)
+        }
+        #[cfg(target_arch = "aarch64")]
+        unsafe {
+            vaddvq_f32(product.0)
+        }
+        #[cfg(not(any(target_arch = "x86_64", target_arch = "aarch64")))]
+        product.0.iter().sum()
+    }
+
+    // Lanes x and y out of `self`, z and w out of `rhs`, see `shuffle_mask`.
+    #[inline]
+    pub fn shuffle<const MASK: i32>(self, rhs: Self) -> Self {
+        #[cfg(target_arch = "x86_64")]
+        unsafe {
+            Self(_mm_shuffle_ps::<MASK>(self.0, rhs.0))
+        }
+        // NEON has no such shuffle, the lanes are constant so this compiles to a few ext/zip/dup
+        #[cfg(not(target_arch = "x86_64"))]
+        {
+            let (a, b) = (self.lanes(), rhs.lanes());
+            let lane = |shift: i32| (MASK >> shift & 0b11) as usize;
+            Self::new([a[lane(0)], a[lane(2)], b[lane(4)], b[lane(6)]])
+        }
+    }
+}
+
+impl From<Vec4> for F32x4 {
+    #[inline]
+    fn from(value: Vec4) -> Self {
+        Self::new([value.x, value.y, value.z, value.w])
+    }
+}
+
+impl From<F32x4> for Vec4 {
+    #[inline]
+    fn from(value: F32x4) -> Self {
+        Vec4::from(value.lanes())
+    }
+}
+
+#[inline]
+fn columns(matrix: &Mat4) -> [F32x4; 4] {
+    std::array::from_fn(|col| F32x4::new(matrix[col]))
+}
+
+// The columns of `matrix` weighted by the lanes of `vector` and summed.
+#[inline]
+pub(crate) fn mul_vec4(matrix: &Mat4, vector: [f32; 4]) -> [f32; 4] {
+    let [c0, c1, c2, c3] = columns(matrix);
+    c0.mul(F32x4::splat(vector[0]))
+        .add(c1.mul(F32x4::splat(vector[1])))
+        .add(c2.mul(F32x4::splat(vector[2])))
+        .add(c3.mul(F32x4::splat(vector[3])))
+        .lanes()
+}
+
+#[inline]
+pub(crate) fn mul_mat4(lhs: &Mat4, rhs: &Mat4) -> Mat4 {
+    Mat4::from(std::array::from_fn(|col| mul_vec4(lhs, rhs[col])))
+}
+
+// Cofactors of the 2x2 minors six at a time, as in GLM's SSE inverse. Like the scalar one, a
+// singular matrix gives infinities.
+#[inline]
+pub(crate) fn invert_mat4(matrix: &Mat4) -> Mat4 {
+    let [c0, c1, c2, c3] = columns(matrix);
+    let factor = |a: F32x4, b: F32x4, c: F32x4, d: F32x4| {
+        let swap = |v: F32x4| v.shuffle::<{ shuffle_mask(0, 0, 0, 2) }>(v);
+        c.mul(swap(a)).sub(swap(b).mul(d))
+    };
+    let fac0 = factor(
+        c3.shuffle::<{ shuffle_mask(3, 3, 3, 3) }>(c2),
+        c3.shuffle::<{ shuffle_mask(2, 2, 2, 2) }>(c2),
+        c2.shuffle::<{ shuffle_mask(2, 2, 2, 2) }>(c1),
+        c2.shuffle::<{ shuffle_mask(3, 3, 3, 3) }>(c1),
+    );
+    let fac1 = factor(
+        c3.shuffle::<{ shuffle_mask(3, 3, 3, 3) }>(c2),
+        c3.shuffle::<{ shuffle_mask(1, 1, 1, 1) }>(c2),
+        c2.shuffle::<{ shuffle_mask(1, 1, 1, 1) }>(c1),
+        c2.shuffle::<{ shuffle_mask(3, 3, 3, 3) }>(c1),
+    );
+    let fac2 = factor(
+        c3.shuffle::<{ shuffle_mask(2, 2, 2, 2) }>(c2),
+        c3.shuffle::<{ shuffle_mask(1, 1, 1, 1) }>(c2),
+        c2.shuffle::<{ shuffle_mask(1, 1, 1, 1) }>(c1),
+        c2.shuffle::<{ shuffle_mask(2, 2, 2, 2) }>(c1),
+    );
+    let fac3 = factor(
+        c3.shuffle::<{ shuffle_mask(3, 3, 3, 3) }>(c2),
+        c3.shuffle::<{ shuffle_mask(0, 0, 0, 0) }>(c2),
+        c2.shuffle::<{ shuffle_mask(0, 0, 0, 0) }>(c1),
+        c2.shuffle::<{ shuffle_mask(3, 3, 3, 3) }>(c1),
+    );
+    let fac4 = factor(
+        c3.shuffle::<{ shuffle_mask(2, 2, 2, 2) }>(c2),
+        c3.shuffle::<{ shuffle_mask(0, 0, 0, 0) }>(c2),
+        c2.shuffle::<{ shuffle_mask(0, 0, 0, 0) }>(c1),
+        c2.shuffle::<{ shuffle_mask(2, 2, 2, 2) }>(c1),
+    );
+    let fac5 = factor(
+        c3.shuffle::<{ shuffle_mask(1, 1, 1, 1) }>(c2),
+        c3.shuffle::<{ shuffle_mask(0, 0, 0, 0) }>(c2),
+        c2.shuffle::<{ shuffle_mask(0, 0, 0, 0) }>(c1),
+        c2.shuffle::<{ shuffle_mask(1, 1, 1, 1) }>(c1),
+    );
+
+    // row `index` of the first two columns as (c1, c0, c0, c0)
+    let spread = |lane: F32x4| lane.shuffle::<{ shuffle_mask(0, 2, 2, 2) }>(lane);
+    let vec0 = spread(c1.shuffle::<{ shuffle_mask(0, 0, 0, 0) }>(c0));
+    let vec1 = spread(c1.shuffle::<{ shuffle_mask(1, 1, 1, 1) }>(c0));
+    let vec2 = spread(c1.shuffle::<{ shuffle_mask(2, 2, 2, 2) }>(c0));
+    let vec3 = spread(c1.shuffle::<{ shuffle_mask(3, 3, 3, 3) }>(c0));
+
+    let sign_a = F32x4::new([-1.0, 1.0, -1.0, 1.0]);
+    let sign_b = F32x4::new([1.0, -1.0, 1.0, -1.0]);
+    let inv0 = sign_b.mul(vec1.mul(fac0).sub(vec2.mul(fac1)).add(vec3.mul(fac2)));
+    let inv1 = sign_a.mul(vec0.mul(fac0).sub(vec2.mul(fac3)).add(vec3.mul(fac4)));
+    let inv2 = sign_b.mul(vec0.mul(fac1).sub(vec1.mul(fac3)).add(vec3.mul(fac5)));
+    let inv3 = sign_a.mul(vec0.mul(fac2).sub(vec1.mul(fac4)).add(vec2.mul(fac5)));
+
+    // the first row of the adjugate against the first column gives the determinant
+    let row0 = inv0.shuffle::<{ shuffle_mask(0, 0, 0, 0) }>(inv1);
+    let row1 = inv2.shuffle::<{ shuffle_mask(0, 0, 0, 0) }>(inv3);
+    let row = row0.shuffle::<{ shuffle_mask(0, 2, 0, 2) }>(row1);
+    let det_recip = F32x4::splat(1.0 / c0.dot(row));
+
+    Mat4::from([inv0, inv1, inv2, inv3].map(|column| column.mul(det_recip).lanes()))
+}
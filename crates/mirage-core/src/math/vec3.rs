@@ -2,7 +2,7 @@ use crate::math::{Vec2, Vec4};
 use std::ops::{Add, Div, Mul, Neg, Sub};
 
 #[repr(C)]
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, PartialEq)]
 pub struct Vec3 {
     pub x: f32,
     pub y: f32,
@@ -1,3 +1,5 @@
+#[cfg(feature = "simd")]
+use super::simd::F32x4;
 use crate::math::{Vec2, Vec3};
 use std::ops::{Add, Div, Mul, Neg, Sub};
 
@@ -18,7 +20,14 @@ impl Vec4 {
 
     #[inline]
     pub fn dot(&self, v: Self) -> f32 {
-        self.x * v.x + self.y * v.y + self.z * v.z + self.w * v.w
+        #[cfg(feature = "simd")]
+        {
+            F32x4::from(*self).dot(F32x4::from(v))
+        }
+        #[cfg(not(feature = "simd"))]
+        {
+            self.x * v.x + self.y * v.y + self.z * v.z + self.w * v.w
+        }
     }
 
     #[inline]
@@ -117,11 +126,18 @@ impl Add<Vec4> for Vec4 {
 
     #[inline]
     fn add(self, rhs: Vec4) -> Self::Output {
-        Self {
-            x: self.x + rhs.x,
-            y: self.y + rhs.y,
-            z: self.z + rhs.z,
-            w: self.w + rhs.w,
+        #[cfg(feature = "simd")]
+        {
+            Vec4::from(F32x4::from(self).add(F32x4::from(rhs)))
+        }
+        #[cfg(not(feature = "simd"))]
+        {
+            Self {
+                x: self.x + rhs.x,
+                y: self.y + rhs.y,
+                z: self.z + rhs.z,
+                w: self.w + rhs.w,
+            }
         }
     }
 }
@@ -130,11 +146,18 @@ impl Sub<Vec4> for Vec4 {
     type Output = Vec4;
 
     fn sub(self, rhs: Vec4) -> Self::Output {
-        Self {
-            x: self.x - rhs.x,
-            y: self.y - rhs.y,
-            z: self.z - rhs.z,
-            w: self.w - rhs.w,
+        #[cfg(feature = "simd")]
+        {
+            Vec4::from(F32x4::from(self).sub(F32x4::from(rhs)))
+        }
+        #[cfg(not(feature = "simd"))]
+        {
+            Self {
+                x: self.x - rhs.x,
+                y: self.y - rhs.y,
+                z: self.z - rhs.z,
+                w: self.w - rhs.w,
+            }
         }
     }
 }
@@ -144,11 +167,18 @@ impl Mul<Vec4> for Vec4 {
 
     #[inline]
     fn mul(self, rhs: Vec4) -> Self::Output {
-        Self {
-            x: self.x * rhs.x,
-            y: self.y * rhs.y,
-            z: self.z * rhs.z,
-            w: self.w * rhs.w,
+        #[cfg(feature = "simd")]
+        {
+            Vec4::from(F32x4::from(self).mul(F32x4::from(rhs)))
+        }
+        #[cfg(not(feature = "simd"))]
+        {
+            Self {
+                x: self.x * rhs.x,
+                y: self.y * rhs.y,
+                z: self.z * rhs.z,
+                w: self.w * rhs.w,
+            }
         }
     }
 }
@@ -0,0 +1,477 @@
+use super::Value;
+use std::fmt::Write;
+
+// The JSON tree of a scene file, objects keep the order their keys were written in.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Json {
+    Null,
+    Bool(bool),
+    // numbers without a fraction or exponent, so ints and floats read back as what they were
+    Int(i64),
+    Float(f64),
+    String(String),
+    Array(Vec<Json>),
+    Object(Vec<(String, Json)>),
+}
+
+impl Json {
+    pub fn parse(text: &str) -> Result<Json, String> {
+        let mut parser = Parser {
+            bytes: text.as_bytes(),
+            position: 0,
+        };
+        let json = parser.parse_value()?;
+        parser.skip_whitespace();
+        if parser.position < parser.bytes.len() {
+            return Err(parser.error("trailing characters"));
+        }
+        Ok(json)
+    }
+
+    // Indented by two spaces, arrays of plain values like vectors stay on one line.
+    pub fn to_string_pretty(&self) -> String {
+        let mut text = String::new();
+        self.write(&mut text, 0);
+        text.push('\n');
+        text
+    }
+
+    pub fn get(&self, key: &str) -> Option<&Json> {
+        match self {
+            Json::Object(entries) => entries
+                .iter()
+                .find(|(name, _)| name == key)
+                .map(|(_, value)| value),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Json::String(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    pub fn as_i64(&self) -> Option<i64> {
+        match self {
+            Json::Int(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    pub fn as_array(&self) -> Option<&[Json]> {
+        match self {
+            Json::Array(values) => Some(values),
+            _ => None,
+        }
+    }
+
+    pub fn as_object(&self) -> Option<&[(String, Json)]> {
+        match self {
+            Json::Object(entries) => Some(entries),
+            _ => None,
+        }
+    }
+
+    // None for nulls and objects, which no field value maps to.
+    pub fn to_value(&self) -> Option<Value> {
+        match self {
+            Json::Bool(value) => Some(Value::Bool(*value)),
+            Json::Int(value) => Some(Value::Int(*value)),
+            Json::Float(value) => Some(Value::Float(*value as f32)),
+            Json::String(value) => Some(Value::String(value.clone())),
+            Json::Array(values) => values
+                .iter()
+                .map(Json::to_value)
+                .collect::<Option<Vec<_>>>()
+                .map(Value::List),
+            Json::Null | Json::Object(_) => None,
+        }
+    }
+
+    fn is_plain(&self) -> bool {
+        !matches!(self, Json::Array(_) | Json::Object(_))
+    }
+
+    fn write(&self, text: &mut String, depth: usize) {
+        let indent = |text: &mut String, depth: usize| {
+            text.push('\n');
+            (0..depth).for_each(|_| text.push_str("  "));
+        };
+        match self {
+            Json::Null => text.push_str("null"),
+            Json::Bool(value) => {
+                let _ = write!(text, "{}", value);
+            }
+            Json::Int(value) => {
+                let _ = write!(text, "{}", value);
+            }
+            // Debug keeps the fraction of whole numbers, 1.0 instead of 1
+            Json::Float(value) if value.is_finite() => {
+                let _ = write!(text, "{:?}", value);
+            }
+            Json::Float(_) => text.push_str("null"),
+            Json::String(value) => write_string(text, value),
+            Json::Array(values) if values.iter().all(Json::is_plain) => {
+                text.push('[');
+                for (index, value) in values.iter().enumerate() {
+                    if index > 0 {
+                        text.push_str(", ");
+                    }
+                    value.write(text, depth);
+                }
+                text.push(']');
+            }
+            Json::Array(values) => {
+                text.push('[');
+                for (index, value) in values.iter().enumerate() {
+                    if index > 0 {
+                        text.push(',');
+                    }
+                    indent(text, depth + 1);
+                    value.write(text, depth + 1);
+                }
+                indent(text, depth);
+                text.push(']');
+            }
+            Json::Object(entries) if entries.is_empty() => text.push_str("{}"),
+            Json::Object(entries) => {
+                text.push('{');
+                for (index, (name, value)) in entries.iter().enumerate() {
+                    if index > 0 {
+                        text.push(',');
+                    }
+                    indent(text, depth + 1);
+                    write_string(text, name);
+                    text.push_str(": ");
+                    value.write(text, depth + 1);
+                }
+                indent(text, depth);
+                text.push('}');
+            }
+        }
+    }
+}
+
+impl From<&Value> for Json {
+    fn from(value: &Value) -> Self {
+        match value {
+            Value::Bool(value) => Json::Bool(*value),
+            Value::Int(value) => Json::Int(*value),
+            Value::Float(value) => Json::Float(*value as f64),
+            Value::String(value) => Json::String(value.clone()),
+            Value::List(values) => Json::Array(values.iter().map(Json::from).collect()),
+        }
+    }
+}
+
+fn write_string(text: &mut String, value: &str) {
+    text.push('"');
+    for char in value.chars() {
+        match char {
+            '"' => text.push_str("\\\""),
+            '\\' => text.push_str("\\\\"),
+            '\n' => text.push_str("\\n"),
+            '\t' => text.push_str("\\t"),
+            char if char.is_control() => {
+                let _ = write!(text, "\\u{:04x}", char as u32);
+            }
+            char => text.push(char),
+        }
+    }
+    text.push('"');
+}
+
+struct Parser<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl Parser<'_> {
+    fn error(&self, message: &str) -> String {
+        let line = self.bytes[..self.position.min(self.bytes.len())]
+            .iter()
+            .filter(|&&byte| byte == b'\n')
+            .count();
+        format!("{} on line {}", message, line + 1)
+    }
+
+    fn skip_whitespace(&mut self) {
+        while let Some(b' ' | b'\t' | b'\n' | b'\r') = self.bytes.get(self.position) {
+            self.position += 1;
+        }
+    }
+
+    fn expect(&mut self, byte: u8) -> Result<(), String> {
+        self.skip_whitespace();
+        if self.bytes.get(self.position) != Some(&byte) {
+            return Err(self.error(&format!("expected '{}'", byte as char)));
+        }
+        self.position += 1;
+        Ok(())
+    }
+
+    // true and past it when the next character is `byte`
+    fn consume(&mut self, byte: u8) -> bool {
+        self.skip_whitespace();
+        let found = self.bytes.get(self.position) == Some(&byte);
+        if found {
+            self.position += 1;
+        }
+        found
+    }
+
+    fn parse_value(&mut self) -> Result<Json, String> {
+        self.skip_whitespace();
+        match self.bytes.get(self.position) {
+            Some(b'{') => self.parse_object(),
+            Some(b'[') => self.parse_array(),
+            Some(b'"') => self.parse_string().map(Json::String),
+            Some(b'-' | b'0'..=b'9') => self.parse_number(),
+            Some(_) => {
+                for (word, json) in [
+                    ("null", Json::Null),
+                    ("true", Json::Bool(true)),
+                    ("false", Json::Bool(false)),
+                ] {
+                    if self.bytes[self.position..].starts_with(word.as_bytes()) {
+                        self.position += word.len();
+                        return Ok(json);
+                    }
+                }
+                Err(self.error("unexpected character"))
+            }
+            None => Err(self.error("unexpected end")),
+        }
+    }
+
+    fn parse_object(&mut self) -> Result<Json, String> {
+        self.expect(b'{')?;
+        let mut entries = vec![];
+        if self.consume(b'}') {
+            return Ok(Json::Object(entries));
+        }
+        loop {
+            self.skip_whitespace();
+            let name = self.parse_string()?;
+            self.expect(b':')?;
+            entries.push((name, self.parse_value()?));
+            if !self.consume(b',') {
+                break;
+            }
+        }
+        self.expect(b'}')?;
+        Ok(Json::Object(entries))
+    }
+
+    fn parse_array(&mut self) -> Result<Json, String> {
+        self.expect(b'[')?;
+        let mut values = vec![];
+        if self.consume(b']') {
+            return Ok(Json::Array(values));
+        }
+        loop {
+            values.push(self.parse_value()?);
+            if !self.consume(b',') {
+                break;
+            }
+        }
+        self.expect(b']')?;
+        Ok(Json::Array(values))
+    }
+
+    fn parse_string(&mut self) -> Result<String, String> {
+        self.expect(b'"')?;
+        let mut bytes = vec![];
+        loop {
+            let Some(&byte) = self.bytes.get(self.position) else {
+                return Err(self.error("unterminated string"));
+            };
+            self.position += 1;
+            match byte {
+                b'"' => break,
+                b'\\' => {
+                    let Some(&escape) = self.bytes.get(self.position) else {
+                        return Err(self.error("unterminated string"));
+                    };
+                    self.position += 1;
+                    let char = match escape {
+                        b'"' => '"',
+                        b'\\' => '\\',
+                        b'/' => '/',
+                        b'b' => '\u{8}',
+                        b'f' => '\u{c}',
+                        b'n' => '\n',
+                        b'r' => '\r',
+                        b't' => '\t',
+                        b'u' => {
+                            // surrogate pairs aren't combined, scene files have no use for them
+                            let hex = self
+                                .bytes
+                                .get(self.position..self.position + 4)
+                                .and_then(|hex| std::str::from_utf8(hex).ok())
+                                .and_then(|hex| u32::from_str_radix(hex, 16).ok())
+                                .ok_or_else(|| self.error("invalid unicode escape"))?;
+                            self.position += 4;
+                            char::from_u32(hex).unwrap_or(char::REPLACEMENT_CHARACTER)
+                        }
+                        _ => return Err(self.error("invalid escape")),
+                    };
+                    bytes.extend_from_slice(char.encode_utf8(&mut [0; 4]).as_bytes());
+                }
+                byte => bytes.push(byte),
+            }
+        }
+        String::from_utf8(bytes).map_err(|_| self.error("invalid utf-8"))
+    }
+
+    fn parse_number(&mut self) -> Result<Json, String> {
+        let start = self.position;
+        while let Some(b'-' | b'+' | b'.' | b'e' | b'E' | b'0'..=b'9') =
+            self.bytes.get(self.position)
+        {
+            self.position += 1;
+        }
+        let text = std::str::from_utf8(&self.bytes[start..self.position]).unwrap_or_default();
+        let is_float = text.contains(['.', 'e', 'E']);
+        let json = match is_float {
+            false => text.parse().ok().map(Json::Int),
+            true => text.parse().ok().map(Json::Float),
+        };
+        json.ok_or_else(|| self.error("invalid number"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Json;
+    use crate::serialize::Value;
+
+    fn object(entries: &[(&str, Json)]) -> Json {
+        Json::Object(
+            entries
+                .iter()
+                .map(|(name, value)| (name.to_string(), value.clone()))
+                .collect(),
+        )
+    }
+
+    #[test]
+    fn round_trips_through_the_pretty_text() {
+        let json = object(&[
+            ("format", Json::Int(1)),
+            ("environment", Json::Null),
+            (
+                "entities",
+                Json::Array(vec![
+                    object(&[("location", Json::Array(vec![Json::Float(0.5); 3]))]),
+                    object(&[]),
+                ]),
+            ),
+            ("empty", Json::Array(vec![])),
+            (
+                "flags",
+                Json::Array(vec![Json::Bool(true), Json::Bool(false)]),
+            ),
+        ]);
+        let text = json.to_string_pretty();
+        assert_eq!(Json::parse(&text), Ok(json));
+    }
+
+    #[test]
+    fn pretty_text_keeps_plain_arrays_on_one_line() {
+        let json = object(&[
+            (
+                "location",
+                Json::Array(vec![Json::Float(1.0), Json::Int(2)]),
+            ),
+            ("comps", Json::Array(vec![object(&[("a", Json::Null)])])),
+        ]);
+        let expected = "{\n  \"location\": [1.0, 2],\n  \"comps\": [\n    {\n      \"a\": null\n    }\n  ]\n}\n";
+        assert_eq!(json.to_string_pretty(), expected);
+    }
+
+    #[test]
+    fn objects_keep_the_order_of_their_keys() {
+        let json = Json::parse(r#"{"b": 1, "a": 2, "c": 3}"#).unwrap();
+        let names = json
+            .as_object()
+            .unwrap()
+            .iter()
+            .map(|(name, _)| name.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(names, ["b", "a", "c"]);
+        assert_eq!(json.get("a"), Some(&Json::Int(2)));
+        assert_eq!(json.get("d"), None);
+    }
+
+    #[test]
+    fn strings_escape_and_unescape() {
+        let value = "quote \" backslash \\ newline \n tab \t bell \u{7} é";
+        let text = Json::String(value.to_string()).to_string_pretty();
+        assert_eq!(
+            text,
+            "\"quote \\\" backslash \\\\ newline \\n tab \\t bell \\u0007 é\"\n"
+        );
+        assert_eq!(Json::parse(&text), Ok(Json::String(value.to_string())));
+
+        let json = Json::parse(r#""\/\b\f\réA""#).unwrap();
+        assert_eq!(json.as_str(), Some("/\u{8}\u{c}\réA"));
+    }
+
+    #[test]
+    fn numbers_read_back_as_ints_or_floats() {
+        assert_eq!(Json::parse("42"), Ok(Json::Int(42)));
+        assert_eq!(Json::parse("-7"), Ok(Json::Int(-7)));
+        assert_eq!(Json::parse("1.0"), Ok(Json::Float(1.0)));
+        assert_eq!(Json::parse("-2.5"), Ok(Json::Float(-2.5)));
+        assert_eq!(Json::parse("1e3"), Ok(Json::Float(1000.0)));
+        assert_eq!(Json::parse("2.5E-1"), Ok(Json::Float(0.25)));
+
+        // whole floats keep their fraction, so they don't come back as ints
+        assert_eq!(Json::Float(3.0).to_string_pretty(), "3.0\n");
+        // not representable in JSON
+        assert_eq!(Json::Float(f64::NAN).to_string_pretty(), "null\n");
+        assert_eq!(Json::Float(f64::INFINITY).to_string_pretty(), "null\n");
+    }
+
+    #[test]
+    fn converts_to_field_values() {
+        let json = Json::parse(r#"[1, 2.5, true, "a", [0.0]]"#).unwrap();
+        assert_eq!(
+            json.to_value(),
+            Some(Value::List(vec![
+                Value::Int(1),
+                Value::Float(2.5),
+                Value::Bool(true),
+                Value::String("a".to_string()),
+                Value::List(vec![Value::Float(0.0)]),
+            ]))
+        );
+        assert_eq!(Json::from(&json.to_value().unwrap()), json);
+
+        // no field value holds them, nor lists containing them
+        assert_eq!(Json::Null.to_value(), None);
+        assert_eq!(Json::parse("{}").unwrap().to_value(), None);
+        assert_eq!(Json::parse("[1, null]").unwrap().to_value(), None);
+    }
+
+    #[test]
+    fn errors_tell_what_and_where() {
+        let error = |text: &str| Json::parse(text).unwrap_err();
+        assert_eq!(error(""), "unexpected end on line 1");
+        assert_eq!(error("{\n  \"a\": }"), "unexpected character on line 2");
+        assert_eq!(error("[1, 2"), "expected ']' on line 1");
+        assert_eq!(error("[1,]"), "unexpected character on line 1");
+        assert_eq!(error("{\"a\" 1}"), "expected ':' on line 1");
+        assert_eq!(error("{a: 1}"), "expected '\"' on line 1");
+        assert_eq!(error("1 2"), "trailing characters on line 1");
+        assert_eq!(error("\"open"), "unterminated string on line 1");
+        assert_eq!(error(r#""\q""#), "invalid escape on line 1");
+        assert_eq!(error(r#""\u12""#), "invalid unicode escape on line 1");
+        assert_eq!(error("1.2.3"), "invalid number on line 1");
+        assert_eq!(error("-"), "invalid number on line 1");
+        assert_eq!(error("tru"), "unexpected character on line 1");
+    }
+}
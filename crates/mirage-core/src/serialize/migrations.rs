@@ -0,0 +1,183 @@
+use super::{Fields, SerializeComp, SerializedComp};
+use std::collections::HashMap;
+
+type Migration = Box<dyn Fn(&mut Fields)>;
+
+// Upgrades components of older scene files step by step, version n to n + 1, until they
+// match the current layout. Steps without a registered migration only bump the version.
+pub struct Migrations {
+    migrations: HashMap<(&'static str, u32), Migration>,
+}
+
+impl Migrations {
+    pub fn new() -> Self {
+        Self {
+            migrations: HashMap::new(),
+        }
+    }
+
+    // `migration` rewrites the fields of `from_version` into the ones of `from_version + 1`,
+    // e.g. renaming a field or converting its unit.
+    pub fn register<T, F>(&mut self, from_version: u32, migration: F)
+    where
+        T: SerializeComp,
+        F: Fn(&mut Fields) + 'static,
+    {
+        self.migrations
+            .insert((T::TYPE_NAME, from_version), Box::new(migration));
+    }
+
+    pub fn migrate<T: SerializeComp>(&self, serialized: &mut SerializedComp) -> bool {
+        if serialized.type_name != T::TYPE_NAME {
+            return false;
+        }
+        // written by a newer build, its fields can't be guessed
+        if serialized.version > T::VERSION {
+            log::warn!(
+                "{} version {} is newer than the supported {}",
+                T::TYPE_NAME,
+                serialized.version,
+                T::VERSION
+            );
+            return false;
+        }
+
+        while serialized.version < T::VERSION {
+            if let Some(migration) = self.migrations.get(&(T::TYPE_NAME, serialized.version)) {
+                migration(&mut serialized.fields);
+            }
+            serialized.version += 1;
+        }
+        true
+    }
+
+    pub fn deserialize<T: SerializeComp>(&self, mut serialized: SerializedComp) -> Option<T> {
+        if !self.migrate::<T>(&mut serialized) {
+            return None;
+        }
+        Some(T::deserialize(&serialized.fields))
+    }
+}
+
+impl Default for Migrations {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Migrations;
+    use crate::ecs::Comp;
+    use crate::serialize::{Fields, SerializeComp, SerializedComp, Value};
+
+    // version 1 renamed "radius" to "size", version 2 doubled it, version 3 added "visible"
+    #[derive(Debug, PartialEq)]
+    struct Marker {
+        size: f32,
+        visible: bool,
+    }
+
+    impl Comp for Marker {}
+
+    impl SerializeComp for Marker {
+        const TYPE_NAME: &'static str = "Marker";
+        const VERSION: u32 = 3;
+
+        fn serialize(&self) -> Fields {
+            Fields::from([
+                ("size".to_string(), Value::Float(self.size)),
+                ("visible".to_string(), Value::Bool(self.visible)),
+            ])
+        }
+
+        fn deserialize(fields: &Fields) -> Self {
+            Self {
+                size: fields.get("size").and_then(Value::as_f32).unwrap_or(1.0),
+                visible: !matches!(fields.get("visible"), Some(Value::Bool(false))),
+            }
+        }
+    }
+
+    fn migrations() -> Migrations {
+        let mut migrations = Migrations::new();
+        migrations.register::<Marker, _>(0, |fields| {
+            if let Some(radius) = fields.remove("radius") {
+                fields.insert("size".to_string(), radius);
+            }
+        });
+        migrations.register::<Marker, _>(1, |fields| {
+            if let Some(size) = fields.get("size").and_then(Value::as_f32) {
+                fields.insert("size".to_string(), Value::Float(size * 2.0));
+            }
+        });
+        migrations
+    }
+
+    fn serialized(type_name: &str, version: u32, fields: &[(&str, Value)]) -> SerializedComp {
+        SerializedComp {
+            type_name: type_name.to_string(),
+            version,
+            fields: fields
+                .iter()
+                .map(|(name, value)| (name.to_string(), value.clone()))
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn old_versions_run_every_step_in_order() {
+        let mut comp = serialized("Marker", 0, &[("radius", Value::Int(3))]);
+        assert!(migrations().migrate::<Marker>(&mut comp));
+        assert_eq!(comp.version, Marker::VERSION);
+        assert_eq!(
+            comp.fields,
+            Fields::from([("size".to_string(), Value::Float(6.0))])
+        );
+
+        // starting halfway skips the earlier steps
+        let comp = serialized("Marker", 1, &[("size", Value::Float(3.0))]);
+        assert_eq!(
+            migrations().deserialize::<Marker>(comp),
+            Some(Marker {
+                size: 6.0,
+                visible: true
+            })
+        );
+    }
+
+    #[test]
+    fn current_versions_are_left_alone() {
+        let marker = Marker {
+            size: 0.5,
+            visible: false,
+        };
+        let mut comp = marker.to_serialized();
+        let fields = comp.fields.clone();
+        assert!(migrations().migrate::<Marker>(&mut comp));
+        assert_eq!(comp.fields, fields);
+        assert_eq!(migrations().deserialize::<Marker>(comp), Some(marker));
+    }
+
+    #[test]
+    fn steps_without_a_migration_only_bump_the_version() {
+        let mut comp = serialized("Marker", 2, &[("size", Value::Float(4.0))]);
+        assert!(Migrations::new().migrate::<Marker>(&mut comp));
+        assert_eq!(comp.version, 3);
+        assert_eq!(
+            comp.fields,
+            Fields::from([("size".to_string(), Value::Float(4.0))])
+        );
+    }
+
+    #[test]
+    fn newer_versions_and_other_types_are_rejected() {
+        let comp = serialized("Marker", Marker::VERSION + 1, &[]);
+        assert_eq!(migrations().deserialize::<Marker>(comp), None);
+
+        let mut comp = serialized("Other", 0, &[("radius", Value::Int(3))]);
+        assert!(!migrations().migrate::<Marker>(&mut comp));
+        assert_eq!(comp.version, 0);
+        assert!(comp.fields.contains_key("radius"));
+    }
+}
@@ -0,0 +1,29 @@
+mod json;
+mod migrations;
+mod value;
+
+pub use json::Json;
+pub use migrations::Migrations;
+pub use value::{Fields, SerializedComp, Value};
+
+use crate::ecs::Comp;
+
+// A component that can be written to and read back from a scene file. Bump VERSION whenever the
+// fields change, and register a migration for changes that can't be covered by a default.
+pub trait SerializeComp: Comp + Sized {
+    const TYPE_NAME: &'static str;
+    const VERSION: u32;
+
+    fn serialize(&self) -> Fields;
+
+    // Missing fields take their default, so fields added since a file was written don't make it fail.
+    fn deserialize(fields: &Fields) -> Self;
+
+    fn to_serialized(&self) -> SerializedComp {
+        SerializedComp {
+            type_name: Self::TYPE_NAME.to_string(),
+            version: Self::VERSION,
+            fields: self.serialize(),
+        }
+    }
+}
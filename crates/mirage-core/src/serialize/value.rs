@@ -0,0 +1,53 @@
+use crate::math::Vec3;
+use std::collections::BTreeMap;
+
+// Format independent tree of a serialized component, the scene file writer maps it to text.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Bool(bool),
+    Int(i64),
+    Float(f32),
+    String(String),
+    List(Vec<Value>),
+}
+
+impl Value {
+    pub fn as_f32(&self) -> Option<f32> {
+        match self {
+            Value::Float(value) => Some(*value),
+            Value::Int(value) => Some(*value as f32),
+            _ => None,
+        }
+    }
+
+    pub fn as_vec3(&self) -> Option<Vec3> {
+        match self {
+            Value::List(values) if values.len() == 3 => Some(Vec3::new(
+                values[0].as_f32()?,
+                values[1].as_f32()?,
+                values[2].as_f32()?,
+            )),
+            _ => None,
+        }
+    }
+}
+
+impl From<Vec3> for Value {
+    fn from(v: Vec3) -> Self {
+        Value::List(vec![
+            Value::Float(v.x),
+            Value::Float(v.y),
+            Value::Float(v.z),
+        ])
+    }
+}
+
+pub type Fields = BTreeMap<String, Value>;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SerializedComp {
+    pub type_name: String,
+    // VERSION of the component when the file was written
+    pub version: u32,
+    pub fields: Fields,
+}
@@ -1,4 +1,4 @@
-use crate::mirage::Mirage;
+use crate::mirage::{Mirage, MirageConfig};
 use std::rc::Rc;
 use winit::application::ApplicationHandler;
 use winit::event::WindowEvent;
@@ -8,23 +8,26 @@ use winit::window::{Window, WindowId};
 pub struct Application {
     pub window: Option<Rc<Window>>,
     pub mirage: Option<Mirage>,
+    // what the Mirage is created with once there is a window
+    config: MirageConfig,
 }
 
 impl Application {
-    pub fn new() -> Self {
+    pub fn new(config: MirageConfig) -> Self {
         Self {
             window: None,
             mirage: None,
+            config,
         }
     }
 
     fn init(&mut self, window: Window) {
         let rc_window = Rc::new(window);
 
-        if let Some(mirage) = &self.mirage {
+        if let Some(mirage) = &mut self.mirage {
             mirage.update_window(Rc::clone(&rc_window));
         } else {
-            let mut mirage = Mirage::new(Rc::clone(&rc_window));
+            let mirage = Mirage::with_config(Rc::clone(&rc_window), &self.config);
             self.mirage = Some(mirage);
         }
 
@@ -38,22 +41,37 @@ impl ApplicationHandler for Application {
             .with_title("Mirage")
             .with_inner_size(winit::dpi::LogicalSize::new(800, 600));
 
-        match event_loop.create_window(attributes) {
-            Ok(window) => self.init(window),
-            Err(_) => {}
+        if let Ok(window) = event_loop.create_window(attributes) {
+            self.init(window);
         }
     }
 
+    // Android destroys the native window whenever the app goes to the background.
+    fn suspended(&mut self, _event_loop: &ActiveEventLoop) {
+        if let Some(mirage) = &mut self.mirage {
+            mirage.suspend();
+        }
+        self.window = None;
+    }
+
     fn window_event(
         &mut self,
         event_loop: &ActiveEventLoop,
         window_id: WindowId,
         event: WindowEvent,
     ) {
-        let window = self.window.as_ref().unwrap();
+        let Some(window) = self.window.as_ref() else {
+            return;
+        };
         if window_id != window.id() {
             return;
         }
+        let Some(mirage) = self.mirage.as_mut() else {
+            return;
+        };
+        // egui sees every event, pointer routing decides later who the pointer belongs to
+        mirage.handle_ui_event(&event);
+        mirage.input.handle_event(&event);
 
         match event {
             WindowEvent::CloseRequested => {
@@ -61,19 +79,18 @@ impl ApplicationHandler for Application {
                 event_loop.exit();
             }
             WindowEvent::RedrawRequested => {
-                self.mirage.as_mut().unwrap().render();
+                mirage.render();
+                mirage.update_cursor(event_loop);
+            }
+            // a new scale factor comes with a new physical size, UI layouts follow on the next update
+            WindowEvent::Resized(_) | WindowEvent::ScaleFactorChanged { .. } => {
+                mirage.resize();
             }
-            // WindowEvent::Resized(size) => {
-            //
-            // }
-            // WindowEvent::ScaleFactorChanged => {
-            //
-            // }
             _ => (),
         }
     }
 
-    fn about_to_wait(&mut self, event_loop: &ActiveEventLoop) {
+    fn about_to_wait(&mut self, _event_loop: &ActiveEventLoop) {
         if self.window.is_none() {
             return;
         }
@@ -2,7 +2,7 @@ pub trait AssetImpl
 where
     Self: 'static + Sized,
 {
-    fn load(data: &[u8]) -> Option<Self> {
+    fn load(_data: &[u8]) -> Option<Self> {
         None
     }
 }
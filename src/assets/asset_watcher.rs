@@ -0,0 +1,183 @@
+use super::asset_impl::AssetImpl;
+use super::{AssetId, Assets, Environment, Geom, Texture};
+use std::collections::HashMap;
+use std::fs;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant, SystemTime};
+
+// Checking every file each frame is wasteful, edits are picked up within this interval.
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum AssetKind {
+    Texture,
+    Geom,
+    Environment,
+}
+
+// The data of an asset loaded again from its changed file.
+pub enum ReloadedAsset {
+    Texture(Texture),
+    Geom(Geom),
+    Environment(Environment),
+}
+
+pub struct AssetReload {
+    pub id: AssetId,
+    pub path: String,
+    // the message is ready to be reported, the asset keeps its data
+    pub asset: Result<ReloadedAsset, String>,
+}
+
+struct Watched {
+    path: String,
+    kind: AssetKind,
+    modified: Option<SystemTime>,
+}
+
+struct Worker {
+    requests: Sender<(AssetId, String, AssetKind)>,
+    reloads: Receiver<AssetReload>,
+    thread: JoinHandle<()>,
+}
+
+// Watches the files of the textures, meshes and environments loaded with `Assets::handle_path`
+// and loads the changed ones again on a worker thread. The caller swaps the data in between
+// frames, see `Mirage::reload_assets`. Materials aren't loaded from files, they pick the reloaded
+// textures up, their shaders are reloaded by the `ShaderCompiler`. Only available where the files
+// are, in debug builds run from the repository.
+pub struct AssetWatcher {
+    dir: Option<PathBuf>,
+    watched: HashMap<AssetId, Watched>,
+    last_poll: Instant,
+    // started with the first change
+    worker: Option<Worker>,
+}
+
+impl AssetWatcher {
+    pub fn new() -> Self {
+        let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("assets");
+        let dir = (cfg!(debug_assertions) && dir.is_dir()).then_some(dir);
+
+        Self {
+            dir,
+            watched: HashMap::new(),
+            last_poll: Instant::now(),
+            worker: None,
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.dir.is_some()
+    }
+
+    // Starts loading the assets whose files changed since the last poll and returns the ones
+    // loaded since, of assets that are still there.
+    pub fn poll(&mut self, assets: &Assets) -> Vec<AssetReload> {
+        if !self.is_enabled() {
+            return vec![];
+        }
+        let reloads = match &self.worker {
+            Some(worker) => worker
+                .reloads
+                .try_iter()
+                .filter(|reload| {
+                    self.watched
+                        .get(&reload.id)
+                        .is_some_and(|watched| watched.path == reload.path)
+                        && assets.contains(reload.id)
+                })
+                .collect(),
+            None => vec![],
+        };
+        if self.last_poll.elapsed() < POLL_INTERVAL {
+            return reloads;
+        }
+        self.last_poll = Instant::now();
+
+        self.watched.retain(|id, _| assets.contains(*id));
+        for (id, path) in assets.paths() {
+            let kind = if assets.is::<Texture>(id) {
+                AssetKind::Texture
+            } else if assets.is::<Geom>(id) {
+                AssetKind::Geom
+            } else if assets.is::<Environment>(id) {
+                AssetKind::Environment
+            } else {
+                continue;
+            };
+            // the file as it is when the asset is first seen is the one it was loaded from
+            let modified = self.modified(path);
+            let watched = self.watched.entry(id).or_insert_with(|| Watched {
+                path: path.to_string(),
+                kind,
+                modified,
+            });
+            if watched.modified == modified || modified.is_none() {
+                continue;
+            }
+            watched.modified = modified;
+
+            let dir = self.dir.clone().unwrap();
+            let worker = self.worker.get_or_insert_with(|| Self::spawn_worker(dir));
+            // the worker runs until the watcher is dropped, sending can't fail
+            _ = worker
+                .requests
+                .send((id, watched.path.clone(), watched.kind));
+        }
+        reloads
+    }
+
+    fn modified(&self, path: &str) -> Option<SystemTime> {
+        let path = self.dir.as_ref()?.join(path);
+        fs::metadata(path).ok()?.modified().ok()
+    }
+
+    fn spawn_worker(dir: PathBuf) -> Worker {
+        let (requests, requested) = mpsc::channel::<(AssetId, String, AssetKind)>();
+        let (loaded, reloads) = mpsc::channel();
+        let thread = thread::spawn(move || {
+            for (id, path, kind) in requested {
+                let asset = Self::load(&dir.join(&path), kind);
+                if loaded.send(AssetReload { id, path, asset }).is_err() {
+                    break;
+                }
+            }
+        });
+
+        Worker {
+            requests,
+            reloads,
+            thread,
+        }
+    }
+
+    // The loaders panic on some malformed files, a file caught while it is written must not take
+    // the app down.
+    fn load(path: &Path, kind: AssetKind) -> Result<ReloadedAsset, String> {
+        let data = fs::read(path)
+            .map_err(|error| format!("failed to read {}: {}", path.display(), error))?;
+        let asset = panic::catch_unwind(AssertUnwindSafe(|| match kind {
+            AssetKind::Texture => Texture::load(&data).map(ReloadedAsset::Texture),
+            AssetKind::Geom => Geom::load(&data).map(ReloadedAsset::Geom),
+            AssetKind::Environment => Environment::load(&data).map(ReloadedAsset::Environment),
+        }));
+        match asset {
+            Ok(Some(asset)) => Ok(asset),
+            Ok(None) | Err(_) => Err(format!("failed to load {}", path.display())),
+        }
+    }
+}
+
+impl Drop for AssetWatcher {
+    fn drop(&mut self) {
+        if let Some(worker) = self.worker.take() {
+            // ends the worker's loop once the file it loads is done
+            drop(worker.requests);
+            _ = worker.thread.join();
+        }
+    }
+}
@@ -2,21 +2,22 @@ use super::asset_handle::{AssetHandle, AssetId};
 use super::asset_impl::AssetImpl;
 use super::{AssetBundle, AssetBundle2};
 use egui::ahash::{HashMap, HashMapExt};
-use rust_embed::RustEmbed;
 use std::any::Any;
 use std::borrow::Cow;
-use std::hash::Hash;
 use std::sync::atomic::{AtomicU32, Ordering};
 
 #[derive(Debug)]
 pub struct Assets {
     pool: HashMap<AssetId, Box<dyn Any>>,
+    // of the assets loaded by `handle_path`, scene files refer to those by it
+    paths: HashMap<AssetId, String>,
 }
 
 impl Assets {
     pub fn new() -> Self {
         Assets {
             pool: HashMap::new(),
+            paths: HashMap::new(),
         }
     }
 
@@ -28,18 +29,22 @@ impl Assets {
         Some(AssetBundle2::get(path)?.data)
     }
 
-    pub fn handle_path<T: AssetImpl>(self: &mut Self, path: &str) -> Option<AssetHandle<T>> {
+    pub fn handle_path<T: AssetImpl>(&mut self, path: &str) -> Option<AssetHandle<T>> {
         let data = Assets::load_raw(path);
         match data {
             None => None,
             Some(data) => match T::load(data.as_ref()) {
                 None => None,
-                Some(asset) => Some(self.handle(asset)),
+                Some(asset) => {
+                    let handle = self.handle(asset);
+                    self.paths.insert(handle.id, path.to_string());
+                    Some(handle)
+                }
             },
         }
     }
 
-    pub fn handle<T: AssetImpl>(self: &mut Self, asset: T) -> AssetHandle<T> {
+    pub fn handle<T: AssetImpl>(&mut self, asset: T) -> AssetHandle<T> {
         static COUNT: AtomicU32 = AtomicU32::new(1);
         // let mut rng = thread_rng();
         // let rnd: u64 = rng.gen_range(0..1 << 16);
@@ -51,6 +56,25 @@ impl Assets {
         AssetHandle::new(id)
     }
 
+    // None for assets created in code.
+    pub fn path<T: AssetImpl>(&self, handle: &AssetHandle<T>) -> Option<&str> {
+        self.paths.get(&handle.id).map(String::as_str)
+    }
+
+    // The assets loaded by `handle_path` with their paths.
+    pub fn paths(&self) -> impl Iterator<Item = (AssetId, &str)> {
+        self.paths.iter().map(|(id, path)| (*id, path.as_str()))
+    }
+
+    pub fn contains(&self, id: AssetId) -> bool {
+        self.pool.contains_key(&id)
+    }
+
+    // Whether the asset is there and of the type.
+    pub fn is<T: AssetImpl>(&self, id: AssetId) -> bool {
+        self.pool.get(&id).is_some_and(|asset| asset.is::<T>())
+    }
+
     pub fn load<T: AssetImpl>(&self, handle: &AssetHandle<T>) -> Option<&T> {
         let asset = self.pool.get(&handle.id).unwrap();
         asset.downcast_ref::<T>()
@@ -60,4 +84,17 @@ impl Assets {
         let asset = self.pool.get_mut(&handle.id).unwrap();
         asset.downcast_mut::<T>()
     }
+
+    // Every asset of the type, e.g. to patch all materials using a reloaded shader.
+    pub fn iter_mut<T: AssetImpl>(&mut self) -> impl Iterator<Item = (AssetId, &mut T)> {
+        self.pool
+            .iter_mut()
+            .filter_map(|(id, asset)| Some((*id, asset.downcast_mut::<T>()?)))
+    }
+
+    pub fn remove<T: AssetImpl>(&mut self, handle: &AssetHandle<T>) -> Option<T> {
+        self.paths.remove(&handle.id);
+        let asset = self.pool.remove(&handle.id)?;
+        asset.downcast::<T>().ok().map(|asset| *asset)
+    }
 }
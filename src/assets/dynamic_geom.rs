@@ -0,0 +1,147 @@
+use crate::assets::asset_impl::AssetImpl;
+use crate::math::{Aabb, Vec3};
+use crate::renderer::vertex::Vertex;
+use crate::renderer::ForwardRenderer;
+use std::ops::Range;
+
+const COPIES: usize = ForwardRenderer::FRAMES_IN_FLIGHT as usize;
+
+// What changed since a GPU copy was last written, None when it is up to date.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct DirtyRanges {
+    pub vertices: Option<Range<usize>>,
+    pub indices: Option<Range<usize>>,
+}
+
+impl DirtyRanges {
+    fn extend(range: &mut Option<Range<usize>>, written: Range<usize>) {
+        *range = Some(match range.take() {
+            Some(range) => range.start.min(written.start)..range.end.max(written.end),
+            None => written,
+        });
+    }
+}
+
+// Geometry rewritten at runtime, e.g. trails, ribbons, debug meshes or cloth. The GPU keeps one
+// copy per frame in flight, so writing never waits on a frame still drawing the old data, and a
+// copy only gets the ranges written since it was last drawn. Draw it with a `DynamicMesh`.
+#[derive(Debug, Clone)]
+pub struct DynamicGeom {
+    vertices: Vec<Vertex>,
+    indices: Vec<u32>,
+    bounds: Aabb,
+    bounds_dirty: bool,
+    dirty: [DirtyRanges; COPIES],
+    // bumped by every change, see `revision`
+    revision: u64,
+}
+
+impl AssetImpl for DynamicGeom {}
+
+impl DynamicGeom {
+    pub fn new(vertices: Vec<Vertex>, indices: Vec<u32>) -> Self {
+        let mut geom = Self {
+            vertices: vec![],
+            indices: vec![],
+            bounds: Aabb::empty(),
+            bounds_dirty: false,
+            dirty: Default::default(),
+            revision: 0,
+        };
+        geom.set(vertices, indices);
+        geom
+    }
+
+    pub fn vertices(&self) -> &[Vertex] {
+        &self.vertices
+    }
+
+    pub fn indices(&self) -> &[u32] {
+        &self.indices
+    }
+
+    // Changes whenever the geometry does, for CPU side copies of it to notice.
+    pub fn revision(&self) -> u64 {
+        self.revision
+    }
+
+    // object space, of the current vertices
+    pub fn bounds(&mut self) -> Aabb {
+        if self.bounds_dirty {
+            self.bounds = Aabb::from_points(self.vertices.iter().map(|v| Vec3::from(v.position)));
+            self.bounds_dirty = false;
+        }
+        self.bounds
+    }
+
+    // Replaces everything, the whole geometry is uploaded again.
+    pub fn set(&mut self, vertices: Vec<Vertex>, indices: Vec<u32>) {
+        self.vertices = vertices;
+        self.indices = indices;
+        self.bounds_dirty = true;
+        self.revision += 1;
+        for dirty in &mut self.dirty {
+            DirtyRanges::extend(&mut dirty.vertices, 0..self.vertices.len());
+            DirtyRanges::extend(&mut dirty.indices, 0..self.indices.len());
+        }
+    }
+
+    // Overwrites the vertices from `start` on, growing the geometry when they run past its end.
+    // Only the written range is uploaded.
+    pub fn write_vertices(&mut self, start: usize, vertices: &[Vertex]) {
+        if vertices.is_empty() {
+            return;
+        }
+        let end = start + vertices.len();
+        if end > self.vertices.len() {
+            self.vertices.resize(end, vertices[0]);
+        }
+        self.vertices[start..end].copy_from_slice(vertices);
+        self.bounds_dirty = true;
+        self.revision += 1;
+        for dirty in &mut self.dirty {
+            DirtyRanges::extend(&mut dirty.vertices, start..end);
+        }
+    }
+
+    // Same as `write_vertices`, indices refer to the vertices as they are when drawn.
+    pub fn write_indices(&mut self, start: usize, indices: &[u32]) {
+        if indices.is_empty() {
+            return;
+        }
+        let end = start + indices.len();
+        if end > self.indices.len() {
+            self.indices.resize(end, 0);
+        }
+        self.indices[start..end].copy_from_slice(indices);
+        self.revision += 1;
+        for dirty in &mut self.dirty {
+            DirtyRanges::extend(&mut dirty.indices, start..end);
+        }
+    }
+
+    // Drops what is past the counts, nothing has to be uploaded for it.
+    pub fn truncate(&mut self, vertex_count: usize, index_count: usize) {
+        if vertex_count < self.vertices.len() {
+            self.vertices.truncate(vertex_count);
+            self.bounds_dirty = true;
+        }
+        self.indices.truncate(index_count);
+        self.revision += 1;
+        for dirty in &mut self.dirty {
+            let clamp = |range: &mut Option<Range<usize>>, len: usize| {
+                *range = range
+                    .take()
+                    .map(|range| range.start.min(len)..range.end.min(len))
+                    .filter(|range| !range.is_empty());
+            };
+            clamp(&mut dirty.vertices, self.vertices.len());
+            clamp(&mut dirty.indices, self.indices.len());
+        }
+    }
+
+    // The ranges the copy of the frame in flight misses, it counts as up to date afterwards.
+    pub(crate) fn take_dirty(&mut self, frame_index: usize) -> DirtyRanges {
+        std::mem::take(&mut self.dirty[frame_index])
+    }
+}
@@ -0,0 +1,163 @@
+use crate::assets::asset_impl::AssetImpl;
+use crate::math::Vec3;
+use crate::renderer::Tonemapping;
+
+// The lighting setup of a scene, loaded from a `.environment` file the scene refers to and applied
+// with `Mirage::apply_environment`. One `key = value` per line, `#` starts a comment:
+//
+//     ambient_color = 0.6 0.7 1.0
+//     ambient_intensity = 0.2
+//     fog_color = 0.5 0.55 0.6
+//     fog_density = 0.02
+//     skybox = px.png nx.png py.png ny.png pz.png nz.png
+//     skybox_intensity = 1.0
+//     sun_direction = -0.3 -1.0 -0.2
+//     sun_color = 1.0 0.95 0.9
+//     sun_intensity = 2.0
+//     sun_contact_shadows = true
+//     tonemapping = aces
+//     exposure = 0.0
+//     sharpening = 0.3
+//
+// Keys left out keep their default, which is what a scene gets without an environment.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Environment {
+    pub ambient_color: Vec3,
+    pub ambient_intensity: f32,
+    pub fog_color: Vec3,
+    // exponential, 0 turns the fog off
+    pub fog_density: f32,
+    // paths of the cube faces in +X, -X, +Y, -Y, +Z, -Z order, see `Mirage::load_environment`
+    pub skybox: Option<[String; 6]>,
+    pub skybox_intensity: f32,
+    // the way the sunlight travels, in world space
+    pub sun_direction: Vec3,
+    pub sun_color: Vec3,
+    // 0 turns the sun off
+    pub sun_intensity: f32,
+    // see `ContactShadowRenderer`
+    pub sun_contact_shadows: bool,
+    pub tonemapping: Tonemapping,
+    // in stops
+    pub exposure: f32,
+    // None leaves the sharpening pass off
+    pub sharpening: Option<f32>,
+}
+
+impl Default for Environment {
+    fn default() -> Self {
+        Self {
+            ambient_color: Vec3::one(),
+            ambient_intensity: 0.0,
+            fog_color: Vec3::one(),
+            fog_density: 0.0,
+            skybox: None,
+            skybox_intensity: 1.0,
+            sun_direction: Vec3::new(0.0, -1.0, 0.0),
+            sun_color: Vec3::one(),
+            sun_intensity: 0.0,
+            sun_contact_shadows: false,
+            tonemapping: Tonemapping::Aces,
+            exposure: 0.0,
+            sharpening: None,
+        }
+    }
+}
+
+impl Environment {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut environment = Self::default();
+        for (number, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap().trim();
+            if line.is_empty() {
+                continue;
+            }
+            let Some((key, value)) = line.split_once('=') else {
+                return Err(format!("line {}: expected `key = value`", number + 1));
+            };
+            environment
+                .set(key.trim(), value.trim())
+                .map_err(|error| format!("line {}: {}", number + 1, error))?;
+        }
+        Ok(environment)
+    }
+
+    fn set(&mut self, key: &str, value: &str) -> Result<(), String> {
+        match key {
+            "ambient_color" => self.ambient_color = parse_vec3(value)?,
+            "ambient_intensity" => self.ambient_intensity = parse_f32(value)?,
+            "fog_color" => self.fog_color = parse_vec3(value)?,
+            "fog_density" => self.fog_density = parse_f32(value)?,
+            "skybox" => {
+                let faces = value
+                    .split_whitespace()
+                    .map(String::from)
+                    .collect::<Vec<_>>();
+                let faces: [String; 6] = faces
+                    .try_into()
+                    .map_err(|_| "skybox needs six cube faces".to_string())?;
+                self.skybox = Some(faces);
+            }
+            "skybox_intensity" => self.skybox_intensity = parse_f32(value)?,
+            "sun_direction" => self.sun_direction = parse_vec3(value)?.normalize(),
+            "sun_color" => self.sun_color = parse_vec3(value)?,
+            "sun_intensity" => self.sun_intensity = parse_f32(value)?,
+            "sun_contact_shadows" => {
+                self.sun_contact_shadows = match value {
+                    "true" => true,
+                    "false" => false,
+                    _ => return Err(format!("expected true or false, got {}", value)),
+                }
+            }
+            "tonemapping" => {
+                self.tonemapping = match value {
+                    "none" => Tonemapping::None,
+                    "reinhard" => Tonemapping::Reinhard,
+                    "aces" => Tonemapping::Aces,
+                    _ => return Err(format!("unknown tonemapping {}", value)),
+                }
+            }
+            "exposure" => self.exposure = parse_f32(value)?,
+            "sharpening" => {
+                self.sharpening = match value {
+                    "none" => None,
+                    value => Some(parse_f32(value)?),
+                }
+            }
+            _ => return Err(format!("unknown key {}", key)),
+        }
+        Ok(())
+    }
+}
+
+fn parse_f32(value: &str) -> Result<f32, String> {
+    value
+        .parse::<f32>()
+        .map_err(|_| format!("expected a number, got {}", value))
+}
+
+fn parse_vec3(value: &str) -> Result<Vec3, String> {
+    let values = value
+        .split_whitespace()
+        .map(parse_f32)
+        .collect::<Result<Vec<_>, _>>()?;
+    match values[..] {
+        [x, y, z] => Ok(Vec3::new(x, y, z)),
+        _ => Err(format!("expected three numbers, got {}", value)),
+    }
+}
+
+impl AssetImpl for Environment {
+    fn load(data: &[u8]) -> Option<Self> {
+        let text = std::str::from_utf8(data)
+            .map_err(|error| log::error!("failed to read environment: {}", error))
+            .ok()?;
+        Self::parse(text)
+            .map_err(|error| log::error!("failed to parse environment: {}", error))
+            .ok()
+    }
+}
@@ -0,0 +1,40 @@
+use super::asset_impl::AssetImpl;
+use ab_glyph::FontArc;
+use std::borrow::Cow;
+
+// A TrueType or OpenType font. The text renderer rasterizes the glyphs it draws into an atlas.
+#[derive(Debug, Clone)]
+pub struct Font {
+    pub font: FontArc,
+}
+
+impl Font {
+    pub fn from_bytes(data: Vec<u8>) -> Option<Self> {
+        FontArc::try_from_vec(data)
+            .map(|font| Self { font })
+            .map_err(|error| log::error!("failed to load font: {}", error))
+            .ok()
+    }
+
+    // The proportional font egui draws with, for text without a font of its own.
+    pub fn builtin() -> Self {
+        let mut definitions = egui::FontDefinitions::default();
+        let data = definitions
+            .font_data
+            .remove("Ubuntu-Light")
+            .expect("failed to find the builtin font!");
+        let font = match data.font {
+            Cow::Borrowed(data) => FontArc::try_from_slice(data),
+            Cow::Owned(data) => FontArc::try_from_vec(data),
+        };
+        Self {
+            font: font.expect("failed to load the builtin font!"),
+        }
+    }
+}
+
+impl AssetImpl for Font {
+    fn load(data: &[u8]) -> Option<Self> {
+        Self::from_bytes(data.to_vec())
+    }
+}
@@ -1,18 +1,146 @@
 use crate::assets::asset_impl::AssetImpl;
-use crate::assets::Assets;
+use crate::loaders::obj;
+use crate::math::{Aabb, Ray, Vec3};
 use crate::renderer::vertex::Vertex;
-use std::io::Cursor;
-use tobj::LoadError;
+use std::collections::HashMap;
+
+// Header of the cooked geometry, followed by the vertex and index counts and the little endian
+// data, see `Geom::to_bytes`.
+const GEOM_MAGIC: [u8; 8] = *b"MIRGEOM1";
+const VERTEX_FLOATS: usize = 11;
 
 #[derive(Debug, Clone)]
 pub struct Geom {
     pub vertices: Vec<Vertex>,
     pub indices: Vec<u32>,
+    // object space, computed once when the geometry is created
+    pub bounds: Aabb,
 }
 
 impl Geom {
     pub fn new(vertices: Vec<Vertex>, indices: Vec<u32>) -> Self {
-        Self { vertices, indices }
+        let bounds = Aabb::from_points(vertices.iter().map(|v| Vec3::from(v.position)));
+        Self {
+            vertices,
+            indices,
+            bounds,
+        }
+    }
+
+    // Closest triangle the ray hits, both in object space.
+    pub fn intersect_ray(&self, ray: &Ray) -> Option<f32> {
+        self.indices
+            .chunks_exact(3)
+            .filter_map(|triangle| {
+                let [a, b, c] = [0, 1, 2]
+                    .map(|corner| Vec3::from(self.vertices[triangle[corner] as usize].position));
+                ray.intersect_triangle(a, b, c)
+            })
+            .min_by(f32::total_cmp)
+    }
+
+    // XZ grid centered at origin, facing +Y.
+    pub fn plane(size: f32, segments: u32) -> Self {
+        let segments = segments.max(1);
+        let step = size / segments as f32;
+        let half = size / 2.0;
+
+        let mut vertices = Vec::with_capacity(((segments + 1) * (segments + 1)) as usize);
+        for z in 0..=segments {
+            for x in 0..=segments {
+                let u = x as f32 / segments as f32;
+                let v = z as f32 / segments as f32;
+                vertices.push(Vertex {
+                    position: [x as f32 * step - half, 0.0, z as f32 * step - half],
+                    color: [1.0, 1.0, 1.0],
+                    uv: [u, v],
+                    normal: [0.0, 1.0, 0.0],
+                });
+            }
+        }
+
+        let mut indices = Vec::with_capacity((segments * segments * 6) as usize);
+        for z in 0..segments {
+            for x in 0..segments {
+                let i0 = z * (segments + 1) + x;
+                let i1 = i0 + 1;
+                let i2 = i0 + segments + 1;
+                let i3 = i2 + 1;
+                // counter clockwise seen from above
+                indices.extend_from_slice(&[i0, i2, i1, i1, i2, i3]);
+            }
+        }
+
+        Self::new(vertices, indices)
+    }
+
+    // Welds identical vertices and orders the rest by their first use in the index buffer, so
+    // drawing walks the vertex buffer forward instead of jumping around it.
+    pub fn optimize(&mut self) {
+        let mut remap = HashMap::new();
+        let mut vertices = Vec::with_capacity(self.vertices.len());
+        for index in self.indices.iter_mut() {
+            let vertex = self.vertices[*index as usize];
+            let key = Self::vertex_floats(&vertex).map(f32::to_bits);
+            *index = *remap.entry(key).or_insert_with(|| {
+                vertices.push(vertex);
+                vertices.len() as u32 - 1
+            });
+        }
+        self.vertices = vertices;
+    }
+
+    // The native format `mirage cook` writes, loaded back without parsing any text.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(
+            GEOM_MAGIC.len() + 8 + self.vertices.len() * VERTEX_FLOATS * 4 + self.indices.len() * 4,
+        );
+        bytes.extend_from_slice(&GEOM_MAGIC);
+        bytes.extend_from_slice(&(self.vertices.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(&(self.indices.len() as u32).to_le_bytes());
+        for vertex in &self.vertices {
+            for float in Self::vertex_floats(vertex) {
+                bytes.extend_from_slice(&float.to_le_bytes());
+            }
+        }
+        for index in &self.indices {
+            bytes.extend_from_slice(&index.to_le_bytes());
+        }
+        bytes
+    }
+
+    fn from_bytes(data: &[u8]) -> Option<Self> {
+        let data = data.strip_prefix(&GEOM_MAGIC)?;
+        let read_u32 = |bytes: &[u8]| u32::from_le_bytes(bytes.try_into().unwrap());
+        let vertex_count = read_u32(data.get(0..4)?) as usize;
+        let index_count = read_u32(data.get(4..8)?) as usize;
+        let vertex_bytes = vertex_count * VERTEX_FLOATS * 4;
+        let vertex_data = data.get(8..8 + vertex_bytes)?;
+        let index_data = data.get(8 + vertex_bytes..8 + vertex_bytes + index_count * 4)?;
+
+        let vertices = vertex_data
+            .chunks_exact(VERTEX_FLOATS * 4)
+            .map(|chunk| {
+                let f =
+                    |index: usize| f32::from_le_bytes(chunk[index * 4..][..4].try_into().unwrap());
+                Vertex {
+                    position: [f(0), f(1), f(2)],
+                    color: [f(3), f(4), f(5)],
+                    uv: [f(6), f(7)],
+                    normal: [f(8), f(9), f(10)],
+                }
+            })
+            .collect();
+        let indices = index_data.chunks_exact(4).map(read_u32).collect();
+        Some(Self::new(vertices, indices))
+    }
+
+    fn vertex_floats(vertex: &Vertex) -> [f32; VERTEX_FLOATS] {
+        let [px, py, pz] = vertex.position;
+        let [r, g, b] = vertex.color;
+        let [u, v] = vertex.uv;
+        let [nx, ny, nz] = vertex.normal;
+        [px, py, pz, r, g, b, u, v, nx, ny, nz]
     }
 }
 
@@ -30,6 +158,7 @@ impl Default for Geom {
             position: [data[0], data[1], data[2]],
             color: [data[3], data[4], data[5]],
             uv: [data[6], data[7]],
+            normal: [0.0, 0.0, 1.0],
         })
         .to_vec();
 
@@ -39,38 +168,15 @@ impl Default for Geom {
 
 impl AssetImpl for Geom {
     fn load(data: &[u8]) -> Option<Self> {
-        let mut buffer = Cursor::new(data);
-        let (models, _) = tobj::load_obj_buf(&mut buffer, &tobj::GPU_LOAD_OPTIONS, |mat_path| {
-            if let Some(file) = Assets::load_raw(mat_path.to_str().unwrap()) {
-                let mut buffer = Cursor::new(file);
-                return tobj::load_mtl_buf(&mut buffer);
-            }
-
-            // #[cfg(feature = "log")]
-            // log::error!("load_mtl - failed to open {:?} due to {}", file_name, _e);
-            Err(LoadError::OpenFileFailed)
-        })
-        .expect("failed to load obj!");
-
-        let mesh = &models[0].mesh;
-        let vertex_count = mesh.positions.len() / 3;
-        let mut vertices = Vec::with_capacity(vertex_count);
-
-        for i in 0..vertex_count {
-            let vertex = Vertex {
-                position: [
-                    mesh.positions[i * 3],
-                    mesh.positions[i * 3 + 1],
-                    mesh.positions[i * 3 + 2],
-                ],
-                color: [1.0, 1.0, 1.0],
-                uv: [mesh.texcoords[i * 2], 1.0 - mesh.texcoords[i * 2 + 1]],
-            };
-            vertices.push(vertex);
+        if data.starts_with(&GEOM_MAGIC) {
+            return Self::from_bytes(data);
         }
 
-        let indices = mesh.indices.to_vec();
-
-        Some(Self::new(vertices, indices))
+        // a single mesh, the loader in `loaders::obj` keeps the objects and materials apart
+        let (models, materials) = obj::parse_obj(data, "").expect("failed to load obj!");
+        let mesh = &models.first()?.mesh;
+        let materials = materials.unwrap_or_default();
+        let material = mesh.material_id.and_then(|id| materials.get(id));
+        Some(obj::geom_from_mesh(mesh, material))
     }
 }
@@ -1,35 +1,165 @@
 use crate::assets::asset_impl::AssetImpl;
 use crate::assets::{AssetHandle, Texture};
-use crate::renderer::Shading;
+use crate::gpu::{SamplerDesc, TextureChannel, TextureSwizzle};
+use crate::math::{Vec2, Vec3, Vec4};
+use crate::renderer::{ParamKind, Shading};
 use egui::ahash::{HashMap, HashMapExt};
 
+// A value of the material parameter block the shading declares, or of a texture slot.
+#[derive(Debug, Clone)]
+pub enum MaterialParam {
+    Float(f32),
+    Vec2(Vec2),
+    Vec3(Vec3),
+    Vec4(Vec4),
+    // linear rgba
+    Color([f32; 4]),
+    Texture(Option<AssetHandle<Texture>>),
+}
+
+impl MaterialParam {
+    // Into a field of `kind`, missing components are zero and extra ones are dropped.
+    fn components(&self, kind: ParamKind) -> Vec<f32> {
+        let values = match self {
+            MaterialParam::Float(value) => vec![*value],
+            MaterialParam::Vec2(value) => vec![value.x, value.y],
+            MaterialParam::Vec3(value) => vec![value.x, value.y, value.z],
+            MaterialParam::Vec4(value) => vec![value.x, value.y, value.z, value.w],
+            MaterialParam::Color(value) => value.to_vec(),
+            MaterialParam::Texture(_) => vec![],
+        };
+        let count = kind.size() as usize / 4;
+        (0..count)
+            .map(|index| values.get(index).copied().unwrap_or(0.0))
+            .collect()
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct Material {
     pub shading: Shading,
     props: HashMap<&'static str, Option<AssetHandle<Texture>>>,
+    // per slot, slots without one read the texture as is
+    swizzles: HashMap<&'static str, TextureSwizzle>,
+    // per slot, slots without one sample with the texture's own, see `Texture::sampler`
+    samplers: HashMap<&'static str, SamplerDesc>,
+    params: HashMap<&'static str, MaterialParam>,
+    // bumped by every `set_param`, the GPU copy of a frame is rewritten when it falls behind
+    params_version: u64,
 }
 
 impl Material {
+    // Texture slots that hold data rather than color, their textures are sampled linear.
+    pub const LINEAR_SLOTS: [&'static str; 6] = [
+        "normal",
+        "occlusion",
+        "roughness",
+        "metallic",
+        "height",
+        "mask",
+    ];
+
     pub fn new(shading: Shading) -> Self {
         Self {
             shading,
             props: HashMap::new(),
+            swizzles: HashMap::new(),
+            samplers: HashMap::new(),
+            params: HashMap::new(),
+            params_version: 0,
         }
     }
 
+    // Fields of the shading's parameter block, see `Shading::set_params`, or texture slots. Takes
+    // effect in the next frame, without rebuilding the pipeline.
+    pub fn set_param(&mut self, key: &'static str, value: MaterialParam) {
+        match value {
+            MaterialParam::Texture(texture) => self.set_texture(key, texture),
+            value => {
+                self.params.insert(key, value);
+                self.params_version += 1;
+            }
+        }
+    }
+
+    pub fn get_param(&self, key: &str) -> Option<MaterialParam> {
+        match self.params.get(key) {
+            Some(value) => Some(value.clone()),
+            None => self
+                .props
+                .contains_key(key)
+                .then(|| MaterialParam::Texture(self.get_texture(key))),
+        }
+    }
+
+    pub fn params_version(&self) -> u64 {
+        self.params_version
+    }
+
+    // The parameter block as the shaders read it, fields without a value are zero.
+    pub fn param_bytes(&self) -> Vec<u8> {
+        let (offsets, size) = self.shading.param_layout();
+        let mut bytes = vec![0; size as usize];
+        for ((key, kind), offset) in self.shading.params.iter().zip(offsets) {
+            let Some(value) = self.params.get(key) else {
+                continue;
+            };
+            for (index, component) in value.components(*kind).into_iter().enumerate() {
+                let start = offset as usize + index * 4;
+                bytes[start..start + 4].copy_from_slice(&component.to_ne_bytes());
+            }
+        }
+        bytes
+    }
+
     pub fn set_texture(&mut self, key: &'static str, value: Option<AssetHandle<Texture>>) {
         self.props.insert(key, value);
     }
 
-    pub fn get_texture(&self, key: &str) -> Option<AssetHandle<Texture>> {
-        match self.props.get(key) {
-            None => None,
-            Some(value) => match value {
-                None => None,
-                Some(tex) => Some(tex.to_owned()),
-            },
+    pub fn set_texture_swizzled(
+        &mut self,
+        key: &'static str,
+        value: Option<AssetHandle<Texture>>,
+        swizzle: TextureSwizzle,
+    ) {
+        self.props.insert(key, value);
+        self.swizzles.insert(key, swizzle);
+    }
+
+    // glTF style occlusion (R), roughness (G), metallic (B) in a single image,
+    // each slot reads its channel from the same texture.
+    pub fn set_packed_orm(&mut self, value: Option<AssetHandle<Texture>>) {
+        for (key, channel) in [
+            ("occlusion", TextureChannel::R),
+            ("roughness", TextureChannel::G),
+            ("metallic", TextureChannel::B),
+        ] {
+            self.set_texture_swizzled(key, value.clone(), TextureSwizzle::splat(channel));
         }
     }
+
+    // The slots with a texture set.
+    pub fn textures(&self) -> impl Iterator<Item = (&'static str, &AssetHandle<Texture>)> {
+        self.props
+            .iter()
+            .filter_map(|(key, value)| Some((*key, value.as_ref()?)))
+    }
+
+    pub fn set_sampler(&mut self, key: &'static str, sampler: SamplerDesc) {
+        self.samplers.insert(key, sampler);
+    }
+
+    pub fn get_sampler(&self, key: &str) -> Option<SamplerDesc> {
+        self.samplers.get(key).copied()
+    }
+
+    pub fn get_swizzle(&self, key: &str) -> TextureSwizzle {
+        self.swizzles.get(key).copied().unwrap_or_default()
+    }
+
+    pub fn get_texture(&self, key: &str) -> Option<AssetHandle<Texture>> {
+        self.props.get(key)?.clone()
+    }
 }
 
 impl AssetImpl for Material {}
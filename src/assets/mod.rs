@@ -1,15 +1,29 @@
 mod asset_handle;
 mod asset_impl;
+mod asset_watcher;
+#[allow(clippy::module_inception)]
 mod assets;
+mod dynamic_geom;
+mod environment;
+mod font;
 mod geom;
 mod material;
 mod texture;
+mod video_texture;
 
 pub use asset_handle::{AssetHandle, AssetId};
+pub(crate) use asset_impl::AssetImpl;
+pub use asset_watcher::{AssetWatcher, ReloadedAsset};
 pub use assets::Assets;
+pub use dynamic_geom::DynamicGeom;
+pub use environment::Environment;
+pub use font::Font;
 pub use geom::Geom;
-pub use material::Material;
+pub use material::{Material, MaterialParam};
 pub use texture::Texture;
+#[cfg(feature = "ffmpeg")]
+pub use video_texture::FfmpegSource;
+pub use video_texture::{VideoFrame, VideoSource, VideoTexture};
 
 use rust_embed::RustEmbed;
 
@@ -1,17 +1,224 @@
 use super::asset_impl::AssetImpl;
+use crate::gpu::{ColorSpace, MipFilter, SamplerDesc, TextureFormat};
+use crate::loaders::ktx2::{is_ktx2, load_ktx2};
+use crate::math::Vec3;
+use image::imageops::{self, FilterType};
+use image::RgbaImage;
+use std::ops::Range;
 
 #[derive(Debug, Clone)]
 pub struct Texture {
     pub width: u32,
     pub height: u32,
     pub mip_levels: u32,
+    // 1, or 6 for a cube map with the faces in +X, -X, +Y, -Y, +Z, -Z order
+    pub layers: u32,
+    // the layout of `pixels`, whether its sRGB or UNORM variant is uploaded follows `color_space`
+    pub format: TextureFormat,
+    pub color_space: ColorSpace,
     pub pixels: Vec<u8>,
+    // byte range of every level in `pixels` when the file came with its mip chain, empty when
+    // the chain is generated on upload from level 0. A level holds all layers back to back.
+    pub mips: Vec<Range<usize>>,
+    // how a generated chain is filtered, normal and roughness maps alias in the distance otherwise
+    pub mip_filter: MipFilter,
+    // materials may sample a slot differently, see `Material::set_sampler`
+    pub sampler: SamplerDesc,
 }
 
-impl Texture {}
+impl Texture {
+    // Uncompressed sRGB color, the mip chain is generated on upload.
+    pub fn rgba8(width: u32, height: u32, mip_levels: u32, pixels: Vec<u8>) -> Self {
+        Self {
+            width,
+            height,
+            mip_levels,
+            layers: 1,
+            format: TextureFormat::Rgba8Srgb,
+            color_space: ColorSpace::Srgb,
+            pixels,
+            mips: vec![],
+            mip_filter: MipFilter::Linear,
+            sampler: SamplerDesc::default(),
+        }
+    }
+
+    // Normal maps, roughness or masks are linear, sampling them as sRGB bends every value.
+    pub fn set_color_space(&mut self, color_space: ColorSpace) {
+        self.color_space = color_space;
+    }
+
+    // e.g. clamped for UI or nearest for pixel art.
+    pub fn set_sampler(&mut self, sampler: SamplerDesc) {
+        self.sampler = sampler;
+    }
+
+    // The pixels are data for `mip_filter` rather than color, they are sampled as linear.
+    pub fn set_mip_filter(&mut self, mip_filter: MipFilter) {
+        if mip_filter != MipFilter::Linear {
+            self.color_space = ColorSpace::Linear;
+        }
+        self.mip_filter = mip_filter;
+    }
+
+    // The format the texture is created with on the device.
+    pub fn upload_format(&self) -> TextureFormat {
+        self.format.with_color_space(self.color_space)
+    }
+
+    // Cube map of six square images of the same size and format, e.g. the faces of a skybox.
+    pub fn cube(faces: [&Texture; 6]) -> Result<Self, String> {
+        let first = faces[0];
+        if first.width != first.height {
+            return Err("cube map faces have to be square".into());
+        }
+        if faces.iter().any(|face| {
+            face.layers != 1
+                || face.width != first.width
+                || face.height != first.height
+                || face.format != first.format
+                || face.color_space != first.color_space
+                || face.mips.len() != first.mips.len()
+        }) {
+            return Err("cube map faces differ in size, format, color space or mip chain".into());
+        }
+
+        // without a precomputed chain the pixels are level 0, the rest is generated on upload
+        let mut pixels = vec![];
+        let mut mips = vec![];
+        if first.mips.is_empty() {
+            faces
+                .iter()
+                .for_each(|face| pixels.extend_from_slice(&face.pixels));
+        }
+        for level in 0..first.mips.len() {
+            let start = pixels.len();
+            for face in faces {
+                pixels.extend_from_slice(&face.pixels[face.mips[level].clone()]);
+            }
+            mips.push(start..pixels.len());
+        }
+
+        Ok(Self {
+            width: first.width,
+            height: first.height,
+            mip_levels: first.mip_levels,
+            layers: 6,
+            format: first.format,
+            color_space: first.color_space,
+            pixels,
+            mips,
+            mip_filter: first.mip_filter,
+            sampler: first.sampler,
+        })
+    }
+
+    // Precomputes the mip chain of uncompressed color on the CPU, so it ships with the texture
+    // instead of being blitted on upload, e.g. when cooking.
+    pub fn build_mips(&mut self) -> Result<(), String> {
+        if self.format.is_compressed() {
+            return Err("mips of compressed textures can't be built".into());
+        }
+        if !self.mips.is_empty() {
+            return Ok(());
+        }
+
+        let (width, height) = (self.width, self.height);
+        let levels = (width.max(height) as f32).log2().floor() as u32 + 1;
+        let layer_size = (width * height * 4) as usize;
+        let mut images = self
+            .pixels
+            .chunks_exact(layer_size)
+            .map(|layer| RgbaImage::from_raw(width, height, layer.to_vec()).unwrap())
+            .collect::<Vec<_>>();
+
+        let mut pixels = vec![];
+        let mut mips = vec![];
+        for level in 0..levels {
+            // each level filters the previous one
+            if level > 0 {
+                images = images
+                    .iter()
+                    .map(|image| match self.mip_filter {
+                        MipFilter::Linear => imageops::resize(
+                            image,
+                            (width >> level).max(1),
+                            (height >> level).max(1),
+                            FilterType::Triangle,
+                        ),
+                        mip_filter => filter_mip(image, mip_filter, level == 1),
+                    })
+                    .collect();
+            }
+            let start = pixels.len();
+            images
+                .iter()
+                .for_each(|image| pixels.extend_from_slice(image.as_raw()));
+            mips.push(start..pixels.len());
+        }
+
+        self.mip_levels = levels;
+        self.pixels = pixels;
+        self.mips = mips;
+        Ok(())
+    }
+
+    pub fn is_cube(&self) -> bool {
+        self.layers == 6
+    }
+}
+
+// The next level of a normal or roughness chain, the same filtering as mip_filter.wgsl does on
+// upload. `from_base` is set when `image` is level 0.
+fn filter_mip(image: &RgbaImage, mip_filter: MipFilter, from_base: bool) -> RgbaImage {
+    let (width, height) = image.dimensions();
+    let texel = |x: u32, y: u32| {
+        let pixel = image.get_pixel(x.min(width - 1), y.min(height - 1)).0;
+        pixel.map(|channel| channel as f32 / 255.0)
+    };
+    let normal = |texel: [f32; 4]| {
+        let direction = Vec3::from(texel) * 2.0 - 1.0;
+        let length = if from_base { 1.0 } else { texel[3] };
+        direction.normalize() * length
+    };
+
+    RgbaImage::from_fn((width / 2).max(1), (height / 2).max(1), |x, y| {
+        let texels = [(0, 0), (1, 0), (0, 1), (1, 1)].map(|(dx, dy)| texel(x * 2 + dx, y * 2 + dy));
+        let mut result = [0.0; 4];
+        for texel in &texels {
+            for channel in 0..4 {
+                result[channel] += texel[channel] * 0.25;
+            }
+        }
+
+        if mip_filter == MipFilter::Normal {
+            // the average of the level 0 normals, shorter the more they diverge (Toksvig)
+            let average = texels
+                .iter()
+                .fold(Vec3::zero(), |sum, texel| sum + normal(*texel) * 0.25);
+            let length = average.len().max(1e-4);
+            let direction = average * (0.5 / length) + 0.5;
+            result = [direction.x, direction.y, direction.z, length];
+        } else if let Some(channel) = mip_filter.roughness_channel() {
+            // averaged as GGX alpha squared, the variance of the microfacets adds up (LEAN)
+            let alpha2 = texels
+                .iter()
+                .map(|texel| texel[channel].powi(4) * 0.25)
+                .sum::<f32>();
+            result[channel] = alpha2.sqrt().sqrt();
+        }
+        image::Rgba(result.map(|channel| (channel.clamp(0.0, 1.0) * 255.0).round() as u8))
+    })
+}
 
 impl AssetImpl for Texture {
     fn load(data: &[u8]) -> Option<Self> {
+        if is_ktx2(data) {
+            return load_ktx2(data)
+                .map_err(|error| log::error!("failed to load ktx2 texture: {}", error))
+                .ok();
+        }
+
         let image = image::load_from_memory(data).expect("failed to load image!");
         let image_rgba8 = image.to_rgba8();
         let width = image_rgba8.width();
@@ -19,11 +226,6 @@ impl AssetImpl for Texture {
         let mip_levels = ((width.min(height) as f32).log2().floor() + 1.0) as u32;
         let pixels = image_rgba8.into_raw();
 
-        Some(Self {
-            width,
-            height,
-            pixels,
-            mip_levels,
-        })
+        Some(Self::rgba8(width, height, mip_levels, pixels))
     }
 }
@@ -0,0 +1,279 @@
+use super::asset_impl::AssetImpl;
+use super::{AssetHandle, Assets, Texture};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+
+// frames decoded ahead of playback
+const FRAME_QUEUE: usize = 4;
+
+// One decoded picture in I420, the chroma planes are half the luma size rounded up.
+pub struct VideoFrame {
+    pub width: u32,
+    pub height: u32,
+    // Y, U and V, BT.709 limited range
+    pub planes: [Vec<u8>; 3],
+}
+
+impl VideoFrame {
+    pub fn chroma_size(width: u32, height: u32) -> (u32, u32) {
+        (width.div_ceil(2), height.div_ceil(2))
+    }
+
+    // Bytes of the three planes together.
+    pub fn byte_size(width: u32, height: u32) -> usize {
+        let (chroma_width, chroma_height) = Self::chroma_size(width, height);
+        (width * height + 2 * chroma_width * chroma_height) as usize
+    }
+}
+
+// A stream of frames, decoded on the worker thread of its `VideoTexture`.
+pub trait VideoSource: Send {
+    fn size(&self) -> (u32, u32);
+    fn frame_rate(&self) -> f32;
+    // None at the end of the stream or on a decoding error
+    fn next_frame(&mut self) -> Option<VideoFrame>;
+    // Starts over for looping, false when the source can't.
+    fn rewind(&mut self) -> bool;
+}
+
+// Plays a video into a texture materials can bind like any other. Frames are decoded ahead on a
+// worker thread, `tick` hands out the one due and the video renderer converts it to RGB on the
+// GPU, straight into the image behind `texture`.
+pub struct VideoTexture {
+    // black until the first frame is converted
+    pub texture: AssetHandle<Texture>,
+    pub width: u32,
+    pub height: u32,
+    pub playing: bool,
+    pub looping: bool,
+    // seconds per frame
+    frame_duration: f32,
+    // playback time not consumed by shown frames yet
+    clock: f32,
+    finished: Arc<AtomicBool>,
+    frames: Option<Receiver<VideoFrame>>,
+    stop: Arc<AtomicBool>,
+    worker: Option<JoinHandle<()>>,
+}
+
+impl VideoTexture {
+    pub fn new(mut source: Box<dyn VideoSource>, looping: bool, assets: &mut Assets) -> Self {
+        let (width, height) = source.size();
+        // a stand-in, the video renderer puts its own image behind the handle
+        let texture = assets.handle(Texture::rgba8(1, 1, 1, vec![0, 0, 0, 255]));
+        let frame_duration = 1.0 / source.frame_rate().max(1.0);
+
+        let (sender, frames) = mpsc::sync_channel(FRAME_QUEUE);
+        let stop = Arc::new(AtomicBool::new(false));
+        let finished = Arc::new(AtomicBool::new(false));
+        let worker = {
+            let stop = stop.clone();
+            let finished = finished.clone();
+            thread::spawn(move || {
+                while !stop.load(Ordering::Relaxed) {
+                    let frame = match source.next_frame() {
+                        Some(frame) => frame,
+                        None if looping && source.rewind() => continue,
+                        None => break,
+                    };
+                    // blocks while the queue is full, fails once the texture is gone
+                    if sender.send(frame).is_err() {
+                        break;
+                    }
+                }
+                finished.store(true, Ordering::Relaxed);
+            })
+        };
+
+        Self {
+            texture,
+            width,
+            height,
+            playing: true,
+            looping,
+            frame_duration,
+            clock: 0.0,
+            finished,
+            frames: Some(frames),
+            stop,
+            worker: Some(worker),
+        }
+    }
+
+    // Decodes the file with the ffmpeg executable, None when it can't be probed.
+    #[cfg(feature = "ffmpeg")]
+    pub fn open(path: &str, looping: bool, assets: &mut Assets) -> Option<Self> {
+        match ffmpeg::FfmpegSource::open(path) {
+            Ok(source) => Some(Self::new(Box::new(source), looping, assets)),
+            Err(error) => {
+                log::error!("failed to open video {}! {}", path, error);
+                None
+            }
+        }
+    }
+
+    // Advances playback, the newest frame that became due or None to keep showing the last one.
+    // Frames the app was too slow for are dropped.
+    pub fn tick(&mut self, delta_time: f32) -> Option<VideoFrame> {
+        if !self.playing {
+            return None;
+        }
+        self.clock += delta_time;
+
+        let frames = self.frames.as_ref()?;
+        let mut due = None;
+        while self.clock >= self.frame_duration {
+            match frames.try_recv() {
+                Ok(frame) => {
+                    self.clock -= self.frame_duration;
+                    due = Some(frame);
+                }
+                // the decoder fell behind, the clock keeps running
+                Err(TryRecvError::Empty) => {
+                    self.clock = self.clock.min(self.frame_duration * FRAME_QUEUE as f32);
+                    break;
+                }
+                Err(TryRecvError::Disconnected) => {
+                    self.playing = false;
+                    break;
+                }
+            }
+        }
+        due
+    }
+
+    // The stream ended and every frame was shown.
+    pub fn is_finished(&self) -> bool {
+        !self.playing && self.finished.load(Ordering::Relaxed)
+    }
+}
+
+impl AssetImpl for VideoTexture {}
+
+impl Drop for VideoTexture {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        // unblocks a worker waiting for room in the queue
+        self.frames = None;
+        if let Some(worker) = self.worker.take() {
+            _ = worker.join();
+        }
+    }
+}
+
+#[cfg(feature = "ffmpeg")]
+mod ffmpeg {
+    use super::{VideoFrame, VideoSource};
+    use std::io::Read;
+    use std::process::{Child, Command, Stdio};
+
+    // Runs `ffmpeg` from the PATH, which decodes anything it can read to raw I420 on its stdout.
+    pub struct FfmpegSource {
+        path: String,
+        width: u32,
+        height: u32,
+        frame_rate: f32,
+        process: Child,
+    }
+
+    impl FfmpegSource {
+        pub fn open(path: &str) -> Result<Self, String> {
+            let (width, height, frame_rate) = Self::probe(path)?;
+            Ok(Self {
+                path: path.to_string(),
+                width,
+                height,
+                frame_rate,
+                process: Self::spawn(path)?,
+            })
+        }
+
+        // width, height and frames per second of the first video stream
+        fn probe(path: &str) -> Result<(u32, u32, f32), String> {
+            let output = Command::new("ffprobe")
+                .args(["-v", "error", "-select_streams", "v:0"])
+                .args(["-show_entries", "stream=width,height,r_frame_rate"])
+                .args(["-of", "csv=p=0", path])
+                .output()
+                .map_err(|error| format!("failed to run ffprobe: {}", error))?;
+            let text = String::from_utf8_lossy(&output.stdout);
+            let fields = text.trim().split(',').collect::<Vec<_>>();
+            let [width, height, rate] = fields[..] else {
+                return Err(format!("unexpected ffprobe output: {}", text.trim()));
+            };
+
+            let width = width.parse().map_err(|_| "bad width")?;
+            let height = height.parse().map_err(|_| "bad height")?;
+            // a fraction like 30000/1001
+            let frame_rate = match rate.split_once('/') {
+                Some((numerator, denominator)) => {
+                    numerator.parse::<f32>().unwrap_or(0.0)
+                        / denominator.parse::<f32>().unwrap_or(1.0)
+                }
+                None => rate.parse().unwrap_or(0.0),
+            };
+            Ok((width, height, frame_rate))
+        }
+
+        fn spawn(path: &str) -> Result<Child, String> {
+            Command::new("ffmpeg")
+                .args(["-v", "error", "-i", path])
+                .args(["-f", "rawvideo", "-pix_fmt", "yuv420p", "-"])
+                .stdin(Stdio::null())
+                .stdout(Stdio::piped())
+                .spawn()
+                .map_err(|error| format!("failed to run ffmpeg: {}", error))
+        }
+    }
+
+    impl VideoSource for FfmpegSource {
+        fn size(&self) -> (u32, u32) {
+            (self.width, self.height)
+        }
+
+        fn frame_rate(&self) -> f32 {
+            self.frame_rate
+        }
+
+        fn next_frame(&mut self) -> Option<VideoFrame> {
+            let stdout = self.process.stdout.as_mut()?;
+            let mut data = vec![0; VideoFrame::byte_size(self.width, self.height)];
+            stdout.read_exact(&mut data).ok()?;
+
+            let (chroma_width, chroma_height) = VideoFrame::chroma_size(self.width, self.height);
+            let luma_size = (self.width * self.height) as usize;
+            let chroma_size = (chroma_width * chroma_height) as usize;
+            let v = data.split_off(luma_size + chroma_size);
+            let u = data.split_off(luma_size);
+            Some(VideoFrame {
+                width: self.width,
+                height: self.height,
+                planes: [data, u, v],
+            })
+        }
+
+        fn rewind(&mut self) -> bool {
+            _ = self.process.kill();
+            _ = self.process.wait();
+            match Self::spawn(&self.path) {
+                Ok(process) => {
+                    self.process = process;
+                    true
+                }
+                Err(_) => false,
+            }
+        }
+    }
+
+    impl Drop for FfmpegSource {
+        fn drop(&mut self) {
+            _ = self.process.kill();
+            _ = self.process.wait();
+        }
+    }
+}
+
+#[cfg(feature = "ffmpeg")]
+pub use ffmpeg::FfmpegSource;
@@ -0,0 +1,146 @@
+use crate::assets::{AssetImpl, Geom, Texture};
+use crate::loaders::ktx2::write_ktx2;
+use crate::renderer::ShaderCompiler;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::{env, fs};
+
+// `mirage cook <input> <output>`, prepares content ahead of time without a window or a device:
+//   simple.wgsl, terrain.vert -> simple.spv, terrain.vert.spv, compiled like build.rs does
+//   texture.png               -> texture.ktx2, RGBA8 with its mip chain precomputed
+//   crate.obj                 -> crate.mesh, welded and reordered, see `Geom::to_bytes`
+// anything else is copied as is. The input is a file or a directory, mirrored into the output
+// directory. Every file is attempted, the error counts the ones that failed.
+pub fn cook(input: &Path, output: &Path) -> Result<(), String> {
+    let mut files = vec![];
+    if input.is_dir() {
+        collect_files(input, input, &mut files)
+            .map_err(|error| format!("failed to read {}: {}", input.display(), error))?;
+    } else if input.is_file() {
+        files.push(PathBuf::from(input.file_name().unwrap()));
+    } else {
+        return Err(format!("{} does not exist", input.display()));
+    }
+    let root = if input.is_dir() {
+        input
+    } else {
+        input.parent().unwrap()
+    };
+    files.sort();
+
+    let mut failed = 0;
+    for relative in &files {
+        let path = root.join(relative);
+        match cook_file(&path, &output.join(relative)) {
+            Ok(Some(target)) => println!("{} -> {}", path.display(), target.display()),
+            Ok(None) => println!("{} skipped", path.display()),
+            Err(error) => {
+                eprintln!("failed to cook {}: {}", path.display(), error);
+                failed += 1;
+            }
+        }
+    }
+
+    match failed {
+        0 => Ok(()),
+        _ => Err(format!(
+            "{} of {} files failed to cook",
+            failed,
+            files.len()
+        )),
+    }
+}
+
+// Path of the written file, None when there is nothing to ship.
+fn cook_file(path: &Path, target: &Path) -> Result<Option<PathBuf>, String> {
+    let extension = path
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .unwrap_or_default()
+        .to_lowercase();
+    let read = || fs::read(path).map_err(|error| error.to_string());
+
+    let (target, bytes) = match extension.as_str() {
+        "wgsl" => (
+            target.with_extension("spv"),
+            spirv_bytes(&ShaderCompiler::compile_file(path)?),
+        ),
+        "vert" | "frag" | "comp" => (
+            target.with_extension(format!("{}.spv", extension)),
+            spirv_bytes(&ShaderCompiler::compile_file(path)?),
+        ),
+        "tesc" | "tese" | "geom" => (
+            target.with_extension(format!("{}.spv", extension)),
+            compile_with_glslc(path)?,
+        ),
+        // templates the standard shader is stitched from at runtime, built into the binary
+        "glsl" => return Ok(None),
+        "png" | "jpg" | "jpeg" | "tga" | "bmp" => {
+            (target.with_extension("ktx2"), cook_texture(&read()?)?)
+        }
+        "obj" => (target.with_extension("mesh"), cook_geom(&read()?)?),
+        _ => (target.to_path_buf(), read()?),
+    };
+
+    if let Some(parent) = target.parent() {
+        fs::create_dir_all(parent).map_err(|error| error.to_string())?;
+    }
+    fs::write(&target, bytes).map_err(|error| error.to_string())?;
+    Ok(Some(target))
+}
+
+fn cook_texture(data: &[u8]) -> Result<Vec<u8>, String> {
+    let image = image::load_from_memory(data)
+        .map_err(|error| error.to_string())?
+        .to_rgba8();
+    let mut texture = Texture::rgba8(image.width(), image.height(), 1, image.into_raw());
+    texture.build_mips()?;
+    write_ktx2(&texture)
+}
+
+fn cook_geom(data: &[u8]) -> Result<Vec<u8>, String> {
+    let mut geom = Geom::load(data).ok_or("no mesh found")?;
+    geom.optimize();
+    Ok(geom.to_bytes())
+}
+
+// naga can't compile tessellation or geometry stages, glslc of the Vulkan SDK can.
+fn compile_with_glslc(path: &Path) -> Result<Vec<u8>, String> {
+    let sdk_bin = env::var_os("VULKAN_SDK").map(|sdk| Path::new(&sdk).join("bin").join("glslc"));
+    let glslc = sdk_bin
+        .filter(|path| path.is_file())
+        .or_else(|| {
+            env::var_os("PATH").and_then(|paths| {
+                env::split_paths(&paths)
+                    .map(|dir| dir.join("glslc"))
+                    .find(|path| path.is_file())
+            })
+        })
+        .ok_or("glslc not found, install the Vulkan SDK")?;
+
+    let output = Command::new(glslc)
+        .arg(path)
+        .args(["-o", "-"])
+        .output()
+        .map_err(|error| error.to_string())?;
+    if !output.status.success() {
+        return Err(String::from_utf8_lossy(&output.stderr).into_owned());
+    }
+    Ok(output.stdout)
+}
+
+fn spirv_bytes(code: &[u32]) -> Vec<u8> {
+    code.iter().flat_map(|word| word.to_le_bytes()).collect()
+}
+
+fn collect_files(root: &Path, dir: &Path, files: &mut Vec<PathBuf>) -> std::io::Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            collect_files(root, &path, files)?;
+        } else {
+            files.push(path.strip_prefix(root).unwrap().to_path_buf());
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,138 @@
+use crate::assets::{AssetHandle, AssetId, Assets, Texture};
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
+use winit::event_loop::ActiveEventLoop;
+use winit::window::{Cursor, CursorIcon, CustomCursor, CustomCursorSource, Icon, Window};
+
+// A system cursor, or one made from a texture asset with its hotspot in pixels from the top left.
+#[derive(Debug, Clone)]
+pub enum CursorShape {
+    Icon(CursorIcon),
+    Custom(AssetHandle<Texture>, u16, u16),
+}
+
+impl PartialEq for CursorShape {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Self::Icon(a), Self::Icon(b)) => a == b,
+            (Self::Custom(a, ax, ay), Self::Custom(b, bx, by)) => {
+                a.id == b.id && ax == bx && ay == by
+            }
+            _ => false,
+        }
+    }
+}
+
+impl From<CursorIcon> for CursorShape {
+    fn from(icon: CursorIcon) -> Self {
+        Self::Icon(icon)
+    }
+}
+
+// The base level of the texture, winit wants tightly packed RGBA8 like the asset already is.
+pub fn window_icon(texture: &Texture) -> Option<Icon> {
+    Icon::from_rgba(texture.pixels.clone(), texture.width, texture.height)
+        .map_err(|error| log::warn!("failed to create window icon: {}", error))
+        .ok()
+}
+
+// Most platforms limit hardware cursors to small images, 32x32 is the safe size.
+pub fn cursor_source(
+    texture: &Texture,
+    hotspot_x: u16,
+    hotspot_y: u16,
+) -> Option<CustomCursorSource> {
+    CustomCursor::from_rgba(
+        texture.pixels.clone(),
+        texture.width as u16,
+        texture.height as u16,
+        hotspot_x,
+        hotspot_y,
+    )
+    .map_err(|error| log::warn!("failed to create cursor: {}", error))
+    .ok()
+}
+
+// egui-winit is kept from touching the cursor, its wishes over the overlay go through `Cursors` too.
+pub fn egui_cursor_icon(icon: egui::CursorIcon) -> CursorIcon {
+    match icon {
+        egui::CursorIcon::PointingHand => CursorIcon::Pointer,
+        egui::CursorIcon::Text => CursorIcon::Text,
+        egui::CursorIcon::Crosshair => CursorIcon::Crosshair,
+        egui::CursorIcon::Move => CursorIcon::Move,
+        egui::CursorIcon::Grab => CursorIcon::Grab,
+        egui::CursorIcon::Grabbing => CursorIcon::Grabbing,
+        egui::CursorIcon::NotAllowed => CursorIcon::NotAllowed,
+        egui::CursorIcon::Wait => CursorIcon::Wait,
+        egui::CursorIcon::Help => CursorIcon::Help,
+        egui::CursorIcon::ResizeHorizontal | egui::CursorIcon::ResizeColumn => CursorIcon::EwResize,
+        egui::CursorIcon::ResizeVertical | egui::CursorIcon::ResizeRow => CursorIcon::NsResize,
+        egui::CursorIcon::ResizeNeSw => CursorIcon::NeswResize,
+        egui::CursorIcon::ResizeNwSe => CursorIcon::NwseResize,
+        egui::CursorIcon::ResizeEast => CursorIcon::EResize,
+        egui::CursorIcon::ResizeWest => CursorIcon::WResize,
+        egui::CursorIcon::ResizeNorth => CursorIcon::NResize,
+        egui::CursorIcon::ResizeSouth => CursorIcon::SResize,
+        egui::CursorIcon::ResizeNorthEast => CursorIcon::NeResize,
+        egui::CursorIcon::ResizeNorthWest => CursorIcon::NwResize,
+        egui::CursorIcon::ResizeSouthEast => CursorIcon::SeResize,
+        egui::CursorIcon::ResizeSouthWest => CursorIcon::SwResize,
+        _ => CursorIcon::Default,
+    }
+}
+
+// Sets the window cursor only when the wanted shape changes, custom cursors are created once per
+// texture and hotspot. Creating them needs the running event loop.
+#[derive(Default)]
+pub struct Cursors {
+    current: Option<CursorShape>,
+    custom_cursors: HashMap<(AssetId, u16, u16), CustomCursor>,
+}
+
+impl Cursors {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn apply(
+        &mut self,
+        event_loop: &ActiveEventLoop,
+        window: &Window,
+        assets: &Assets,
+        shape: &CursorShape,
+    ) {
+        if self.current.as_ref() == Some(shape) {
+            return;
+        }
+
+        let cursor = match shape {
+            CursorShape::Icon(icon) => Cursor::Icon(*icon),
+            CursorShape::Custom(handle, hotspot_x, hotspot_y) => {
+                let key = (handle.id, *hotspot_x, *hotspot_y);
+                let custom_cursor = match self.custom_cursors.entry(key) {
+                    Entry::Occupied(entry) => entry.get().clone(),
+                    Entry::Vacant(entry) => {
+                        let Some(source) = assets
+                            .load(handle)
+                            .and_then(|texture| cursor_source(texture, *hotspot_x, *hotspot_y))
+                        else {
+                            return;
+                        };
+                        entry
+                            .insert(event_loop.create_custom_cursor(source))
+                            .clone()
+                    }
+                };
+                Cursor::Custom(custom_cursor)
+            }
+        };
+
+        window.set_cursor(cursor);
+        self.current = Some(shape.clone());
+    }
+
+    // The cursor left the window, or something else changed it, it is set again on the next apply.
+    pub fn invalidate(&mut self) {
+        self.current = None;
+    }
+}
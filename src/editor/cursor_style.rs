@@ -0,0 +1,40 @@
+use crate::cursor::CursorShape;
+use crate::input::{Input, PointerTarget};
+use winit::window::CursorIcon;
+
+// Cursor of each editor interaction, picked from what the pointer is over and who holds it.
+#[derive(Debug, Clone)]
+pub struct CursorStyle {
+    pub default: CursorShape,
+    pub hover_entity: CursorShape,
+    pub drag_entity: CursorShape,
+    // dragging empty space moves the camera
+    pub drag_camera: CursorShape,
+}
+
+impl Default for CursorStyle {
+    fn default() -> Self {
+        Self {
+            default: CursorIcon::Default.into(),
+            hover_entity: CursorIcon::Pointer.into(),
+            drag_entity: CursorIcon::Grabbing.into(),
+            drag_camera: CursorIcon::Move.into(),
+        }
+    }
+}
+
+impl CursorStyle {
+    // None over the egui overlay, it picks its own cursors.
+    pub fn resolve(&self, input: &Input) -> Option<&CursorShape> {
+        let dragging = input.is_pointer_captured();
+        let shape = match input.pointer_owner()? {
+            PointerTarget::Overlay => return None,
+            PointerTarget::Ui(_) => &self.default,
+            PointerTarget::Scene(_) if dragging => &self.drag_entity,
+            PointerTarget::Scene(_) => &self.hover_entity,
+            PointerTarget::Background if dragging => &self.drag_camera,
+            PointerTarget::Background => &self.default,
+        };
+        Some(shape)
+    }
+}
@@ -0,0 +1,53 @@
+use crate::math::Vec3;
+
+// Snaps gizmo translations to a world grid, each axis has its own increment.
+#[derive(Debug, Copy, Clone)]
+pub struct GridSnap {
+    pub enabled: bool,
+    pub increment: Vec3,
+}
+
+impl Default for GridSnap {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            increment: Vec3::one(),
+        }
+    }
+}
+
+impl GridSnap {
+    pub fn new(increment: f32) -> Self {
+        Self {
+            enabled: true,
+            increment: Vec3::new(increment, increment, increment),
+        }
+    }
+
+    pub fn snap(&self, position: Vec3) -> Vec3 {
+        if !self.enabled {
+            return position;
+        }
+
+        Vec3::new(
+            Self::snap_axis(position.x, self.increment.x),
+            Self::snap_axis(position.y, self.increment.y),
+            Self::snap_axis(position.z, self.increment.z),
+        )
+    }
+
+    // Result of dragging an object at `origin` by `delta`. The object lands on the grid
+    // instead of keeping its offset to it, like most editors do.
+    pub fn snap_translation(&self, origin: Vec3, delta: Vec3) -> Vec3 {
+        self.snap(origin + delta)
+    }
+
+    // An increment of zero (or less) leaves that axis free.
+    fn snap_axis(value: f32, increment: f32) -> f32 {
+        if increment <= 0.0 {
+            value
+        } else {
+            (value / increment).round() * increment
+        }
+    }
+}
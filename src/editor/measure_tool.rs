@@ -0,0 +1,51 @@
+use super::GridSnap;
+use crate::math::Vec3;
+use crate::scene::Measurement;
+
+// Places `Measurement`s between points picked in the scene: a click picks the start, the next one
+// the end and makes the measurement, the click after that starts another. The points go through
+// the grid snap like dragged entities do.
+#[derive(Debug, Copy, Clone, Default)]
+pub struct MeasureTool {
+    pub enabled: bool,
+    // picked and waiting for its end
+    start: Option<Vec3>,
+    // the pointer was down at the last update, a click counts once on press
+    was_pressed: bool,
+}
+
+impl MeasureTool {
+    pub fn new() -> Self {
+        Self {
+            enabled: true,
+            ..Self::default()
+        }
+    }
+
+    // True once per press of the pointer, with the state of this update.
+    pub fn press(&mut self, pressed: bool) -> bool {
+        let was_pressed = std::mem::replace(&mut self.was_pressed, pressed);
+        pressed && !was_pressed
+    }
+
+    // The measurement the picked point completes, None when it starts one.
+    pub fn click(&mut self, point: Vec3, grid_snap: &GridSnap) -> Option<Measurement> {
+        let point = grid_snap.snap(point);
+        match self.start.take() {
+            Some(start) => Some(Measurement::new(start, point)),
+            None => {
+                self.start = Some(point);
+                None
+            }
+        }
+    }
+
+    pub fn start(&self) -> Option<Vec3> {
+        self.start
+    }
+
+    // Drops a picked start, e.g. when the tool is switched off.
+    pub fn cancel(&mut self) {
+        self.start = None;
+    }
+}
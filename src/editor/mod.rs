@@ -0,0 +1,9 @@
+mod cursor_style;
+mod grid_snap;
+mod measure_tool;
+mod translate_drag;
+
+pub use cursor_style::CursorStyle;
+pub use grid_snap::GridSnap;
+pub use measure_tool::MeasureTool;
+pub use translate_drag::TranslateDrag;
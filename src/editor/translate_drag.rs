@@ -0,0 +1,42 @@
+use super::GridSnap;
+use crate::math::{Ray, Vec3};
+use crate::scene::Entity;
+
+// Moves a grabbed entity with the pointer across the plane facing the camera through where the
+// entity was when grabbed. Where it lands goes through the grid snap.
+#[derive(Debug, Copy, Clone)]
+pub struct TranslateDrag {
+    pub entity: Entity,
+    // location of the entity when grabbed
+    origin: Vec3,
+    // the point on the plane under the pointer when grabbed
+    grab: Vec3,
+    normal: Vec3,
+}
+
+impl TranslateDrag {
+    pub fn new(entity: Entity, origin: Vec3, ray: &Ray) -> Self {
+        let normal = ray.direction;
+        // the pointer ray meets the plane where it is closest to the origin
+        let grab = ray.at((origin - ray.origin).dot(normal));
+        Self {
+            entity,
+            origin,
+            grab,
+            normal,
+        }
+    }
+
+    // None while the pointer ray misses the plane, e.g. pointing away from it.
+    pub fn location(&self, ray: &Ray, grid_snap: &GridSnap) -> Option<Vec3> {
+        let denominator = ray.direction.dot(self.normal);
+        if denominator.abs() < 1e-6 {
+            return None;
+        }
+        let distance = (self.grab - ray.origin).dot(self.normal) / denominator;
+        if distance < 0.0 {
+            return None;
+        }
+        Some(grid_snap.snap_translation(self.origin, ray.at(distance) - self.grab))
+    }
+}
@@ -0,0 +1,87 @@
+use std::cell::RefCell;
+use std::fmt::Display;
+
+// errors shown at once, older ones are dropped
+const MAX_ERRORS: usize = 8;
+
+struct Error {
+    // what failed, e.g. "shader simple.frag", a later report of the same source replaces it
+    source: String,
+    message: String,
+    count: u32,
+}
+
+thread_local! {
+    static ERRORS: RefCell<Vec<Error>> = const { RefCell::new(vec![]) };
+}
+
+// Logs the error in full and shows it on screen until it's dismissed or its source recovers,
+// for failures the engine keeps running through: a shader that doesn't compile on reload, a
+// swap chain that has to be rebuilt, an asset that is missing. The same error reported every
+// frame is shown once with a count.
+pub fn report(source: impl Into<String>, message: impl Display) {
+    let source = source.into();
+    let message = message.to_string();
+    ERRORS.with_borrow_mut(
+        |errors| match errors.iter_mut().find(|error| error.source == source) {
+            Some(error) if error.message == message => error.count += 1,
+            _ => {
+                log::error!("{}: {}", source, message);
+                errors.retain(|error| error.source != source);
+                if errors.len() == MAX_ERRORS {
+                    errors.remove(0);
+                }
+                errors.push(Error {
+                    source,
+                    message,
+                    count: 1,
+                });
+            }
+        },
+    );
+}
+
+// The source works again, e.g. the shader compiled after a fix.
+pub fn resolve(source: &str) {
+    ERRORS.with_borrow_mut(|errors| errors.retain(|error| error.source != source));
+}
+
+// A window over the top of the screen with the errors, drawn with the rest of the UI.
+pub fn show(context: &egui::Context) {
+    ERRORS.with_borrow_mut(|errors| {
+        if errors.is_empty() {
+            return;
+        }
+        let mut dismissed = None;
+        let mut dismiss_all = false;
+        egui::Window::new("Errors")
+            .anchor(egui::Align2::CENTER_TOP, egui::vec2(0.0, 8.0))
+            .collapsible(true)
+            .resizable(false)
+            .show(context, |ui| {
+                let color = ui.visuals().error_fg_color;
+                for (index, error) in errors.iter().enumerate() {
+                    ui.horizontal(|ui| {
+                        let title = match error.count {
+                            1 => error.source.clone(),
+                            count => format!("{} (x{})", error.source, count),
+                        };
+                        ui.colored_label(color, egui::RichText::new(title).strong());
+                        if ui.small_button("Dismiss").clicked() {
+                            dismissed = Some(index);
+                        }
+                    });
+                    ui.label(egui::RichText::new(&error.message).monospace());
+                    ui.separator();
+                }
+                if ui.button("Dismiss all").clicked() {
+                    dismiss_all = true;
+                }
+            });
+        if dismiss_all {
+            errors.clear();
+        } else if let Some(index) = dismissed {
+            errors.remove(index);
+        }
+    });
+}
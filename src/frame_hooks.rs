@@ -0,0 +1,33 @@
+use crate::gpu::GPU;
+use crate::mirage::Mirage;
+use ash::vk;
+
+pub type UpdateHook = Box<dyn FnMut(&mut Mirage)>;
+pub type RenderHook = Box<dyn FnMut(&RenderFrame)>;
+// with the new swap chain width and height
+pub type SwapChainHook = Box<dyn FnMut(&mut Mirage, u32, u32)>;
+
+// The frame a render hook records into, commands go between the engine's passes.
+pub struct RenderFrame<'a> {
+    pub gpu: &'a GPU,
+    pub command_buffer: vk::CommandBuffer,
+    // slot of the frame in flight, for per frame resources
+    pub frame_index: usize,
+    // swap chain image the frame presents
+    pub image_index: u32,
+}
+
+// User code run at fixed points of every frame, in registration order:
+//   pre_update:  before the systems tick, after the jobs of the render queue ran
+//   post_update: after the systems and the UI layout, the world is final for the frame
+//   pre_render:  the frame's command buffer was begun and nothing recorded yet
+//   post_render: after the egui overlay, the last commands before the submit
+//   swap_chain_recreated: after a resize or resume, the renderers are resized already
+#[derive(Default)]
+pub struct FrameHooks {
+    pub pre_update: Vec<UpdateHook>,
+    pub post_update: Vec<UpdateHook>,
+    pub pre_render: Vec<RenderHook>,
+    pub post_render: Vec<RenderHook>,
+    pub swap_chain_recreated: Vec<SwapChainHook>,
+}
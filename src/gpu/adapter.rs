@@ -0,0 +1,100 @@
+use std::fmt;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum AdapterType {
+    Discrete,
+    Integrated,
+    Virtual,
+    // a software rasterizer like lavapipe or SwiftShader
+    Cpu,
+    Other,
+}
+
+// A GPU the instance can create a device on, in the order the driver enumerates them.
+#[derive(Debug, Clone)]
+pub struct AdapterInfo {
+    pub index: usize,
+    pub name: String,
+    pub adapter_type: AdapterType,
+    pub vendor_id: u32,
+    pub device_id: u32,
+    // "major.minor.patch" of the Vulkan version the driver supports
+    pub api_version: String,
+    // has the queues, extensions and features the renderer needs, and can present to the surface
+    pub suitable: bool,
+}
+
+impl fmt::Display for AdapterInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "[{}] {} ({:?}, Vulkan {}{})",
+            self.index,
+            self.name,
+            self.adapter_type,
+            self.api_version,
+            if self.suitable { "" } else { ", unsuitable" }
+        )
+    }
+}
+
+// Which adapter the GPU is created on.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub enum AdapterSelection {
+    // the suitable one with the highest score, discrete over integrated over the rest
+    #[default]
+    Auto,
+    // the `AdapterInfo::index`
+    Index(usize),
+    // the first whose name contains it, ignoring case, e.g. "intel" or "rtx 3080"
+    Name(String),
+}
+
+impl AdapterSelection {
+    // Read by `MirageConfig::default`, an index or a part of the name.
+    pub const ENV_VAR: &'static str = "MIRAGE_ADAPTER";
+
+    // Auto when the variable isn't set or empty.
+    pub fn from_env() -> Self {
+        match std::env::var(Self::ENV_VAR) {
+            Ok(value) => Self::parse(&value),
+            Err(_) => Self::Auto,
+        }
+    }
+
+    pub fn parse(value: &str) -> Self {
+        let value = value.trim();
+        if value.is_empty() || value.eq_ignore_ascii_case("auto") {
+            return Self::Auto;
+        }
+        match value.parse() {
+            Ok(index) => Self::Index(index),
+            Err(_) => Self::Name(value.to_string()),
+        }
+    }
+
+    // The requested adapter if it is there and suitable, None leaves it to the score.
+    pub fn find<'a>(&self, adapters: &'a [AdapterInfo]) -> Option<&'a AdapterInfo> {
+        let adapter = match self {
+            Self::Auto => return None,
+            Self::Index(index) => adapters.iter().find(|adapter| adapter.index == *index),
+            Self::Name(name) => {
+                let name = name.to_lowercase();
+                adapters
+                    .iter()
+                    .find(|adapter| adapter.name.to_lowercase().contains(&name))
+            }
+        };
+        match adapter {
+            Some(adapter) if adapter.suitable => Some(adapter),
+            Some(adapter) => {
+                log::warn!("GPU adapter {} is not suitable, picking one", adapter);
+                None
+            }
+            None => {
+                log::warn!("no GPU adapter matches {:?}, picking one", self);
+                None
+            }
+        }
+    }
+}
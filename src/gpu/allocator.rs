@@ -0,0 +1,240 @@
+use ash::vk;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::ffi::c_void;
+
+// Size of the vkAllocateMemory blocks resources are carved out of. Resources bigger than half a block
+// get a dedicated allocation instead, they would waste most of a block anyway.
+const BLOCK_SIZE: vk::DeviceSize = 64 * 1024 * 1024;
+
+// A range of a memory block bound to one buffer or image.
+#[derive(Debug, Copy, Clone)]
+pub struct Allocation {
+    pub memory: vk::DeviceMemory,
+    pub offset: vk::DeviceSize,
+    pub size: vk::DeviceSize,
+    // start of the range when the memory is host visible, null otherwise
+    pub mapped: *mut c_void,
+
+    pool: PoolKey,
+    // None for a dedicated allocation
+    block: Option<usize>,
+}
+
+// Buffers and linear images never share a block with optimal images, so the
+// bufferImageGranularity between neighbours doesn't have to be taken care of.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+struct PoolKey {
+    memory_type_index: u32,
+    linear: bool,
+}
+
+struct Block {
+    memory: vk::DeviceMemory,
+    mapped: *mut c_void,
+    // sorted by offset, adjacent ranges are merged on free
+    free_ranges: Vec<(vk::DeviceSize, vk::DeviceSize)>,
+}
+
+impl Block {
+    fn allocate(
+        &mut self,
+        size: vk::DeviceSize,
+        alignment: vk::DeviceSize,
+    ) -> Option<vk::DeviceSize> {
+        // first fit
+        let (index, offset) =
+            self.free_ranges
+                .iter()
+                .enumerate()
+                .find_map(|(index, &(start, len))| {
+                    let offset = start.next_multiple_of(alignment);
+                    (offset + size <= start + len).then_some((index, offset))
+                })?;
+
+        let (start, len) = self.free_ranges.remove(index);
+        let end = start + len;
+        // alignment padding before and leftover after stay free
+        if offset + size < end {
+            self.free_ranges
+                .insert(index, (offset + size, end - offset - size));
+        }
+        if start < offset {
+            self.free_ranges.insert(index, (start, offset - start));
+        }
+        Some(offset)
+    }
+
+    fn free(&mut self, offset: vk::DeviceSize, size: vk::DeviceSize) {
+        let index = self
+            .free_ranges
+            .partition_point(|&(start, _)| start < offset);
+        self.free_ranges.insert(index, (offset, size));
+
+        // merge with the next range, then with the previous one
+        if index + 1 < self.free_ranges.len() {
+            let (next_start, next_len) = self.free_ranges[index + 1];
+            if offset + size == next_start {
+                self.free_ranges[index].1 += next_len;
+                self.free_ranges.remove(index + 1);
+            }
+        }
+        if index > 0 {
+            let (prev_start, prev_len) = self.free_ranges[index - 1];
+            if prev_start + prev_len == offset {
+                self.free_ranges[index - 1].1 += self.free_ranges[index].1;
+                self.free_ranges.remove(index);
+            }
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.free_ranges.len() == 1 && self.free_ranges[0] == (0, BLOCK_SIZE)
+    }
+}
+
+// Sub-allocates buffers and images from a few big memory blocks per memory type, instead of one
+// vkAllocateMemory per resource which quickly runs into maxMemoryAllocationCount (as low as 4096).
+// Host visible blocks are mapped once for their whole lifetime, a block can't be mapped twice.
+pub struct Allocator {
+    memory_properties: vk::PhysicalDeviceMemoryProperties,
+    pools: RefCell<HashMap<PoolKey, Vec<Block>>>,
+}
+
+impl Allocator {
+    pub fn new(memory_properties: vk::PhysicalDeviceMemoryProperties) -> Self {
+        Self {
+            memory_properties,
+            pools: RefCell::new(HashMap::new()),
+        }
+    }
+
+    /// # Safety
+    ///
+    /// `device` must be the device the memory properties were queried for, and the allocation
+    /// goes back through `free` on the same device before `destroy`.
+    pub unsafe fn allocate(
+        &self,
+        device: &ash::Device,
+        requirements: vk::MemoryRequirements,
+        memory_type_index: u32,
+        linear: bool,
+    ) -> Allocation {
+        let pool = PoolKey {
+            memory_type_index,
+            linear,
+        };
+
+        if requirements.size > BLOCK_SIZE / 2 {
+            let memory = self.allocate_memory(device, requirements.size, memory_type_index);
+            return Allocation {
+                memory,
+                offset: 0,
+                size: requirements.size,
+                mapped: self.map(device, memory, memory_type_index),
+                pool,
+                block: None,
+            };
+        }
+
+        let mut pools = self.pools.borrow_mut();
+        let blocks = pools.entry(pool).or_default();
+
+        let found = blocks.iter_mut().enumerate().find_map(|(index, block)| {
+            let offset = block.allocate(requirements.size, requirements.alignment)?;
+            Some((index, offset))
+        });
+        let (block_index, offset) = match found {
+            Some(found) => found,
+            None => {
+                let memory = self.allocate_memory(device, BLOCK_SIZE, memory_type_index);
+                let mut block = Block {
+                    memory,
+                    mapped: self.map(device, memory, memory_type_index),
+                    free_ranges: vec![(0, BLOCK_SIZE)],
+                };
+                let offset = block
+                    .allocate(requirements.size, requirements.alignment)
+                    .unwrap();
+                blocks.push(block);
+                (blocks.len() - 1, offset)
+            }
+        };
+
+        let block = &blocks[block_index];
+        Allocation {
+            memory: block.memory,
+            offset,
+            size: requirements.size,
+            mapped: if block.mapped.is_null() {
+                block.mapped
+            } else {
+                block.mapped.add(offset as usize)
+            },
+            pool,
+            block: Some(block_index),
+        }
+    }
+
+    /// Emptied blocks are kept for the next allocations, they are only released by `destroy`.
+    ///
+    /// # Safety
+    ///
+    /// `allocation` comes from this allocator on `device` and the GPU is done with it.
+    pub unsafe fn free(&self, device: &ash::Device, allocation: Allocation) {
+        match allocation.block {
+            None => device.free_memory(allocation.memory, None),
+            Some(block_index) => {
+                let mut pools = self.pools.borrow_mut();
+                let block = &mut pools.get_mut(&allocation.pool).unwrap()[block_index];
+                block.free(allocation.offset, allocation.size);
+            }
+        }
+    }
+
+    /// # Safety
+    ///
+    /// The GPU is done with every allocation of `device`, none is used afterwards.
+    pub unsafe fn destroy(&self, device: &ash::Device) {
+        let mut pools = self.pools.borrow_mut();
+        for block in pools.values().flatten() {
+            if !block.is_empty() {
+                log::warn!("freeing a memory block with live allocations");
+            }
+            device.free_memory(block.memory, None);
+        }
+        pools.clear();
+    }
+
+    unsafe fn allocate_memory(
+        &self,
+        device: &ash::Device,
+        size: vk::DeviceSize,
+        memory_type_index: u32,
+    ) -> vk::DeviceMemory {
+        let allocate_info = vk::MemoryAllocateInfo::default()
+            .allocation_size(size)
+            .memory_type_index(memory_type_index);
+
+        device
+            .allocate_memory(&allocate_info, None)
+            .expect("failed to allocate memory!")
+    }
+
+    unsafe fn map(
+        &self,
+        device: &ash::Device,
+        memory: vk::DeviceMemory,
+        memory_type_index: u32,
+    ) -> *mut c_void {
+        let property_flags =
+            self.memory_properties.memory_types[memory_type_index as usize].property_flags;
+        if !property_flags.contains(vk::MemoryPropertyFlags::HOST_VISIBLE) {
+            return std::ptr::null_mut();
+        }
+
+        device
+            .map_memory(memory, 0, vk::WHOLE_SIZE, vk::MemoryMapFlags::empty())
+            .expect("failed to map memory!")
+    }
+}
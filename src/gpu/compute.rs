@@ -0,0 +1,271 @@
+use crate::gpu::GPU;
+use ash::vk;
+use std::ffi::CString;
+
+// What reads the output of a dispatch next, picks the stage and access the barrier after it waits
+// for.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum ComputeReader {
+    // another dispatch
+    Compute,
+    // vertex or index buffers, e.g. simulated particles
+    Vertex,
+    // the arguments of indirect draws or dispatches, and the storage buffers their vertex
+    // shaders read, e.g. the visible lists of the GPU culling
+    Indirect,
+    // sampled or read as storage by fragment shaders, e.g. a post process result
+    Fragment,
+    // copies
+    Transfer,
+    // the CPU after the submission's fence, e.g. read back results
+    Host,
+}
+
+impl ComputeReader {
+    fn stage_access(self) -> (vk::PipelineStageFlags, vk::AccessFlags) {
+        match self {
+            ComputeReader::Compute => (
+                vk::PipelineStageFlags::COMPUTE_SHADER,
+                vk::AccessFlags::SHADER_READ | vk::AccessFlags::SHADER_WRITE,
+            ),
+            ComputeReader::Vertex => (
+                vk::PipelineStageFlags::VERTEX_INPUT,
+                vk::AccessFlags::VERTEX_ATTRIBUTE_READ | vk::AccessFlags::INDEX_READ,
+            ),
+            ComputeReader::Indirect => (
+                vk::PipelineStageFlags::DRAW_INDIRECT | vk::PipelineStageFlags::VERTEX_SHADER,
+                vk::AccessFlags::INDIRECT_COMMAND_READ | vk::AccessFlags::SHADER_READ,
+            ),
+            ComputeReader::Fragment => (
+                vk::PipelineStageFlags::FRAGMENT_SHADER,
+                vk::AccessFlags::SHADER_READ,
+            ),
+            ComputeReader::Transfer => (
+                vk::PipelineStageFlags::TRANSFER,
+                vk::AccessFlags::TRANSFER_READ,
+            ),
+            ComputeReader::Host => (vk::PipelineStageFlags::HOST, vk::AccessFlags::HOST_READ),
+        }
+    }
+}
+
+// A compute shader from SPIR-V with the layout of its set 0 and its push constants, for particle
+// simulations, culling or post processing on compute. Dispatches are recorded into any command
+// buffer, e.g. the frame's in an `on_pre_render` hook, or run on their own with `run`.
+// Destroyed with `destroy`, like the other pipelines of the renderers.
+pub struct ComputePipeline {
+    pub descriptor_set_layout: vk::DescriptorSetLayout,
+    pub pipeline_layout: vk::PipelineLayout,
+    pub pipeline: vk::Pipeline,
+    shader_module: vk::ShaderModule,
+    // descriptor type of each binding of set 0, in binding order
+    bindings: Vec<vk::DescriptorType>,
+    push_constant_size: u32,
+}
+
+impl ComputePipeline {
+    // `bindings` are the descriptor types of set 0, binding n is the nth.
+    pub fn new(
+        gpu: &GPU,
+        code: &[u32],
+        entry: &str,
+        bindings: &[vk::DescriptorType],
+        push_constant_size: u32,
+    ) -> Self {
+        let device = &gpu.device_context.device;
+        let layout_bindings = bindings
+            .iter()
+            .enumerate()
+            .map(
+                |(binding, descriptor_type)| vk::DescriptorSetLayoutBinding {
+                    binding: binding as u32,
+                    descriptor_type: *descriptor_type,
+                    descriptor_count: 1,
+                    stage_flags: vk::ShaderStageFlags::COMPUTE,
+                    ..Default::default()
+                },
+            )
+            .collect();
+        let descriptor_set_layout = gpu.create_descriptor_set_layout(&layout_bindings);
+
+        unsafe {
+            let push_constant_ranges = [vk::PushConstantRange::default()
+                .stage_flags(vk::ShaderStageFlags::COMPUTE)
+                .offset(0)
+                .size(push_constant_size)];
+            let descriptor_set_layouts = [descriptor_set_layout];
+            let mut layout_create_info =
+                vk::PipelineLayoutCreateInfo::default().set_layouts(&descriptor_set_layouts);
+            if push_constant_size > 0 {
+                layout_create_info = layout_create_info.push_constant_ranges(&push_constant_ranges);
+            }
+            let pipeline_layout = device
+                .create_pipeline_layout(&layout_create_info, None)
+                .expect("failed to create pipeline layout!");
+
+            let shader_module = gpu.create_shader_module(code);
+            let entry = CString::new(entry).unwrap();
+            let create_info = vk::ComputePipelineCreateInfo::default()
+                .stage(
+                    vk::PipelineShaderStageCreateInfo::default()
+                        .module(shader_module)
+                        .stage(vk::ShaderStageFlags::COMPUTE)
+                        .name(entry.as_c_str()),
+                )
+                .layout(pipeline_layout);
+            let pipeline = device
+                .create_compute_pipelines(vk::PipelineCache::null(), &[create_info], None)
+                .expect("failed to create compute pipeline!")[0];
+
+            Self {
+                descriptor_set_layout,
+                pipeline_layout,
+                pipeline,
+                shader_module,
+                bindings: bindings.to_vec(),
+                push_constant_size,
+            }
+        }
+    }
+
+    // Sets of the set 0 layout from the GPU's descriptor allocator, given back with
+    // `GPU::free_descriptor_sets`, e.g. one per frame in flight.
+    pub fn create_sets(&self, gpu: &GPU, count: usize) -> Vec<vk::DescriptorSet> {
+        gpu.create_descriptor_sets(&vec![self.descriptor_set_layout; count])
+    }
+
+    // A storage or uniform buffer at `binding`, the whole buffer with `vk::WHOLE_SIZE`.
+    pub fn write_buffer(
+        &self,
+        gpu: &GPU,
+        set: vk::DescriptorSet,
+        binding: u32,
+        buffer: vk::Buffer,
+        range: vk::DeviceSize,
+    ) {
+        let buffer_infos = [vk::DescriptorBufferInfo {
+            buffer,
+            offset: 0,
+            range,
+        }];
+        let write = vk::WriteDescriptorSet::default()
+            .descriptor_type(self.bindings[binding as usize])
+            .buffer_info(&buffer_infos)
+            .dst_set(set)
+            .dst_binding(binding);
+        unsafe {
+            gpu.device_context
+                .device
+                .update_descriptor_sets(&[write], &[]);
+        }
+    }
+
+    // A sampled or storage image at `binding`. Storage images are written in GENERAL, sampled
+    // ones are read in SHADER_READ_ONLY_OPTIMAL, with the sampler a combined binding has.
+    pub fn write_image(
+        &self,
+        gpu: &GPU,
+        set: vk::DescriptorSet,
+        binding: u32,
+        image_view: vk::ImageView,
+        sampler: vk::Sampler,
+    ) {
+        let descriptor_type = self.bindings[binding as usize];
+        let image_layout = match descriptor_type {
+            vk::DescriptorType::STORAGE_IMAGE => vk::ImageLayout::GENERAL,
+            _ => vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+        };
+        let image_infos = [vk::DescriptorImageInfo {
+            image_view,
+            image_layout,
+            sampler,
+        }];
+        let write = vk::WriteDescriptorSet::default()
+            .descriptor_type(descriptor_type)
+            .image_info(&image_infos)
+            .dst_set(set)
+            .dst_binding(binding);
+        unsafe {
+            gpu.device_context
+                .device
+                .update_descriptor_sets(&[write], &[]);
+        }
+    }
+
+    // Records a dispatch of `groups` workgroups, `push_constants` may be empty when the pipeline
+    // has none. Follow it with `barrier` before anything reads what it wrote.
+    pub fn dispatch(
+        &self,
+        gpu: &GPU,
+        command_buffer: vk::CommandBuffer,
+        set: vk::DescriptorSet,
+        push_constants: &[u8],
+        groups: [u32; 3],
+    ) {
+        let device = &gpu.device_context.device;
+        unsafe {
+            device.cmd_bind_pipeline(
+                command_buffer,
+                vk::PipelineBindPoint::COMPUTE,
+                self.pipeline,
+            );
+            device.cmd_bind_descriptor_sets(
+                command_buffer,
+                vk::PipelineBindPoint::COMPUTE,
+                self.pipeline_layout,
+                0,
+                &[set],
+                &[],
+            );
+            if !push_constants.is_empty() {
+                debug_assert!(push_constants.len() as u32 <= self.push_constant_size);
+                device.cmd_push_constants(
+                    command_buffer,
+                    self.pipeline_layout,
+                    vk::ShaderStageFlags::COMPUTE,
+                    0,
+                    push_constants,
+                );
+            }
+            device.cmd_dispatch(command_buffer, groups[0], groups[1], groups[2]);
+        }
+    }
+
+    // Makes the shader writes of the dispatches recorded so far visible to `reader`. A global
+    // memory barrier, image layout changes are up to the caller.
+    pub fn barrier(gpu: &GPU, command_buffer: vk::CommandBuffer, reader: ComputeReader) {
+        let (dst_stage, dst_access) = reader.stage_access();
+        let barrier = vk::MemoryBarrier::default()
+            .src_access_mask(vk::AccessFlags::SHADER_WRITE)
+            .dst_access_mask(dst_access);
+        unsafe {
+            gpu.device_context.device.cmd_pipeline_barrier(
+                command_buffer,
+                vk::PipelineStageFlags::COMPUTE_SHADER,
+                dst_stage,
+                vk::DependencyFlags::empty(),
+                &[barrier],
+                &[],
+                &[],
+            );
+        }
+    }
+
+    // Records with `record` into a command buffer of its own and waits for it, for work outside
+    // the frame like baking or one off simulations. Frames in flight keep running meanwhile.
+    pub fn run(gpu: &GPU, record: impl FnOnce(vk::CommandBuffer)) {
+        let command_buffer = gpu.begin_single_time_command();
+        record(command_buffer);
+        gpu.end_single_time_command(command_buffer);
+    }
+
+    pub fn destroy(&mut self, gpu: &GPU) {
+        unsafe {
+            let device = &gpu.device_context.device;
+            device.destroy_pipeline(self.pipeline, None);
+            device.destroy_pipeline_layout(self.pipeline_layout, None);
+            device.destroy_shader_module(self.shader_module, None);
+            device.destroy_descriptor_set_layout(self.descriptor_set_layout, None);
+        }
+    }
+}
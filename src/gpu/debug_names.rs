@@ -0,0 +1,67 @@
+use super::{VkContext, VkDeviceContext};
+use ash::vk;
+use std::ffi::CString;
+
+// Names Vulkan objects and labels command buffer regions through VK_EXT_debug_utils, so
+// validation messages and RenderDoc captures say "texture grass.png" instead of a raw handle.
+// Does nothing when the instance came without the extension, e.g. release builds outside a
+// graphics debugger.
+pub struct DebugNames {
+    debug_utils: Option<ash::ext::debug_utils::Device>,
+}
+
+impl DebugNames {
+    pub fn new(context: &VkContext, device_context: &VkDeviceContext) -> Self {
+        let debug_utils = context
+            .debug_utils
+            .then(|| ash::ext::debug_utils::Device::new(&context.instance, &device_context.device));
+        Self { debug_utils }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.debug_utils.is_some()
+    }
+
+    // Any buffer, image, view, pipeline, command buffer and so on, the type comes from the handle.
+    pub fn set_name<H: vk::Handle>(&self, handle: H, name: &str) {
+        let Some(debug_utils) = &self.debug_utils else {
+            return;
+        };
+        let name = Self::c_string(name);
+        let name_info = vk::DebugUtilsObjectNameInfoEXT::default()
+            .object_handle(handle)
+            .object_name(&name);
+        unsafe {
+            if let Err(error) = debug_utils.set_debug_utils_object_name(&name_info) {
+                log::warn!("failed to set debug name {:?}: {}", name, error);
+            }
+        }
+    }
+
+    // Opens a region of the command buffer, closed by `end_label`. Regions nest.
+    pub fn begin_label(&self, command_buffer: vk::CommandBuffer, label: &str) {
+        let Some(debug_utils) = &self.debug_utils else {
+            return;
+        };
+        let label = Self::c_string(label);
+        let label_info = vk::DebugUtilsLabelEXT::default().label_name(&label);
+        unsafe {
+            debug_utils.cmd_begin_debug_utils_label(command_buffer, &label_info);
+        }
+    }
+
+    pub fn end_label(&self, command_buffer: vk::CommandBuffer) {
+        let Some(debug_utils) = &self.debug_utils else {
+            return;
+        };
+        unsafe {
+            debug_utils.cmd_end_debug_utils_label(command_buffer);
+        }
+    }
+
+    // names with a nul in them are cut there
+    fn c_string(text: &str) -> CString {
+        let text = text.split('\0').next().unwrap_or_default();
+        CString::new(text).unwrap_or_default()
+    }
+}
@@ -0,0 +1,107 @@
+use super::{Allocation, Allocator};
+use ash::vk;
+use std::cell::{Cell, RefCell};
+
+// A Vulkan object created outside the crate, handed over to be destroyed once no frame in flight
+// can use it anymore.
+pub enum RawResource {
+    Buffer(vk::Buffer),
+    Image(vk::Image),
+    ImageView(vk::ImageView),
+    Sampler(vk::Sampler),
+    // memory from `GPU::raw().allocator`
+    Allocation(Allocation),
+    // memory allocated with vkAllocateMemory directly
+    Memory(vk::DeviceMemory),
+    Pipeline(vk::Pipeline),
+    PipelineLayout(vk::PipelineLayout),
+    DescriptorSetLayout(vk::DescriptorSetLayout),
+    ShaderModule(vk::ShaderModule),
+    RenderPass(vk::RenderPass),
+    Framebuffer(vk::Framebuffer),
+    Semaphore(vk::Semaphore),
+    Fence(vk::Fence),
+    CommandPool(vk::CommandPool),
+    QueryPool(vk::QueryPool),
+    // anything else, e.g. objects of an extension
+    Custom(Box<dyn FnOnce(&ash::Device)>),
+}
+
+impl RawResource {
+    unsafe fn destroy(self, device: &ash::Device, allocator: &Allocator) {
+        match self {
+            Self::Buffer(buffer) => device.destroy_buffer(buffer, None),
+            Self::Image(image) => device.destroy_image(image, None),
+            Self::ImageView(view) => device.destroy_image_view(view, None),
+            Self::Sampler(sampler) => device.destroy_sampler(sampler, None),
+            Self::Allocation(allocation) => allocator.free(device, allocation),
+            Self::Memory(memory) => device.free_memory(memory, None),
+            Self::Pipeline(pipeline) => device.destroy_pipeline(pipeline, None),
+            Self::PipelineLayout(layout) => device.destroy_pipeline_layout(layout, None),
+            Self::DescriptorSetLayout(layout) => device.destroy_descriptor_set_layout(layout, None),
+            Self::ShaderModule(module) => device.destroy_shader_module(module, None),
+            Self::RenderPass(render_pass) => device.destroy_render_pass(render_pass, None),
+            Self::Framebuffer(framebuffer) => device.destroy_framebuffer(framebuffer, None),
+            Self::Semaphore(semaphore) => device.destroy_semaphore(semaphore, None),
+            Self::Fence(fence) => device.destroy_fence(fence, None),
+            Self::CommandPool(pool) => device.destroy_command_pool(pool, None),
+            Self::QueryPool(pool) => device.destroy_query_pool(pool, None),
+            Self::Custom(destroy) => destroy(device),
+        }
+    }
+}
+
+// Resources queued for destruction per frame slot, like the freed descriptor sets of
+// `DescriptorAllocator`: what is queued while a slot is recorded goes once its fence was waited
+// on the next time around. Resources are destroyed in the order they were queued.
+pub struct DeletionQueue {
+    pending: RefCell<Vec<Vec<RawResource>>>,
+    frame_index: Cell<usize>,
+}
+
+impl DeletionQueue {
+    pub fn new() -> Self {
+        Self {
+            pending: RefCell::new(vec![vec![]]),
+            frame_index: Cell::new(0),
+        }
+    }
+
+    pub fn push(&self, resource: RawResource) {
+        self.pending.borrow_mut()[self.frame_index.get()].push(resource);
+    }
+
+    // Call once the fence of `frame_index` was waited on.
+    pub unsafe fn flush(&self, device: &ash::Device, allocator: &Allocator, frame_index: usize) {
+        let resources = {
+            let mut pending = self.pending.borrow_mut();
+            if pending.len() <= frame_index {
+                pending.resize_with(frame_index + 1, Vec::new);
+            }
+            self.frame_index.set(frame_index);
+            std::mem::take(&mut pending[frame_index])
+        };
+        // custom destructors may queue more
+        resources
+            .into_iter()
+            .for_each(|resource| resource.destroy(device, allocator));
+    }
+
+    // Everything still queued, the device has to be idle.
+    pub unsafe fn destroy(&self, device: &ash::Device, allocator: &Allocator) {
+        loop {
+            let resources = self
+                .pending
+                .borrow_mut()
+                .iter_mut()
+                .flat_map(std::mem::take)
+                .collect::<Vec<_>>();
+            if resources.is_empty() {
+                break;
+            }
+            resources
+                .into_iter()
+                .for_each(|resource| resource.destroy(device, allocator));
+        }
+    }
+}
@@ -0,0 +1,180 @@
+use ash::vk;
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+
+// Descriptors of each type a pool holds per set, most sets are a uniform buffer or a few
+// textures with their samplers.
+const POOL_RATIOS: [(vk::DescriptorType, u32); 8] = [
+    (vk::DescriptorType::UNIFORM_BUFFER, 2),
+    (vk::DescriptorType::UNIFORM_BUFFER_DYNAMIC, 1),
+    (vk::DescriptorType::STORAGE_BUFFER, 1),
+    (vk::DescriptorType::STORAGE_BUFFER_DYNAMIC, 1),
+    (vk::DescriptorType::SAMPLED_IMAGE, 4),
+    (vk::DescriptorType::SAMPLER, 4),
+    (vk::DescriptorType::INPUT_ATTACHMENT, 1),
+    (vk::DescriptorType::STORAGE_IMAGE, 1),
+];
+// every new pool is twice the size of the last one, up to the max
+const FIRST_POOL_SETS: u32 = 64;
+const MAX_POOL_SETS: u32 = 4096;
+
+struct Pool {
+    pool: vk::DescriptorPool,
+    // sets allocated and not freed yet
+    live_sets: u32,
+    // an allocation failed, skipped until sets of it are freed
+    full: bool,
+}
+
+// Hands out descriptor sets from a growing list of pools instead of one fixed pool, a full pool
+// gets a bigger one next to it. Freed sets go back to their pool once the frames that may still
+// bind them are done, see `recycle`. Material sets are pushed instead on devices with
+// VK_KHR_push_descriptor, see `VkDeviceContext::push_descriptor`.
+pub struct DescriptorAllocator {
+    pools: RefCell<Vec<Pool>>,
+    // index of the pool every live set came from
+    owners: RefCell<HashMap<vk::DescriptorSet, usize>>,
+    // sets freed while each frame slot was being recorded
+    pending_frees: RefCell<Vec<Vec<vk::DescriptorSet>>>,
+    frame_index: Cell<usize>,
+}
+
+impl DescriptorAllocator {
+    pub fn new() -> Self {
+        Self {
+            pools: RefCell::new(vec![]),
+            owners: RefCell::new(HashMap::new()),
+            pending_frees: RefCell::new(vec![vec![]]),
+            frame_index: Cell::new(0),
+        }
+    }
+
+    pub unsafe fn allocate(
+        &self,
+        device: &ash::Device,
+        layouts: &[vk::DescriptorSetLayout],
+    ) -> Vec<vk::DescriptorSet> {
+        let mut pools = self.pools.borrow_mut();
+
+        // the newest pools have the most room
+        let mut allocation = None;
+        for index in (0..pools.len()).rev() {
+            if pools[index].full {
+                continue;
+            }
+            match Self::allocate_from(device, pools[index].pool, layouts) {
+                Ok(sets) => {
+                    allocation = Some((index, sets));
+                    break;
+                }
+                Err(vk::Result::ERROR_OUT_OF_POOL_MEMORY | vk::Result::ERROR_FRAGMENTED_POOL) => {
+                    pools[index].full = true;
+                }
+                Err(error) => panic!("failed to allocate descriptor sets! {}", error),
+            }
+        }
+
+        let (index, sets) = allocation.unwrap_or_else(|| {
+            let max_sets = (FIRST_POOL_SETS << pools.len().min(16))
+                .min(MAX_POOL_SETS)
+                .max(layouts.len() as u32);
+            pools.push(Pool {
+                pool: Self::create_pool(device, max_sets),
+                live_sets: 0,
+                full: false,
+            });
+            let index = pools.len() - 1;
+            let sets = Self::allocate_from(device, pools[index].pool, layouts)
+                .expect("failed to allocate descriptor sets!");
+            (index, sets)
+        });
+
+        pools[index].live_sets += sets.len() as u32;
+        let mut owners = self.owners.borrow_mut();
+        sets.iter().for_each(|&set| _ = owners.insert(set, index));
+        sets
+    }
+
+    // The sets are returned to their pools by the `recycle` of the current frame slot's next turn.
+    pub fn free(&self, sets: &[vk::DescriptorSet]) {
+        self.pending_frees.borrow_mut()[self.frame_index.get()].extend_from_slice(sets);
+    }
+
+    // Call once the fence of `frame_index` was waited on. The sets freed the last time this slot
+    // was recorded can't be bound by any frame still in flight, they go back to their pools.
+    pub unsafe fn recycle(&self, device: &ash::Device, frame_index: usize) {
+        let mut pending_frees = self.pending_frees.borrow_mut();
+        if pending_frees.len() <= frame_index {
+            pending_frees.resize(frame_index + 1, vec![]);
+        }
+        self.frame_index.set(frame_index);
+
+        let sets = std::mem::take(&mut pending_frees[frame_index]);
+        if sets.is_empty() {
+            return;
+        }
+
+        let mut by_pool: HashMap<usize, Vec<vk::DescriptorSet>> = HashMap::new();
+        let mut owners = self.owners.borrow_mut();
+        for set in sets {
+            if let Some(index) = owners.remove(&set) {
+                by_pool.entry(index).or_default().push(set);
+            }
+        }
+
+        let mut pools = self.pools.borrow_mut();
+        for (index, sets) in by_pool {
+            let pool = &mut pools[index];
+            pool.live_sets -= sets.len() as u32;
+            pool.full = false;
+            if pool.live_sets == 0 {
+                // starts over unfragmented
+                device
+                    .reset_descriptor_pool(pool.pool, vk::DescriptorPoolResetFlags::empty())
+                    .expect("failed to reset descriptor pool!");
+            } else {
+                device
+                    .free_descriptor_sets(pool.pool, &sets)
+                    .expect("failed to free descriptor sets!");
+            }
+        }
+    }
+
+    pub unsafe fn destroy(&self, device: &ash::Device) {
+        self.pools
+            .borrow_mut()
+            .drain(..)
+            .for_each(|pool| device.destroy_descriptor_pool(pool.pool, None));
+        self.owners.borrow_mut().clear();
+        self.pending_frees
+            .borrow_mut()
+            .iter_mut()
+            .for_each(|sets| sets.clear());
+    }
+
+    unsafe fn allocate_from(
+        device: &ash::Device,
+        pool: vk::DescriptorPool,
+        layouts: &[vk::DescriptorSetLayout],
+    ) -> Result<Vec<vk::DescriptorSet>, vk::Result> {
+        let allocate_info = vk::DescriptorSetAllocateInfo::default()
+            .descriptor_pool(pool)
+            .set_layouts(layouts);
+        device.allocate_descriptor_sets(&allocate_info)
+    }
+
+    unsafe fn create_pool(device: &ash::Device, max_sets: u32) -> vk::DescriptorPool {
+        let pool_sizes = POOL_RATIOS.map(|(ty, ratio)| vk::DescriptorPoolSize {
+            ty,
+            descriptor_count: max_sets * ratio,
+        });
+        let create_info = vk::DescriptorPoolCreateInfo::default()
+            .flags(vk::DescriptorPoolCreateFlags::FREE_DESCRIPTOR_SET)
+            .pool_sizes(&pool_sizes)
+            .max_sets(max_sets);
+
+        device
+            .create_descriptor_pool(&create_info, None)
+            .expect("failed to create descriptor pool!")
+    }
+}
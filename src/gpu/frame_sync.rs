@@ -0,0 +1,162 @@
+use super::GPU;
+use ash::vk;
+
+// What the CPU waits on before the command buffer and resources of a frame slot are reused.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum FrameSignal {
+    // the frame timeline reaching the value, 0 is there from the start
+    Timeline(vk::Semaphore, u64),
+    // devices without timeline semaphores
+    Fence(vk::Fence),
+}
+
+struct FrameSlot {
+    // signaled by the acquire, waited on by the submit
+    image_available: vk::Semaphore,
+    // of the last submit
+    signal: FrameSignal,
+    // the swap chain image the last submit rendered to
+    image_index: Option<u32>,
+}
+
+// The synchronization of the frames in flight. Every frame submit signals the next value of one
+// timeline semaphore, a slot is free again once the timeline reached the value of its last submit,
+// and work on other queues can wait on the same values. Acquire and present only take binary
+// semaphores: one per slot signaled by the acquire, and one per swap chain image signaled by the
+// submit and waited on by the present. A present's wait is only known to be done once its image is
+// acquired again, a semaphore per slot could be signaled again while a present still waits on it.
+// Devices without timeline semaphores keep a fence per slot.
+pub struct FrameSync {
+    timeline: Option<vk::Semaphore>,
+    // the last value a submit signals
+    timeline_value: u64,
+    slots: Vec<FrameSlot>,
+    // [swap chain image], created as images show up
+    render_finished: Vec<vk::Semaphore>,
+}
+
+impl FrameSync {
+    pub fn new(gpu: &GPU, frames_in_flight: u32) -> Self {
+        let device = &gpu.device_context.device;
+        let timeline = gpu.device_context.timeline_semaphore.then(|| unsafe {
+            let mut type_info = vk::SemaphoreTypeCreateInfo::default()
+                .semaphore_type(vk::SemaphoreType::TIMELINE)
+                .initial_value(0);
+            let create_info = vk::SemaphoreCreateInfo::default().push_next(&mut type_info);
+            let timeline = device
+                .create_semaphore(&create_info, None)
+                .expect("failed to create frame timeline semaphore!");
+            gpu.debug_names.set_name(timeline, "frame timeline");
+            timeline
+        });
+        if timeline.is_none() {
+            log::info!("timeline semaphores are not supported, frames are waited on with fences");
+        }
+
+        let mut frame_sync = Self {
+            timeline,
+            timeline_value: 0,
+            slots: vec![],
+            render_finished: vec![],
+        };
+        frame_sync.slots = (0..frames_in_flight)
+            .map(|_| frame_sync.create_slot(gpu))
+            .collect();
+        frame_sync
+    }
+
+    // The semaphore and the value of the latest frame submit, for other queues to wait on the
+    // frame. None without timeline semaphores.
+    pub fn timeline(&self) -> Option<(vk::Semaphore, u64)> {
+        self.timeline
+            .map(|timeline| (timeline, self.timeline_value))
+    }
+
+    // Of the last submit of the slot, waited on before the slot is recorded again.
+    pub fn pending(&self, frame_index: usize) -> FrameSignal {
+        self.slots[frame_index].signal
+    }
+
+    pub fn image_available(&self, frame_index: usize) -> vk::Semaphore {
+        self.slots[frame_index].image_available
+    }
+
+    pub fn render_finished(&mut self, gpu: &GPU, image_index: u32) -> vk::Semaphore {
+        let image_index = image_index as usize;
+        while self.render_finished.len() <= image_index {
+            let semaphore = Self::create_semaphore(gpu, "render finished");
+            self.render_finished.push(semaphore);
+        }
+        self.render_finished[image_index]
+    }
+
+    // What the submit of the slot rendering to the image signals, to be reset before.
+    pub fn next_signal(&mut self, frame_index: usize, image_index: u32) -> FrameSignal {
+        let slot = &mut self.slots[frame_index];
+        if let FrameSignal::Timeline(timeline, _) = slot.signal {
+            self.timeline_value += 1;
+            slot.signal = FrameSignal::Timeline(timeline, self.timeline_value);
+        }
+        slot.image_index = Some(image_index);
+        slot.signal
+    }
+
+    // The frame of the slot hung and is given up on. Its semaphores and fence stay pending on the
+    // GPU and can't be reused or destroyed, the slot and its image get new ones and they leak.
+    pub fn abandon(&mut self, gpu: &GPU, frame_index: usize) {
+        if let Some(image_index) = self.slots[frame_index].image_index {
+            self.render_finished[image_index as usize] =
+                Self::create_semaphore(gpu, "render finished");
+        }
+        self.slots[frame_index] = self.create_slot(gpu);
+    }
+
+    // After the device was waited on.
+    pub unsafe fn destroy(&mut self, device: &ash::Device) {
+        for slot in self.slots.drain(..) {
+            device.destroy_semaphore(slot.image_available, None);
+            if let FrameSignal::Fence(fence) = slot.signal {
+                device.destroy_fence(fence, None);
+            }
+        }
+        for semaphore in self.render_finished.drain(..) {
+            device.destroy_semaphore(semaphore, None);
+        }
+        if let Some(timeline) = self.timeline.take() {
+            device.destroy_semaphore(timeline, None);
+        }
+    }
+
+    fn create_slot(&self, gpu: &GPU) -> FrameSlot {
+        let signal = match self.timeline {
+            Some(timeline) => FrameSignal::Timeline(timeline, 0),
+            None => unsafe {
+                let fence_create_info =
+                    vk::FenceCreateInfo::default().flags(vk::FenceCreateFlags::SIGNALED);
+                FrameSignal::Fence(
+                    gpu.device_context
+                        .device
+                        .create_fence(&fence_create_info, None)
+                        .expect("failed to create in-flight fence!"),
+                )
+            },
+        };
+        FrameSlot {
+            image_available: Self::create_semaphore(gpu, "image available"),
+            signal,
+            image_index: None,
+        }
+    }
+
+    fn create_semaphore(gpu: &GPU, name: &str) -> vk::Semaphore {
+        unsafe {
+            let semaphore = gpu
+                .device_context
+                .device
+                .create_semaphore(&vk::SemaphoreCreateInfo::default(), None)
+                .unwrap_or_else(|_| panic!("failed to create {} semaphore!", name));
+            gpu.debug_names.set_name(semaphore, name);
+            semaphore
+        }
+    }
+}
@@ -1,35 +1,330 @@
 use super::*;
+use crate::error_overlay;
 use ash::vk;
 use ash::vk::BufferCopy;
-use std::ffi::c_void;
+use std::cell::{Cell, RefCell};
 use std::mem::{align_of, size_of};
+use std::ops::Range;
 use std::rc::Rc;
+use std::time::Duration;
 use winit::window::Window;
 
 pub struct GPU {
     pub context: VkContext,
     pub device_context: VkDeviceContext,
-    pub swap_chain: SwapChain,
+    pub swap_chain: RefCell<SwapChain>,
+    // added to the mip level picked by every texture sampler, negative values sharpen, see
+    // `set_mip_lod_bias`
+    pub mip_lod_bias: Cell<f32>,
+    // the samplers of textures, see `get_sampler`
+    pub samplers: SamplerCache,
+    // bytes of push constants per draw payloads may use, up to maxPushConstantsSize
+    push_constant_budget: Cell<u32>,
 
     pub transient_command_pool: vk::CommandPool,
-    pub descriptor_pool: vk::DescriptorPool,
+    // staging copies waiting for the next submit, see `flush_uploads`
+    pub uploads: RefCell<UploadManager>,
+    // timeline semaphore values the next frame submit waits on, see `wait_in_frame`
+    pub frame_waits: RefCell<Vec<(vk::Semaphore, u64, vk::PipelineStageFlags)>>,
+    // frames queued for presentation, see `PresentThread`
+    pub presents: RefCell<PresentThread>,
+    pub descriptor_allocator: DescriptorAllocator,
+    // raw resources of user code, see `defer_destroy`
+    pub deletion_queue: DeletionQueue,
+    // shared by every pipeline creation, the main thread and the warm-up threads alike
+    pub pipeline_cache: vk::PipelineCache,
+    // off by default, see `set_watchdog`
+    pub watchdog: RefCell<Option<Watchdog>>,
+    // off by default, see `set_profiling`
+    pub profiler: RefCell<Option<Profiler>>,
+    // labels for validation messages and graphics debuggers, see `DebugNames`
+    pub debug_names: DebugNames,
+}
+
+// Borrowed from `GPU::raw`, the queues are shared with the renderers and submitting to them
+// has to happen on the thread owning the GPU.
+pub struct RawHandles<'a> {
+    pub entry: &'a ash::Entry,
+    pub instance: &'a ash::Instance,
+    pub physical_device: vk::PhysicalDevice,
+    pub device: &'a ash::Device,
+    // memory the crate's buffers and images come from
+    pub allocator: &'a Allocator,
+    pub graphic_queue: vk::Queue,
+    pub graphic_queue_family: u32,
+    pub present_queue: Option<vk::Queue>,
+    pub compute_queue: Option<vk::Queue>,
+    pub compute_queue_family: Option<u32>,
 }
 
 impl GPU {
-    pub fn new(window: Rc<Window>) -> Self {
+    pub fn new(window: Rc<Window>, adapter: &AdapterSelection, present_mode: PresentMode) -> Self {
         let context = VkContext::new(window);
-        let device_context = VkDeviceContext::new(&context);
-        let swap_chain = SwapChain::new(&context, &device_context);
+        let device_context = VkDeviceContext::new(&context, adapter);
+        let swap_chain = SwapChain::new(&context, &device_context, present_mode);
+        Self::with_swap_chain(context, device_context, swap_chain)
+    }
+
+    // Renders into offscreen images of `width` x `height`, e.g. for CI or batch rendering on
+    // machines without a display. See `read_back_image`.
+    pub fn new_headless(width: u32, height: u32, adapter: &AdapterSelection) -> Self {
+        let context = VkContext::new_headless();
+        let device_context = VkDeviceContext::new(&context, adapter);
+        let swap_chain = SwapChain::new_offscreen(&device_context, vk::Extent2D { width, height });
+        Self::with_swap_chain(context, device_context, swap_chain)
+    }
+
+    fn with_swap_chain(
+        context: VkContext,
+        device_context: VkDeviceContext,
+        swap_chain: SwapChain,
+    ) -> Self {
         let transient_command_pool = Self::create_command_pools(&device_context);
-        let descriptor_pool = Self::create_descriptor_pool(&device_context);
+        let uploads = UploadManager::new(&device_context);
+        let presents = PresentThread::new(device_context.queue_lock.clone());
+        let pipeline_cache = Self::create_pipeline_cache(&device_context);
+        let debug_names = DebugNames::new(&context, &device_context);
+        let push_constant_budget = device_context
+            .physical_device_properties
+            .limits
+            .max_push_constants_size;
 
         Self {
             context,
             device_context,
-            swap_chain,
+            swap_chain: RefCell::new(swap_chain),
+            mip_lod_bias: Cell::new(0.0),
+            samplers: SamplerCache::new(),
+            push_constant_budget: Cell::new(push_constant_budget),
             transient_command_pool,
-            descriptor_pool,
+            uploads: RefCell::new(uploads),
+            frame_waits: RefCell::new(vec![]),
+            presents: RefCell::new(presents),
+            descriptor_allocator: DescriptorAllocator::new(),
+            deletion_queue: DeletionQueue::new(),
+            pipeline_cache,
+            watchdog: RefCell::new(None),
+            profiler: RefCell::new(None),
+            debug_names,
+        }
+    }
+
+    // Submits the staging copies recorded since the last call, the graphics queue waits for them
+    // before anything submitted afterwards. Called before every frame and single time command.
+    pub fn flush_uploads(&self) {
+        self.uploads.borrow_mut().flush(&self.device_context);
+    }
+
+    // The next frame submit waits at `stage` for the timeline semaphore to reach `value`, e.g. for
+    // work of the compute or transfer queue the frame reads. Needs timeline semaphore support.
+    pub fn wait_in_frame(
+        &self,
+        semaphore: vk::Semaphore,
+        value: u64,
+        stage: vk::PipelineStageFlags,
+    ) {
+        assert!(
+            self.device_context.timeline_semaphore,
+            "timeline semaphores are not supported!"
+        );
+        self.frame_waits
+            .borrow_mut()
+            .push((semaphore, value, stage));
+    }
+
+    pub fn wait_idle(&self) {
+        self.presents.borrow_mut().wait();
+        unsafe {
+            let _queue = self.device_context.queue_lock.lock().unwrap();
+            self.device_context
+                .device
+                .device_wait_idle()
+                .expect("failed to wait device idle!");
+        }
+    }
+
+    // Frames that take longer than `timeout` are reported and given up on, see `Watchdog`.
+    // None turns it off.
+    pub fn set_watchdog(&self, timeout: Option<Duration>) {
+        self.wait_idle();
+        if let Some(mut watchdog) = self.watchdog.take() {
+            unsafe { watchdog.destroy(&self.device_context) };
+        }
+        *self.watchdog.borrow_mut() =
+            timeout.map(|timeout| Watchdog::new(&self.device_context, timeout));
+    }
+
+    // Times the labelled passes of every frame, see `Profiler`. Devices whose graphics queue can't
+    // write timestamps are reported and stay unprofiled.
+    pub fn set_profiling(&self, enabled: bool) {
+        self.wait_idle();
+        if let Some(mut profiler) = self.profiler.take() {
+            unsafe { profiler.destroy(&self.device_context.device) };
+        }
+        if !enabled {
+            return;
+        }
+
+        let device_context = &self.device_context;
+        let queue_families = unsafe {
+            self.context
+                .instance
+                .get_physical_device_queue_family_properties(device_context.physical_device)
+        };
+        let timestamp_valid_bits = queue_families
+            [device_context.graphic_queue_family.unwrap() as usize]
+            .timestamp_valid_bits;
+        if timestamp_valid_bits == 0 {
+            log::warn!("the graphics queue doesn't support timestamps, profiling is off!");
+            return;
+        }
+        let timestamp_period = device_context
+            .physical_device_properties
+            .limits
+            .timestamp_period;
+        *self.profiler.borrow_mut() = Some(Profiler::new(timestamp_period, timestamp_valid_bits));
+    }
+
+    // Of the latest frame the GPU finished, None while profiling is off.
+    pub fn get_gpu_timings(&self) -> Option<GpuTimings> {
+        self.profiler.borrow().as_ref()?.timings().cloned()
+    }
+
+    // Waits on the signal of a frame, forever without a watchdog. With one, a frame past the
+    // timeout returns its report so the caller can give the frame up, a lost device or a hang
+    // that keeps coming back aborts with it.
+    pub fn wait_frame(&self, signal: FrameSignal) -> Result<(), HangReport> {
+        let Some(timeout) = self
+            .watchdog
+            .borrow()
+            .as_ref()
+            .map(|watchdog| watchdog.timeout)
+        else {
+            self.wait_fence(signal);
+            return Ok(());
+        };
+
+        let result = self.wait_signal(signal, timeout.as_nanos().min(u64::MAX as u128) as u64);
+        let mut watchdog = self.watchdog.borrow_mut();
+        let watchdog = watchdog.as_mut().unwrap();
+        let device_lost = match result {
+            Ok(()) => {
+                watchdog.finished();
+                return Ok(());
+            }
+            Err(vk::Result::TIMEOUT) => false,
+            Err(vk::Result::ERROR_DEVICE_LOST) => true,
+            Err(error) => panic!("failed to wait frame! {}", error),
+        };
+
+        let (report, recoverable) = watchdog.hang(signal, device_lost);
+        if !recoverable {
+            panic!("the GPU hung, giving up!\n{}", report);
+        }
+        error_overlay::report(
+            "GPU",
+            format!("the GPU hung, skipping the frame!\n{}", report),
+        );
+        Err(report)
+    }
+
+    // The fence signaled or the timeline reached the value within `timeout` nanoseconds.
+    pub fn wait_signal(&self, signal: FrameSignal, timeout: u64) -> ash::prelude::VkResult<()> {
+        let device = &self.device_context.device;
+        unsafe {
+            match signal {
+                FrameSignal::Timeline(timeline, value) => {
+                    let semaphores = [timeline];
+                    let values = [value];
+                    let wait_info = vk::SemaphoreWaitInfo::default()
+                        .semaphores(&semaphores)
+                        .values(&values);
+                    device.wait_semaphores(&wait_info, timeout)
+                }
+                FrameSignal::Fence(fence) => device.wait_for_fences(&[fence], true, timeout),
+            }
+        }
+    }
+
+    pub fn has_surface(&self) -> bool {
+        self.context.surface.get().is_some()
+    }
+
+    pub fn is_headless(&self) -> bool {
+        self.context.is_headless()
+    }
+
+    // The size frames are rendered at, the window's or the offscreen one when headless.
+    pub fn surface_size(&self) -> vk::Extent2D {
+        match self.context.window.borrow().as_ref() {
+            Some(window) => {
+                let size = window.inner_size();
+                vk::Extent2D {
+                    width: size.width,
+                    height: size.height,
+                }
+            }
+            None => self.swap_chain.borrow().extent,
+        }
+    }
+
+    // The native window is gone (Android backgrounding), release everything tied to it.
+    pub fn suspend(&self) {
+        self.wait_idle();
+        self.swap_chain.borrow_mut().destroy(&self.device_context);
+        self.context.destroy_surface();
+    }
+
+    pub fn resume(&self, window: Rc<Window>) {
+        self.context.set_window(window);
+        self.recreate_swap_chain();
+    }
+
+    pub fn recreate_swap_chain(&self) {
+        self.wait_idle();
+        self.swap_chain
+            .borrow_mut()
+            .recreate(&self.context, &self.device_context);
+    }
+
+    // Takes effect the next time the swap chain is recreated.
+    pub fn set_surface_format_mode(&self, format_mode: SurfaceFormatMode) {
+        self.swap_chain.borrow_mut().format_mode = format_mode;
+    }
+
+    // Takes effect the next time the swap chain is recreated.
+    pub fn set_present_mode(&self, present_mode: PresentMode) {
+        self.swap_chain.borrow_mut().requested_present_mode = present_mode;
+    }
+
+    pub fn supported_present_modes(&self) -> Vec<PresentMode> {
+        SwapChain::supported_present_modes(&self.context, &self.device_context)
+    }
+
+    pub fn get_push_constant_budget(&self) -> u32 {
+        self.push_constant_budget.get()
+    }
+
+    // Lower than the device limit to leave room for other payloads, or to try the uniform buffer
+    // fallback on a desktop GPU. Pipelines created afterwards follow it.
+    pub fn set_push_constant_budget(&self, budget: u32) {
+        let limit = self
+            .device_context
+            .physical_device_properties
+            .limits
+            .max_push_constants_size;
+        if budget > limit {
+            log::warn!(
+                "push constant budget {} exceeds the device limit {}",
+                budget,
+                limit
+            );
         }
+        self.push_constant_budget.set(budget.min(limit));
+    }
+
+    pub fn fits_push_constants(&self, size: usize) -> bool {
+        size <= self.push_constant_budget.get() as usize
     }
 
     pub fn create_shader_module(&self, code: &[u32]) -> vk::ShaderModule {
@@ -56,29 +351,84 @@ impl GPU {
         }
     }
 
+    // A layout of descriptors pushed with `cmd_push_descriptor_set` rather than allocated, needs
+    // `VkDeviceContext::push_descriptor`.
+    pub fn create_push_descriptor_set_layout(
+        &self,
+        bindings: &[vk::DescriptorSetLayoutBinding],
+    ) -> vk::DescriptorSetLayout {
+        unsafe {
+            let create_info = vk::DescriptorSetLayoutCreateInfo::default()
+                .flags(vk::DescriptorSetLayoutCreateFlags::PUSH_DESCRIPTOR_KHR)
+                .bindings(bindings);
+            self.device_context
+                .device
+                .create_descriptor_set_layout(&create_info, None)
+                .expect("failed to create descriptor set layout!")
+        }
+    }
+
     pub fn create_descriptor_sets(
         &self,
-        layouts: &Vec<vk::DescriptorSetLayout>,
+        layouts: &[vk::DescriptorSetLayout],
     ) -> Vec<vk::DescriptorSet> {
         unsafe {
-            let allocate_info = vk::DescriptorSetAllocateInfo::default()
-                .descriptor_pool(self.descriptor_pool)
-                .set_layouts(layouts);
+            self.descriptor_allocator
+                .allocate(&self.device_context.device, layouts)
+        }
+    }
 
-            let descriptor_sets = self
-                .device_context
-                .device
-                .allocate_descriptor_sets(&allocate_info)
-                .expect("failed to allocate descriptor sets!");
+    // The Vulkan objects the crate runs on, for custom Vulkan code next to the renderers. Objects
+    // created with them are the caller's, `defer_destroy` releases them once the GPU is done.
+    pub fn raw(&self) -> RawHandles<'_> {
+        let device_context = &self.device_context;
+        RawHandles {
+            entry: &self.context.entry,
+            instance: &self.context.instance,
+            physical_device: device_context.physical_device,
+            device: &device_context.device,
+            allocator: &device_context.allocator,
+            graphic_queue: device_context.graphic_queue.unwrap(),
+            graphic_queue_family: device_context.graphic_queue_family.unwrap(),
+            present_queue: device_context.present_queue,
+            compute_queue: device_context.compute_queue,
+            compute_queue_family: device_context.compute_queue_family,
+        }
+    }
+
+    // Destroys the resource once the frames in flight that may use it are done.
+    pub fn defer_destroy(&self, resource: RawResource) {
+        self.deletion_queue.push(resource);
+    }
+
+    // After waiting on the fence of `frame_index`, see `DeletionQueue::flush`.
+    pub fn flush_deletions(&self, frame_index: usize) {
+        unsafe {
+            self.deletion_queue.flush(
+                &self.device_context.device,
+                &self.device_context.allocator,
+                frame_index,
+            );
+        }
+    }
 
-            descriptor_sets
+    // Frames in flight may still bind them, they are reused once those are done.
+    pub fn free_descriptor_sets(&self, sets: &[vk::DescriptorSet]) {
+        self.descriptor_allocator.free(sets);
+    }
+
+    // After waiting on the fence of `frame_index`, see `DescriptorAllocator::recycle`.
+    pub fn recycle_descriptor_sets(&self, frame_index: usize) {
+        unsafe {
+            self.descriptor_allocator
+                .recycle(&self.device_context.device, frame_index);
         }
     }
 
     pub fn create_texture_image(
         &self,
         path: &str,
-    ) -> (vk::Image, vk::DeviceMemory, vk::ImageView, vk::Sampler) {
+    ) -> (vk::Image, Allocation, vk::ImageView, vk::Sampler) {
         unsafe {
             let image = image::open(path).expect("failed to load image!");
             let image_rgba8 = image.to_rgba8();
@@ -88,24 +438,17 @@ impl GPU {
             let pixels = image_rgba8.into_raw();
             let image_size = (pixels.len() * size_of::<u8>()) as vk::DeviceSize;
 
-            let (staging_buffer, staging_memory, _) = self.device_context.create_buffer(
+            let (staging_buffer, staging_memory) = self.device_context.create_buffer(
                 image_size,
                 vk::BufferUsageFlags::TRANSFER_SRC,
                 vk::MemoryPropertyFlags::HOST_COHERENT | vk::MemoryPropertyFlags::HOST_VISIBLE,
             );
-            let staging_memory_mapped = self
-                .device_context
-                .device
-                .map_memory(staging_memory, 0, image_size, vk::MemoryMapFlags::empty())
-                .expect("failed to map staging memory!");
-
             let mut align = ash::util::Align::new(
-                staging_memory_mapped,
+                staging_memory.mapped,
                 align_of::<u8>() as vk::DeviceSize,
                 image_size,
             );
             align.copy_from_slice(&pixels);
-            self.device_context.device.unmap_memory(staging_memory);
 
             let (image, memory) = self.device_context.create_image(
                 width,
@@ -125,10 +468,11 @@ impl GPU {
                     image,
                     vk::Format::R8G8B8A8_SRGB,
                     mip_levels,
+                    1,
                     vk::ImageLayout::UNDEFINED,
                     vk::ImageLayout::TRANSFER_DST_OPTIMAL,
                 );
-                self.copy_buffer_to_image(staging_buffer, image, width, height);
+                self.copy_buffer_to_image(staging_buffer, image, width, height, 1);
                 if mip_levels > 1 {
                     self.generate_mipmaps(
                         image,
@@ -136,21 +480,21 @@ impl GPU {
                         width,
                         height,
                         mip_levels,
+                        1,
                     );
                 } else {
                     self.transition_image_layout(
                         image,
                         vk::Format::R8G8B8A8_SRGB,
                         1,
+                        1,
                         vk::ImageLayout::TRANSFER_DST_OPTIMAL,
                         vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
                     );
                 }
 
-                self.device_context.device.free_memory(staging_memory, None);
                 self.device_context
-                    .device
-                    .destroy_buffer(staging_buffer, None);
+                    .destroy_buffer(staging_buffer, staging_memory);
             }
 
             let image_view = self.device_context.create_image_view(
@@ -160,88 +504,83 @@ impl GPU {
                 mip_levels,
             );
 
-            let create_info = vk::SamplerCreateInfo::default()
-                .anisotropy_enable(true)
-                .max_anisotropy(
-                    self.device_context
-                        .physical_device_properties
-                        .limits
-                        .max_sampler_anisotropy,
-                )
-                .compare_enable(false)
-                .compare_op(vk::CompareOp::ALWAYS)
-                .min_filter(vk::Filter::LINEAR)
-                .mag_filter(vk::Filter::LINEAR)
-                .mipmap_mode(vk::SamplerMipmapMode::LINEAR)
-                .min_lod(0.0)
-                .max_lod(mip_levels as f32)
-                .mip_lod_bias(0.0)
-                .unnormalized_coordinates(false)
-                .address_mode_u(vk::SamplerAddressMode::REPEAT)
-                .address_mode_v(vk::SamplerAddressMode::REPEAT)
-                .address_mode_w(vk::SamplerAddressMode::REPEAT)
-                .border_color(vk::BorderColor::FLOAT_OPAQUE_BLACK);
-
-            let sampler = self
-                .device_context
-                .device
-                .create_sampler(&create_info, None)
-                .expect("failed to create image sampler!");
+            let sampler = self.get_sampler(&SamplerDesc::default());
 
             (image, memory, image_view, sampler)
         }
     }
 
+    // Shared with every other texture sampled the same way, owned by the cache.
+    pub fn get_sampler(&self, desc: &SamplerDesc) -> vk::Sampler {
+        self.samplers
+            .get(&self.device_context, desc, self.mip_lod_bias.get())
+    }
+
+    // Samplers bake the bias in, the cached ones are destroyed once the device is idle and
+    // callers have to get theirs again.
+    pub fn set_mip_lod_bias(&self, mip_lod_bias: f32) {
+        self.mip_lod_bias.set(mip_lod_bias);
+        self.wait_idle();
+        unsafe {
+            self.samplers.clear(&self.device_context.device);
+        }
+    }
+
     pub fn create_buffer_with_data<T: Copy>(
         &self,
-        array: &Vec<T>,
+        array: &[T],
         usage: vk::BufferUsageFlags,
-    ) -> (vk::Buffer, vk::DeviceMemory) {
+    ) -> (vk::Buffer, Allocation) {
         unsafe {
-            let buffer_size = (size_of::<T>() * array.len()) as vk::DeviceSize;
-            let (staging_buffer, staging_memory, _) = self.device_context.create_buffer(
-                buffer_size,
-                vk::BufferUsageFlags::TRANSFER_SRC,
-                vk::MemoryPropertyFlags::HOST_COHERENT | vk::MemoryPropertyFlags::HOST_VISIBLE,
-            );
-
-            let staging_memory_mapped = self
-                .device_context
-                .device
-                .map_memory(staging_memory, 0, buffer_size, vk::MemoryMapFlags::empty())
-                .expect("failed to map buffer staging memory!");
-            let mut align = ash::util::Align::new(
-                staging_memory_mapped,
-                align_of::<T>() as vk::DeviceSize,
-                buffer_size,
-            );
-            align.copy_from_slice(array);
-            self.device_context.device.unmap_memory(staging_memory);
-
-            let (buffer, buffer_memory, _) = self.device_context.create_buffer(
+            let buffer_size = std::mem::size_of_val(array) as vk::DeviceSize;
+            let (buffer, buffer_memory) = self.device_context.create_buffer(
                 buffer_size,
                 vk::BufferUsageFlags::TRANSFER_DST | usage,
                 vk::MemoryPropertyFlags::DEVICE_LOCAL,
             );
 
-            // The transfer of data to the GPU is an operation that happens in the background and the specification
-            // simply tells us that it is guaranteed to be complete as of the next call to vkQueueSubmit.
-            // https://registry.khronos.org/vulkan/specs/1.3-extensions/html/chap7.html#synchronization-submission-host-writes
-            self.copy_buffer(staging_buffer, buffer, buffer_size);
-            self.device_context
-                .device
-                .destroy_buffer(staging_buffer, None);
-            self.device_context.device.free_memory(staging_memory, None);
+            // copied with the other uploads of the frame, see `flush_uploads`
+            let bytes =
+                std::slice::from_raw_parts(array.as_ptr() as *const u8, buffer_size as usize);
+            self.uploads
+                .borrow_mut()
+                .upload_buffer(&self.device_context, bytes, buffer);
 
             (buffer, buffer_memory)
         }
     }
 
+    // Into a buffer from `VkDeviceContext::create_shared_buffer` at `offset` bytes, copied with
+    // the other uploads of the frame like `create_buffer_with_data`.
+    pub fn upload_buffer_region<T: Copy>(
+        &self,
+        buffer: vk::Buffer,
+        offset: vk::DeviceSize,
+        array: &[T],
+    ) {
+        if array.is_empty() {
+            return;
+        }
+        unsafe {
+            let bytes = std::slice::from_raw_parts(
+                array.as_ptr() as *const u8,
+                std::mem::size_of_val(array),
+            );
+            self.uploads.borrow_mut().upload_buffer_region(
+                &self.device_context,
+                bytes,
+                buffer,
+                offset,
+            );
+        }
+    }
+
     pub fn transition_image_layout(
         &self,
         image: vk::Image,
         format: vk::Format,
         mip_levels: u32,
+        layers: u32,
         old_layout: vk::ImageLayout,
         new_layout: vk::ImageLayout,
     ) {
@@ -308,7 +647,7 @@ impl GPU {
                 base_mip_level: 0,
                 level_count: mip_levels,
                 base_array_layer: 0,
-                layer_count: 1,
+                layer_count: layers,
             });
 
         unsafe {
@@ -354,33 +693,113 @@ impl GPU {
         }
     }
 
-    pub fn create_mapped_buffers(
-        &self,
-        size: vk::DeviceSize,
-    ) -> (vk::Buffer, vk::DeviceMemory, *mut c_void) {
+    pub fn create_mapped_buffers(&self, size: vk::DeviceSize) -> (vk::Buffer, Allocation) {
         unsafe {
-            let (buffer, memory, _) = self.device_context.create_buffer(
+            // host visible allocations come persistently mapped
+            self.device_context.create_buffer(
                 size,
                 vk::BufferUsageFlags::UNIFORM_BUFFER,
                 vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT,
+            )
+        }
+    }
+
+    // Copies a finished frame of 4 byte texels to the host, rows tightly packed top to bottom.
+    // The image is expected in PRESENT_SRC_KHR and left there, waits for the GPU to go idle.
+    pub fn read_back_image(&self, image: vk::Image, width: u32, height: u32) -> Vec<u8> {
+        self.wait_idle();
+        unsafe {
+            let size = (width * height * 4) as vk::DeviceSize;
+            let (buffer, allocation) = self.device_context.create_buffer(
+                size,
+                vk::BufferUsageFlags::TRANSFER_DST,
+                vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT,
             );
 
-            let memory_mapped = self
-                .device_context
-                .device
-                .map_memory(memory, 0, size, vk::MemoryMapFlags::empty())
-                .expect("failed to map buffer memory!");
+            let subresource_range = vk::ImageSubresourceRange {
+                aspect_mask: vk::ImageAspectFlags::COLOR,
+                base_mip_level: 0,
+                level_count: 1,
+                base_array_layer: 0,
+                layer_count: 1,
+            };
+            let to_transfer = vk::ImageMemoryBarrier::default()
+                .image(image)
+                .old_layout(vk::ImageLayout::PRESENT_SRC_KHR)
+                .new_layout(vk::ImageLayout::TRANSFER_SRC_OPTIMAL)
+                .src_access_mask(vk::AccessFlags::COLOR_ATTACHMENT_WRITE)
+                .dst_access_mask(vk::AccessFlags::TRANSFER_READ)
+                .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+                .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+                .subresource_range(subresource_range);
+            let to_present = to_transfer
+                .old_layout(vk::ImageLayout::TRANSFER_SRC_OPTIMAL)
+                .new_layout(vk::ImageLayout::PRESENT_SRC_KHR)
+                .src_access_mask(vk::AccessFlags::TRANSFER_READ)
+                .dst_access_mask(vk::AccessFlags::empty());
+            let region = vk::BufferImageCopy {
+                buffer_offset: 0,
+                buffer_row_length: 0,
+                buffer_image_height: 0,
+                image_subresource: vk::ImageSubresourceLayers {
+                    aspect_mask: vk::ImageAspectFlags::COLOR,
+                    mip_level: 0,
+                    base_array_layer: 0,
+                    layer_count: 1,
+                },
+                image_offset: vk::Offset3D { x: 0, y: 0, z: 0 },
+                image_extent: vk::Extent3D {
+                    width,
+                    height,
+                    depth: 1,
+                },
+            };
+
+            let device = &self.device_context.device;
+            let command_buffer = self.begin_single_time_command();
+            device.cmd_pipeline_barrier(
+                command_buffer,
+                vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
+                vk::PipelineStageFlags::TRANSFER,
+                vk::DependencyFlags::empty(),
+                &[],
+                &[],
+                &[to_transfer],
+            );
+            device.cmd_copy_image_to_buffer(
+                command_buffer,
+                image,
+                vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                buffer,
+                &[region],
+            );
+            device.cmd_pipeline_barrier(
+                command_buffer,
+                vk::PipelineStageFlags::TRANSFER,
+                vk::PipelineStageFlags::BOTTOM_OF_PIPE,
+                vk::DependencyFlags::empty(),
+                &[],
+                &[],
+                &[to_present],
+            );
+            // waits for the copy, the coherent memory is up to date after it
+            self.end_single_time_command(command_buffer);
 
-            (buffer, memory, memory_mapped)
+            let pixels =
+                std::slice::from_raw_parts(allocation.mapped as *const u8, size as usize).to_vec();
+            self.device_context.destroy_buffer(buffer, allocation);
+            pixels
         }
     }
 
+    // Level 0 of every layer, packed back to back in the buffer.
     pub fn copy_buffer_to_image(
         &self,
         buffer: vk::Buffer,
         image: vk::Image,
         width: u32,
         height: u32,
+        layers: u32,
     ) {
         let command_buffer = self.begin_single_time_command();
 
@@ -394,7 +813,7 @@ impl GPU {
                 aspect_mask: vk::ImageAspectFlags::COLOR,
                 mip_level: 0,
                 base_array_layer: 0,
-                layer_count: 1,
+                layer_count: layers,
             },
             image_offset: vk::Offset3D { x: 0, y: 0, z: 0 },
             image_extent: vk::Extent3D {
@@ -417,6 +836,55 @@ impl GPU {
         self.end_single_time_command(command_buffer);
     }
 
+    // One region per level of the chain, laid out back to back in the buffer. A level holds
+    // every layer, packed the same way.
+    pub fn copy_buffer_to_image_mips(
+        &self,
+        buffer: vk::Buffer,
+        image: vk::Image,
+        width: u32,
+        height: u32,
+        layers: u32,
+        mips: &[Range<usize>],
+    ) {
+        let command_buffer = self.begin_single_time_command();
+
+        let regions = mips
+            .iter()
+            .enumerate()
+            .map(|(level, range)| vk::BufferImageCopy {
+                buffer_offset: range.start as vk::DeviceSize,
+                buffer_row_length: 0,
+                buffer_image_height: 0,
+                image_subresource: vk::ImageSubresourceLayers {
+                    aspect_mask: vk::ImageAspectFlags::COLOR,
+                    mip_level: level as u32,
+                    base_array_layer: 0,
+                    layer_count: layers,
+                },
+                image_offset: vk::Offset3D { x: 0, y: 0, z: 0 },
+                // blocks of compressed formats may hang over the edge of the smallest levels
+                image_extent: vk::Extent3D {
+                    width: (width >> level).max(1),
+                    height: (height >> level).max(1),
+                    depth: 1,
+                },
+            })
+            .collect::<Vec<_>>();
+
+        unsafe {
+            self.device_context.device.cmd_copy_buffer_to_image(
+                command_buffer,
+                buffer,
+                image,
+                vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                &regions,
+            );
+        }
+
+        self.end_single_time_command(command_buffer);
+    }
+
     pub fn generate_mipmaps(
         &self,
         image: vk::Image,
@@ -424,6 +892,7 @@ impl GPU {
         width: u32,
         height: u32,
         mip_levels: u32,
+        layers: u32,
     ) {
         let format_properties = self.get_format_properties(format);
         if !format_properties
@@ -434,7 +903,31 @@ impl GPU {
         }
 
         let command_buffer = self.begin_single_time_command();
+        unsafe {
+            Self::record_mipmaps(
+                &self.device_context.device,
+                command_buffer,
+                image,
+                width,
+                height,
+                mip_levels,
+                layers,
+            );
+        }
+        self.end_single_time_command(command_buffer);
+    }
 
+    // Blits every level from the one above, level 0 is expected in TRANSFER_DST_OPTIMAL with the
+    // rest. All levels end up in SHADER_READ_ONLY_OPTIMAL. Needs a graphics queue.
+    pub(super) unsafe fn record_mipmaps(
+        device: &ash::Device,
+        command_buffer: vk::CommandBuffer,
+        image: vk::Image,
+        width: u32,
+        height: u32,
+        mip_levels: u32,
+        layers: u32,
+    ) {
         let mut barrier = vk::ImageMemoryBarrier::default()
             .image(image)
             .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
@@ -444,7 +937,7 @@ impl GPU {
                 base_mip_level: 0,
                 level_count: 1,
                 base_array_layer: 0,
-                layer_count: 1,
+                layer_count: layers,
             });
 
         let mut mip_width = width as i32;
@@ -456,17 +949,15 @@ impl GPU {
             barrier.src_access_mask = vk::AccessFlags::TRANSFER_WRITE;
             barrier.dst_access_mask = vk::AccessFlags::TRANSFER_READ;
             barrier.subresource_range.base_mip_level = i - 1;
-            unsafe {
-                self.device_context.device.cmd_pipeline_barrier(
-                    command_buffer,
-                    vk::PipelineStageFlags::TRANSFER,
-                    vk::PipelineStageFlags::TRANSFER,
-                    vk::DependencyFlags::empty(),
-                    &[],
-                    &[],
-                    &[barrier],
-                );
-            }
+            device.cmd_pipeline_barrier(
+                command_buffer,
+                vk::PipelineStageFlags::TRANSFER,
+                vk::PipelineStageFlags::TRANSFER,
+                vk::DependencyFlags::empty(),
+                &[],
+                &[],
+                &[barrier],
+            );
 
             let next_mip_width = if mip_width > 1 {
                 mip_width / 2
@@ -484,7 +975,7 @@ impl GPU {
                     aspect_mask: vk::ImageAspectFlags::COLOR,
                     mip_level: i - 1,
                     base_array_layer: 0,
-                    layer_count: 1,
+                    layer_count: layers,
                 },
                 src_offsets: [
                     vk::Offset3D { x: 0, y: 0, z: 0 },
@@ -498,7 +989,7 @@ impl GPU {
                     aspect_mask: vk::ImageAspectFlags::COLOR,
                     mip_level: i,
                     base_array_layer: 0,
-                    layer_count: 1,
+                    layer_count: layers,
                 },
                 dst_offsets: [
                     vk::Offset3D { x: 0, y: 0, z: 0 },
@@ -510,46 +1001,22 @@ impl GPU {
                 ],
             };
 
-            unsafe {
-                self.device_context.device.cmd_blit_image(
-                    command_buffer,
-                    image,
-                    vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
-                    image,
-                    vk::ImageLayout::TRANSFER_DST_OPTIMAL,
-                    &[region],
-                    vk::Filter::LINEAR,
-                );
-            }
+            device.cmd_blit_image(
+                command_buffer,
+                image,
+                vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                image,
+                vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                &[region],
+                vk::Filter::LINEAR,
+            );
 
             barrier.old_layout = vk::ImageLayout::TRANSFER_SRC_OPTIMAL;
             barrier.new_layout = vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL;
             barrier.src_access_mask = vk::AccessFlags::TRANSFER_READ;
             barrier.dst_access_mask = vk::AccessFlags::SHADER_READ;
             barrier.subresource_range.base_mip_level = i - 1;
-            unsafe {
-                self.device_context.device.cmd_pipeline_barrier(
-                    command_buffer,
-                    vk::PipelineStageFlags::TRANSFER,
-                    vk::PipelineStageFlags::VERTEX_SHADER | vk::PipelineStageFlags::FRAGMENT_SHADER,
-                    vk::DependencyFlags::empty(),
-                    &[],
-                    &[],
-                    &[barrier],
-                );
-            }
-
-            mip_width = next_mip_width;
-            mip_height = next_mip_height;
-        }
-
-        barrier.old_layout = vk::ImageLayout::TRANSFER_DST_OPTIMAL;
-        barrier.new_layout = vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL;
-        barrier.src_access_mask = vk::AccessFlags::TRANSFER_WRITE;
-        barrier.dst_access_mask = vk::AccessFlags::SHADER_READ;
-        barrier.subresource_range.base_mip_level = mip_levels - 1;
-        unsafe {
-            self.device_context.device.cmd_pipeline_barrier(
+            device.cmd_pipeline_barrier(
                 command_buffer,
                 vk::PipelineStageFlags::TRANSFER,
                 vk::PipelineStageFlags::VERTEX_SHADER | vk::PipelineStageFlags::FRAGMENT_SHADER,
@@ -558,9 +1025,25 @@ impl GPU {
                 &[],
                 &[barrier],
             );
+
+            mip_width = next_mip_width;
+            mip_height = next_mip_height;
         }
 
-        self.end_single_time_command(command_buffer);
+        barrier.old_layout = vk::ImageLayout::TRANSFER_DST_OPTIMAL;
+        barrier.new_layout = vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL;
+        barrier.src_access_mask = vk::AccessFlags::TRANSFER_WRITE;
+        barrier.dst_access_mask = vk::AccessFlags::SHADER_READ;
+        barrier.subresource_range.base_mip_level = mip_levels - 1;
+        device.cmd_pipeline_barrier(
+            command_buffer,
+            vk::PipelineStageFlags::TRANSFER,
+            vk::PipelineStageFlags::VERTEX_SHADER | vk::PipelineStageFlags::FRAGMENT_SHADER,
+            vk::DependencyFlags::empty(),
+            &[],
+            &[],
+            &[barrier],
+        );
     }
 
     pub fn find_supported_format(
@@ -571,13 +1054,12 @@ impl GPU {
     ) -> vk::Format {
         for format in candidates {
             let properties = self.get_format_properties(format);
-            if tiling == vk::ImageTiling::LINEAR
-                && properties.linear_tiling_features & features == features
-            {
-                return format;
-            } else if tiling == vk::ImageTiling::OPTIMAL
-                && properties.optimal_tiling_features & features == features
-            {
+            let supported = match tiling {
+                vk::ImageTiling::LINEAR => properties.linear_tiling_features,
+                vk::ImageTiling::OPTIMAL => properties.optimal_tiling_features,
+                _ => vk::FormatFeatureFlags::empty(),
+            };
+            if supported & features == features {
                 return format;
             }
         }
@@ -585,7 +1067,7 @@ impl GPU {
         panic!("failed to find supported format!")
     }
 
-    fn get_format_properties(&self, format: vk::Format) -> vk::FormatProperties {
+    pub fn get_format_properties(&self, format: vk::Format) -> vk::FormatProperties {
         unsafe {
             self.context
                 .instance
@@ -593,33 +1075,13 @@ impl GPU {
         }
     }
 
-    fn create_descriptor_pool(device: &VkDeviceContext) -> vk::DescriptorPool {
+    fn create_pipeline_cache(device: &VkDeviceContext) -> vk::PipelineCache {
         unsafe {
-            // todo: VK_KHR_push_descriptor
-
-            let mut pool_sizes: Vec<vk::DescriptorPoolSize> = vec![];
-
-            pool_sizes.push(vk::DescriptorPoolSize {
-                ty: vk::DescriptorType::UNIFORM_BUFFER,
-                descriptor_count: 500,
-            });
-            pool_sizes.push(vk::DescriptorPoolSize {
-                ty: vk::DescriptorType::SAMPLED_IMAGE,
-                descriptor_count: 500,
-            });
-            pool_sizes.push(vk::DescriptorPoolSize {
-                ty: vk::DescriptorType::SAMPLER,
-                descriptor_count: 500,
-            });
-
-            let create_info = vk::DescriptorPoolCreateInfo::default()
-                .pool_sizes(&pool_sizes)
-                .max_sets(500);
-
+            // internally synchronized, pipelines can be created from several threads at once
             device
                 .device
-                .create_descriptor_pool(&create_info, None)
-                .expect("failed to create descriptor pool!")
+                .create_pipeline_cache(&vk::PipelineCacheCreateInfo::default(), None)
+                .expect("failed to create pipeline cache!")
         }
     }
 
@@ -633,16 +1095,14 @@ impl GPU {
             .queue_family_index(device.graphic_queue_family.unwrap());
 
         unsafe {
-            let transient_command_pool = device
+            device
                 .device
                 .create_command_pool(&create_info, None)
-                .expect("failed to create transient command pool!");
-
-            transient_command_pool
+                .expect("failed to create transient command pool!")
         }
     }
 
-    fn begin_single_time_command(&self) -> vk::CommandBuffer {
+    pub fn begin_single_time_command(&self) -> vk::CommandBuffer {
         unsafe {
             let device = &self.device_context.device;
 
@@ -664,28 +1124,37 @@ impl GPU {
         }
     }
 
-    fn end_single_time_command(&self, command_buffer: vk::CommandBuffer) {
+    pub fn end_single_time_command(&self, command_buffer: vk::CommandBuffer) {
         unsafe {
             let device = &self.device_context.device;
             device
                 .end_command_buffer(command_buffer)
                 .expect("failed to end single time command buffer!");
 
+            // the command may read what was uploaded before it
+            self.flush_uploads();
+
             let command_buffers = [command_buffer];
             let submit_info = vk::SubmitInfo::default().command_buffers(&command_buffers);
+            let fence = device
+                .create_fence(&vk::FenceCreateInfo::default(), None)
+                .expect("failed to create single time fence!");
 
+            let queue = self.device_context.queue_lock.lock().unwrap();
             device
                 .queue_submit(
                     self.device_context.graphic_queue.unwrap(),
                     &[submit_info],
-                    vk::Fence::null(),
+                    fence,
                 )
                 .expect("failed to submit single time command buffer");
+            drop(queue);
 
-            // todo: Schedule multiple transfers simultaneously and wait for all of them complete, instead of executing one at a time.
+            // only this command is waited on, frames in flight keep running
             device
-                .device_wait_idle()
-                .expect("failed to wait device idle!");
+                .wait_for_fences(&[fence], true, u64::MAX)
+                .expect("failed to wait single time fence!");
+            device.destroy_fence(fence, None);
             device.free_command_buffers(self.transient_command_pool, &[command_buffer]);
         }
     }
@@ -701,34 +1170,34 @@ impl Drop for GPU {
     fn drop(&mut self) {
         unsafe {
             let device = &self.device_context.device;
+            self.presents.borrow_mut().wait();
             device.device_wait_idle().unwrap();
 
-            for &image_view in self.swap_chain.image_views.iter() {
-                device.destroy_image_view(image_view, None);
-            }
-            self.swap_chain
-                .swap_chain_fn
-                .as_ref()
-                .unwrap()
-                .destroy_swapchain(self.swap_chain.swap_chain.unwrap(), None);
+            self.swap_chain.borrow_mut().destroy(&self.device_context);
 
             device.destroy_command_pool(self.transient_command_pool, None);
-            device.destroy_descriptor_pool(self.descriptor_pool, None);
+            self.uploads.borrow_mut().destroy(&self.device_context);
+            self.descriptor_allocator.destroy(device);
+            self.samplers.clear(device);
+            self.deletion_queue
+                .destroy(device, &self.device_context.allocator);
+            if let Some(mut watchdog) = self.watchdog.take() {
+                watchdog.destroy(&self.device_context);
+            }
+            if let Some(mut profiler) = self.profiler.take() {
+                profiler.destroy(device);
+            }
+            device.destroy_pipeline_cache(self.pipeline_cache, None);
+            self.device_context.allocator.destroy(device);
 
             device.destroy_device(None);
 
             let context = &self.context;
-            context
-                .surface_fn
-                .as_ref()
-                .unwrap()
-                .destroy_surface(context.surface.unwrap(), None);
-            if context.debug_utils_fn.is_some() && context.debug_utils_messenger.is_some() {
-                context
-                    .debug_utils_fn
-                    .as_ref()
-                    .unwrap()
-                    .destroy_debug_utils_messenger(context.debug_utils_messenger.unwrap(), None);
+            context.destroy_surface();
+            if let (Some(debug_utils_fn), Some(debug_utils_messenger)) =
+                (&context.debug_utils_fn, context.debug_utils_messenger)
+            {
+                debug_utils_fn.destroy_debug_utils_messenger(debug_utils_messenger, None);
             }
             context.instance.destroy_instance(None);
         }
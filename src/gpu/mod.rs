@@ -1,9 +1,46 @@
+mod adapter;
+mod allocator;
+mod compute;
+mod debug_names;
+mod deletion_queue;
+mod descriptor_allocator;
+mod frame_sync;
+#[allow(clippy::module_inception)]
 mod gpu;
+mod present_thread;
+mod profiler;
+mod render_queue;
+mod rhi;
+mod sampler_cache;
+mod secondary_commands;
 mod swap_chain;
+mod upload_manager;
 mod vk_context;
 mod vk_device_context;
+mod vk_rhi;
+mod watchdog;
 
-pub use gpu::GPU;
+pub use adapter::{AdapterInfo, AdapterSelection, AdapterType};
+pub use allocator::{Allocation, Allocator};
+pub use compute::{ComputePipeline, ComputeReader};
+pub use debug_names::DebugNames;
+pub use deletion_queue::{DeletionQueue, RawResource};
+pub use descriptor_allocator::DescriptorAllocator;
+pub use frame_sync::{FrameSignal, FrameSync};
+pub use gpu::{RawHandles, GPU};
+pub use present_thread::PresentThread;
+pub use profiler::{GpuTimings, Profiler};
+pub use render_queue::{RenderQueue, RenderSender};
+pub use rhi::{
+    ColorSpace, MipFilter, PassDesc, Rhi, SamplerAddress, SamplerDesc, SamplerFilter,
+    TextureChannel, TextureDesc, TextureFormat, TextureSwizzle,
+};
+pub use sampler_cache::SamplerCache;
+pub use secondary_commands::SecondaryCommands;
 use swap_chain::SwapChain;
+pub use swap_chain::{PresentMode, SurfaceFormatMode};
+pub use upload_manager::{ImageUpload, ImageUploadFinish, UploadManager};
 use vk_context::VkContext;
 use vk_device_context::VkDeviceContext;
+pub use vk_rhi::{VkBuffer, VkPipeline, VkTexture};
+pub use watchdog::{HangReport, Watchdog};
@@ -0,0 +1,144 @@
+use crate::error_overlay;
+use ash::prelude::VkResult;
+use ash::vk;
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+
+struct PresentRequest {
+    swap_chain_fn: ash::khr::swapchain::Device,
+    queue: vk::Queue,
+    swap_chain: vk::SwapchainKHR,
+    image_index: u32,
+    wait: vk::Semaphore,
+}
+
+// Hands the frames to the presentation engine from a thread of its own. vkQueuePresentKHR blocks
+// on many platforms until an earlier image is on screen, off the main thread that time goes to
+// the next frame's update and recording instead, the frame after the one presenting is acquired
+// and recorded while the present is in progress. Recreating the swap chain and waiting on the
+// device need the swap chain to themselves, `wait` has to come first. Vulkan queues and swap
+// chains are externally synchronized: every submit takes `queue_lock` like the presents do, and
+// acquires take `swap_chain_lock` only, so a blocking acquire never holds up the submits.
+pub struct PresentThread {
+    // taken by the presents before `queue_lock`
+    pub swap_chain_lock: Arc<Mutex<()>>,
+
+    requests: Option<Sender<PresentRequest>>,
+    // Ok(true) for suboptimal
+    results: Receiver<VkResult<bool>>,
+    pending: usize,
+    // a present since the last `up_to_date` found the swap chain no longer matching the surface
+    outdated: bool,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl PresentThread {
+    pub fn new(queue_lock: Arc<Mutex<()>>) -> Self {
+        let (requests, requested) = mpsc::channel::<PresentRequest>();
+        let (presented, results) = mpsc::channel();
+        let swap_chain_lock = Arc::new(Mutex::new(()));
+        let present_lock = swap_chain_lock.clone();
+        let thread = thread::Builder::new()
+            .name("present".to_string())
+            .spawn(move || {
+                for request in requested {
+                    let wait_semaphores = [request.wait];
+                    let image_indices = [request.image_index];
+                    let swap_chains = [request.swap_chain];
+                    let present_info = vk::PresentInfoKHR::default()
+                        .wait_semaphores(&wait_semaphores)
+                        .image_indices(&image_indices)
+                        .swapchains(&swap_chains);
+                    let result = unsafe {
+                        let _swap_chain = present_lock.lock().unwrap();
+                        let _queue = queue_lock.lock().unwrap();
+                        request
+                            .swap_chain_fn
+                            .queue_present(request.queue, &present_info)
+                    };
+                    if presented.send(result).is_err() {
+                        break;
+                    }
+                }
+            })
+            .expect("failed to spawn present thread!");
+
+        Self {
+            swap_chain_lock,
+
+            requests: Some(requests),
+            results,
+            pending: 0,
+            outdated: false,
+            thread: Some(thread),
+        }
+    }
+
+    pub fn present(
+        &mut self,
+        swap_chain_fn: &ash::khr::swapchain::Device,
+        queue: vk::Queue,
+        swap_chain: vk::SwapchainKHR,
+        image_index: u32,
+        wait: vk::Semaphore,
+    ) {
+        let request = PresentRequest {
+            swap_chain_fn: swap_chain_fn.clone(),
+            queue,
+            swap_chain,
+            image_index,
+            wait,
+        };
+        // the thread runs until this is dropped
+        _ = self.requests.as_ref().unwrap().send(request);
+        self.pending += 1;
+    }
+
+    // Blocks until the queued presents are done.
+    pub fn wait(&mut self) {
+        self.wait_until(0);
+    }
+
+    // Blocks until at most `pending` presents are left in the queue.
+    pub fn wait_until(&mut self, pending: usize) {
+        while self.pending > pending {
+            let Ok(result) = self.results.recv() else {
+                break;
+            };
+            self.finished(result);
+        }
+    }
+
+    // False when a present since the last call found the swap chain out of date or suboptimal.
+    // Presents still in progress are left for the next call.
+    pub fn up_to_date(&mut self) -> bool {
+        while let Ok(result) = self.results.try_recv() {
+            self.finished(result);
+        }
+        !std::mem::take(&mut self.outdated)
+    }
+
+    fn finished(&mut self, result: VkResult<bool>) {
+        self.pending -= 1;
+        match result {
+            Ok(is_suboptimal) => self.outdated |= is_suboptimal,
+            Err(vk::Result::ERROR_OUT_OF_DATE_KHR) => self.outdated = true,
+            Err(error) => {
+                error_overlay::report("swap chain", format!("failed to present: {}", error));
+                self.outdated = true;
+            }
+        }
+    }
+}
+
+impl Drop for PresentThread {
+    fn drop(&mut self) {
+        self.wait();
+        // ends the thread's loop
+        self.requests.take();
+        if let Some(thread) = self.thread.take() {
+            _ = thread.join();
+        }
+    }
+}
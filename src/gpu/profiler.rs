@@ -0,0 +1,204 @@
+use ash::vk;
+use std::time::Duration;
+
+// timestamps per frame, the frame's own two and a pair per pass
+const MAX_QUERIES: u32 = 128;
+const FRAME_QUERIES: u32 = 2;
+
+// How long the GPU spent on a frame and on each of its labelled passes.
+#[derive(Debug, Clone, Default)]
+pub struct GpuTimings {
+    pub frame: u64,
+    // from the start of the command buffer to its last command
+    pub total: Duration,
+    // in the order they were recorded
+    pub passes: Vec<(&'static str, Duration)>,
+}
+
+struct FrameQueries {
+    command_buffer: vk::CommandBuffer,
+    query_pool: vk::QueryPool,
+    frame: u64,
+    // label and query of the pass start, the end is the one after it
+    passes: Vec<(&'static str, u32)>,
+    next_query: u32,
+    // waiting for its results
+    recorded: bool,
+}
+
+// Times the passes of every frame with timestamp queries. Each command buffer of a frame in flight
+// gets a query pool, its results are read back the next time it is recorded, once its fence was
+// waited on, so the timings lag the frame being recorded by the frames in flight.
+pub struct Profiler {
+    // nanoseconds per tick
+    timestamp_period: f32,
+    // the queue writes fewer than 64 bits on some devices
+    timestamp_mask: u64,
+    frame: u64,
+    slots: Vec<FrameQueries>,
+    latest: Option<GpuTimings>,
+}
+
+impl Profiler {
+    pub fn new(timestamp_period: f32, timestamp_valid_bits: u32) -> Self {
+        Self {
+            timestamp_period,
+            timestamp_mask: match timestamp_valid_bits {
+                64.. => u64::MAX,
+                bits => (1 << bits) - 1,
+            },
+            frame: 0,
+            slots: vec![],
+            latest: None,
+        }
+    }
+
+    // The timings of the latest frame the GPU finished, None until there is one.
+    pub fn timings(&self) -> Option<&GpuTimings> {
+        self.latest.as_ref()
+    }
+
+    // The command buffer was waited on and is recorded again.
+    pub unsafe fn begin(&mut self, device: &ash::Device, command_buffer: vk::CommandBuffer) {
+        self.frame += 1;
+        let slot = match self.slot(command_buffer) {
+            Some(slot) => slot,
+            None => {
+                let create_info = vk::QueryPoolCreateInfo::default()
+                    .query_type(vk::QueryType::TIMESTAMP)
+                    .query_count(MAX_QUERIES);
+                let query_pool = device
+                    .create_query_pool(&create_info, None)
+                    .expect("failed to create query pool!");
+                self.slots.push(FrameQueries {
+                    command_buffer,
+                    query_pool,
+                    frame: 0,
+                    passes: vec![],
+                    next_query: FRAME_QUERIES,
+                    recorded: false,
+                });
+                self.slots.len() - 1
+            }
+        };
+
+        if self.slots[slot].recorded {
+            if let Some(timings) = self.resolve(device, &self.slots[slot]) {
+                self.latest = Some(timings);
+            }
+        }
+
+        let queries = &mut self.slots[slot];
+        queries.frame = self.frame;
+        queries.passes.clear();
+        queries.next_query = FRAME_QUERIES;
+        queries.recorded = false;
+        device.cmd_reset_query_pool(command_buffer, queries.query_pool, 0, MAX_QUERIES);
+        device.cmd_write_timestamp(
+            command_buffer,
+            vk::PipelineStageFlags::TOP_OF_PIPE,
+            queries.query_pool,
+            0,
+        );
+    }
+
+    pub unsafe fn begin_pass(
+        &mut self,
+        device: &ash::Device,
+        command_buffer: vk::CommandBuffer,
+        label: &'static str,
+    ) {
+        let Some(queries) = self.queries_mut(command_buffer) else {
+            return;
+        };
+        // passes past the pool's size go untimed
+        if queries.next_query + 2 > MAX_QUERIES {
+            return;
+        }
+        let query = queries.next_query;
+        queries.passes.push((label, query));
+        queries.next_query += 2;
+        device.cmd_write_timestamp(
+            command_buffer,
+            vk::PipelineStageFlags::TOP_OF_PIPE,
+            queries.query_pool,
+            query,
+        );
+    }
+
+    pub unsafe fn end_pass(&mut self, device: &ash::Device, command_buffer: vk::CommandBuffer) {
+        let Some(queries) = self.queries_mut(command_buffer) else {
+            return;
+        };
+        let Some(&(_, query)) = queries.passes.last() else {
+            return;
+        };
+        device.cmd_write_timestamp(
+            command_buffer,
+            vk::PipelineStageFlags::BOTTOM_OF_PIPE,
+            queries.query_pool,
+            query + 1,
+        );
+    }
+
+    // Right before the command buffer is ended.
+    pub unsafe fn end(&mut self, device: &ash::Device, command_buffer: vk::CommandBuffer) {
+        let Some(queries) = self.queries_mut(command_buffer) else {
+            return;
+        };
+        queries.recorded = true;
+        device.cmd_write_timestamp(
+            command_buffer,
+            vk::PipelineStageFlags::BOTTOM_OF_PIPE,
+            queries.query_pool,
+            1,
+        );
+    }
+
+    pub unsafe fn destroy(&mut self, device: &ash::Device) {
+        self.slots
+            .drain(..)
+            .for_each(|queries| device.destroy_query_pool(queries.query_pool, None));
+    }
+
+    // None when the frame never finished, e.g. it was given up on after a hang
+    unsafe fn resolve(&self, device: &ash::Device, queries: &FrameQueries) -> Option<GpuTimings> {
+        let mut ticks = vec![0u64; queries.next_query as usize];
+        device
+            .get_query_pool_results(
+                queries.query_pool,
+                0,
+                &mut ticks,
+                vk::QueryResultFlags::TYPE_64,
+            )
+            .ok()?;
+
+        let duration = |start: u32, end: u32| {
+            let start = ticks[start as usize] & self.timestamp_mask;
+            let end = ticks[end as usize] & self.timestamp_mask;
+            let nanos = end.wrapping_sub(start) & self.timestamp_mask;
+            Duration::from_nanos((nanos as f64 * self.timestamp_period as f64) as u64)
+        };
+        Some(GpuTimings {
+            frame: queries.frame,
+            total: duration(0, 1),
+            passes: queries
+                .passes
+                .iter()
+                .map(|&(label, query)| (label, duration(query, query + 1)))
+                .collect(),
+        })
+    }
+
+    fn slot(&self, command_buffer: vk::CommandBuffer) -> Option<usize> {
+        self.slots
+            .iter()
+            .position(|queries| queries.command_buffer == command_buffer)
+    }
+
+    fn queries_mut(&mut self, command_buffer: vk::CommandBuffer) -> Option<&mut FrameQueries> {
+        self.slots
+            .iter_mut()
+            .find(|queries| queries.command_buffer == command_buffer)
+    }
+}
@@ -0,0 +1,67 @@
+use std::sync::mpsc::{self, Receiver, Sender};
+
+pub type RenderJob<T> = Box<dyn FnOnce(&mut T) + Send>;
+
+// Work other threads hand to the thread owning the GPU. The GPU and everything holding it are
+// `Rc` based and stay on that thread, loader and worker threads do the CPU side (decoding,
+// building vertex data) and post a job touching the GPU, which runs when the owner drains the
+// queue. `T` is what the jobs get, the app's queue runs them on `Mirage`.
+pub struct RenderQueue<T> {
+    sender: Sender<RenderJob<T>>,
+    receiver: Receiver<RenderJob<T>>,
+}
+
+impl<T> RenderQueue<T> {
+    pub fn new() -> Self {
+        let (sender, receiver) = mpsc::channel();
+        Self { sender, receiver }
+    }
+
+    pub fn sender(&self) -> RenderSender<T> {
+        RenderSender {
+            sender: self.sender.clone(),
+        }
+    }
+
+    // The jobs posted so far in posting order, for the owning thread to run. Taken out first so
+    // they can borrow whatever owns the queue.
+    pub fn take(&self) -> Vec<RenderJob<T>> {
+        self.receiver.try_iter().collect()
+    }
+}
+
+// The end of a `RenderQueue` other threads get, cheap to clone and `Send`.
+pub struct RenderSender<T> {
+    sender: Sender<RenderJob<T>>,
+}
+
+impl<T> Clone for RenderSender<T> {
+    fn clone(&self) -> Self {
+        Self {
+            sender: self.sender.clone(),
+        }
+    }
+}
+
+impl<T> RenderSender<T> {
+    // False when the queue is gone, e.g. the app is shutting down.
+    pub fn post(&self, job: impl FnOnce(&mut T) + Send + 'static) -> bool {
+        self.sender.send(Box::new(job)).is_ok()
+    }
+
+    // Runs the job on the owning thread and blocks until its result is back, None when the queue
+    // is gone before it ran. Calling it from the owning thread deadlocks.
+    pub fn call<R: Send + 'static>(
+        &self,
+        job: impl FnOnce(&mut T) -> R + Send + 'static,
+    ) -> Option<R> {
+        let (result_sender, result_receiver) = mpsc::channel();
+        let posted = self.post(move |target| {
+            _ = result_sender.send(job(target));
+        });
+        if !posted {
+            return None;
+        }
+        result_receiver.recv().ok()
+    }
+}
@@ -0,0 +1,387 @@
+// Render hardware interface between the renderer and the graphics API.
+// The renderer creates its resources, records passes and submits frames through this trait only,
+// GPU implements it on top of Vulkan. Another backend (wgpu, Metal, DX12) has to provide the same surface.
+
+use std::hash::{Hash, Hasher};
+use std::ops::Range;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum BufferUsage {
+    Vertex,
+    Index,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum TextureFormat {
+    Rgba8Srgb,
+    Rgba8Unorm,
+    // block compressed, 4x4 texels per block
+    Bc1Srgb,
+    Bc1Unorm,
+    Bc3Srgb,
+    Bc3Unorm,
+    Bc4Unorm,
+    Bc5Unorm,
+    Bc7Srgb,
+    Bc7Unorm,
+    Etc2Rgba8Srgb,
+    Etc2Rgba8Unorm,
+    Astc4x4Srgb,
+    Astc4x4Unorm,
+}
+
+impl TextureFormat {
+    // Bytes of a 4x4 block, None for formats stored per texel.
+    pub fn block_bytes(&self) -> Option<usize> {
+        match self {
+            Self::Rgba8Srgb | Self::Rgba8Unorm => None,
+            Self::Bc1Srgb | Self::Bc1Unorm | Self::Bc4Unorm => Some(8),
+            _ => Some(16),
+        }
+    }
+
+    pub fn is_compressed(&self) -> bool {
+        self.block_bytes().is_some()
+    }
+
+    pub fn color_space(&self) -> ColorSpace {
+        match self {
+            Self::Rgba8Srgb
+            | Self::Bc1Srgb
+            | Self::Bc3Srgb
+            | Self::Bc7Srgb
+            | Self::Etc2Rgba8Srgb
+            | Self::Astc4x4Srgb => ColorSpace::Srgb,
+            _ => ColorSpace::Linear,
+        }
+    }
+
+    // The variant with the same texel layout that samples in `color_space`. BC4 and BC5 only
+    // come linear.
+    pub fn with_color_space(&self, color_space: ColorSpace) -> Self {
+        match (self, color_space) {
+            (Self::Rgba8Srgb | Self::Rgba8Unorm, ColorSpace::Srgb) => Self::Rgba8Srgb,
+            (Self::Rgba8Srgb | Self::Rgba8Unorm, ColorSpace::Linear) => Self::Rgba8Unorm,
+            (Self::Bc1Srgb | Self::Bc1Unorm, ColorSpace::Srgb) => Self::Bc1Srgb,
+            (Self::Bc1Srgb | Self::Bc1Unorm, ColorSpace::Linear) => Self::Bc1Unorm,
+            (Self::Bc3Srgb | Self::Bc3Unorm, ColorSpace::Srgb) => Self::Bc3Srgb,
+            (Self::Bc3Srgb | Self::Bc3Unorm, ColorSpace::Linear) => Self::Bc3Unorm,
+            (Self::Bc7Srgb | Self::Bc7Unorm, ColorSpace::Srgb) => Self::Bc7Srgb,
+            (Self::Bc7Srgb | Self::Bc7Unorm, ColorSpace::Linear) => Self::Bc7Unorm,
+            (Self::Etc2Rgba8Srgb | Self::Etc2Rgba8Unorm, ColorSpace::Srgb) => Self::Etc2Rgba8Srgb,
+            (Self::Etc2Rgba8Srgb | Self::Etc2Rgba8Unorm, ColorSpace::Linear) => {
+                Self::Etc2Rgba8Unorm
+            }
+            (Self::Astc4x4Srgb | Self::Astc4x4Unorm, ColorSpace::Srgb) => Self::Astc4x4Srgb,
+            (Self::Astc4x4Srgb | Self::Astc4x4Unorm, ColorSpace::Linear) => Self::Astc4x4Unorm,
+            (format, _) => *format,
+        }
+    }
+}
+
+// How the texels of a texture are encoded. Srgb is color, sampling decodes it to linear values.
+// Linear is data, normals, roughness, masks, noise, sampled as stored.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Default)]
+pub enum ColorSpace {
+    #[default]
+    Srgb,
+    Linear,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum TextureChannel {
+    R,
+    G,
+    B,
+    A,
+    Zero,
+    One,
+}
+
+// Which source channel each of r, g, b, a reads, e.g. the roughness of a packed ORM texture
+// is `TextureSwizzle::splat(TextureChannel::G)`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct TextureSwizzle(pub [TextureChannel; 4]);
+
+impl TextureSwizzle {
+    pub const IDENTITY: Self = Self([
+        TextureChannel::R,
+        TextureChannel::G,
+        TextureChannel::B,
+        TextureChannel::A,
+    ]);
+
+    pub fn splat(channel: TextureChannel) -> Self {
+        Self([channel; 4])
+    }
+}
+
+impl Default for TextureSwizzle {
+    fn default() -> Self {
+        Self::IDENTITY
+    }
+}
+
+// How the levels of a generated mip chain are filtered from the level above.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Default)]
+pub enum MipFilter {
+    // box filtered color, the blit chain
+    #[default]
+    Linear,
+    // Tangent space normals in rgb, renormalized on every level. Alpha keeps the length of the
+    // averaged level 0 normals, `(1 - a) / a` is the variance a shader adds to its roughness
+    // squared against specular aliasing (Toksvig).
+    Normal,
+    // Perceptual roughness in the channel, averaged as GGX alpha squared so a level keeps the
+    // variance of the texels it covers. The other channels are box filtered.
+    Roughness(TextureChannel),
+}
+
+impl MipFilter {
+    // Which of r, g, b, a holds the roughness.
+    pub fn roughness_channel(&self) -> Option<usize> {
+        match self {
+            Self::Roughness(TextureChannel::R) => Some(0),
+            Self::Roughness(TextureChannel::G) => Some(1),
+            Self::Roughness(TextureChannel::B) => Some(2),
+            Self::Roughness(TextureChannel::A) => Some(3),
+            _ => None,
+        }
+    }
+}
+
+pub struct TextureDesc<'a> {
+    pub width: u32,
+    pub height: u32,
+    pub mip_levels: u32,
+    // 6 for a cube map
+    pub layers: u32,
+    pub format: TextureFormat,
+    pub pixels: &'a [u8],
+    // byte range of every mip level in `pixels`, empty to generate the chain from level 0
+    pub mips: &'a [Range<usize>],
+    pub mip_filter: MipFilter,
+    pub sampler: SamplerDesc,
+}
+
+impl TextureDesc<'_> {
+    // The generated chain is filtered by a compute pass after the upload instead of blitted, see
+    // `MipGenerator`. Only plain 2D RGBA8 without precomputed levels is.
+    pub fn is_mip_filtered(&self) -> bool {
+        self.mip_filter != MipFilter::Linear
+            && self.mips.is_empty()
+            && self.mip_levels > 1
+            && self.layers == 1
+            && self.format == TextureFormat::Rgba8Unorm
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Default)]
+pub enum SamplerFilter {
+    #[default]
+    Linear,
+    // blocky magnification, e.g. pixel art
+    Nearest,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Default)]
+pub enum SamplerAddress {
+    #[default]
+    Repeat,
+    MirroredRepeat,
+    // no bleeding from the opposite edge, e.g. UI and atlases
+    ClampToEdge,
+}
+
+// Comparison against the reference of a shadow sampler, the result is filtered instead of depth.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum SamplerCompare {
+    Less,
+    LessOrEqual,
+    Greater,
+    GreaterOrEqual,
+}
+
+// How a texture is sampled. Identical descs share one sampler, see `SamplerCache`.
+#[derive(Debug, Copy, Clone)]
+pub struct SamplerDesc {
+    pub filter: SamplerFilter,
+    pub address: SamplerAddress,
+    // up to the device limit, off for textures viewed head on anyway
+    pub anisotropy: bool,
+    // added to the renderer's bias, see `GPU::mip_lod_bias`
+    pub mip_bias: f32,
+    pub compare: Option<SamplerCompare>,
+}
+
+impl SamplerDesc {
+    pub fn clamped() -> Self {
+        Self {
+            address: SamplerAddress::ClampToEdge,
+            ..Default::default()
+        }
+    }
+
+    pub fn nearest() -> Self {
+        Self {
+            filter: SamplerFilter::Nearest,
+            anisotropy: false,
+            ..Default::default()
+        }
+    }
+}
+
+impl Default for SamplerDesc {
+    fn default() -> Self {
+        Self {
+            filter: SamplerFilter::Linear,
+            address: SamplerAddress::Repeat,
+            anisotropy: true,
+            mip_bias: 0.0,
+            compare: None,
+        }
+    }
+}
+
+// the bias compares by its bits, so descs can key a map
+type SamplerKey = (
+    SamplerFilter,
+    SamplerAddress,
+    bool,
+    u32,
+    Option<SamplerCompare>,
+);
+
+impl SamplerDesc {
+    fn key(&self) -> SamplerKey {
+        (
+            self.filter,
+            self.address,
+            self.anisotropy,
+            self.mip_bias.to_bits(),
+            self.compare,
+        )
+    }
+}
+
+impl PartialEq for SamplerDesc {
+    fn eq(&self, other: &Self) -> bool {
+        self.key() == other.key()
+    }
+}
+
+impl Eq for SamplerDesc {}
+
+impl Hash for SamplerDesc {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.key().hash(state);
+    }
+}
+
+pub struct PassDesc<R: Rhi + ?Sized> {
+    // names the pass in hang reports, see `Watchdog`
+    pub label: &'static str,
+    pub render_pass: R::RenderPass,
+    pub framebuffer: R::Framebuffer,
+    pub width: u32,
+    pub height: u32,
+    pub clear_color: [f32; 4],
+    pub clear_depth: f32,
+}
+
+pub trait Rhi {
+    type Buffer: Copy;
+    type Texture: Copy;
+    type Pipeline: Copy;
+    type ResourceSet: Copy;
+    type RenderPass: Copy;
+    type Framebuffer: Copy;
+    type CommandBuffer: Copy;
+    type Semaphore: Copy;
+    // what the CPU waits on for a submit to finish
+    type Fence: Copy;
+
+    // resources
+    fn create_buffer<T: Copy>(&self, data: &[T], usage: BufferUsage) -> Self::Buffer;
+    fn destroy_buffer(&self, buffer: Self::Buffer);
+    // false when the device can't sample it, compressed formats depend on the platform
+    fn is_texture_format_supported(&self, format: TextureFormat) -> bool;
+    fn create_texture(&self, desc: &TextureDesc) -> Self::Texture;
+    fn destroy_texture(&self, texture: Self::Texture);
+    // another view of the same texture reading swizzled channels, destroying it leaves the texture alive
+    fn create_texture_view(
+        &self,
+        texture: &Self::Texture,
+        swizzle: TextureSwizzle,
+    ) -> Self::Texture;
+    fn destroy_texture_view(&self, view: Self::Texture);
+    // texture at `binding`, its sampler at `binding + 1`
+    fn write_texture(&self, set: Self::ResourceSet, binding: u32, texture: &Self::Texture);
+    fn destroy_pipeline(&self, pipeline: Self::Pipeline);
+
+    // passes
+    fn begin_commands(&self, command_buffer: Self::CommandBuffer);
+    fn end_commands(&self, command_buffer: Self::CommandBuffer);
+    fn begin_pass(&self, command_buffer: Self::CommandBuffer, desc: &PassDesc<Self>);
+    // the pass has more than one subpass, the timings stay with the pass
+    fn next_subpass(&self, command_buffer: Self::CommandBuffer);
+    // Like `begin_pass` and `next_subpass`, the subpass is then filled by `execute_commands` only.
+    fn begin_secondary_pass(&self, command_buffer: Self::CommandBuffer, desc: &PassDesc<Self>);
+    fn next_secondary_subpass(&self, command_buffer: Self::CommandBuffer);
+    // command buffers recorded for the current subpass, possibly on other threads
+    fn execute_commands(
+        &self,
+        command_buffer: Self::CommandBuffer,
+        secondaries: &[Self::CommandBuffer],
+    );
+    fn end_pass(&self, command_buffer: Self::CommandBuffer);
+    fn bind_pipeline(&self, command_buffer: Self::CommandBuffer, pipeline: &Self::Pipeline);
+    fn bind_resource_sets(
+        &self,
+        command_buffer: Self::CommandBuffer,
+        pipeline: &Self::Pipeline,
+        first_set: u32,
+        sets: &[Self::ResourceSet],
+    );
+    // one offset per dynamic buffer in the sets, in binding order
+    fn bind_resource_sets_dynamic(
+        &self,
+        command_buffer: Self::CommandBuffer,
+        pipeline: &Self::Pipeline,
+        first_set: u32,
+        sets: &[Self::ResourceSet],
+        dynamic_offsets: &[u32],
+    );
+    fn push_constants(
+        &self,
+        command_buffer: Self::CommandBuffer,
+        pipeline: &Self::Pipeline,
+        data: &[u8],
+    );
+    // `indices` of the index buffer, `vertex_offset` added to each
+    fn draw_indexed(
+        &self,
+        command_buffer: Self::CommandBuffer,
+        vertex_buffer: &Self::Buffer,
+        index_buffer: &Self::Buffer,
+        indices: Range<u32>,
+        vertex_offset: i32,
+    );
+    // no bound buffers, vertices come from the vertex index (fullscreen passes)
+    fn draw(&self, command_buffer: Self::CommandBuffer, vertex_count: u32);
+
+    // submits
+    fn wait_fence(&self, fence: Self::Fence);
+    fn reset_fence(&self, fence: Self::Fence);
+    // None when the swap chain is out of date
+    fn acquire_image(&self, signal: Self::Semaphore) -> Option<u32>;
+    fn submit(
+        &self,
+        command_buffer: Self::CommandBuffer,
+        wait: Self::Semaphore,
+        signal: Self::Semaphore,
+        fence: Self::Fence,
+    );
+    // false when the swap chain has to be recreated
+    fn present(&self, image_index: u32, wait: Self::Semaphore) -> bool;
+}
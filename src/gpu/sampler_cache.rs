@@ -0,0 +1,86 @@
+use super::{SamplerDesc, SamplerFilter, VkDeviceContext};
+use ash::vk;
+use std::cell::RefCell;
+use std::collections::HashMap;
+
+// One sampler per distinct `SamplerDesc`, shared by every texture sampled that way. The renderer's
+// mip bias is baked into all of them, see `clear`.
+pub struct SamplerCache {
+    samplers: RefCell<HashMap<SamplerDesc, vk::Sampler>>,
+}
+
+impl SamplerCache {
+    pub fn new() -> Self {
+        Self {
+            samplers: RefCell::new(HashMap::new()),
+        }
+    }
+
+    pub fn get(
+        &self,
+        device_context: &VkDeviceContext,
+        desc: &SamplerDesc,
+        mip_lod_bias: f32,
+    ) -> vk::Sampler {
+        *self
+            .samplers
+            .borrow_mut()
+            .entry(*desc)
+            .or_insert_with(|| unsafe { Self::create_sampler(device_context, desc, mip_lod_bias) })
+    }
+
+    // Destroys every sampler handed out, e.g. when the bias changed. Nothing may use them anymore,
+    // the next `get` creates them again.
+    pub unsafe fn clear(&self, device: &ash::Device) {
+        self.samplers
+            .borrow_mut()
+            .drain()
+            .for_each(|(_, sampler)| device.destroy_sampler(sampler, None));
+    }
+
+    unsafe fn create_sampler(
+        device_context: &VkDeviceContext,
+        desc: &SamplerDesc,
+        mip_lod_bias: f32,
+    ) -> vk::Sampler {
+        let limits = &device_context.physical_device_properties.limits;
+        let mip_lod_bias = (mip_lod_bias + desc.mip_bias)
+            .clamp(-limits.max_sampler_lod_bias, limits.max_sampler_lod_bias);
+        let mipmap_mode = match desc.filter {
+            SamplerFilter::Linear => vk::SamplerMipmapMode::LINEAR,
+            SamplerFilter::Nearest => vk::SamplerMipmapMode::NEAREST,
+        };
+        let address_mode = vk::SamplerAddressMode::from(desc.address);
+        let compare_op = desc
+            .compare
+            .map_or(vk::CompareOp::ALWAYS, vk::CompareOp::from);
+
+        let anisotropy = desc.anisotropy && device_context.is_anisotropy_supported();
+
+        // every level is reachable, the sampler doesn't depend on the texture's chain
+        let create_info = vk::SamplerCreateInfo::default()
+            .anisotropy_enable(anisotropy)
+            .max_anisotropy(match anisotropy {
+                true => limits.max_sampler_anisotropy,
+                false => 1.0,
+            })
+            .compare_enable(desc.compare.is_some())
+            .compare_op(compare_op)
+            .min_filter(desc.filter.into())
+            .mag_filter(desc.filter.into())
+            .mipmap_mode(mipmap_mode)
+            .min_lod(0.0)
+            .max_lod(vk::LOD_CLAMP_NONE)
+            .mip_lod_bias(mip_lod_bias)
+            .unnormalized_coordinates(false)
+            .address_mode_u(address_mode)
+            .address_mode_v(address_mode)
+            .address_mode_w(address_mode)
+            .border_color(vk::BorderColor::FLOAT_OPAQUE_BLACK);
+
+        device_context
+            .device
+            .create_sampler(&create_info, None)
+            .expect("failed to create image sampler!")
+    }
+}
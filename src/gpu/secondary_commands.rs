@@ -0,0 +1,148 @@
+use super::GPU;
+use ash::vk;
+
+// Secondary command buffers recorded on worker threads and executed by a pass of the thread owning
+// the GPU. A command pool may only be used by one thread at a time, so every worker has its own per
+// frame in flight, and the buffers of a frame are reset together once its fence was waited on.
+pub struct SecondaryCommands {
+    device: ash::Device,
+    // [frame in flight][worker]
+    pools: Vec<Vec<vk::CommandPool>>,
+    // allocated so far per pool, reused every time the frame comes around
+    buffers: Vec<Vec<Vec<vk::CommandBuffer>>>,
+    // handed out this frame per pool
+    used: Vec<usize>,
+}
+
+impl SecondaryCommands {
+    pub fn new(gpu: &GPU, frames_in_flight: u32, workers: usize) -> Self {
+        let device = gpu.device_context.device.clone();
+        let create_info = vk::CommandPoolCreateInfo::default()
+            .flags(vk::CommandPoolCreateFlags::TRANSIENT)
+            .queue_family_index(gpu.device_context.graphic_queue_family.unwrap());
+        let pools = (0..frames_in_flight)
+            .map(|_| {
+                (0..workers.max(1))
+                    .map(|_| unsafe {
+                        device
+                            .create_command_pool(&create_info, None)
+                            .expect("failed to create secondary command pool!")
+                    })
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>();
+        let buffers = pools
+            .iter()
+            .map(|pools| vec![vec![]; pools.len()])
+            .collect();
+
+        Self {
+            device,
+            pools,
+            buffers,
+            used: vec![0; workers.max(1)],
+        }
+    }
+
+    pub fn device(&self) -> &ash::Device {
+        &self.device
+    }
+
+    // The last submit of the frame must have completed.
+    pub fn begin_frame(&mut self, frame_index: usize) {
+        unsafe {
+            for pool in &self.pools[frame_index] {
+                self.device
+                    .reset_command_pool(*pool, vk::CommandPoolResetFlags::empty())
+                    .expect("failed to reset secondary command pool!");
+            }
+        }
+        self.used.fill(0);
+    }
+
+    // A buffer of the worker's pool begun inside the subpass, with the viewport and scissor set as
+    // secondaries don't inherit them. Only the worker may record into it until it is ended.
+    pub fn begin(
+        &mut self,
+        frame_index: usize,
+        worker: usize,
+        render_pass: vk::RenderPass,
+        subpass: u32,
+        framebuffer: vk::Framebuffer,
+        extent: vk::Extent2D,
+    ) -> vk::CommandBuffer {
+        let buffers = &mut self.buffers[frame_index][worker];
+        let used = &mut self.used[worker];
+        unsafe {
+            if *used == buffers.len() {
+                let allocate_info = vk::CommandBufferAllocateInfo::default()
+                    .command_pool(self.pools[frame_index][worker])
+                    .command_buffer_count(1)
+                    .level(vk::CommandBufferLevel::SECONDARY);
+                buffers.push(
+                    self.device
+                        .allocate_command_buffers(&allocate_info)
+                        .expect("failed to allocate secondary command buffer!")[0],
+                );
+            }
+            let command_buffer = buffers[*used];
+            *used += 1;
+
+            let inheritance_info = vk::CommandBufferInheritanceInfo::default()
+                .render_pass(render_pass)
+                .subpass(subpass)
+                .framebuffer(framebuffer);
+            let begin_info = vk::CommandBufferBeginInfo::default()
+                .flags(
+                    vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT
+                        | vk::CommandBufferUsageFlags::RENDER_PASS_CONTINUE,
+                )
+                .inheritance_info(&inheritance_info);
+            self.device
+                .begin_command_buffer(command_buffer, &begin_info)
+                .expect("failed to begin secondary command buffer!");
+
+            self.device.cmd_set_viewport(
+                command_buffer,
+                0,
+                &[vk::Viewport {
+                    x: 0.0,
+                    y: 0.0,
+                    width: extent.width as f32,
+                    height: extent.height as f32,
+                    min_depth: 0.0,
+                    max_depth: 1.0,
+                }],
+            );
+            self.device.cmd_set_scissor(
+                command_buffer,
+                0,
+                &[vk::Rect2D {
+                    offset: vk::Offset2D { x: 0, y: 0 },
+                    extent,
+                }],
+            );
+            command_buffer
+        }
+    }
+
+    // On the worker that recorded it, or on the owning thread once the workers are done.
+    pub fn end(device: &ash::Device, command_buffer: vk::CommandBuffer) {
+        unsafe {
+            device
+                .end_command_buffer(command_buffer)
+                .expect("failed to end secondary command buffer!");
+        }
+    }
+}
+
+impl Drop for SecondaryCommands {
+    // The caller waits for the device, the buffers go with their pools.
+    fn drop(&mut self) {
+        unsafe {
+            for pool in self.pools.iter().flatten() {
+                self.device.destroy_command_pool(*pool, None);
+            }
+        }
+    }
+}
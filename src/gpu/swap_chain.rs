@@ -1,24 +1,96 @@
 use super::*;
+use crate::error_overlay;
 use ash::vk;
 use ash::vk::{Fence, Semaphore};
+use std::cell::Cell;
+
+// One per frame in flight, handing them out round robin never gives back an image still rendered to.
+const OFFSCREEN_IMAGE_COUNT: u32 = 2;
+
+// Srgb lets the hardware gamma encode on write, what a window wants.
+// Linear keeps the shader output untouched (UNORM, pass-through color space when available),
+// for capture pipelines that do their own encoding.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum SurfaceFormatMode {
+    #[default]
+    Srgb,
+    Linear,
+}
+
+// How finished frames reach the display, the trade between latency, tearing and blocking.
+// Modes the surface doesn't support fall back to Fifo, the one every surface has.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum PresentMode {
+    // vsync, the frame waits for a free slot in the display queue
+    Fifo,
+    // vsync, but a frame that missed the vertical blank is shown right away and may tear
+    FifoRelaxed,
+    // vsync without blocking, queued frames are replaced by newer ones
+    #[default]
+    Mailbox,
+    // no vsync, lowest latency with tearing
+    Immediate,
+}
+
+impl PresentMode {
+    pub const ALL: [Self; 4] = [
+        Self::Fifo,
+        Self::FifoRelaxed,
+        Self::Mailbox,
+        Self::Immediate,
+    ];
+
+    pub fn vk(self) -> vk::PresentModeKHR {
+        match self {
+            PresentMode::Fifo => vk::PresentModeKHR::FIFO,
+            PresentMode::FifoRelaxed => vk::PresentModeKHR::FIFO_RELAXED,
+            PresentMode::Mailbox => vk::PresentModeKHR::MAILBOX,
+            PresentMode::Immediate => vk::PresentModeKHR::IMMEDIATE,
+        }
+    }
+}
 
 pub struct SwapChain {
     pub swap_chain_fn: Option<ash::khr::swapchain::Device>,
     pub swap_chain: Option<vk::SwapchainKHR>,
 
+    pub format_mode: SurfaceFormatMode,
     pub format: vk::Format,
     pub color_space: vk::ColorSpaceKHR,
+    // what is asked for, `present_mode` is what the surface supports of it
+    pub requested_present_mode: PresentMode,
     pub present_mode: vk::PresentModeKHR,
     pub extent: vk::Extent2D,
     pub images: Vec<vk::Image>,
     pub image_views: Vec<vk::ImageView>,
+    // images that may still wait on the present thread when the next one is acquired, an
+    // application owns at most `images.len() - min_image_count` of them at a time
+    pub queued_presents: usize,
+
+    // headless, the images are the swap chain's own and nothing is presented, see `new_offscreen`
+    pub offscreen: bool,
+    offscreen_allocations: Vec<Allocation>,
+    next_offscreen_image: Cell<u32>,
 }
 
 impl SwapChain {
-    pub fn new(context: &VkContext, device_context: &VkDeviceContext) -> Self {
+    pub fn new(
+        context: &VkContext,
+        device_context: &VkDeviceContext,
+        requested_present_mode: PresentMode,
+    ) -> Self {
         unsafe {
-            let (swap_chain_fn, swap_chain, surface_format, present_mode, extent) =
-                Self::create_swap_chain(&context, device_context);
+            let swap_chain_fn =
+                ash::khr::swapchain::Device::new(&context.instance, &device_context.device);
+            let (swap_chain, surface_format, present_mode, extent, min_image_count) =
+                Self::create_swap_chain(
+                    context,
+                    device_context,
+                    &swap_chain_fn,
+                    vk::SwapchainKHR::null(),
+                    SurfaceFormatMode::default(),
+                    requested_present_mode,
+                );
             //delay
             let (images, image_views) = Self::get_swap_chain_images(
                 device_context,
@@ -32,41 +104,190 @@ impl SwapChain {
                 swap_chain: Some(swap_chain),
 
                 extent,
+                format_mode: SurfaceFormatMode::default(),
                 format: surface_format.format,
                 color_space: surface_format.color_space,
+                requested_present_mode,
                 present_mode,
+                queued_presents: Self::queued_presents(&images, min_image_count),
                 images,
                 image_views,
+
+                offscreen: false,
+                offscreen_allocations: vec![],
+                next_offscreen_image: Cell::new(0),
             }
         }
     }
 
+    // RGBA8 images of `extent` standing in for a swap chain, they can be copied out after the frame.
+    pub fn new_offscreen(device_context: &VkDeviceContext, extent: vk::Extent2D) -> Self {
+        let format_mode = SurfaceFormatMode::default();
+        let format = Self::choose_offscreen_format(format_mode);
+        let mut swap_chain = Self {
+            swap_chain_fn: None,
+            swap_chain: None,
+
+            format_mode,
+            format,
+            color_space: vk::ColorSpaceKHR::SRGB_NONLINEAR,
+            requested_present_mode: PresentMode::default(),
+            present_mode: vk::PresentModeKHR::FIFO,
+            extent,
+            images: vec![],
+            image_views: vec![],
+            // nothing is presented
+            queued_presents: 0,
+
+            offscreen: true,
+            offscreen_allocations: vec![],
+            next_offscreen_image: Cell::new(0),
+        };
+        unsafe { swap_chain.create_offscreen_images(device_context) };
+        swap_chain
+    }
+
+    // Returns None when the swap chain is out of date and has to be recreated.
     pub fn acquire_image(
         &self,
         timeout: u64,
         semaphore: Option<Semaphore>,
         fence: Option<Fence>,
-    ) -> u32 {
+    ) -> Option<u32> {
+        if self.offscreen {
+            let image_index = self.next_offscreen_image.get();
+            self.next_offscreen_image
+                .set((image_index + 1) % OFFSCREEN_IMAGE_COUNT);
+            return Some(image_index);
+        }
+
         unsafe {
             let acquire_result = self.swap_chain_fn.as_ref().unwrap().acquire_next_image(
-                self.swap_chain.unwrap(),
+                self.swap_chain?,
                 timeout,
                 semaphore.unwrap_or_default(),
                 fence.unwrap_or_default(),
             );
 
-            let (image_index, _) = match acquire_result {
-                Ok(result) => result,
-                Err(err_code) => {
-                    if err_code == vk::Result::ERROR_OUT_OF_DATE_KHR {
-                        // self.recreate_swap_chain();
-                        // return;
-                    }
-                    panic!("failed to acquire swap chain image!");
+            match acquire_result {
+                Ok((image_index, _)) => Some(image_index),
+                Err(vk::Result::ERROR_OUT_OF_DATE_KHR) => None,
+                // e.g. a lost surface, the frame is skipped and the swap chain rebuilt
+                Err(error) => {
+                    error_overlay::report(
+                        "swap chain",
+                        format!("failed to acquire image: {}", error),
+                    );
+                    None
                 }
-            };
+            }
+        }
+    }
+
+    // Called after a resize or when the surface came back after a suspend.
+    // The old swap chain is handed over to the new one so in-flight presents can finish.
+    // Offscreen images are recreated at the current `extent`.
+    pub fn recreate(&mut self, context: &VkContext, device_context: &VkDeviceContext) {
+        unsafe {
+            if self.offscreen {
+                self.destroy_image_views(device_context);
+                self.format = Self::choose_offscreen_format(self.format_mode);
+                self.create_offscreen_images(device_context);
+                return;
+            }
+
+            let old_swap_chain = self.swap_chain.take().unwrap_or_default();
+            self.destroy_image_views(device_context);
+
+            let swap_chain_fn = self.swap_chain_fn.as_ref().unwrap();
+            let (swap_chain, surface_format, present_mode, extent, min_image_count) =
+                Self::create_swap_chain(
+                    context,
+                    device_context,
+                    swap_chain_fn,
+                    old_swap_chain,
+                    self.format_mode,
+                    self.requested_present_mode,
+                );
+            if old_swap_chain != vk::SwapchainKHR::null() {
+                swap_chain_fn.destroy_swapchain(old_swap_chain, None);
+            }
+
+            let (images, image_views) = Self::get_swap_chain_images(
+                device_context,
+                swap_chain_fn,
+                swap_chain,
+                surface_format.format,
+            );
 
-            image_index
+            self.swap_chain = Some(swap_chain);
+            self.extent = extent;
+            self.format = surface_format.format;
+            self.color_space = surface_format.color_space;
+            self.present_mode = present_mode;
+            self.queued_presents = Self::queued_presents(&images, min_image_count);
+            self.images = images;
+            self.image_views = image_views;
+        }
+    }
+
+    pub fn destroy(&mut self, device_context: &VkDeviceContext) {
+        unsafe {
+            self.destroy_image_views(device_context);
+            if let Some(swap_chain) = self.swap_chain.take() {
+                self.swap_chain_fn
+                    .as_ref()
+                    .unwrap()
+                    .destroy_swapchain(swap_chain, None);
+            }
+        }
+    }
+
+    unsafe fn destroy_image_views(&mut self, device_context: &VkDeviceContext) {
+        for image_view in self.image_views.drain(..) {
+            device_context.device.destroy_image_view(image_view, None);
+        }
+        // the swap chain's images go with it, offscreen ones are released here
+        for (image, allocation) in self
+            .images
+            .drain(..)
+            .zip(self.offscreen_allocations.drain(..))
+        {
+            device_context.destroy_image(image, allocation);
+        }
+        self.images.clear();
+    }
+
+    unsafe fn create_offscreen_images(&mut self, device_context: &VkDeviceContext) {
+        for _ in 0..OFFSCREEN_IMAGE_COUNT {
+            let (image, allocation) = device_context.create_image(
+                self.extent.width,
+                self.extent.height,
+                1,
+                vk::SampleCountFlags::TYPE_1,
+                self.format,
+                vk::ImageTiling::OPTIMAL,
+                vk::ImageUsageFlags::COLOR_ATTACHMENT | vk::ImageUsageFlags::TRANSFER_SRC,
+                vk::MemoryPropertyFlags::DEVICE_LOCAL,
+            );
+            let image_view = device_context.create_image_view(
+                image,
+                self.format,
+                vk::ImageAspectFlags::COLOR,
+                1,
+            );
+            self.images.push(image);
+            self.image_views.push(image_view);
+            self.offscreen_allocations.push(allocation);
+        }
+        self.next_offscreen_image.set(0);
+    }
+
+    // RGBA, so read back pixels come out in the order image files want them.
+    fn choose_offscreen_format(format_mode: SurfaceFormatMode) -> vk::Format {
+        match format_mode {
+            SurfaceFormatMode::Srgb => vk::Format::R8G8B8A8_SRGB,
+            SurfaceFormatMode::Linear => vk::Format::R8G8B8A8_UNORM,
         }
     }
 
@@ -79,7 +300,7 @@ impl SwapChain {
         Vec<vk::PresentModeKHR>,
     ) {
         let surface_fn = context.surface_fn.as_ref().unwrap();
-        let surface = context.surface.unwrap();
+        let surface = context.surface.get().unwrap();
 
         let capabilities = surface_fn
             .get_physical_device_surface_capabilities(physical_device, surface)
@@ -97,24 +318,30 @@ impl SwapChain {
     unsafe fn create_swap_chain(
         context: &VkContext,
         device: &VkDeviceContext,
+        swap_chain_fn: &ash::khr::swapchain::Device,
+        old_swap_chain: vk::SwapchainKHR,
+        format_mode: SurfaceFormatMode,
+        requested_present_mode: PresentMode,
     ) -> (
-        ash::khr::swapchain::Device,
         vk::SwapchainKHR,
         vk::SurfaceFormatKHR,
         vk::PresentModeKHR,
         vk::Extent2D,
+        u32,
     ) {
         let (surface_capabilities, surface_formats, surface_present_modes) =
             Self::query_surface_support(context, device.physical_device);
 
-        let surface_format = Self::choose_surface_format(&surface_formats);
-        let present_mode = Self::choose_surface_present_mode(&surface_present_modes);
+        let surface_format = Self::choose_surface_format(&surface_formats, format_mode);
+        let present_mode =
+            Self::choose_surface_present_mode(&surface_present_modes, requested_present_mode);
         let extent = Self::choose_surface_extent(context, &surface_capabilities);
 
-        let image_count = (surface_capabilities.min_image_count + 1).clamp(
-            surface_capabilities.min_image_count,
-            surface_capabilities.max_image_count,
-        );
+        // one image being recorded and one handed to the present thread, a max of 0 is no limit
+        let mut image_count = surface_capabilities.min_image_count + 2;
+        if surface_capabilities.max_image_count > 0 {
+            image_count = image_count.min(surface_capabilities.max_image_count);
+        }
 
         let pre_transform = if surface_capabilities
             .supported_transforms
@@ -126,7 +353,7 @@ impl SwapChain {
         };
 
         let mut create_info = vk::SwapchainCreateInfoKHR::default()
-            .surface(context.surface.unwrap())
+            .surface(context.surface.get().unwrap())
             .min_image_count(image_count)
             .image_format(surface_format.format)
             .image_color_space(surface_format.color_space)
@@ -136,8 +363,8 @@ impl SwapChain {
             .pre_transform(pre_transform)
             .composite_alpha(vk::CompositeAlphaFlagsKHR::OPAQUE)
             .present_mode(present_mode)
-            .clipped(true);
-        // .old_swapchain(None)
+            .clipped(true)
+            .old_swapchain(old_swap_chain);
 
         if device.graphic_queue_family == device.present_queue_family {
             create_info.image_sharing_mode = vk::SharingMode::EXCLUSIVE;
@@ -153,20 +380,26 @@ impl SwapChain {
             .as_ptr();
         }
 
-        let swap_chain_fn = ash::khr::swapchain::Device::new(&context.instance, &device.device);
         let swap_chain = swap_chain_fn
             .create_swapchain(&create_info, None)
             .expect("failed to create swap chain!");
 
         (
-            swap_chain_fn,
             swap_chain,
             surface_format,
             present_mode,
             extent,
+            surface_capabilities.min_image_count,
         )
     }
 
+    fn queued_presents(images: &[vk::Image], min_image_count: u32) -> usize {
+        images
+            .len()
+            .saturating_sub(min_image_count as usize)
+            .saturating_sub(1)
+    }
+
     unsafe fn get_swap_chain_images(
         device: &VkDeviceContext,
         swap_chain_fn: &ash::khr::swapchain::Device,
@@ -186,7 +419,28 @@ impl SwapChain {
         (images, image_views)
     }
 
-    fn choose_surface_format(surface_formats: &Vec<vk::SurfaceFormatKHR>) -> vk::SurfaceFormatKHR {
+    fn choose_surface_format(
+        surface_formats: &[vk::SurfaceFormatKHR],
+        format_mode: SurfaceFormatMode,
+    ) -> vk::SurfaceFormatKHR {
+        if format_mode == SurfaceFormatMode::Linear {
+            let is_unorm = |format: &vk::SurfaceFormatKHR| {
+                format.format == vk::Format::B8G8R8A8_UNORM
+                    || format.format == vk::Format::R8G8B8A8_UNORM
+            };
+            // PASS_THROUGH_EXT is only reported with VK_EXT_swapchain_colorspace
+            let linear_format = surface_formats
+                .iter()
+                .cloned()
+                .filter(is_unorm)
+                .find(|format| format.color_space == vk::ColorSpaceKHR::PASS_THROUGH_EXT)
+                .or_else(|| surface_formats.iter().cloned().find(is_unorm));
+            match linear_format {
+                Some(format) => return format,
+                None => log::warn!("surface has no UNORM format, keeping sRGB output!"),
+            }
+        }
+
         surface_formats
             .iter()
             .cloned()
@@ -197,7 +451,26 @@ impl SwapChain {
             .unwrap_or(surface_formats[0])
     }
 
-    fn choose_surface_present_mode(present_modes: &Vec<vk::PresentModeKHR>) -> vk::PresentModeKHR {
+    // The modes of `PresentMode` the surface supports, Fifo at least.
+    pub fn supported_present_modes(
+        context: &VkContext,
+        device_context: &VkDeviceContext,
+    ) -> Vec<PresentMode> {
+        if context.surface.get().is_none() {
+            return vec![PresentMode::Fifo];
+        }
+        let (_, _, present_modes) =
+            unsafe { Self::query_surface_support(context, device_context.physical_device) };
+        PresentMode::ALL
+            .into_iter()
+            .filter(|mode| *mode == PresentMode::Fifo || present_modes.contains(&mode.vk()))
+            .collect()
+    }
+
+    fn choose_surface_present_mode(
+        present_modes: &[vk::PresentModeKHR],
+        requested: PresentMode,
+    ) -> vk::PresentModeKHR {
         // VK_PRESENT_MODE_IMMEDIATE_KHR: Images submitted by your application are transferred to the screen right away, which may result in tearing.
         // VK_PRESENT_MODE_FIFO_KHR: The swap chain is a queue where the display takes an image from the front of the queue when the display is refreshed
         //  and the program inserts rendered images at the back of the queue. If the queue is full then the program has to wait. This is most similar to
@@ -209,11 +482,14 @@ impl SwapChain {
         //  images that are already queued are simply replaced with the newer ones. This mode can be used to render frames as fast as possible while
         //  still avoiding tearing, resulting in fewer latency issues than standard vertical sync. This is commonly known as "triple buffering",
         //  although the existence of three buffers alone does not necessarily mean that the framerate is unlocked.
-        present_modes
-            .iter()
-            .cloned()
-            .find(|&present_mode| present_mode == vk::PresentModeKHR::MAILBOX)
-            .unwrap_or(vk::PresentModeKHR::FIFO)
+        if present_modes.contains(&requested.vk()) {
+            return requested.vk();
+        }
+        log::info!(
+            "{:?} presentation is not supported by the surface, using Fifo",
+            requested
+        );
+        vk::PresentModeKHR::FIFO
     }
 
     fn choose_surface_extent(
@@ -222,7 +498,7 @@ impl SwapChain {
     ) -> vk::Extent2D {
         match capabilities.current_extent.width {
             u32::MAX => {
-                let inner_size = context.window.inner_size();
+                let inner_size = context.window.borrow().as_ref().unwrap().inner_size();
                 vk::Extent2D {
                     width: inner_size.width.clamp(
                         capabilities.min_image_extent.width,
@@ -0,0 +1,528 @@
+use super::{Allocation, VkDeviceContext, GPU};
+use ash::vk;
+use std::collections::VecDeque;
+
+// How an uploaded image is handed to the graphics queue.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum ImageUploadFinish {
+    // every level in SHADER_READ_ONLY_OPTIMAL
+    Sampled,
+    // levels past 0 are blitted on the graphics queue, transfer queues may not blit
+    GenerateMips,
+}
+
+// An image to fill from the staged pixels, all levels start out UNDEFINED.
+pub struct ImageUpload<'a> {
+    pub image: vk::Image,
+    pub width: u32,
+    pub height: u32,
+    pub mip_levels: u32,
+    pub layers: u32,
+    // buffer offsets into the pixels
+    pub regions: &'a [vk::BufferImageCopy],
+    pub finish: ImageUploadFinish,
+}
+
+struct PendingMips {
+    image: vk::Image,
+    width: u32,
+    height: u32,
+    mip_levels: u32,
+    layers: u32,
+}
+
+// Copies recorded since the last flush.
+struct Recording {
+    command_buffer: vk::CommandBuffer,
+    staging: Vec<(vk::Buffer, Allocation)>,
+    // ownership moves to the graphics family when the transfer queue has a family of its own
+    buffer_acquires: Vec<vk::BufferMemoryBarrier<'static>>,
+    image_acquires: Vec<vk::ImageMemoryBarrier<'static>>,
+    mips: Vec<PendingMips>,
+}
+
+enum Completion {
+    // reached by the timeline semaphore once the graphics queue took the batch over
+    Timeline(u64),
+    Fence(vk::Fence),
+}
+
+// One flush, in flight until the graphics queue is through with it.
+struct Batch {
+    completion: Completion,
+    transfer_command_buffer: vk::CommandBuffer,
+    acquire_command_buffer: vk::CommandBuffer,
+    staging: Vec<(vk::Buffer, Allocation)>,
+}
+
+// Collects the staging copies of buffer and texture uploads into one command buffer of the
+// transfer queue (the compute family, the graphics queue when there is none) instead of waiting
+// for the device to go idle after every single one. `flush` submits them before the frame: the
+// transfer submit signals a timeline semaphore, a small graphics submit waits on it, takes over
+// the resources and generates mips, and everything submitted after it sees the data. Staging
+// buffers are freed once the semaphore says the batch is done. Devices without timeline
+// semaphores wait on a fence for the transfer part instead, still once per batch.
+pub struct UploadManager {
+    transfer_queue: vk::Queue,
+    transfer_queue_family: u32,
+    graphic_queue: vk::Queue,
+    graphic_queue_family: u32,
+    transfer_command_pool: vk::CommandPool,
+    acquire_command_pool: vk::CommandPool,
+    timeline: Option<vk::Semaphore>,
+    timeline_value: u64,
+
+    recording: Option<Recording>,
+    in_flight: VecDeque<Batch>,
+}
+
+impl UploadManager {
+    pub fn new(device_context: &VkDeviceContext) -> Self {
+        let graphic_queue = device_context.graphic_queue.unwrap();
+        let graphic_queue_family = device_context.graphic_queue_family.unwrap();
+        let (transfer_queue, transfer_queue_family) = device_context
+            .compute_queue
+            .zip(device_context.compute_queue_family)
+            .unwrap_or((graphic_queue, graphic_queue_family));
+
+        unsafe {
+            let device = &device_context.device;
+            let create_pool = |family: u32| {
+                let create_info = vk::CommandPoolCreateInfo::default()
+                    .flags(vk::CommandPoolCreateFlags::TRANSIENT)
+                    .queue_family_index(family);
+                device
+                    .create_command_pool(&create_info, None)
+                    .expect("failed to create upload command pool!")
+            };
+            let transfer_command_pool = create_pool(transfer_queue_family);
+            let acquire_command_pool = create_pool(graphic_queue_family);
+
+            let timeline = device_context.timeline_semaphore.then(|| {
+                let mut type_info = vk::SemaphoreTypeCreateInfo::default()
+                    .semaphore_type(vk::SemaphoreType::TIMELINE)
+                    .initial_value(0);
+                let create_info = vk::SemaphoreCreateInfo::default().push_next(&mut type_info);
+                device
+                    .create_semaphore(&create_info, None)
+                    .expect("failed to create upload timeline semaphore!")
+            });
+
+            Self {
+                transfer_queue,
+                transfer_queue_family,
+                graphic_queue,
+                graphic_queue_family,
+                transfer_command_pool,
+                acquire_command_pool,
+                timeline,
+                timeline_value: 0,
+                recording: None,
+                in_flight: VecDeque::new(),
+            }
+        }
+    }
+
+    fn is_shared_family(&self) -> bool {
+        self.transfer_queue_family == self.graphic_queue_family
+    }
+
+    // The data is staged right away, the copy runs with the next flush. `buffer` needs
+    // TRANSFER_DST usage and must not be read before then.
+    pub unsafe fn upload_buffer(
+        &mut self,
+        device_context: &VkDeviceContext,
+        data: &[u8],
+        buffer: vk::Buffer,
+    ) {
+        let size = data.len() as vk::DeviceSize;
+        let shared_family = self.is_shared_family();
+        let (transfer_queue_family, graphic_queue_family) =
+            (self.transfer_queue_family, self.graphic_queue_family);
+        let recording = self.begin(device_context);
+        let staging_buffer = Self::stage(device_context, recording, data);
+
+        let device = &device_context.device;
+        device.cmd_copy_buffer(
+            recording.command_buffer,
+            staging_buffer,
+            buffer,
+            &[vk::BufferCopy {
+                src_offset: 0,
+                dst_offset: 0,
+                size,
+            }],
+        );
+
+        // the semaphore makes the copy visible within the same family
+        if !shared_family {
+            let release = vk::BufferMemoryBarrier::default()
+                .buffer(buffer)
+                .offset(0)
+                .size(vk::WHOLE_SIZE)
+                .src_access_mask(vk::AccessFlags::TRANSFER_WRITE)
+                .dst_access_mask(vk::AccessFlags::empty())
+                .src_queue_family_index(transfer_queue_family)
+                .dst_queue_family_index(graphic_queue_family);
+            device.cmd_pipeline_barrier(
+                recording.command_buffer,
+                vk::PipelineStageFlags::TRANSFER,
+                vk::PipelineStageFlags::BOTTOM_OF_PIPE,
+                vk::DependencyFlags::empty(),
+                &[],
+                &[release],
+                &[],
+            );
+            recording.buffer_acquires.push(
+                release
+                    .src_access_mask(vk::AccessFlags::empty())
+                    .dst_access_mask(vk::AccessFlags::MEMORY_READ),
+            );
+        }
+    }
+
+    // Copies to `offset` of a buffer from `VkDeviceContext::create_shared_buffer`, no ownership
+    // changes hands. The range must not be read before the next flush, the rest of the buffer
+    // may be drawn meanwhile.
+    pub unsafe fn upload_buffer_region(
+        &mut self,
+        device_context: &VkDeviceContext,
+        data: &[u8],
+        buffer: vk::Buffer,
+        offset: vk::DeviceSize,
+    ) {
+        let recording = self.begin(device_context);
+        let staging_buffer = Self::stage(device_context, recording, data);
+        device_context.device.cmd_copy_buffer(
+            recording.command_buffer,
+            staging_buffer,
+            buffer,
+            &[vk::BufferCopy {
+                src_offset: 0,
+                dst_offset: offset,
+                size: data.len() as vk::DeviceSize,
+            }],
+        );
+    }
+
+    // Same as `upload_buffer`, the image is sampled or mipmapped no earlier than the next flush.
+    pub unsafe fn upload_image(
+        &mut self,
+        device_context: &VkDeviceContext,
+        pixels: &[u8],
+        upload: &ImageUpload,
+    ) {
+        let shared_family = self.is_shared_family();
+        let (transfer_queue_family, graphic_queue_family) =
+            (self.transfer_queue_family, self.graphic_queue_family);
+        let recording = self.begin(device_context);
+        let staging_buffer = Self::stage(device_context, recording, pixels);
+
+        let device = &device_context.device;
+        let to_transfer = vk::ImageMemoryBarrier::default()
+            .image(upload.image)
+            .old_layout(vk::ImageLayout::UNDEFINED)
+            .new_layout(vk::ImageLayout::TRANSFER_DST_OPTIMAL)
+            .src_access_mask(vk::AccessFlags::NONE)
+            .dst_access_mask(vk::AccessFlags::TRANSFER_WRITE)
+            .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+            .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+            .subresource_range(vk::ImageSubresourceRange {
+                aspect_mask: vk::ImageAspectFlags::COLOR,
+                base_mip_level: 0,
+                level_count: upload.mip_levels,
+                base_array_layer: 0,
+                layer_count: upload.layers,
+            });
+        device.cmd_pipeline_barrier(
+            recording.command_buffer,
+            vk::PipelineStageFlags::TOP_OF_PIPE,
+            vk::PipelineStageFlags::TRANSFER,
+            vk::DependencyFlags::empty(),
+            &[],
+            &[],
+            &[to_transfer],
+        );
+        device.cmd_copy_buffer_to_image(
+            recording.command_buffer,
+            staging_buffer,
+            upload.image,
+            vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+            upload.regions,
+        );
+
+        // mips are blitted from TRANSFER_DST_OPTIMAL
+        let final_layout = match upload.finish {
+            ImageUploadFinish::Sampled => vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+            ImageUploadFinish::GenerateMips => vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+        };
+        let finish = to_transfer
+            .old_layout(vk::ImageLayout::TRANSFER_DST_OPTIMAL)
+            .new_layout(final_layout)
+            .src_access_mask(vk::AccessFlags::TRANSFER_WRITE);
+        if shared_family {
+            // the transfer queue is the graphics queue, a plain transition does
+            if upload.finish == ImageUploadFinish::Sampled {
+                device.cmd_pipeline_barrier(
+                    recording.command_buffer,
+                    vk::PipelineStageFlags::TRANSFER,
+                    vk::PipelineStageFlags::VERTEX_SHADER
+                        | vk::PipelineStageFlags::FRAGMENT_SHADER
+                        | vk::PipelineStageFlags::COMPUTE_SHADER,
+                    vk::DependencyFlags::empty(),
+                    &[],
+                    &[],
+                    &[finish.dst_access_mask(vk::AccessFlags::SHADER_READ)],
+                );
+            }
+        } else {
+            // released here with the layout transition, acquired with the same one on flush
+            let release = finish
+                .dst_access_mask(vk::AccessFlags::empty())
+                .src_queue_family_index(transfer_queue_family)
+                .dst_queue_family_index(graphic_queue_family);
+            device.cmd_pipeline_barrier(
+                recording.command_buffer,
+                vk::PipelineStageFlags::TRANSFER,
+                vk::PipelineStageFlags::BOTTOM_OF_PIPE,
+                vk::DependencyFlags::empty(),
+                &[],
+                &[],
+                &[release],
+            );
+            let dst_access_mask = match upload.finish {
+                ImageUploadFinish::Sampled => vk::AccessFlags::SHADER_READ,
+                ImageUploadFinish::GenerateMips => {
+                    vk::AccessFlags::TRANSFER_READ | vk::AccessFlags::TRANSFER_WRITE
+                }
+            };
+            recording.image_acquires.push(
+                release
+                    .src_access_mask(vk::AccessFlags::empty())
+                    .dst_access_mask(dst_access_mask),
+            );
+        }
+
+        if upload.finish == ImageUploadFinish::GenerateMips {
+            recording.mips.push(PendingMips {
+                image: upload.image,
+                width: upload.width,
+                height: upload.height,
+                mip_levels: upload.mip_levels,
+                layers: upload.layers,
+            });
+        }
+    }
+
+    // Submits what was recorded since the last flush, commands submitted to the graphics queue
+    // afterwards see the uploads. Frees the staging buffers of batches that are done.
+    pub fn flush(&mut self, device_context: &VkDeviceContext) {
+        unsafe {
+            self.reclaim(device_context);
+            let Some(recording) = self.recording.take() else {
+                return;
+            };
+
+            let device = &device_context.device;
+            device
+                .end_command_buffer(recording.command_buffer)
+                .expect("failed to end upload command buffer!");
+
+            let acquire_command_buffer = self.allocate(device, self.acquire_command_pool);
+            if !recording.buffer_acquires.is_empty() || !recording.image_acquires.is_empty() {
+                device.cmd_pipeline_barrier(
+                    acquire_command_buffer,
+                    vk::PipelineStageFlags::TOP_OF_PIPE,
+                    vk::PipelineStageFlags::ALL_COMMANDS,
+                    vk::DependencyFlags::empty(),
+                    &[],
+                    &recording.buffer_acquires,
+                    &recording.image_acquires,
+                );
+            }
+            for mips in &recording.mips {
+                GPU::record_mipmaps(
+                    device,
+                    acquire_command_buffer,
+                    mips.image,
+                    mips.width,
+                    mips.height,
+                    mips.mip_levels,
+                    mips.layers,
+                );
+            }
+            device
+                .end_command_buffer(acquire_command_buffer)
+                .expect("failed to end upload acquire command buffer!");
+
+            let transfer_command_buffers = [recording.command_buffer];
+            let acquire_command_buffers = [acquire_command_buffer];
+            let _queue = device_context.queue_lock.lock().unwrap();
+            let completion = match self.timeline {
+                Some(timeline) => {
+                    // the transfer signals n, the graphics queue waits on it and signals n + 1
+                    let transferred = self.timeline_value + 1;
+                    let acquired = self.timeline_value + 2;
+                    self.timeline_value = acquired;
+                    let semaphores = [timeline];
+
+                    let transferred_values = [transferred];
+                    let mut transfer_timeline = vk::TimelineSemaphoreSubmitInfo::default()
+                        .signal_semaphore_values(&transferred_values);
+                    let transfer_submit = vk::SubmitInfo::default()
+                        .command_buffers(&transfer_command_buffers)
+                        .signal_semaphores(&semaphores)
+                        .push_next(&mut transfer_timeline);
+                    device
+                        .queue_submit(self.transfer_queue, &[transfer_submit], vk::Fence::null())
+                        .expect("failed to submit upload command buffer!");
+
+                    let acquired_values = [acquired];
+                    let mut acquire_timeline = vk::TimelineSemaphoreSubmitInfo::default()
+                        .wait_semaphore_values(&transferred_values)
+                        .signal_semaphore_values(&acquired_values);
+                    let acquire_submit = vk::SubmitInfo::default()
+                        .command_buffers(&acquire_command_buffers)
+                        .wait_semaphores(&semaphores)
+                        .wait_dst_stage_mask(&[vk::PipelineStageFlags::ALL_COMMANDS])
+                        .signal_semaphores(&semaphores)
+                        .push_next(&mut acquire_timeline);
+                    device
+                        .queue_submit(self.graphic_queue, &[acquire_submit], vk::Fence::null())
+                        .expect("failed to submit upload acquire command buffer!");
+
+                    Completion::Timeline(acquired)
+                }
+                None => {
+                    let fence_info = vk::FenceCreateInfo::default();
+                    let transferred = device
+                        .create_fence(&fence_info, None)
+                        .expect("failed to create upload fence!");
+                    let transfer_submit =
+                        vk::SubmitInfo::default().command_buffers(&transfer_command_buffers);
+                    device
+                        .queue_submit(self.transfer_queue, &[transfer_submit], transferred)
+                        .expect("failed to submit upload command buffer!");
+                    device
+                        .wait_for_fences(&[transferred], true, u64::MAX)
+                        .expect("failed to wait upload fence!");
+                    device.destroy_fence(transferred, None);
+
+                    let acquired = device
+                        .create_fence(&fence_info, None)
+                        .expect("failed to create upload fence!");
+                    let acquire_submit =
+                        vk::SubmitInfo::default().command_buffers(&acquire_command_buffers);
+                    device
+                        .queue_submit(self.graphic_queue, &[acquire_submit], acquired)
+                        .expect("failed to submit upload acquire command buffer!");
+                    Completion::Fence(acquired)
+                }
+            };
+
+            self.in_flight.push_back(Batch {
+                completion,
+                transfer_command_buffer: recording.command_buffer,
+                acquire_command_buffer,
+                staging: recording.staging,
+            });
+        }
+    }
+
+    // After the device went idle.
+    pub unsafe fn destroy(&mut self, device_context: &VkDeviceContext) {
+        let device = &device_context.device;
+        if let Some(recording) = self.recording.take() {
+            for (buffer, allocation) in recording.staging {
+                device_context.destroy_buffer(buffer, allocation);
+            }
+        }
+        for batch in std::mem::take(&mut self.in_flight) {
+            self.free_batch(device_context, batch);
+        }
+        device.destroy_command_pool(self.transfer_command_pool, None);
+        device.destroy_command_pool(self.acquire_command_pool, None);
+        if let Some(timeline) = self.timeline.take() {
+            device.destroy_semaphore(timeline, None);
+        }
+    }
+
+    unsafe fn begin(&mut self, device_context: &VkDeviceContext) -> &mut Recording {
+        if self.recording.is_none() {
+            let command_buffer = self.allocate(&device_context.device, self.transfer_command_pool);
+            self.recording = Some(Recording {
+                command_buffer,
+                staging: vec![],
+                buffer_acquires: vec![],
+                image_acquires: vec![],
+                mips: vec![],
+            });
+        }
+        self.recording.as_mut().unwrap()
+    }
+
+    unsafe fn allocate(&self, device: &ash::Device, pool: vk::CommandPool) -> vk::CommandBuffer {
+        let allocate_info = vk::CommandBufferAllocateInfo::default()
+            .command_pool(pool)
+            .level(vk::CommandBufferLevel::PRIMARY)
+            .command_buffer_count(1);
+        let command_buffer = device
+            .allocate_command_buffers(&allocate_info)
+            .expect("failed to allocate upload command buffer!")[0];
+        let begin_info = vk::CommandBufferBeginInfo::default()
+            .flags(vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT);
+        device
+            .begin_command_buffer(command_buffer, &begin_info)
+            .expect("failed to begin upload command buffer!");
+        command_buffer
+    }
+
+    unsafe fn stage(
+        device_context: &VkDeviceContext,
+        recording: &mut Recording,
+        data: &[u8],
+    ) -> vk::Buffer {
+        let (buffer, allocation) = device_context.create_buffer(
+            data.len() as vk::DeviceSize,
+            vk::BufferUsageFlags::TRANSFER_SRC,
+            vk::MemoryPropertyFlags::HOST_COHERENT | vk::MemoryPropertyFlags::HOST_VISIBLE,
+        );
+        std::ptr::copy_nonoverlapping(data.as_ptr(), allocation.mapped as *mut u8, data.len());
+        recording.staging.push((buffer, allocation));
+        buffer
+    }
+
+    unsafe fn reclaim(&mut self, device_context: &VkDeviceContext) {
+        let device = &device_context.device;
+        let reached = match self.timeline {
+            Some(timeline) => device
+                .get_semaphore_counter_value(timeline)
+                .expect("failed to get upload timeline value!"),
+            None => 0,
+        };
+        // batches complete in order, both queues run them in submission order
+        while let Some(batch) = self.in_flight.front() {
+            let done = match batch.completion {
+                Completion::Timeline(value) => value <= reached,
+                Completion::Fence(fence) => device.get_fence_status(fence).unwrap_or(false),
+            };
+            if !done {
+                break;
+            }
+            let batch = self.in_flight.pop_front().unwrap();
+            self.free_batch(device_context, batch);
+        }
+    }
+
+    unsafe fn free_batch(&self, device_context: &VkDeviceContext, batch: Batch) {
+        let device = &device_context.device;
+        if let Completion::Fence(fence) = batch.completion {
+            device.destroy_fence(fence, None);
+        }
+        device.free_command_buffers(self.transfer_command_pool, &[batch.transfer_command_buffer]);
+        device.free_command_buffers(self.acquire_command_pool, &[batch.acquire_command_buffer]);
+        for (buffer, allocation) in batch.staging {
+            device_context.destroy_buffer(buffer, allocation);
+        }
+    }
+}
@@ -1,62 +1,122 @@
 use ash::{vk, Entry};
 use raw_window_handle::{HasDisplayHandle, HasWindowHandle};
 use std::borrow::Cow;
+use std::cell::{Cell, RefCell};
 use std::ffi::CStr;
+use std::fmt::Write;
 use std::os;
 use std::rc::Rc;
 use winit::window::Window;
 
-#[cfg(all(debug_assertions))]
+// Android devices don't ship the validation layers, they have to be packaged into the APK manually.
+#[cfg(all(debug_assertions, not(target_os = "android")))]
 const ENABLE_VALIDATION_LAYERS: bool = true;
-#[cfg(not(debug_assertions))]
+#[cfg(any(not(debug_assertions), target_os = "android"))]
 const ENABLE_VALIDATION_LAYERS: bool = false;
 
-const VALIDATION_LAYERS: &[&CStr] =
-    &[unsafe { CStr::from_bytes_with_nul_unchecked(b"VK_LAYER_KHRONOS_validation\0") }];
+const VALIDATION_LAYERS: &[&CStr] = &[c"VK_LAYER_KHRONOS_validation"];
 
 pub struct VkContext {
-    pub window: Rc<Window>,
+    // replaced on resume, Android hands out a new native window every time the app comes back.
+    // None when rendering headless, see `new_headless`
+    pub window: RefCell<Option<Rc<Window>>>,
 
     pub entry: Entry,
     pub instance: ash::Instance,
+    // VK_EXT_debug_utils is enabled, with the validation layers or when a graphics debugger like
+    // RenderDoc offers it, see `DebugNames`
+    pub debug_utils: bool,
     pub debug_utils_fn: Option<ash::ext::debug_utils::Instance>,
     pub debug_utils_messenger: Option<vk::DebugUtilsMessengerEXT>,
     pub surface_fn: Option<ash::khr::surface::Instance>,
-    pub surface: Option<vk::SurfaceKHR>,
+    pub surface: Cell<Option<vk::SurfaceKHR>>,
 }
 
 impl VkContext {
     pub fn new(window: Rc<Window>) -> Self {
         let entry = Entry::linked();
-        let instance = Self::create_instance(&entry, &window);
+        let debug_utils = Self::has_debug_utils(&entry);
+        let instance = Self::create_instance(&entry, Some(&window), debug_utils);
         let (debug_utils_fn, debug_utils_messenger) = Self::setup_debug_utils(&entry, &instance);
-        let (surface_fn, surface) = Self::create_surface(&entry, &instance, &window);
+        let surface_fn = ash::khr::surface::Instance::new(&entry, &instance);
+        let surface = Self::create_surface(&entry, &instance, &window);
 
         Self {
-            window,
+            window: RefCell::new(Some(window)),
             entry,
             instance,
+            debug_utils,
             debug_utils_fn,
             debug_utils_messenger,
             surface_fn: Some(surface_fn),
-            surface: Some(surface),
+            surface: Cell::new(Some(surface)),
         }
     }
 
-    fn create_instance(entry: &Entry, window: &Window) -> ash::Instance {
-        if ENABLE_VALIDATION_LAYERS && !Self::check_validation_layers_support(&entry) {
+    // Without a window or a surface, the frames go to offscreen images instead of a swap chain.
+    pub fn new_headless() -> Self {
+        let entry = Entry::linked();
+        let debug_utils = Self::has_debug_utils(&entry);
+        let instance = Self::create_instance(&entry, None, debug_utils);
+        let (debug_utils_fn, debug_utils_messenger) = Self::setup_debug_utils(&entry, &instance);
+        let surface_fn = ash::khr::surface::Instance::new(&entry, &instance);
+
+        Self {
+            window: RefCell::new(None),
+            entry,
+            instance,
+            debug_utils,
+            debug_utils_fn,
+            debug_utils_messenger,
+            surface_fn: Some(surface_fn),
+            surface: Cell::new(None),
+        }
+    }
+
+    pub fn is_headless(&self) -> bool {
+        self.window.borrow().is_none()
+    }
+
+    pub fn set_window(&self, window: Rc<Window>) {
+        self.destroy_surface();
+
+        let surface = Self::create_surface(&self.entry, &self.instance, &window);
+        self.surface.set(Some(surface));
+        *self.window.borrow_mut() = Some(window);
+    }
+
+    // The swap chain created from the surface has to be destroyed first.
+    pub fn destroy_surface(&self) {
+        if let Some(surface) = self.surface.take() {
+            unsafe {
+                self.surface_fn
+                    .as_ref()
+                    .unwrap()
+                    .destroy_surface(surface, None);
+            }
+        }
+    }
+
+    fn has_debug_utils(entry: &Entry) -> bool {
+        ENABLE_VALIDATION_LAYERS
+            || Self::check_instance_extension_support(entry, vk::EXT_DEBUG_UTILS_NAME)
+    }
+
+    fn create_instance(entry: &Entry, window: Option<&Window>, debug_utils: bool) -> ash::Instance {
+        if ENABLE_VALIDATION_LAYERS && !Self::check_validation_layers_support(entry) {
             panic!("Validation layers requested, but not available!")
         }
 
         unsafe {
-            let app_name = CStr::from_bytes_with_nul_unchecked(b"Mirage\0");
+            let app_name = c"Mirage";
 
             let app_info = vk::ApplicationInfo::default()
                 .application_name(app_name)
                 .application_version(0)
                 .engine_name(app_name)
                 .engine_version(0)
-                .api_version(vk::make_api_version(0, 1, 0, 0));
+                // 1.2 for timeline semaphores, older devices still run, see `UploadManager`
+                .api_version(vk::make_api_version(0, 1, 2, 0));
 
             let layer_names = VALIDATION_LAYERS
                 .iter()
@@ -64,35 +124,48 @@ impl VkContext {
                 .map(|layer| layer.as_ptr())
                 .collect::<Vec<_>>();
 
-            let mut extension_names =
-                ash_window::enumerate_required_extensions(window.display_handle().unwrap().into())
-                    .unwrap()
-                    .to_vec();
+            // VK_KHR_surface plus the platform one, e.g. VK_KHR_android_surface for the NDK window.
+            // Headless keeps VK_KHR_surface alone, VK_KHR_swapchain needs it and the passes still
+            // leave their images in PRESENT_SRC_KHR.
+            let mut extension_names = match window {
+                Some(window) => ash_window::enumerate_required_extensions(
+                    window.display_handle().unwrap().into(),
+                )
+                .unwrap()
+                .to_vec(),
+                None => vec![vk::KHR_SURFACE_NAME.as_ptr()],
+            };
 
-            #[cfg(any(target_os = "macos", target_os = "ios"))]
-            {
+            // Since loader 1.3.216 portability drivers like MoltenVK are only enumerated when asked for.
+            // Enabled whenever the loader offers it, not only on Apple targets, so MoltenVK installs elsewhere work too.
+            let portability_enumeration =
+                Self::check_instance_extension_support(entry, vk::KHR_PORTABILITY_ENUMERATION_NAME);
+            if portability_enumeration {
                 extension_names.push(vk::KHR_PORTABILITY_ENUMERATION_NAME.as_ptr());
                 // required by *device* extension VK_KHR_portability_subset
                 extension_names.push(vk::KHR_GET_PHYSICAL_DEVICE_PROPERTIES2_NAME.as_ptr());
             }
 
-            if ENABLE_VALIDATION_LAYERS {
+            if debug_utils {
                 extension_names.push(vk::EXT_DEBUG_UTILS_NAME.as_ptr());
             }
 
-            let create_flags = if cfg!(any(target_os = "macos", target_os = "ios")) {
+            let create_flags = if portability_enumeration {
                 vk::InstanceCreateFlags::ENUMERATE_PORTABILITY_KHR
             } else {
                 vk::InstanceCreateFlags::default()
             };
 
+            // messages of vkCreateInstance and vkDestroyInstance, the messenger covers the rest
             let mut debug_info = Self::build_debug_utils_messenger_create_info();
-            let create_info = vk::InstanceCreateInfo::default()
+            let mut create_info = vk::InstanceCreateInfo::default()
                 .application_info(&app_info)
                 .enabled_layer_names(&layer_names)
                 .enabled_extension_names(&extension_names)
-                .flags(create_flags)
-                .push_next(&mut debug_info);
+                .flags(create_flags);
+            if ENABLE_VALIDATION_LAYERS {
+                create_info = create_info.push_next(&mut debug_info);
+            }
 
             entry
                 .create_instance(&create_info, None)
@@ -111,7 +184,7 @@ impl VkContext {
         }
 
         unsafe {
-            let debug_utils_fn = ash::ext::debug_utils::Instance::new(&entry, &instance);
+            let debug_utils_fn = ash::ext::debug_utils::Instance::new(entry, instance);
             let debug_info = Self::build_debug_utils_messenger_create_info();
             let debug_utils_messenger = debug_utils_fn
                 .create_debug_utils_messenger(&debug_info, None)
@@ -121,25 +194,28 @@ impl VkContext {
         }
     }
 
-    fn create_surface(
-        entry: &Entry,
-        instance: &ash::Instance,
-        window: &Window,
-    ) -> (ash::khr::surface::Instance, vk::SurfaceKHR) {
+    fn create_surface(entry: &Entry, instance: &ash::Instance, window: &Window) -> vk::SurfaceKHR {
         unsafe {
-            let surface_fn = ash::khr::surface::Instance::new(entry, instance);
-            let surface = ash_window::create_surface(
+            ash_window::create_surface(
                 entry,
                 instance,
                 window.display_handle().unwrap().into(),
                 window.window_handle().unwrap().into(),
                 None,
             )
-            .unwrap();
-
-            (surface_fn, surface)
+            .expect("failed to create surface!")
         }
     }
+    fn check_instance_extension_support(entry: &Entry, extension: &CStr) -> bool {
+        unsafe {
+            entry
+                .enumerate_instance_extension_properties(None)
+                .unwrap()
+                .iter()
+                .any(|properties| CStr::from_ptr(properties.extension_name.as_ptr()) == extension)
+        }
+    }
+
     fn check_validation_layers_support(entry: &Entry) -> bool {
         unsafe {
             let supported_layers = entry
@@ -160,7 +236,8 @@ impl VkContext {
             .message_severity(
                 vk::DebugUtilsMessageSeverityFlagsEXT::ERROR
                     | vk::DebugUtilsMessageSeverityFlagsEXT::WARNING
-                    | vk::DebugUtilsMessageSeverityFlagsEXT::INFO,
+                    | vk::DebugUtilsMessageSeverityFlagsEXT::INFO
+                    | vk::DebugUtilsMessageSeverityFlagsEXT::VERBOSE,
             )
             .message_type(
                 vk::DebugUtilsMessageTypeFlagsEXT::GENERAL
@@ -171,6 +248,8 @@ impl VkContext {
     }
 }
 
+// Validation messages go to the `log` crate under the "vulkan" target, errors as errors and so
+// on, verbose ones as traces. The objects a message is about are listed with their `DebugNames`.
 unsafe extern "system" fn vulkan_debug_callback(
     message_severity: vk::DebugUtilsMessageSeverityFlagsEXT,
     message_types: vk::DebugUtilsMessageTypeFlagsEXT,
@@ -192,7 +271,39 @@ unsafe extern "system" fn vulkan_debug_callback(
         CStr::from_ptr(callback_data.p_message).to_string_lossy()
     };
 
-    println!("[{message_severity:?}:{message_types:?}] {message_id_name} ({message_id_number}):\n{message}");
+    let objects = match callback_data.p_objects.is_null() {
+        true => &[][..],
+        false => {
+            std::slice::from_raw_parts(callback_data.p_objects, callback_data.object_count as usize)
+        }
+    };
+    let mut object_names = String::new();
+    for object in objects {
+        let name = match object.p_object_name.is_null() {
+            true => Cow::from("unnamed"),
+            false => CStr::from_ptr(object.p_object_name).to_string_lossy(),
+        };
+        let _ = write!(
+            object_names,
+            "\n  {:?} {:#x} \"{}\"",
+            object.object_type, object.object_handle, name
+        );
+    }
+
+    let level = if message_severity.contains(vk::DebugUtilsMessageSeverityFlagsEXT::ERROR) {
+        log::Level::Error
+    } else if message_severity.contains(vk::DebugUtilsMessageSeverityFlagsEXT::WARNING) {
+        log::Level::Warn
+    } else if message_severity.contains(vk::DebugUtilsMessageSeverityFlagsEXT::INFO) {
+        log::Level::Info
+    } else {
+        log::Level::Trace
+    };
+    log::log!(
+        target: "vulkan",
+        level,
+        "[{message_types:?}] {message_id_name} ({message_id_number}):\n{message}{object_names}"
+    );
 
     vk::FALSE
 }
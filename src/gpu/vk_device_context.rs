@@ -1,60 +1,97 @@
 use super::*;
 use ash::vk;
-use std::collections::{BTreeMap, HashSet};
+use std::collections::HashSet;
 use std::ffi::CStr;
+use std::sync::{Arc, Mutex};
 
 const DEVICE_EXTENSIONS: &[&CStr] = &[
-    // The Vulkan spec states: If the VK_KHR_portability_subset extension is included in pProperties
-    // of vkEnumerateDeviceExtensionProperties, ppEnabledExtensionNames must include "VK_KHR_portability_subset"
-    #[cfg(any(target_os = "macos", target_os = "ios"))]
-    vk::KHR_PORTABILITY_SUBSET_NAME,
     vk::KHR_SWAPCHAIN_NAME,
     // vk::ExtShaderAtomicFloatFn::name()
 ];
 
 pub struct VkDeviceContext {
+    // every adapter of the instance, the device was created on `adapter`
+    pub adapters: Vec<AdapterInfo>,
+    pub adapter: AdapterInfo,
     pub physical_device: vk::PhysicalDevice,
     pub physical_device_properties: vk::PhysicalDeviceProperties,
+    pub physical_device_features: vk::PhysicalDeviceFeatures,
     pub physical_device_memory_properties: vk::PhysicalDeviceMemoryProperties,
+    // Some when the device is a portability implementation (MoltenVK) that doesn't cover all of Vulkan
+    pub portability_subset: Option<vk::PhysicalDevicePortabilitySubsetFeaturesKHR<'static>>,
     pub graphic_queue_family: Option<u32>,
     pub present_queue_family: Option<u32>,
     pub compute_queue_family: Option<u32>,
     pub msaa_samples: vk::SampleCountFlags,
+    // core since 1.2, uploads fall back to fences without them
+    pub timeline_semaphore: bool,
+    // Some when VK_KHR_push_descriptor is enabled, material sets are then pushed with each draw
+    // instead of rewritten in descriptor sets, see `GPUPipeline::push_descriptors`
+    pub push_descriptor: Option<ash::khr::push_descriptor::Device>,
 
     pub device: ash::Device,
     pub graphic_queue: Option<vk::Queue>,
     pub present_queue: Option<vk::Queue>,
     pub compute_queue: Option<vk::Queue>,
+    // held while submitting or presenting to any of the queues, the present thread does so too
+    pub queue_lock: Arc<Mutex<()>>,
+
+    pub allocator: Allocator,
 }
 
 impl VkDeviceContext {
-    pub fn new(context: &VkContext) -> Self {
+    pub fn new(context: &VkContext, selection: &AdapterSelection) -> Self {
         unsafe {
-            let physical_device = Self::pick_physical_device(context);
+            let (adapters, adapter, physical_device) =
+                Self::pick_physical_device(context, selection);
             let physical_device_properties = context
                 .instance
                 .get_physical_device_properties(physical_device);
             let physical_device_memory_properties = context
                 .instance
                 .get_physical_device_memory_properties(physical_device);
+            let physical_device_features = context
+                .instance
+                .get_physical_device_features(physical_device);
 
+            let portability_subset = Self::query_portability_subset(context, physical_device);
             let msaa_samples = Self::get_max_usable_sample_count(&physical_device_properties);
+            let timeline_semaphore = Self::query_timeline_semaphore(
+                context,
+                physical_device,
+                &physical_device_properties,
+            );
+            let push_descriptor_supported = Self::is_extension_supported(
+                context,
+                physical_device,
+                vk::KHR_PUSH_DESCRIPTOR_NAME,
+            );
 
-            let (graphic_queue_family, present_queue_family, compute_queue_family) =
-                Self::find_queue_families(&context, physical_device);
+            let queue_families = Self::find_queue_families(context, physical_device);
+            let (graphic_queue_family, present_queue_family, compute_queue_family) = queue_families;
             let (device, graphic_queue, present_queue, compute_queue) = Self::create_logical_device(
-                &context,
+                context,
                 physical_device,
-                graphic_queue_family,
-                present_queue_family,
-                compute_queue_family,
+                &physical_device_features,
+                portability_subset,
+                timeline_semaphore,
+                push_descriptor_supported,
+                queue_families,
             );
+            let push_descriptor = push_descriptor_supported
+                .then(|| ash::khr::push_descriptor::Device::new(&context.instance, &device));
+
+            let allocator = Allocator::new(physical_device_memory_properties);
 
             Self {
+                adapters,
+                adapter,
                 physical_device,
                 device,
                 physical_device_properties,
+                physical_device_features,
                 physical_device_memory_properties,
+                portability_subset,
 
                 graphic_queue_family,
                 present_queue_family,
@@ -62,8 +99,13 @@ impl VkDeviceContext {
                 graphic_queue,
                 present_queue,
                 compute_queue,
+                queue_lock: Arc::new(Mutex::new(())),
 
                 msaa_samples,
+                timeline_semaphore,
+                push_descriptor,
+
+                allocator,
             }
         }
     }
@@ -73,7 +115,7 @@ impl VkDeviceContext {
         size: vk::DeviceSize,
         usage: vk::BufferUsageFlags,
         memory_properties: vk::MemoryPropertyFlags,
-    ) -> (vk::Buffer, vk::DeviceMemory, vk::DeviceSize) {
+    ) -> (vk::Buffer, Allocation) {
         let create_info = vk::BufferCreateInfo::default()
             // The flags parameter is used to configure sparse buffer memory,
             // which is not relevant right now. We'll leave it at the default value of 0.
@@ -82,31 +124,67 @@ impl VkDeviceContext {
             .usage(usage)
             .sharing_mode(vk::SharingMode::EXCLUSIVE);
 
+        self.create_buffer_with_info(&create_info, memory_properties)
+    }
+
+    // Concurrently owned by the graphics and the upload queue families when those differ, for
+    // buffers uploaded to in parts while frames in flight read the rest, without the ownership
+    // transfers of `UploadManager::upload_buffer`.
+    pub unsafe fn create_shared_buffer(
+        &self,
+        size: vk::DeviceSize,
+        usage: vk::BufferUsageFlags,
+        memory_properties: vk::MemoryPropertyFlags,
+    ) -> (vk::Buffer, Allocation) {
+        let graphic_queue_family = self.graphic_queue_family.unwrap();
+        let queue_families = [
+            graphic_queue_family,
+            self.compute_queue_family.unwrap_or(graphic_queue_family),
+        ];
+        let create_info = vk::BufferCreateInfo::default().size(size).usage(usage);
+        let create_info = if queue_families[0] == queue_families[1] {
+            create_info.sharing_mode(vk::SharingMode::EXCLUSIVE)
+        } else {
+            create_info
+                .sharing_mode(vk::SharingMode::CONCURRENT)
+                .queue_family_indices(&queue_families)
+        };
+
+        self.create_buffer_with_info(&create_info, memory_properties)
+    }
+
+    unsafe fn create_buffer_with_info(
+        &self,
+        create_info: &vk::BufferCreateInfo,
+        memory_properties: vk::MemoryPropertyFlags,
+    ) -> (vk::Buffer, Allocation) {
         let buffer = self
             .device
-            .create_buffer(&create_info, None)
+            .create_buffer(create_info, None)
             .expect("failed to create buffer!");
 
         let requirements = self.device.get_buffer_memory_requirements(buffer);
-        let allocate_info = vk::MemoryAllocateInfo::default()
-            .allocation_size(requirements.size)
-            .memory_type_index(
-                self.find_memory_type_index(requirements.memory_type_bits, memory_properties),
-            );
-
-        let buffer_memory = self
-            .device
-            .allocate_memory(&allocate_info, None)
-            .expect("failed to allocate memory!");
+        let allocation = self.allocator.allocate(
+            &self.device,
+            requirements,
+            self.find_memory_type_index(requirements.memory_type_bits, memory_properties),
+            true,
+        );
 
         // If the offset is non-zero, then it is required to be divisible by memRequirements.alignment.
         self.device
-            .bind_buffer_memory(buffer, buffer_memory, 0)
+            .bind_buffer_memory(buffer, allocation.memory, allocation.offset)
             .expect("failed to bind buffer memory!");
 
-        (buffer, buffer_memory, requirements.size)
+        (buffer, allocation)
     }
 
+    pub unsafe fn destroy_buffer(&self, buffer: vk::Buffer, allocation: Allocation) {
+        self.device.destroy_buffer(buffer, None);
+        self.allocator.free(&self.device, allocation);
+    }
+
+    #[allow(clippy::too_many_arguments)]
     pub unsafe fn create_image(
         &self,
         width: u32,
@@ -117,7 +195,36 @@ impl VkDeviceContext {
         tiling: vk::ImageTiling,
         usage: vk::ImageUsageFlags,
         memory_properties: vk::MemoryPropertyFlags,
-    ) -> (vk::Image, vk::DeviceMemory) {
+    ) -> (vk::Image, Allocation) {
+        self.create_layered_image(
+            width,
+            height,
+            mip_levels,
+            1,
+            vk::ImageCreateFlags::empty(),
+            samples,
+            format,
+            tiling,
+            usage,
+            memory_properties,
+        )
+    }
+
+    // Array layers of the same size, e.g. the 6 faces of a cube map with CUBE_COMPATIBLE.
+    #[allow(clippy::too_many_arguments)]
+    pub unsafe fn create_layered_image(
+        &self,
+        width: u32,
+        height: u32,
+        mip_levels: u32,
+        layers: u32,
+        flags: vk::ImageCreateFlags,
+        samples: vk::SampleCountFlags,
+        format: vk::Format,
+        tiling: vk::ImageTiling,
+        usage: vk::ImageUsageFlags,
+        memory_properties: vk::MemoryPropertyFlags,
+    ) -> (vk::Image, Allocation) {
         // https://www.reddit.com/r/vulkan/comments/48cvzq/image_layouts/
         // Image tiling is the addressing layout of texels within an image. This is currently opaque, and it is not defined when you access it using the CPU.
         // The reason GPUs like image tiling to be "OPTIMAL" is for texel filtering. Consider a simple linear filter, the resulting value will have four texels contributing from a 2x2 quad.
@@ -140,7 +247,7 @@ impl VkDeviceContext {
             })
             .format(format)
             .mip_levels(mip_levels)
-            .array_layers(1)
+            .array_layers(layers)
             .sharing_mode(vk::SharingMode::EXCLUSIVE)
             // .queue_family_indices()
             // VK_IMAGE_TILING_LINEAR: Texels are laid out in row-major order like our pixels array
@@ -151,10 +258,10 @@ impl VkDeviceContext {
             //      One example, however, would be if you wanted to use an image as a staging image in combination with the VK_IMAGE_TILING_LINEAR layout.
             .initial_layout(vk::ImageLayout::UNDEFINED)
             .usage(usage)
-            .samples(samples);
+            .samples(samples)
+            .flags(flags);
         // There are some optional flags for images that are related to sparse images. Sparse images are images where only certain regions are actually backed by memory.
         // If you were using a 3D texture for a voxel terrain, for example, then you could use this to avoid allocating memory to store large volumes of "air" values.
-        // .flags()
 
         let image = self
             .device
@@ -162,23 +269,23 @@ impl VkDeviceContext {
             .expect("failed to create image!");
 
         let memory_requirements = self.device.get_image_memory_requirements(image);
+        let allocation = self.allocator.allocate(
+            &self.device,
+            memory_requirements,
+            self.find_memory_type_index(memory_requirements.memory_type_bits, memory_properties),
+            tiling == vk::ImageTiling::LINEAR,
+        );
 
-        let allocate_info = vk::MemoryAllocateInfo {
-            allocation_size: memory_requirements.size,
-            memory_type_index: self
-                .find_memory_type_index(memory_requirements.memory_type_bits, memory_properties),
-            ..Default::default()
-        };
-
-        let image_memory = self
-            .device
-            .allocate_memory(&allocate_info, None)
-            .expect("failed to allocate memory!");
         self.device
-            .bind_image_memory(image, image_memory, 0)
+            .bind_image_memory(image, allocation.memory, allocation.offset)
             .expect("failed to bind image memory!");
 
-        (image, image_memory)
+        (image, allocation)
+    }
+
+    pub unsafe fn destroy_image(&self, image: vk::Image, allocation: Allocation) {
+        self.device.destroy_image(image, None);
+        self.allocator.free(&self.device, allocation);
     }
 
     pub unsafe fn create_image_view(
@@ -188,16 +295,33 @@ impl VkDeviceContext {
         aspect_flags: vk::ImageAspectFlags,
         mips: u32,
     ) -> vk::ImageView {
-        let create_info = vk::ImageViewCreateInfo::default()
-            .image(image)
-            .view_type(vk::ImageViewType::TYPE_2D)
-            .format(format)
-            .components(vk::ComponentMapping {
+        self.create_swizzled_image_view(
+            image,
+            format,
+            aspect_flags,
+            mips,
+            vk::ComponentMapping {
                 r: vk::ComponentSwizzle::IDENTITY,
                 g: vk::ComponentSwizzle::IDENTITY,
                 b: vk::ComponentSwizzle::IDENTITY,
                 a: vk::ComponentSwizzle::IDENTITY,
-            })
+            },
+        )
+    }
+
+    pub unsafe fn create_swizzled_image_view(
+        &self,
+        image: vk::Image,
+        format: vk::Format,
+        aspect_flags: vk::ImageAspectFlags,
+        mips: u32,
+        components: vk::ComponentMapping,
+    ) -> vk::ImageView {
+        let create_info = vk::ImageViewCreateInfo::default()
+            .image(image)
+            .view_type(vk::ImageViewType::TYPE_2D)
+            .format(format)
+            .components(components)
             .subresource_range(vk::ImageSubresourceRange {
                 // https://github.com/KhronosGroup/Vulkan-Guide/blob/main/chapters/formats.adoc
                 // The VkImageAspectFlagBits values are used to represent which part of the data is being accessed
@@ -219,6 +343,30 @@ impl VkDeviceContext {
             .expect("failed to create image view!")
     }
 
+    // All 6 faces, sampled with a direction.
+    pub unsafe fn create_cube_image_view(
+        &self,
+        image: vk::Image,
+        format: vk::Format,
+        mips: u32,
+    ) -> vk::ImageView {
+        let create_info = vk::ImageViewCreateInfo::default()
+            .image(image)
+            .view_type(vk::ImageViewType::CUBE)
+            .format(format)
+            .subresource_range(vk::ImageSubresourceRange {
+                aspect_mask: vk::ImageAspectFlags::COLOR,
+                base_array_layer: 0,
+                layer_count: 6,
+                base_mip_level: 0,
+                level_count: mips,
+            });
+
+        self.device
+            .create_image_view(&create_info, None)
+            .expect("failed to create cube image view!")
+    }
+
     fn find_memory_type_index(
         &self,
         type_bits: u32,
@@ -244,9 +392,16 @@ impl VkDeviceContext {
     unsafe fn create_logical_device(
         context: &VkContext,
         physical_device: vk::PhysicalDevice,
-        graphic_queue_family: Option<u32>,
-        present_queue_family: Option<u32>,
-        compute_queue_family: Option<u32>,
+        supported_features: &vk::PhysicalDeviceFeatures,
+        portability_subset: Option<vk::PhysicalDevicePortabilitySubsetFeaturesKHR<'static>>,
+        timeline_semaphore: bool,
+        push_descriptor: bool,
+        // graphic, present and compute, see `find_queue_families`
+        (graphic_queue_family, present_queue_family, compute_queue_family): (
+            Option<u32>,
+            Option<u32>,
+            Option<u32>,
+        ),
     ) -> (
         ash::Device,
         Option<vk::Queue>,
@@ -258,9 +413,8 @@ impl VkDeviceContext {
             present_queue_family,
             compute_queue_family,
         ]
-        .iter()
-        .filter(|family| family.is_some())
-        .map(|family| family.unwrap())
+        .into_iter()
+        .flatten()
         .collect::<HashSet<_>>();
 
         let mut queue_infos: Vec<vk::DeviceQueueCreateInfo> = vec![];
@@ -272,68 +426,142 @@ impl VkDeviceContext {
             queue_infos.push(info);
         });
 
+        // Optional stages are only enabled when the device supports them,
+        // shadings that need them are rejected at pipeline creation otherwise. Samplers skip
+        // anisotropic filtering without it.
         let features = vk::PhysicalDeviceFeatures::default()
-            .sampler_anisotropy(true)
-            .sample_rate_shading(true);
+            .sampler_anisotropy(supported_features.sampler_anisotropy == vk::TRUE)
+            .sample_rate_shading(supported_features.sample_rate_shading == vk::TRUE)
+            .geometry_shader(supported_features.geometry_shader == vk::TRUE)
+            .tessellation_shader(supported_features.tessellation_shader == vk::TRUE);
 
-        let extension_names = DEVICE_EXTENSIONS
+        let mut extension_names = DEVICE_EXTENSIONS
             .iter()
             .cloned()
             .map(|extension| extension.as_ptr())
             .collect::<Vec<_>>();
 
-        let create_info = vk::DeviceCreateInfo::default()
-            .enabled_extension_names(&extension_names)
+        let mut create_info = vk::DeviceCreateInfo::default()
             .enabled_features(&features)
             .queue_create_infos(&queue_infos);
 
+        // The Vulkan spec states: If the VK_KHR_portability_subset extension is included in pProperties
+        // of vkEnumerateDeviceExtensionProperties, ppEnabledExtensionNames must include "VK_KHR_portability_subset"
+        // Everything the subset reports is enabled, the renderer checks the flags before relying on them.
+        let mut portability_features = portability_subset.unwrap_or_default();
+        if portability_subset.is_some() {
+            extension_names.push(vk::KHR_PORTABILITY_SUBSET_NAME.as_ptr());
+            create_info = create_info.push_next(&mut portability_features);
+        }
+        let mut timeline_semaphore_features =
+            vk::PhysicalDeviceTimelineSemaphoreFeatures::default().timeline_semaphore(true);
+        if timeline_semaphore {
+            create_info = create_info.push_next(&mut timeline_semaphore_features);
+        }
+        if push_descriptor {
+            extension_names.push(vk::KHR_PUSH_DESCRIPTOR_NAME.as_ptr());
+        }
+        create_info = create_info.enabled_extension_names(&extension_names);
+
         let device = context
             .instance
             .create_device(physical_device, &create_info, None)
             .expect("failed to create logical device!");
 
-        let graphic_queue = if let Some(queue_family) = graphic_queue_family {
-            Some(device.get_device_queue(queue_family, 0))
-        } else {
-            None
-        };
+        let graphic_queue =
+            graphic_queue_family.map(|queue_family| device.get_device_queue(queue_family, 0));
 
         let present_queue = if graphic_queue_family == present_queue_family {
             graphic_queue
-        } else if let Some(queue_family) = present_queue_family {
-            Some(device.get_device_queue(queue_family, 0))
         } else {
-            None
+            present_queue_family.map(|queue_family| device.get_device_queue(queue_family, 0))
         };
 
-        let compute_queue = if let Some(queue_family) = compute_queue_family {
-            Some(device.get_device_queue(queue_family, 0))
-        } else {
-            None
-        };
+        let compute_queue =
+            compute_queue_family.map(|queue_family| device.get_device_queue(queue_family, 0));
 
         (device, graphic_queue, present_queue, compute_queue)
     }
 
-    unsafe fn pick_physical_device(context: &VkContext) -> vk::PhysicalDevice {
+    // The selected adapter when it is suitable, otherwise the highest rated one.
+    unsafe fn pick_physical_device(
+        context: &VkContext,
+        selection: &AdapterSelection,
+    ) -> (Vec<AdapterInfo>, AdapterInfo, vk::PhysicalDevice) {
         let physical_devices = context
             .instance
             .enumerate_physical_devices()
             .expect("failed to find GPUs with vulkan support!");
-
-        let score_map: BTreeMap<u32, vk::PhysicalDevice> = physical_devices
-            .into_iter()
+        let scores = physical_devices
+            .iter()
             .map(|physical_device| {
-                (
-                    Self::rate_physical_device_suitability(context, physical_device),
-                    physical_device,
-                )
+                Self::rate_physical_device_suitability(context, *physical_device)
+            })
+            .collect::<Vec<_>>();
+        let adapters = physical_devices
+            .iter()
+            .zip(&scores)
+            .enumerate()
+            .map(|(index, (physical_device, score))| {
+                Self::adapter_info(context, *physical_device, index, *score > 0)
             })
-            .collect();
+            .collect::<Vec<_>>();
+        log::info!("GPU adapters:");
+        for adapter in &adapters {
+            log::info!("  {}", adapter);
+        }
+
+        let index = match selection.find(&adapters) {
+            Some(adapter) => adapter.index,
+            None => match (0..scores.len()).max_by_key(|index| scores[*index]) {
+                Some(index) if scores[index] > 0 => index,
+                _ => panic!("failed to find a suitable device!"),
+            },
+        };
+        let adapter = adapters[index].clone();
+        log::info!(
+            "using GPU adapter {}, set {} to an index or name to pick another",
+            adapter,
+            AdapterSelection::ENV_VAR
+        );
+        (adapters, adapter, physical_devices[index])
+    }
 
-        match score_map.first_key_value() {
-            Some((count, physical_device)) if *count > 0 => *physical_device,
-            _ => panic!("failed to find a suitable device!"),
+    unsafe fn adapter_info(
+        context: &VkContext,
+        physical_device: vk::PhysicalDevice,
+        index: usize,
+        suitable: bool,
+    ) -> AdapterInfo {
+        let properties = context
+            .instance
+            .get_physical_device_properties(physical_device);
+        let adapter_type = match properties.device_type {
+            vk::PhysicalDeviceType::DISCRETE_GPU => AdapterType::Discrete,
+            vk::PhysicalDeviceType::INTEGRATED_GPU => AdapterType::Integrated,
+            vk::PhysicalDeviceType::VIRTUAL_GPU => AdapterType::Virtual,
+            vk::PhysicalDeviceType::CPU => AdapterType::Cpu,
+            _ => AdapterType::Other,
+        };
+        let name = properties
+            .device_name_as_c_str()
+            .map_or(String::from("unknown"), |name| {
+                name.to_string_lossy().into_owned()
+            });
+        let version = properties.api_version;
+        AdapterInfo {
+            index,
+            name,
+            adapter_type,
+            vendor_id: properties.vendor_id,
+            device_id: properties.device_id,
+            api_version: format!(
+                "{}.{}.{}",
+                vk::api_version_major(version),
+                vk::api_version_minor(version),
+                vk::api_version_patch(version)
+            ),
+            suitable,
         }
     }
 
@@ -345,9 +573,6 @@ impl VkDeviceContext {
         let properties = context
             .instance
             .get_physical_device_properties(physical_device);
-        let features = context
-            .instance
-            .get_physical_device_features(physical_device);
 
         match properties.device_type {
             vk::PhysicalDeviceType::DISCRETE_GPU => score += 10000,
@@ -366,10 +591,9 @@ impl VkDeviceContext {
             || present_queue_family.is_none()
             || compute_queue_family.is_none()
             || !Self::check_device_extension_support(&context.instance, physical_device)
-            || features.sampler_anisotropy == vk::FALSE
         {
             score = 0;
-        } else {
+        } else if context.surface.get().is_some() {
             let (_, formats, present_modes) =
                 SwapChain::query_surface_support(context, physical_device);
             if formats.is_empty() || present_modes.is_empty() {
@@ -377,21 +601,35 @@ impl VkDeviceContext {
             }
         }
 
-        return score;
+        score
     }
 
     unsafe fn find_queue_families(
         context: &VkContext,
         physical_device: vk::PhysicalDevice,
     ) -> (Option<u32>, Option<u32>, Option<u32>) {
-        let mut graphic_queue_family: Option<u32> = None;
-        let mut present_queue_family: Option<u32> = None;
-        let mut compute_queue_family: Option<u32> = None;
-
         let properties = context
             .instance
             .get_physical_device_queue_family_properties(physical_device);
 
+        // headless, nothing is presented and the graphics queue stands in for the present one
+        let Some(surface) = context.surface.get() else {
+            let graphic_queue_family = properties
+                .iter()
+                .position(|property| property.queue_flags.contains(vk::QueueFlags::GRAPHICS))
+                .map(|index| index as u32);
+            let compute_queue_family =
+                Self::find_compute_queue_family(&properties, graphic_queue_family);
+            return (
+                graphic_queue_family,
+                graphic_queue_family,
+                compute_queue_family,
+            );
+        };
+
+        let mut graphic_queue_family: Option<u32> = None;
+        let mut present_queue_family: Option<u32> = None;
+
         // Any queue family with VK_QUEUE_GRAPHICS_BIT or VK_QUEUE_COMPUTE_BIT capabilities already implicitly support VK_QUEUE_TRANSFER_BIT operations.
         for (index, property) in properties.iter().enumerate() {
             if property.queue_flags.contains(vk::QueueFlags::GRAPHICS) {
@@ -403,11 +641,7 @@ impl VkDeviceContext {
                     .surface_fn
                     .as_ref()
                     .unwrap()
-                    .get_physical_device_surface_support(
-                        physical_device,
-                        index as u32,
-                        context.surface.unwrap(),
-                    )
+                    .get_physical_device_surface_support(physical_device, index as u32, surface)
                     .unwrap();
 
                 if is_support_surface {
@@ -424,11 +658,7 @@ impl VkDeviceContext {
                     .surface_fn
                     .as_ref()
                     .unwrap()
-                    .get_physical_device_surface_support(
-                        physical_device,
-                        index as u32,
-                        context.surface.unwrap(),
-                    )
+                    .get_physical_device_surface_support(physical_device, index as u32, surface)
                     .unwrap();
 
                 if is_support_surface {
@@ -438,6 +668,22 @@ impl VkDeviceContext {
             }
         }
 
+        let compute_queue_family =
+            Self::find_compute_queue_family(&properties, graphic_queue_family);
+
+        (
+            graphic_queue_family,
+            present_queue_family,
+            compute_queue_family,
+        )
+    }
+
+    // A family of its own when there is one, the graphics family otherwise.
+    fn find_compute_queue_family(
+        properties: &[vk::QueueFamilyProperties],
+        graphic_queue_family: Option<u32>,
+    ) -> Option<u32> {
+        let mut compute_queue_family: Option<u32> = None;
         for (index, property) in properties.iter().enumerate() {
             if property.queue_flags.contains(vk::QueueFlags::COMPUTE) {
                 if compute_queue_family.is_none() {
@@ -450,12 +696,7 @@ impl VkDeviceContext {
                 }
             }
         }
-
-        (
-            graphic_queue_family,
-            present_queue_family,
-            compute_queue_family,
-        )
+        compute_queue_family
     }
 
     unsafe fn check_device_extension_support(
@@ -474,6 +715,103 @@ impl VkDeviceContext {
             .all(|extension| supported_extensions.contains(extension))
     }
 
+    // Optional extensions, enabled when the device has them.
+    unsafe fn is_extension_supported(
+        context: &VkContext,
+        physical_device: vk::PhysicalDevice,
+        name: &CStr,
+    ) -> bool {
+        context
+            .instance
+            .enumerate_device_extension_properties(physical_device)
+            .unwrap()
+            .iter()
+            .any(|extension| CStr::from_ptr(extension.extension_name.as_ptr()) == name)
+    }
+
+    unsafe fn query_portability_subset(
+        context: &VkContext,
+        physical_device: vk::PhysicalDevice,
+    ) -> Option<vk::PhysicalDevicePortabilitySubsetFeaturesKHR<'static>> {
+        let is_portability_device = context
+            .instance
+            .enumerate_device_extension_properties(physical_device)
+            .unwrap()
+            .iter()
+            .any(|extension| {
+                CStr::from_ptr(extension.extension_name.as_ptr()) == vk::KHR_PORTABILITY_SUBSET_NAME
+            });
+        if !is_portability_device {
+            return None;
+        }
+
+        // the device may predate 1.1, the query goes through VK_KHR_get_physical_device_properties2
+        let properties2_fn = ash::khr::get_physical_device_properties2::Instance::new(
+            &context.entry,
+            &context.instance,
+        );
+        let mut portability_features = vk::PhysicalDevicePortabilitySubsetFeaturesKHR::default();
+        let mut features2 =
+            vk::PhysicalDeviceFeatures2::default().push_next(&mut portability_features);
+        properties2_fn.get_physical_device_features2(physical_device, &mut features2);
+
+        Some(portability_features)
+    }
+
+    unsafe fn query_timeline_semaphore(
+        context: &VkContext,
+        physical_device: vk::PhysicalDevice,
+        properties: &vk::PhysicalDeviceProperties,
+    ) -> bool {
+        if properties.api_version < vk::make_api_version(0, 1, 2, 0) {
+            return false;
+        }
+        let mut timeline_features = vk::PhysicalDeviceTimelineSemaphoreFeatures::default();
+        let mut features2 =
+            vk::PhysicalDeviceFeatures2::default().push_next(&mut timeline_features);
+        context
+            .instance
+            .get_physical_device_features2(physical_device, &mut features2);
+        timeline_features.timeline_semaphore == vk::TRUE
+    }
+
+    pub fn is_topology_supported(&self, topology: vk::PrimitiveTopology) -> bool {
+        match (topology, self.portability_subset) {
+            (vk::PrimitiveTopology::TRIANGLE_FAN, Some(subset)) => subset.triangle_fans == vk::TRUE,
+            _ => true,
+        }
+    }
+
+    pub fn is_anisotropy_supported(&self) -> bool {
+        self.physical_device_features.sampler_anisotropy == vk::TRUE
+    }
+
+    pub fn is_sample_shading_supported(&self) -> bool {
+        self.physical_device_features.sample_rate_shading == vk::TRUE
+    }
+
+    // For the forward pass, its color and depth are attachments and the depth is sampled after it.
+    pub fn is_sample_count_supported(&self, samples: vk::SampleCountFlags) -> bool {
+        let limits = &self.physical_device_properties.limits;
+        (limits.framebuffer_color_sample_counts
+            & limits.framebuffer_depth_sample_counts
+            & limits.sampled_image_depth_sample_counts)
+            .contains(samples)
+    }
+
+    pub fn is_shader_stage_supported(&self, stage: vk::ShaderStageFlags) -> bool {
+        match stage {
+            vk::ShaderStageFlags::GEOMETRY => {
+                self.physical_device_features.geometry_shader == vk::TRUE
+            }
+            vk::ShaderStageFlags::TESSELLATION_CONTROL
+            | vk::ShaderStageFlags::TESSELLATION_EVALUATION => {
+                self.physical_device_features.tessellation_shader == vk::TRUE
+            }
+            _ => true,
+        }
+    }
+
     unsafe fn get_max_usable_sample_count(
         properties: &vk::PhysicalDeviceProperties,
     ) -> vk::SampleCountFlags {
@@ -0,0 +1,699 @@
+use super::rhi::*;
+use super::{Allocation, FrameSignal, ImageUpload, ImageUploadFinish, GPU};
+use crate::cpu_profiler;
+use ash::vk;
+use std::ops::Range;
+
+#[derive(Debug, Copy, Clone)]
+pub struct VkBuffer {
+    pub buffer: vk::Buffer,
+    pub memory: Allocation,
+}
+
+#[derive(Debug, Copy, Clone)]
+pub struct VkTexture {
+    pub image: vk::Image,
+    pub image_memory: Allocation,
+    pub image_view: vk::ImageView,
+    pub image_sampler: vk::Sampler,
+    pub format: vk::Format,
+    pub mip_levels: u32,
+}
+
+#[derive(Debug, Copy, Clone)]
+pub struct VkPipeline {
+    pub pipeline: vk::Pipeline,
+    pub layout: vk::PipelineLayout,
+}
+
+impl From<TextureFormat> for vk::Format {
+    fn from(format: TextureFormat) -> Self {
+        match format {
+            TextureFormat::Rgba8Srgb => vk::Format::R8G8B8A8_SRGB,
+            TextureFormat::Rgba8Unorm => vk::Format::R8G8B8A8_UNORM,
+            TextureFormat::Bc1Srgb => vk::Format::BC1_RGBA_SRGB_BLOCK,
+            TextureFormat::Bc1Unorm => vk::Format::BC1_RGBA_UNORM_BLOCK,
+            TextureFormat::Bc3Srgb => vk::Format::BC3_SRGB_BLOCK,
+            TextureFormat::Bc3Unorm => vk::Format::BC3_UNORM_BLOCK,
+            TextureFormat::Bc4Unorm => vk::Format::BC4_UNORM_BLOCK,
+            TextureFormat::Bc5Unorm => vk::Format::BC5_UNORM_BLOCK,
+            TextureFormat::Bc7Srgb => vk::Format::BC7_SRGB_BLOCK,
+            TextureFormat::Bc7Unorm => vk::Format::BC7_UNORM_BLOCK,
+            TextureFormat::Etc2Rgba8Srgb => vk::Format::ETC2_R8G8B8A8_SRGB_BLOCK,
+            TextureFormat::Etc2Rgba8Unorm => vk::Format::ETC2_R8G8B8A8_UNORM_BLOCK,
+            TextureFormat::Astc4x4Srgb => vk::Format::ASTC_4X4_SRGB_BLOCK,
+            TextureFormat::Astc4x4Unorm => vk::Format::ASTC_4X4_UNORM_BLOCK,
+        }
+    }
+}
+
+impl From<TextureChannel> for vk::ComponentSwizzle {
+    fn from(channel: TextureChannel) -> Self {
+        match channel {
+            TextureChannel::R => vk::ComponentSwizzle::R,
+            TextureChannel::G => vk::ComponentSwizzle::G,
+            TextureChannel::B => vk::ComponentSwizzle::B,
+            TextureChannel::A => vk::ComponentSwizzle::A,
+            TextureChannel::Zero => vk::ComponentSwizzle::ZERO,
+            TextureChannel::One => vk::ComponentSwizzle::ONE,
+        }
+    }
+}
+
+impl From<SamplerFilter> for vk::Filter {
+    fn from(filter: SamplerFilter) -> Self {
+        match filter {
+            SamplerFilter::Linear => vk::Filter::LINEAR,
+            SamplerFilter::Nearest => vk::Filter::NEAREST,
+        }
+    }
+}
+
+impl From<SamplerAddress> for vk::SamplerAddressMode {
+    fn from(address: SamplerAddress) -> Self {
+        match address {
+            SamplerAddress::Repeat => vk::SamplerAddressMode::REPEAT,
+            SamplerAddress::MirroredRepeat => vk::SamplerAddressMode::MIRRORED_REPEAT,
+            SamplerAddress::ClampToEdge => vk::SamplerAddressMode::CLAMP_TO_EDGE,
+        }
+    }
+}
+
+impl From<SamplerCompare> for vk::CompareOp {
+    fn from(compare: SamplerCompare) -> Self {
+        match compare {
+            SamplerCompare::Less => vk::CompareOp::LESS,
+            SamplerCompare::LessOrEqual => vk::CompareOp::LESS_OR_EQUAL,
+            SamplerCompare::Greater => vk::CompareOp::GREATER,
+            SamplerCompare::GreaterOrEqual => vk::CompareOp::GREATER_OR_EQUAL,
+        }
+    }
+}
+
+impl Rhi for GPU {
+    type Buffer = VkBuffer;
+    type Texture = VkTexture;
+    type Pipeline = VkPipeline;
+    type ResourceSet = vk::DescriptorSet;
+    type RenderPass = vk::RenderPass;
+    type Framebuffer = vk::Framebuffer;
+    type CommandBuffer = vk::CommandBuffer;
+    type Semaphore = vk::Semaphore;
+    type Fence = FrameSignal;
+
+    fn create_buffer<T: Copy>(&self, data: &[T], usage: BufferUsage) -> VkBuffer {
+        let usage = match usage {
+            BufferUsage::Vertex => vk::BufferUsageFlags::VERTEX_BUFFER,
+            BufferUsage::Index => vk::BufferUsageFlags::INDEX_BUFFER,
+        };
+        let (buffer, memory) = self.create_buffer_with_data(data, usage);
+
+        VkBuffer { buffer, memory }
+    }
+
+    fn destroy_buffer(&self, buffer: VkBuffer) {
+        unsafe {
+            self.device_context
+                .destroy_buffer(buffer.buffer, buffer.memory);
+        }
+    }
+
+    fn is_texture_format_supported(&self, format: TextureFormat) -> bool {
+        let features = self
+            .get_format_properties(format.into())
+            .optimal_tiling_features;
+        features.contains(
+            vk::FormatFeatureFlags::SAMPLED_IMAGE
+                | vk::FormatFeatureFlags::SAMPLED_IMAGE_FILTER_LINEAR,
+        )
+    }
+
+    fn create_texture(&self, desc: &TextureDesc) -> VkTexture {
+        unsafe {
+            let format = vk::Format::from(desc.format);
+
+            let is_cube = desc.layers == 6;
+            let mut usage = vk::ImageUsageFlags::TRANSFER_SRC
+                | vk::ImageUsageFlags::TRANSFER_DST
+                | vk::ImageUsageFlags::SAMPLED;
+            if desc.is_mip_filtered() {
+                usage |= vk::ImageUsageFlags::STORAGE;
+            }
+            let (image, image_memory) = self.device_context.create_layered_image(
+                desc.width,
+                desc.height,
+                desc.mip_levels,
+                desc.layers,
+                if is_cube {
+                    vk::ImageCreateFlags::CUBE_COMPATIBLE
+                } else {
+                    vk::ImageCreateFlags::empty()
+                },
+                vk::SampleCountFlags::TYPE_1,
+                format,
+                vk::ImageTiling::OPTIMAL,
+                usage,
+                vk::MemoryPropertyFlags::DEVICE_LOCAL,
+            );
+
+            let level_region = |level: u32, offset: usize| vk::BufferImageCopy {
+                buffer_offset: offset as vk::DeviceSize,
+                // If either of these values is zero, that aspect of the buffer memory is considered to
+                // be tightly packed according to the imageExtent.
+                buffer_row_length: 0,
+                buffer_image_height: 0,
+                image_subresource: vk::ImageSubresourceLayers {
+                    aspect_mask: vk::ImageAspectFlags::COLOR,
+                    mip_level: level,
+                    base_array_layer: 0,
+                    layer_count: desc.layers,
+                },
+                image_offset: vk::Offset3D { x: 0, y: 0, z: 0 },
+                // blocks of compressed formats may hang over the edge of the smallest levels
+                image_extent: vk::Extent3D {
+                    width: (desc.width >> level).max(1),
+                    height: (desc.height >> level).max(1),
+                    depth: 1,
+                },
+            };
+            let (regions, finish) = if !desc.mips.is_empty() {
+                // precomputed chain, compressed formats can't be blitted anyway
+                let regions = desc
+                    .mips
+                    .iter()
+                    .enumerate()
+                    .map(|(level, range)| level_region(level as u32, range.start))
+                    .collect::<Vec<_>>();
+                (regions, ImageUploadFinish::Sampled)
+            } else if desc.is_mip_filtered() || desc.mip_levels == 1 {
+                // levels past 0 are filtered by the renderer's compute pass, see `MipGenerator`
+                (vec![level_region(0, 0)], ImageUploadFinish::Sampled)
+            } else {
+                if !self
+                    .get_format_properties(format)
+                    .optimal_tiling_features
+                    .contains(vk::FormatFeatureFlags::SAMPLED_IMAGE_FILTER_LINEAR)
+                {
+                    panic!(
+                        "failed to generate mipmaps, texture image does not support linear filter!"
+                    )
+                }
+                (vec![level_region(0, 0)], ImageUploadFinish::GenerateMips)
+            };
+            // copied with the other uploads of the frame, see `GPU::flush_uploads`
+            self.uploads.borrow_mut().upload_image(
+                &self.device_context,
+                desc.pixels,
+                &ImageUpload {
+                    image,
+                    width: desc.width,
+                    height: desc.height,
+                    mip_levels: desc.mip_levels,
+                    layers: desc.layers,
+                    regions: &regions,
+                    finish,
+                },
+            );
+
+            let image_view = if is_cube {
+                self.device_context
+                    .create_cube_image_view(image, format, desc.mip_levels)
+            } else {
+                self.device_context.create_image_view(
+                    image,
+                    format,
+                    vk::ImageAspectFlags::COLOR,
+                    desc.mip_levels,
+                )
+            };
+
+            let image_sampler = self.get_sampler(&desc.sampler);
+
+            VkTexture {
+                image,
+                image_memory,
+                image_view,
+                image_sampler,
+                format,
+                mip_levels: desc.mip_levels,
+            }
+        }
+    }
+
+    fn destroy_texture(&self, texture: VkTexture) {
+        unsafe {
+            // the sampler is the cache's
+            let device = &self.device_context.device;
+            device.destroy_image_view(texture.image_view, None);
+            self.device_context
+                .destroy_image(texture.image, texture.image_memory);
+        }
+    }
+
+    fn create_texture_view(&self, texture: &VkTexture, swizzle: TextureSwizzle) -> VkTexture {
+        let [r, g, b, a] = swizzle.0.map(vk::ComponentSwizzle::from);
+        let image_view = unsafe {
+            self.device_context.create_swizzled_image_view(
+                texture.image,
+                texture.format,
+                vk::ImageAspectFlags::COLOR,
+                texture.mip_levels,
+                vk::ComponentMapping { r, g, b, a },
+            )
+        };
+
+        VkTexture {
+            image_view,
+            ..*texture
+        }
+    }
+
+    fn destroy_texture_view(&self, view: VkTexture) {
+        unsafe {
+            self.device_context
+                .device
+                .destroy_image_view(view.image_view, None);
+        }
+    }
+
+    fn write_texture(&self, set: vk::DescriptorSet, binding: u32, texture: &VkTexture) {
+        let image_infos = [vk::DescriptorImageInfo {
+            image_view: texture.image_view,
+            image_layout: vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+            sampler: texture.image_sampler,
+        }];
+
+        let texture_write = vk::WriteDescriptorSet::default()
+            .descriptor_type(vk::DescriptorType::SAMPLED_IMAGE)
+            .image_info(&image_infos)
+            .dst_set(set)
+            .dst_binding(binding)
+            .dst_array_element(0);
+
+        let sampler_write = vk::WriteDescriptorSet::default()
+            .descriptor_type(vk::DescriptorType::SAMPLER)
+            .image_info(&image_infos)
+            .dst_set(set)
+            .dst_binding(binding + 1)
+            .dst_array_element(0);
+
+        unsafe {
+            self.device_context
+                .device
+                .update_descriptor_sets(&[texture_write, sampler_write], &[]);
+        }
+    }
+
+    fn destroy_pipeline(&self, pipeline: VkPipeline) {
+        unsafe {
+            let device = &self.device_context.device;
+            device.destroy_pipeline(pipeline.pipeline, None);
+            device.destroy_pipeline_layout(pipeline.layout, None);
+        }
+    }
+
+    fn begin_commands(&self, command_buffer: vk::CommandBuffer) {
+        unsafe {
+            let device = &self.device_context.device;
+            device
+                .reset_command_buffer(command_buffer, vk::CommandBufferResetFlags::empty())
+                .expect("failed to reset command buffer!");
+
+            let begin_info = vk::CommandBufferBeginInfo::default()
+                // ONE_TIME_SUBMIT_BIT: The command buffer will be rerecorded right after executing it once.
+                // RENDER_PASS_CONTINUE_BIT: This is a secondary command buffer that will be entirely within a single render pass.
+                // SIMULTANEOUS_USE_BIT: The command buffer can be resubmitted while it is also already pending execution.
+                .flags(vk::CommandBufferUsageFlags::SIMULTANEOUS_USE);
+            // Only relevant for secondary command buffers. It specifies which state to inherit from the calling primary command buffers.
+            // .inheritance_info()
+
+            device
+                .begin_command_buffer(command_buffer, &begin_info)
+                .expect("failed to begin command buffer!");
+        }
+        if let Some(watchdog) = self.watchdog.borrow_mut().as_mut() {
+            watchdog.begin(command_buffer);
+        }
+        if let Some(profiler) = self.profiler.borrow_mut().as_mut() {
+            unsafe { profiler.begin(&self.device_context.device, command_buffer) };
+        }
+    }
+
+    fn end_commands(&self, command_buffer: vk::CommandBuffer) {
+        unsafe {
+            if let Some(profiler) = self.profiler.borrow_mut().as_mut() {
+                profiler.end(&self.device_context.device, command_buffer);
+            }
+            self.device_context
+                .device
+                .end_command_buffer(command_buffer)
+                .expect("failed to end command buffer!");
+        }
+    }
+
+    fn begin_pass(&self, command_buffer: vk::CommandBuffer, desc: &PassDesc<Self>) {
+        self.begin_pass_contents(command_buffer, desc, vk::SubpassContents::INLINE);
+    }
+
+    fn next_subpass(&self, command_buffer: vk::CommandBuffer) {
+        unsafe {
+            self.device_context
+                .device
+                .cmd_next_subpass(command_buffer, vk::SubpassContents::INLINE);
+        }
+    }
+
+    fn begin_secondary_pass(&self, command_buffer: vk::CommandBuffer, desc: &PassDesc<Self>) {
+        self.begin_pass_contents(
+            command_buffer,
+            desc,
+            vk::SubpassContents::SECONDARY_COMMAND_BUFFERS,
+        );
+    }
+
+    fn next_secondary_subpass(&self, command_buffer: vk::CommandBuffer) {
+        unsafe {
+            self.device_context.device.cmd_next_subpass(
+                command_buffer,
+                vk::SubpassContents::SECONDARY_COMMAND_BUFFERS,
+            );
+        }
+    }
+
+    fn execute_commands(
+        &self,
+        command_buffer: vk::CommandBuffer,
+        secondaries: &[vk::CommandBuffer],
+    ) {
+        if secondaries.is_empty() {
+            return;
+        }
+        unsafe {
+            self.device_context
+                .device
+                .cmd_execute_commands(command_buffer, secondaries);
+        }
+    }
+
+    fn end_pass(&self, command_buffer: vk::CommandBuffer) {
+        unsafe {
+            let device = &self.device_context.device;
+            device.cmd_end_render_pass(command_buffer);
+            if let Some(profiler) = self.profiler.borrow_mut().as_mut() {
+                profiler.end_pass(device, command_buffer);
+            }
+            if let Some(watchdog) = self.watchdog.borrow().as_ref() {
+                watchdog.end_pass(device, command_buffer);
+            }
+            self.debug_names.end_label(command_buffer);
+            cpu_profiler::end_scope();
+        }
+    }
+
+    fn bind_pipeline(&self, command_buffer: vk::CommandBuffer, pipeline: &VkPipeline) {
+        unsafe {
+            self.device_context.device.cmd_bind_pipeline(
+                command_buffer,
+                vk::PipelineBindPoint::GRAPHICS,
+                pipeline.pipeline,
+            );
+        }
+    }
+
+    fn bind_resource_sets(
+        &self,
+        command_buffer: vk::CommandBuffer,
+        pipeline: &VkPipeline,
+        first_set: u32,
+        sets: &[vk::DescriptorSet],
+    ) {
+        self.bind_resource_sets_dynamic(command_buffer, pipeline, first_set, sets, &[]);
+    }
+
+    fn bind_resource_sets_dynamic(
+        &self,
+        command_buffer: vk::CommandBuffer,
+        pipeline: &VkPipeline,
+        first_set: u32,
+        sets: &[vk::DescriptorSet],
+        dynamic_offsets: &[u32],
+    ) {
+        unsafe {
+            self.device_context.device.cmd_bind_descriptor_sets(
+                command_buffer,
+                vk::PipelineBindPoint::GRAPHICS,
+                pipeline.layout,
+                first_set,
+                sets,
+                dynamic_offsets,
+            );
+        }
+    }
+
+    fn push_constants(
+        &self,
+        command_buffer: vk::CommandBuffer,
+        pipeline: &VkPipeline,
+        data: &[u8],
+    ) {
+        unsafe {
+            self.device_context.device.cmd_push_constants(
+                command_buffer,
+                pipeline.layout,
+                vk::ShaderStageFlags::ALL_GRAPHICS,
+                0,
+                data,
+            );
+        }
+    }
+
+    fn draw_indexed(
+        &self,
+        command_buffer: vk::CommandBuffer,
+        vertex_buffer: &VkBuffer,
+        index_buffer: &VkBuffer,
+        indices: Range<u32>,
+        vertex_offset: i32,
+    ) {
+        unsafe {
+            let device = &self.device_context.device;
+            device.cmd_bind_vertex_buffers(command_buffer, 0, &[vertex_buffer.buffer], &[0]);
+            device.cmd_bind_index_buffer(
+                command_buffer,
+                index_buffer.buffer,
+                0,
+                vk::IndexType::UINT32,
+            );
+            device.cmd_draw_indexed(
+                command_buffer,
+                indices.len() as u32,
+                1,
+                indices.start,
+                vertex_offset,
+                0,
+            );
+        }
+    }
+
+    fn draw(&self, command_buffer: vk::CommandBuffer, vertex_count: u32) {
+        unsafe {
+            self.device_context
+                .device
+                .cmd_draw(command_buffer, vertex_count, 1, 0, 0);
+        }
+    }
+
+    fn wait_fence(&self, fence: FrameSignal) {
+        self.wait_signal(fence, u64::MAX)
+            .expect("failed to wait fence!");
+    }
+
+    fn reset_fence(&self, fence: FrameSignal) {
+        // a timeline only moves forward, the next submit signals a higher value
+        let FrameSignal::Fence(fence) = fence else {
+            return;
+        };
+        unsafe {
+            self.device_context
+                .device
+                .reset_fences(&[fence])
+                .expect("failed to reset fence!");
+        }
+    }
+
+    fn acquire_image(&self, signal: vk::Semaphore) -> Option<u32> {
+        // Images queued for the present thread still belong to the application until presented,
+        // as many of them as the swap chain has spare images keep going while the next frame is
+        // recorded. The swap chain is externally synchronized against the present thread, the
+        // queue isn't involved, the submits and uploads go on while this waits for an image.
+        let swap_chain = self.swap_chain.borrow();
+        let mut presents = self.presents.borrow_mut();
+        presents.wait_until(swap_chain.queued_presents);
+        if !presents.up_to_date() {
+            return None;
+        }
+        let _swap_chain = presents.swap_chain_lock.lock().unwrap();
+        swap_chain.acquire_image(u64::MAX, Some(signal), None)
+    }
+
+    fn submit(
+        &self,
+        command_buffer: vk::CommandBuffer,
+        wait: vk::Semaphore,
+        signal: vk::Semaphore,
+        fence: FrameSignal,
+    ) {
+        // offscreen images are neither acquired nor presented, nothing would signal or wait
+        let offscreen = self.swap_chain.borrow().offscreen;
+        let mut wait_semaphores = vec![];
+        let mut wait_values = vec![];
+        let mut stage_masks = vec![];
+        let mut signal_semaphores = vec![];
+        let mut signal_values = vec![];
+        if !offscreen {
+            // binary semaphores ignore their value
+            wait_semaphores.push(wait);
+            wait_values.push(0);
+            stage_masks.push(vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT);
+            signal_semaphores.push(signal);
+            signal_values.push(0);
+        }
+        for (semaphore, value, stage) in self.frame_waits.take() {
+            wait_semaphores.push(semaphore);
+            wait_values.push(value);
+            stage_masks.push(stage);
+        }
+        let submit_fence = match fence {
+            FrameSignal::Timeline(timeline, value) => {
+                signal_semaphores.push(timeline);
+                signal_values.push(value);
+                vk::Fence::null()
+            }
+            FrameSignal::Fence(fence) => fence,
+        };
+        let command_buffers = [command_buffer];
+
+        // the frame draws with what was uploaded while it was recorded
+        self.flush_uploads();
+
+        let mut timeline_info = vk::TimelineSemaphoreSubmitInfo::default()
+            .wait_semaphore_values(&wait_values)
+            .signal_semaphore_values(&signal_values);
+        let mut submit_info = vk::SubmitInfo::default()
+            .command_buffers(&command_buffers)
+            .wait_semaphores(&wait_semaphores)
+            .wait_dst_stage_mask(&stage_masks)
+            .signal_semaphores(&signal_semaphores);
+        if self.device_context.timeline_semaphore {
+            submit_info = submit_info.push_next(&mut timeline_info);
+        }
+
+        unsafe {
+            let _queue = self.device_context.queue_lock.lock().unwrap();
+            self.device_context
+                .device
+                .queue_submit(
+                    self.device_context.graphic_queue.unwrap(),
+                    &[submit_info],
+                    submit_fence,
+                )
+                .expect("failed to submit draw command buffer!");
+        }
+        if let Some(watchdog) = self.watchdog.borrow_mut().as_mut() {
+            watchdog.submit(command_buffer, fence);
+        }
+    }
+
+    // Queued for the present thread, the result is of the presents that finished since the last
+    // call. Queueing an image for presentation waits on the semaphore and hands the image to the
+    // presentation engine, it is free to be acquired again in a later frame.
+    fn present(&self, image_index: u32, wait: vk::Semaphore) -> bool {
+        let swap_chain = self.swap_chain.borrow();
+        if swap_chain.offscreen {
+            return true;
+        }
+        let mut presents = self.presents.borrow_mut();
+        presents.present(
+            swap_chain.swap_chain_fn.as_ref().unwrap(),
+            self.device_context.present_queue.unwrap(),
+            swap_chain.swap_chain.unwrap(),
+            image_index,
+            wait,
+        );
+        presents.up_to_date()
+    }
+}
+
+impl GPU {
+    fn begin_pass_contents(
+        &self,
+        command_buffer: vk::CommandBuffer,
+        desc: &PassDesc<Self>,
+        contents: vk::SubpassContents,
+    ) {
+        unsafe {
+            let device = &self.device_context.device;
+            let extent = vk::Extent2D {
+                width: desc.width,
+                height: desc.height,
+            };
+
+            cpu_profiler::begin_scope(desc.label, "pass");
+            self.debug_names.begin_label(command_buffer, desc.label);
+            if let Some(profiler) = self.profiler.borrow_mut().as_mut() {
+                profiler.begin_pass(device, command_buffer, desc.label);
+            }
+            device.cmd_set_viewport(
+                command_buffer,
+                0,
+                &[vk::Viewport {
+                    x: 0.0,
+                    y: 0.0,
+                    width: desc.width as f32,
+                    height: desc.height as f32,
+                    min_depth: 0.0,
+                    max_depth: 1.0,
+                }],
+            );
+            device.cmd_set_scissor(
+                command_buffer,
+                0,
+                &[vk::Rect2D {
+                    offset: vk::Offset2D { x: 0, y: 0 },
+                    extent,
+                }],
+            );
+
+            let clear_values = [
+                vk::ClearValue {
+                    color: vk::ClearColorValue {
+                        float32: desc.clear_color,
+                    },
+                },
+                vk::ClearValue {
+                    depth_stencil: vk::ClearDepthStencilValue {
+                        depth: desc.clear_depth,
+                        stencil: 0,
+                    },
+                },
+            ];
+
+            let render_pass_begin_info = vk::RenderPassBeginInfo::default()
+                .clear_values(&clear_values)
+                .render_pass(desc.render_pass)
+                .framebuffer(desc.framebuffer)
+                .render_area(vk::Rect2D {
+                    offset: vk::Offset2D { x: 0, y: 0 },
+                    extent,
+                });
+
+            // INLINE: The render pass commands will be embedded in the primary command buffer itself
+            // and no secondary command buffers will be executed.
+            // SECONDARY_COMMAND_BUFFERS: The render pass commands will be executed from secondary command buffers.
+            device.cmd_begin_render_pass(command_buffer, &render_pass_begin_info, contents);
+        }
+        if let Some(watchdog) = self.watchdog.borrow_mut().as_mut() {
+            watchdog.begin_pass(command_buffer, desc.label);
+        }
+    }
+}
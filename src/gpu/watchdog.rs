@@ -0,0 +1,243 @@
+use super::{Allocation, FrameSignal, VkDeviceContext};
+use ash::vk;
+use std::fmt;
+use std::mem::size_of;
+use std::time::{Duration, Instant};
+
+// command buffers with a breadcrumb slot, enough for every frame in flight
+const MAX_TRAILS: usize = 8;
+
+// What the watchdog knows about a frame that didn't finish in time.
+#[derive(Debug, Clone)]
+pub struct HangReport {
+    pub frame: u64,
+    // since the frame was submitted
+    pub elapsed: Duration,
+    // labels of the passes recorded into the frame in order, with the time since each was recorded
+    pub passes: Vec<(&'static str, Duration)>,
+    // how many of them the GPU got through according to the breadcrumbs
+    pub completed_passes: usize,
+    pub device_lost: bool,
+}
+
+impl fmt::Display for HangReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "frame {} {} after {:.1}s, {} of {} passes done",
+            self.frame,
+            if self.device_lost {
+                "lost the device"
+            } else {
+                "hasn't finished"
+            },
+            self.elapsed.as_secs_f32(),
+            self.completed_passes,
+            self.passes.len()
+        )?;
+        for (index, (label, age)) in self.passes.iter().enumerate() {
+            let state = match index.cmp(&self.completed_passes) {
+                std::cmp::Ordering::Less => "done",
+                std::cmp::Ordering::Equal => "stuck",
+                std::cmp::Ordering::Greater => "pending",
+            };
+            writeln!(
+                f,
+                "  {:<8} {} (recorded {:.1}s ago)",
+                state,
+                label,
+                age.as_secs_f32()
+            )?;
+        }
+        Ok(())
+    }
+}
+
+struct Trail {
+    command_buffer: vk::CommandBuffer,
+    frame: u64,
+    passes: Vec<(&'static str, Instant)>,
+    signal: Option<FrameSignal>,
+    submitted: Option<Instant>,
+}
+
+// Notices frames whose signal isn't reached within `timeout` instead of waiting forever on a driver
+// hang. The passes recorded into every frame are labelled, and after each one the GPU writes how
+// many it finished to a host visible breadcrumb buffer, so the report tells which pass got stuck.
+// The barrier in front of every breadcrumb serializes the passes, it is meant for diagnosis.
+pub struct Watchdog {
+    pub timeout: Duration,
+    // frames in a row that were given up on before the app is aborted
+    pub max_resets: u32,
+    resets: u32,
+
+    frame: u64,
+    // one per breadcrumb slot
+    trails: Vec<Trail>,
+    breadcrumbs: vk::Buffer,
+    breadcrumb_memory: Allocation,
+}
+
+impl Watchdog {
+    pub fn new(device_context: &VkDeviceContext, timeout: Duration) -> Self {
+        let (breadcrumbs, breadcrumb_memory) = unsafe {
+            device_context.create_buffer(
+                (MAX_TRAILS * size_of::<u32>()) as vk::DeviceSize,
+                vk::BufferUsageFlags::TRANSFER_DST,
+                vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT,
+            )
+        };
+
+        Self {
+            timeout,
+            max_resets: 2,
+            resets: 0,
+
+            frame: 0,
+            trails: vec![],
+            breadcrumbs,
+            breadcrumb_memory,
+        }
+    }
+
+    // The command buffer was waited on and is recorded again.
+    pub fn begin(&mut self, command_buffer: vk::CommandBuffer) {
+        self.frame += 1;
+        let slot = match self
+            .trails
+            .iter()
+            .position(|trail| trail.command_buffer == command_buffer)
+        {
+            Some(slot) => slot,
+            None if self.trails.len() < MAX_TRAILS => {
+                self.trails.push(Trail {
+                    command_buffer,
+                    frame: 0,
+                    passes: vec![],
+                    signal: None,
+                    submitted: None,
+                });
+                self.trails.len() - 1
+            }
+            // the least recent frame gives its slot up
+            None => (0..self.trails.len())
+                .min_by_key(|&slot| self.trails[slot].frame)
+                .unwrap(),
+        };
+
+        let trail = &mut self.trails[slot];
+        trail.command_buffer = command_buffer;
+        trail.frame = self.frame;
+        trail.passes.clear();
+        trail.signal = None;
+        trail.submitted = None;
+        self.write_breadcrumb(slot, 0);
+    }
+
+    pub fn begin_pass(&mut self, command_buffer: vk::CommandBuffer, label: &'static str) {
+        if let Some(trail) = self.trail_mut(command_buffer) {
+            trail.passes.push((label, Instant::now()));
+        }
+    }
+
+    pub unsafe fn end_pass(&self, device: &ash::Device, command_buffer: vk::CommandBuffer) {
+        let Some(slot) = self.slot(command_buffer) else {
+            return;
+        };
+        // written once everything before it finished
+        device.cmd_pipeline_barrier(
+            command_buffer,
+            vk::PipelineStageFlags::ALL_COMMANDS,
+            vk::PipelineStageFlags::TRANSFER,
+            vk::DependencyFlags::empty(),
+            &[],
+            &[],
+            &[],
+        );
+        device.cmd_fill_buffer(
+            command_buffer,
+            self.breadcrumbs,
+            (slot * size_of::<u32>()) as vk::DeviceSize,
+            size_of::<u32>() as vk::DeviceSize,
+            self.trails[slot].passes.len() as u32,
+        );
+    }
+
+    pub fn submit(&mut self, command_buffer: vk::CommandBuffer, signal: FrameSignal) {
+        if let Some(trail) = self.trail_mut(command_buffer) {
+            trail.signal = Some(signal);
+            trail.submitted = Some(Instant::now());
+        }
+    }
+
+    // The frame finished in time, the hangs before were transient.
+    pub fn finished(&mut self) {
+        self.resets = 0;
+    }
+
+    // The frame signaling `signal` hung, true while it may be given up on and the app carry on.
+    // A lost device or too many hangs in a row can't be recovered from.
+    pub fn hang(&mut self, signal: FrameSignal, device_lost: bool) -> (HangReport, bool) {
+        let trail = self
+            .trails
+            .iter()
+            .enumerate()
+            .find(|(_, trail)| trail.signal == Some(signal) && trail.submitted.is_some());
+        let report = match trail {
+            Some((slot, trail)) => HangReport {
+                frame: trail.frame,
+                elapsed: trail.submitted.unwrap().elapsed(),
+                passes: trail
+                    .passes
+                    .iter()
+                    .map(|(label, recorded)| (*label, recorded.elapsed()))
+                    .collect(),
+                completed_passes: self.read_breadcrumb(slot) as usize,
+                device_lost,
+            },
+            None => HangReport {
+                frame: 0,
+                elapsed: self.timeout,
+                passes: vec![],
+                completed_passes: 0,
+                device_lost,
+            },
+        };
+
+        self.resets += 1;
+        let recoverable = !device_lost && self.resets <= self.max_resets;
+        (report, recoverable)
+    }
+
+    pub unsafe fn destroy(&mut self, device_context: &VkDeviceContext) {
+        device_context.destroy_buffer(self.breadcrumbs, self.breadcrumb_memory);
+    }
+
+    fn slot(&self, command_buffer: vk::CommandBuffer) -> Option<usize> {
+        self.trails
+            .iter()
+            .position(|trail| trail.command_buffer == command_buffer)
+    }
+
+    fn trail_mut(&mut self, command_buffer: vk::CommandBuffer) -> Option<&mut Trail> {
+        self.trails
+            .iter_mut()
+            .find(|trail| trail.command_buffer == command_buffer)
+    }
+
+    fn write_breadcrumb(&self, slot: usize, value: u32) {
+        unsafe {
+            (self.breadcrumb_memory.mapped as *mut u32)
+                .add(slot)
+                .write_volatile(value);
+        }
+    }
+
+    fn read_breadcrumb(&self, slot: usize) -> u32 {
+        unsafe {
+            (self.breadcrumb_memory.mapped as *const u32)
+                .add(slot)
+                .read_volatile()
+        }
+    }
+}
@@ -0,0 +1,117 @@
+use crate::math::Vec2;
+use crate::scene::Entity;
+use std::collections::HashMap;
+use winit::event::{ElementState, MouseButton, Touch, TouchPhase, WindowEvent};
+
+// What the pointer is over. The UI layer is hit-tested first and only a miss falls through to the scene.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum PointerTarget {
+    Ui(Entity),
+    // an egui window of the debug overlay
+    Overlay,
+    Scene(Entity),
+    // nothing hit, camera navigation
+    Background,
+}
+
+// Pointer state collected from window events.
+// The first finger down drives the same pointer as the left mouse button, so touch screens work without special casing.
+#[derive(Debug, Default)]
+pub struct Input {
+    pub pointer_position: Option<Vec2>,
+    pub pointer_pressed: bool,
+    pub touches: HashMap<u64, Vec2>,
+    primary_touch: Option<u64>,
+
+    hovered: Option<PointerTarget>,
+    // owner of the current drag, keeps the pointer until release whatever it moves over
+    captured: Option<PointerTarget>,
+}
+
+impl Input {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn handle_event(&mut self, event: &WindowEvent) {
+        match event {
+            WindowEvent::CursorMoved { position, .. } => {
+                self.pointer_position = Some(Vec2::new(position.x as f32, position.y as f32));
+            }
+            WindowEvent::CursorLeft { .. } => {
+                self.pointer_position = None;
+            }
+            WindowEvent::MouseInput {
+                state,
+                button: MouseButton::Left,
+                ..
+            } => {
+                self.pointer_pressed = *state == ElementState::Pressed;
+            }
+            WindowEvent::Touch(touch) => self.handle_touch(touch),
+            _ => {}
+        }
+    }
+
+    // Called once per update with the hit-test result. A press captures the pointer for the target
+    // under it, so dragging a gizmo over empty space doesn't start rotating the camera.
+    pub fn set_hovered(&mut self, hovered: Option<PointerTarget>) {
+        self.hovered = hovered;
+        if !self.pointer_pressed {
+            self.captured = None;
+        } else if self.captured.is_none() {
+            self.captured = hovered;
+        }
+    }
+
+    // Takes the pointer over for the rest of the drag, e.g. a gizmo handle picked on press
+    // overriding the entity behind it.
+    pub fn capture_pointer(&mut self, target: PointerTarget) {
+        if self.pointer_pressed {
+            self.captured = Some(target);
+        }
+    }
+
+    pub fn is_pointer_captured(&self) -> bool {
+        self.captured.is_some()
+    }
+
+    // Who gets the pointer events, the capture owner during a drag, the hovered target otherwise.
+    pub fn pointer_owner(&self) -> Option<PointerTarget> {
+        self.captured.or(self.hovered)
+    }
+
+    pub fn is_pointer_over_ui(&self) -> bool {
+        matches!(
+            self.pointer_owner(),
+            Some(PointerTarget::Ui(_) | PointerTarget::Overlay)
+        )
+    }
+
+    fn handle_touch(&mut self, touch: &Touch) {
+        let position = Vec2::new(touch.location.x as f32, touch.location.y as f32);
+        if self.primary_touch.is_none() || self.primary_touch == Some(touch.id) {
+            self.pointer_position = Some(position);
+        }
+
+        match touch.phase {
+            TouchPhase::Started => {
+                self.touches.insert(touch.id, position);
+                if self.primary_touch.is_none() {
+                    self.primary_touch = Some(touch.id);
+                    self.pointer_pressed = true;
+                }
+            }
+            TouchPhase::Moved => {
+                self.touches.insert(touch.id, position);
+            }
+            TouchPhase::Ended | TouchPhase::Cancelled => {
+                self.touches.remove(&touch.id);
+                if self.primary_touch == Some(touch.id) {
+                    self.primary_touch = None;
+                    self.pointer_pressed = false;
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,76 @@
+mod app;
+mod assets;
+mod cook;
+mod cursor;
+mod editor;
+mod error_overlay;
+mod frame_hooks;
+mod gpu;
+mod input;
+mod loaders;
+mod mirage;
+mod renderer;
+pub mod scene;
+pub mod ui;
+
+use app::Application;
+pub use ash;
+#[cfg(feature = "ffmpeg")]
+pub use assets::FfmpegSource;
+pub use assets::{AssetHandle, Material, MaterialParam, VideoFrame, VideoSource, VideoTexture};
+pub use cook::cook;
+pub use egui;
+pub use frame_hooks::RenderFrame;
+pub use gpu::{
+    AdapterInfo, AdapterSelection, AdapterType, Allocation, Allocator, ComputePipeline,
+    ComputeReader, PresentMode, RawHandles, RawResource, GPU,
+};
+pub use mirage::{Mirage, MirageConfig, SetupCallback};
+use mirage_core::cpu_profiler;
+pub use mirage_core::math;
+pub use renderer::{ShaderHooks, Shading};
+use winit::event_loop::{ControlFlow, EventLoop};
+
+pub fn run(event_loop: EventLoop<()>) {
+    run_with_config(event_loop, MirageConfig::default());
+}
+
+pub fn run_with_config(event_loop: EventLoop<()>, config: MirageConfig) {
+    let mut app = Application::new(config);
+
+    event_loop.set_control_flow(ControlFlow::Poll);
+    event_loop.run_app(&mut app).unwrap();
+}
+
+// Renders `frames` frames of `width` x `height` without a window and returns the pixels of the
+// last one, see `Mirage::read_back_frame`. The setup of `config` loads what is drawn.
+pub fn run_headless(
+    width: u32,
+    height: u32,
+    frames: u32,
+    config: MirageConfig,
+) -> Option<(Vec<u8>, u32, u32)> {
+    let mut mirage = Mirage::headless_with_config(width, height, &config);
+    for _ in 0..frames {
+        mirage.render();
+    }
+    mirage.read_back_frame()
+}
+
+// Entry point of the native activity, the library is packaged as libmirage.so into the APK.
+// Assets and shaders come from the embedded bundle, there is no filesystem access needed.
+#[cfg(target_os = "android")]
+#[no_mangle]
+fn android_main(android_app: winit::platform::android::activity::AndroidApp) {
+    use winit::platform::android::EventLoopBuilderExtAndroid;
+
+    android_logger::init_once(
+        android_logger::Config::default().with_max_level(log::LevelFilter::Info),
+    );
+
+    let event_loop = EventLoop::builder()
+        .with_android_app(android_app)
+        .build()
+        .unwrap();
+    run(event_loop);
+}
@@ -1,8 +1,11 @@
-use crate::assets::Assets;
-use crate::gpu::GPU;
-use crate::renderer::RenderObject;
+use crate::assets::{AssetHandle, Assets, Environment};
 use crate::scene::World;
 
-pub fn load_gltf_scene(world: &mut World, assets: &mut Assets, path: &str) {
-
+pub fn load_gltf_scene(
+    _world: &mut World,
+    _assets: &mut Assets,
+    path: &str,
+) -> Option<AssetHandle<Environment>> {
+    log::warn!("failed to load {}, glTF scenes aren't supported yet!", path);
+    None
 }
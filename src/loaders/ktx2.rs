@@ -0,0 +1,224 @@
+use crate::assets::{Assets, Texture};
+use crate::gpu::{MipFilter, Rhi, SamplerDesc, TextureFormat, GPU};
+use ::ktx2::{Format, Reader, SupercompressionScheme};
+use ash::vk;
+use std::io::Read;
+
+const KTX2_MAGIC: [u8; 12] = [
+    0xAB, 0x4B, 0x54, 0x58, 0x20, 0x32, 0x30, 0xBB, 0x0D, 0x0A, 0x1A, 0x0A,
+];
+// Khronos data format, https://registry.khronos.org/DataFormat/specs/1.3/dataformat.1.3.html
+const KHR_DF_TRANSFER_LINEAR: u8 = 1;
+const KHR_DF_TRANSFER_SRGB: u8 = 2;
+const KHR_DF_SAMPLE_DATATYPE_LINEAR: u8 = 0x10;
+
+pub fn is_ktx2(data: &[u8]) -> bool {
+    data.starts_with(&KTX2_MAGIC)
+}
+
+// 2D and cube map KTX2 textures with their mip chain as stored, block compressed data is
+// uploaded as is. Levels may be zstd supercompressed, Basis Universal (BasisLZ, UASTC) needs a
+// transcoder and is rejected.
+pub fn load_ktx2(data: &[u8]) -> Result<Texture, String> {
+    let reader = Reader::new(data).map_err(|error| error.to_string())?;
+    let header = reader.header();
+    if header.pixel_depth > 1 || header.layer_count > 1 {
+        return Err("only 2D textures and cube maps are supported, no arrays or volumes".into());
+    }
+    let format = header
+        .format
+        .ok_or("Basis Universal textures have to be transcoded first")?;
+    let (format, expand) = texture_format(format)?;
+
+    let mut pixels = vec![];
+    let mut mips = vec![];
+    for level in reader.levels() {
+        let level_data = match header.supercompression_scheme {
+            None => level.data.to_vec(),
+            Some(SupercompressionScheme::Zstandard) => {
+                let mut decoder =
+                    ruzstd::StreamingDecoder::new(level.data).map_err(|error| error.to_string())?;
+                let mut level_data = Vec::with_capacity(level.uncompressed_byte_length as usize);
+                decoder
+                    .read_to_end(&mut level_data)
+                    .map_err(|error| error.to_string())?;
+                level_data
+            }
+            Some(scheme) => return Err(format!("unsupported supercompression {:?}", scheme)),
+        };
+
+        let start = pixels.len();
+        match expand {
+            Expand::None => pixels.extend_from_slice(&level_data),
+            Expand::Bgra => level_data
+                .chunks_exact(4)
+                .for_each(|bgra| pixels.extend_from_slice(&[bgra[2], bgra[1], bgra[0], bgra[3]])),
+            // 3 byte texels are rarely sampleable, every device takes RGBA
+            Expand::Rgb => level_data
+                .chunks_exact(3)
+                .for_each(|rgb| pixels.extend_from_slice(&[rgb[0], rgb[1], rgb[2], 255])),
+        }
+        mips.push(start..pixels.len());
+    }
+
+    Ok(Texture {
+        width: header.pixel_width,
+        height: header.pixel_height.max(1),
+        mip_levels: mips.len() as u32,
+        // the faces of a level are stored back to back, the way they are uploaded
+        layers: header.face_count.max(1),
+        format,
+        color_space: format.color_space(),
+        pixels,
+        mips,
+        mip_filter: MipFilter::Linear,
+        sampler: SamplerDesc::default(),
+    })
+}
+
+// The first of the files, e.g. the BC7 and ASTC encodings of the same image, whose format the
+// device can sample. Only the headers are read.
+pub fn select_ktx2_variant<'a>(gpu: &GPU, paths: &[&'a str]) -> Option<&'a str> {
+    paths.iter().copied().find(|path| {
+        let Some(data) = Assets::load_raw(path) else {
+            return false;
+        };
+        let format = Reader::new(&*data)
+            .ok()
+            .and_then(|reader| reader.header().format)
+            .and_then(|format| texture_format(format).ok());
+        format.is_some_and(|(format, _)| gpu.is_texture_format_supported(format))
+    })
+}
+
+// Uncompressed RGBA8 textures as KTX2, with every level in `mips` or just level 0 without them.
+// What `mirage cook` emits for images, `load_ktx2` reads it back with the chain as is.
+pub fn write_ktx2(texture: &Texture) -> Result<Vec<u8>, String> {
+    let format = texture.upload_format();
+    let transfer_function = match format {
+        TextureFormat::Rgba8Srgb => KHR_DF_TRANSFER_SRGB,
+        TextureFormat::Rgba8Unorm => KHR_DF_TRANSFER_LINEAR,
+        format => return Err(format!("can't write {:?} textures", format)),
+    };
+    let levels = if texture.mips.is_empty() {
+        // a single level over all the pixels
+        let whole = 0..texture.pixels.len();
+        vec![whole]
+    } else {
+        texture.mips.clone()
+    };
+
+    // basic data format descriptor block, one 8 bit sample per channel, R G B and A ids, alpha
+    // stays linear in sRGB textures
+    let alpha = if transfer_function == KHR_DF_TRANSFER_SRGB {
+        15 | KHR_DF_SAMPLE_DATATYPE_LINEAR
+    } else {
+        15
+    };
+    let channels = [0, 1, 2, alpha];
+    let block_size = 24 + 16 * channels.len() as u32;
+    let mut dfd = vec![];
+    dfd.extend_from_slice(&(4 + block_size).to_le_bytes());
+    // vendor and descriptor type 0, version 2
+    dfd.extend_from_slice(&0u32.to_le_bytes());
+    dfd.extend_from_slice(&2u16.to_le_bytes());
+    dfd.extend_from_slice(&(block_size as u16).to_le_bytes());
+    // RGBSDA, BT.709 primaries, straight alpha
+    dfd.extend_from_slice(&[1, 1, transfer_function, 0]);
+    // a block is one texel of 4 bytes
+    dfd.extend_from_slice(&[0, 0, 0, 0]);
+    dfd.extend_from_slice(&[4, 0, 0, 0, 0, 0, 0, 0]);
+    for (index, channel) in channels.into_iter().enumerate() {
+        // bit offset, bit length - 1, channel, position, lower and upper
+        dfd.extend_from_slice(&(index as u16 * 8).to_le_bytes());
+        dfd.extend_from_slice(&[7, channel]);
+        dfd.extend_from_slice(&[0, 0, 0, 0]);
+        dfd.extend_from_slice(&0u32.to_le_bytes());
+        dfd.extend_from_slice(&255u32.to_le_bytes());
+    }
+
+    let header_size = 80 + 24 * levels.len();
+    let dfd_offset = header_size;
+    let mut level_offset = (dfd_offset + dfd.len()).next_multiple_of(4);
+
+    let mut bytes = Vec::with_capacity(level_offset + texture.pixels.len());
+    bytes.extend_from_slice(&KTX2_MAGIC);
+    for value in [
+        vk::Format::from(format).as_raw() as u32,
+        // type size
+        1,
+        texture.width,
+        texture.height,
+        // depth and array layers, 0 for neither
+        0,
+        0,
+        texture.layers,
+        levels.len() as u32,
+        // no supercompression
+        0,
+        dfd_offset as u32,
+        dfd.len() as u32,
+        // no key/value data
+        0,
+        0,
+    ] {
+        bytes.extend_from_slice(&value.to_le_bytes());
+    }
+    // no supercompression global data
+    bytes.extend_from_slice(&[0; 16]);
+
+    // the smallest level is stored first, the index still starts at level 0
+    let mut offsets = vec![0; levels.len()];
+    for (level, range) in levels.iter().enumerate().rev() {
+        offsets[level] = level_offset;
+        level_offset = (level_offset + range.len()).next_multiple_of(4);
+    }
+    for (range, offset) in levels.iter().zip(&offsets) {
+        for value in [*offset, range.len(), range.len()] {
+            bytes.extend_from_slice(&(value as u64).to_le_bytes());
+        }
+    }
+    bytes.extend_from_slice(&dfd);
+    for (range, offset) in levels.iter().zip(&offsets).rev() {
+        bytes.resize(*offset, 0);
+        bytes.extend_from_slice(&texture.pixels[range.clone()]);
+    }
+    Ok(bytes)
+}
+
+// Repacking of uncompressed texels into the RGBA8 layout of the texture format.
+enum Expand {
+    None,
+    Bgra,
+    Rgb,
+}
+
+fn texture_format(format: Format) -> Result<(TextureFormat, Expand), String> {
+    let texture_format = match format {
+        Format::R8G8B8A8_SRGB => (TextureFormat::Rgba8Srgb, Expand::None),
+        Format::R8G8B8A8_UNORM => (TextureFormat::Rgba8Unorm, Expand::None),
+        Format::B8G8R8A8_SRGB => (TextureFormat::Rgba8Srgb, Expand::Bgra),
+        Format::B8G8R8A8_UNORM => (TextureFormat::Rgba8Unorm, Expand::Bgra),
+        Format::R8G8B8_SRGB => (TextureFormat::Rgba8Srgb, Expand::Rgb),
+        Format::R8G8B8_UNORM => (TextureFormat::Rgba8Unorm, Expand::Rgb),
+        // the blocks are the same, without alpha they always decode opaque
+        Format::BC1_RGB_SRGB_BLOCK | Format::BC1_RGBA_SRGB_BLOCK => {
+            (TextureFormat::Bc1Srgb, Expand::None)
+        }
+        Format::BC1_RGB_UNORM_BLOCK | Format::BC1_RGBA_UNORM_BLOCK => {
+            (TextureFormat::Bc1Unorm, Expand::None)
+        }
+        Format::BC3_SRGB_BLOCK => (TextureFormat::Bc3Srgb, Expand::None),
+        Format::BC3_UNORM_BLOCK => (TextureFormat::Bc3Unorm, Expand::None),
+        Format::BC4_UNORM_BLOCK => (TextureFormat::Bc4Unorm, Expand::None),
+        Format::BC5_UNORM_BLOCK => (TextureFormat::Bc5Unorm, Expand::None),
+        Format::BC7_SRGB_BLOCK => (TextureFormat::Bc7Srgb, Expand::None),
+        Format::BC7_UNORM_BLOCK => (TextureFormat::Bc7Unorm, Expand::None),
+        Format::ETC2_R8G8B8A8_SRGB_BLOCK => (TextureFormat::Etc2Rgba8Srgb, Expand::None),
+        Format::ETC2_R8G8B8A8_UNORM_BLOCK => (TextureFormat::Etc2Rgba8Unorm, Expand::None),
+        Format::ASTC_4x4_SRGB_BLOCK => (TextureFormat::Astc4x4Srgb, Expand::None),
+        Format::ASTC_4x4_UNORM_BLOCK => (TextureFormat::Astc4x4Unorm, Expand::None),
+        format => return Err(format!("unsupported format {:?}", format)),
+    };
+    Ok(texture_format)
+}
@@ -1,3 +1,5 @@
 pub mod gltf;
+pub mod ktx2;
+pub mod obj;
 pub mod simple;
 
@@ -0,0 +1,352 @@
+use crate::assets::{AssetHandle, Assets, Geom, Material, Texture};
+use crate::error_overlay;
+use crate::math::Vec3;
+use crate::renderer::vertex::Vertex;
+use crate::renderer::Shading;
+use std::collections::HashMap;
+use std::io::Cursor;
+use tobj::LoadError;
+
+// One object of an OBJ file with the MTL material its faces use.
+#[derive(Debug, Clone)]
+pub struct ObjModel {
+    pub name: String,
+    pub geom: AssetHandle<Geom>,
+    pub material: Option<AssetHandle<Material>>,
+}
+
+// Loads every object of the OBJ at `path` as an indexed `Geom`. The MTL libraries and the
+// textures they name are looked up next to the OBJ, each material is shared by its objects.
+pub fn load_obj(assets: &mut Assets, path: &str) -> Option<Vec<ObjModel>> {
+    let data = Assets::load_raw(path)?;
+    let base_dir = path.rsplit_once('/').map_or("", |(dir, _)| dir);
+
+    let (models, materials) = match parse_obj(&data, base_dir) {
+        Ok(result) => result,
+        Err(error) => {
+            error_overlay::report(format!("obj {}", path), error);
+            return None;
+        }
+    };
+    let materials = materials.unwrap_or_else(|error| {
+        log::warn!("failed to load materials of {}! {}", path, error);
+        vec![]
+    });
+
+    let material_handles = materials
+        .iter()
+        .map(|material| load_material(assets, material, base_dir))
+        .collect::<Vec<_>>();
+
+    let models = models
+        .iter()
+        .map(|model| {
+            let obj_material = model.mesh.material_id.and_then(|id| materials.get(id));
+            ObjModel {
+                name: model.name.clone(),
+                geom: assets.handle(geom_from_mesh(&model.mesh, obj_material)),
+                material: model
+                    .mesh
+                    .material_id
+                    .and_then(|id| material_handles.get(id).cloned()),
+            }
+        })
+        .collect();
+    Some(models)
+}
+
+pub(crate) fn parse_obj(data: &[u8], base_dir: &str) -> tobj::LoadResult {
+    let mut buffer = Cursor::new(data);
+    // the face indices of each attribute stay apart, corners are welded by `geom_from_mesh`
+    let load_options = tobj::LoadOptions {
+        single_index: false,
+        triangulate: true,
+        ignore_points: true,
+        ignore_lines: true,
+    };
+    tobj::load_obj_buf(&mut buffer, &load_options, |mtl_path| {
+        let mtl_path = resolve(base_dir, mtl_path.to_str().unwrap());
+        match Assets::load_raw(&mtl_path) {
+            Some(file) => tobj::load_mtl_buf(&mut Cursor::new(file)),
+            None => Err(LoadError::OpenFileFailed),
+        }
+    })
+}
+
+// Welds the corners with the same position, uv and normal indices into one vertex, and gives
+// meshes without normals smooth ones. The diffuse color of the material goes to the vertices.
+pub(crate) fn geom_from_mesh(mesh: &tobj::Mesh, material: Option<&tobj::Material>) -> Geom {
+    let color = material
+        .and_then(|material| material.diffuse)
+        .unwrap_or([1.0, 1.0, 1.0]);
+    let has_uvs = !mesh.texcoord_indices.is_empty();
+    let has_normals = !mesh.normal_indices.is_empty();
+
+    let mut welded = HashMap::new();
+    let mut vertices = vec![];
+    // position index of every vertex, the smooth normals are shared across uv seams
+    let mut vertex_positions = vec![];
+    let mut indices = Vec::with_capacity(mesh.indices.len());
+    for corner in 0..mesh.indices.len() {
+        let position = mesh.indices[corner] as usize;
+        let uv = has_uvs.then(|| mesh.texcoord_indices[corner] as usize);
+        let normal = has_normals.then(|| mesh.normal_indices[corner] as usize);
+
+        let index = *welded.entry((position, uv, normal)).or_insert_with(|| {
+            let p = &mesh.positions[position * 3..][..3];
+            vertices.push(Vertex {
+                position: [p[0], p[1], p[2]],
+                color,
+                uv: uv.map_or([0.0, 0.0], |uv| {
+                    [mesh.texcoords[uv * 2], 1.0 - mesh.texcoords[uv * 2 + 1]]
+                }),
+                normal: normal.map_or([0.0, 0.0, 0.0], |normal| {
+                    let n = &mesh.normals[normal * 3..][..3];
+                    [n[0], n[1], n[2]]
+                }),
+            });
+            vertex_positions.push(position);
+            vertices.len() as u32 - 1
+        });
+        indices.push(index);
+    }
+
+    if !has_normals {
+        let normals = smooth_normals(&mesh.positions, &mesh.indices);
+        for (vertex, position) in vertices.iter_mut().zip(vertex_positions) {
+            let normal = normals[position];
+            vertex.normal = [normal.x, normal.y, normal.z];
+        }
+    }
+
+    Geom::new(vertices, indices)
+}
+
+// Sum of the normals of the faces around every position, weighted by their area since the cross
+// product isn't normalized before adding it up.
+fn smooth_normals(positions: &[f32], indices: &[u32]) -> Vec<Vec3> {
+    let position = |index: u32| {
+        let p = &positions[index as usize * 3..][..3];
+        Vec3::new(p[0], p[1], p[2])
+    };
+
+    let mut normals = vec![Vec3::zero(); positions.len() / 3];
+    for triangle in indices.chunks_exact(3) {
+        let [a, b, c] = [triangle[0], triangle[1], triangle[2]];
+        let face_normal = (position(b) - position(a)).cross(position(c) - position(a));
+        for index in [a, b, c] {
+            normals[index as usize] = normals[index as usize] + face_normal;
+        }
+    }
+
+    normals
+        .into_iter()
+        .map(|normal| {
+            if normal.len_sq() > f32::EPSILON {
+                normal.normalize()
+            } else {
+                Vec3::new(0.0, 1.0, 0.0)
+            }
+        })
+        .collect()
+}
+
+fn load_material(
+    assets: &mut Assets,
+    material: &tobj::Material,
+    base_dir: &str,
+) -> AssetHandle<Material> {
+    let mut result = Material::new(Shading::load("simple.spv"));
+    if let Some(texture) = &material.diffuse_texture {
+        let path = resolve(base_dir, texture);
+        let handle = assets.handle_path::<Texture>(&path);
+        if handle.is_none() {
+            log::warn!(
+                "failed to load texture {} of material {}!",
+                path,
+                material.name
+            );
+        }
+        result.set_texture("texture", handle);
+    }
+    assets.handle(result)
+}
+
+// Paths in OBJ and MTL files are relative to the OBJ, backslashes are from Windows exporters.
+fn resolve(base_dir: &str, path: &str) -> String {
+    let path = path.replace('\\', "/");
+    if base_dir.is_empty() || path.starts_with('/') {
+        path
+    } else {
+        format!("{}/{}", base_dir, path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{geom_from_mesh, parse_obj, smooth_normals};
+    use crate::assets::Geom;
+    use crate::math::Vec3;
+
+    fn load(obj: &str) -> Geom {
+        let (models, _) = parse_obj(obj.as_bytes(), "").expect("failed to parse obj!");
+        assert_eq!(models.len(), 1);
+        geom_from_mesh(&models[0].mesh, None)
+    }
+
+    fn assert_close(a: [f32; 3], b: Vec3) {
+        let b = [b.x, b.y, b.z];
+        assert!(
+            a.iter().zip(b).all(|(a, b)| (a - b).abs() < 1e-5),
+            "{:?} != {:?}",
+            a,
+            b
+        );
+    }
+
+    const QUAD: &str = "
+v 0 0 0
+v 1 0 0
+v 1 1 0
+v 0 1 0
+vt 0 0
+vt 1 0
+vt 1 1
+vt 0 1
+vn 0 0 1
+f 1/1/1 2/2/1 3/3/1 4/4/1
+";
+
+    #[test]
+    fn corners_with_the_same_indices_are_welded() {
+        let geom = load(QUAD);
+        // the quad is split in two triangles sharing the diagonal
+        assert_eq!(geom.vertices.len(), 4);
+        assert_eq!(geom.indices.len(), 6);
+        assert_eq!(geom.indices[0], geom.indices[3]);
+        assert_eq!(geom.indices[2], geom.indices[4]);
+
+        for vertex in &geom.vertices {
+            assert_eq!(vertex.normal, [0.0, 0.0, 1.0]);
+            assert_eq!(vertex.color, [1.0, 1.0, 1.0]);
+            // v points down in the uv space of the renderer
+            assert_eq!(vertex.uv, [vertex.position[0], 1.0 - vertex.position[1]]);
+        }
+    }
+
+    #[test]
+    fn corners_differing_in_uv_or_normal_stay_apart() {
+        // two faces folded along the x axis, each with its own normal and uvs
+        let geom = load(
+            "
+v 0 0 0
+v 1 0 0
+v 0 1 0
+v 0 0 1
+vt 0 0
+vt 1 0
+vt 0 1
+vt 0.5 0.5
+vn 0 0 1
+vn 0 -1 0
+f 1/1/1 2/2/1 3/3/1
+f 1/1/2 4/4/2 2/2/2
+",
+        );
+        // the shared edge is split by its normals
+        assert_eq!(geom.vertices.len(), 6);
+
+        let uv_seam = load(
+            "
+v 0 0 0
+v 1 0 0
+v 0 1 0
+v 0 0 1
+vt 0 0
+vt 1 0
+vt 0 1
+vt 0.5 0.5
+f 1/1 2/2 3/3
+f 1/4 4/4 2/2
+",
+        );
+        // position 1 has two uvs, position 2 keeps one
+        assert_eq!(uv_seam.vertices.len(), 5);
+    }
+
+    #[test]
+    fn meshes_without_normals_get_smooth_ones_across_uv_seams() {
+        // a ridge along x between two slopes facing up and to either side
+        let geom = load(
+            "
+v 0 1 0
+v 1 1 0
+v 0.5 0 -1
+v 0.5 0 1
+vt 0 0
+vt 1 0
+vt 0.5 1
+vt 0.5 0.5
+f 1/1 2/2 3/3
+f 2/2 1/4 4/3
+",
+        );
+        assert_eq!(geom.vertices.len(), 5);
+        for vertex in &geom.vertices {
+            let expected = match vertex.position[2] {
+                -1.0 => Vec3::new(0.0, 1.0, -1.0).normalize(),
+                1.0 => Vec3::new(0.0, 1.0, 1.0).normalize(),
+                _ => Vec3::new(0.0, 1.0, 0.0),
+            };
+            assert_close(vertex.normal, expected);
+        }
+        // the uv seam at the start of the ridge doesn't split its normal
+        let seam = geom
+            .vertices
+            .iter()
+            .filter(|vertex| vertex.position == [0.0, 1.0, 0.0])
+            .collect::<Vec<_>>();
+        assert_eq!(seam.len(), 2);
+        assert_ne!(seam[0].uv, seam[1].uv);
+        assert_eq!(seam[0].normal, seam[1].normal);
+    }
+
+    #[test]
+    fn smooth_normals_are_weighted_by_area() {
+        // a large face towards +z and a small one towards +x share the first position
+        let positions = [
+            0.0, 0.0, 0.0, //
+            4.0, 0.0, 0.0, //
+            0.0, 4.0, 0.0, //
+            0.0, 1.0, 0.0, //
+            0.0, 0.0, 1.0,
+        ];
+        let normals = smooth_normals(&positions, &[0, 1, 2, 0, 3, 4]);
+        let expected = Vec3::new(1.0, 0.0, 16.0).normalize();
+        assert_close([normals[0].x, normals[0].y, normals[0].z], expected);
+        assert_close(
+            [normals[1].x, normals[1].y, normals[1].z],
+            Vec3::new(0.0, 0.0, 1.0),
+        );
+    }
+
+    #[test]
+    fn degenerate_faces_point_up() {
+        let normals = smooth_normals(&[0.0; 9], &[0, 1, 2]);
+        assert_eq!(normals, vec![Vec3::new(0.0, 1.0, 0.0); 3]);
+    }
+
+    #[test]
+    fn material_diffuse_colors_the_vertices() {
+        let (models, _) = parse_obj(QUAD.as_bytes(), "").expect("failed to parse obj!");
+        let material = tobj::Material {
+            diffuse: Some([0.5, 0.25, 1.0]),
+            ..Default::default()
+        };
+        let geom = geom_from_mesh(&models[0].mesh, Some(&material));
+        assert!(geom
+            .vertices
+            .iter()
+            .all(|vertex| vertex.color == [0.5, 0.25, 1.0]));
+    }
+}
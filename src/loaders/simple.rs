@@ -1,11 +1,15 @@
-use crate::assets::{Assets, Geom, Material, Texture};
-use crate::math::{Euler, Vec3};
+use crate::assets::{AssetHandle, Assets, Environment, Geom, Material, Texture};
+use crate::math::{Quat, Vec3};
 use crate::renderer::Shading;
 use crate::scene::camera::Camera;
 use crate::scene::{StaticMesh, Transform, World};
 use std::f32::consts::PI;
 
-pub fn load_simple_scene(world: &mut World, assets: &mut Assets) {
+// The environment the scene refers to, applied by the caller.
+pub fn load_simple_scene(
+    world: &mut World,
+    assets: &mut Assets,
+) -> Option<AssetHandle<Environment>> {
     let entity = world.add_entity();
     let geom_handle = assets.handle_path::<Geom>("viking_room.obj");
     let material_handle = assets.handle(Material::new(Shading::load("simple.spv")));
@@ -18,7 +22,7 @@ pub fn load_simple_scene(world: &mut World, assets: &mut Assets) {
         entity,
         Transform::new(
             Vec3::new(1.0, 0.0, -0.8),
-            Euler::default(),
+            Quat::identity(),
             Vec3::new(2.0, 2.0, 2.0),
         ),
     );
@@ -37,17 +41,41 @@ pub fn load_simple_scene(world: &mut World, assets: &mut Assets) {
         entity,
         Transform::new(
             Vec3::new(3.0, 0.0, 1.2),
-            Euler::default(),
+            Quat::identity(),
             Vec3::new(2.0, 2.0, 2.0),
         ),
     );
 
     world.add_entity_comp(entity, StaticMesh::new(geom_handle, Some(material_handle)));
 
+    // the terrain stages are GLSL, built only where glslc was found
+    let terrain_shading = Shading::load_terrain();
+    if terrain_shading.is_available() {
+        let entity = world.add_entity();
+        let terrain_geom_handle = assets.handle(Geom::plane(8.0, 16));
+        let terrain_material_handle = assets.handle(Material::new(terrain_shading));
+        let texture_handle = assets.handle_path::<Texture>("texture.jpg");
+        let material = assets.load_mut(&terrain_material_handle).unwrap();
+        material.set_texture("texture", texture_handle);
+
+        world.add_entity_comp(
+            entity,
+            Transform::new(Vec3::new(0.0, -1.0, 0.0), Quat::identity(), Vec3::one()),
+        );
+        world.add_entity_comp(
+            entity,
+            StaticMesh::new(Some(terrain_geom_handle), Some(terrain_material_handle)),
+        );
+    } else {
+        log::warn!("terrain shaders not built, the simple scene has no terrain!");
+    }
+
     let camera = world.add_entity();
     world.add_entity_comp(
         camera,
-        Transform::new(Vec3::new(0.0, 10.0, -10.0), Euler::default(), Vec3::one()),
+        Transform::new(Vec3::new(0.0, 10.0, -10.0), Quat::identity(), Vec3::one()),
     );
     world.add_entity_comp(camera, Camera::new(PI / 2.0, 1.0, 0.01));
+
+    assets.handle_path::<Environment>("simple.environment")
 }
@@ -1,19 +1,23 @@
-mod math;
-mod mirage;
-mod scene;
-mod app;
-mod renderer;
-mod gpu;
-mod loaders;
-mod assets;
+use std::path::Path;
+use std::process::ExitCode;
+use winit::event_loop::EventLoop;
 
-use winit::event_loop::{ControlFlow, EventLoop};
-use app::Application;
+fn main() -> ExitCode {
+    let args = std::env::args().collect::<Vec<_>>();
+    if args.get(1).is_some_and(|command| command == "cook") {
+        let [_, _, input, output] = args.as_slice() else {
+            eprintln!("usage: mirage cook <input> <output>");
+            return ExitCode::from(2);
+        };
+        return match mirage::cook(Path::new(input), Path::new(output)) {
+            Ok(()) => ExitCode::SUCCESS,
+            Err(error) => {
+                eprintln!("{}", error);
+                ExitCode::FAILURE
+            }
+        };
+    }
 
-fn main() {
-    let mut app = Application::new();
-
-    let event_loop = EventLoop::new().unwrap();
-    event_loop.set_control_flow(ControlFlow::Poll);
-    event_loop.run_app(&mut app).unwrap();
+    mirage::run(EventLoop::new().unwrap());
+    ExitCode::SUCCESS
 }
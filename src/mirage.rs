@@ -1,86 +1,452 @@
 use crate::assets::*;
+use crate::cpu_profiler;
+use crate::cursor::{egui_cursor_icon, window_icon, CursorShape, Cursors};
+use crate::editor::{CursorStyle, GridSnap, MeasureTool, TranslateDrag};
+use crate::error_overlay;
+use crate::frame_hooks::{FrameHooks, RenderFrame, UpdateHook};
 use crate::gpu::*;
+use crate::input::{Input, PointerTarget};
+use crate::loaders::gltf::load_gltf_scene;
+use crate::loaders::ktx2::select_ktx2_variant;
+use crate::loaders::obj::load_obj;
+use crate::loaders::simple::load_simple_scene;
 use crate::math::*;
 use crate::renderer::*;
-use crate::scene::camera::Camera;
+use crate::scene::camera::{window_camera, Camera, CameraTarget};
+use crate::scene::serialize::{self, Migrations};
 use crate::scene::*;
+use crate::ui::{hit_test_ui, layout_ui, Viewport};
 use ash::vk;
-use std::cell::{Cell, RefCell};
+use std::cell::{Cell, RefCell, RefMut};
 use std::rc::Rc;
-use std::time::Instant;
+use std::time::{Duration, Instant};
+use winit::event::WindowEvent;
+use winit::event_loop::ActiveEventLoop;
 use winit::window::Window;
-use crate::loaders::gltf::load_gltf_scene;
-use crate::loaders::simple::load_simple_scene;
+
+// The app's code run on every new Mirage, where hooks, the UI and scenes are set up.
+pub type SetupCallback = Rc<dyn Fn(&mut Mirage)>;
+type UiCallback = Box<dyn FnMut(&egui::Context)>;
+
+// What has to be known before the GPU exists.
+#[derive(Clone)]
+pub struct MirageConfig {
+    pub adapter: AdapterSelection,
+    // see `Mirage::set_present_mode`
+    pub present_mode: PresentMode,
+    // called once the Mirage is created, `run_with_config` has no other way to hand it out
+    pub setup: Option<SetupCallback>,
+}
+
+impl MirageConfig {
+    pub fn with_setup(mut self, setup: impl Fn(&mut Mirage) + 'static) -> Self {
+        self.setup = Some(Rc::new(setup));
+        self
+    }
+}
+
+impl Default for MirageConfig {
+    // The adapter from `MIRAGE_ADAPTER`, see `AdapterSelection::from_env`.
+    fn default() -> Self {
+        Self {
+            adapter: AdapterSelection::from_env(),
+            present_mode: PresentMode::default(),
+            setup: None,
+        }
+    }
+}
+
+impl std::fmt::Debug for MirageConfig {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("MirageConfig")
+            .field("adapter", &self.adapter)
+            .field("present_mode", &self.present_mode)
+            .field("setup", &self.setup.is_some())
+            .finish()
+    }
+}
 
 pub struct Mirage {
     gpu: Rc<GPU>,
     assets: Rc<RefCell<Assets>>,
     gpu_assets: Rc<RefCell<GPUAssets>>,
-    // pub ui_state: egui_winit::State,
+    egui_context: egui::Context,
+    // None when headless, egui then runs without platform input
+    ui_state: Option<egui_winit::State>,
+    // draws the app's debug windows, see `ui`
+    ui_callback: Option<UiCallback>,
+    // the app's code at fixed points of the frame, see `FrameHooks`
+    hooks: FrameHooks,
+    // what egui wants over the overlay, applied with the editor cursors
+    egui_cursor: egui::CursorIcon,
     command_pool: vk::CommandPool,
     command_buffers: Vec<vk::CommandBuffer>,
-    image_available_semaphores: Vec<vk::Semaphore>,
-    render_finished_semaphores: Vec<vk::Semaphore>,
-    in_flight_fences: Vec<vk::Fence>,
+    frame_sync: FrameSync,
     frame_index: Cell<usize>,
+    swap_chain_dirty: bool,
+    // the swap chain image the last frame went to, see `read_back_frame`
+    last_image_index: Option<u32>,
 
+    pub input: Input,
     timer: Instant,
+    started: Instant,
+    // bound for every shader, see `GlobalsData`
+    globals: GlobalsData,
+    camera_uniforms: Rc<CameraUniforms>,
     forward_renderer: ForwardRenderer,
+    shadow_renderer: ShadowRenderer,
+    decal_renderer: DecalRenderer,
+    pub contact_shadow_renderer: ContactShadowRenderer,
+    post_chain: PostChain,
+    normal_debugger: NormalDebugger,
+    measurement_renderer: MeasurementRenderer,
+    trail_renderer: TrailRenderer,
+    culling_debugger: CullingDebugger,
+    skeleton_debugger: SkeletonDebugger,
+    debug_draw_renderer: DebugDrawRenderer,
+    outline_renderer: OutlineRenderer,
+    egui_renderer: EguiRenderer,
+    text_renderer: TextRenderer,
+    video_renderer: VideoRenderer,
+    texture_camera_renderer: TextureCameraRenderer,
+    // tileable noise for effects sampling it, see `NoiseTextures`
+    pub noise: NoiseTextures,
+    ibl_baker: IblBaker,
+    // the prefiltered environment lighting the standard shading, None without an environment
+    ibl: Option<IblTextures>,
+    brdf_lut: AssetHandle<Texture>,
+    // bound in place of the environment maps while there is no environment
+    black_cube: AssetHandle<Texture>,
+    pub grid_snap: GridSnap,
+    // dragging a picked entity moves it, snapped by `grid_snap`
+    pub drag_to_translate: bool,
+    translate_drag: Option<TranslateDrag>,
+    // clicks on the scene pick the ends of new measurements while enabled, see `MeasureTool`
+    pub measure_tool: MeasureTool,
+    // picking and raycasts test the mesh triangles inside the boxes, exact but slower
+    pub pick_triangles: bool,
+    pub cursor_style: CursorStyle,
+    // a window with the pass timings while GPU profiling is on
+    pub show_gpu_timings: bool,
+    // set by the app, wins over the cursor style
+    cursor_override: Option<CursorShape>,
+    cursors: Cursors,
+    shader_compiler: ShaderCompiler,
+    asset_watcher: AssetWatcher,
+    // GPU work posted by other threads, run at the start of every update
+    render_queue: RenderQueue<Mirage>,
     scheduler: Scheduler,
     world: World,
+    // world space boxes of the meshes, refit every update
+    pub bvh: Bvh,
+    // upgrade comps of older `.scene` files, see `Migrations`
+    pub scene_migrations: Migrations,
+    // the last one applied, written to saved scenes
+    environment: Option<AssetHandle<Environment>>,
+    // unjittered, of the last generated render context
+    previous_view_projection: Option<Mat4>,
 }
 
 impl Mirage {
     pub fn new(window: Rc<Window>) -> Self {
-        let gpu = Rc::new(GPU::new(window));
+        Self::with_config(window, &MirageConfig::default())
+    }
+
+    pub fn with_config(window: Rc<Window>, config: &MirageConfig) -> Self {
+        let gpu = GPU::new(window, &config.adapter, config.present_mode);
+        Self::with_gpu(gpu).set_up(config)
+    }
+
+    // Without a window, frames of `width` x `height` are rendered offscreen whenever `render` is
+    // called and can be copied out with `read_back_frame`, e.g. for golden image tests in CI.
+    pub fn new_headless(width: u32, height: u32) -> Self {
+        Self::headless_with_config(width, height, &MirageConfig::default())
+    }
+
+    pub fn headless_with_config(width: u32, height: u32, config: &MirageConfig) -> Self {
+        Self::with_gpu(GPU::new_headless(width, height, &config.adapter)).set_up(config)
+    }
+
+    fn set_up(mut self, config: &MirageConfig) -> Self {
+        if let Some(setup) = &config.setup {
+            setup(&mut self);
+        }
+        self
+    }
+
+    // Every adapter of the instance, see `MirageConfig::adapter` to pick one.
+    pub fn adapters(&self) -> &[AdapterInfo] {
+        &self.gpu.device_context.adapters
+    }
+
+    // The adapter the GPU runs on.
+    pub fn adapter(&self) -> &AdapterInfo {
+        &self.gpu.device_context.adapter
+    }
+
+    fn with_gpu(gpu: GPU) -> Self {
+        let gpu = Rc::new(gpu);
         let assets = Rc::new(RefCell::new(Assets::new()));
         let gpu_assets = Rc::new(RefCell::new(GPUAssets::new(gpu.clone(), assets.clone())));
-        // let egui_context = egui::Context::default();
-        // let egui_state = egui_winit::State::new(
-        //     egui_context,
-        //     egui::ViewportId::ROOT,
-        //     &gpu.context.window,
-        //     Some(gpu.context.window.scale_factor() as f32),
-        //     None
-        // );
+        let egui_context = egui::Context::default();
+        let ui_state = gpu.context.window.borrow().as_ref().map(|window| {
+            egui_winit::State::new(
+                egui_context.clone(),
+                egui::ViewportId::ROOT,
+                &**window,
+                Some(window.scale_factor() as f32),
+                None,
+                None,
+            )
+        });
 
         let command_pool = Self::create_command_pools(&gpu);
 
-        let mut forward_renderer = ForwardRenderer::new(&gpu);
-        forward_renderer.depth_reverse_z = true;
+        let camera_uniforms = Rc::new(CameraUniforms::new(&gpu, ForwardRenderer::FRAMES_IN_FLIGHT));
+        let forward_renderer = ForwardRenderer::new(&gpu, camera_uniforms.clone());
+        let shadow_renderer = ShadowRenderer::new(&gpu, &camera_uniforms);
+        let decal_renderer = DecalRenderer::new(&gpu, &forward_renderer);
+        let contact_shadow_renderer = ContactShadowRenderer::new(&gpu, &forward_renderer);
+        let post_chain = PostChain::new(&gpu, &forward_renderer.scene_color);
+        let measurement_renderer = MeasurementRenderer::new(&mut assets.borrow_mut());
+        let trail_renderer = TrailRenderer::new(&mut assets.borrow_mut());
+        let culling_debugger = CullingDebugger::new(&mut assets.borrow_mut());
+        let skeleton_debugger = SkeletonDebugger::new(&mut assets.borrow_mut());
+        let debug_draw_renderer = DebugDrawRenderer::new(&mut assets.borrow_mut());
+        let outline_renderer = OutlineRenderer::new(&gpu);
+        let egui_renderer = EguiRenderer::new(
+            &gpu,
+            assets.clone(),
+            gpu_assets.clone(),
+            ForwardRenderer::FRAMES_IN_FLIGHT,
+        );
+        let text_renderer = TextRenderer::new(
+            &gpu,
+            assets.clone(),
+            gpu_assets.clone(),
+            ForwardRenderer::FRAMES_IN_FLIGHT,
+        );
+        let video_renderer = VideoRenderer::new(&gpu, gpu_assets.clone());
+        let texture_camera_renderer = TextureCameraRenderer::new(&gpu, gpu_assets.clone());
+        let normal_debugger =
+            NormalDebugger::new(&gpu, gpu_assets.clone(), &mut assets.borrow_mut());
         let command_buffers =
             Self::create_command_buffers(&gpu, command_pool, ForwardRenderer::FRAMES_IN_FLIGHT);
-        let (image_available_semaphores, render_finished_semaphores, in_flight_fences) =
-            Self::create_sync_objects(&gpu, ForwardRenderer::FRAMES_IN_FLIGHT);
+        let frame_sync = FrameSync::new(&gpu, ForwardRenderer::FRAMES_IN_FLIGHT);
 
+        let noise = Self::create_noise_textures(&gpu, &mut assets.borrow_mut());
+        // sampled by the dissolve of the standard shading
+        let dissolve_noise = gpu_assets
+            .borrow()
+            .get_texture(noise.perlin.clone())
+            .expect("failed to upload noise texture!");
+        camera_uniforms.set_noise(&dissolve_noise.texture);
+        let blue_noise = gpu_assets
+            .borrow()
+            .get_texture(noise.blue.clone())
+            .expect("failed to upload noise texture!");
+        camera_uniforms.set_blue_noise(&blue_noise.texture);
+        let ibl_baker = IblBaker::new(&gpu);
+        let brdf_lut = assets.borrow_mut().handle(ibl_baker.brdf_lut());
+        let black_cube = assets.borrow_mut().handle(Texture {
+            width: 1,
+            height: 1,
+            mip_levels: 1,
+            layers: 6,
+            format: TextureFormat::Rgba8Srgb,
+            color_space: ColorSpace::Srgb,
+            pixels: vec![0; 6 * 4],
+            mips: vec![],
+            mip_filter: MipFilter::Linear,
+            sampler: SamplerDesc::default(),
+        });
         let scheduler = Self::create_scheduler();
 
-        Self {
+        let mut mirage = Self {
             gpu,
             assets,
             gpu_assets,
-            // ui_state: egui_state,
+            egui_context,
+            ui_state,
+            ui_callback: None,
+            hooks: FrameHooks::default(),
+            egui_cursor: egui::CursorIcon::Default,
             command_pool,
             command_buffers,
-            image_available_semaphores,
-            render_finished_semaphores,
-            in_flight_fences,
+            frame_sync,
             frame_index: Cell::new(0),
+            swap_chain_dirty: false,
+            last_image_index: None,
 
+            input: Input::new(),
             timer: Instant::now(),
+            started: Instant::now(),
+            globals: GlobalsData::default(),
+            camera_uniforms,
             forward_renderer,
+            shadow_renderer,
+            decal_renderer,
+            contact_shadow_renderer,
+            post_chain,
+            normal_debugger,
+            measurement_renderer,
+            trail_renderer,
+            culling_debugger,
+            skeleton_debugger,
+            debug_draw_renderer,
+            outline_renderer,
+            egui_renderer,
+            text_renderer,
+            video_renderer,
+            texture_camera_renderer,
+            noise,
+            ibl_baker,
+            ibl: None,
+            brdf_lut,
+            black_cube,
+            grid_snap: GridSnap::default(),
+            drag_to_translate: false,
+            translate_drag: None,
+            measure_tool: MeasureTool::default(),
+            pick_triangles: false,
+            cursor_style: CursorStyle::default(),
+            show_gpu_timings: false,
+            cursor_override: None,
+            cursors: Cursors::new(),
+            shader_compiler: ShaderCompiler::new(),
+            asset_watcher: AssetWatcher::new(),
+            render_queue: RenderQueue::new(),
             world: World::new(),
             scheduler,
+            bvh: Bvh::new(),
+            scene_migrations: Migrations::new(),
+            environment: None,
+            previous_view_projection: None,
+        };
+        mirage.world.insert_resource(DebugDraw::new());
+        mirage.bind_ibl();
+        mirage
+    }
+
+    fn create_noise_textures(gpu: &Rc<GPU>, assets: &mut Assets) -> NoiseTextures {
+        let generator = NoiseGenerator::new(gpu);
+        NoiseTextures {
+            perlin: assets.handle(generator.generate(&NoiseDesc::perlin(256, 8))),
+            worley: assets.handle(generator.generate(&NoiseDesc::worley(128, 6))),
+            blue: assets.handle(generator.generate(&NoiseDesc::blue(64))),
         }
     }
 
+    // For threads that need the GPU or the assets, see `RenderQueue`.
+    pub fn render_sender(&self) -> RenderSender<Mirage> {
+        self.render_queue.sender()
+    }
+
+    // For custom Vulkan code, see `GPU::raw`.
+    pub fn get_gpu(&self) -> Rc<GPU> {
+        self.gpu.clone()
+    }
+
+    // The Vulkan image of a texture, uploaded on first use. Code writing into it, e.g. a video
+    // decoder, has to leave it in SHADER_READ_ONLY_OPTIMAL with all of its mip levels.
+    pub fn get_raw_texture(&self, handle: &AssetHandle<Texture>) -> Option<VkTexture> {
+        let texture = self.gpu_assets.borrow().get_texture(handle.clone())?;
+        Some(texture.texture)
+    }
+
+    // Decodes the texture on a loader thread, then registers and uploads it at the start of an
+    // update and hands the handle over, None when it failed to load.
+    pub fn load_texture_async(
+        &self,
+        path: &str,
+        on_loaded: impl FnOnce(&mut Mirage, Option<AssetHandle<Texture>>) + Send + 'static,
+    ) {
+        let sender = self.render_sender();
+        let path = path.to_string();
+        std::thread::spawn(move || {
+            let texture = Assets::load_raw(&path).and_then(|data| Texture::load(&data));
+            sender.post(move |mirage| {
+                // reported here, the overlay lives on the main thread
+                if texture.is_none() {
+                    error_overlay::report(format!("texture {}", path), "failed to load");
+                }
+                let handle = texture.map(|texture| mirage.assets.borrow_mut().handle(texture));
+                if let Some(handle) = &handle {
+                    mirage.gpu_assets.borrow().get_texture(handle.clone());
+                }
+                on_loaded(mirage, handle);
+            });
+        });
+    }
+
+    // The first of the encodings of one image the device can sample, e.g. a BC7 and an ASTC KTX2
+    // file, None when it supports none of them.
+    pub fn load_texture_variant(&mut self, paths: &[&str]) -> Option<AssetHandle<Texture>> {
+        let path = select_ktx2_variant(&self.gpu, paths)?;
+        self.assets.borrow_mut().handle_path::<Texture>(path)
+    }
+
+    // E.g. the material of a `StaticMesh`, to change its params with `Material::set_param`.
+    pub fn get_material_mut(&self, handle: &AssetHandle<Material>) -> Option<RefMut<'_, Material>> {
+        RefMut::filter_map(self.assets.borrow_mut(), |assets| assets.load_mut(handle)).ok()
+    }
+
+    // Starts playing the source, the video's texture goes on materials like any other.
+    pub fn play_video(
+        &mut self,
+        source: Box<dyn VideoSource>,
+        looping: bool,
+    ) -> AssetHandle<VideoTexture> {
+        let mut assets = self.assets.borrow_mut();
+        let video = VideoTexture::new(source, looping, &mut assets);
+        assets.handle(video)
+    }
+
+    // Plays the file with the ffmpeg executable, None when it can't be opened.
+    #[cfg(feature = "ffmpeg")]
+    pub fn load_video(&mut self, path: &str, looping: bool) -> Option<AssetHandle<VideoTexture>> {
+        let mut assets = self.assets.borrow_mut();
+        let video = VideoTexture::open(path, looping, &mut assets)?;
+        Some(assets.handle(video))
+    }
+
+    pub fn get_video_mut(
+        &self,
+        handle: &AssetHandle<VideoTexture>,
+    ) -> Option<RefMut<'_, VideoTexture>> {
+        RefMut::filter_map(self.assets.borrow_mut(), |assets| assets.load_mut(handle)).ok()
+    }
+
+    // Stops decoding, the texture keeps the last frame until the video's GPU side is released on
+    // the next update.
+    pub fn remove_video(&mut self, handle: &AssetHandle<VideoTexture>) {
+        self.assets.borrow_mut().remove(handle);
+    }
+
+    // Noise beyond the startup set, uploaded on first use like loaded textures.
+    pub fn generate_noise(&mut self, desc: &NoiseDesc) -> AssetHandle<Texture> {
+        let texture = NoiseGenerator::new(&self.gpu).generate(desc);
+        self.assets.borrow_mut().handle(texture)
+    }
+
     pub fn create_scheduler() -> Scheduler {
         let mut scheduler = Scheduler::new();
+        scheduler.add_system_with_access(
+            SystemAccess::new().write::<Lifetime>().write::<Pooled>(),
+            expire_lifetimes,
+        );
+        scheduler.add_system_with_access(
+            SystemAccess::new()
+                .read::<Transform>()
+                .read::<Pooled>()
+                .write::<Trail>(),
+            record_trails,
+        );
         scheduler.add_system(|world: &mut World, state: &SystemState| {
             let query = Query::<(&mut Transform, Option<&Camera>)>::new(world);
             for (transform, camera) in query {
                 if camera.is_none() {
-                    transform.rotation = Euler::new(0.0, state.elapsed_time, 0.0);
+                    transform.set_euler(Euler::new(0.0, state.elapsed_time, 0.0));
                 }
             }
         });
@@ -88,192 +454,1509 @@ impl Mirage {
         scheduler
     }
 
-    pub fn generate_render_context(&mut self) -> RenderContext {
+    // The window camera's context with every object, `extract_render` culls them.
+    fn generate_render_context(&mut self) -> RenderContext {
         let mut objects = vec![];
+        self.sync_camera_depth();
+
+        let motion_query =
+            Query::<(&Transform, &mut Motion, Option<&Skeleton>)>::new(&mut self.world);
+        for (transform, motion, skeleton) in motion_query {
+            let palette = skeleton.map(Skeleton::palette).unwrap_or_default();
+            motion.advance(transform.matrix(), palette);
+        }
 
-        let query = Query::<(&Transform, &StaticMesh)>::new(&mut self.world);
-        for (transform, static_mesh) in query {
-            match (&static_mesh.geom, &static_mesh.material) {
-                (Some(geom), Some(material)) => {
-                    let object =
+        // one walk over the entities, in query order, keeps their ids for the outline
+        let assets = self.assets.borrow();
+        for entity in self.world.ordered_entities() {
+            let world = &self.world;
+            let Some(transform) = world.get_entity_comp::<Transform>(entity) else {
+                continue;
+            };
+            if world
+                .get_entity_comp::<Pooled>(entity)
+                .is_some_and(|pooled| !pooled.active)
+            {
+                continue;
+            }
+            let mut meshes = vec![];
+            if let Some(static_mesh) = world.get_entity_comp::<StaticMesh>(entity) {
+                if let (Some(geom), Some(material)) = (&static_mesh.geom, &static_mesh.material) {
+                    let mut object =
                         RenderObject::new(geom.clone(), material.clone(), transform.matrix());
-                    objects.push(object);
+                    // only shadings reading the payload get one, see object_motion.glsl
+                    let reads_payload = assets
+                        .load(material)
+                        .is_some_and(|material| material.shading.object_payload);
+                    match world.get_entity_comp::<Motion>(entity) {
+                        Some(motion) if reads_payload => object.set_motion(motion),
+                        Some(motion) => object.previous_model = motion.previous_model(),
+                        None => {}
+                    }
+                    meshes.push(object);
+                }
+            }
+            if let Some(dynamic_mesh) = world.get_entity_comp::<DynamicMesh>(entity) {
+                meshes.push(RenderObject::new(
+                    dynamic_mesh.geom.clone(),
+                    dynamic_mesh.material.clone(),
+                    transform.matrix(),
+                ));
+            }
+
+            let dissolve = world.get_entity_comp::<Dissolve>(entity).copied();
+            let selected = world.has_entity_comp::<Selected>(entity);
+            for mut object in meshes {
+                object.dissolve = dissolve;
+                object.entity = Some(entity);
+                if selected {
+                    object.flags |= RenderFlags::SELECTED;
                 }
-                _ => {}
+                objects.push(object);
             }
         }
+        drop(assets);
+        self.normal_debugger
+            .collect(&mut self.world, &mut self.assets.borrow_mut(), &mut objects);
+        self.measurement_renderer.collect(
+            &mut self.world,
+            &mut self.assets.borrow_mut(),
+            &mut objects,
+        );
+        self.skeleton_debugger.collect(
+            &mut self.world,
+            &mut self.assets.borrow_mut(),
+            &mut objects,
+        );
+        self.debug_draw_renderer
+            .collect(&self.world, &mut self.assets.borrow_mut(), &mut objects);
 
-        let camera_query = Query::<(&Transform, &Camera)>::new(&mut self.world);
+        let extent = self.gpu.swap_chain.borrow().extent;
         let mut view = Mat4::identity();
         let mut projection = Mat4::identity();
-        for (transform, camera) in camera_query {
-            // let aspect = self.swapchain_properties.extent.width as f32
-            //     / self.swapchain_properties.extent.height as f32;
-            // view = Mat4::look_at_rh(
-            //     Vec3::new(0.0, 10.0, 10.0),
-            //     Vec3::new(0.0, 0.0, 0.0),
-            //     Vec3::new(0.0, 1.0, 0.0),
-            // );
-            view = transform.matrix().invert();
-            // projection = Mat4::orthographic_rh(-2.0, 2.0, -2.0, 2.0, 0.01, 100.0);
-            projection =
-                Mat4::perspective_reversed_z_infinite_rh(camera.fov, camera.aspect, camera.near);
+        let mut camera_location = Vec3::zero();
+        let mut post_overrides = PostOverrides::new();
+        let mut globals = self.globals;
+        if let Some((transform, camera, overrides)) =
+            window_camera(&mut self.world, (extent.width, extent.height))
+        {
+            camera_location = transform.location;
+            globals.camera_position = [
+                camera_location.x,
+                camera_location.y,
+                camera_location.z,
+                camera.near,
+            ];
+            globals.camera_params = camera.params();
+            post_overrides = overrides.cloned().unwrap_or_default();
+            view = camera.view(transform);
+            projection = camera.projection();
         }
+        let view_projection = projection * view;
+        globals.previous_view_projection = self
+            .previous_view_projection
+            .replace(view_projection)
+            .unwrap_or(view_projection);
+        // shifts clip space by the jitter times w, which is a constant NDC offset
+        for col in 0..4 {
+            projection[col][0] += globals.jitter[0] * projection[col][3];
+            projection[col][1] += globals.jitter[1] * projection[col][3];
+        }
+        // facing the real camera, not the frozen one of the culling debugger
+        self.trail_renderer.collect(
+            &mut self.world,
+            &mut self.assets.borrow_mut(),
+            camera_location,
+            &mut objects,
+        );
+        let (culling, camera_location) = self.culling_debugger.culling(
+            &mut self.assets.borrow_mut(),
+            projection * view,
+            camera_location,
+            self.forward_renderer.is_reverse_z(),
+        );
+        self.culling_debugger.collect(&mut objects);
+
+        self.allocate_shadows(&culling, camera_location, globals.camera_params);
+        let lights = self.collect_lights(&culling, camera_location);
 
         RenderContext {
             gpu_assets: self.gpu_assets.clone(),
             view,
             projection,
+            culling,
+            globals,
             objects,
+            lights,
+            post_overrides,
         }
     }
 
-    pub fn load_scene(&mut self, path: &str) {
-        match path {
-            "" => {
-                load_simple_scene(&mut self.world, &mut self.assets.borrow_mut());
+    // The extraction phase of a frame: the render context of the window camera with its objects
+    // culled, the texture cameras and the other per frame data the passes need from the world.
+    fn extract_render(&mut self) -> RenderExtract {
+        let mut context = self.generate_render_context();
+        let objects = std::mem::take(&mut context.objects);
+        let texture_cameras = self.texture_camera_views(&context, &objects);
+        let shadows = self.shadow_views(&objects);
+        context.objects = self.cull_objects(&objects, &context.culling);
+        let decals = self.collect_decals();
+
+        // text stays put under the jitter, the unjittered matrix was just stored
+        let view_projection = self
+            .previous_view_projection
+            .unwrap_or(context.projection * context.view);
+        let viewport = self.viewport();
+        self.text_renderer
+            .collect(&mut self.world, &context.view, &view_projection, viewport);
+
+        RenderExtract {
+            context,
+            texture_cameras,
+            shadows,
+            decals,
+        }
+    }
+
+    // The objects whose bounds are inside the frustum of `culling`. The bounds are the CPU side
+    // ones the GPU geoms are uploaded with, objects whose geom isn't loaded are dropped as well.
+    fn cull_objects(&self, objects: &[RenderObject], culling: &Mat4) -> Vec<RenderObject> {
+        let frustum = Frustum::from_matrix(culling);
+        let mut assets = self.assets.borrow_mut();
+        objects
+            .iter()
+            .filter(|object| {
+                // culled by the forward renderer on the GPU
+                let indirect = assets
+                    .load(&object.material)
+                    .is_some_and(|material| material.shading.indirect);
+                if indirect {
+                    return true;
+                }
+                let bounds = match &object.geom {
+                    RenderGeom::Static(geom) => assets.load(geom).map(|geom| geom.bounds),
+                    RenderGeom::Dynamic(geom) => assets.load_mut(geom).map(DynamicGeom::bounds),
+                };
+                bounds
+                    .is_some_and(|bounds| frustum.intersects_aabb(&bounds.transform(&object.model)))
+            })
+            .cloned()
+            .collect()
+    }
+
+    // Every light with the transform placing it, in entity order.
+    fn world_lights(&self) -> impl Iterator<Item = (Entity, &Transform, &Light)> {
+        self.world
+            .ordered_entities()
+            .into_iter()
+            .filter_map(|entity| {
+                let transform = self.world.get_entity_comp::<Transform>(entity)?;
+                let light = self.world.get_entity_comp::<Light>(entity)?;
+                Some((entity, transform, light))
+            })
+    }
+
+    // Spot lights shine down the local -Z axis.
+    fn light_direction(transform: &Transform) -> Vec3 {
+        let matrix = transform.matrix();
+        -Vec3::new(matrix[2][0], matrix[2][1], matrix[2][2]).normalize()
+    }
+
+    // Tiles of the shadow atlas for the shadow casting lights inside the culling frustum, sized by
+    // how much of the screen they cover. The texture cameras use the same tiles.
+    fn allocate_shadows(&mut self, culling: &Mat4, camera_location: Vec3, camera_params: [f32; 4]) {
+        let frustum = Frustum::from_matrix(culling);
+        let requests = self
+            .world_lights()
+            .filter(|(_, _, light)| light.cast_shadows)
+            .filter_map(|(entity, transform, light)| {
+                let faces = ShadowRenderer::shadow_faces(light);
+                let direction = Self::light_direction(transform);
+                let (center, radius) = light.bounding_sphere(transform.location, direction);
+                (faces > 0 && frustum.intersects_sphere(center, radius)).then(|| {
+                    let coverage =
+                        ShadowRenderer::coverage(center, radius, camera_location, camera_params);
+                    (entity, coverage, faces)
+                })
+            })
+            .collect::<Vec<_>>();
+        self.shadow_renderer.atlas.allocate(&requests);
+    }
+
+    // The tiles of the shadow atlas, one per cube face of a point light, and the objects casting
+    // into them. The debug helpers cast no shadows.
+    fn shadow_views(&self, objects: &[RenderObject]) -> Vec<ShadowView> {
+        let casters = objects
+            .iter()
+            .filter(|object| object.entity.is_some())
+            .cloned()
+            .collect::<Vec<_>>();
+        let atlas = &self.shadow_renderer.atlas;
+        self.world_lights()
+            .flat_map(|(entity, transform, light)| {
+                let tiles = atlas.get_tiles(entity);
+                let direction = Self::light_direction(transform);
+                let view_projections =
+                    ShadowRenderer::light_view_projections(light, transform.location, direction);
+                tiles
+                    .iter()
+                    .zip(view_projections)
+                    .map(|(&tile, view_projection)| ShadowView {
+                        tile,
+                        view_projection,
+                        objects: self.cull_objects(&casters, &view_projection),
+                    })
+                    .collect::<Vec<_>>()
+            })
+            .collect()
+    }
+
+    // Closest to reaching the camera first, only the first MAX_LIGHTS make it to the uniform block
+    // and the first MAX_CLUSTERED_LIGHTS to the light clusters of the standard shading.
+    // Lights whose volume is outside the culling frustum don't take up a slot.
+    fn collect_lights(&self, culling: &Mat4, camera_location: Vec3) -> Vec<LightData> {
+        let frustum = Frustum::from_matrix(culling);
+        let atlas = &self.shadow_renderer.atlas;
+        let mut lights = vec![];
+        for (entity, transform, light) in self.world_lights() {
+            let direction = Self::light_direction(transform);
+            let (center, radius) = light.bounding_sphere(transform.location, direction);
+            if !frustum.intersects_sphere(center, radius) {
+                continue;
+            }
+            let distance = (transform.location - camera_location).len() - light.range;
+            let mut data = LightData::new(light, transform.location, direction);
+            let tiles = atlas.get_tiles(entity);
+            if !tiles.is_empty() && tiles.len() as u32 == ShadowRenderer::shadow_faces(light) {
+                let rects = tiles
+                    .iter()
+                    .map(|tile| tile.uv_rect(atlas.size()))
+                    .collect::<Vec<_>>();
+                match light.kind {
+                    LightKind::Point => {
+                        let (near, far) = ShadowRenderer::depth_range(light);
+                        data.set_cube_shadow(&rects, near, far);
+                    }
+                    LightKind::Spot { .. } => {
+                        data.shadow = rects[0];
+                        data.shadow_matrix = ShadowRenderer::light_view_projections(
+                            light,
+                            transform.location,
+                            direction,
+                        )[0];
+                    }
+                }
             }
+            lights.push((distance, data));
+        }
+        lights.sort_by(|a, b| a.0.total_cmp(&b.0));
+        lights.into_iter().map(|(_, light)| light).collect()
+    }
+
+    // The cameras rendering into textures see the objects extracted for the window camera from
+    // their own transform, without jitter or motion history.
+    fn texture_camera_views(
+        &mut self,
+        context: &RenderContext,
+        objects: &[RenderObject],
+    ) -> Vec<TextureCameraView> {
+        let mut views = Query::<(&Transform, &Camera)>::new(&mut self.world)
+            .filter_map(|(transform, camera)| {
+                let CameraTarget::Texture {
+                    texture,
+                    width,
+                    height,
+                } = &camera.target
+                else {
+                    return None;
+                };
+                if !camera.active {
+                    return None;
+                }
+                let mut globals = context.globals;
+                let location = transform.location;
+                globals.camera_position = [location.x, location.y, location.z, camera.near];
+                globals.camera_params = camera.params();
+                globals.jitter = [0.0; 4];
+                let (view, projection) = (camera.view(transform), camera.projection());
+                globals.previous_view_projection = projection * view;
+                Some(TextureCameraView {
+                    texture: texture.clone(),
+                    width: *width,
+                    height: *height,
+                    context: RenderContext {
+                        gpu_assets: self.gpu_assets.clone(),
+                        view,
+                        projection,
+                        culling: projection * view,
+                        globals,
+                        objects: vec![],
+                        lights: vec![],
+                        post_overrides: PostOverrides::new(),
+                    },
+                })
+            })
+            .collect::<Vec<_>>();
+        for view in &mut views {
+            let [x, y, z, _] = view.context.globals.camera_position;
+            view.context.lights = self.collect_lights(&view.context.culling, Vec3::new(x, y, z));
+            view.context.objects = self.cull_objects(objects, &view.context.culling);
+        }
+        views
+    }
+
+    pub fn load_scene(&mut self, path: &str) {
+        let environment = match path {
+            "" => load_simple_scene(&mut self.world, &mut self.assets.borrow_mut()),
             path if path.ends_with(".gltf") => {
-                load_gltf_scene(&mut self.world, &mut self.assets.borrow_mut(), path);
+                load_gltf_scene(&mut self.world, &mut self.assets.borrow_mut(), path)
+            }
+            path if path.ends_with(".scene") => self.load_scene_file(path),
+            path if path.ends_with(".obj") => self.load_obj_file(path),
+            path if path.ends_with(".usd") => None,
+            _ => None,
+        };
+        // scenes without one keep the current environment
+        if let Some(environment) = environment {
+            self.apply_environment(&environment);
+        }
+        // out of the loading screen once the first scene is in
+        if self.scheduler.get_app_state() == AppState::Loading {
+            self.scheduler.set_app_state(AppState::Editor);
+        }
+
+        // pipelines of the scene compile in the background instead of on first draw
+        let materials = Query::<&StaticMesh>::new(&mut self.world)
+            .filter_map(|static_mesh| static_mesh.material.clone())
+            .collect::<Vec<_>>();
+        self.gpu_assets
+            .borrow()
+            .warm_up(&materials, &self.forward_renderer);
+    }
+
+    // Bundled assets first, then the file system, e.g. for scenes saved by `save_scene`.
+    fn load_scene_file(&mut self, path: &str) -> Option<AssetHandle<Environment>> {
+        let data = match Assets::load_raw(path) {
+            Some(data) => data.into_owned(),
+            None => match std::fs::read(path) {
+                Ok(data) => data,
+                Err(error) => {
+                    error_overlay::report(format!("scene {}", path), error);
+                    return None;
+                }
+            },
+        };
+        let text = String::from_utf8_lossy(&data);
+        let loaded = serialize::load_scene(
+            &mut self.world,
+            &mut self.assets.borrow_mut(),
+            &self.scene_migrations,
+            &text,
+        );
+        loaded.unwrap_or_else(|error| {
+            error_overlay::report(format!("scene {}", path), error);
+            None
+        })
+    }
+
+    // An entity at the origin for every object of the OBJ, with the materials of its MTL.
+    // Objects without one share a plain material.
+    fn load_obj_file(&mut self, path: &str) -> Option<AssetHandle<Environment>> {
+        let mut assets = self.assets.borrow_mut();
+        let Some(models) = load_obj(&mut assets, path) else {
+            log::warn!("failed to load {}!", path);
+            return None;
+        };
+        let mut plain = None;
+        for model in models {
+            let material = model.material.unwrap_or_else(|| {
+                log::warn!("object {} of {} has no material!", model.name, path);
+                plain
+                    .get_or_insert_with(|| {
+                        assets.handle(Material::new(Shading::load("simple.spv")))
+                    })
+                    .clone()
+            });
+            let entity = self.world.add_entity();
+            self.world.add_entity_comp(entity, Transform::default());
+            self.world
+                .add_entity_comp(entity, StaticMesh::new(Some(model.geom), Some(material)));
+        }
+        None
+    }
+
+    // The entities with transforms, meshes, cameras or lights as a `.scene` file for `load_scene`.
+    pub fn save_scene(&self, path: &str) -> std::io::Result<()> {
+        let assets = self.assets.borrow();
+        let environment = self
+            .environment
+            .as_ref()
+            .and_then(|environment| assets.path(environment));
+        let text = serialize::save_scene(&self.world, &assets, environment);
+        std::fs::write(path, text)
+    }
+
+    // A new native window after a suspend, the surface and everything sized by it is rebuilt.
+    pub fn update_window(&mut self, window: Rc<Window>) {
+        self.gpu.resume(window);
+        self.forward_renderer.resize();
+        self.decal_renderer.resize(&self.forward_renderer);
+        self.contact_shadow_renderer.resize(&self.forward_renderer);
+        self.post_chain.resize(&self.forward_renderer.scene_color);
+        self.outline_renderer.resize();
+        self.egui_renderer.resize();
+        self.text_renderer.resize();
+        self.swap_chain_dirty = false;
+        self.run_swap_chain_hooks();
+    }
+
+    pub fn suspend(&mut self) {
+        self.gpu.suspend();
+    }
+
+    pub fn resize(&mut self) {
+        self.swap_chain_dirty = true;
+    }
+
+    // Headless only, the frames from the next `render` on have the new size.
+    pub fn resize_offscreen(&mut self, width: u32, height: u32) {
+        if !self.gpu.is_headless() {
+            log::warn!("resize_offscreen needs a headless Mirage, the window decides the size!");
+            return;
+        }
+        self.gpu.swap_chain.borrow_mut().extent = vk::Extent2D { width, height };
+        self.swap_chain_dirty = true;
+    }
+
+    // The RGBA8 pixels of the last rendered frame with its width and height, rows top to bottom.
+    // sRGB encoded unless the surface format mode is linear. Headless only, presented images
+    // belong to the presentation engine, None with a window or before the first frame.
+    pub fn read_back_frame(&self) -> Option<(Vec<u8>, u32, u32)> {
+        if !self.gpu.is_headless() {
+            log::warn!("read_back_frame needs a headless Mirage!");
+            return None;
+        }
+        let image_index = self.last_image_index?;
+        let swap_chain = self.gpu.swap_chain.borrow();
+        let extent = swap_chain.extent;
+        let image = swap_chain.images[image_index as usize];
+        drop(swap_chain);
+        let pixels = self.gpu.read_back_image(image, extent.width, extent.height);
+        Some((pixels, extent.width, extent.height))
+    }
+
+    // Debug windows drawn with egui over the scene, called once per frame.
+    pub fn ui(&mut self, ui: impl FnMut(&egui::Context) + 'static) {
+        self.ui_callback = Some(Box::new(ui));
+    }
+
+    // Before the systems tick every update, see `FrameHooks`.
+    pub fn on_pre_update(&mut self, hook: impl FnMut(&mut Mirage) + 'static) {
+        self.hooks.pre_update.push(Box::new(hook));
+    }
+
+    // After the systems and the UI layout of every update.
+    pub fn on_post_update(&mut self, hook: impl FnMut(&mut Mirage) + 'static) {
+        self.hooks.post_update.push(Box::new(hook));
+    }
+
+    // Records into the frame's command buffer before the scene is drawn.
+    pub fn on_pre_render(&mut self, hook: impl FnMut(&RenderFrame) + 'static) {
+        self.hooks.pre_render.push(Box::new(hook));
+    }
+
+    // Records into the frame's command buffer after the egui overlay, right before the submit.
+    pub fn on_post_render(&mut self, hook: impl FnMut(&RenderFrame) + 'static) {
+        self.hooks.post_render.push(Box::new(hook));
+    }
+
+    // With the new size whenever the swap chain was recreated, e.g. to resize the app's targets.
+    pub fn on_swapchain_recreated(&mut self, hook: impl FnMut(&mut Mirage, u32, u32) + 'static) {
+        self.hooks.swap_chain_recreated.push(Box::new(hook));
+    }
+
+    // The hooks get the engine, they are taken out while running. Hooks registered meanwhile run
+    // from the next time on.
+    fn run_update_hooks(&mut self, select: fn(&mut FrameHooks) -> &mut Vec<UpdateHook>) {
+        let mut hooks = std::mem::take(select(&mut self.hooks));
+        hooks.iter_mut().for_each(|hook| hook(self));
+        let added = std::mem::replace(select(&mut self.hooks), hooks);
+        select(&mut self.hooks).extend(added);
+    }
+
+    fn run_swap_chain_hooks(&mut self) {
+        let extent = self.gpu.swap_chain.borrow().extent;
+        let mut hooks = std::mem::take(&mut self.hooks.swap_chain_recreated);
+        hooks
+            .iter_mut()
+            .for_each(|hook| hook(self, extent.width, extent.height));
+        let added = std::mem::replace(&mut self.hooks.swap_chain_recreated, hooks);
+        self.hooks.swap_chain_recreated.extend(added);
+    }
+
+    // true when egui took the event, e.g. typing into a text field
+    pub fn handle_ui_event(&mut self, event: &WindowEvent) -> bool {
+        let (Some(window), Some(ui_state)) = (
+            self.gpu.context.window.borrow().clone(),
+            self.ui_state.as_mut(),
+        ) else {
+            return false;
+        };
+        let response = ui_state.on_window_event(&window, event);
+        response.consumed
+    }
+
+    // None keeps the current value.
+    pub fn set_selection_outline(&mut self, color: Option<[f32; 4]>, width: Option<f32>) {
+        if let Some(color) = color {
+            self.outline_renderer.color = color;
+        }
+        if let Some(width) = width {
+            self.outline_renderer.width = width;
+        }
+    }
+
+    pub fn set_window_icon(&self, texture: &AssetHandle<Texture>) {
+        let assets = self.assets.borrow();
+        let icon = assets.load(texture).and_then(window_icon);
+        if let Some(window) = self.gpu.context.window.borrow().as_ref() {
+            window.set_window_icon(icon);
+        }
+    }
+
+    // None goes back to the cursors of the cursor style.
+    pub fn set_cursor(&mut self, cursor: Option<CursorShape>) {
+        self.cursor_override = cursor;
+    }
+
+    // After the frame, custom cursors can only be created with the running event loop.
+    pub fn update_cursor(&mut self, event_loop: &ActiveEventLoop) {
+        if self.input.pointer_position.is_none() {
+            // set again on the way back in, the platform may have changed it outside the window
+            self.cursors.invalidate();
+            return;
+        }
+
+        let egui_shape = CursorShape::Icon(egui_cursor_icon(self.egui_cursor));
+        let shape = self
+            .cursor_override
+            .as_ref()
+            .or_else(|| self.cursor_style.resolve(&self.input))
+            .unwrap_or(&egui_shape);
+        let Some(window) = self.gpu.context.window.borrow().clone() else {
+            return;
+        };
+        self.cursors
+            .apply(event_loop, &window, &self.assets.borrow(), shape);
+    }
+
+    pub fn get_app_state(&self) -> AppState {
+        self.scheduler.get_app_state()
+    }
+
+    // Switches between loading, menu, play and editor mode on the next update.
+    pub fn set_app_state(&mut self, app_state: AppState) {
+        self.scheduler.set_app_state(app_state);
+    }
+
+    // Lines for this frame from outside the systems, see `DebugDraw`.
+    pub fn debug_draw(&mut self) -> &mut DebugDraw {
+        if !self.world.has_resource::<DebugDraw>() {
+            self.world.insert_resource(DebugDraw::new());
+        }
+        self.world.get_resource_mut::<DebugDraw>().unwrap()
+    }
+
+    pub fn get_scheduler_mut(&mut self) -> &mut Scheduler {
+        &mut self.scheduler
+    }
+
+    pub fn get_world(&self) -> &World {
+        &self.world
+    }
+
+    // Where the app spawns its entities, e.g. from the setup callback of `MirageConfig`.
+    pub fn get_world_mut(&mut self) -> &mut World {
+        &mut self.world
+    }
+
+    // Reaches the systems' `EventReader`s on the next update, e.g. input or loaded assets.
+    pub fn send_event<T: 'static>(&self, event: T) {
+        self.scheduler.send_event(event);
+    }
+
+    // Linear output for capture workflows that expect un-encoded frames.
+    pub fn set_surface_format_mode(&mut self, format_mode: SurfaceFormatMode) {
+        self.gpu.set_surface_format_mode(format_mode);
+        self.swap_chain_dirty = true;
+    }
+
+    // Vsync or not and how, rebuilds the swap chain. A mode the surface doesn't support falls back
+    // to `PresentMode::Fifo`, see `supported_present_modes`.
+    pub fn set_present_mode(&mut self, present_mode: PresentMode) {
+        self.gpu.set_present_mode(present_mode);
+        self.swap_chain_dirty = true;
+    }
+
+    // The requested one, which the surface may not support.
+    pub fn get_present_mode(&self) -> PresentMode {
+        self.gpu.swap_chain.borrow().requested_present_mode
+    }
+
+    pub fn supported_present_modes(&self) -> Vec<PresentMode> {
+        self.gpu.supported_present_modes()
+    }
+
+    // Negative values pick sharper mips, useful when TAA or upscaling softens the image.
+    pub fn set_mip_lod_bias(&mut self, mip_lod_bias: f32) {
+        self.gpu_assets.borrow().set_mip_lod_bias(mip_lod_bias);
+    }
+
+    // Device memory the textures of materials may take, the least recently drawn are evicted past
+    // it and uploaded again when they show up. None keeps every upload.
+    pub fn set_texture_budget(&mut self, budget: Option<u64>) {
+        self.gpu_assets.borrow().set_texture_budget(budget);
+    }
+
+    // Frames taking longer than `timeout` on the GPU are logged with the pass they got stuck in
+    // and skipped instead of freezing the app, see `Watchdog`. None turns it off.
+    pub fn set_watchdog(&mut self, timeout: Option<Duration>) {
+        self.gpu.set_watchdog(timeout);
+    }
+
+    // Times every pass of the frame on the GPU, see `Profiler`. The timings show up in the overlay
+    // with `show_gpu_timings`.
+    pub fn set_gpu_profiling(&mut self, enabled: bool) {
+        self.gpu.set_profiling(enabled);
+        self.show_gpu_timings = enabled;
+    }
+
+    // Writes the CPU scopes of the next `frames` frames, per system and per pass, to `path` as a
+    // Chrome trace JSON for about://tracing, see `cpu_profiler`.
+    pub fn capture_trace(&mut self, frames: u32, path: &str) {
+        cpu_profiler::start_capture(frames, path);
+    }
+
+    // Of the latest finished frame, a few frames behind the one being rendered.
+    pub fn get_gpu_timings(&self) -> Option<GpuTimings> {
+        self.gpu.get_gpu_timings()
+    }
+
+    // The timeline semaphore the frames signal and the value of the latest submitted one, work on
+    // other queues waits on it to read what the frame rendered. `GPU::wait_in_frame` goes the
+    // other way. None when the device has no timeline semaphores.
+    pub fn frame_timeline(&self) -> Option<(vk::Semaphore, u64)> {
+        self.frame_sync.timeline()
+    }
+
+    // Dynamic resolution, the scene renders at `render_scale` of the window and the post chain upscales it.
+    // Textures get a matching negative mip bias so they keep the detail of the output resolution.
+    pub fn set_render_scale(&mut self, render_scale: f32) {
+        self.gpu.wait_idle();
+        self.forward_renderer.set_render_scale(render_scale);
+        self.decal_renderer.resize(&self.forward_renderer);
+        self.contact_shadow_renderer.resize(&self.forward_renderer);
+        self.post_chain.resize(&self.forward_renderer.scene_color);
+        self.set_mip_lod_bias(self.forward_renderer.get_render_scale().log2());
+    }
+
+    pub fn is_mobile_friendly(&self) -> bool {
+        self.forward_renderer.is_mobile_friendly()
+    }
+
+    // Renders the scene as a depth prepass and a shading subpass in one render pass with transient
+    // attachments, which keeps them in tile memory on mobile GPUs. Material pipelines get rebuilt.
+    pub fn set_mobile_friendly(&mut self, mobile_friendly: bool) {
+        if self.forward_renderer.is_mobile_friendly() == mobile_friendly {
+            return;
+        }
+        self.gpu.wait_idle();
+        self.forward_renderer
+            .set_mobile_friendly(mobile_friendly, &self.gpu_assets.borrow());
+        self.decal_renderer.resize(&self.forward_renderer);
+        self.contact_shadow_renderer.resize(&self.forward_renderer);
+        self.post_chain.resize(&self.forward_renderer.scene_color);
+    }
+
+    pub fn get_msaa(&self) -> Msaa {
+        self.forward_renderer.get_msaa()
+    }
+
+    pub fn is_reverse_z(&self) -> bool {
+        self.forward_renderer.is_reverse_z()
+    }
+
+    // On by default, off for comparison. Switches the projection of every camera, the depth test
+    // of every pipeline and the depth clear together, material pipelines get rebuilt.
+    pub fn set_reverse_z(&mut self, reverse_z: bool) {
+        if self.forward_renderer.is_reverse_z() == reverse_z {
+            return;
+        }
+        self.gpu.wait_idle();
+        self.gpu_assets
+            .borrow()
+            .release_render_pass(self.forward_renderer.render_pass);
+        self.forward_renderer.set_reverse_z(reverse_z);
+        self.sync_camera_depth();
+        // the previous frame was projected the other way around
+        self.previous_view_projection = None;
+    }
+
+    pub fn get_recording_threads(&self) -> usize {
+        self.forward_renderer.get_recording_threads()
+    }
+
+    // Threads recording the forward pass draws into secondary command buffers, one per core up to
+    // 8 by default. Only scenes with enough draws to keep them busy use them, 1 always records on
+    // the calling thread.
+    pub fn set_recording_threads(&mut self, threads: usize) {
+        self.gpu.wait_idle();
+        self.forward_renderer.set_recording_threads(threads);
+    }
+
+    // Cameras project with the depth of the renderer, the ones added since the last sync as well.
+    fn sync_camera_depth(&mut self) {
+        let reverse_z = self.forward_renderer.is_reverse_z();
+        for camera in Query::<&mut Camera>::new(&mut self.world) {
+            camera.reverse_z = reverse_z;
+        }
+    }
+
+    // Samples per pixel of the scene, lowered to what the device supports. Material pipelines get
+    // rebuilt like for `set_mobile_friendly`.
+    pub fn set_msaa(&mut self, msaa: Msaa) {
+        if self.forward_renderer.get_msaa() == msaa {
+            return;
+        }
+        self.gpu.wait_idle();
+        self.forward_renderer
+            .set_msaa(msaa, &self.gpu_assets.borrow());
+        self.decal_renderer.resize(&self.forward_renderer);
+        self.contact_shadow_renderer.resize(&self.forward_renderer);
+        self.post_chain.resize(&self.forward_renderer.scene_color);
+    }
+
+    // Subpixel offset of the projection in NDC for the coming frame, e.g. for temporal
+    // antialiasing. Shaders see it and the previous one in `globals.jitter`.
+    pub fn set_jitter(&mut self, jitter: [f32; 2]) {
+        let previous = self.globals.jitter;
+        self.globals.jitter = [jitter[0], jitter[1], previous[0], previous[1]];
+    }
+
+    pub fn is_culling_frozen(&self) -> bool {
+        self.culling_debugger.is_frozen()
+    }
+
+    // Keeps culling objects and picking lights with the camera as it is now, while the view
+    // follows the camera. The frozen frustum is drawn as lines to see what falls out of it.
+    pub fn set_culling_frozen(&mut self, frozen: bool) {
+        self.culling_debugger
+            .set_frozen(frozen, &mut self.assets.borrow_mut());
+    }
+
+    // Six face images in +X, -X, +Y, -Y, +Z, -Z order as one cube map, e.g. for `set_environment`.
+    pub fn load_environment(&mut self, paths: [&str; 6]) -> Option<AssetHandle<Texture>> {
+        let mut assets = self.assets.borrow_mut();
+        let mut faces = vec![];
+        for path in paths {
+            let Some(face) = assets.handle_path::<Texture>(path) else {
+                error_overlay::report(format!("environment face {}", path), "failed to load");
+                faces.iter().for_each(|face| _ = assets.remove(face));
+                return None;
+            };
+            faces.push(face);
+        }
+
+        let cube = Texture::cube(std::array::from_fn(|index| {
+            assets.load(&faces[index]).unwrap()
+        }));
+        faces.iter().for_each(|face| _ = assets.remove(face));
+        match cube {
+            Ok(cube) => Some(assets.handle(cube)),
+            Err(error) => {
+                error_overlay::report("environment", error);
+                None
+            }
+        }
+    }
+
+    // The cube map drawn behind the scene and lighting it, None clears it. The lighting is
+    // prefiltered right away, which takes a moment.
+    pub fn set_environment(&mut self, environment: Option<AssetHandle<Texture>>) {
+        if let Some(environment) = &environment {
+            if !self
+                .assets
+                .borrow()
+                .load(environment)
+                .is_some_and(Texture::is_cube)
+            {
+                error_overlay::report("environment", "the environment has to be a cube map");
+                return;
+            }
+        }
+        self.forward_renderer.skybox.environment = environment.clone();
+
+        let ibl = environment.and_then(|environment| self.bake_ibl(&environment));
+        let previous = std::mem::replace(&mut self.ibl, ibl);
+        // frames in flight still sample the previous maps
+        self.gpu.wait_idle();
+        self.bind_ibl();
+        if let Some(previous) = previous {
+            let gpu_assets = self.gpu_assets.borrow();
+            let mut assets = self.assets.borrow_mut();
+            for texture in [previous.irradiance, previous.specular] {
+                gpu_assets.release_texture(&texture);
+                assets.remove(&texture);
             }
-            path if path.ends_with(".usd") => {}
-            _ => {}
         }
     }
 
-    pub fn update_window(&self, window: Rc<Window>) {}
+    // Irradiance and specular maps of the environment, None when it can't be uploaded.
+    fn bake_ibl(&self, environment: &AssetHandle<Texture>) -> Option<IblTextures> {
+        let texture = self.gpu_assets.borrow().get_texture(environment.clone())?;
+        let size = self.assets.borrow().load(environment)?.width;
+        let irradiance = self.ibl_baker.irradiance(&texture, size);
+        let specular = self.ibl_baker.specular(&texture, size);
+
+        let mut assets = self.assets.borrow_mut();
+        Some(IblTextures {
+            irradiance: assets.handle(irradiance),
+            specular: assets.handle(specular),
+        })
+    }
+
+    fn bind_ibl(&self) {
+        let (irradiance, specular, specular_mips) = match &self.ibl {
+            Some(ibl) => (ibl.irradiance.clone(), ibl.specular.clone(), SPECULAR_MIPS),
+            None => (self.black_cube.clone(), self.black_cube.clone(), 0),
+        };
+        let gpu_assets = self.gpu_assets.borrow();
+        let [irradiance, specular, brdf_lut] =
+            [irradiance, specular, self.brdf_lut.clone()].map(|handle| {
+                gpu_assets
+                    .get_texture(handle)
+                    .expect("failed to upload environment lighting!")
+            });
+        self.camera_uniforms.set_ibl(
+            &irradiance.texture,
+            &specular.texture,
+            &brdf_lut.texture,
+            specular_mips,
+        );
+    }
+
+    // Ambient light, fog, sun, skybox and post defaults of a scene at once.
+    pub fn apply_environment(&mut self, environment: &AssetHandle<Environment>) {
+        let Some(loaded) = self.assets.borrow().load(environment).cloned() else {
+            return;
+        };
+        self.environment = Some(environment.clone());
+        let environment = loaded;
+        self.camera_uniforms.set_environment(&environment);
+        let skybox = environment.skybox.as_ref().and_then(|faces| {
+            let faces = faces.each_ref().map(String::as_str);
+            self.load_environment(faces)
+        });
+        self.set_environment(skybox);
+        self.set_environment_intensity(environment.skybox_intensity);
+        self.set_tonemapping(environment.tonemapping, environment.exposure);
+        self.set_sharpening(environment.sharpening);
+    }
+
+    pub fn set_environment_intensity(&mut self, intensity: f32) {
+        self.forward_renderer.skybox.intensity = intensity;
+    }
+
+    // How the HDR scene color maps to the display, exposure in stops. Cameras can override the
+    // "tonemap" effect's params with `PostOverrides`.
+    pub fn set_tonemapping(&mut self, tonemapping: Tonemapping, exposure: f32) {
+        self.post_chain
+            .set_effect(PostEffect::tonemap(tonemapping, exposure));
+    }
+
+    // None turns the sharpening pass at the end of the post chain off.
+    pub fn set_sharpening(&mut self, sharpness: Option<f32>) {
+        match sharpness {
+            Some(sharpness) => self.post_chain.set_effect(PostEffect::sharpen(sharpness)),
+            None => self
+                .post_chain
+                .effects
+                .retain(|effect| effect.name != "sharpen"),
+        }
+    }
+
+    fn recreate_swap_chain(&mut self) -> bool {
+        // minimized, a zero sized swap chain can't be created
+        let size = self.gpu.surface_size();
+        if size.width == 0 || size.height == 0 {
+            return false;
+        }
+
+        self.gpu.recreate_swap_chain();
+        self.last_image_index = None;
+        self.forward_renderer.resize();
+        self.decal_renderer.resize(&self.forward_renderer);
+        self.contact_shadow_renderer.resize(&self.forward_renderer);
+        self.post_chain.resize(&self.forward_renderer.scene_color);
+        self.outline_renderer.resize();
+        self.egui_renderer.resize();
+        self.text_renderer.resize();
+        self.swap_chain_dirty = false;
+        self.run_swap_chain_hooks();
+        true
+    }
 
     pub fn update(&mut self) {
         let current_time = Instant::now();
         let delta_time = current_time.duration_since(self.timer).as_secs_f32();
         self.timer = current_time;
+        self.globals.time = [
+            current_time.duration_since(self.started).as_secs_f32(),
+            delta_time,
+            self.globals.time[2] + 1.0,
+            0.0,
+        ];
+
+        for job in self.render_queue.take() {
+            job(self);
+        }
+        // the lines of the last update were drawn with its frame
+        if let Some(debug_draw) = self.world.get_resource_mut::<DebugDraw>() {
+            debug_draw.clear();
+        }
+        self.run_update_hooks(|hooks| &mut hooks.pre_update);
+        self.reload_shaders();
+        self.reload_assets();
+        self.route_pointer();
+        self.update_translate_drag();
+        self.update_measure_tool();
+        {
+            let _scope = cpu_profiler::scope("systems");
+            self.scheduler.tick(&mut self.world, delta_time);
+        }
+        self.video_renderer
+            .update(&mut self.assets.borrow_mut(), delta_time);
+
+        {
+            let _scope = cpu_profiler::scope("bvh");
+            self.update_bvh();
+        }
+        // joint names next to the bones of skeletons tagged with `DebugSkeleton`
+        for (name, position) in SkeletonDebugger::joint_labels(&mut self.world) {
+            self.debug_draw().draw_text_3d(position, name, Vec3::one());
+        }
+        MeasurementRenderer::draw_labels(&mut self.world, self.measure_tool.start());
+
+        let viewport = self.viewport();
+        layout_ui(&mut self.world, viewport);
+        self.run_update_hooks(|hooks| &mut hooks.post_update);
+    }
+
+    // Of the window, headless targets have no scale.
+    fn viewport(&self) -> Viewport {
+        match self.gpu.context.window.borrow().as_ref() {
+            Some(window) => Viewport::from_window(window),
+            None => {
+                let size = self.gpu.surface_size();
+                Viewport {
+                    width: size.width,
+                    height: size.height,
+                    scale_factor: 1.0,
+                }
+            }
+        }
+    }
+
+    // Materials using a changed shader get the new SPIR-V and their pipelines rebuilt on next use.
+    // A shader that fails to compile is reported and the old one kept, so a typo doesn't end the app.
+    fn reload_shaders(&mut self) {
+        for name in self.shader_compiler.poll() {
+            let code = match self.shader_compiler.compile(&name) {
+                Ok(code) => Rc::new(code),
+                Err(error) => {
+                    error_overlay::report(format!("shader {}", name), error);
+                    continue;
+                }
+            };
+            error_overlay::resolve(&format!("shader {}", name));
+
+            let mut materials = vec![];
+            for (id, material) in self.assets.borrow_mut().iter_mut::<Material>() {
+                let mut uses_shader = false;
+                for stage in material.shading.stages.iter_mut() {
+                    if stage.path == name {
+                        stage.code = Some(code.clone());
+                        uses_shader = true;
+                    }
+                }
+                if uses_shader {
+                    materials.push(id);
+                }
+            }
 
-        self.scheduler.tick(&mut self.world, delta_time);
+            let gpu_assets = self.gpu_assets.borrow();
+            materials
+                .iter()
+                .for_each(|id| gpu_assets.release_pipelines(*id));
+            log::info!("reloaded shader {} for {} materials", name, materials.len());
+        }
+    }
+
+    // Textures, meshes and environments whose files changed get the data the watcher loaded on its
+    // worker, between frames so nothing recorded sees half of it. The GPU copies are uploaded again
+    // on next use, the old ones outlive the frames in flight. A file that fails to load is reported
+    // and the old data kept.
+    fn reload_assets(&mut self) {
+        let reloads = self.asset_watcher.poll(&self.assets.borrow());
+        for reload in reloads {
+            let source = format!("asset {}", reload.path);
+            let asset = match reload.asset {
+                Ok(asset) => asset,
+                Err(error) => {
+                    error_overlay::report(source, error);
+                    continue;
+                }
+            };
+            error_overlay::resolve(&source);
+
+            match asset {
+                ReloadedAsset::Texture(texture) => {
+                    let handle = AssetHandle::<Texture>::new(reload.id);
+                    *self.assets.borrow_mut().load_mut(&handle).unwrap() = texture;
+                    if !self.gpu_assets.borrow().reload_texture(&handle) {
+                        log::warn!(
+                            "texture {} is bound outside the materials, restart to see it",
+                            reload.path
+                        );
+                    }
+                }
+                ReloadedAsset::Geom(geom) => {
+                    let handle = AssetHandle::<Geom>::new(reload.id);
+                    *self.assets.borrow_mut().load_mut(&handle).unwrap() = geom;
+                    self.gpu_assets.borrow().reload_geom(&handle);
+                    let query = Query::<(&StaticMesh, &mut DebugNormals)>::new(&mut self.world);
+                    for (static_mesh, debug_normals) in query {
+                        if static_mesh
+                            .geom
+                            .as_ref()
+                            .is_some_and(|geom| geom.id == handle.id)
+                        {
+                            debug_normals.invalidate();
+                        }
+                    }
+                }
+                ReloadedAsset::Environment(environment) => {
+                    let handle = AssetHandle::<Environment>::new(reload.id);
+                    *self.assets.borrow_mut().load_mut(&handle).unwrap() = environment;
+                    if self
+                        .environment
+                        .as_ref()
+                        .is_some_and(|applied| applied.id == handle.id)
+                    {
+                        self.apply_environment(&handle);
+                    }
+                }
+            }
+            log::info!("reloaded asset {}", reload.path);
+        }
+    }
+
+    fn update_bvh(&mut self) {
+        let assets = self.assets.borrow();
+        let mut items = vec![];
+        for entity in self.world.ordered_entities() {
+            let (Some(transform), Some(static_mesh)) = (
+                self.world.get_entity_comp::<Transform>(entity),
+                self.world.get_entity_comp::<StaticMesh>(entity),
+            ) else {
+                continue;
+            };
+            if self
+                .world
+                .get_entity_comp::<Pooled>(entity)
+                .is_some_and(|pooled| !pooled.active)
+            {
+                continue;
+            }
+            let Some(geom) = static_mesh.geom.as_ref().and_then(|geom| assets.load(geom)) else {
+                continue;
+            };
+            items.push((entity, geom.bounds.transform(&transform.matrix())));
+        }
+
+        self.bvh.update(&items);
+    }
+
+    // Against the layout and boxes of the last frame, what the user actually saw under the pointer.
+    fn route_pointer(&mut self) {
+        let hovered = self.input.pointer_position.map(|pointer| {
+            if self.egui_context.is_pointer_over_area() {
+                return PointerTarget::Overlay;
+            }
+            if let Some(entity) = hit_test_ui(&self.world, pointer) {
+                return PointerTarget::Ui(entity);
+            }
+            match self.pick(pointer) {
+                Some(entity) => PointerTarget::Scene(entity),
+                None => PointerTarget::Background,
+            }
+        });
+        self.input.set_hovered(hovered);
+    }
+
+    // The entity the pointer captured on press follows it while the button is held.
+    fn update_translate_drag(&mut self) {
+        let grabbed = match self.input.pointer_owner() {
+            Some(PointerTarget::Scene(entity)) if self.input.is_pointer_captured() => entity,
+            _ => {
+                self.translate_drag = None;
+                return;
+            }
+        };
+        if !self.drag_to_translate {
+            return;
+        }
+        let Some(pointer) = self.input.pointer_position else {
+            return;
+        };
+        let Some(ray) = self.pointer_ray(pointer) else {
+            return;
+        };
+        let Some(transform) = self.world.get_entity_comp_mut::<Transform>(grabbed) else {
+            return;
+        };
+        let drag = match self.translate_drag {
+            Some(drag) if drag.entity == grabbed => drag,
+            _ => *self
+                .translate_drag
+                .insert(TranslateDrag::new(grabbed, transform.location, &ray)),
+        };
+        if let Some(location) = drag.location(&ray, &self.grid_snap) {
+            transform.location = location;
+        }
+    }
+
+    // A press on an entity of the scene picks the point under the pointer for the measure tool,
+    // every other press drops a picked start.
+    fn update_measure_tool(&mut self) {
+        if !self.measure_tool.enabled {
+            self.measure_tool.cancel();
+            return;
+        }
+        if !self.measure_tool.press(self.input.pointer_pressed) {
+            return;
+        }
+        let point = match (self.input.pointer_owner(), self.input.pointer_position) {
+            (Some(PointerTarget::Scene(_)), Some(pointer)) => self.pick_point(pointer),
+            _ => None,
+        };
+        let Some(point) = point else {
+            self.measure_tool.cancel();
+            return;
+        };
+        if let Some(measurement) = self.measure_tool.click(point, &self.grid_snap) {
+            let entity = self.world.add_entity();
+            self.world.add_entity_comp(entity, measurement);
+        }
+    }
+
+    // From the window camera through a pixel of the window.
+    fn pointer_ray(&mut self, pointer: Vec2) -> Option<Ray> {
+        self.sync_camera_depth();
+        let size = self.gpu.surface_size();
+        let size = Vec2::new(size.width as f32, size.height as f32);
+        let query = Query::<(&Transform, &Camera)>::new(&mut self.world);
+        let (transform, camera) = query
+            .filter(|(_, camera)| matches!(camera.target, CameraTarget::Window))
+            .last()?;
+        Some(camera.screen_to_world_ray(transform, pointer, size))
+    }
+
+    // Closest entity under a pixel of the window.
+    pub fn pick(&mut self, pointer: Vec2) -> Option<Entity> {
+        let ray = self.pointer_ray(pointer)?;
+        self.raycast(&ray, f32::INFINITY).map(|(entity, _)| entity)
+    }
+
+    // Where the closest entity under a pixel of the window is hit, e.g. an end of a measurement.
+    pub fn pick_point(&mut self, pointer: Vec2) -> Option<Vec3> {
+        let ray = self.pointer_ray(pointer)?;
+        self.raycast(&ray, f32::INFINITY)
+            .map(|(_, distance)| ray.at(distance))
+    }
+
+    pub fn raycast(&self, ray: &Ray, max_distance: f32) -> Option<(Entity, f32)> {
+        if !self.pick_triangles {
+            return self.bvh.raycast(ray, max_distance);
+        }
+        let assets = self.assets.borrow();
+        self.bvh.raycast_with(ray, max_distance, |entity, _| {
+            let transform = self.world.get_entity_comp::<Transform>(entity)?;
+            let static_mesh = self.world.get_entity_comp::<StaticMesh>(entity)?;
+            let geom = assets.load(static_mesh.geom.as_ref()?)?;
+            // the mesh is tested in object space, the distance measured back in world space
+            let model = transform.matrix();
+            let inverse = model.invert();
+            let local = Ray::new(
+                inverse.transform_point(ray.origin),
+                inverse.transform_vector(ray.direction),
+            );
+            let distance = geom.intersect_ray(&local)?;
+            Some((model.transform_point(local.at(distance)) - ray.origin).len())
+        })
+    }
+
+    // Marks the entity `Selected`, e.g. the one `pick` found under a click, and unmarks the
+    // others. None clears the selection.
+    pub fn select(&mut self, entity: Option<Entity>) {
+        for selected in self.get_selected() {
+            self.world.remove_entity_comp::<Selected>(selected);
+        }
+        if let Some(entity) = entity {
+            self.world.add_entity_comp(entity, Selected::new());
+        }
+    }
+
+    pub fn get_selected(&self) -> Vec<Entity> {
+        self.world
+            .ordered_entities()
+            .into_iter()
+            .filter(|&entity| self.world.has_entity_comp::<Selected>(entity))
+            .collect()
     }
 
     pub fn render(&mut self) {
-        self.update();
+        let _frame = cpu_profiler::frame();
+        {
+            let _scope = cpu_profiler::scope("update");
+            self.update();
+        }
 
-        unsafe {
-            let frame_index = self.frame_index.get();
+        if !self.gpu.has_surface() && !self.gpu.is_headless() {
+            return;
+        }
+        if self.swap_chain_dirty && !self.recreate_swap_chain() {
+            return;
+        }
 
-            let fence = self.in_flight_fences[frame_index];
-            let image_available_semaphore = self.image_available_semaphores[frame_index];
-            let render_finished_semaphore = self.render_finished_semaphores[frame_index];
+        // before anything can bail out of the frame, egui texture changes must not get lost
+        let (primitives, pixels_per_point) = self.run_ui();
 
-            // There happens to be two kinds of semaphores in Vulkan, binary and timeline. We use binary semaphores here.
-            // A fence has a similar purpose, in that it is used to synchronize execution, but it is for ordering the execution on the CPU, otherwise known as the host.
-            self.gpu
-                .device_context
-                .device
-                .wait_for_fences(&[fence], true, u64::MAX)
-                .expect("failed to wait fence!");
+        let frame_index = self.frame_index.get();
 
-            let image_index =
-                self.gpu
-                    .swap_chain
-                    .acquire_image(u64::MAX, Some(image_available_semaphore), None);
+        let image_available_semaphore = self.frame_sync.image_available(frame_index);
 
-            self.gpu
-                .device_context
-                .device
-                .reset_fences(&[fence])
-                .expect("failed to reset fence!");
+        // There happens to be two kinds of semaphores in Vulkan, binary and timeline. The frames
+        // signal a timeline, acquire and present only take binary ones, see `FrameSync`.
+        let waited = {
+            let _scope = cpu_profiler::scope("wait frame");
+            self.gpu.wait_frame(self.frame_sync.pending(frame_index))
+        };
+        if waited.is_err() {
+            self.abandon_frame(frame_index);
+            return;
+        }
+        self.gpu.recycle_descriptor_sets(frame_index);
+        self.gpu.flush_deletions(frame_index);
 
-            let command_buffer = self.command_buffers[frame_index];
-            self.gpu
-                .device_context
-                .device
-                .reset_command_buffer(command_buffer, vk::CommandBufferResetFlags::empty())
-                .expect("failed to reset command buffer!");
-
-            let begin_info = vk::CommandBufferBeginInfo::default()
-                // ONE_TIME_SUBMIT_BIT: The command buffer will be rerecorded right after executing it once.
-                // RENDER_PASS_CONTINUE_BIT: This is a secondary command buffer that will be entirely within a single render pass.
-                // SIMULTANEOUS_USE_BIT: The command buffer can be resubmitted while it is also already pending execution.
-                .flags(vk::CommandBufferUsageFlags::SIMULTANEOUS_USE);
-            // Only relevant for secondary command buffers. It specifies which state to inherit from the calling primary command buffers.
-            // .inheritance_info()
-
-            self.gpu
-                .device_context
-                .device
-                .begin_command_buffer(command_buffer, &begin_info)
-                .expect("failed to begin command buffer!");
+        let Some(image_index) = self.gpu.acquire_image(image_available_semaphore) else {
+            self.swap_chain_dirty = true;
+            return;
+        };
 
-            {
-                let context = self.generate_render_context();
-                self.forward_renderer.render(
+        let signal = self.frame_sync.next_signal(frame_index, image_index);
+        self.gpu.reset_fence(signal);
+        let render_finished_semaphore = self.frame_sync.render_finished(&self.gpu, image_index);
+
+        let extract = {
+            let _scope = cpu_profiler::scope("extract");
+            self.extract_render()
+        };
+
+        let command_buffer = self.command_buffers[frame_index];
+        self.gpu.begin_commands(command_buffer);
+        let frame = RenderFrame {
+            gpu: &self.gpu,
+            command_buffer,
+            frame_index,
+            image_index,
+        };
+        self.hooks
+            .pre_render
+            .iter_mut()
+            .for_each(|hook| hook(&frame));
+        {
+            let RenderExtract {
+                context,
+                texture_cameras,
+                shadows,
+                decals,
+            } = extract;
+            let view_projection = context.projection * context.view;
+            let post_effects = self.post_chain.resolve(&context.post_overrides);
+            let selected = Self::selected_objects(&context.objects);
+            self.video_renderer.render(command_buffer, frame_index);
+            self.normal_debugger.render(command_buffer, frame_index);
+            self.shadow_renderer
+                .render(command_buffer, frame_index, &self.gpu_assets, &shadows);
+            self.texture_camera_renderer.render(
+                command_buffer,
+                frame_index,
+                &self.forward_renderer,
+                texture_cameras,
+            );
+            self.forward_renderer
+                .render(command_buffer, context, frame_index);
+            self.decal_renderer.render(
+                command_buffer,
+                frame_index,
+                &self.camera_uniforms,
+                &self.gpu_assets,
+                &decals,
+            );
+            if self.camera_uniforms.has_contact_shadows() {
+                self.contact_shadow_renderer.render(
                     command_buffer,
-                    context,
-                    image_index as usize,
                     frame_index,
+                    &self.camera_uniforms,
+                    self.forward_renderer.far_depth(),
                 );
             }
+            self.post_chain
+                .render(command_buffer, image_index as usize, &post_effects);
+            self.outline_renderer.render(
+                command_buffer,
+                image_index as usize,
+                &self.gpu_assets,
+                view_projection,
+                &selected,
+            );
+            self.text_renderer
+                .render(command_buffer, image_index as usize, frame_index);
+            self.egui_renderer.render(
+                command_buffer,
+                image_index as usize,
+                frame_index,
+                &primitives,
+                pixels_per_point,
+            );
+        }
+        let frame = RenderFrame {
+            gpu: &self.gpu,
+            command_buffer,
+            frame_index,
+            image_index,
+        };
+        self.hooks
+            .post_render
+            .iter_mut()
+            .for_each(|hook| hook(&frame));
+        self.gpu.end_commands(command_buffer);
 
-            self.gpu
-                .device_context
-                .device
-                .end_command_buffer(command_buffer)
-                .expect("failed to end command buffer!");
-
-            let wait_semaphores = [image_available_semaphore];
-            let signal_semaphores = [render_finished_semaphore];
-            let command_buffers = [command_buffer];
-            let stage_masks = [vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT];
-
-            let submit_info = vk::SubmitInfo::default()
-                .command_buffers(&command_buffers)
-                .wait_semaphores(&wait_semaphores)
-                .wait_dst_stage_mask(&stage_masks)
-                .signal_semaphores(&signal_semaphores);
-            self.gpu
-                .device_context
-                .device
-                .queue_submit(
-                    self.gpu.device_context.graphic_queue.unwrap(),
-                    &[submit_info],
-                    fence,
-                )
-                .unwrap();
-
-            let image_indices = [image_index];
-            let swap_chains = [self.gpu.swap_chain.swap_chain.unwrap()];
-            let present_info = vk::PresentInfoKHR::default()
-                .wait_semaphores(&signal_semaphores)
-                .image_indices(&image_indices)
-                .swapchains(&swap_chains);
-
-            // Queueing an image for presentation defines a set of queue operations, including waiting on the semaphores and submitting a presentation
-            // request to the presentation engine. However, the scope of this set of queue operations does not include the actual processing of the
-            // image by the presentation engine.
-            // vkQueuePresentKHR releases the acquisition of the image, which signals imageAvailableSemaphores for that image in later frames.
-            let present_result = self
-                .gpu
-                .swap_chain
-                .swap_chain_fn
-                .as_ref()
-                .unwrap()
-                .queue_present(
-                    self.gpu.device_context.present_queue.unwrap(),
-                    &present_info,
-                );
+        let _scope = cpu_profiler::scope("submit");
+        self.gpu.submit(
+            command_buffer,
+            image_available_semaphore,
+            render_finished_semaphore,
+            signal,
+        );
+        if !self.gpu.present(image_index, render_finished_semaphore) {
+            self.swap_chain_dirty = true;
+        }
+        self.last_image_index = Some(image_index);
+        self.gpu_assets.borrow().end_frame();
+
+        self.frame_index
+            .set((frame_index + 1) % self.command_buffers.len());
+    }
+
+    fn collect_decals(&mut self) -> Vec<DecalObject> {
+        let query = Query::<(&Transform, &Decal, Option<&Pooled>)>::new(&mut self.world);
+        query
+            .filter(|(_, _, pooled)| !pooled.is_some_and(|pooled| !pooled.active))
+            .map(|(transform, decal, _)| DecalObject {
+                texture: decal.texture.clone(),
+                model: transform.matrix(),
+                color: [decal.color.x, decal.color.y, decal.color.z, decal.opacity],
+                blend: decal.blend,
+                sort_order: decal.sort_order,
+            })
+            .collect()
+    }
 
-            let is_suboptimal = present_result.unwrap_or_else(|err_code| {
-                if err_code == vk::Result::ERROR_OUT_OF_DATE_KHR {
-                    true
-                } else {
-                    panic!("failed to submit present queue!");
+    // Only static meshes are outlined, off screen ones were culled already.
+    fn selected_objects(objects: &[RenderObject]) -> Vec<SelectedObject> {
+        objects
+            .iter()
+            .filter(|object| object.flags.contains(RenderFlags::SELECTED))
+            .filter_map(|object| match (&object.geom, object.entity) {
+                (RenderGeom::Static(geom), Some(entity)) => Some(SelectedObject {
+                    // 0 is the empty mask
+                    id: entity.id + 1,
+                    geom: geom.clone(),
+                    model: object.model,
+                }),
+                _ => None,
+            })
+            .collect()
+    }
+
+    fn run_ui(&mut self) -> (Vec<egui::ClippedPrimitive>, f32) {
+        let window = self.gpu.context.window.borrow().clone();
+        let raw_input = match (&window, self.ui_state.as_mut()) {
+            (Some(window), Some(ui_state)) => ui_state.take_egui_input(window),
+            _ => {
+                let size = self.gpu.surface_size();
+                egui::RawInput {
+                    screen_rect: Some(egui::Rect::from_min_size(
+                        egui::Pos2::ZERO,
+                        egui::vec2(size.width as f32, size.height as f32),
+                    )),
+                    ..Default::default()
                 }
-            });
-            if is_suboptimal {
-                // framebufferResized = false;
-                // self.recreate_swap_chain();
             }
-
-            self.frame_index
-                .set((frame_index + 1) % (self.in_flight_fences.len()));
+        };
+        let gpu_timings = match self.show_gpu_timings {
+            true => self.gpu.get_gpu_timings(),
+            false => None,
+        };
+        let mut output = self.egui_context.run(raw_input, |context| {
+            if let Some(ui_callback) = &mut self.ui_callback {
+                ui_callback(context);
+            }
+            if let Some(timings) = &gpu_timings {
+                Self::show_gpu_timings_window(context, timings);
+            }
+            error_overlay::show(context);
+        });
+        self.egui_cursor = std::mem::take(&mut output.platform_output.cursor_icon);
+        if let (Some(window), Some(ui_state)) = (&window, self.ui_state.as_mut()) {
+            ui_state.handle_platform_output(window, output.platform_output);
         }
+
+        self.egui_renderer.update_textures(&output.textures_delta);
+        let primitives = self
+            .egui_context
+            .tessellate(output.shapes, output.pixels_per_point);
+        (primitives, output.pixels_per_point)
+    }
+
+    fn show_gpu_timings_window(context: &egui::Context, timings: &GpuTimings) {
+        let milliseconds = |time: &Duration| format!("{:.3} ms", time.as_secs_f64() * 1000.0);
+        egui::Window::new("GPU")
+            .resizable(false)
+            .show(context, |ui| {
+                egui::Grid::new("gpu_timings").striped(true).show(ui, |ui| {
+                    for (label, time) in &timings.passes {
+                        ui.label(*label);
+                        ui.label(milliseconds(time));
+                        ui.end_row();
+                    }
+                    ui.strong("frame");
+                    ui.strong(milliseconds(&timings.total));
+                    ui.end_row();
+                });
+            });
     }
 
     fn create_command_pools(gpu: &GPU) -> vk::CommandPool {
@@ -285,16 +1968,21 @@ impl Mirage {
             let create_info = vk::CommandPoolCreateInfo::default()
                 .flags(vk::CommandPoolCreateFlags::RESET_COMMAND_BUFFER)
                 .queue_family_index(gpu.device_context.graphic_queue_family.unwrap());
-            let command_pool = gpu
-                .device_context
+            gpu.device_context
                 .device
                 .create_command_pool(&create_info, None)
-                .expect("failed to create command pool!");
-
-            command_pool
+                .expect("failed to create command pool!")
         }
     }
 
+    // The frame of the slot hung and is given up on. Its sync objects and command buffer stay
+    // pending on the GPU and can't be reused or destroyed, the slot gets new ones and they leak.
+    fn abandon_frame(&mut self, frame_index: usize) {
+        self.frame_sync.abandon(&self.gpu, frame_index);
+        self.command_buffers[frame_index] =
+            Self::create_command_buffers(&self.gpu, self.command_pool, 1)[0];
+    }
+
     fn create_command_buffers(
         gpu: &GPU,
         command_pool: vk::CommandPool,
@@ -308,54 +1996,15 @@ impl Mirage {
                 .command_buffer_count(count)
                 .level(vk::CommandBufferLevel::PRIMARY);
 
-            gpu.device_context
+            let command_buffers = gpu
+                .device_context
                 .device
                 .allocate_command_buffers(&allocate_info)
-                .expect("failed to allocate command buffers!")
-        }
-    }
-
-    fn create_sync_objects(
-        gpu: &GPU,
-        count: u32,
-    ) -> (Vec<vk::Semaphore>, Vec<vk::Semaphore>, Vec<vk::Fence>) {
-        unsafe {
-            let semaphore_create_info = vk::SemaphoreCreateInfo::default();
-
-            let image_available_semaphores = (0..count)
-                .map(|_| {
-                    gpu.device_context
-                        .device
-                        .create_semaphore(&semaphore_create_info, None)
-                        .expect("failed to create image available semaphore!")
-                })
-                .collect::<Vec<vk::Semaphore>>();
-
-            let render_finished_semaphores = (0..count)
-                .map(|_| {
-                    gpu.device_context
-                        .device
-                        .create_semaphore(&semaphore_create_info, None)
-                        .expect("failed to create render finished semaphore!")
-                })
-                .collect::<Vec<vk::Semaphore>>();
-
-            let fence_create_info =
-                vk::FenceCreateInfo::default().flags(vk::FenceCreateFlags::SIGNALED);
-            let in_flight_fences: Vec<vk::Fence> = (0..count)
-                .map(|_| {
-                    gpu.device_context
-                        .device
-                        .create_fence(&fence_create_info, None)
-                        .expect("failed to create in-flight fence!")
-                })
-                .collect::<Vec<vk::Fence>>();
-
-            (
-                image_available_semaphores,
-                render_finished_semaphores,
-                in_flight_fences,
-            )
+                .expect("failed to allocate command buffers!");
+            command_buffers
+                .iter()
+                .for_each(|command_buffer| gpu.debug_names.set_name(*command_buffer, "frame"));
+            command_buffers
         }
     }
 }
@@ -363,18 +2012,11 @@ impl Mirage {
 impl Drop for Mirage {
     fn drop(&mut self) {
         unsafe {
+            // the presents still queued wait on the render finished semaphores
+            self.gpu.wait_idle();
             let device = &self.gpu.device_context.device;
-            device.device_wait_idle().unwrap();
 
-            self.image_available_semaphores
-                .iter()
-                .for_each(|&semaphore| device.destroy_semaphore(semaphore, None));
-            self.render_finished_semaphores
-                .iter()
-                .for_each(|&semaphore| device.destroy_semaphore(semaphore, None));
-            self.in_flight_fences
-                .iter()
-                .for_each(|&fence| device.destroy_fence(fence, None));
+            self.frame_sync.destroy(device);
 
             device.destroy_command_pool(self.command_pool, None);
         }
@@ -0,0 +1,360 @@
+use super::camera_uniforms::{LightData, SceneData};
+use super::forward_renderer::ObjectData;
+use super::{GlobalsData, LightsData, Shading, GLOBALS_BINDING, OBJECT_SET};
+use std::mem::{offset_of, size_of};
+
+// Where a shader reads a block from.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum BlockBinding {
+    Uniform { set: u32, binding: u32 },
+    PushConstant,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct BlockField {
+    pub name: &'static str,
+    pub offset: u32,
+    pub size: u32,
+    // of a struct field or of the elements of an array of structs, their members are checked too
+    pub layout: Option<BlockLayout>,
+}
+
+// The layout of a `#[repr(C)]` struct, or of bytes packed by hand, the way it is uploaded into a
+// uniform block. Shaders may declare fewer members than there are fields, the leading ones have
+// to line up though.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BlockLayout {
+    pub name: &'static str,
+    pub fields: Vec<BlockField>,
+    pub size: u32,
+}
+
+fn field_size<T, F>(_: fn(&T) -> &F) -> u32 {
+    size_of::<F>() as u32
+}
+
+// Fields holding structs are followed by the layout of the struct, `field: layout`.
+macro_rules! block_layout {
+    (@layout) => {
+        None
+    };
+    (@layout $layout:expr) => {
+        Some($layout)
+    };
+    ($ty:ty { $($field:ident $(: $layout:expr)?),* $(,)? }) => {
+        BlockLayout {
+            name: stringify!($ty),
+            fields: vec![$(BlockField {
+                name: stringify!($field),
+                offset: offset_of!($ty, $field) as u32,
+                size: field_size(|data: &$ty| &data.$field),
+                layout: block_layout!(@layout $($layout)?),
+            }),*],
+            size: size_of::<$ty>() as u32,
+        }
+    };
+}
+
+impl BlockLayout {
+    pub fn scene() -> Self {
+        block_layout!(SceneData {
+            view,
+            projection,
+            view_projection,
+            inverse_view_projection,
+        })
+    }
+
+    pub fn lights() -> Self {
+        block_layout!(LightsData {
+            count,
+            lights: Self::light(),
+            ambient,
+            fog,
+            sun_direction,
+            sun_color_intensity,
+            clusters,
+        })
+    }
+
+    pub fn light() -> Self {
+        block_layout!(LightData {
+            position_range,
+            color_intensity,
+            direction_kind,
+            cone,
+            shadow,
+            shadow_matrix,
+        })
+    }
+
+    pub fn globals() -> Self {
+        block_layout!(GlobalsData {
+            time,
+            screen,
+            jitter,
+            camera_position,
+            camera_params,
+            previous_view_projection,
+        })
+    }
+
+    pub fn object() -> Self {
+        block_layout!(ObjectData {
+            model,
+            dissolve,
+            dissolve_edge,
+        })
+    }
+
+    // The parameter block `Material::param_bytes` fills, see `Shading::set_params`.
+    pub fn params(shading: &Shading) -> Self {
+        let (offsets, size) = shading.param_layout();
+        Self {
+            name: "material params",
+            fields: shading
+                .params
+                .iter()
+                .zip(offsets)
+                .map(|(&(name, kind), offset)| BlockField {
+                    name,
+                    offset,
+                    size: kind.size(),
+                    layout: None,
+                })
+                .collect(),
+            size,
+        }
+    }
+
+    // The blocks a material pipeline provides: the camera set, the object data and the params.
+    pub fn material_blocks(shading: &Shading, push_constants: bool) -> Vec<(BlockBinding, Self)> {
+        let object_binding = match push_constants {
+            true => BlockBinding::PushConstant,
+            false => BlockBinding::Uniform {
+                set: OBJECT_SET,
+                binding: 0,
+            },
+        };
+        let mut blocks = vec![
+            (BlockBinding::Uniform { set: 0, binding: 0 }, Self::scene()),
+            (BlockBinding::Uniform { set: 0, binding: 1 }, Self::lights()),
+            (
+                BlockBinding::Uniform {
+                    set: 0,
+                    binding: GLOBALS_BINDING,
+                },
+                Self::globals(),
+            ),
+            (object_binding, Self::object()),
+        ];
+        if !shading.params.is_empty() {
+            blocks.push((
+                BlockBinding::Uniform {
+                    set: 1,
+                    binding: Shading::PARAMS_BINDING,
+                },
+                Self::params(shading),
+            ));
+        }
+        blocks
+    }
+
+    // Compares the blocks the SPIR-V module declares against the layouts that are uploaded into
+    // them, by member position since the names differ between the languages. Mismatched offsets
+    // are usually std140 padding, a vec3 followed by a float or a field added on one side only,
+    // they render garbage without any validation error.
+    pub fn validate(code: &[u32], blocks: &[(BlockBinding, Self)]) -> Result<(), String> {
+        let bytes = code
+            .iter()
+            .flat_map(|word| word.to_le_bytes())
+            .collect::<Vec<_>>();
+        let options = naga::front::spv::Options {
+            adjust_coordinate_space: false,
+            ..Default::default()
+        };
+        let module = naga::front::spv::parse_u8_slice(&bytes, &options)
+            .map_err(|error| error.to_string())?;
+
+        let mut errors = vec![];
+        for (_, variable) in module.global_variables.iter() {
+            let binding = match (variable.space, &variable.binding) {
+                (naga::AddressSpace::PushConstant, _) => BlockBinding::PushConstant,
+                (naga::AddressSpace::Uniform, Some(binding)) => BlockBinding::Uniform {
+                    set: binding.group,
+                    binding: binding.binding,
+                },
+                _ => continue,
+            };
+            let Some((_, layout)) = blocks.iter().find(|(block, _)| *block == binding) else {
+                continue;
+            };
+            let mut ty = &module.types[variable.ty];
+            // naga puts the struct of a block into an unnamed struct with just that member
+            if let (None, naga::TypeInner::Struct { members, .. }) = (&ty.name, &ty.inner) {
+                if let [member] = members.as_slice() {
+                    let wrapped = &module.types[member.ty];
+                    if matches!(wrapped.inner, naga::TypeInner::Struct { .. }) {
+                        ty = wrapped;
+                    }
+                }
+            }
+            let naga::TypeInner::Struct { members, span } = &ty.inner else {
+                continue;
+            };
+            let block = ty.name.as_deref().unwrap_or("block");
+
+            Self::validate_members(&module, block, members, layout, &mut errors);
+            if *span > layout.size {
+                errors.push(format!(
+                    "{} is {} bytes, over the {} bytes of {}",
+                    block, span, layout.size, layout.name
+                ));
+            }
+        }
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors.join("\n"))
+        }
+    }
+
+    // Nested structs are named by their path from the block, e.g. `Lights.lights.color`.
+    fn validate_members(
+        module: &naga::Module,
+        block: &str,
+        members: &[naga::StructMember],
+        layout: &Self,
+        errors: &mut Vec<String>,
+    ) {
+        for (index, member) in members.iter().enumerate() {
+            let member_name = member.name.as_deref().unwrap_or("?");
+            let member_ty = &module.types[member.ty].inner;
+            let member_size = member_ty.size(module.to_ctx());
+            let Some(field) = layout.fields.get(index) else {
+                errors.push(format!(
+                    "{} member {} at offset {} is past the {} fields of {}",
+                    block,
+                    member_name,
+                    member.offset,
+                    layout.fields.len(),
+                    layout.name
+                ));
+                break;
+            };
+            if member.offset != field.offset || member_size != field.size {
+                errors.push(format!(
+                    "{} member {} is at offset {} with {} bytes, {}::{} at offset {} with {} bytes",
+                    block,
+                    member_name,
+                    member.offset,
+                    member_size,
+                    layout.name,
+                    field.name,
+                    field.offset,
+                    field.size
+                ));
+            }
+
+            let Some(field_layout) = &field.layout else {
+                continue;
+            };
+            let element_ty = match member_ty {
+                naga::TypeInner::Array { base, stride, .. } => {
+                    if *stride != field_layout.size {
+                        errors.push(format!(
+                            "{} member {} has a stride of {} bytes, {} is {} bytes",
+                            block, member_name, stride, field_layout.name, field_layout.size
+                        ));
+                    }
+                    &module.types[*base].inner
+                }
+                ty => ty,
+            };
+            if let naga::TypeInner::Struct { members, .. } = element_ty {
+                let path = format!("{}.{}", block, member_name);
+                Self::validate_members(module, &path, members, field_layout, errors);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{field_size, BlockBinding, BlockField, BlockLayout};
+    use std::mem::{offset_of, size_of};
+
+    #[repr(C)]
+    struct Item {
+        color: [f32; 4],
+        intensity: [f32; 4],
+    }
+
+    #[repr(C)]
+    struct Items {
+        count: [u32; 4],
+        items: [Item; 2],
+    }
+
+    fn layout() -> BlockLayout {
+        block_layout!(Items {
+            count,
+            items: block_layout!(Item { color, intensity }),
+        })
+    }
+
+    fn spirv(item: &str) -> Vec<u32> {
+        let source = format!(
+            "struct Item {{ {} }}
+            struct Items {{ count: vec4<u32>, items: array<Item, 2> }}
+            @group(0) @binding(0) var<uniform> items: Items;
+            @fragment
+            fn fs() -> @location(0) vec4<f32> {{
+                return vec4<f32>(items.items[1].color.x);
+            }}",
+            item
+        );
+        let module = naga::front::wgsl::parse_str(&source).unwrap();
+        let info = naga::valid::Validator::new(
+            naga::valid::ValidationFlags::all(),
+            naga::valid::Capabilities::all(),
+        )
+        .validate(&module)
+        .unwrap();
+        naga::back::spv::write_vec(&module, &info, &naga::back::spv::Options::default(), None)
+            .unwrap()
+    }
+
+    fn validate(item: &str) -> Result<(), String> {
+        let blocks = [(BlockBinding::Uniform { set: 0, binding: 0 }, layout())];
+        BlockLayout::validate(&spirv(item), &blocks)
+    }
+
+    #[test]
+    fn matching_nested_structs_validate() {
+        assert_eq!(validate("color: vec4<f32>, intensity: vec4<f32>"), Ok(()));
+    }
+
+    #[test]
+    fn members_of_nested_structs_are_checked() {
+        assert_eq!(
+            validate("color: vec3<f32>, intensity: vec4<f32>"),
+            Err(
+                "Items.items member color is at offset 0 with 12 bytes, Item::color at offset 0 \
+                 with 16 bytes"
+                    .to_string()
+            )
+        );
+    }
+
+    #[test]
+    fn array_elements_of_another_size_are_reported() {
+        let error =
+            validate("color: vec4<f32>, intensity: vec4<f32>, range: vec4<f32>").unwrap_err();
+        assert!(error.contains("Items member items has a stride of 48 bytes, Item is 32 bytes"));
+        assert!(
+            error.contains("Items.items member range at offset 32 is past the 2 fields of Item")
+        );
+    }
+}
@@ -0,0 +1,565 @@
+use super::PerFrameBuffer;
+use crate::assets::Environment;
+use crate::gpu::{Rhi, GPU};
+use crate::math::{Mat4, Vec3};
+use crate::scene::{Light, LightKind};
+use ash::vk;
+use std::cell::{Cell, RefCell};
+use std::rc::Rc;
+
+#[repr(C)]
+#[derive(Copy, Clone, PartialEq)]
+pub struct SceneData {
+    pub view: Mat4,
+    pub projection: Mat4,
+    pub view_projection: Mat4,
+    // clip space back to world space, e.g. to reconstruct positions from the depth buffer
+    pub inverse_view_projection: Mat4,
+}
+
+// Keep in sync with MAX_LIGHTS in the standard shader
+pub const MAX_LIGHTS: usize = 16;
+
+// input_attachment_index 0 as well, multisampled like the forward pass
+pub const DEPTH_INPUT_BINDING: u32 = 10;
+// Keep in sync with engine_globals.glsl
+pub const GLOBALS_BINDING: u32 = 11;
+pub const BLUE_NOISE_BINDING: u32 = 12;
+// the clustered lights and the light grid after it, see LightClusters
+pub const CLUSTER_LIGHTS_BINDING: u32 = 14;
+// the shadow atlas and its comparison sampler after it, see ShadowRenderer
+pub const SHADOW_ATLAS_BINDING: u32 = 16;
+
+const LIGHT_KIND_POINT: f32 = 0.0;
+const LIGHT_KIND_SPOT: f32 = 1.0;
+
+// All vec4 so the std140 layout matches without padding fields.
+#[repr(C)]
+#[derive(Debug, Copy, Clone, Default, PartialEq)]
+pub struct LightData {
+    // xyz world position, w range
+    pub position_range: [f32; 4],
+    // rgb color, a intensity
+    pub color_intensity: [f32; 4],
+    // xyz world direction, w LIGHT_KIND_*
+    pub direction_kind: [f32; 4],
+    // x, y cos of the inner and outer cone angles, z 1 with contact shadows
+    pub cone: [f32; 4],
+    // xy offset, zw scale of the light's tile in the shadow atlas, 0 without shadows. The first
+    // cube face of a point light, see `set_cube_shadow`
+    pub shadow: [f32; 4],
+    // world space to the clip space of the light's shadow map, the face offsets of a point light
+    pub shadow_matrix: Mat4,
+}
+
+impl LightData {
+    pub fn new(light: &Light, position: Vec3, direction: Vec3) -> Self {
+        let (kind, mut cone) = match light.kind {
+            LightKind::Point => (LIGHT_KIND_POINT, [0.0; 4]),
+            LightKind::Spot {
+                inner_angle,
+                outer_angle,
+            } => (
+                LIGHT_KIND_SPOT,
+                [inner_angle.cos(), outer_angle.cos(), 0.0, 0.0],
+            ),
+        };
+        if light.contact_shadows {
+            cone[2] = 1.0;
+        }
+
+        Self {
+            position_range: [position.x, position.y, position.z, light.range],
+            color_intensity: [light.color.x, light.color.y, light.color.z, light.intensity],
+            direction_kind: [direction.x, direction.y, direction.z, kind],
+            cone,
+            shadow: [0.0; 4],
+            shadow_matrix: Mat4::identity(),
+        }
+    }
+
+    // The cube faces of a point light's shadow map, `rects` like `ShadowTile::uv_rect` in
+    // CUBE_FACES order, all of the same size. The shading rebuilds each face's projection from
+    // the near and far planes, so the shadow matrix carries the faces instead: columns 0 to 2
+    // the xy offsets of two faces each, column 3 x near, y far.
+    pub fn set_cube_shadow(&mut self, rects: &[[f32; 4]], near: f32, far: f32) {
+        let mut columns = [[0.0; 4]; 4];
+        for (face, rect) in rects.iter().take(6).enumerate() {
+            let column = &mut columns[face / 2];
+            column[face % 2 * 2] = rect[0];
+            column[face % 2 * 2 + 1] = rect[1];
+        }
+        columns[3] = [near, far, 0.0, 0.0];
+        self.shadow = rects[0];
+        self.shadow_matrix = Mat4::from(columns);
+    }
+}
+
+// Per frame values every shader can read without the material wiring them, see engine_globals.glsl.
+#[repr(C)]
+#[derive(Debug, Copy, Clone, Default, PartialEq)]
+pub struct GlobalsData {
+    // x seconds since start, y seconds since the last frame, z frames rendered
+    pub time: [f32; 4],
+    // xy scene color size in pixels, zw one over it
+    pub screen: [f32; 4],
+    // xy subpixel offset of the projection in NDC, zw the previous frame's
+    pub jitter: [f32; 4],
+    // xyz world position, w near plane
+    pub camera_position: [f32; 4],
+    // x vertical field of view in radians, y aspect, z height of an orthographic camera, 0 for
+    // perspective ones
+    pub camera_params: [f32; 4],
+    // of the last frame without its jitter, for motion vectors
+    pub previous_view_projection: Mat4,
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, PartialEq)]
+pub struct LightsData {
+    pub count: [u32; 4],
+    pub lights: [LightData; MAX_LIGHTS],
+    // rgb color, a intensity
+    pub ambient: [f32; 4],
+    // rgb color, a exponential density, 0 without fog
+    pub fog: [f32; 4],
+    // xyz the way the sunlight travels, w 1 with contact shadows
+    pub sun_direction: [f32; 4],
+    // rgb color, a intensity, 0 without sun
+    pub sun_color_intensity: [f32; 4],
+    // x near, y far depth of the cluster slices, z 1 for linear slices, w clustered lights, 0
+    // without clustering, see LightClusters
+    pub clusters: [f32; 4],
+}
+
+// Camera matrices, lights, the engine noise, the environment lighting and the engine globals shared by every pass through a single descriptor set (set 0).
+// The uniform buffer of a frame is only rewritten when the matrices actually changed
+// since the last time that frame slot was written.
+pub struct CameraUniforms {
+    gpu: Rc<GPU>,
+
+    pub descriptor_set_layout: vk::DescriptorSetLayout,
+    pub descriptor_sets: Vec<vk::DescriptorSet>,
+
+    scene_data: RefCell<Option<SceneData>>,
+    lights_data: RefCell<LightsData>,
+    // changes every frame, written by every flush
+    globals_data: Cell<GlobalsData>,
+    // one dirty flag per frame in flight, each frame owns its own buffer
+    frames_dirty: Vec<Cell<bool>>,
+
+    uniform_buffer: PerFrameBuffer<SceneData>,
+    light_buffer: PerFrameBuffer<LightsData>,
+    globals_buffer: PerFrameBuffer<GlobalsData>,
+}
+
+impl CameraUniforms {
+    pub fn new(gpu: &Rc<GPU>, frames_in_flight: u32) -> Self {
+        let mut bindings = vec![
+            vk::DescriptorSetLayoutBinding {
+                binding: 0,
+                descriptor_type: vk::DescriptorType::UNIFORM_BUFFER,
+                descriptor_count: 1,
+                stage_flags: vk::ShaderStageFlags::ALL_GRAPHICS,
+                ..Default::default()
+            },
+            vk::DescriptorSetLayoutBinding {
+                binding: 1,
+                descriptor_type: vk::DescriptorType::UNIFORM_BUFFER,
+                descriptor_count: 1,
+                stage_flags: vk::ShaderStageFlags::ALL_GRAPHICS,
+                ..Default::default()
+            },
+            vk::DescriptorSetLayoutBinding {
+                binding: 2,
+                descriptor_type: vk::DescriptorType::SAMPLED_IMAGE,
+                descriptor_count: 1,
+                stage_flags: vk::ShaderStageFlags::FRAGMENT,
+                ..Default::default()
+            },
+            vk::DescriptorSetLayoutBinding {
+                binding: 3,
+                descriptor_type: vk::DescriptorType::SAMPLER,
+                descriptor_count: 1,
+                stage_flags: vk::ShaderStageFlags::FRAGMENT,
+                ..Default::default()
+            },
+        ];
+        // irradiance cube, specular cube and BRDF LUT, each a texture and its sampler
+        for binding in [4, 6, 8] {
+            bindings.push(vk::DescriptorSetLayoutBinding {
+                binding,
+                descriptor_type: vk::DescriptorType::SAMPLED_IMAGE,
+                descriptor_count: 1,
+                stage_flags: vk::ShaderStageFlags::FRAGMENT,
+                ..Default::default()
+            });
+            bindings.push(vk::DescriptorSetLayoutBinding {
+                binding: binding + 1,
+                descriptor_type: vk::DescriptorType::SAMPLER,
+                descriptor_count: 1,
+                stage_flags: vk::ShaderStageFlags::FRAGMENT,
+                ..Default::default()
+            });
+        }
+        // the depth of the prepass, see `set_depth_input`
+        bindings.push(vk::DescriptorSetLayoutBinding {
+            binding: DEPTH_INPUT_BINDING,
+            descriptor_type: vk::DescriptorType::INPUT_ATTACHMENT,
+            descriptor_count: 1,
+            stage_flags: vk::ShaderStageFlags::FRAGMENT,
+            ..Default::default()
+        });
+        // the engine globals and the blue noise texture and sampler, see `set_globals`
+        for (binding, descriptor_type) in [
+            (GLOBALS_BINDING, vk::DescriptorType::UNIFORM_BUFFER),
+            (BLUE_NOISE_BINDING, vk::DescriptorType::SAMPLED_IMAGE),
+            (BLUE_NOISE_BINDING + 1, vk::DescriptorType::SAMPLER),
+        ] {
+            bindings.push(vk::DescriptorSetLayoutBinding {
+                binding,
+                descriptor_type,
+                descriptor_count: 1,
+                stage_flags: vk::ShaderStageFlags::ALL_GRAPHICS,
+                ..Default::default()
+            });
+        }
+        // the clustered lights and the light grid, see `set_light_clusters`
+        for binding in [CLUSTER_LIGHTS_BINDING, CLUSTER_LIGHTS_BINDING + 1] {
+            bindings.push(vk::DescriptorSetLayoutBinding {
+                binding,
+                descriptor_type: vk::DescriptorType::STORAGE_BUFFER,
+                descriptor_count: 1,
+                stage_flags: vk::ShaderStageFlags::FRAGMENT,
+                ..Default::default()
+            });
+        }
+        // the shadow atlas and its sampler, see `set_shadow_atlas`
+        for (binding, descriptor_type) in [
+            (SHADOW_ATLAS_BINDING, vk::DescriptorType::SAMPLED_IMAGE),
+            (SHADOW_ATLAS_BINDING + 1, vk::DescriptorType::SAMPLER),
+        ] {
+            bindings.push(vk::DescriptorSetLayoutBinding {
+                binding,
+                descriptor_type,
+                descriptor_count: 1,
+                stage_flags: vk::ShaderStageFlags::FRAGMENT,
+                ..Default::default()
+            });
+        }
+        let descriptor_set_layout = gpu.create_descriptor_set_layout(&bindings);
+
+        let descriptor_sets =
+            gpu.create_descriptor_sets(&vec![descriptor_set_layout; frames_in_flight as usize]);
+        let uniform_buffer = PerFrameBuffer::new(gpu, frames_in_flight);
+        let light_buffer = PerFrameBuffer::new(gpu, frames_in_flight);
+        let globals_buffer = PerFrameBuffer::new(gpu, frames_in_flight);
+        for (index, descriptor_set) in descriptor_sets.iter().enumerate() {
+            uniform_buffer.bind(index, *descriptor_set, 0);
+            light_buffer.bind(index, *descriptor_set, 1);
+            globals_buffer.bind(index, *descriptor_set, GLOBALS_BINDING);
+        }
+
+        Self {
+            gpu: Rc::clone(gpu),
+
+            descriptor_set_layout,
+            descriptor_sets,
+
+            scene_data: RefCell::new(None),
+            lights_data: RefCell::new(LightsData {
+                count: [0; 4],
+                lights: [LightData::default(); MAX_LIGHTS],
+                ambient: [0.0; 4],
+                fog: [0.0; 4],
+                sun_direction: [0.0; 4],
+                sun_color_intensity: [0.0; 4],
+                clusters: [0.0; 4],
+            }),
+            globals_data: Cell::new(GlobalsData::default()),
+            frames_dirty: (0..frames_in_flight).map(|_| Cell::new(true)).collect(),
+
+            uniform_buffer,
+            light_buffer,
+            globals_buffer,
+        }
+    }
+
+    pub fn set(&self, view: Mat4, projection: Mat4) {
+        let mut scene_data = self.scene_data.borrow_mut();
+        match *scene_data {
+            Some(data) if data.view == view && data.projection == projection => {}
+            _ => {
+                let view_projection = projection * view;
+                *scene_data = Some(SceneData {
+                    view,
+                    projection,
+                    view_projection,
+                    inverse_view_projection: view_projection.invert(),
+                });
+                self.frames_dirty.iter().for_each(|dirty| dirty.set(true));
+            }
+        }
+    }
+
+    // Lights past MAX_LIGHTS are dropped, pass the most relevant ones first. The standard shading
+    // reads them from the light clusters instead.
+    pub fn set_lights(&self, lights: &[LightData]) {
+        let count = lights.len().min(MAX_LIGHTS);
+        // the environment stays as `set_ibl` and `set_environment` left it
+        let mut lights_data = *self.lights_data.borrow();
+        lights_data.count[0] = count as u32;
+        lights_data.lights = [LightData::default(); MAX_LIGHTS];
+        lights_data.lights[..count].copy_from_slice(&lights[..count]);
+
+        if *self.lights_data.borrow() != lights_data {
+            *self.lights_data.borrow_mut() = lights_data;
+            self.frames_dirty.iter().for_each(|dirty| dirty.set(true));
+        }
+    }
+
+    // What the light clustering of the frame left in `LightsData::clusters`.
+    pub fn set_clusters(&self, clusters: [f32; 4]) {
+        let mut lights_data = self.lights_data.borrow_mut();
+        if lights_data.clusters != clusters {
+            lights_data.clusters = clusters;
+            self.frames_dirty.iter().for_each(|dirty| dirty.set(true));
+        }
+    }
+
+    // The buffers of the frame's light clustering at CLUSTER_LIGHTS_BINDING (lights) and the one
+    // after it (grid), written once.
+    pub fn set_light_clusters(&self, frame_index: usize, lights: vk::Buffer, grid: vk::Buffer) {
+        let buffer_infos = [lights, grid].map(|buffer| {
+            [vk::DescriptorBufferInfo {
+                buffer,
+                offset: 0,
+                range: vk::WHOLE_SIZE,
+            }]
+        });
+        let writes = buffer_infos
+            .iter()
+            .zip(CLUSTER_LIGHTS_BINDING..)
+            .map(|(buffer_info, binding)| {
+                vk::WriteDescriptorSet::default()
+                    .descriptor_type(vk::DescriptorType::STORAGE_BUFFER)
+                    .buffer_info(buffer_info)
+                    .dst_set(self.descriptor_sets[frame_index])
+                    .dst_binding(binding)
+            })
+            .collect::<Vec<_>>();
+        unsafe {
+            self.gpu
+                .device_context
+                .device
+                .update_descriptor_sets(&writes, &[]);
+        }
+    }
+
+    // Tileable noise at binding 2 (texture) and 3 (sampler), written once for every frame.
+    pub fn set_noise(&self, texture: &<GPU as Rhi>::Texture) {
+        self.descriptor_sets
+            .iter()
+            .for_each(|&set| self.gpu.write_texture(set, 2, texture));
+    }
+
+    // Blue noise at BLUE_NOISE_BINDING (texture) and the one after it (sampler), written once for
+    // every frame.
+    pub fn set_blue_noise(&self, texture: &<GPU as Rhi>::Texture) {
+        self.descriptor_sets
+            .iter()
+            .for_each(|&set| self.gpu.write_texture(set, BLUE_NOISE_BINDING, texture));
+    }
+
+    pub fn set_globals(&self, globals: GlobalsData) {
+        self.globals_data.set(globals);
+    }
+
+    // Image based lighting at binding 4 (irradiance cube), 6 (specular cube) and 8 (BRDF LUT), written
+    // once for every frame. `specular_mips` goes to count.y, 0 turns environment lighting off.
+    pub fn set_ibl(
+        &self,
+        irradiance: &<GPU as Rhi>::Texture,
+        specular: &<GPU as Rhi>::Texture,
+        brdf_lut: &<GPU as Rhi>::Texture,
+        specular_mips: u32,
+    ) {
+        for &set in &self.descriptor_sets {
+            self.gpu.write_texture(set, 4, irradiance);
+            self.gpu.write_texture(set, 6, specular);
+            self.gpu.write_texture(set, 8, brdf_lut);
+        }
+
+        let mut lights_data = self.lights_data.borrow_mut();
+        if lights_data.count[1] != specular_mips {
+            lights_data.count[1] = specular_mips;
+            self.frames_dirty.iter().for_each(|dirty| dirty.set(true));
+        }
+    }
+
+    // Ambient light, fog and sun of the scene, the rest of the environment isn't in the uniforms.
+    pub fn set_environment(&self, environment: &Environment) {
+        let color = |color: Vec3, w: f32| [color.x, color.y, color.z, w];
+        let mut lights_data = self.lights_data.borrow_mut();
+        let previous = *lights_data;
+        lights_data.ambient = color(environment.ambient_color, environment.ambient_intensity);
+        lights_data.fog = color(environment.fog_color, environment.fog_density);
+        lights_data.sun_direction = color(
+            environment.sun_direction,
+            if environment.sun_contact_shadows {
+                1.0
+            } else {
+                0.0
+            },
+        );
+        lights_data.sun_color_intensity = color(environment.sun_color, environment.sun_intensity);
+        if *lights_data != previous {
+            self.frames_dirty.iter().for_each(|dirty| dirty.set(true));
+        }
+    }
+
+    // The depth attachment of a mobile friendly forward pass, shaders may only read it in its shading
+    // subpass. Left unwritten otherwise, written for every frame while the device is idle.
+    pub fn set_depth_input(&self, depth_view: vk::ImageView) {
+        let image_infos = [vk::DescriptorImageInfo {
+            image_view: depth_view,
+            image_layout: vk::ImageLayout::DEPTH_STENCIL_READ_ONLY_OPTIMAL,
+            sampler: vk::Sampler::null(),
+        }];
+        let writes = self
+            .descriptor_sets
+            .iter()
+            .map(|&set| {
+                vk::WriteDescriptorSet::default()
+                    .descriptor_type(vk::DescriptorType::INPUT_ATTACHMENT)
+                    .image_info(&image_infos)
+                    .dst_set(set)
+                    .dst_binding(DEPTH_INPUT_BINDING)
+            })
+            .collect::<Vec<_>>();
+        unsafe {
+            self.gpu
+                .device_context
+                .device
+                .update_descriptor_sets(&writes, &[]);
+        }
+    }
+
+    // The shadow atlas at SHADOW_ATLAS_BINDING and the sampler comparing against it after it,
+    // written once for every frame.
+    pub fn set_shadow_atlas(&self, image_view: vk::ImageView, sampler: vk::Sampler) {
+        let image_infos = [vk::DescriptorImageInfo {
+            image_view,
+            image_layout: vk::ImageLayout::DEPTH_STENCIL_READ_ONLY_OPTIMAL,
+            sampler: vk::Sampler::null(),
+        }];
+        let sampler_infos = [vk::DescriptorImageInfo {
+            sampler,
+            ..Default::default()
+        }];
+        let writes = self
+            .descriptor_sets
+            .iter()
+            .flat_map(|&set| {
+                [
+                    vk::WriteDescriptorSet::default()
+                        .descriptor_type(vk::DescriptorType::SAMPLED_IMAGE)
+                        .image_info(&image_infos)
+                        .dst_set(set)
+                        .dst_binding(SHADOW_ATLAS_BINDING),
+                    vk::WriteDescriptorSet::default()
+                        .descriptor_type(vk::DescriptorType::SAMPLER)
+                        .image_info(&sampler_infos)
+                        .dst_set(set)
+                        .dst_binding(SHADOW_ATLAS_BINDING + 1),
+                ]
+            })
+            .collect::<Vec<_>>();
+        unsafe {
+            self.gpu
+                .device_context
+                .device
+                .update_descriptor_sets(&writes, &[]);
+        }
+    }
+
+    // The noise, environment lighting, ambient, fog and sun of `source` for the frame, e.g. the
+    // window camera's for a camera rendering into a texture, and the shadow atlas. Lights and
+    // matrices stay its own.
+    pub fn inherit_environment(&self, frame_index: usize, source: &CameraUniforms) {
+        let bindings = (2..DEPTH_INPUT_BINDING)
+            .chain(BLUE_NOISE_BINDING..BLUE_NOISE_BINDING + 2)
+            .chain(SHADOW_ATLAS_BINDING..SHADOW_ATLAS_BINDING + 2);
+        let copies = bindings
+            .map(|binding| {
+                vk::CopyDescriptorSet::default()
+                    .src_set(source.descriptor_sets[frame_index])
+                    .src_binding(binding)
+                    .dst_set(self.descriptor_sets[frame_index])
+                    .dst_binding(binding)
+                    .descriptor_count(1)
+            })
+            .collect::<Vec<_>>();
+        unsafe {
+            self.gpu
+                .device_context
+                .device
+                .update_descriptor_sets(&[], &copies);
+        }
+
+        let source_data = *source.lights_data.borrow();
+        let mut lights_data = self.lights_data.borrow_mut();
+        let previous = *lights_data;
+        lights_data.count[1] = source_data.count[1];
+        lights_data.ambient = source_data.ambient;
+        lights_data.fog = source_data.fog;
+        lights_data.sun_direction = source_data.sun_direction;
+        lights_data.sun_color_intensity = source_data.sun_color_intensity;
+        if *lights_data != previous {
+            self.frames_dirty.iter().for_each(|dirty| dirty.set(true));
+        }
+    }
+
+    // Whether the sun or any of the set lights asks for contact shadows.
+    pub fn has_contact_shadows(&self) -> bool {
+        let lights_data = self.lights_data.borrow();
+        let count = lights_data.count[0] as usize;
+        lights_data.sun_direction[3] > 0.0
+            || lights_data.lights[..count]
+                .iter()
+                .any(|light| light.cone[2] > 0.0)
+    }
+
+    pub fn get(&self) -> Option<SceneData> {
+        *self.scene_data.borrow()
+    }
+
+    pub fn flush(&self, frame_index: usize) {
+        self.globals_buffer
+            .write(frame_index, &self.globals_data.get());
+        if !self.frames_dirty[frame_index].get() {
+            return;
+        }
+        let Some(scene_data) = *self.scene_data.borrow() else {
+            return;
+        };
+
+        self.uniform_buffer.write(frame_index, &scene_data);
+        self.light_buffer
+            .write(frame_index, &self.lights_data.borrow());
+        self.frames_dirty[frame_index].set(false);
+    }
+
+    pub fn get_descriptor_set(&self, frame_index: usize) -> vk::DescriptorSet {
+        self.descriptor_sets[frame_index]
+    }
+}
+
+impl Drop for CameraUniforms {
+    fn drop(&mut self) {
+        unsafe {
+            let device = &self.gpu.device_context.device;
+            device.destroy_descriptor_set_layout(self.descriptor_set_layout, None);
+        }
+    }
+}
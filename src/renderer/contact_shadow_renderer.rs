@@ -0,0 +1,388 @@
+use super::{CameraUniforms, ForwardRenderer};
+use crate::assets::Assets;
+use crate::gpu::{PassDesc, Rhi, VkPipeline, GPU};
+use ash::vk;
+use std::ffi::CString;
+use std::io;
+use std::mem::size_of;
+use std::rc::Rc;
+
+const CONTACT_SHADOWS_SHADER: &str = "contact_shadows.spv";
+// reads the depth of a forward pass without MSAA
+const CONTACT_SHADOWS_SINGLE_SAMPLE_SHADER: &str = "contact_shadows_single_sample.spv";
+const DEPTH_BINDING: u32 = 0;
+
+#[repr(C)]
+#[derive(Copy, Clone)]
+struct ContactParams {
+    length: f32,
+    thickness: f32,
+    steps: u32,
+    far_depth: f32,
+}
+
+// Screen space contact shadows between the decals and the post chain. A fullscreen pass marches a
+// few steps from every pixel toward the sun and the lights that enable `Light::contact_shadows`,
+// and darkens the scene color by the share of their light it finds blocked in the depth buffer.
+// It catches what the shadow maps lose to their bias where objects touch. The forward pass can't
+// read its own depth, so this runs after the shading rather than inside it. Nothing is drawn while
+// the depth stays in tile memory, see `get_depth_view`.
+pub struct ContactShadowRenderer {
+    gpu: Rc<GPU>,
+
+    // world units the rays travel
+    pub length: f32,
+    // how far behind a surface a ray still counts as occluded
+    pub thickness: f32,
+    pub steps: u32,
+
+    render_pass: vk::RenderPass,
+    framebuffer: vk::Framebuffer,
+    width: u32,
+    height: u32,
+    depth_view: Option<vk::ImageView>,
+
+    descriptor_set_layout: vk::DescriptorSetLayout,
+    // only the depth, the same for every frame in flight
+    descriptor_set: vk::DescriptorSet,
+    pipeline_layout: vk::PipelineLayout,
+    shader_module: vk::ShaderModule,
+    pipeline: vk::Pipeline,
+    // the shader and pipeline were built for a depth without MSAA
+    single_sample: bool,
+}
+
+impl ContactShadowRenderer {
+    pub fn new(gpu: &Rc<GPU>, forward_renderer: &ForwardRenderer) -> Self {
+        unsafe {
+            let render_pass = Self::create_render_pass(gpu);
+            let descriptor_set_layout =
+                gpu.create_descriptor_set_layout(&vec![vk::DescriptorSetLayoutBinding {
+                    binding: DEPTH_BINDING,
+                    descriptor_type: vk::DescriptorType::SAMPLED_IMAGE,
+                    descriptor_count: 1,
+                    stage_flags: vk::ShaderStageFlags::FRAGMENT,
+                    ..Default::default()
+                }]);
+            let descriptor_set = gpu.create_descriptor_sets(&[descriptor_set_layout])[0];
+            let pipeline_layout = Self::create_pipeline_layout(
+                gpu,
+                &forward_renderer.camera_uniforms,
+                descriptor_set_layout,
+            );
+
+            let single_sample = forward_renderer.msaa_samples() == vk::SampleCountFlags::TYPE_1;
+            let shader_module = Self::create_shader_module(gpu, single_sample);
+            let pipeline = Self::create_pipeline(gpu, shader_module, pipeline_layout, render_pass);
+
+            let mut contact_shadow_renderer = Self {
+                gpu: Rc::clone(gpu),
+
+                length: 0.3,
+                thickness: 0.1,
+                steps: 12,
+
+                render_pass,
+                framebuffer: vk::Framebuffer::null(),
+                width: 0,
+                height: 0,
+                depth_view: None,
+
+                descriptor_set_layout,
+                descriptor_set,
+                pipeline_layout,
+                shader_module,
+                pipeline,
+                single_sample,
+            };
+            contact_shadow_renderer.resize(forward_renderer);
+            contact_shadow_renderer
+        }
+    }
+
+    // After the forward renderer recreated its attachments, the shader follows its sample count.
+    pub fn resize(&mut self, forward_renderer: &ForwardRenderer) {
+        unsafe {
+            let single_sample = forward_renderer.msaa_samples() == vk::SampleCountFlags::TYPE_1;
+            if single_sample != self.single_sample {
+                let device = &self.gpu.device_context.device;
+                device.destroy_pipeline(self.pipeline, None);
+                device.destroy_shader_module(self.shader_module, None);
+                self.shader_module = Self::create_shader_module(&self.gpu, single_sample);
+                self.pipeline = Self::create_pipeline(
+                    &self.gpu,
+                    self.shader_module,
+                    self.pipeline_layout,
+                    self.render_pass,
+                );
+                self.single_sample = single_sample;
+            }
+
+            if self.framebuffer != vk::Framebuffer::null() {
+                self.gpu
+                    .device_context
+                    .device
+                    .destroy_framebuffer(self.framebuffer, None);
+            }
+
+            let scene_color = &forward_renderer.scene_color;
+            let attachments = [scene_color.view];
+            let create_info = vk::FramebufferCreateInfo::default()
+                .width(scene_color.width)
+                .height(scene_color.height)
+                .layers(1)
+                .attachments(&attachments)
+                .render_pass(self.render_pass);
+            self.framebuffer = self
+                .gpu
+                .device_context
+                .device
+                .create_framebuffer(&create_info, None)
+                .expect("failed to create framebuffer!");
+            self.width = scene_color.width;
+            self.height = scene_color.height;
+        }
+
+        self.depth_view = forward_renderer.get_depth_view();
+        let Some(depth_view) = self.depth_view else {
+            return;
+        };
+        // depth is read with textureLoad, no sampler
+        let image_infos = [vk::DescriptorImageInfo {
+            image_view: depth_view,
+            image_layout: vk::ImageLayout::DEPTH_STENCIL_READ_ONLY_OPTIMAL,
+            sampler: vk::Sampler::null(),
+        }];
+        let depth_write = vk::WriteDescriptorSet::default()
+            .descriptor_type(vk::DescriptorType::SAMPLED_IMAGE)
+            .image_info(&image_infos)
+            .dst_set(self.descriptor_set)
+            .dst_binding(DEPTH_BINDING);
+        unsafe {
+            self.gpu
+                .device_context
+                .device
+                .update_descriptor_sets(&[depth_write], &[]);
+        }
+    }
+
+    // Inside no pass, after the decals. `far_depth` is what the depth buffer is cleared to.
+    pub fn render(
+        &self,
+        command_buffer: vk::CommandBuffer,
+        frame_index: usize,
+        camera_uniforms: &CameraUniforms,
+        far_depth: f32,
+    ) {
+        if self.depth_view.is_none() || self.steps == 0 {
+            return;
+        }
+        let gpu = &self.gpu;
+        let pipeline = VkPipeline {
+            pipeline: self.pipeline,
+            layout: self.pipeline_layout,
+        };
+        let params = ContactParams {
+            length: self.length,
+            thickness: self.thickness,
+            steps: self.steps,
+            far_depth,
+        };
+
+        gpu.begin_pass(
+            command_buffer,
+            &PassDesc {
+                label: "contact shadows",
+                render_pass: self.render_pass,
+                framebuffer: self.framebuffer,
+                width: self.width,
+                height: self.height,
+                clear_color: [0.0; 4],
+                clear_depth: 1.0,
+            },
+        );
+        gpu.bind_pipeline(command_buffer, &pipeline);
+        gpu.bind_resource_sets(
+            command_buffer,
+            &pipeline,
+            0,
+            &[
+                camera_uniforms.get_descriptor_set(frame_index),
+                self.descriptor_set,
+            ],
+        );
+        gpu.push_constants(command_buffer, &pipeline, unsafe {
+            std::slice::from_raw_parts(
+                (&params as *const ContactParams) as *const u8,
+                size_of::<ContactParams>(),
+            )
+        });
+        gpu.draw(command_buffer, 3);
+        gpu.end_pass(command_buffer);
+    }
+
+    unsafe fn create_pipeline_layout(
+        gpu: &GPU,
+        camera_uniforms: &CameraUniforms,
+        descriptor_set_layout: vk::DescriptorSetLayout,
+    ) -> vk::PipelineLayout {
+        let push_constant_ranges = [vk::PushConstantRange::default()
+            .stage_flags(vk::ShaderStageFlags::ALL_GRAPHICS)
+            .offset(0)
+            .size(size_of::<ContactParams>() as u32)];
+        let set_layouts = [camera_uniforms.descriptor_set_layout, descriptor_set_layout];
+        let layout_create_info = vk::PipelineLayoutCreateInfo::default()
+            .set_layouts(&set_layouts)
+            .push_constant_ranges(&push_constant_ranges);
+
+        gpu.device_context
+            .device
+            .create_pipeline_layout(&layout_create_info, None)
+            .expect("failed to create pipeline layout!")
+    }
+
+    // The shader writes what is left of the lighting, multiplied into the scene color.
+    unsafe fn create_shader_module(gpu: &GPU, single_sample: bool) -> vk::ShaderModule {
+        let shader = match single_sample {
+            true => CONTACT_SHADOWS_SINGLE_SAMPLE_SHADER,
+            false => CONTACT_SHADOWS_SHADER,
+        };
+        let data = Assets::load_raw(shader).unwrap();
+        let shader_code = ash::util::read_spv(&mut io::Cursor::new(&data)).unwrap();
+        gpu.create_shader_module(&shader_code)
+    }
+
+    unsafe fn create_pipeline(
+        gpu: &GPU,
+        shader_module: vk::ShaderModule,
+        layout: vk::PipelineLayout,
+        render_pass: vk::RenderPass,
+    ) -> vk::Pipeline {
+        let vertex_entry = CString::new("vs").unwrap();
+        let fragment_entry = CString::new("fs").unwrap();
+        let shader_stages = [
+            vk::PipelineShaderStageCreateInfo::default()
+                .module(shader_module)
+                .stage(vk::ShaderStageFlags::VERTEX)
+                .name(vertex_entry.as_c_str()),
+            vk::PipelineShaderStageCreateInfo::default()
+                .module(shader_module)
+                .stage(vk::ShaderStageFlags::FRAGMENT)
+                .name(fragment_entry.as_c_str()),
+        ];
+
+        // a fullscreen triangle from the vertex index
+        let vertex_input_state = vk::PipelineVertexInputStateCreateInfo::default();
+        let input_assembly_stage = vk::PipelineInputAssemblyStateCreateInfo::default()
+            .topology(vk::PrimitiveTopology::TRIANGLE_LIST);
+        let dynamic_state = vk::PipelineDynamicStateCreateInfo::default()
+            .dynamic_states(&[vk::DynamicState::VIEWPORT, vk::DynamicState::SCISSOR]);
+        let viewport_state = vk::PipelineViewportStateCreateInfo::default()
+            .viewport_count(1)
+            .scissor_count(1);
+        let rasterization_state = vk::PipelineRasterizationStateCreateInfo::default()
+            .cull_mode(vk::CullModeFlags::NONE)
+            .polygon_mode(vk::PolygonMode::FILL)
+            .line_width(1.0);
+        let multisample = vk::PipelineMultisampleStateCreateInfo::default()
+            .rasterization_samples(vk::SampleCountFlags::TYPE_1);
+        // surface * remaining
+        let color_attachments = [vk::PipelineColorBlendAttachmentState {
+            blend_enable: true.into(),
+            src_color_blend_factor: vk::BlendFactor::DST_COLOR,
+            dst_color_blend_factor: vk::BlendFactor::ZERO,
+            color_blend_op: vk::BlendOp::ADD,
+            src_alpha_blend_factor: vk::BlendFactor::ZERO,
+            dst_alpha_blend_factor: vk::BlendFactor::ONE,
+            alpha_blend_op: vk::BlendOp::ADD,
+            color_write_mask: vk::ColorComponentFlags::R
+                | vk::ColorComponentFlags::G
+                | vk::ColorComponentFlags::B,
+        }];
+        let color_blend =
+            vk::PipelineColorBlendStateCreateInfo::default().attachments(&color_attachments);
+        let depth_stencil = vk::PipelineDepthStencilStateCreateInfo::default()
+            .depth_test_enable(false)
+            .depth_write_enable(false);
+
+        let create_info = vk::GraphicsPipelineCreateInfo::default()
+            .stages(&shader_stages)
+            .vertex_input_state(&vertex_input_state)
+            .input_assembly_state(&input_assembly_stage)
+            .dynamic_state(&dynamic_state)
+            .viewport_state(&viewport_state)
+            .rasterization_state(&rasterization_state)
+            .multisample_state(&multisample)
+            .color_blend_state(&color_blend)
+            .depth_stencil_state(&depth_stencil)
+            .layout(layout)
+            .render_pass(render_pass)
+            .subpass(0);
+
+        gpu.device_context
+            .device
+            .create_graphics_pipelines(gpu.pipeline_cache, &[create_info], None)
+            .expect("failed to create contact shadow pipeline!")[0]
+    }
+
+    unsafe fn create_render_pass(gpu: &GPU) -> vk::RenderPass {
+        let attachments = [vk::AttachmentDescription {
+            format: ForwardRenderer::SCENE_FORMAT,
+            samples: vk::SampleCountFlags::TYPE_1,
+            load_op: vk::AttachmentLoadOp::LOAD,
+            store_op: vk::AttachmentStoreOp::STORE,
+            stencil_load_op: vk::AttachmentLoadOp::DONT_CARE,
+            stencil_store_op: vk::AttachmentStoreOp::DONT_CARE,
+            initial_layout: vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+            final_layout: vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+            flags: Default::default(),
+        }];
+        let color_attachment_refs = [vk::AttachmentReference {
+            attachment: 0,
+            layout: vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+        }];
+        let sub_passes = [vk::SubpassDescription::default()
+            .pipeline_bind_point(vk::PipelineBindPoint::GRAPHICS)
+            .color_attachments(&color_attachment_refs)];
+
+        // the forward pass and the decals wrote the color, the forward pass the depth
+        let dependencies = [vk::SubpassDependency {
+            src_subpass: vk::SUBPASS_EXTERNAL,
+            src_stage_mask: vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT
+                | vk::PipelineStageFlags::LATE_FRAGMENT_TESTS,
+            src_access_mask: vk::AccessFlags::COLOR_ATTACHMENT_WRITE
+                | vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE,
+            dst_subpass: 0,
+            dst_stage_mask: vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT
+                | vk::PipelineStageFlags::FRAGMENT_SHADER,
+            dst_access_mask: vk::AccessFlags::COLOR_ATTACHMENT_READ
+                | vk::AccessFlags::COLOR_ATTACHMENT_WRITE
+                | vk::AccessFlags::SHADER_READ,
+            ..Default::default()
+        }];
+
+        let create_info = vk::RenderPassCreateInfo::default()
+            .attachments(&attachments)
+            .subpasses(&sub_passes)
+            .dependencies(&dependencies);
+
+        gpu.device_context
+            .device
+            .create_render_pass(&create_info, None)
+            .expect("failed to create contact shadow render pass!")
+    }
+}
+
+impl Drop for ContactShadowRenderer {
+    fn drop(&mut self) {
+        unsafe {
+            let device = &self.gpu.device_context.device;
+            device.destroy_framebuffer(self.framebuffer, None);
+            device.destroy_pipeline(self.pipeline, None);
+            device.destroy_pipeline_layout(self.pipeline_layout, None);
+            device.destroy_shader_module(self.shader_module, None);
+            device.destroy_descriptor_set_layout(self.descriptor_set_layout, None);
+            device.destroy_render_pass(self.render_pass, None);
+        }
+    }
+}
@@ -0,0 +1,114 @@
+use crate::assets::{AssetHandle, Assets, Geom, Material};
+use crate::math::{Mat4, Vec3};
+use crate::renderer::vertex::Vertex;
+use crate::renderer::{RenderObject, Shading};
+
+const FRUSTUM_COLOR: [f32; 3] = [0.0, 1.0, 1.0];
+// the camera's far plane is at infinity, the frustum lines stop this far from its near plane
+const FRUSTUM_LENGTH: f32 = 100.0;
+
+struct FrozenCamera {
+    view_projection: Mat4,
+    location: Vec3,
+    lines: AssetHandle<Geom>,
+}
+
+// Keeps culling with the camera as it was when frozen while the view camera moves on, and draws
+// the frozen frustum so what gets culled can be checked from outside of it.
+pub struct CullingDebugger {
+    material: AssetHandle<Material>,
+    frozen: bool,
+    // captured by the first `culling` call after freezing
+    camera: Option<FrozenCamera>,
+}
+
+impl CullingDebugger {
+    pub fn new(assets: &mut Assets) -> Self {
+        Self {
+            material: assets.handle(Material::new(Shading::load_debug_line())),
+            frozen: false,
+            camera: None,
+        }
+    }
+
+    pub fn is_frozen(&self) -> bool {
+        self.frozen
+    }
+
+    pub fn set_frozen(&mut self, frozen: bool, assets: &mut Assets) {
+        self.frozen = frozen;
+        if !frozen {
+            if let Some(camera) = self.camera.take() {
+                assets.remove(&camera.lines);
+            }
+        }
+    }
+
+    // The view projection and location to cull with, the given camera's unless frozen.
+    pub fn culling(
+        &mut self,
+        assets: &mut Assets,
+        view_projection: Mat4,
+        location: Vec3,
+        reverse_z: bool,
+    ) -> (Mat4, Vec3) {
+        if !self.frozen {
+            return (view_projection, location);
+        }
+        let camera = self.camera.get_or_insert_with(|| FrozenCamera {
+            view_projection,
+            location,
+            lines: assets.handle(Self::build_lines(&view_projection, location, reverse_z)),
+        });
+        (camera.view_projection, camera.location)
+    }
+
+    pub fn collect(&self, objects: &mut Vec<RenderObject>) {
+        if let Some(camera) = &self.camera {
+            // corners are in world space already
+            objects.push(RenderObject::new(
+                camera.lines.clone(),
+                self.material.clone(),
+                Mat4::identity(),
+            ));
+        }
+    }
+
+    pub fn build_lines(view_projection: &Mat4, location: Vec3, reverse_z: bool) -> Geom {
+        let inverse = view_projection.invert();
+        // the near plane is at depth 1 with reversed z, 0 otherwise
+        let near_depth = if reverse_z { 1.0 } else { 0.0 };
+        let unproject = |x: f32, y: f32| {
+            let clip = [x, y, near_depth, 1.0];
+            let mut point = [0.0; 4];
+            for col in 0..4 {
+                for row in 0..4 {
+                    point[row] += inverse[col][row] * clip[col];
+                }
+            }
+            Vec3::new(point[0], point[1], point[2]) / point[3]
+        };
+        let near =
+            [(-1.0, -1.0), (1.0, -1.0), (1.0, 1.0), (-1.0, 1.0)].map(|(x, y)| unproject(x, y));
+        let far = near.map(|corner| corner + (corner - location).normalize() * FRUSTUM_LENGTH);
+
+        let mut points = vec![];
+        for index in 0..4 {
+            let next = (index + 1) % 4;
+            points.extend([near[index], near[next], far[index], far[next]]);
+            points.extend([near[index], far[index]]);
+        }
+        let vertices = points
+            .iter()
+            .map(|point| Vertex {
+                position: [point.x, point.y, point.z],
+                color: FRUSTUM_COLOR,
+                uv: [0.0, 0.0],
+                normal: [0.0, 0.0, 0.0],
+            })
+            .collect::<Vec<_>>();
+        let indices = (0..vertices.len() as u32).collect();
+
+        Geom::new(vertices, indices)
+    }
+}
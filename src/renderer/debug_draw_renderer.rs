@@ -0,0 +1,65 @@
+use crate::assets::{AssetHandle, Assets, DynamicGeom, Material};
+use crate::math::Mat4;
+use crate::renderer::vertex::Vertex;
+use crate::renderer::{RenderObject, Shading};
+use crate::scene::{DebugDraw, World};
+
+// Draws the lines of the world's `DebugDraw` with the debug line shading, all of them as one
+// dynamic geometry rewritten every frame.
+pub struct DebugDrawRenderer {
+    material: AssetHandle<Material>,
+    // created by the first frame with lines
+    geom: Option<AssetHandle<DynamicGeom>>,
+}
+
+impl DebugDrawRenderer {
+    pub fn new(assets: &mut Assets) -> Self {
+        Self {
+            material: assets.handle(Material::new(Shading::load_debug_line())),
+            geom: None,
+        }
+    }
+
+    pub fn collect(&mut self, world: &World, assets: &mut Assets, objects: &mut Vec<RenderObject>) {
+        let Some(debug_draw) = world.get_resource::<DebugDraw>() else {
+            return;
+        };
+        if debug_draw.lines().is_empty() {
+            return;
+        }
+
+        let vertices = debug_draw
+            .lines()
+            .iter()
+            .flat_map(|&(from, to, color)| {
+                [from, to].map(|position| Vertex {
+                    position: [position.x, position.y, position.z],
+                    color: [color.x, color.y, color.z],
+                    uv: [0.0, 0.0],
+                    normal: [0.0, 0.0, 0.0],
+                })
+            })
+            .collect::<Vec<_>>();
+        let indices = (0..vertices.len() as u32).collect::<Vec<_>>();
+
+        let geom = match &self.geom {
+            Some(geom) => {
+                if let Some(dynamic_geom) = assets.load_mut(geom) {
+                    dynamic_geom.set(vertices, indices);
+                }
+                geom.clone()
+            }
+            None => {
+                let geom = assets.handle(DynamicGeom::new(vertices, indices));
+                self.geom = Some(geom.clone());
+                geom
+            }
+        };
+        // lines are in world space already
+        objects.push(RenderObject::new(
+            geom,
+            self.material.clone(),
+            Mat4::identity(),
+        ));
+    }
+}
@@ -0,0 +1,486 @@
+use super::{CameraUniforms, ForwardRenderer, GPUAssets};
+use crate::assets::{AssetHandle, Assets, Texture};
+use crate::gpu::{PassDesc, Rhi, VkPipeline, GPU};
+use crate::math::Mat4;
+use crate::scene::DecalBlend;
+use ash::vk;
+use std::cell::RefCell;
+use std::ffi::CString;
+use std::io;
+use std::mem::size_of;
+use std::rc::Rc;
+
+const DECAL_SHADER: &str = "decal.spv";
+// reads the depth of a forward pass without MSAA
+const DECAL_SINGLE_SAMPLE_SHADER: &str = "decal_single_sample.spv";
+const DEPTH_BINDING: u32 = 0;
+const TEXTURE_BINDING: u32 = 1;
+
+#[repr(C)]
+#[derive(Copy, Clone)]
+struct DecalParams {
+    model: Mat4,
+    world_to_decal: [[f32; 4]; 3],
+    color: [f32; 4],
+}
+
+// A decal volume to draw, see `Decal`.
+pub struct DecalObject {
+    pub texture: AssetHandle<Texture>,
+    pub model: Mat4,
+    // rgb tint, a opacity
+    pub color: [f32; 4],
+    pub blend: DecalBlend,
+    pub sort_order: i32,
+}
+
+// Deferred decals between the forward pass and the post chain. Each decal rasterizes the back faces
+// of its box into the resolved scene color and projects its texture onto the world positions the
+// forward pass left in the depth buffer, so it follows any surface without a mesh of its own or a
+// depth bias. Nothing is drawn while the depth stays in tile memory, see `get_depth_view`.
+pub struct DecalRenderer {
+    gpu: Rc<GPU>,
+
+    render_pass: vk::RenderPass,
+    framebuffer: vk::Framebuffer,
+    width: u32,
+    height: u32,
+    depth_view: Option<vk::ImageView>,
+
+    descriptor_set_layout: vk::DescriptorSetLayout,
+    pipeline_layout: vk::PipelineLayout,
+    shader_module: vk::ShaderModule,
+    // one per `DecalBlend`
+    pipelines: [vk::Pipeline; 3],
+    // the shader and pipelines were built for a depth without MSAA
+    single_sample: bool,
+    // one set per drawn decal of every frame in flight, grown on demand
+    descriptor_sets: RefCell<Vec<Vec<vk::DescriptorSet>>>,
+}
+
+impl DecalRenderer {
+    pub fn new(gpu: &Rc<GPU>, forward_renderer: &ForwardRenderer) -> Self {
+        unsafe {
+            let render_pass = Self::create_render_pass(gpu);
+            let descriptor_set_layout = gpu.create_descriptor_set_layout(&vec![
+                vk::DescriptorSetLayoutBinding {
+                    binding: DEPTH_BINDING,
+                    descriptor_type: vk::DescriptorType::SAMPLED_IMAGE,
+                    descriptor_count: 1,
+                    stage_flags: vk::ShaderStageFlags::FRAGMENT,
+                    ..Default::default()
+                },
+                vk::DescriptorSetLayoutBinding {
+                    binding: TEXTURE_BINDING,
+                    descriptor_type: vk::DescriptorType::SAMPLED_IMAGE,
+                    descriptor_count: 1,
+                    stage_flags: vk::ShaderStageFlags::FRAGMENT,
+                    ..Default::default()
+                },
+                vk::DescriptorSetLayoutBinding {
+                    binding: TEXTURE_BINDING + 1,
+                    descriptor_type: vk::DescriptorType::SAMPLER,
+                    descriptor_count: 1,
+                    stage_flags: vk::ShaderStageFlags::FRAGMENT,
+                    ..Default::default()
+                },
+            ]);
+            let pipeline_layout = Self::create_pipeline_layout(
+                gpu,
+                &forward_renderer.camera_uniforms,
+                descriptor_set_layout,
+            );
+
+            let single_sample = forward_renderer.msaa_samples() == vk::SampleCountFlags::TYPE_1;
+            let shader_module = Self::create_shader_module(gpu, single_sample);
+            let pipelines =
+                Self::create_pipelines(gpu, shader_module, pipeline_layout, render_pass);
+
+            let mut decal_renderer = Self {
+                gpu: Rc::clone(gpu),
+
+                render_pass,
+                framebuffer: vk::Framebuffer::null(),
+                width: 0,
+                height: 0,
+                depth_view: None,
+
+                descriptor_set_layout,
+                pipeline_layout,
+                shader_module,
+                pipelines,
+                single_sample,
+                descriptor_sets: RefCell::new(vec![
+                    vec![];
+                    ForwardRenderer::FRAMES_IN_FLIGHT as usize
+                ]),
+            };
+            decal_renderer.resize(forward_renderer);
+            decal_renderer
+        }
+    }
+
+    // After the forward renderer recreated its attachments, the shader follows its sample count.
+    pub fn resize(&mut self, forward_renderer: &ForwardRenderer) {
+        unsafe {
+            let single_sample = forward_renderer.msaa_samples() == vk::SampleCountFlags::TYPE_1;
+            if single_sample != self.single_sample {
+                let device = &self.gpu.device_context.device;
+                self.pipelines
+                    .iter()
+                    .for_each(|&pipeline| device.destroy_pipeline(pipeline, None));
+                device.destroy_shader_module(self.shader_module, None);
+                self.shader_module = Self::create_shader_module(&self.gpu, single_sample);
+                self.pipelines = Self::create_pipelines(
+                    &self.gpu,
+                    self.shader_module,
+                    self.pipeline_layout,
+                    self.render_pass,
+                );
+                self.single_sample = single_sample;
+            }
+
+            if self.framebuffer != vk::Framebuffer::null() {
+                self.gpu
+                    .device_context
+                    .device
+                    .destroy_framebuffer(self.framebuffer, None);
+            }
+
+            let scene_color = &forward_renderer.scene_color;
+            let attachments = [scene_color.view];
+            let create_info = vk::FramebufferCreateInfo::default()
+                .width(scene_color.width)
+                .height(scene_color.height)
+                .layers(1)
+                .attachments(&attachments)
+                .render_pass(self.render_pass);
+            self.framebuffer = self
+                .gpu
+                .device_context
+                .device
+                .create_framebuffer(&create_info, None)
+                .expect("failed to create framebuffer!");
+            self.width = scene_color.width;
+            self.height = scene_color.height;
+        }
+
+        self.depth_view = forward_renderer.get_depth_view();
+        let descriptor_sets = self.descriptor_sets.borrow();
+        descriptor_sets
+            .iter()
+            .flatten()
+            .for_each(|&set| self.write_depth(set));
+    }
+
+    // Inside no pass, right after the forward pass. Decals with a lower `sort_order` are drawn first.
+    pub fn render(
+        &self,
+        command_buffer: vk::CommandBuffer,
+        frame_index: usize,
+        camera_uniforms: &CameraUniforms,
+        gpu_assets: &RefCell<GPUAssets>,
+        decals: &[DecalObject],
+    ) {
+        if decals.is_empty() || self.depth_view.is_none() {
+            return;
+        }
+        let gpu = &self.gpu;
+        let gpu_assets = gpu_assets.borrow();
+
+        let mut decals = decals
+            .iter()
+            .filter_map(|decal| Some((decal, gpu_assets.get_texture(decal.texture.clone())?)))
+            .collect::<Vec<_>>();
+        decals.sort_by_key(|(decal, _)| decal.sort_order);
+
+        // every decal binds its own texture, all of them are written before the pass records
+        self.reserve(frame_index, decals.len());
+        let descriptor_sets = self.descriptor_sets.borrow();
+        let descriptor_sets = &descriptor_sets[frame_index];
+        for ((_, texture), &set) in decals.iter().zip(descriptor_sets) {
+            gpu.write_texture(set, TEXTURE_BINDING, &texture.texture);
+        }
+
+        gpu.begin_pass(
+            command_buffer,
+            &PassDesc {
+                label: "decals",
+                render_pass: self.render_pass,
+                framebuffer: self.framebuffer,
+                width: self.width,
+                height: self.height,
+                clear_color: [0.0; 4],
+                clear_depth: 1.0,
+            },
+        );
+        for ((decal, _), &set) in decals.iter().zip(descriptor_sets) {
+            let pipeline = VkPipeline {
+                pipeline: self.pipelines[decal.blend as usize],
+                layout: self.pipeline_layout,
+            };
+            let inverse = decal.model.invert();
+            let params = DecalParams {
+                model: decal.model,
+                world_to_decal: std::array::from_fn(|row| {
+                    [
+                        inverse[0][row],
+                        inverse[1][row],
+                        inverse[2][row],
+                        inverse[3][row],
+                    ]
+                }),
+                color: decal.color,
+            };
+
+            gpu.bind_pipeline(command_buffer, &pipeline);
+            gpu.bind_resource_sets(
+                command_buffer,
+                &pipeline,
+                0,
+                &[camera_uniforms.get_descriptor_set(frame_index), set],
+            );
+            gpu.push_constants(command_buffer, &pipeline, unsafe {
+                std::slice::from_raw_parts(
+                    (&params as *const DecalParams) as *const u8,
+                    size_of::<DecalParams>(),
+                )
+            });
+            gpu.draw(command_buffer, 36);
+        }
+        gpu.end_pass(command_buffer);
+    }
+
+    // Makes sure the frame has `count` sets, the frame's previous submission must be done.
+    fn reserve(&self, frame_index: usize, count: usize) {
+        let mut descriptor_sets = self.descriptor_sets.borrow_mut();
+        let sets = &mut descriptor_sets[frame_index];
+        if count <= sets.len() {
+            return;
+        }
+
+        let added = self.gpu.create_descriptor_sets(&vec![
+            self.descriptor_set_layout;
+            count.next_power_of_two() - sets.len()
+        ]);
+        added.iter().for_each(|&set| self.write_depth(set));
+        sets.extend(added);
+    }
+
+    fn write_depth(&self, set: vk::DescriptorSet) {
+        let Some(depth_view) = self.depth_view else {
+            return;
+        };
+        // depth is read with textureLoad, no sampler
+        let image_infos = [vk::DescriptorImageInfo {
+            image_view: depth_view,
+            image_layout: vk::ImageLayout::DEPTH_STENCIL_READ_ONLY_OPTIMAL,
+            sampler: vk::Sampler::null(),
+        }];
+        let depth_write = vk::WriteDescriptorSet::default()
+            .descriptor_type(vk::DescriptorType::SAMPLED_IMAGE)
+            .image_info(&image_infos)
+            .dst_set(set)
+            .dst_binding(DEPTH_BINDING);
+        unsafe {
+            self.gpu
+                .device_context
+                .device
+                .update_descriptor_sets(&[depth_write], &[]);
+        }
+    }
+
+    unsafe fn create_pipeline_layout(
+        gpu: &GPU,
+        camera_uniforms: &CameraUniforms,
+        descriptor_set_layout: vk::DescriptorSetLayout,
+    ) -> vk::PipelineLayout {
+        let push_constant_ranges = [vk::PushConstantRange::default()
+            .stage_flags(vk::ShaderStageFlags::ALL_GRAPHICS)
+            .offset(0)
+            .size(size_of::<DecalParams>() as u32)];
+        let set_layouts = [camera_uniforms.descriptor_set_layout, descriptor_set_layout];
+        let layout_create_info = vk::PipelineLayoutCreateInfo::default()
+            .set_layouts(&set_layouts)
+            .push_constant_ranges(&push_constant_ranges);
+
+        gpu.device_context
+            .device
+            .create_pipeline_layout(&layout_create_info, None)
+            .expect("failed to create pipeline layout!")
+    }
+
+    // The shader writes premultiplied color, the blend state decides how it lands on the surface.
+    unsafe fn create_shader_module(gpu: &GPU, single_sample: bool) -> vk::ShaderModule {
+        let shader = match single_sample {
+            true => DECAL_SINGLE_SAMPLE_SHADER,
+            false => DECAL_SHADER,
+        };
+        let data = Assets::load_raw(shader).unwrap();
+        let shader_code = ash::util::read_spv(&mut io::Cursor::new(&data)).unwrap();
+        gpu.create_shader_module(&shader_code)
+    }
+
+    // One per `DecalBlend`.
+    unsafe fn create_pipelines(
+        gpu: &GPU,
+        shader_module: vk::ShaderModule,
+        layout: vk::PipelineLayout,
+        render_pass: vk::RenderPass,
+    ) -> [vk::Pipeline; 3] {
+        let blends = [
+            DecalBlend::Alpha,
+            DecalBlend::Multiply,
+            DecalBlend::Additive,
+        ];
+        blends.map(|blend| Self::create_pipeline(gpu, shader_module, layout, render_pass, blend))
+    }
+
+    unsafe fn create_pipeline(
+        gpu: &GPU,
+        shader_module: vk::ShaderModule,
+        layout: vk::PipelineLayout,
+        render_pass: vk::RenderPass,
+        blend: DecalBlend,
+    ) -> vk::Pipeline {
+        let vertex_entry = CString::new("vs").unwrap();
+        let fragment_entry = CString::new("fs").unwrap();
+        let shader_stages = [
+            vk::PipelineShaderStageCreateInfo::default()
+                .module(shader_module)
+                .stage(vk::ShaderStageFlags::VERTEX)
+                .name(vertex_entry.as_c_str()),
+            vk::PipelineShaderStageCreateInfo::default()
+                .module(shader_module)
+                .stage(vk::ShaderStageFlags::FRAGMENT)
+                .name(fragment_entry.as_c_str()),
+        ];
+
+        // the box is generated from the vertex index
+        let vertex_input_state = vk::PipelineVertexInputStateCreateInfo::default();
+        let input_assembly_stage = vk::PipelineInputAssemblyStateCreateInfo::default()
+            .topology(vk::PrimitiveTopology::TRIANGLE_LIST);
+        let dynamic_state = vk::PipelineDynamicStateCreateInfo::default()
+            .dynamic_states(&[vk::DynamicState::VIEWPORT, vk::DynamicState::SCISSOR]);
+        let viewport_state = vk::PipelineViewportStateCreateInfo::default()
+            .viewport_count(1)
+            .scissor_count(1);
+        // back faces only, they still cover the box with the camera inside it
+        let rasterization_state = vk::PipelineRasterizationStateCreateInfo::default()
+            .cull_mode(vk::CullModeFlags::FRONT)
+            .front_face(vk::FrontFace::COUNTER_CLOCKWISE)
+            .polygon_mode(vk::PolygonMode::FILL)
+            .line_width(1.0);
+        let multisample = vk::PipelineMultisampleStateCreateInfo::default()
+            .rasterization_samples(vk::SampleCountFlags::TYPE_1);
+        let (src_color_blend_factor, dst_color_blend_factor) = match blend {
+            DecalBlend::Alpha => (vk::BlendFactor::ONE, vk::BlendFactor::ONE_MINUS_SRC_ALPHA),
+            // surface * (1 - a + color * a)
+            DecalBlend::Multiply => (
+                vk::BlendFactor::DST_COLOR,
+                vk::BlendFactor::ONE_MINUS_SRC_ALPHA,
+            ),
+            DecalBlend::Additive => (vk::BlendFactor::ONE, vk::BlendFactor::ONE),
+        };
+        let color_attachments = [vk::PipelineColorBlendAttachmentState {
+            blend_enable: true.into(),
+            src_color_blend_factor,
+            dst_color_blend_factor,
+            color_blend_op: vk::BlendOp::ADD,
+            src_alpha_blend_factor: vk::BlendFactor::ZERO,
+            dst_alpha_blend_factor: vk::BlendFactor::ONE,
+            alpha_blend_op: vk::BlendOp::ADD,
+            color_write_mask: vk::ColorComponentFlags::R
+                | vk::ColorComponentFlags::G
+                | vk::ColorComponentFlags::B,
+        }];
+        let color_blend =
+            vk::PipelineColorBlendStateCreateInfo::default().attachments(&color_attachments);
+        let depth_stencil = vk::PipelineDepthStencilStateCreateInfo::default()
+            .depth_test_enable(false)
+            .depth_write_enable(false);
+
+        let create_info = vk::GraphicsPipelineCreateInfo::default()
+            .stages(&shader_stages)
+            .vertex_input_state(&vertex_input_state)
+            .input_assembly_state(&input_assembly_stage)
+            .dynamic_state(&dynamic_state)
+            .viewport_state(&viewport_state)
+            .rasterization_state(&rasterization_state)
+            .multisample_state(&multisample)
+            .color_blend_state(&color_blend)
+            .depth_stencil_state(&depth_stencil)
+            .layout(layout)
+            .render_pass(render_pass)
+            .subpass(0);
+
+        gpu.device_context
+            .device
+            .create_graphics_pipelines(gpu.pipeline_cache, &[create_info], None)
+            .expect("failed to create decal pipeline!")[0]
+    }
+
+    unsafe fn create_render_pass(gpu: &GPU) -> vk::RenderPass {
+        // blended over the resolved scene color the forward pass left for the post chain
+        let attachments = [vk::AttachmentDescription {
+            format: ForwardRenderer::SCENE_FORMAT,
+            samples: vk::SampleCountFlags::TYPE_1,
+            load_op: vk::AttachmentLoadOp::LOAD,
+            store_op: vk::AttachmentStoreOp::STORE,
+            stencil_load_op: vk::AttachmentLoadOp::DONT_CARE,
+            stencil_store_op: vk::AttachmentStoreOp::DONT_CARE,
+            initial_layout: vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+            final_layout: vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+            flags: Default::default(),
+        }];
+        let color_attachment_refs = [vk::AttachmentReference {
+            attachment: 0,
+            layout: vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+        }];
+        let sub_passes = [vk::SubpassDescription::default()
+            .pipeline_bind_point(vk::PipelineBindPoint::GRAPHICS)
+            .color_attachments(&color_attachment_refs)];
+
+        // the forward pass wrote the color and the depth this pass reads
+        let dependencies = [vk::SubpassDependency {
+            src_subpass: vk::SUBPASS_EXTERNAL,
+            src_stage_mask: vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT
+                | vk::PipelineStageFlags::LATE_FRAGMENT_TESTS,
+            src_access_mask: vk::AccessFlags::COLOR_ATTACHMENT_WRITE
+                | vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE,
+            dst_subpass: 0,
+            dst_stage_mask: vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT
+                | vk::PipelineStageFlags::FRAGMENT_SHADER,
+            dst_access_mask: vk::AccessFlags::COLOR_ATTACHMENT_READ
+                | vk::AccessFlags::COLOR_ATTACHMENT_WRITE
+                | vk::AccessFlags::SHADER_READ,
+            ..Default::default()
+        }];
+
+        let create_info = vk::RenderPassCreateInfo::default()
+            .attachments(&attachments)
+            .subpasses(&sub_passes)
+            .dependencies(&dependencies);
+
+        gpu.device_context
+            .device
+            .create_render_pass(&create_info, None)
+            .expect("failed to create decal render pass!")
+    }
+}
+
+impl Drop for DecalRenderer {
+    fn drop(&mut self) {
+        unsafe {
+            let device = &self.gpu.device_context.device;
+            device.destroy_framebuffer(self.framebuffer, None);
+            self.pipelines
+                .iter()
+                .for_each(|&pipeline| device.destroy_pipeline(pipeline, None));
+            device.destroy_pipeline_layout(self.pipeline_layout, None);
+            device.destroy_shader_module(self.shader_module, None);
+            device.destroy_descriptor_set_layout(self.descriptor_set_layout, None);
+            device.destroy_render_pass(self.render_pass, None);
+        }
+    }
+}
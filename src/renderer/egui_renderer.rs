@@ -0,0 +1,659 @@
+use super::GPUAssets;
+use crate::assets::{AssetHandle, Assets, Texture};
+use crate::gpu::{
+    Allocation, PassDesc, Rhi, SamplerAddress, SamplerDesc, SamplerFilter, VkPipeline, GPU,
+};
+use ash::vk;
+use egui::epaint::{ClippedPrimitive, ImageData, ImageDelta, Primitive, Vertex};
+use egui::{TextureFilter, TextureId, TextureWrapMode, TexturesDelta};
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::ffi::CString;
+use std::io;
+use std::mem::{offset_of, size_of};
+use std::rc::Rc;
+
+const EGUI_SHADER: &str = "egui.spv";
+// grown to the next power of two when a frame needs more
+const INITIAL_VERTEX_CAPACITY: usize = 1 << 14;
+const INITIAL_INDEX_CAPACITY: usize = 1 << 15;
+
+#[repr(C)]
+#[derive(Copy, Clone)]
+struct EguiParams {
+    screen_size: [f32; 2],
+    srgb_target: u32,
+    _padding: u32,
+}
+
+// Host visible vertex and index buffers of one frame in flight, rewritten every frame.
+struct FrameBuffers {
+    vertex_buffer: vk::Buffer,
+    vertex_memory: Allocation,
+    vertex_capacity: usize,
+    index_buffer: vk::Buffer,
+    index_memory: Allocation,
+    index_capacity: usize,
+}
+
+impl FrameBuffers {
+    fn new(gpu: &GPU, vertex_capacity: usize, index_capacity: usize) -> Self {
+        unsafe {
+            let memory_properties =
+                vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT;
+            let (vertex_buffer, vertex_memory) = gpu.device_context.create_buffer(
+                (vertex_capacity * size_of::<Vertex>()) as vk::DeviceSize,
+                vk::BufferUsageFlags::VERTEX_BUFFER,
+                memory_properties,
+            );
+            let (index_buffer, index_memory) = gpu.device_context.create_buffer(
+                (index_capacity * size_of::<u32>()) as vk::DeviceSize,
+                vk::BufferUsageFlags::INDEX_BUFFER,
+                memory_properties,
+            );
+
+            Self {
+                vertex_buffer,
+                vertex_memory,
+                vertex_capacity,
+                index_buffer,
+                index_memory,
+                index_capacity,
+            }
+        }
+    }
+
+    fn drop(&mut self, gpu: &GPU) {
+        unsafe {
+            gpu.device_context
+                .destroy_buffer(self.vertex_buffer, self.vertex_memory);
+            gpu.device_context
+                .destroy_buffer(self.index_buffer, self.index_memory);
+        }
+    }
+}
+
+struct EguiTexture {
+    handle: AssetHandle<Texture>,
+    // one per frame in flight, rewritten before use like the material sets
+    descriptor_sets: Vec<vk::DescriptorSet>,
+}
+
+// Draws the egui output over the swap chain image, after the post chain.
+// egui textures (the font atlas, user images) are regular texture assets uploaded through GPUAssets,
+// partial updates patch the asset and upload it again. They only happen when new glyphs show up.
+pub struct EguiRenderer {
+    gpu: Rc<GPU>,
+    assets: Rc<RefCell<Assets>>,
+    gpu_assets: Rc<RefCell<GPUAssets>>,
+
+    format: vk::Format,
+    render_pass: vk::RenderPass,
+    descriptor_set_layout: vk::DescriptorSetLayout,
+    pipeline_layout: vk::PipelineLayout,
+    shader_module: vk::ShaderModule,
+    pipeline: vk::Pipeline,
+    framebuffers: Vec<vk::Framebuffer>,
+
+    textures: HashMap<TextureId, EguiTexture>,
+    spare_descriptor_sets: Vec<Vec<vk::DescriptorSet>>,
+    // freed at the next update, the frame that still draws them was recorded already
+    pending_frees: Vec<TextureId>,
+    frames: Vec<FrameBuffers>,
+}
+
+impl EguiRenderer {
+    pub fn new(
+        gpu: &Rc<GPU>,
+        assets: Rc<RefCell<Assets>>,
+        gpu_assets: Rc<RefCell<GPUAssets>>,
+        frames_in_flight: u32,
+    ) -> Self {
+        unsafe {
+            let format = gpu.swap_chain.borrow().format;
+            let render_pass = Self::create_render_pass(gpu, format);
+
+            let descriptor_set_layout = gpu.create_descriptor_set_layout(&vec![
+                vk::DescriptorSetLayoutBinding {
+                    binding: 0,
+                    descriptor_type: vk::DescriptorType::SAMPLED_IMAGE,
+                    descriptor_count: 1,
+                    stage_flags: vk::ShaderStageFlags::FRAGMENT,
+                    ..Default::default()
+                },
+                vk::DescriptorSetLayoutBinding {
+                    binding: 1,
+                    descriptor_type: vk::DescriptorType::SAMPLER,
+                    descriptor_count: 1,
+                    stage_flags: vk::ShaderStageFlags::FRAGMENT,
+                    ..Default::default()
+                },
+            ]);
+            let push_constant_ranges = [vk::PushConstantRange::default()
+                .stage_flags(vk::ShaderStageFlags::ALL_GRAPHICS)
+                .offset(0)
+                .size(size_of::<EguiParams>() as u32)];
+            let descriptor_set_layouts = [descriptor_set_layout];
+            let layout_create_info = vk::PipelineLayoutCreateInfo::default()
+                .set_layouts(&descriptor_set_layouts)
+                .push_constant_ranges(&push_constant_ranges);
+            let pipeline_layout = gpu
+                .device_context
+                .device
+                .create_pipeline_layout(&layout_create_info, None)
+                .expect("failed to create pipeline layout!");
+
+            let data = Assets::load_raw(EGUI_SHADER).unwrap();
+            let shader_code = ash::util::read_spv(&mut io::Cursor::new(&data)).unwrap();
+            let shader_module = gpu.create_shader_module(&shader_code);
+            let pipeline = Self::create_pipeline(gpu, shader_module, pipeline_layout, render_pass);
+
+            let frames = (0..frames_in_flight)
+                .map(|_| FrameBuffers::new(gpu, INITIAL_VERTEX_CAPACITY, INITIAL_INDEX_CAPACITY))
+                .collect();
+
+            let mut egui_renderer = Self {
+                gpu: Rc::clone(gpu),
+                assets,
+                gpu_assets,
+
+                format,
+                render_pass,
+                descriptor_set_layout,
+                pipeline_layout,
+                shader_module,
+                pipeline,
+                framebuffers: vec![],
+
+                textures: HashMap::new(),
+                spare_descriptor_sets: vec![],
+                pending_frees: vec![],
+                frames,
+            };
+            egui_renderer.create_framebuffers();
+            egui_renderer
+        }
+    }
+
+    // Follows the swap chain, the pipeline only has to be rebuilt when its format changed.
+    pub fn resize(&mut self) {
+        unsafe {
+            self.destroy_framebuffers();
+
+            let format = self.gpu.swap_chain.borrow().format;
+            if format != self.format {
+                let device = &self.gpu.device_context.device;
+                device.destroy_pipeline(self.pipeline, None);
+                device.destroy_render_pass(self.render_pass, None);
+
+                self.render_pass = Self::create_render_pass(&self.gpu, format);
+                self.pipeline = Self::create_pipeline(
+                    &self.gpu,
+                    self.shader_module,
+                    self.pipeline_layout,
+                    self.render_pass,
+                );
+                self.format = format;
+            }
+
+            self.create_framebuffers();
+        }
+    }
+
+    // Applies the texture changes of an egui frame, before its meshes are drawn.
+    pub fn update_textures(&mut self, textures_delta: &TexturesDelta) {
+        for id in std::mem::take(&mut self.pending_frees) {
+            self.free_texture(id);
+        }
+        for (id, delta) in &textures_delta.set {
+            self.set_texture(*id, delta);
+        }
+        self.pending_frees.extend(&textures_delta.free);
+    }
+
+    pub fn render(
+        &mut self,
+        command_buffer: vk::CommandBuffer,
+        image_index: usize,
+        frame_index: usize,
+        primitives: &[ClippedPrimitive],
+        pixels_per_point: f32,
+    ) {
+        // paint callbacks are not supported
+        let meshes = primitives
+            .iter()
+            .filter_map(|primitive| match &primitive.primitive {
+                Primitive::Mesh(mesh) => Some((primitive.clip_rect, mesh)),
+                Primitive::Callback(_) => None,
+            })
+            .collect::<Vec<_>>();
+        if meshes.is_empty() {
+            return;
+        }
+
+        let vertex_count = meshes.iter().map(|(_, mesh)| mesh.vertices.len()).sum();
+        let index_count = meshes.iter().map(|(_, mesh)| mesh.indices.len()).sum();
+        self.reserve(frame_index, vertex_count, index_count);
+
+        let gpu = &self.gpu;
+        let device = &gpu.device_context.device;
+        let frame = &self.frames[frame_index];
+        let (width, height) = {
+            let extent = gpu.swap_chain.borrow().extent;
+            (extent.width, extent.height)
+        };
+
+        let pipeline = VkPipeline {
+            pipeline: self.pipeline,
+            layout: self.pipeline_layout,
+        };
+        let params = EguiParams {
+            screen_size: [
+                width as f32 / pixels_per_point,
+                height as f32 / pixels_per_point,
+            ],
+            srgb_target: Self::is_srgb(self.format) as u32,
+            _padding: 0,
+        };
+
+        gpu.begin_pass(
+            command_buffer,
+            &PassDesc {
+                label: "egui",
+                render_pass: self.render_pass,
+                framebuffer: self.framebuffers[image_index],
+                width,
+                height,
+                clear_color: [0.0, 0.0, 0.0, 0.0],
+                clear_depth: 1.0,
+            },
+        );
+        gpu.bind_pipeline(command_buffer, &pipeline);
+        gpu.push_constants(command_buffer, &pipeline, unsafe {
+            std::slice::from_raw_parts(
+                (&params as *const EguiParams) as *const u8,
+                size_of::<EguiParams>(),
+            )
+        });
+
+        let mut written_textures = HashSet::new();
+        let mut vertex_offset = 0;
+        let mut index_offset = 0;
+        unsafe {
+            device.cmd_bind_vertex_buffers(command_buffer, 0, &[frame.vertex_buffer], &[0]);
+            device.cmd_bind_index_buffer(
+                command_buffer,
+                frame.index_buffer,
+                0,
+                vk::IndexType::UINT32,
+            );
+
+            for (clip_rect, mesh) in meshes {
+                let vertices = frame.vertex_memory.mapped as *mut Vertex;
+                let indices = frame.index_memory.mapped as *mut u32;
+                std::ptr::copy_nonoverlapping(
+                    mesh.vertices.as_ptr(),
+                    vertices.add(vertex_offset),
+                    mesh.vertices.len(),
+                );
+                std::ptr::copy_nonoverlapping(
+                    mesh.indices.as_ptr(),
+                    indices.add(index_offset),
+                    mesh.indices.len(),
+                );
+                let first_vertex = vertex_offset;
+                let first_index = index_offset;
+                vertex_offset += mesh.vertices.len();
+                index_offset += mesh.indices.len();
+
+                // clip rects are in points, scissors in pixels
+                let min_x = (clip_rect.min.x * pixels_per_point)
+                    .round()
+                    .clamp(0.0, width as f32);
+                let min_y = (clip_rect.min.y * pixels_per_point)
+                    .round()
+                    .clamp(0.0, height as f32);
+                let max_x = (clip_rect.max.x * pixels_per_point)
+                    .round()
+                    .clamp(min_x, width as f32);
+                let max_y = (clip_rect.max.y * pixels_per_point)
+                    .round()
+                    .clamp(min_y, height as f32);
+                if max_x <= min_x || max_y <= min_y {
+                    continue;
+                }
+
+                let Some(texture) = self.textures.get(&mesh.texture_id) else {
+                    continue;
+                };
+                let descriptor_set = texture.descriptor_sets[frame_index];
+                if written_textures.insert(mesh.texture_id) {
+                    let Some(gpu_texture) =
+                        self.gpu_assets.borrow().get_texture(texture.handle.clone())
+                    else {
+                        continue;
+                    };
+                    gpu.write_texture(descriptor_set, 0, &gpu_texture.texture);
+                }
+
+                gpu.bind_resource_sets(command_buffer, &pipeline, 0, &[descriptor_set]);
+                device.cmd_set_scissor(
+                    command_buffer,
+                    0,
+                    &[vk::Rect2D {
+                        offset: vk::Offset2D {
+                            x: min_x as i32,
+                            y: min_y as i32,
+                        },
+                        extent: vk::Extent2D {
+                            width: (max_x - min_x) as u32,
+                            height: (max_y - min_y) as u32,
+                        },
+                    }],
+                );
+                device.cmd_draw_indexed(
+                    command_buffer,
+                    mesh.indices.len() as u32,
+                    1,
+                    first_index as u32,
+                    first_vertex as i32,
+                    0,
+                );
+            }
+        }
+
+        gpu.end_pass(command_buffer);
+    }
+
+    fn set_texture(&mut self, id: TextureId, delta: &ImageDelta) {
+        let [width, height] = delta.image.size();
+        let pixels = match &delta.image {
+            ImageData::Color(image) => image
+                .pixels
+                .iter()
+                .flat_map(|color| color.to_array())
+                .collect::<Vec<_>>(),
+            ImageData::Font(image) => image
+                .srgba_pixels(None)
+                .flat_map(|color| color.to_array())
+                .collect::<Vec<_>>(),
+        };
+
+        // a patch of an existing texture, copied into the asset row by row
+        if let Some([x, y]) = delta.pos {
+            let Some(texture) = self.textures.get(&id) else {
+                log::warn!("egui texture {:?} patched before being set", id);
+                return;
+            };
+            let mut assets = self.assets.borrow_mut();
+            let asset = assets.load_mut(&texture.handle).unwrap();
+            let row_size = width * 4;
+            for row in 0..height {
+                let start = ((y + row) * asset.width as usize + x) * 4;
+                asset.pixels[start..start + row_size]
+                    .copy_from_slice(&pixels[row * row_size..(row + 1) * row_size]);
+            }
+            self.gpu_assets.borrow().release_texture(&texture.handle);
+            return;
+        }
+
+        self.free_texture(id);
+        let mut texture = Texture::rgba8(width as u32, height as u32, 1, pixels);
+        texture.set_sampler(Self::sampler(delta));
+        let handle = self.assets.borrow_mut().handle(texture);
+        let descriptor_sets = self.spare_descriptor_sets.pop().unwrap_or_else(|| {
+            self.gpu
+                .create_descriptor_sets(&vec![self.descriptor_set_layout; self.frames.len()])
+        });
+        self.textures.insert(
+            id,
+            EguiTexture {
+                handle,
+                descriptor_sets,
+            },
+        );
+    }
+
+    // Without mips only the magnification filter matters.
+    fn sampler(delta: &ImageDelta) -> SamplerDesc {
+        SamplerDesc {
+            filter: match delta.options.magnification {
+                TextureFilter::Linear => SamplerFilter::Linear,
+                TextureFilter::Nearest => SamplerFilter::Nearest,
+            },
+            address: match delta.options.wrap_mode {
+                TextureWrapMode::ClampToEdge => SamplerAddress::ClampToEdge,
+                TextureWrapMode::Repeat => SamplerAddress::Repeat,
+                TextureWrapMode::MirroredRepeat => SamplerAddress::MirroredRepeat,
+            },
+            anisotropy: false,
+            ..Default::default()
+        }
+    }
+
+    fn free_texture(&mut self, id: TextureId) {
+        let Some(texture) = self.textures.remove(&id) else {
+            return;
+        };
+        // the upload goes through the deletion queue, the descriptor sets are free to reuse as
+        // each frame only writes its own
+        self.gpu_assets.borrow().release_texture(&texture.handle);
+        self.assets.borrow_mut().remove(&texture.handle);
+        self.spare_descriptor_sets.push(texture.descriptor_sets);
+    }
+
+    // The frame's fence was waited on, its old buffers are no longer read.
+    fn reserve(&mut self, frame_index: usize, vertex_count: usize, index_count: usize) {
+        let frame = &self.frames[frame_index];
+        if vertex_count <= frame.vertex_capacity && index_count <= frame.index_capacity {
+            return;
+        }
+
+        let vertex_capacity = frame.vertex_capacity.max(vertex_count.next_power_of_two());
+        let index_capacity = frame.index_capacity.max(index_count.next_power_of_two());
+        let mut frame = std::mem::replace(
+            &mut self.frames[frame_index],
+            FrameBuffers::new(&self.gpu, vertex_capacity, index_capacity),
+        );
+        frame.drop(&self.gpu);
+    }
+
+    fn is_srgb(format: vk::Format) -> bool {
+        matches!(
+            format,
+            vk::Format::B8G8R8A8_SRGB
+                | vk::Format::R8G8B8A8_SRGB
+                | vk::Format::A8B8G8R8_SRGB_PACK32
+        )
+    }
+
+    unsafe fn create_pipeline(
+        gpu: &GPU,
+        shader_module: vk::ShaderModule,
+        pipeline_layout: vk::PipelineLayout,
+        render_pass: vk::RenderPass,
+    ) -> vk::Pipeline {
+        let vertex_entry = CString::new("vs").unwrap();
+        let fragment_entry = CString::new("fs").unwrap();
+        let shader_stages = [
+            vk::PipelineShaderStageCreateInfo::default()
+                .module(shader_module)
+                .stage(vk::ShaderStageFlags::VERTEX)
+                .name(vertex_entry.as_c_str()),
+            vk::PipelineShaderStageCreateInfo::default()
+                .module(shader_module)
+                .stage(vk::ShaderStageFlags::FRAGMENT)
+                .name(fragment_entry.as_c_str()),
+        ];
+
+        let binding_descriptions = [vk::VertexInputBindingDescription {
+            binding: 0,
+            stride: size_of::<Vertex>() as u32,
+            input_rate: vk::VertexInputRate::VERTEX,
+        }];
+        let attribute_descriptions = [
+            vk::VertexInputAttributeDescription {
+                location: 0,
+                binding: 0,
+                format: vk::Format::R32G32_SFLOAT,
+                offset: offset_of!(Vertex, pos) as u32,
+            },
+            vk::VertexInputAttributeDescription {
+                location: 1,
+                binding: 0,
+                format: vk::Format::R32G32_SFLOAT,
+                offset: offset_of!(Vertex, uv) as u32,
+            },
+            // sRGB bytes, the shader does the conversion
+            vk::VertexInputAttributeDescription {
+                location: 2,
+                binding: 0,
+                format: vk::Format::R8G8B8A8_UNORM,
+                offset: offset_of!(Vertex, color) as u32,
+            },
+        ];
+        let vertex_input_state = vk::PipelineVertexInputStateCreateInfo::default()
+            .vertex_binding_descriptions(&binding_descriptions)
+            .vertex_attribute_descriptions(&attribute_descriptions);
+        let input_assembly_stage = vk::PipelineInputAssemblyStateCreateInfo::default()
+            .topology(vk::PrimitiveTopology::TRIANGLE_LIST);
+        let dynamic_state = vk::PipelineDynamicStateCreateInfo::default()
+            .dynamic_states(&[vk::DynamicState::VIEWPORT, vk::DynamicState::SCISSOR]);
+        let viewport_state = vk::PipelineViewportStateCreateInfo::default()
+            .viewport_count(1)
+            .scissor_count(1);
+        let rasterization_state = vk::PipelineRasterizationStateCreateInfo::default()
+            .cull_mode(vk::CullModeFlags::NONE)
+            .polygon_mode(vk::PolygonMode::FILL)
+            .line_width(1.0);
+        let multisample = vk::PipelineMultisampleStateCreateInfo::default()
+            .rasterization_samples(vk::SampleCountFlags::TYPE_1);
+        // premultiplied alpha
+        let color_attachments = [vk::PipelineColorBlendAttachmentState {
+            blend_enable: true.into(),
+            src_color_blend_factor: vk::BlendFactor::ONE,
+            dst_color_blend_factor: vk::BlendFactor::ONE_MINUS_SRC_ALPHA,
+            color_blend_op: vk::BlendOp::ADD,
+            src_alpha_blend_factor: vk::BlendFactor::ONE_MINUS_DST_ALPHA,
+            dst_alpha_blend_factor: vk::BlendFactor::ONE,
+            alpha_blend_op: vk::BlendOp::ADD,
+            color_write_mask: vk::ColorComponentFlags::RGBA,
+        }];
+        let color_blend =
+            vk::PipelineColorBlendStateCreateInfo::default().attachments(&color_attachments);
+        let depth_stencil = vk::PipelineDepthStencilStateCreateInfo::default()
+            .depth_test_enable(false)
+            .depth_write_enable(false);
+
+        let create_info = vk::GraphicsPipelineCreateInfo::default()
+            .stages(&shader_stages)
+            .vertex_input_state(&vertex_input_state)
+            .input_assembly_state(&input_assembly_stage)
+            .dynamic_state(&dynamic_state)
+            .viewport_state(&viewport_state)
+            .rasterization_state(&rasterization_state)
+            .multisample_state(&multisample)
+            .color_blend_state(&color_blend)
+            .depth_stencil_state(&depth_stencil)
+            .layout(pipeline_layout)
+            .render_pass(render_pass)
+            .subpass(0);
+
+        gpu.device_context
+            .device
+            .create_graphics_pipelines(gpu.pipeline_cache, &[create_info], None)
+            .expect("failed to create egui pipeline!")[0]
+    }
+
+    unsafe fn create_render_pass(gpu: &GPU, format: vk::Format) -> vk::RenderPass {
+        // drawn on top of what the post chain presented
+        let attachments = [vk::AttachmentDescription {
+            format,
+            samples: vk::SampleCountFlags::TYPE_1,
+            load_op: vk::AttachmentLoadOp::LOAD,
+            store_op: vk::AttachmentStoreOp::STORE,
+            stencil_load_op: vk::AttachmentLoadOp::DONT_CARE,
+            stencil_store_op: vk::AttachmentStoreOp::DONT_CARE,
+            initial_layout: vk::ImageLayout::PRESENT_SRC_KHR,
+            final_layout: vk::ImageLayout::PRESENT_SRC_KHR,
+            flags: Default::default(),
+        }];
+        let color_attachment_refs = [vk::AttachmentReference {
+            attachment: 0,
+            layout: vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+        }];
+        let sub_passes = [vk::SubpassDescription::default()
+            .pipeline_bind_point(vk::PipelineBindPoint::GRAPHICS)
+            .color_attachments(&color_attachment_refs)];
+
+        let dependencies = [vk::SubpassDependency {
+            src_subpass: vk::SUBPASS_EXTERNAL,
+            src_stage_mask: vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
+            src_access_mask: vk::AccessFlags::COLOR_ATTACHMENT_WRITE,
+            dst_subpass: 0,
+            dst_stage_mask: vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
+            dst_access_mask: vk::AccessFlags::COLOR_ATTACHMENT_READ
+                | vk::AccessFlags::COLOR_ATTACHMENT_WRITE,
+            ..Default::default()
+        }];
+
+        let create_info = vk::RenderPassCreateInfo::default()
+            .attachments(&attachments)
+            .subpasses(&sub_passes)
+            .dependencies(&dependencies);
+
+        gpu.device_context
+            .device
+            .create_render_pass(&create_info, None)
+            .expect("failed to create egui render pass!")
+    }
+
+    unsafe fn create_framebuffers(&mut self) {
+        let swap_chain = self.gpu.swap_chain.borrow();
+        let extent = swap_chain.extent;
+        self.framebuffers = swap_chain
+            .image_views
+            .iter()
+            .map(|&image_view| {
+                let attachments = [image_view];
+                let create_info = vk::FramebufferCreateInfo::default()
+                    .width(extent.width)
+                    .height(extent.height)
+                    .layers(1)
+                    .attachments(&attachments)
+                    .render_pass(self.render_pass);
+
+                self.gpu
+                    .device_context
+                    .device
+                    .create_framebuffer(&create_info, None)
+                    .expect("failed to create framebuffer!")
+            })
+            .collect();
+    }
+
+    unsafe fn destroy_framebuffers(&mut self) {
+        let device = &self.gpu.device_context.device;
+        self.framebuffers
+            .drain(..)
+            .for_each(|framebuffer| device.destroy_framebuffer(framebuffer, None));
+    }
+}
+
+impl Drop for EguiRenderer {
+    fn drop(&mut self) {
+        unsafe {
+            self.destroy_framebuffers();
+            self.frames
+                .iter_mut()
+                .for_each(|frame| frame.drop(&self.gpu));
+
+            let device = &self.gpu.device_context.device;
+            device.destroy_pipeline(self.pipeline, None);
+            device.destroy_shader_module(self.shader_module, None);
+            device.destroy_pipeline_layout(self.pipeline_layout, None);
+            device.destroy_descriptor_set_layout(self.descriptor_set_layout, None);
+            device.destroy_render_pass(self.render_pass, None);
+        }
+    }
+}
@@ -1,23 +1,48 @@
 use super::*;
-use crate::gpu::GPU;
-use crate::math::Mat4;
+use crate::gpu::{Allocation, PassDesc, Rhi, SecondaryCommands, GPU};
+use crate::math::{Mat4, Vec3};
 use ash::vk;
-use std::ffi::c_void;
-use std::mem::{align_of, size_of};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::mem::size_of;
 use std::rc::Rc;
 
-#[repr(C)]
-#[derive(Copy, Clone, PartialEq)]
-pub struct SceneData {
-    pub view: Mat4,
-    pub projection: Mat4,
-    pub view_projection: Mat4,
-}
-
 #[repr(C)]
 #[derive(Copy, Clone, PartialEq)]
 pub struct ObjectData {
     pub model: Mat4,
+    // x amount, y edge width, z noise scale, see `Dissolve`
+    pub dissolve: [f32; 4],
+    // rgb edge glow
+    pub dissolve_edge: [f32; 4],
+}
+
+impl ObjectData {
+    pub fn new(object: &RenderObject) -> Self {
+        let (dissolve, dissolve_edge) = match object.dissolve {
+            Some(dissolve) => (
+                [
+                    dissolve.amount,
+                    dissolve.edge_width,
+                    dissolve.noise_scale,
+                    0.0,
+                ],
+                [
+                    dissolve.edge_color.x,
+                    dissolve.edge_color.y,
+                    dissolve.edge_color.z,
+                    0.0,
+                ],
+            ),
+            None => ([0.0; 4], [0.0; 4]),
+        };
+
+        Self {
+            model: object.model,
+            dissolve,
+            dissolve_edge,
+        }
+    }
 }
 
 // https://stackoverflow.com/questions/28127165/how-to-convert-struct-to-u8
@@ -25,103 +50,342 @@ unsafe fn any_as_u8_slice<T: Sized>(p: &T) -> &[u8] {
     ::core::slice::from_raw_parts((p as *const T) as *const u8, ::core::mem::size_of::<T>())
 }
 
-unsafe fn u8_slice_as_any<T>(p: &[u8]) -> &T {
-    assert_eq!(p.len(), ::core::mem::size_of::<T>());
-    &*(p.as_ptr() as *const T)
+// struct FrameData {}
+
+// Where the `ObjectData` of a draw comes from.
+#[derive(Copy, Clone)]
+enum ObjectSource {
+    // pushed, or at the slot of the object buffer
+    Slot(usize),
+    // read by instance from the visible list of the batch the object is the first of
+    Indirect(IndirectBatch),
 }
 
-// struct FrameData {}
+// A descriptor of a material set pushed with the draw, see `GPUPipeline::push_descriptors`.
+#[derive(Copy, Clone)]
+enum PushedDescriptor {
+    // the binding of a texture and its sampler in the one after it
+    Texture(u32, vk::DescriptorImageInfo),
+    Params(vk::DescriptorBufferInfo),
+}
+
+// Everything a draw binds, resolved on the thread owning the GPU so any thread can record it.
+#[derive(Clone)]
+struct Draw {
+    pipeline: vk::Pipeline,
+    layout: vk::PipelineLayout,
+    // camera, material and object buffer, or the GPU culling set of an indirect batch
+    sets: [vk::DescriptorSet; 3],
+    // the material set when pushed rather than bound, `sets` has a null one then
+    pushed: Option<Vec<PushedDescriptor>>,
+    // pushed instead of read from the object buffer at `object_offset`
+    push_constants: Option<ObjectData>,
+    // the offset of the visible list of an indirect batch
+    object_offset: u32,
+    // the payload buffer set and the offset of the object's payload in it
+    payload: Option<(vk::DescriptorSet, u32)>,
+    // shared by most draws, see `GeomArena`
+    vertex_buffer: vk::Buffer,
+    index_buffer: vk::Buffer,
+    first_index: u32,
+    vertex_offset: i32,
+    index_count: u32,
+    // the draw command of an indirect batch and its offset
+    indirect: Option<(vk::Buffer, vk::DeviceSize)>,
+}
+
+impl Draw {
+    // With the raw device rather than through `Rhi`, the GPU is `Rc` based and stays on its thread.
+    // `bound` are the vertex and index buffers the command buffer has bound already.
+    unsafe fn record(
+        &self,
+        device: &ash::Device,
+        push_descriptor: Option<&ash::khr::push_descriptor::Device>,
+        command_buffer: vk::CommandBuffer,
+        bound: &mut Option<(vk::Buffer, vk::Buffer)>,
+    ) {
+        let bind_point = vk::PipelineBindPoint::GRAPHICS;
+        // the material set is left out of the bound ones when pushed
+        let material_sets = if self.pushed.is_some() { 1 } else { 2 };
+        match &self.push_constants {
+            Some(object_data) => {
+                device.cmd_push_constants(
+                    command_buffer,
+                    self.layout,
+                    vk::ShaderStageFlags::ALL_GRAPHICS,
+                    0,
+                    any_as_u8_slice(object_data),
+                );
+                device.cmd_bind_descriptor_sets(
+                    command_buffer,
+                    bind_point,
+                    self.layout,
+                    0,
+                    &self.sets[..material_sets],
+                    &[],
+                );
+            }
+            None if self.pushed.is_some() => {
+                device.cmd_bind_descriptor_sets(
+                    command_buffer,
+                    bind_point,
+                    self.layout,
+                    0,
+                    &self.sets[..1],
+                    &[],
+                );
+                device.cmd_bind_descriptor_sets(
+                    command_buffer,
+                    bind_point,
+                    self.layout,
+                    OBJECT_SET,
+                    &self.sets[2..],
+                    &[self.object_offset],
+                );
+            }
+            None => device.cmd_bind_descriptor_sets(
+                command_buffer,
+                bind_point,
+                self.layout,
+                0,
+                &self.sets,
+                &[self.object_offset],
+            ),
+        }
+        if let (Some(descriptors), Some(push_descriptor)) = (&self.pushed, push_descriptor) {
+            Self::push_material_set(push_descriptor, command_buffer, self.layout, descriptors);
+        }
+        if let Some((set, offset)) = self.payload {
+            device.cmd_bind_descriptor_sets(
+                command_buffer,
+                bind_point,
+                self.layout,
+                PAYLOAD_SET,
+                &[set],
+                &[offset],
+            );
+        }
+        device.cmd_bind_pipeline(command_buffer, bind_point, self.pipeline);
+        let buffers = (self.vertex_buffer, self.index_buffer);
+        if *bound != Some(buffers) {
+            device.cmd_bind_vertex_buffers(command_buffer, 0, &[self.vertex_buffer], &[0]);
+            device.cmd_bind_index_buffer(
+                command_buffer,
+                self.index_buffer,
+                0,
+                vk::IndexType::UINT32,
+            );
+            *bound = Some(buffers);
+        }
+        match self.indirect {
+            Some((buffer, offset)) => device.cmd_draw_indexed_indirect(
+                command_buffer,
+                buffer,
+                offset,
+                1,
+                size_of::<vk::DrawIndexedIndirectCommand>() as u32,
+            ),
+            None => device.cmd_draw_indexed(
+                command_buffer,
+                self.index_count,
+                1,
+                self.first_index,
+                self.vertex_offset,
+                0,
+            ),
+        }
+    }
+
+    unsafe fn push_material_set(
+        push_descriptor: &ash::khr::push_descriptor::Device,
+        command_buffer: vk::CommandBuffer,
+        layout: vk::PipelineLayout,
+        descriptors: &[PushedDescriptor],
+    ) {
+        let writes = descriptors
+            .iter()
+            .flat_map(|descriptor| match descriptor {
+                PushedDescriptor::Texture(binding, image_info) => {
+                    let image_info = std::slice::from_ref(image_info);
+                    vec![
+                        vk::WriteDescriptorSet::default()
+                            .descriptor_type(vk::DescriptorType::SAMPLED_IMAGE)
+                            .image_info(image_info)
+                            .dst_binding(*binding),
+                        vk::WriteDescriptorSet::default()
+                            .descriptor_type(vk::DescriptorType::SAMPLER)
+                            .image_info(image_info)
+                            .dst_binding(binding + 1),
+                    ]
+                }
+                PushedDescriptor::Params(buffer_info) => vec![vk::WriteDescriptorSet::default()
+                    .descriptor_type(vk::DescriptorType::UNIFORM_BUFFER)
+                    .buffer_info(std::slice::from_ref(buffer_info))
+                    .dst_binding(Shading::PARAMS_BINDING)],
+            })
+            .collect::<Vec<_>>();
+        if writes.is_empty() {
+            return;
+        }
+        push_descriptor.cmd_push_descriptor_set(
+            command_buffer,
+            vk::PipelineBindPoint::GRAPHICS,
+            layout,
+            1,
+            &writes,
+        );
+    }
+}
+
+// Samples per pixel of the forward pass, smoother geometry edges for more bandwidth and memory.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Msaa {
+    // renders straight into the scene color, nothing to resolve
+    Off,
+    X2,
+    X4,
+    X8,
+}
+
+impl Msaa {
+    pub fn samples(self) -> vk::SampleCountFlags {
+        match self {
+            Msaa::Off => vk::SampleCountFlags::TYPE_1,
+            Msaa::X2 => vk::SampleCountFlags::TYPE_2,
+            Msaa::X4 => vk::SampleCountFlags::TYPE_4,
+            Msaa::X8 => vk::SampleCountFlags::TYPE_8,
+        }
+    }
+
+    // The highest the device supports up to this one, every device has Off.
+    pub fn supported(self, gpu: &GPU) -> Msaa {
+        [Msaa::X8, Msaa::X4, Msaa::X2]
+            .into_iter()
+            .filter(|msaa| msaa.samples().as_raw() <= self.samples().as_raw())
+            .find(|msaa| gpu.device_context.is_sample_count_supported(msaa.samples()))
+            .unwrap_or(Msaa::Off)
+    }
+}
 
 pub struct ForwardRenderer {
     gpu: Rc<GPU>,
 
     pub render_pass: vk::RenderPass,
-    pub descriptor_set_layout: vk::DescriptorSetLayout,
-    pub descriptor_sets: Vec<vk::DescriptorSet>,
+    pub camera_uniforms: Rc<CameraUniforms>,
+    // per draw data of pipelines over the push constant budget
+    pub object_buffer: ObjectBuffer,
+    // per draw data of any size, see `RenderObject::payload`
+    pub payload_buffer: PayloadBuffer,
+    // per object data and culling of the indirect batches, see `Shading::indirect`
+    pub gpu_culling: GPUCulling,
+    // the lights of the standard shading per cluster of the view, see `LightClusters`
+    pub light_clusters: LightClusters,
+    // environment cube map behind the scene
+    pub skybox: Skybox,
 
-    pub depth_reverse_z: bool,
+    // depth 1 at the near plane going to 0 far away, for precision in the distance. Cameras
+    // project, pipelines compare and the pass clears to match, see `set_reverse_z`
+    reverse_z: bool,
+    // internal resolution relative to the swap chain, the post chain upscales below 1
+    render_scale: f32,
+    // a depth prepass subpass the shading subpass reads as input attachment, with the MSAA color and
+    // depth lazily allocated so they stay in tile memory on tiling GPUs
+    mobile_friendly: bool,
+    // the sample count of the color and depth attachments and of every pipeline drawing in the pass
+    msaa: Msaa,
+    // threads recording the draws into secondary command buffers, 1 records them inline
+    recording_threads: usize,
+    // a pool per recording thread and frame in flight
+    secondary_commands: RefCell<SecondaryCommands>,
 
-    framebuffers: Vec<vk::Framebuffer>,
-    color_image: vk::Image,
-    color_image_memory: vk::DeviceMemory,
-    color_image_view: vk::ImageView,
+    // the MSAA color resolves into it, or is rendered into with MSAA off, HDR until the post chain tone maps it to the swap chain
+    pub scene_color: RenderTarget,
+    // resolved into instead of a swap chain sized scene color, owned by the caller, see
+    // `with_target`
+    target: Option<RenderTarget>,
+    framebuffer: vk::Framebuffer,
+    // None with MSAA off
+    color: Option<(vk::Image, Allocation, vk::ImageView)>,
     depth_image: vk::Image,
-    depth_image_memory: vk::DeviceMemory,
+    depth_image_memory: Allocation,
     depth_image_view: vk::ImageView,
-
-    uniform_buffers: Vec<vk::Buffer>,
-    uniform_buffer_memories: Vec<vk::DeviceMemory>,
-    uniform_buffer_memories_mapped: Vec<*mut c_void>,
 }
 
 impl ForwardRenderer {
     pub const FRAMES_IN_FLIGHT: u32 = 2;
+    // linear light without an upper bound
+    pub const SCENE_FORMAT: vk::Format = vk::Format::R16G16B16A16_SFLOAT;
+    // fewer draws per thread cost more in thread startup and secondary command buffers than they
+    // save, smaller scenes are recorded inline
+    pub const MIN_DRAWS_PER_THREAD: usize = 256;
 
-    pub fn new(gpu: &Rc<GPU>) -> Self {
+    pub fn new(gpu: &Rc<GPU>, camera_uniforms: Rc<CameraUniforms>) -> Self {
+        Self::create(gpu, camera_uniforms, None)
+    }
+
+    // Renders into `target` at its size whatever the swap chain does, e.g. for cameras rendering
+    // into textures. The target outlives the renderer, which doesn't destroy it.
+    pub fn with_target(
+        gpu: &Rc<GPU>,
+        camera_uniforms: Rc<CameraUniforms>,
+        target: RenderTarget,
+    ) -> Self {
+        Self::create(gpu, camera_uniforms, Some(target))
+    }
+
+    fn create(
+        gpu: &Rc<GPU>,
+        camera_uniforms: Rc<CameraUniforms>,
+        target: Option<RenderTarget>,
+    ) -> Self {
         unsafe {
-            let render_pass = Self::create_render_pass(gpu);
-            let (color_image, color_image_memory, color_image_view) =
-                Self::create_color_resources(gpu);
+            let msaa = Msaa::X8.supported(gpu);
+            let render_pass = Self::create_render_pass(gpu, false, msaa.samples());
+            let extent = Self::target_extent(gpu, target.as_ref(), 1.0);
+            let color = Self::create_color_resources(gpu, extent, false, msaa.samples());
             let (depth_image, depth_image_memory, depth_image_view) =
-                Self::create_depth_resources(gpu);
-            let framebuffers =
-                Self::create_framebuffers(gpu, render_pass, color_image_view, depth_image_view);
-
-            let descriptor_set_layout =
-                gpu.create_descriptor_set_layout(&vec![vk::DescriptorSetLayoutBinding {
-                    binding: 0,
-                    descriptor_type: vk::DescriptorType::UNIFORM_BUFFER,
-                    descriptor_count: 1,
-                    stage_flags: vk::ShaderStageFlags::ALL_GRAPHICS,
-                    ..Default::default()
-                }]);
-
-            let descriptor_sets = gpu.create_descriptor_sets(&vec![
-                descriptor_set_layout;
-                Self::FRAMES_IN_FLIGHT as usize
-            ]);
-            let (uniform_buffers, uniform_buffer_memories, uniform_buffer_memories_mapped) =
-                Self::create_uniform_buffers(gpu);
-
-            for (index, descriptor_set) in descriptor_sets.iter().enumerate() {
-                let buffer_infos = [vk::DescriptorBufferInfo {
-                    buffer: uniform_buffers[index],
-                    offset: 0,
-                    range: size_of::<SceneData>() as vk::DeviceSize,
-                }];
-                let ubo_write = vk::WriteDescriptorSet::default()
-                    .descriptor_type(vk::DescriptorType::UNIFORM_BUFFER)
-                    .buffer_info(&buffer_infos)
-                    .dst_set(*descriptor_set)
-                    .dst_binding(0)
-                    // starting element in that array
-                    .dst_array_element(0);
-
-                gpu.device_context
-                    .device
-                    .update_descriptor_sets(&[ubo_write], &[]);
-            }
+                Self::create_depth_resources(gpu, extent, false, msaa.samples());
+            let scene_color = target.unwrap_or_else(|| Self::create_scene_color(gpu, extent));
+            let framebuffer = Self::create_framebuffer(
+                gpu,
+                render_pass,
+                color.map(|(_, _, view)| view),
+                depth_image_view,
+                &scene_color,
+            );
+            let skybox = Skybox::new(gpu, &camera_uniforms, Self::FRAMES_IN_FLIGHT);
+            let light_clusters = LightClusters::new(gpu, &camera_uniforms, Self::FRAMES_IN_FLIGHT);
+            let recording_threads = std::thread::available_parallelism()
+                .map_or(1, |threads| threads.get())
+                .min(8);
+            let secondary_commands =
+                SecondaryCommands::new(gpu, Self::FRAMES_IN_FLIGHT, recording_threads);
 
             Self {
                 gpu: Rc::clone(gpu),
 
-                descriptor_set_layout,
-                descriptor_sets,
+                camera_uniforms,
+                object_buffer: ObjectBuffer::new(gpu, Self::FRAMES_IN_FLIGHT),
+                payload_buffer: PayloadBuffer::new(gpu, Self::FRAMES_IN_FLIGHT),
+                gpu_culling: GPUCulling::new(gpu, Self::FRAMES_IN_FLIGHT),
+                light_clusters,
+                skybox,
 
-                depth_reverse_z: false,
+                reverse_z: true,
+                render_scale: 1.0,
+                mobile_friendly: false,
+                msaa,
+                recording_threads,
+                secondary_commands: RefCell::new(secondary_commands),
 
-                framebuffers,
+                scene_color,
+                target,
+                framebuffer,
                 render_pass,
-                color_image,
-                color_image_memory,
-                color_image_view,
+                color,
                 depth_image,
                 depth_image_memory,
                 depth_image_view,
-
-                uniform_buffers,
-                uniform_buffer_memories,
-                uniform_buffer_memories_mapped,
             }
         }
     }
@@ -130,223 +394,698 @@ impl ForwardRenderer {
         &self,
         command_buffer: vk::CommandBuffer,
         context: RenderContext,
-        image_index: usize,
         frame_index: usize,
     ) {
-        unsafe {
-            let device = &self.gpu.device_context.device;
-            let scene_data = SceneData {
-                view: context.view,
-                projection: context.projection,
-                view_projection: context.projection * context.view,
+        let gpu = &self.gpu;
+        self.camera_uniforms.set(context.view, context.projection);
+        self.camera_uniforms.set_lights(&context.lights);
+        let mut globals = context.globals;
+        let (width, height) = (self.scene_color.width, self.scene_color.height);
+        globals.screen = [
+            width as f32,
+            height as f32,
+            1.0 / width as f32,
+            1.0 / height as f32,
+        ];
+        self.camera_uniforms.set_globals(globals);
+        let clusters =
+            self.light_clusters
+                .update(frame_index, &context.lights, context.view, &globals);
+        self.camera_uniforms.set_clusters(clusters);
+        self.camera_uniforms.flush(frame_index);
+        self.light_clusters.build(command_buffer, frame_index);
+
+        // culled during extraction, a geom may still fail to upload
+        let mut gpu_assets = context.gpu_assets.borrow_mut();
+        let objects = context
+            .objects
+            .iter()
+            .filter(|object| {
+                gpu_assets
+                    .get_render_geom(&object.geom, frame_index)
+                    .is_some()
+            })
+            .collect::<Vec<_>>();
+
+        // the material sets of the frame, pipelines without any push theirs with each draw, see
+        // `prepare_draw`
+        objects.iter().for_each(|object| {
+            let Some((pipeline, textures)) = gpu_assets.get_material(&object.material, self) else {
+                return;
             };
-            let mut align = ash::util::Align::new(
-                self.uniform_buffer_memories_mapped[frame_index],
-                align_of::<SceneData>() as vk::DeviceSize,
-                size_of::<SceneData>() as vk::DeviceSize,
-            );
-            align.copy_from_slice(&[scene_data]);
+            if pipeline.push_descriptors {
+                return;
+            }
 
-            let mut gpu_assets = context.gpu_assets.borrow_mut();
-            context.objects.iter().for_each(|object| {
-                let Some((pipeline, properties)) = gpu_assets.get_material(&object.material, self)
-                else {
-                    return;
-                };
-                let Some(Some(texture)) = properties.get("texture") else {
-                    return;
+            for (slot, texture) in textures.iter().enumerate() {
+                let Some(texture) = texture else {
+                    continue;
                 };
+                gpu.write_texture(
+                    pipeline.get_descriptor_set(frame_index),
+                    slot as u32 * 2,
+                    &texture.texture,
+                );
+            }
+            gpu_assets.bind_material_params(
+                &object.material,
+                frame_index,
+                pipeline.get_descriptor_set(frame_index),
+            );
+        });
 
-                let image_infos = [vk::DescriptorImageInfo {
-                    image_view: texture.image_view,
-                    image_layout: vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
-                    sampler: texture.image_sampler,
-                }];
-
-                let texture_write = vk::WriteDescriptorSet::default()
-                    .descriptor_type(vk::DescriptorType::SAMPLED_IMAGE)
-                    .image_info(&image_infos)
-                    .dst_set(pipeline.get_descriptor_set(frame_index))
-                    .dst_binding(0)
-                    .dst_array_element(0);
-
-                let sampler_write = vk::WriteDescriptorSet::default()
-                    .descriptor_type(vk::DescriptorType::SAMPLER)
-                    .image_info(&image_infos)
-                    .dst_set(pipeline.get_descriptor_set(frame_index))
-                    .dst_binding(1)
-                    .dst_array_element(0);
-
-                device.update_descriptor_sets(&[texture_write, sampler_write], &[]);
+        // culled on the GPU and drawn in batches of the same material and geom, see
+        // `Shading::indirect`
+        let (indirect, objects): (Vec<_>, Vec<_>) = objects.into_iter().partition(|object| {
+            gpu_assets
+                .get_pipeline(&object.material, self)
+                .is_some_and(|pipeline| pipeline.indirect)
+        });
+        let mut batches: Vec<Vec<&RenderObject>> = vec![];
+        let mut batch_indices = HashMap::new();
+        for object in indirect {
+            let Some(geom) = gpu_assets.get_render_geom(&object.geom, frame_index) else {
+                continue;
+            };
+            let key = (
+                object.material.id,
+                geom.index_buffer.buffer,
+                geom.first_index,
+            );
+            let index = *batch_indices.entry(key).or_insert_with(|| {
+                batches.push(vec![]);
+                batches.len() - 1
             });
+            batches[index].push(object);
         }
+        let indirect_count = batches.iter().map(Vec::len).sum();
+        self.gpu_culling
+            .begin_frame(frame_index, indirect_count, batches.len());
+        let batches = batches
+            .into_iter()
+            .filter_map(|objects| {
+                let geom = gpu_assets.get_render_geom(&objects[0].geom, frame_index)?;
+                let batch = self.gpu_culling.push_batch(frame_index, &geom, &objects);
+                Some((objects[0], batch))
+            })
+            .collect::<Vec<_>>();
+        self.gpu_culling
+            .cull(command_buffer, frame_index, &context.culling);
 
-        unsafe {
-            let device = &self.gpu.device_context.device;
-            device.cmd_set_viewport(
-                command_buffer,
-                0,
-                &[vk::Viewport {
-                    x: 0.0,
-                    y: 0.0,
-                    width: self.gpu.swap_chain.extent.width as f32,
-                    height: self.gpu.swap_chain.extent.height as f32,
-                    min_depth: 0.0,
-                    max_depth: 1.0,
-                }],
-            );
-            device.cmd_set_scissor(
+        self.object_buffer.reserve(frame_index, objects.len());
+        // a payload is pushed per draw, the prepass pushes it once more
+        let payload_size = self
+            .payload_buffer
+            .aligned_size(objects.iter().map(|object| object.payload.as_slice()));
+        let draws = if self.mobile_friendly { 2 } else { 1 };
+        self.payload_buffer
+            .begin_frame(frame_index, payload_size * draws);
+
+        let clear_depth = self.far_depth();
+
+        // resolved in draw order, object buffer slots and payloads are written here
+        let indirect = batches
+            .iter()
+            .map(|&(object, batch)| (ObjectSource::Indirect(batch), object));
+        let prepass = match self.mobile_friendly {
+            // depth only, the shading subpass then runs each pixel's fragment shader once
+            true => indirect
+                .clone()
+                .chain(
+                    objects
+                        .iter()
+                        .enumerate()
+                        .map(|(slot, object)| (ObjectSource::Slot(slot), *object)),
+                )
+                .filter_map(|(source, object)| {
+                    self.prepare_draw(frame_index, &mut gpu_assets, source, object, true)
+                })
+                .collect(),
+            false => vec![],
+        };
+        // the slot in the object buffer stays the object's index whatever phase draws it
+        let (transparent, opaque): (Vec<_>, Vec<_>) =
+            objects.iter().enumerate().partition(|(_, object)| {
+                gpu_assets
+                    .get_pipeline(&object.material, self)
+                    .is_some_and(|pipeline| pipeline.transparent)
+            });
+        let opaque = indirect
+            .chain(
+                opaque
+                    .into_iter()
+                    .map(|(slot, object)| (ObjectSource::Slot(slot), *object)),
+            )
+            .filter_map(|(source, object)| {
+                self.prepare_draw(frame_index, &mut gpu_assets, source, object, false)
+            })
+            .collect::<Vec<_>>();
+        // blended over everything else, farthest first
+        let mut transparent = transparent
+            .into_iter()
+            .map(|(slot, object)| {
+                let depth = Self::view_depth(&mut gpu_assets, frame_index, context.view, object);
+                (depth, slot, object)
+            })
+            .collect::<Vec<_>>();
+        transparent.sort_by(|a, b| b.0.total_cmp(&a.0));
+        let transparent = transparent
+            .into_iter()
+            .filter_map(|(_, slot, object)| {
+                let source = ObjectSource::Slot(slot);
+                self.prepare_draw(frame_index, &mut gpu_assets, source, object, false)
+            })
+            .collect::<Vec<_>>();
+
+        let pass = PassDesc {
+            label: "forward",
+            render_pass: self.render_pass,
+            framebuffer: self.framebuffer,
+            width: self.scene_color.width,
+            height: self.scene_color.height,
+            clear_color: [0.0, 0.0, 0.0, 1.0],
+            clear_depth,
+        };
+        let draws = prepass.len() + opaque.len() + transparent.len();
+        let threads = (draws / Self::MIN_DRAWS_PER_THREAD).min(self.recording_threads);
+        if threads > 1 {
+            self.record_in_parallel(
                 command_buffer,
-                0,
-                &[vk::Rect2D {
-                    offset: vk::Offset2D { x: 0, y: 0 },
-                    extent: self.gpu.swap_chain.extent,
-                }],
+                frame_index,
+                &pass,
+                [&prepass, &opaque, &transparent],
+                threads,
+                &gpu_assets,
             );
+            return;
+        }
 
-            let clear_values = [
-                vk::ClearValue {
-                    color: vk::ClearColorValue {
-                        float32: [0.0, 0.0, 0.0, 1.0],
-                    },
-                },
-                vk::ClearValue {
-                    depth_stencil: vk::ClearDepthStencilValue {
-                        depth: if self.depth_reverse_z { 0.0 } else { 1.0 },
-                        stencil: 0,
-                    },
-                },
-            ];
-
-            let render_pass_begin_info = vk::RenderPassBeginInfo::default()
-                .clear_values(&clear_values)
-                .render_pass(self.render_pass)
-                .framebuffer(self.framebuffers[image_index])
-                .render_area(vk::Rect2D {
-                    offset: vk::Offset2D { x: 0, y: 0 },
-                    extent: self.gpu.swap_chain.extent,
+        let device = &gpu.device_context.device;
+        let push_descriptor = gpu.device_context.push_descriptor.as_ref();
+        // the skybox binds buffers of its own in between
+        let record = |draws: &[Draw]| {
+            let mut bound = None;
+            draws.iter().for_each(|draw| unsafe {
+                draw.record(device, push_descriptor, command_buffer, &mut bound)
+            })
+        };
+        gpu.begin_pass(command_buffer, &pass);
+        if self.mobile_friendly {
+            record(&prepass);
+            gpu.next_subpass(command_buffer);
+        }
+        record(&opaque);
+        // after the opaque geometry, it only fills what is left
+        self.skybox.render(
+            command_buffer,
+            frame_index,
+            self.shading_target(),
+            self.camera_uniforms.get_descriptor_set(frame_index),
+            &gpu_assets,
+            clear_depth,
+        );
+        record(&transparent);
+        gpu.end_pass(command_buffer);
+    }
+
+    // Each thread records a contiguous chunk of every phase into a secondary command buffer of its
+    // own, the skybox gets one recorded here. The primary executes them in draw order.
+    fn record_in_parallel(
+        &self,
+        command_buffer: vk::CommandBuffer,
+        frame_index: usize,
+        pass: &PassDesc<GPU>,
+        phases: [&[Draw]; 3],
+        threads: usize,
+        gpu_assets: &GPUAssets,
+    ) {
+        let gpu = &self.gpu;
+        let mut secondary_commands = self.secondary_commands.borrow_mut();
+        secondary_commands.begin_frame(frame_index);
+        let extent = vk::Extent2D {
+            width: pass.width,
+            height: pass.height,
+        };
+        let subpasses = [0, self.shading_subpass(), self.shading_subpass()];
+
+        // [phase][thread], begun here as a pool can't be shared with the recording threads
+        let mut jobs = vec![vec![]; threads];
+        let mut phase_buffers = [vec![], vec![], vec![]];
+        for (phase, draws) in phases.iter().enumerate() {
+            let chunk_size = draws.len().div_ceil(threads).max(1);
+            for (thread, chunk) in draws.chunks(chunk_size).enumerate() {
+                let secondary = secondary_commands.begin(
+                    frame_index,
+                    thread,
+                    self.render_pass,
+                    subpasses[phase],
+                    self.framebuffer,
+                    extent,
+                );
+                jobs[thread].push((secondary, chunk));
+                phase_buffers[phase].push(secondary);
+            }
+        }
+
+        let device = secondary_commands.device();
+        let push_descriptor = gpu.device_context.push_descriptor.as_ref();
+        std::thread::scope(|scope| {
+            for jobs in &jobs {
+                scope.spawn(move || {
+                    for &(secondary, draws) in jobs {
+                        let mut bound = None;
+                        for draw in draws {
+                            unsafe { draw.record(device, push_descriptor, secondary, &mut bound) };
+                        }
+                        SecondaryCommands::end(device, secondary);
+                    }
                 });
+            }
+        });
 
-            // INLINE: The render pass commands will be embedded in the primary command buffer itself
-            // and no secondary command buffers will be executed.
-            // SECONDARY_COMMAND_BUFFERS: The render pass commands will be executed from secondary command buffers.
-            device.cmd_begin_render_pass(
-                command_buffer,
-                &render_pass_begin_info,
-                vk::SubpassContents::INLINE,
-            );
+        // after the opaque geometry, it only fills what is left
+        let skybox = secondary_commands.begin(
+            frame_index,
+            0,
+            self.render_pass,
+            self.shading_subpass(),
+            self.framebuffer,
+            extent,
+        );
+        self.skybox.render(
+            skybox,
+            frame_index,
+            self.shading_target(),
+            self.camera_uniforms.get_descriptor_set(frame_index),
+            gpu_assets,
+            pass.clear_depth,
+        );
+        SecondaryCommands::end(secondary_commands.device(), skybox);
 
-            let mut gpu_assets = context.gpu_assets.borrow_mut();
-            context.objects.iter().for_each(|object| {
-                let Some(pipeline) = gpu_assets.get_pipeline(&object.material, self) else {
-                    return;
-                };
-                let Some(geom) = gpu_assets.get_geom(&object.geom) else {
-                    return;
-                };
+        let [prepass, opaque, transparent] = phase_buffers;
+        gpu.begin_secondary_pass(command_buffer, pass);
+        if self.mobile_friendly {
+            gpu.execute_commands(command_buffer, &prepass);
+            gpu.next_secondary_subpass(command_buffer);
+        }
+        gpu.execute_commands(command_buffer, &opaque);
+        gpu.execute_commands(command_buffer, &[skybox]);
+        gpu.execute_commands(command_buffer, &transparent);
+        gpu.end_pass(command_buffer);
+    }
+
+    // How far in front of the camera the center of the object's bounds is.
+    fn view_depth(
+        gpu_assets: &mut GPUAssets,
+        frame_index: usize,
+        view: Mat4,
+        object: &RenderObject,
+    ) -> f32 {
+        let center = gpu_assets
+            .get_render_geom(&object.geom, frame_index)
+            .map_or(Vec3::new(0.0, 0.0, 0.0), |geom| geom.bounds.center());
+        -(view * object.model).transform_point(center).z
+    }
+
+    // Materials without a depth prepass variant only draw in the shading subpass.
+    fn prepare_draw(
+        &self,
+        frame_index: usize,
+        gpu_assets: &mut GPUAssets,
+        source: ObjectSource,
+        object: &RenderObject,
+        depth_prepass: bool,
+    ) -> Option<Draw> {
+        let pipeline = gpu_assets.get_pipeline(&object.material, self)?;
+        let bound_pipeline = match (depth_prepass, pipeline.depth_prepass) {
+            (false, _) => pipeline.pipeline,
+            (true, Some(depth_prepass)) => depth_prepass,
+            (true, None) => return None,
+        };
+        let (layout, push_constants, object_payload) = (
+            pipeline.pipeline.layout,
+            pipeline.push_constants,
+            pipeline.object_payload,
+        );
+        let (material_set, pushed) = match pipeline.push_descriptors {
+            true => (
+                vk::DescriptorSet::null(),
+                Some(self.material_descriptors(frame_index, gpu_assets, object)),
+            ),
+            false => (pipeline.get_descriptor_set(frame_index), None),
+        };
+        let mut sets = [
+            self.camera_uniforms.get_descriptor_set(frame_index),
+            material_set,
+            self.object_buffer.get_descriptor_set(frame_index),
+        ];
+        let geom = gpu_assets.get_render_geom(&object.geom, frame_index)?;
+
+        let object_data = ObjectData::new(object);
+        let (push_constants, object_offset, indirect) = match (source, push_constants) {
+            (ObjectSource::Indirect(batch), _) => {
+                sets[2] = self.gpu_culling.get_descriptor_set(frame_index);
+                let command = (batch.commands, batch.command_offset);
+                (None, batch.visible_offset, Some(command))
+            }
+            (ObjectSource::Slot(_), true) => (Some(object_data), 0, None),
+            (ObjectSource::Slot(slot), false) => (
+                None,
+                self.object_buffer.write(frame_index, slot, &object_data),
+                None,
+            ),
+        };
+        let payload = object_payload.then(|| {
+            (
+                self.payload_buffer.get_descriptor_set(frame_index),
+                self.payload_buffer.push(frame_index, &object.payload),
+            )
+        });
+        Some(Draw {
+            pipeline: bound_pipeline.pipeline,
+            layout,
+            sets,
+            pushed,
+            push_constants,
+            object_offset,
+            payload,
+            vertex_buffer: geom.vertex_buffer.buffer,
+            index_buffer: geom.index_buffer.buffer,
+            first_index: geom.first_index,
+            vertex_offset: geom.vertex_offset,
+            index_count: geom.indices_length as u32,
+            indirect,
+        })
+    }
 
-                let object_data = ObjectData {
-                    model: object.model,
+    // The textures and params of the object's material, what the material set of a pipeline
+    // without descriptor sets is pushed with.
+    fn material_descriptors(
+        &self,
+        frame_index: usize,
+        gpu_assets: &GPUAssets,
+        object: &RenderObject,
+    ) -> Vec<PushedDescriptor> {
+        let textures = gpu_assets
+            .get_material(&object.material, self)
+            .map_or(vec![], |(_, textures)| textures);
+        let mut descriptors = textures
+            .iter()
+            .enumerate()
+            .filter_map(|(slot, texture)| {
+                let texture = texture.as_ref()?;
+                let image_info = vk::DescriptorImageInfo {
+                    image_view: texture.texture.image_view,
+                    image_layout: vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+                    sampler: texture.texture.image_sampler,
                 };
-                device.cmd_push_constants(
-                    command_buffer,
-                    pipeline.pipeline_layout,
-                    vk::ShaderStageFlags::ALL_GRAPHICS,
-                    0,
-                    any_as_u8_slice(&object_data),
-                );
+                Some(PushedDescriptor::Texture(slot as u32 * 2, image_info))
+            })
+            .collect::<Vec<_>>();
+        if let Some(params) = gpu_assets.update_material_params(&object.material, frame_index) {
+            descriptors.push(PushedDescriptor::Params(params));
+        }
+        descriptors
+    }
 
-                device.cmd_bind_descriptor_sets(
-                    command_buffer,
-                    vk::PipelineBindPoint::GRAPHICS,
-                    pipeline.pipeline_layout,
-                    0,
-                    &[
-                        self.descriptor_sets[frame_index],
-                        pipeline.get_descriptor_set(frame_index),
-                    ],
-                    &[],
-                );
+    // Swap chain sized attachments have to follow the swap chain whenever it gets recreated.
+    pub fn resize(&mut self) {
+        unsafe {
+            self.destroy_attachments();
 
-                device.cmd_bind_pipeline(
-                    command_buffer,
-                    vk::PipelineBindPoint::GRAPHICS,
-                    pipeline.pipeline,
-                );
+            let extent = Self::target_extent(&self.gpu, self.target.as_ref(), self.render_scale);
+            let samples = self.msaa_samples();
+            let color =
+                Self::create_color_resources(&self.gpu, extent, self.mobile_friendly, samples);
+            let (depth_image, depth_image_memory, depth_image_view) =
+                Self::create_depth_resources(&self.gpu, extent, self.mobile_friendly, samples);
+            if self.mobile_friendly {
+                self.camera_uniforms.set_depth_input(depth_image_view);
+            }
+            self.scene_color = self
+                .target
+                .unwrap_or_else(|| Self::create_scene_color(&self.gpu, extent));
+            self.framebuffer = Self::create_framebuffer(
+                &self.gpu,
+                self.render_pass,
+                color.map(|(_, _, view)| view),
+                depth_image_view,
+                &self.scene_color,
+            );
 
-                device.cmd_bind_vertex_buffers(command_buffer, 0, &[geom.vertex_buffer], &[0]);
-                device.cmd_bind_index_buffer(
-                    command_buffer,
-                    geom.index_buffer,
-                    0,
-                    vk::IndexType::UINT32,
-                );
-                // device.cmd_draw(command_buffer, );
-                // device.cmd_draw_indexed(command_buffer, self.geom.indices.len() as u32, 1, 0, 0, 0);
-                device.cmd_draw_indexed(command_buffer, geom.indices_length as u32, 1, 0, 0, 0);
-            });
+            self.color = color;
+            self.depth_image = depth_image;
+            self.depth_image_memory = depth_image_memory;
+            self.depth_image_view = depth_image_view;
+        }
+    }
+
+    pub fn get_render_scale(&self) -> f32 {
+        self.render_scale
+    }
+
+    // Recreates the attachments at the new internal resolution, the device must be idle.
+    pub fn set_render_scale(&mut self, render_scale: f32) {
+        self.render_scale = render_scale.clamp(0.25, 1.0);
+        self.resize();
+    }
+
+    pub fn is_mobile_friendly(&self) -> bool {
+        self.mobile_friendly
+    }
 
-            device.cmd_end_render_pass(command_buffer);
+    // The depth of the last pass, multisampled unless MSAA is off, in DEPTH_STENCIL_READ_ONLY_OPTIMAL, None when mobile
+    // friendly as it never leaves tile memory then. Changes with `resize`.
+    pub fn get_depth_view(&self) -> Option<vk::ImageView> {
+        (!self.mobile_friendly).then_some(self.depth_image_view)
+    }
+
+    // The subpass materials shade in, after the depth prepass when mobile friendly.
+    pub fn shading_subpass(&self) -> u32 {
+        if self.mobile_friendly {
+            1
+        } else {
+            0
         }
     }
 
-    fn create_uniform_buffers(
-        gpu: &GPU,
-    ) -> (Vec<vk::Buffer>, Vec<vk::DeviceMemory>, Vec<*mut c_void>) {
-        let buffer_size = size_of::<SceneData>() as vk::DeviceSize;
-        let mut buffers = Vec::new();
-        let mut memories = Vec::new();
-        let mut memories_mapped = Vec::new();
-
-        for _ in 0..Self::FRAMES_IN_FLIGHT {
-            let (buffer, memory, memory_mapped) = gpu.create_mapped_buffers(buffer_size);
-
-            buffers.push(buffer);
-            memories.push(memory);
-            memories_mapped.push(memory_mapped);
+    // What the passes drawn along with the materials build their pipelines for: the render pass,
+    // the shading subpass and the sample count.
+    pub fn shading_target(&self) -> (vk::RenderPass, u32, vk::SampleCountFlags) {
+        (
+            self.render_pass,
+            self.shading_subpass(),
+            self.msaa_samples(),
+        )
+    }
+
+    // Recreates the render pass and its attachments, the device must be idle. The material
+    // pipelines built for the old render pass are released from `gpu_assets`.
+    pub fn set_mobile_friendly(&mut self, mobile_friendly: bool, gpu_assets: &GPUAssets) {
+        if self.mobile_friendly == mobile_friendly {
+            return;
         }
+        self.mobile_friendly = mobile_friendly;
+        self.recreate_render_pass(gpu_assets);
+    }
+
+    pub fn is_reverse_z(&self) -> bool {
+        self.reverse_z
+    }
+
+    // Material pipelines compare depth with it, the ones built before have to be released by the
+    // caller. The skybox reads the clear value per draw.
+    pub fn set_reverse_z(&mut self, reverse_z: bool) {
+        self.reverse_z = reverse_z;
+    }
 
-        (buffers, memories, memories_mapped)
+    // What the depth buffer is cleared to, where nothing was drawn.
+    pub fn far_depth(&self) -> f32 {
+        match self.reverse_z {
+            true => 0.0,
+            false => 1.0,
+        }
     }
 
-    unsafe fn create_color_resources(gpu: &GPU) -> (vk::Image, vk::DeviceMemory, vk::ImageView) {
+    pub fn get_msaa(&self) -> Msaa {
+        self.msaa
+    }
+
+    pub fn msaa_samples(&self) -> vk::SampleCountFlags {
+        self.msaa.samples()
+    }
+
+    // Falls back to the highest sample count below `msaa` the device supports. Recreates the render
+    // pass and its attachments like `set_mobile_friendly`, with the same requirements.
+    pub fn set_msaa(&mut self, msaa: Msaa, gpu_assets: &GPUAssets) {
+        let supported = msaa.supported(&self.gpu);
+        if supported != msaa {
+            log::warn!(
+                "{:?} MSAA is not supported by the device, using {:?}",
+                msaa,
+                supported
+            );
+        }
+        if self.msaa == supported {
+            return;
+        }
+        self.msaa = supported;
+        self.recreate_render_pass(gpu_assets);
+    }
+
+    pub fn get_recording_threads(&self) -> usize {
+        self.recording_threads
+    }
+
+    // Threads recording the draws of scenes with at least `MIN_DRAWS_PER_THREAD` draws per thread,
+    // 1 records everything inline. The device must be idle, the secondary command pools are
+    // recreated.
+    pub fn set_recording_threads(&mut self, threads: usize) {
+        let threads = threads.max(1);
+        if self.recording_threads == threads {
+            return;
+        }
+        self.recording_threads = threads;
+        *self.secondary_commands.get_mut() =
+            SecondaryCommands::new(&self.gpu, Self::FRAMES_IN_FLIGHT, threads);
+    }
+
+    // The pipelines of the old render pass go first, a new one may get the same handle.
+    fn recreate_render_pass(&mut self, gpu_assets: &GPUAssets) {
+        unsafe {
+            let previous = self.render_pass;
+            gpu_assets.release_render_pass(previous);
+            self.render_pass =
+                Self::create_render_pass(&self.gpu, self.mobile_friendly, self.msaa_samples());
+            self.resize();
+            self.gpu
+                .device_context
+                .device
+                .destroy_render_pass(previous, None);
+        }
+    }
+
+    // The size of a fixed target isn't scaled.
+    fn target_extent(gpu: &GPU, target: Option<&RenderTarget>, render_scale: f32) -> vk::Extent2D {
+        if let Some(target) = target {
+            return vk::Extent2D {
+                width: target.width,
+                height: target.height,
+            };
+        }
+        let extent = gpu.swap_chain.borrow().extent;
+        vk::Extent2D {
+            width: ((extent.width as f32 * render_scale) as u32).max(1),
+            height: ((extent.height as f32 * render_scale) as u32).max(1),
+        }
+    }
+
+    unsafe fn destroy_attachments(&mut self) {
+        let device = &self.gpu.device_context.device;
+        device.destroy_framebuffer(self.framebuffer, None);
+        if self.target.is_none() {
+            self.scene_color.drop(&self.gpu);
+        }
+
+        if let Some((image, memory, view)) = self.color.take() {
+            device.destroy_image_view(view, None);
+            self.gpu.device_context.destroy_image(image, memory);
+        }
+
+        device.destroy_image_view(self.depth_image_view, None);
+        self.gpu
+            .device_context
+            .destroy_image(self.depth_image, self.depth_image_memory);
+    }
+
+    // Using VK_IMAGE_USAGE_TRANSIENT_ATTACHMENT_BIT combined with VK_MEMORY_PROPERTY_LAZILY_ALLOCATED_BIT memory.
+    // The idea is that lazy memory allocation prevents allocations for the multisample color attachment, which is
+    // only used as a temporary during the render pass, and therefore remains on-chip instead of stored in device memory.
+    // https://registry.khronos.org/vulkan/specs/1.2-extensions/html/vkspec.html#memory-device-lazy_allocation
+    fn transient_memory(gpu: &GPU) -> vk::MemoryPropertyFlags {
+        let lazy =
+            vk::MemoryPropertyFlags::DEVICE_LOCAL | vk::MemoryPropertyFlags::LAZILY_ALLOCATED;
+        let properties = &gpu.device_context.physical_device_memory_properties;
+        // desktop GPUs have no such memory, the attachments are still never stored there
+        match properties.memory_types[..properties.memory_type_count as usize]
+            .iter()
+            .any(|memory_type| memory_type.property_flags.contains(lazy))
+        {
+            true => lazy,
+            false => vk::MemoryPropertyFlags::DEVICE_LOCAL,
+        }
+    }
+
+    // None for a single sample, the pass renders into the scene color then.
+    unsafe fn create_color_resources(
+        gpu: &GPU,
+        extent: vk::Extent2D,
+        transient: bool,
+        samples: vk::SampleCountFlags,
+    ) -> Option<(vk::Image, Allocation, vk::ImageView)> {
+        if samples == vk::SampleCountFlags::TYPE_1 {
+            return None;
+        }
+        let (usage, memory) = match transient {
+            true => (
+                vk::ImageUsageFlags::COLOR_ATTACHMENT | vk::ImageUsageFlags::TRANSIENT_ATTACHMENT,
+                Self::transient_memory(gpu),
+            ),
+            false => (
+                vk::ImageUsageFlags::COLOR_ATTACHMENT,
+                vk::MemoryPropertyFlags::DEVICE_LOCAL,
+            ),
+        };
         let (color_image, color_image_memory) = gpu.device_context.create_image(
-            gpu.swap_chain.extent.width,
-            gpu.swap_chain.extent.height,
+            extent.width,
+            extent.height,
             1,
-            gpu.device_context.msaa_samples,
-            gpu.swap_chain.format,
+            samples,
+            Self::SCENE_FORMAT,
             vk::ImageTiling::OPTIMAL,
-            // Using VK_IMAGE_USAGE_TRANSIENT_ATTACHMENT_BIT combined with VK_MEMORY_PROPERTY_LAZILY_ALLOCATED_BIT memory.
-            // The idea is that lazy memory allocation prevents allocations for the multisample color attachment, which is
-            // only used as a temporary during the render pass, and therefore remains on-chip instead of stored in device memory.
-            // https://registry.khronos.org/vulkan/specs/1.2-extensions/html/vkspec.html#memory-device-lazy_allocation
-            // vk::ImageUsageFlags::COLOR_ATTACHMENT | vk::ImageUsageFlags::TRANSIENT_ATTACHMENT,
-            vk::ImageUsageFlags::COLOR_ATTACHMENT,
-            // vk::MemoryPropertyFlags::DEVICE_LOCAL | vk::MemoryPropertyFlags::LAZILY_ALLOCATED,
-            vk::MemoryPropertyFlags::DEVICE_LOCAL,
+            usage,
+            memory,
         );
         let color_image_view = gpu.device_context.create_image_view(
             color_image,
-            gpu.swap_chain.format,
+            Self::SCENE_FORMAT,
             vk::ImageAspectFlags::COLOR,
             1,
         );
 
-        (color_image, color_image_memory, color_image_view)
+        Some((color_image, color_image_memory, color_image_view))
     }
 
-    unsafe fn create_depth_resources(gpu: &GPU) -> (vk::Image, vk::DeviceMemory, vk::ImageView) {
+    // With `transient` the depth is also read by the shading subpass as input attachment.
+    unsafe fn create_depth_resources(
+        gpu: &GPU,
+        extent: vk::Extent2D,
+        transient: bool,
+        samples: vk::SampleCountFlags,
+    ) -> (vk::Image, Allocation, vk::ImageView) {
         let depth_format = Self::find_depth_format(gpu);
+        let (usage, memory) = match transient {
+            true => (
+                vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT
+                    | vk::ImageUsageFlags::TRANSIENT_ATTACHMENT
+                    | vk::ImageUsageFlags::INPUT_ATTACHMENT,
+                Self::transient_memory(gpu),
+            ),
+            // sampled by the passes after it, e.g. the decals
+            false => (
+                vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT | vk::ImageUsageFlags::SAMPLED,
+                vk::MemoryPropertyFlags::DEVICE_LOCAL,
+            ),
+        };
         let (depth_image, depth_image_memory) = gpu.device_context.create_image(
-            gpu.swap_chain.extent.width,
-            gpu.swap_chain.extent.height,
+            extent.width,
+            extent.height,
             1,
-            gpu.device_context.msaa_samples,
+            samples,
             depth_format,
             vk::ImageTiling::OPTIMAL,
-            vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT,
-            vk::MemoryPropertyFlags::DEVICE_LOCAL,
+            usage,
+            memory,
         );
         let depth_image_view = gpu.device_context.create_image_view(
             depth_image,
@@ -358,7 +1097,13 @@ impl ForwardRenderer {
         (depth_image, depth_image_memory, depth_image_view)
     }
 
-    unsafe fn create_render_pass(gpu: &GPU) -> vk::RenderPass {
+    // Without MSAA the color attachment is the scene color and there is no resolve attachment.
+    unsafe fn create_render_pass(
+        gpu: &GPU,
+        mobile_friendly: bool,
+        samples: vk::SampleCountFlags,
+    ) -> vk::RenderPass {
+        let resolve = samples != vk::SampleCountFlags::TYPE_1;
         // Textures and framebuffers in Vulkan are represented by VkImage objects with a certain pixel format,
         //   however the layout of the pixels in memory can change based on what you're trying to do with an image.
         // Some of the most common layouts are:
@@ -366,40 +1111,54 @@ impl ForwardRenderer {
         //   VK_IMAGE_LAYOUT_PRESENT_SRC_KHR: Images to be presented in the swap chain
         //   VK_IMAGE_LAYOUT_TRANSFER_DST_OPTIMAL: Images to be used as destination for a memory copy operation
         let color_attachment = vk::AttachmentDescription {
-            format: gpu.swap_chain.format,
-            samples: gpu.device_context.msaa_samples,
+            format: Self::SCENE_FORMAT,
+            samples,
             load_op: vk::AttachmentLoadOp::CLEAR,
-            store_op: vk::AttachmentStoreOp::STORE,
+            // only the resolve outlives the pass, a transient attachment is never written back
+            store_op: match mobile_friendly && resolve {
+                true => vk::AttachmentStoreOp::DONT_CARE,
+                false => vk::AttachmentStoreOp::STORE,
+            },
             stencil_load_op: vk::AttachmentLoadOp::DONT_CARE,
             stencil_store_op: vk::AttachmentStoreOp::DONT_CARE,
             initial_layout: vk::ImageLayout::UNDEFINED,
-            final_layout: vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+            final_layout: match resolve {
+                true => vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+                false => vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+            },
             flags: Default::default(),
         };
         let depth_attachment = vk::AttachmentDescription {
             format: Self::find_depth_format(gpu),
-            samples: gpu.device_context.msaa_samples,
+            samples,
             load_op: vk::AttachmentLoadOp::CLEAR,
-            store_op: vk::AttachmentStoreOp::DONT_CARE,
+            // kept for the decals, the transient one never leaves the tile
+            store_op: match mobile_friendly {
+                true => vk::AttachmentStoreOp::DONT_CARE,
+                false => vk::AttachmentStoreOp::STORE,
+            },
             stencil_load_op: vk::AttachmentLoadOp::DONT_CARE,
             stencil_store_op: vk::AttachmentStoreOp::DONT_CARE,
             initial_layout: vk::ImageLayout::UNDEFINED,
-            final_layout: vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL,
+            final_layout: vk::ImageLayout::DEPTH_STENCIL_READ_ONLY_OPTIMAL,
             flags: Default::default(),
         };
         let resolve_color_attachment = vk::AttachmentDescription {
-            format: gpu.swap_chain.format,
+            format: Self::SCENE_FORMAT,
             samples: vk::SampleCountFlags::TYPE_1,
             load_op: vk::AttachmentLoadOp::DONT_CARE,
             store_op: vk::AttachmentStoreOp::STORE,
             stencil_load_op: vk::AttachmentLoadOp::DONT_CARE,
             stencil_store_op: vk::AttachmentStoreOp::DONT_CARE,
             initial_layout: vk::ImageLayout::UNDEFINED,
-            final_layout: vk::ImageLayout::PRESENT_SRC_KHR,
+            final_layout: vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
             flags: Default::default(),
         };
 
-        let attachments = [color_attachment, depth_attachment, resolve_color_attachment];
+        let attachments = match resolve {
+            true => vec![color_attachment, depth_attachment, resolve_color_attachment],
+            false => vec![color_attachment, depth_attachment],
+        };
 
         let color_attachment_refs = [vk::AttachmentReference {
             attachment: 0,
@@ -414,27 +1173,64 @@ impl ForwardRenderer {
             layout: vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
         }];
 
-        let sub_passes = [vk::SubpassDescription::default()
+        // the prepass writes the depth, the shading tests against it and reads it per pixel
+        let depth_read_only_ref = [vk::AttachmentReference {
+            attachment: 1,
+            layout: vk::ImageLayout::DEPTH_STENCIL_READ_ONLY_OPTIMAL,
+        }];
+
+        let shading = vk::SubpassDescription::default()
             .pipeline_bind_point(vk::PipelineBindPoint::GRAPHICS)
-            .color_attachments(&color_attachment_refs)
-            .depth_stencil_attachment(&depth_attachment_ref)
-            .resolve_attachments(&resolve_color_attachment_refs)];
-        // .input_attachments()
+            .color_attachments(&color_attachment_refs);
+        let shading = match resolve {
+            true => shading.resolve_attachments(&resolve_color_attachment_refs),
+            false => shading,
+        };
         // .preserve_attachments()
+        let sub_passes = match mobile_friendly {
+            true => vec![
+                vk::SubpassDescription::default()
+                    .pipeline_bind_point(vk::PipelineBindPoint::GRAPHICS)
+                    .depth_stencil_attachment(&depth_attachment_ref),
+                shading
+                    .depth_stencil_attachment(&depth_read_only_ref[0])
+                    .input_attachments(&depth_read_only_ref),
+            ],
+            false => vec![shading.depth_stencil_attachment(&depth_attachment_ref)],
+        };
 
-        let dependencies = [vk::SubpassDependency {
-            src_subpass: vk::SUBPASS_EXTERNAL,
-            src_stage_mask: vk::PipelineStageFlags::LATE_FRAGMENT_TESTS
-                | vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
-            src_access_mask: vk::AccessFlags::NONE,
-            dst_subpass: 0,
-            dst_stage_mask: vk::PipelineStageFlags::EARLY_FRAGMENT_TESTS
-                | vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
-            dst_access_mask: vk::AccessFlags::COLOR_ATTACHMENT_WRITE
-                | vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_READ
-                | vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE,
-            ..Default::default()
-        }];
+        // every subpass waits for the previous frame, the prepass doesn't touch the color
+        let mut dependencies = (0..sub_passes.len() as u32)
+            .map(|dst_subpass| vk::SubpassDependency {
+                src_subpass: vk::SUBPASS_EXTERNAL,
+                // FRAGMENT_SHADER: the post passes of the previous frame may still sample the scene color
+                src_stage_mask: vk::PipelineStageFlags::LATE_FRAGMENT_TESTS
+                    | vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT
+                    | vk::PipelineStageFlags::FRAGMENT_SHADER,
+                src_access_mask: vk::AccessFlags::NONE,
+                dst_subpass,
+                dst_stage_mask: vk::PipelineStageFlags::EARLY_FRAGMENT_TESTS
+                    | vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
+                dst_access_mask: vk::AccessFlags::COLOR_ATTACHMENT_WRITE
+                    | vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_READ
+                    | vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE,
+                ..Default::default()
+            })
+            .collect::<Vec<_>>();
+        if mobile_friendly {
+            // the shading only reads the depth of its own pixel, which stays on tile
+            dependencies.push(vk::SubpassDependency {
+                src_subpass: 0,
+                src_stage_mask: vk::PipelineStageFlags::LATE_FRAGMENT_TESTS,
+                src_access_mask: vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE,
+                dst_subpass: 1,
+                dst_stage_mask: vk::PipelineStageFlags::EARLY_FRAGMENT_TESTS
+                    | vk::PipelineStageFlags::FRAGMENT_SHADER,
+                dst_access_mask: vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_READ
+                    | vk::AccessFlags::INPUT_ATTACHMENT_READ,
+                dependency_flags: vk::DependencyFlags::BY_REGION,
+            });
+        }
 
         let create_info = vk::RenderPassCreateInfo::default()
             .attachments(&attachments)
@@ -447,32 +1243,33 @@ impl ForwardRenderer {
             .expect("failed to create render pass!")
     }
 
-    unsafe fn create_framebuffers(
+    unsafe fn create_scene_color(gpu: &GPU, extent: vk::Extent2D) -> RenderTarget {
+        RenderTarget::new(gpu, extent.width, extent.height, Self::SCENE_FORMAT)
+    }
+
+    unsafe fn create_framebuffer(
         gpu: &GPU,
         render_pass: vk::RenderPass,
-        color_image_view: vk::ImageView,
+        color_image_view: Option<vk::ImageView>,
         depth_image_view: vk::ImageView,
-    ) -> Vec<vk::Framebuffer> {
-        // be aware, here is not using MAX_INFLIGHT
-        gpu.swap_chain
-            .image_views
-            .iter()
-            .map(|&image_view| {
-                let attachments = [color_image_view, depth_image_view, image_view];
-
-                let create_info = vk::FramebufferCreateInfo::default()
-                    .width(gpu.swap_chain.extent.width)
-                    .height(gpu.swap_chain.extent.height)
-                    .layers(1)
-                    .attachments(&attachments)
-                    .render_pass(render_pass);
-
-                gpu.device_context
-                    .device
-                    .create_framebuffer(&create_info, None)
-                    .expect("failed to create framebuffer!")
-            })
-            .collect::<Vec<vk::Framebuffer>>()
+        scene_color: &RenderTarget,
+    ) -> vk::Framebuffer {
+        let attachments = match color_image_view {
+            Some(color_image_view) => vec![color_image_view, depth_image_view, scene_color.view],
+            None => vec![scene_color.view, depth_image_view],
+        };
+
+        let create_info = vk::FramebufferCreateInfo::default()
+            .width(scene_color.width)
+            .height(scene_color.height)
+            .layers(1)
+            .attachments(&attachments)
+            .render_pass(render_pass);
+
+        gpu.device_context
+            .device
+            .create_framebuffer(&create_info, None)
+            .expect("failed to create framebuffer!")
     }
 
     unsafe fn find_depth_format(gpu: &GPU) -> vk::Format {
@@ -491,28 +1288,11 @@ impl ForwardRenderer {
 impl Drop for ForwardRenderer {
     fn drop(&mut self) {
         unsafe {
-            let device = &self.gpu.device_context.device;
-            self.uniform_buffers.iter().for_each(|buffer| {
-                device.destroy_buffer(*buffer, None);
-            });
-            self.uniform_buffer_memories.iter().for_each(|memory| {
-                device.free_memory(*memory, None);
-            });
-
-            self.framebuffers
-                .iter()
-                .for_each(|&framebuffer| device.destroy_framebuffer(framebuffer, None));
-
-            device.destroy_image(self.color_image, None);
-            device.free_memory(self.color_image_memory, None);
-            device.destroy_image_view(self.color_image_view, None);
-
-            device.destroy_image(self.depth_image, None);
-            device.free_memory(self.depth_image_memory, None);
-            device.destroy_image_view(self.depth_image_view, None);
-            device.destroy_render_pass(self.render_pass, None);
-
-            device.destroy_descriptor_set_layout(self.descriptor_set_layout, None);
+            self.destroy_attachments();
+            self.gpu
+                .device_context
+                .device
+                .destroy_render_pass(self.render_pass, None);
         }
     }
 }
@@ -0,0 +1,278 @@
+use super::gpu_geom::GPUGeom;
+use super::vertex::Vertex;
+use crate::assets::Geom;
+use crate::gpu::{RawResource, VkBuffer, GPU};
+use ash::vk;
+use std::cell::RefCell;
+use std::mem::size_of;
+use std::ops::Range;
+use std::rc::Rc;
+
+// Capacity of a block in elements, a geom larger than either gets a block of its own size.
+const BLOCK_VERTICES: u32 = 1 << 18;
+const BLOCK_INDICES: u32 = 1 << 20;
+
+// First fit over the free ranges of a block, in elements. Freed ranges merge with their
+// neighbours.
+struct RangeAllocator {
+    // sorted, never adjacent
+    free: Vec<Range<u32>>,
+}
+
+impl RangeAllocator {
+    fn new(size: u32) -> Self {
+        // all of it is free
+        let whole = 0..size;
+        Self { free: vec![whole] }
+    }
+
+    fn allocate(&mut self, size: u32) -> Option<Range<u32>> {
+        if size == 0 {
+            return Some(0..0);
+        }
+        let index = self
+            .free
+            .iter()
+            .position(|range| range.end - range.start >= size)?;
+        let start = self.free[index].start;
+        self.free[index].start += size;
+        if self.free[index].is_empty() {
+            self.free.remove(index);
+        }
+        Some(start..start + size)
+    }
+
+    fn free(&mut self, range: Range<u32>) {
+        if range.is_empty() {
+            return;
+        }
+        let index = self.free.partition_point(|free| free.start < range.start);
+        let merges_previous = index > 0 && self.free[index - 1].end == range.start;
+        let merges_next = self
+            .free
+            .get(index)
+            .is_some_and(|next| next.start == range.end);
+        match (merges_previous, merges_next) {
+            (true, true) => {
+                self.free[index - 1].end = self.free[index].end;
+                self.free.remove(index);
+            }
+            (true, false) => self.free[index - 1].end = range.end,
+            (false, true) => self.free[index].start = range.start,
+            (false, false) => self.free.insert(index, range),
+        }
+    }
+}
+
+struct ArenaBlock {
+    vertex_buffer: VkBuffer,
+    index_buffer: VkBuffer,
+    vertices: RangeAllocator,
+    indices: RangeAllocator,
+}
+
+// Where a geom lives in the arena, given back with `GeomArena::free`.
+#[derive(Debug, Clone)]
+pub struct GeomAllocation {
+    block: usize,
+    vertices: Range<u32>,
+    indices: Range<u32>,
+}
+
+// The vertices and indices of every static geom in a few large buffers, a geom is a range of
+// each, drawn with its first index and vertex offset. Draws of different geoms bind the same
+// buffers and the device memory isn't split into a pair of allocations per mesh. The buffers
+// are shared with the upload queue, a geom is uploaded while the frames draw the others.
+pub struct GeomArena {
+    gpu: Rc<GPU>,
+
+    // shared with the deferred frees
+    blocks: Rc<RefCell<Vec<ArenaBlock>>>,
+}
+
+impl GeomArena {
+    pub fn new(gpu: &Rc<GPU>) -> Self {
+        Self {
+            gpu: Rc::clone(gpu),
+            blocks: Rc::new(RefCell::new(vec![])),
+        }
+    }
+
+    // Uploads the geom into the first block with room for it, a new one when none has.
+    pub fn allocate(&self, geom: &Geom) -> (GPUGeom, GeomAllocation) {
+        let (vertex_count, index_count) = (geom.vertices.len() as u32, geom.indices.len() as u32);
+        let mut blocks = self.blocks.borrow_mut();
+        let found = blocks.iter_mut().enumerate().find_map(|(index, block)| {
+            let vertices = block.vertices.allocate(vertex_count)?;
+            let Some(indices) = block.indices.allocate(index_count) else {
+                block.vertices.free(vertices);
+                return None;
+            };
+            Some((index, vertices, indices))
+        });
+        let (block, vertices, indices) = found.unwrap_or_else(|| {
+            let mut block = self.create_block(
+                blocks.len(),
+                vertex_count.max(BLOCK_VERTICES),
+                index_count.max(BLOCK_INDICES),
+            );
+            let vertices = block.vertices.allocate(vertex_count).unwrap();
+            let indices = block.indices.allocate(index_count).unwrap();
+            blocks.push(block);
+            (blocks.len() - 1, vertices, indices)
+        });
+
+        let arena_block = &blocks[block];
+        self.gpu.upload_buffer_region(
+            arena_block.vertex_buffer.buffer,
+            (vertices.start as usize * size_of::<Vertex>()) as vk::DeviceSize,
+            &geom.vertices,
+        );
+        self.gpu.upload_buffer_region(
+            arena_block.index_buffer.buffer,
+            (indices.start as usize * size_of::<u32>()) as vk::DeviceSize,
+            &geom.indices,
+        );
+
+        let gpu_geom = GPUGeom {
+            vertex_buffer: arena_block.vertex_buffer,
+            index_buffer: arena_block.index_buffer,
+            first_index: indices.start,
+            vertex_offset: vertices.start as i32,
+            indices_length: geom.indices.len(),
+            bounds: geom.bounds,
+        };
+        let allocation = GeomAllocation {
+            block,
+            vertices,
+            indices,
+        };
+        (gpu_geom, allocation)
+    }
+
+    // Frames in flight may still draw the geom, its ranges are reused once they are done.
+    pub fn free(&self, allocation: GeomAllocation) {
+        let blocks = Rc::clone(&self.blocks);
+        self.gpu
+            .defer_destroy(RawResource::Custom(Box::new(move |_| {
+                // the arena may be gone by then
+                if let Some(block) = blocks.borrow_mut().get_mut(allocation.block) {
+                    block.vertices.free(allocation.vertices);
+                    block.indices.free(allocation.indices);
+                }
+            })));
+    }
+
+    fn create_block(&self, index: usize, vertex_capacity: u32, index_capacity: u32) -> ArenaBlock {
+        let create_buffer = |size: usize, usage: vk::BufferUsageFlags| unsafe {
+            let (buffer, memory) = self.gpu.device_context.create_shared_buffer(
+                size as vk::DeviceSize,
+                // read as storage by compute passes too, e.g. the normal debugger
+                vk::BufferUsageFlags::TRANSFER_DST | vk::BufferUsageFlags::STORAGE_BUFFER | usage,
+                vk::MemoryPropertyFlags::DEVICE_LOCAL,
+            );
+            VkBuffer { buffer, memory }
+        };
+        let vertex_buffer = create_buffer(
+            vertex_capacity as usize * size_of::<Vertex>(),
+            vk::BufferUsageFlags::VERTEX_BUFFER,
+        );
+        let index_buffer = create_buffer(
+            index_capacity as usize * size_of::<u32>(),
+            vk::BufferUsageFlags::INDEX_BUFFER,
+        );
+        let debug_names = &self.gpu.debug_names;
+        if debug_names.is_enabled() {
+            let name = format!("geom arena {} vertices", index);
+            debug_names.set_name(vertex_buffer.buffer, &name);
+            let name = format!("geom arena {} indices", index);
+            debug_names.set_name(index_buffer.buffer, &name);
+        }
+
+        ArenaBlock {
+            vertex_buffer,
+            index_buffer,
+            vertices: RangeAllocator::new(vertex_capacity),
+            indices: RangeAllocator::new(index_capacity),
+        }
+    }
+}
+
+impl Drop for GeomArena {
+    fn drop(&mut self) {
+        unsafe {
+            let device_context = &self.gpu.device_context;
+            for block in self.blocks.borrow_mut().drain(..) {
+                device_context
+                    .destroy_buffer(block.vertex_buffer.buffer, block.vertex_buffer.memory);
+                device_context.destroy_buffer(block.index_buffer.buffer, block.index_buffer.memory);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::RangeAllocator;
+
+    #[test]
+    fn allocate_takes_the_first_range_that_fits() {
+        let mut allocator = RangeAllocator::new(100);
+        assert_eq!(allocator.allocate(30), Some(0..30));
+        assert_eq!(allocator.allocate(50), Some(30..80));
+        assert_eq!(allocator.allocate(0), Some(0..0));
+        assert_eq!(allocator.free, vec![80..100]);
+
+        // the hole at the front is too small for 20, the tail isn't
+        allocator.free(0..10);
+        assert_eq!(allocator.allocate(20), Some(80..100));
+        assert_eq!(allocator.allocate(10), Some(0..10));
+        assert!(allocator.free.is_empty());
+    }
+
+    #[test]
+    fn freed_ranges_merge_with_their_neighbours() {
+        let mut allocator = RangeAllocator::new(100);
+        let ranges = [10, 20, 30, 40].map(|size| allocator.allocate(size).unwrap());
+        assert!(allocator.free.is_empty());
+
+        // no neighbour free, then with the previous, then with the next, then with both
+        allocator.free(ranges[0].clone());
+        assert_eq!(allocator.free, vec![0..10]);
+        allocator.free(ranges[1].clone());
+        assert_eq!(allocator.free, vec![0..30]);
+        allocator.free(ranges[3].clone());
+        assert_eq!(allocator.free, vec![0..30, 60..100]);
+        allocator.free(ranges[2].clone());
+        assert_eq!(allocator.free, vec![0..100]);
+
+        // freeing nothing changes nothing
+        allocator.free(5..5);
+        assert_eq!(allocator.free, vec![0..100]);
+    }
+
+    #[test]
+    fn a_freed_range_between_others_is_reused() {
+        let mut allocator = RangeAllocator::new(90);
+        let ranges = [30, 30, 30].map(|size| allocator.allocate(size).unwrap());
+        allocator.free(ranges[1].clone());
+        assert_eq!(allocator.free, vec![30..60]);
+        assert_eq!(allocator.allocate(40), None);
+        assert_eq!(allocator.allocate(25), Some(30..55));
+        assert_eq!(allocator.free, vec![55..60]);
+    }
+
+    #[test]
+    fn running_out_of_space_leaves_the_free_ranges_alone() {
+        let mut allocator = RangeAllocator::new(64);
+        assert_eq!(allocator.allocate(65), None);
+        assert_eq!(allocator.allocate(64), Some(0..64));
+        assert_eq!(allocator.allocate(1), None);
+        assert!(allocator.free.is_empty());
+
+        allocator.free(16..32);
+        allocator.free(48..64);
+        assert_eq!(allocator.allocate(17), None);
+        assert_eq!(allocator.free, vec![16..32, 48..64]);
+    }
+}
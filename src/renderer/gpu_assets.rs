@@ -1,61 +1,475 @@
-use crate::assets::{AssetHandle, AssetId, Assets, Geom, Material, Texture};
-use crate::gpu::GPU;
+use crate::assets::{AssetHandle, AssetId, Assets, DynamicGeom, Geom, Material, Texture};
+use crate::gpu::{RawResource, Rhi, SamplerDesc, TextureSwizzle, GPU};
+use crate::renderer::geom_arena::{GeomAllocation, GeomArena};
+use crate::renderer::gpu_dynamic_geom::GPUDynamicGeom;
 use crate::renderer::gpu_geom::GPUGeom;
+use crate::renderer::gpu_material_params::GPUMaterialParams;
 use crate::renderer::gpu_pipeline::GPUPipeline;
 use crate::renderer::gpu_texture::GPUTexture;
-use crate::renderer::ForwardRenderer;
+use crate::renderer::mip_generator::MipGenerator;
+use crate::renderer::{ForwardRenderer, RenderGeom, Shading};
 use ash::vk;
-use std::cell::RefCell;
-use std::collections::HashMap;
+use std::cell::{Cell, RefCell};
+use std::collections::{HashMap, HashSet};
+use std::hash::{DefaultHasher, Hash, Hasher};
 use std::rc::Rc;
+use std::thread::JoinHandle;
+
+// the shading pipeline and its depth prepass variant, see `PipelineDesc::build`
+type PipelineBuild = JoinHandle<(vk::Pipeline, Option<vk::Pipeline>)>;
+
+// What an upload is pooled under, texture assets with the same pixels share one.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+enum TextureKey {
+    // hash of the pixels and the layout, see `GPUAssets::content_hash`
+    Content(u64),
+    // an image rendered elsewhere, see `replace_texture`
+    Replaced(AssetId),
+}
+
+struct PooledTexture {
+    texture: GPUTexture,
+    // what its sampler was got with, see `set_mip_lod_bias`
+    sampler: SamplerDesc,
+    // texture assets sharing the upload, it is dropped with the last one
+    refs: usize,
+    // the frame it was last drawn with, see `end_frame`
+    last_used: u64,
+    // bound outside the descriptors rewritten every frame, e.g. by the egui or lighting sets,
+    // never evicted
+    pinned: bool,
+}
 
 pub struct GPUAssets {
     gpu: Rc<GPU>,
     assets: Rc<RefCell<Assets>>,
 
     pipeline_pool: RefCell<HashMap<AssetId, HashMap<vk::RenderPass, GPUPipeline>>>,
-    geom_pool: RefCell<HashMap<AssetId, GPUGeom>>,
-    texture_pool: RefCell<HashMap<AssetId, GPUTexture>>,
+    // pipelines still compiling on a warm-up thread, moved into the pool once done
+    pending_pipelines: RefCell<HashMap<(AssetId, vk::RenderPass), (GPUPipeline, PipelineBuild)>>,
+    // parameter blocks of the materials whose shading declares one
+    material_params_pool: RefCell<HashMap<AssetId, GPUMaterialParams>>,
+    geom_pool: RefCell<HashMap<AssetId, (GPUGeom, GeomAllocation)>>,
+    // the buffers the static geoms share
+    geom_arena: GeomArena,
+    dynamic_geom_pool: RefCell<HashMap<AssetId, GPUDynamicGeom>>,
+    // buffers written on the GPU behind dynamic geom handles, see `replace_dynamic_geom`
+    replaced_geoms: RefCell<HashMap<AssetId, GPUGeom>>,
+    texture_pool: RefCell<HashMap<TextureKey, PooledTexture>>,
+    // the upload of each texture asset
+    texture_keys: RefCell<HashMap<AssetId, TextureKey>>,
+    // swizzled views of pooled textures, for channel packed slots
+    texture_view_pool: RefCell<HashMap<(TextureKey, TextureSwizzle), GPUTexture>>,
+    // device memory textures may take before the least recently drawn are evicted, None keeps
+    // every upload
+    texture_budget: Cell<Option<vk::DeviceSize>>,
+    // frames ended so far
+    frame: Cell<u64>,
+    // textures in a format the device can't sample, reported once
+    unsupported_textures: RefCell<HashSet<AssetId>>,
+    // normal and roughness chains, see `Texture::mip_filter`
+    mip_generator: MipGenerator,
 }
 
 impl GPUAssets {
     pub fn new(gpu: Rc<GPU>, assets: Rc<RefCell<Assets>>) -> Self {
         GPUAssets {
+            mip_generator: MipGenerator::new(&gpu),
+            geom_arena: GeomArena::new(&gpu),
             gpu,
             assets,
             pipeline_pool: RefCell::new(HashMap::new()),
+            pending_pipelines: RefCell::new(HashMap::new()),
+            material_params_pool: RefCell::new(HashMap::new()),
             geom_pool: RefCell::new(HashMap::new()),
+            dynamic_geom_pool: RefCell::new(HashMap::new()),
+            replaced_geoms: RefCell::new(HashMap::new()),
             texture_pool: RefCell::new(HashMap::new()),
+            texture_keys: RefCell::new(HashMap::new()),
+            texture_view_pool: RefCell::new(HashMap::new()),
+            texture_budget: Cell::new(None),
+            frame: Cell::new(0),
+            unsupported_textures: RefCell::new(HashSet::new()),
         }
     }
 
+    // The upload stays until released, the caller may keep it bound, e.g. in a descriptor set
+    // written once. Textures of materials are fetched with `get_material` and may be evicted.
     pub fn get_texture(&self, handle: AssetHandle<Texture>) -> Option<GPUTexture> {
+        self.upload_texture(&handle, true)
+            .map(|(_, texture)| texture)
+    }
+
+    fn upload_texture(
+        &self,
+        handle: &AssetHandle<Texture>,
+        pin: bool,
+    ) -> Option<(TextureKey, GPUTexture)> {
+        let frame = self.frame.get();
         let mut texture_pool = self.texture_pool.borrow_mut();
-        match texture_pool.get(&handle.id) {
-            None => {
-                let assets = self.assets.borrow();
-                let texture = assets.load(&handle)?;
-                let tex_gpu = GPUTexture::new(&self.gpu, &texture);
+        let key = self.texture_keys.borrow().get(&handle.id).copied();
+        if let Some(pooled) = key.and_then(|key| texture_pool.get_mut(&key)) {
+            pooled.last_used = frame;
+            pooled.pinned |= pin;
+            return Some((key.unwrap(), pooled.texture));
+        }
+
+        let assets = self.assets.borrow();
+        let texture = assets.load(handle)?;
+        // e.g. BCn on a mobile GPU, ship the variant `select_ktx2_variant` picks instead
+        let format = texture.upload_format();
+        if !self.gpu.is_texture_format_supported(format) {
+            if self.unsupported_textures.borrow_mut().insert(handle.id) {
+                log::error!(
+                    "texture format {:?} is not supported by the device!",
+                    format
+                );
+            }
+            return None;
+        }
+
+        let key = TextureKey::Content(Self::content_hash(texture));
+        let pooled = texture_pool.entry(key).or_insert_with(|| {
+            let tex_gpu = GPUTexture::new(&self.gpu, texture);
+            if self.gpu.debug_names.is_enabled() {
+                let name = match assets.path(handle) {
+                    Some(path) => format!("texture {}", path),
+                    None => format!("texture {}", handle.id),
+                };
+                self.gpu.debug_names.set_name(tex_gpu.texture.image, &name);
+                self.gpu
+                    .debug_names
+                    .set_name(tex_gpu.texture.image_view, &name);
+            }
+            if GPUTexture::desc(texture).is_mip_filtered() {
+                self.mip_generator.generate(
+                    &tex_gpu,
+                    texture.width,
+                    texture.height,
+                    texture.mip_filter,
+                );
+            }
+            PooledTexture {
+                texture: tex_gpu,
+                sampler: texture.sampler,
+                refs: 0,
+                last_used: frame,
+                pinned: false,
+            }
+        });
+        pooled.refs += 1;
+        pooled.last_used = frame;
+        pooled.pinned |= pin;
+        self.texture_keys.borrow_mut().insert(handle.id, key);
+        Some((key, pooled.texture))
+    }
+
+    // Identical textures loaded from different files or generated twice hash the same.
+    fn content_hash(texture: &Texture) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        texture.width.hash(&mut hasher);
+        texture.height.hash(&mut hasher);
+        texture.mip_levels.hash(&mut hasher);
+        texture.layers.hash(&mut hasher);
+        std::mem::discriminant(&texture.format).hash(&mut hasher);
+        texture.color_space.hash(&mut hasher);
+        texture.pixels.hash(&mut hasher);
+        texture.mips.hash(&mut hasher);
+        texture.mip_filter.hash(&mut hasher);
+        texture.sampler.hash(&mut hasher);
+        hasher.finish()
+    }
+
+    // Drops the asset's use of its upload, the next `get_texture` uploads the asset again, e.g.
+    // after its pixels changed. Other assets with the same pixels keep the upload, the last one
+    // hands it to the deletion queue as frames in flight may still sample it.
+    pub fn release_texture(&self, handle: &AssetHandle<Texture>) {
+        let Some(key) = self.texture_keys.borrow_mut().remove(&handle.id) else {
+            return;
+        };
+        let mut texture_pool = self.texture_pool.borrow_mut();
+        let Some(pooled) = texture_pool.get_mut(&key) else {
+            return;
+        };
+        pooled.refs -= 1;
+        if pooled.refs == 0 {
+            let pooled = texture_pool.remove(&key).unwrap();
+            self.destroy_texture(key, pooled);
+        }
+    }
+
+    // The asset's pixels were swapped, e.g. by a hot reload, its next use uploads them again.
+    // Material descriptors are rewritten every frame and the old upload goes through the deletion
+    // queue. False for a pinned upload, sets written once keep binding it, the new pixels show
+    // after a restart.
+    pub fn reload_texture(&self, handle: &AssetHandle<Texture>) -> bool {
+        let Some(key) = self.texture_keys.borrow().get(&handle.id).copied() else {
+            return true;
+        };
+        let mut texture_pool = self.texture_pool.borrow_mut();
+        if let Some(pooled) = texture_pool.get_mut(&key) {
+            if pooled.pinned {
+                return false;
+            }
+            pooled.refs -= 1;
+            if pooled.refs == 0 {
+                let pooled = texture_pool.remove(&key).unwrap();
+                self.destroy_texture(key, pooled);
+            }
+        }
+        self.texture_keys.borrow_mut().remove(&handle.id);
+        true
+    }
 
-                texture_pool.insert(handle.id, tex_gpu)
+    // Puts an image rendered elsewhere behind the handle, e.g. the target of a video. The pool owns
+    // it from then on, a previous upload is dropped.
+    pub fn replace_texture(&self, handle: &AssetHandle<Texture>, texture: GPUTexture) {
+        self.release_texture(handle);
+        let key = TextureKey::Replaced(handle.id);
+        self.texture_pool.borrow_mut().insert(
+            key,
+            PooledTexture {
+                texture,
+                sampler: SamplerDesc::default(),
+                refs: 1,
+                last_used: self.frame.get(),
+                pinned: true,
+            },
+        );
+        self.texture_keys.borrow_mut().insert(handle.id, key);
+    }
+
+    // Textures are evicted past the budget, least recently drawn first. Evicted ones are uploaded
+    // again when a material draws them, from the pixels the assets keep.
+    pub fn set_texture_budget(&self, budget: Option<vk::DeviceSize>) {
+        self.texture_budget.set(budget);
+    }
+
+    // Device memory of the uploaded textures.
+    pub fn texture_memory(&self) -> vk::DeviceSize {
+        self.texture_pool
+            .borrow()
+            .values()
+            .map(|pooled| pooled.texture.texture.image_memory.size)
+            .sum()
+    }
+
+    // After the frame was submitted, evicts textures past the budget. Textures the frame drew are
+    // kept, the others go through the deletion queue, so frames in flight can still sample them.
+    pub fn end_frame(&self) {
+        let frame = self.frame.get();
+        self.frame.set(frame + 1);
+        let Some(budget) = self.texture_budget.get() else {
+            return;
+        };
+        let mut memory = self.texture_memory();
+        if memory <= budget {
+            return;
+        }
+
+        let mut texture_pool = self.texture_pool.borrow_mut();
+        let mut evictable = texture_pool
+            .iter()
+            .filter(|(_, pooled)| !pooled.pinned && pooled.last_used < frame)
+            .map(|(key, pooled)| (pooled.last_used, *key))
+            .collect::<Vec<_>>();
+        evictable.sort_by_key(|(last_used, _)| *last_used);
+        for (_, key) in evictable {
+            if memory <= budget {
+                break;
             }
-            Some(tex) => Some(tex.to_owned()),
+            let pooled = texture_pool.remove(&key).unwrap();
+            memory -= pooled.texture.texture.image_memory.size;
+            self.texture_keys
+                .borrow_mut()
+                .retain(|_, id_key| *id_key != key);
+            self.destroy_texture(key, pooled);
+        }
+        if memory > budget {
+            log::warn!(
+                "textures drawn this frame take {} bytes, past the budget of {}!",
+                memory,
+                budget
+            );
         }
     }
 
+    // Through the deletion queue, frames in flight may still sample it.
+    fn destroy_texture(&self, key: TextureKey, pooled: PooledTexture) {
+        self.texture_view_pool
+            .borrow_mut()
+            .retain(|(view_key, _), view| {
+                if *view_key == key {
+                    self.gpu
+                        .defer_destroy(RawResource::ImageView(view.texture.image_view));
+                }
+                *view_key != key
+            });
+        // the sampler is shared, see `GPU::get_sampler`
+        let texture = pooled.texture.texture;
+        self.gpu
+            .defer_destroy(RawResource::ImageView(texture.image_view));
+        self.gpu.defer_destroy(RawResource::Image(texture.image));
+        self.gpu
+            .defer_destroy(RawResource::Allocation(texture.image_memory));
+    }
+
+    // Drops the pipelines built for the material on any render pass, they are rebuilt from the
+    // material's current shading on next use.
+    pub fn release_pipelines(&self, id: AssetId) {
+        self.pending_pipelines
+            .borrow_mut()
+            .extract_if(|(key_id, _), _| *key_id == id)
+            .for_each(|(_, (mut pipeline, thread))| {
+                pipeline.set_built(thread.join().expect("failed to warm up pipeline!"));
+                pipeline.defer_drop(&self.gpu);
+            });
+
+        let Some(mut pipelines) = self.pipeline_pool.borrow_mut().remove(&id) else {
+            return;
+        };
+        // frames in flight may still use them
+        pipelines
+            .values_mut()
+            .for_each(|pipeline| pipeline.defer_drop(&self.gpu));
+    }
+
+    // Drops the pipelines built against a render pass that is about to be destroyed, a new one may
+    // be created with the same handle.
+    pub fn release_render_pass(&self, render_pass: vk::RenderPass) {
+        self.pending_pipelines
+            .borrow_mut()
+            .extract_if(|(_, pass), _| *pass == render_pass)
+            .for_each(|(_, (mut pipeline, thread))| {
+                pipeline.set_built(thread.join().expect("failed to warm up pipeline!"));
+                pipeline.defer_drop(&self.gpu);
+            });
+
+        // frames in flight may still use them
+        self.pipeline_pool
+            .borrow_mut()
+            .values_mut()
+            .for_each(|pipelines| {
+                if let Some(mut pipeline) = pipelines.remove(&render_pass) {
+                    pipeline.defer_drop(&self.gpu);
+                }
+            });
+    }
+
+    // Samplers bake the bias in, so the already uploaded textures get rebuilt ones.
+    // Texture descriptors are rewritten every frame and pick the new samplers up.
+    pub fn set_mip_lod_bias(&self, mip_lod_bias: f32) {
+        self.gpu.set_mip_lod_bias(mip_lod_bias);
+
+        let mut texture_pool = self.texture_pool.borrow_mut();
+        texture_pool.values_mut().for_each(|pooled| {
+            pooled.texture.texture.image_sampler = self.gpu.get_sampler(&pooled.sampler);
+        });
+        // views share the sampler of their texture
+        self.texture_view_pool
+            .borrow_mut()
+            .iter_mut()
+            .for_each(|((key, _), view)| {
+                if let Some(pooled) = texture_pool.get(key) {
+                    view.texture.image_sampler = pooled.texture.texture.image_sampler;
+                }
+            });
+    }
+
+    pub fn get_texture_swizzled(
+        &self,
+        handle: AssetHandle<Texture>,
+        swizzle: TextureSwizzle,
+    ) -> Option<GPUTexture> {
+        self.texture_view(&handle, swizzle, true)
+    }
+
+    fn texture_view(
+        &self,
+        handle: &AssetHandle<Texture>,
+        swizzle: TextureSwizzle,
+        pin: bool,
+    ) -> Option<GPUTexture> {
+        let (key, texture) = self.upload_texture(handle, pin)?;
+        if swizzle == TextureSwizzle::IDENTITY {
+            return Some(texture);
+        }
+
+        let view_key = (key, swizzle);
+        if let Some(view) = self.texture_view_pool.borrow().get(&view_key) {
+            return Some(*view);
+        }
+
+        let view = GPUTexture {
+            texture: self.gpu.create_texture_view(&texture.texture, swizzle),
+        };
+        self.texture_view_pool.borrow_mut().insert(view_key, view);
+        Some(view)
+    }
+
+    // Compiles the pipelines of the given materials on background threads, so the first frames of a
+    // freshly loaded scene don't hitch. The drivers are free-threaded for vkCreateGraphicsPipelines and
+    // everything else (modules, layouts, descriptor sets) is still created here on the main thread.
+    pub fn warm_up(&self, materials: &[AssetHandle<Material>], renderer: &ForwardRenderer) {
+        let assets = self.assets.borrow();
+        let pipeline_pool = self.pipeline_pool.borrow();
+        let mut pending_pipelines = self.pending_pipelines.borrow_mut();
+
+        for handle in materials {
+            let key = (handle.id, renderer.render_pass);
+            let is_built = pipeline_pool
+                .get(&handle.id)
+                .is_some_and(|pipelines| pipelines.contains_key(&renderer.render_pass));
+            if is_built || pending_pipelines.contains_key(&key) {
+                continue;
+            }
+            let Some(material) = assets.load(handle) else {
+                continue;
+            };
+
+            let (pipeline, desc) = GPUPipeline::prepare(&self.gpu, material, renderer);
+            let thread = std::thread::spawn(move || desc.build());
+            pending_pipelines.insert(key, (pipeline, thread));
+        }
+    }
+
+    // true while the pipeline is still compiling, finished ones are moved into the pool
+    fn poll_warm_up(&self, id: AssetId, render_pass: vk::RenderPass) -> bool {
+        let mut pending_pipelines = self.pending_pipelines.borrow_mut();
+        match pending_pipelines.get(&(id, render_pass)) {
+            None => return false,
+            Some((_, thread)) if !thread.is_finished() => return true,
+            _ => {}
+        }
+
+        let (mut pipeline, thread) = pending_pipelines.remove(&(id, render_pass)).unwrap();
+        pipeline.set_built(thread.join().expect("failed to warm up pipeline!"));
+        pipeline.set_debug_names(&self.gpu);
+        self.pipeline_pool
+            .borrow_mut()
+            .entry(id)
+            .or_default()
+            .insert(render_pass, pipeline);
+        false
+    }
+
     pub fn get_pipeline(
         &self,
         handle: &AssetHandle<Material>,
         renderer: &ForwardRenderer,
     ) -> Option<GPUPipeline> {
+        if self.poll_warm_up(handle.id, renderer.render_pass) {
+            return None;
+        }
+
         let mut pipeline_pool = self.pipeline_pool.borrow_mut();
-        let pipelines = pipeline_pool.entry(handle.id).or_insert(HashMap::new());
+        let pipelines = pipeline_pool.entry(handle.id).or_default();
 
         match pipelines.get(&renderer.render_pass) {
             None => {
                 let assets = self.assets.borrow();
-                let material = assets.load(&handle)?;
-                let pipeline_gpu = GPUPipeline::new(&self.gpu, &material, renderer);
+                let material = assets.load(handle)?;
+                let pipeline_gpu = GPUPipeline::new(&self.gpu, material, renderer);
                 pipelines.insert(renderer.render_pass, pipeline_gpu)
             }
             Some(pipeline) => Some(pipeline.to_owned()),
@@ -66,27 +480,108 @@ impl GPUAssets {
         &self,
         handle: &AssetHandle<Material>,
         renderer: &ForwardRenderer,
-    ) -> Option<(GPUPipeline, HashMap<&str, Option<GPUTexture>>)> {
+    ) -> Option<(GPUPipeline, Vec<Option<GPUTexture>>)> {
+        if self.poll_warm_up(handle.id, renderer.render_pass) {
+            return None;
+        }
+
         let mut pipeline_pool = self.pipeline_pool.borrow_mut();
-        let pipelines = pipeline_pool.entry(handle.id).or_insert(HashMap::new());
+        let pipelines = pipeline_pool.entry(handle.id).or_default();
 
         let assets = self.assets.borrow();
-        let material = assets.load(&handle)?;
+        let material = assets.load(handle)?;
 
         let pipeline = match pipelines.get(&renderer.render_pass) {
             None => {
-                let pipeline = GPUPipeline::new(&self.gpu, &material, renderer);
+                let pipeline = GPUPipeline::new(&self.gpu, material, renderer);
                 pipelines.insert(renderer.render_pass, pipeline)?
             }
             Some(pipeline) => pipeline.to_owned(),
         };
 
-        let mut properties = HashMap::new();
-        if let Some(value) = material.get_texture("texture") {
-            properties.insert("texture", self.get_texture(value));
+        // one per shading texture slot
+        let textures = material
+            .shading
+            .texture_slots
+            .iter()
+            .map(|slot| {
+                let value = material.get_texture(slot)?;
+                let mut texture = self.texture_view(&value, material.get_swizzle(slot), false)?;
+                if let Some(sampler) = material.get_sampler(slot) {
+                    texture.texture.image_sampler = self.gpu.get_sampler(&sampler);
+                }
+                Some(texture)
+            })
+            .collect();
+
+        Some((pipeline, textures))
+    }
+
+    // Writes the material's parameter block for the frame if `Material::set_param` changed it and
+    // binds it to the descriptor set, nothing for shadings without params.
+    // Whether any texture slot of the material is bound to `texture`.
+    pub fn material_samples(&self, handle: &AssetHandle<Material>, texture: AssetId) -> bool {
+        let assets = self.assets.borrow();
+        let Some(material) = assets.load(handle) else {
+            return false;
+        };
+        material.shading.texture_slots.iter().any(|slot| {
+            material
+                .get_texture(slot)
+                .is_some_and(|value| value.id == texture)
+        })
+    }
+
+    // Points PARAMS_BINDING of the material set at the frame's up to date params.
+    pub fn bind_material_params(
+        &self,
+        handle: &AssetHandle<Material>,
+        frame_index: usize,
+        descriptor_set: vk::DescriptorSet,
+    ) {
+        let Some(buffer_info) = self.update_material_params(handle, frame_index) else {
+            return;
+        };
+        let buffer_infos = [buffer_info];
+        let ubo_write = vk::WriteDescriptorSet::default()
+            .descriptor_type(vk::DescriptorType::UNIFORM_BUFFER)
+            .buffer_info(&buffer_infos)
+            .dst_set(descriptor_set)
+            .dst_binding(Shading::PARAMS_BINDING)
+            .dst_array_element(0);
+        unsafe {
+            self.gpu
+                .device_context
+                .device
+                .update_descriptor_sets(&[ubo_write], &[]);
         }
+    }
 
-        Some((pipeline, properties))
+    // The frame's copy of the material params brought up to date, None without params. Pushed
+    // with the draw when the material set is, see `GPUPipeline::push_descriptors`.
+    pub fn update_material_params(
+        &self,
+        handle: &AssetHandle<Material>,
+        frame_index: usize,
+    ) -> Option<vk::DescriptorBufferInfo> {
+        let assets = self.assets.borrow();
+        let material = assets.load(handle)?;
+        if material.shading.params.is_empty() {
+            return None;
+        }
+        let size = material.shading.param_layout().1 as vk::DeviceSize;
+
+        let mut material_params_pool = self.material_params_pool.borrow_mut();
+        let params = material_params_pool
+            .entry(handle.id)
+            .or_insert_with(|| GPUMaterialParams::new(&self.gpu, size));
+        // the shading was swapped for one with another block
+        if params.size() != size {
+            self.gpu.wait_idle();
+            params.drop(&self.gpu);
+            *params = GPUMaterialParams::new(&self.gpu, size);
+        }
+        Some(params.update(frame_index, material))
     }
 
     pub fn get_geom(&mut self, handle: &AssetHandle<Geom>) -> Option<GPUGeom> {
@@ -94,18 +589,66 @@ impl GPUAssets {
         match geom_pool.get(&handle.id) {
             None => {
                 let assets = self.assets.borrow();
-                let geom = assets.load(&handle)?;
-                let geom_gpu = GPUGeom::new(&self.gpu, geom);
-
-                geom_pool.insert(handle.id, geom_gpu)
+                let geom = assets.load(handle)?;
+                let (geom_gpu, allocation) = self.geom_arena.allocate(geom);
+                geom_pool.insert(handle.id, (geom_gpu, allocation));
+                Some(geom_gpu)
             }
-            Some(geom) => Some(geom.to_owned()),
+            Some((geom, _)) => Some(geom.to_owned()),
+        }
+    }
+
+    // The asset's vertices were swapped, e.g. by a hot reload, its next use uploads them again.
+    // Frames in flight may still draw the old ranges, they are freed through the deletion queue.
+    pub fn reload_geom(&self, handle: &AssetHandle<Geom>) {
+        let Some((_, allocation)) = self.geom_pool.borrow_mut().remove(&handle.id) else {
+            return;
+        };
+        self.geom_arena.free(allocation);
+    }
+
+    // The copy of the frame in flight, updated with what was written since it was last drawn.
+    pub fn get_dynamic_geom(
+        &self,
+        handle: &AssetHandle<DynamicGeom>,
+        frame_index: usize,
+    ) -> Option<GPUGeom> {
+        if let Some(geom) = self.replaced_geoms.borrow().get(&handle.id) {
+            return Some(*geom);
+        }
+        let mut assets = self.assets.borrow_mut();
+        let geom = assets.load_mut(handle)?;
+        let mut dynamic_geom_pool = self.dynamic_geom_pool.borrow_mut();
+        let gpu_geom = dynamic_geom_pool
+            .entry(handle.id)
+            .or_insert_with(GPUDynamicGeom::new);
+        Some(gpu_geom.update(&self.gpu, frame_index, geom))
+    }
+
+    // Draws buffers written elsewhere for the handle, e.g. lines expanded by a compute pass. The
+    // caller keeps owning them, the CPU side geometry then only gives the bounds for culling.
+    pub fn replace_dynamic_geom(&self, handle: &AssetHandle<DynamicGeom>, geom: GPUGeom) {
+        self.replaced_geoms.borrow_mut().insert(handle.id, geom);
+    }
+
+    pub fn get_render_geom(&mut self, geom: &RenderGeom, frame_index: usize) -> Option<GPUGeom> {
+        match geom {
+            RenderGeom::Static(handle) => self.get_geom(handle),
+            RenderGeom::Dynamic(handle) => self.get_dynamic_geom(handle, frame_index),
         }
     }
 }
 
 impl Drop for GPUAssets {
     fn drop(&mut self) {
+        self.pending_pipelines
+            .borrow_mut()
+            .drain()
+            .for_each(|(_, (mut pipeline, thread))| {
+                pipeline.set_built(thread.join().expect("failed to warm up pipeline!"));
+                pipeline.drop(&self.gpu);
+            });
+
         self.pipeline_pool
             .borrow_mut()
             .values_mut()
@@ -114,14 +657,24 @@ impl Drop for GPUAssets {
                     .for_each(|pipeline| pipeline.drop(&self.gpu))
             });
 
-        self.geom_pool
+        self.material_params_pool
+            .borrow_mut()
+            .values_mut()
+            .for_each(|params| params.drop(&self.gpu));
+
+        self.dynamic_geom_pool
             .borrow_mut()
             .values_mut()
             .for_each(|geom| geom.drop(&self.gpu));
 
+        self.texture_view_pool
+            .borrow_mut()
+            .values()
+            .for_each(|view| self.gpu.destroy_texture_view(view.texture));
+
         self.texture_pool
             .borrow_mut()
             .values_mut()
-            .for_each(|tex| tex.drop(&self.gpu));
+            .for_each(|pooled| pooled.texture.drop(&self.gpu));
     }
 }
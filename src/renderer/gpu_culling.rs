@@ -0,0 +1,359 @@
+use super::forward_renderer::ObjectData;
+use super::gpu_geom::GPUGeom;
+use super::RenderObject;
+use crate::assets::Assets;
+use crate::gpu::{Allocation, ComputePipeline, ComputeReader, GPU};
+use crate::math::{Frustum, Mat4};
+use ash::vk;
+use std::cell::{Cell, RefCell};
+use std::io;
+use std::mem::size_of;
+use std::rc::Rc;
+
+const INDIRECT_CULL_SHADER: &str = "indirect_cull.spv";
+// Keep in sync with @workgroup_size in indirect_cull.wgsl
+const WORKGROUP_SIZE: u32 = 64;
+
+// Keep in sync with CullObject in indirect_cull.wgsl
+#[repr(C)]
+#[derive(Copy, Clone)]
+struct CullObject {
+    sphere: [f32; 4],
+    slot: u32,
+    batch: u32,
+    first: u32,
+    _pad: u32,
+}
+
+// Keep in sync with CullParams in indirect_cull.wgsl
+#[repr(C)]
+#[derive(Copy, Clone)]
+struct CullParams {
+    planes: [[f32; 4]; 6],
+    count: u32,
+    _pad: [u32; 3],
+}
+
+// A batch's draw command and where its visible list is, see `GPUCulling::push_batch`.
+#[derive(Debug, Copy, Clone)]
+pub struct IndirectBatch {
+    pub commands: vk::Buffer,
+    pub command_offset: vk::DeviceSize,
+    // dynamic offset of the visible list on the draw set
+    pub visible_offset: u32,
+}
+
+struct FrameBuffers {
+    // `ObjectData` of every object, read by the vertex shaders
+    objects: (vk::Buffer, Allocation),
+    culls: (vk::Buffer, Allocation),
+    // a `vk::DrawIndexedIndirectCommand` per batch
+    commands: (vk::Buffer, Allocation),
+    // slots of the visible objects, batch after batch at the storage offset alignment
+    visible: (vk::Buffer, Allocation),
+    // in objects
+    capacity: usize,
+    // in batches
+    batch_capacity: usize,
+}
+
+// The draws of shadings with `Shading::indirect`, batched by material and geometry. The CPU
+// writes every object of the frame and a draw command without instances per batch, a compute
+// pass tests the objects against the frustum and appends the visible ones to their batch, which
+// is then a single `vkCmdDrawIndexedIndirect` of as many instances. The vertex shaders read their
+// `ObjectData` from the visible list on OBJECT_SET, see simple_indirect.wgsl. A step towards
+// GPU driven rendering, the batches still bind their own vertex and index buffers.
+pub struct GPUCulling {
+    gpu: Rc<GPU>,
+
+    // set OBJECT_SET of the indirect pipelines
+    pub descriptor_set_layout: vk::DescriptorSetLayout,
+    descriptor_sets: Vec<vk::DescriptorSet>,
+    pipeline: ComputePipeline,
+    cull_sets: Vec<vk::DescriptorSet>,
+    frames: RefCell<Vec<FrameBuffers>>,
+    // of the frame being drawn
+    objects: Cell<usize>,
+    batches: Cell<usize>,
+    visible: Cell<usize>,
+    // in visible list entries
+    alignment: usize,
+}
+
+impl GPUCulling {
+    pub fn new(gpu: &Rc<GPU>, frames_in_flight: u32) -> Self {
+        let descriptor_set_layout = gpu.create_descriptor_set_layout(&vec![
+            vk::DescriptorSetLayoutBinding {
+                binding: 0,
+                descriptor_type: vk::DescriptorType::STORAGE_BUFFER,
+                descriptor_count: 1,
+                stage_flags: vk::ShaderStageFlags::ALL_GRAPHICS,
+                ..Default::default()
+            },
+            vk::DescriptorSetLayoutBinding {
+                binding: 1,
+                descriptor_type: vk::DescriptorType::STORAGE_BUFFER_DYNAMIC,
+                descriptor_count: 1,
+                stage_flags: vk::ShaderStageFlags::ALL_GRAPHICS,
+                ..Default::default()
+            },
+        ]);
+        let descriptor_sets =
+            gpu.create_descriptor_sets(&vec![descriptor_set_layout; frames_in_flight as usize]);
+
+        let data = Assets::load_raw(INDIRECT_CULL_SHADER).unwrap();
+        let mut buffer = io::Cursor::new(&data);
+        let shader_code = ash::util::read_spv(&mut buffer).unwrap();
+        let pipeline = ComputePipeline::new(
+            gpu,
+            &shader_code,
+            "cs",
+            &[vk::DescriptorType::STORAGE_BUFFER; 3],
+            size_of::<CullParams>() as u32,
+        );
+        gpu.debug_names.set_name(pipeline.pipeline, "indirect cull");
+        let cull_sets = pipeline.create_sets(gpu, frames_in_flight as usize);
+
+        let alignment = gpu
+            .device_context
+            .physical_device_properties
+            .limits
+            .min_storage_buffer_offset_alignment as usize;
+
+        let gpu_culling = Self {
+            gpu: Rc::clone(gpu),
+            descriptor_set_layout,
+            descriptor_sets,
+            pipeline,
+            cull_sets,
+            frames: RefCell::new(vec![]),
+            objects: Cell::new(0),
+            batches: Cell::new(0),
+            visible: Cell::new(0),
+            alignment: (alignment / size_of::<u32>()).max(1),
+        };
+        let frames = (0..frames_in_flight as usize)
+            .map(|frame_index| gpu_culling.create_frame(frame_index, 64, 16))
+            .collect();
+        *gpu_culling.frames.borrow_mut() = frames;
+        gpu_culling
+    }
+
+    pub fn get_descriptor_set(&self, frame_index: usize) -> vk::DescriptorSet {
+        self.descriptor_sets[frame_index]
+    }
+
+    // Starts the frame over, with room for `objects` in `batches`. The frame's previous
+    // submission must be done.
+    pub fn begin_frame(&self, frame_index: usize, objects: usize, batches: usize) {
+        self.objects.set(0);
+        self.batches.set(0);
+        self.visible.set(0);
+        let mut frames = self.frames.borrow_mut();
+        let frame = &frames[frame_index];
+        if objects <= frame.capacity && batches <= frame.batch_capacity {
+            return;
+        }
+
+        let capacity = objects.next_power_of_two().max(frame.capacity);
+        let batch_capacity = batches.next_power_of_two().max(frame.batch_capacity);
+        let frame = self.create_frame(frame_index, capacity, batch_capacity);
+        let old = std::mem::replace(&mut frames[frame_index], frame);
+        unsafe {
+            let device_context = &self.gpu.device_context;
+            for (buffer, memory) in [old.objects, old.culls, old.commands, old.visible] {
+                device_context.destroy_buffer(buffer, memory);
+            }
+        }
+    }
+
+    // Writes the objects and a draw command of the geom without instances, the culling pass
+    // adds the visible ones.
+    pub fn push_batch(
+        &self,
+        frame_index: usize,
+        geom: &GPUGeom,
+        objects: &[&RenderObject],
+    ) -> IndirectBatch {
+        let frames = self.frames.borrow();
+        let frame = &frames[frame_index];
+        let (first_slot, batch, first) =
+            (self.objects.get(), self.batches.get(), self.visible.get());
+        assert!(
+            first_slot + objects.len() <= frame.capacity && batch < frame.batch_capacity,
+            "indirect batches out of space!"
+        );
+
+        unsafe {
+            let object_data = frame.objects.1.mapped as *mut ObjectData;
+            let culls = frame.culls.1.mapped as *mut CullObject;
+            for (index, object) in objects.iter().enumerate() {
+                let slot = first_slot + index;
+                let bounds = geom.bounds.transform(&object.model);
+                let (center, radius) = (bounds.center(), bounds.size().len() * 0.5);
+                object_data.add(slot).write(ObjectData::new(object));
+                culls.add(slot).write(CullObject {
+                    sphere: [center.x, center.y, center.z, radius],
+                    slot: slot as u32,
+                    batch: batch as u32,
+                    first: first as u32,
+                    _pad: 0,
+                });
+            }
+
+            let command = vk::DrawIndexedIndirectCommand {
+                index_count: geom.indices_length as u32,
+                instance_count: 0,
+                first_index: geom.first_index,
+                vertex_offset: geom.vertex_offset,
+                first_instance: 0,
+            };
+            (frame.commands.1.mapped as *mut vk::DrawIndexedIndirectCommand)
+                .add(batch)
+                .write(command);
+        }
+
+        self.objects.set(first_slot + objects.len());
+        self.batches.set(batch + 1);
+        self.visible
+            .set(first + objects.len().next_multiple_of(self.alignment));
+        IndirectBatch {
+            commands: frame.commands.0,
+            command_offset: (batch * size_of::<vk::DrawIndexedIndirectCommand>()) as vk::DeviceSize,
+            visible_offset: (first * size_of::<u32>()) as u32,
+        }
+    }
+
+    // Records the culling of the batches pushed so far, outside of a render pass and before the
+    // draws. `culling` is the view projection the frustum comes from.
+    pub fn cull(&self, command_buffer: vk::CommandBuffer, frame_index: usize, culling: &Mat4) {
+        let count = self.objects.get() as u32;
+        if count == 0 {
+            return;
+        }
+
+        let frustum = Frustum::from_matrix(culling);
+        let params = CullParams {
+            planes: frustum
+                .planes
+                .map(|plane| [plane.x, plane.y, plane.z, plane.w]),
+            count,
+            _pad: [0; 3],
+        };
+        let params = unsafe {
+            std::slice::from_raw_parts(
+                (&params as *const CullParams) as *const u8,
+                size_of::<CullParams>(),
+            )
+        };
+        self.pipeline.dispatch(
+            &self.gpu,
+            command_buffer,
+            self.cull_sets[frame_index],
+            params,
+            [count.div_ceil(WORKGROUP_SIZE), 1, 1],
+        );
+        ComputePipeline::barrier(&self.gpu, command_buffer, ComputeReader::Indirect);
+    }
+
+    fn create_frame(
+        &self,
+        frame_index: usize,
+        capacity: usize,
+        batch_capacity: usize,
+    ) -> FrameBuffers {
+        let create_buffer = |size: usize, usage: vk::BufferUsageFlags| unsafe {
+            // host visible allocations come persistently mapped
+            self.gpu.device_context.create_buffer(
+                size as vk::DeviceSize,
+                vk::BufferUsageFlags::STORAGE_BUFFER | usage,
+                vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT,
+            )
+        };
+        // the lists are padded to the alignment, a batch's list is at most every object, bound
+        // from any batch start the range stays inside the buffer
+        let visible_capacity = capacity + batch_capacity * self.alignment;
+        let frame = FrameBuffers {
+            objects: create_buffer(
+                capacity * size_of::<ObjectData>(),
+                vk::BufferUsageFlags::empty(),
+            ),
+            culls: create_buffer(
+                capacity * size_of::<CullObject>(),
+                vk::BufferUsageFlags::empty(),
+            ),
+            commands: create_buffer(
+                batch_capacity * size_of::<vk::DrawIndexedIndirectCommand>(),
+                vk::BufferUsageFlags::INDIRECT_BUFFER,
+            ),
+            visible: create_buffer(
+                (visible_capacity + capacity) * size_of::<u32>(),
+                vk::BufferUsageFlags::empty(),
+            ),
+            capacity,
+            batch_capacity,
+        };
+
+        let cull_set = self.cull_sets[frame_index];
+        for (binding, (buffer, _)) in [&frame.culls, &frame.commands, &frame.visible]
+            .into_iter()
+            .enumerate()
+        {
+            self.pipeline.write_buffer(
+                &self.gpu,
+                cull_set,
+                binding as u32,
+                *buffer,
+                vk::WHOLE_SIZE,
+            );
+        }
+
+        let object_infos = [vk::DescriptorBufferInfo {
+            buffer: frame.objects.0,
+            offset: 0,
+            range: vk::WHOLE_SIZE,
+        }];
+        let visible_infos = [vk::DescriptorBufferInfo {
+            buffer: frame.visible.0,
+            offset: 0,
+            range: (capacity * size_of::<u32>()) as vk::DeviceSize,
+        }];
+        let writes = [
+            vk::WriteDescriptorSet::default()
+                .descriptor_type(vk::DescriptorType::STORAGE_BUFFER)
+                .buffer_info(&object_infos)
+                .dst_set(self.descriptor_sets[frame_index])
+                .dst_binding(0),
+            vk::WriteDescriptorSet::default()
+                .descriptor_type(vk::DescriptorType::STORAGE_BUFFER_DYNAMIC)
+                .buffer_info(&visible_infos)
+                .dst_set(self.descriptor_sets[frame_index])
+                .dst_binding(1),
+        ];
+        unsafe {
+            self.gpu
+                .device_context
+                .device
+                .update_descriptor_sets(&writes, &[]);
+        }
+        frame
+    }
+}
+
+impl Drop for GPUCulling {
+    fn drop(&mut self) {
+        self.pipeline.destroy(&self.gpu);
+        unsafe {
+            let device_context = &self.gpu.device_context;
+            for frame in self.frames.take() {
+                for (buffer, memory) in [frame.objects, frame.culls, frame.commands, frame.visible]
+                {
+                    device_context.destroy_buffer(buffer, memory);
+                }
+            }
+            device_context
+                .device
+                .destroy_descriptor_set_layout(self.descriptor_set_layout, None);
+        }
+    }
+}
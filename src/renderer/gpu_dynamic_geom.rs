@@ -0,0 +1,129 @@
+use crate::assets::DynamicGeom;
+use crate::gpu::{VkBuffer, GPU};
+use crate::renderer::gpu_geom::GPUGeom;
+use crate::renderer::vertex::Vertex;
+use crate::renderer::ForwardRenderer;
+use ash::vk;
+use std::mem::size_of;
+use std::ops::Range;
+
+// Host visible buffers of one frame in flight, written in place.
+struct DynamicCopy {
+    vertex_buffer: VkBuffer,
+    index_buffer: VkBuffer,
+    vertex_capacity: usize,
+    index_capacity: usize,
+}
+
+// The GPU side of a `DynamicGeom`, a copy per frame in flight. The copy of a frame is only
+// written once its fence was waited on, so no frame ever draws a half written copy.
+pub struct GPUDynamicGeom {
+    copies: Vec<Option<DynamicCopy>>,
+}
+
+impl GPUDynamicGeom {
+    pub fn new() -> Self {
+        Self {
+            copies: (0..ForwardRenderer::FRAMES_IN_FLIGHT)
+                .map(|_| None)
+                .collect(),
+        }
+    }
+
+    // Brings the copy of the frame up to date and returns it for drawing.
+    pub fn update(&mut self, gpu: &GPU, frame_index: usize, geom: &mut DynamicGeom) -> GPUGeom {
+        let dirty = geom.take_dirty(frame_index);
+        let (vertices, indices) = (geom.vertices(), geom.indices());
+
+        let copy = &mut self.copies[frame_index];
+        let fits = copy.as_ref().is_some_and(|copy| {
+            vertices.len() <= copy.vertex_capacity && indices.len() <= copy.index_capacity
+        });
+        if !fits {
+            // grown by powers of two, a geometry growing every frame doesn't reallocate every frame
+            if let Some(copy) = copy.take() {
+                Self::destroy_copy(gpu, copy);
+            }
+            let vertex_capacity = vertices.len().max(1).next_power_of_two();
+            let index_capacity = indices.len().max(1).next_power_of_two();
+            let new_copy = unsafe {
+                DynamicCopy {
+                    vertex_buffer: Self::create_buffer::<Vertex>(
+                        gpu,
+                        vertex_capacity,
+                        vk::BufferUsageFlags::VERTEX_BUFFER,
+                    ),
+                    index_buffer: Self::create_buffer::<u32>(
+                        gpu,
+                        index_capacity,
+                        vk::BufferUsageFlags::INDEX_BUFFER,
+                    ),
+                    vertex_capacity,
+                    index_capacity,
+                }
+            };
+            // a new copy has none of the data
+            Self::write(&new_copy.vertex_buffer, vertices, 0..vertices.len());
+            Self::write(&new_copy.index_buffer, indices, 0..indices.len());
+            *copy = Some(new_copy);
+        } else {
+            let copy = copy.as_ref().unwrap();
+            if let Some(range) = dirty.vertices {
+                Self::write(&copy.vertex_buffer, vertices, range);
+            }
+            if let Some(range) = dirty.indices {
+                Self::write(&copy.index_buffer, indices, range);
+            }
+        }
+
+        let copy = self.copies[frame_index].as_ref().unwrap();
+        GPUGeom {
+            vertex_buffer: copy.vertex_buffer,
+            index_buffer: copy.index_buffer,
+            first_index: 0,
+            vertex_offset: 0,
+            indices_length: geom.indices().len(),
+            bounds: geom.bounds(),
+        }
+    }
+
+    pub fn drop(&mut self, gpu: &GPU) {
+        for copy in self.copies.iter_mut().filter_map(Option::take) {
+            Self::destroy_copy(gpu, copy);
+        }
+    }
+
+    fn write<T: Copy>(buffer: &VkBuffer, data: &[T], range: Range<usize>) {
+        if range.is_empty() {
+            return;
+        }
+        unsafe {
+            let source = &data[range.clone()];
+            let target = (buffer.memory.mapped as *mut T).add(range.start);
+            std::ptr::copy_nonoverlapping(source.as_ptr(), target, source.len());
+        }
+    }
+
+    unsafe fn create_buffer<T>(
+        gpu: &GPU,
+        capacity: usize,
+        usage: vk::BufferUsageFlags,
+    ) -> VkBuffer {
+        // host visible allocations come persistently mapped, coherent so no flush is needed
+        // read as storage by compute passes too, e.g. the normal debugger
+        let (buffer, memory) = gpu.device_context.create_buffer(
+            (capacity * size_of::<T>()) as vk::DeviceSize,
+            vk::BufferUsageFlags::STORAGE_BUFFER | usage,
+            vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT,
+        );
+        VkBuffer { buffer, memory }
+    }
+
+    fn destroy_copy(gpu: &GPU, copy: DynamicCopy) {
+        unsafe {
+            let device_context = &gpu.device_context;
+            device_context.destroy_buffer(copy.vertex_buffer.buffer, copy.vertex_buffer.memory);
+            device_context.destroy_buffer(copy.index_buffer.buffer, copy.index_buffer.memory);
+        }
+    }
+}
@@ -0,0 +1,59 @@
+use crate::assets::Material;
+use crate::gpu::{Allocation, GPU};
+use crate::renderer::ForwardRenderer;
+use ash::vk;
+
+// The GPU side of a material's parameter block, a uniform buffer per frame in flight. A frame's
+// copy is rewritten when the material's params changed since it was last drawn, so setting a
+// param never touches a buffer the GPU may still be reading.
+pub struct GPUMaterialParams {
+    buffers: Vec<(vk::Buffer, Allocation)>,
+    size: vk::DeviceSize,
+    // `Material::params_version` each copy was written with
+    versions: Vec<Option<u64>>,
+}
+
+impl GPUMaterialParams {
+    pub fn new(gpu: &GPU, size: vk::DeviceSize) -> Self {
+        let frames = ForwardRenderer::FRAMES_IN_FLIGHT as usize;
+        Self {
+            buffers: (0..frames)
+                .map(|_| gpu.create_mapped_buffers(size))
+                .collect(),
+            size,
+            versions: vec![None; frames],
+        }
+    }
+
+    pub fn size(&self) -> vk::DeviceSize {
+        self.size
+    }
+
+    // Brings the frame's copy up to date, PARAMS_BINDING of the material set points at it.
+    pub fn update(&mut self, frame_index: usize, material: &Material) -> vk::DescriptorBufferInfo {
+        let (buffer, memory) = self.buffers[frame_index];
+        let version = Some(material.params_version());
+        if self.versions[frame_index] != version {
+            let bytes = material.param_bytes();
+            let length = bytes.len().min(self.size as usize);
+            unsafe {
+                std::ptr::copy_nonoverlapping(bytes.as_ptr(), memory.mapped as *mut u8, length);
+            }
+            self.versions[frame_index] = version;
+        }
+
+        vk::DescriptorBufferInfo {
+            buffer,
+            offset: 0,
+            range: self.size,
+        }
+    }
+
+    pub fn drop(&mut self, gpu: &GPU) {
+        unsafe {
+            for (buffer, memory) in self.buffers.drain(..) {
+                gpu.device_context.destroy_buffer(buffer, memory);
+            }
+        }
+    }
+}
@@ -1,25 +1,95 @@
 use crate::assets::{Assets, Material};
-use crate::gpu::GPU;
+use crate::error_overlay;
+use crate::gpu::{RawResource, Rhi, VkPipeline, GPU};
 use crate::renderer::forward_renderer::ObjectData;
+use crate::renderer::object_buffer::OBJECT_SET;
 use crate::renderer::vertex::Vertex;
-use crate::renderer::{ForwardRenderer, Shading};
+use crate::renderer::{BlockLayout, ForwardRenderer, ShaderCompiler, Shading};
 use ash::vk;
-use std::ffi::CStr;
+use std::ffi::CString;
 use std::io;
 
+// every implementation of VK_KHR_push_descriptor allows at least as many in a set
+const MAX_PUSH_DESCRIPTORS: u32 = 32;
+
+const GRAPHICS_STAGES: [vk::ShaderStageFlags; 5] = [
+    vk::ShaderStageFlags::VERTEX,
+    vk::ShaderStageFlags::TESSELLATION_CONTROL,
+    vk::ShaderStageFlags::TESSELLATION_EVALUATION,
+    vk::ShaderStageFlags::GEOMETRY,
+    vk::ShaderStageFlags::FRAGMENT,
+];
+
 #[derive(Debug, Copy, Clone)]
 pub struct GPUPipeline {
     pub descriptor_set_layout: vk::DescriptorSetLayout,
 
-    pub shader_module: vk::ShaderModule,
-    pub pipeline: vk::Pipeline,
-    pub pipeline_layout: vk::PipelineLayout,
+    pub shader_modules: [Option<vk::ShaderModule>; 5],
+    pub pipeline: VkPipeline,
+    // depth only, drawn in the prepass subpass of a mobile friendly forward pass
+    pub depth_prepass: Option<VkPipeline>,
+    // false when `ObjectData` is over the push constant budget, it comes from the object buffer then
+    pub push_constants: bool,
+    // binds the payload buffer on PAYLOAD_SET, see `Shading::object_payload`
+    pub object_payload: bool,
+    // drawn in the batches of `GPUCulling`, see `Shading::indirect`
+    pub indirect: bool,
+    // drawn in the sorted transparent phase, see `Shading::transparent`
+    pub transparent: bool,
+    // the material set is pushed with each draw and has no descriptor sets, on devices with
+    // VK_KHR_push_descriptor
+    pub push_descriptors: bool,
+    // of the shading, for `DebugNames`
+    pub name: &'static str,
 
     descriptor_sets: [Option<vk::DescriptorSet>; 5],
 }
 
 impl GPUPipeline {
     pub fn new(gpu: &GPU, material: &Material, renderer: &ForwardRenderer) -> Self {
+        let (mut pipeline, desc) = Self::prepare(gpu, material, renderer);
+        pipeline.set_built(desc.build());
+        pipeline.set_debug_names(gpu);
+        pipeline
+    }
+
+    // Once built, the pipelines and the objects made for them are labelled with the shading name.
+    pub fn set_debug_names(&self, gpu: &GPU) {
+        let debug_names = &gpu.debug_names;
+        if !debug_names.is_enabled() {
+            return;
+        }
+        debug_names.set_name(self.pipeline.pipeline, self.name);
+        debug_names.set_name(self.pipeline.layout, self.name);
+        debug_names.set_name(self.descriptor_set_layout, self.name);
+        if let Some(depth_prepass) = &self.depth_prepass {
+            debug_names.set_name(
+                depth_prepass.pipeline,
+                &format!("{} depth prepass", self.name),
+            );
+        }
+        self.descriptor_sets
+            .iter()
+            .flatten()
+            .for_each(|set| debug_names.set_name(*set, self.name));
+    }
+
+    // What `PipelineDesc::build` returned.
+    pub fn set_built(&mut self, (pipeline, depth_prepass): (vk::Pipeline, Option<vk::Pipeline>)) {
+        self.pipeline.pipeline = pipeline;
+        self.depth_prepass = depth_prepass.map(|pipeline| VkPipeline {
+            pipeline,
+            layout: self.pipeline.layout,
+        });
+    }
+
+    // Everything but the vk::Pipeline itself, the slow part that `PipelineDesc::build` compiles,
+    // possibly on another thread.
+    pub fn prepare(
+        gpu: &GPU,
+        material: &Material,
+        renderer: &ForwardRenderer,
+    ) -> (Self, PipelineDesc) {
         // The Vulkan SDK includes libshaderc, which is a library to compile GLSL code to SPIR-V from within your program.
         // https://github.com/google/shaderc
         // little endian
@@ -30,69 +100,361 @@ impl GPUPipeline {
 
         // let vert_shader_module = device.create_shader_module(&vert_shader_code);
         // let frag_shader_module = device.create_shader_module(&frag_shader_code);
-        let data = Assets::load_raw(material.shading.path).unwrap();
-        let mut buffer = io::Cursor::new(&data);
-        let shader_code = ash::util::read_spv(&mut buffer).unwrap();
-        let shader_module = gpu.create_shader_module(&shader_code);
+        if !material.shading.has_stage(vk::ShaderStageFlags::VERTEX)
+            || !material.shading.has_stage(vk::ShaderStageFlags::FRAGMENT)
+        {
+            panic!(
+                "shading {} requires both vertex and fragment stages!",
+                material.shading.name
+            );
+        }
+
+        let indirect = material.shading.indirect;
+        if indirect && material.shading.object_payload {
+            panic!(
+                "shading {} can't read an object payload when drawn indirect!",
+                material.shading.name
+            );
+        }
+
+        let push_constants = !indirect && gpu.fits_push_constants(size_of::<ObjectData>());
+        if !push_constants && !indirect {
+            log::info!(
+                "ObjectData of {} is over the push constant budget, using a uniform buffer",
+                material.shading.name
+            );
+        }
+
+        let blocks = BlockLayout::material_blocks(&material.shading, push_constants);
+        let mut shader_modules = [None; 5];
+        let mut loaded_modules: Vec<(&str, vk::ShaderModule)> = vec![];
+        let mut stages: Vec<(vk::ShaderStageFlags, vk::ShaderModule, CString)> = vec![];
+
+        // Stages are ordered the way the pipeline expects them, regardless of declaration order.
+        for stage_flag in GRAPHICS_STAGES {
+            let Some(stage) = material.shading.get_stage(stage_flag) else {
+                continue;
+            };
+            // Optional stages are dropped on devices without the feature, shaders are expected
+            // to still produce a valid (non displaced / non expanded) result from vert + frag.
+            if !gpu.device_context.is_shader_stage_supported(stage.stage) {
+                log::warn!(
+                    "shader stage {:?} of {} is not supported by the device, skipped",
+                    stage.stage,
+                    material.shading.name
+                );
+                continue;
+            }
 
-        let descriptor_set_layout = gpu.create_descriptor_set_layout(&material.shading.bindings);
-        let (pipeline, pipeline_layout) = Self::create_pipeline(
+            let shader_module = match loaded_modules.iter().find(|(path, _)| *path == stage.path) {
+                Some((_, shader_module)) => *shader_module,
+                None => {
+                    let shader_code = match &stage.code {
+                        Some(code) => code.to_vec(),
+                        None => {
+                            let data = Assets::load_raw(stage.path).unwrap();
+                            let mut buffer = io::Cursor::new(&data);
+                            ash::util::read_spv(&mut buffer).unwrap()
+                        }
+                    };
+                    let shader_code = if push_constants || indirect {
+                        shader_code
+                    } else {
+                        ShaderCompiler::push_constants_to_uniform(&shader_code, OBJECT_SET, 0)
+                            .unwrap_or_else(|error| {
+                                panic!(
+                                    "failed to move the push constants of {} to a uniform buffer!\n{}",
+                                    stage.path, error
+                                )
+                            })
+                    };
+                    // a block laid out differently than the struct uploaded into it renders
+                    // garbage without any Vulkan validation error
+                    let source =
+                        format!("shader layout {} of {}", stage.path, material.shading.name);
+                    match BlockLayout::validate(&shader_code, &blocks) {
+                        Ok(()) => error_overlay::resolve(&source),
+                        Err(error) => error_overlay::report(source, error),
+                    }
+                    let shader_module = gpu.create_shader_module(&shader_code);
+                    gpu.debug_names.set_name(shader_module, stage.path);
+
+                    shader_modules[loaded_modules.len()] = Some(shader_module);
+                    loaded_modules.push((stage.path, shader_module));
+                    shader_module
+                }
+            };
+
+            let entry = CString::new(stage.entry).expect("invalid shader entry point name!");
+            stages.push((stage.stage, shader_module, entry));
+        }
+
+        let bindings = &material.shading.bindings;
+        let push_descriptors = gpu.device_context.push_descriptor.is_some()
+            && bindings
+                .iter()
+                .map(|binding| binding.descriptor_count)
+                .sum::<u32>()
+                <= MAX_PUSH_DESCRIPTORS;
+        let descriptor_set_layout = match push_descriptors {
+            true => gpu.create_push_descriptor_set_layout(bindings),
+            false => gpu.create_descriptor_set_layout(bindings),
+        };
+        let object_payload = material.shading.object_payload;
+        let layout = Self::create_pipeline_layout(
             gpu,
             renderer,
-            &material.shading,
-            shader_module,
             descriptor_set_layout,
+            push_constants,
+            object_payload,
+            indirect,
         );
+        let desc = PipelineDesc::new(gpu, renderer, &material.shading, stages, layout);
 
         let mut descriptor_sets = [None; 5];
-        gpu.create_descriptor_sets(&vec![
-            descriptor_set_layout;
-            ForwardRenderer::FRAMES_IN_FLIGHT.min(5) as usize
-        ])
-        .into_iter()
-        .enumerate()
-        .for_each(|(index, set)| {
-            descriptor_sets[index] = Some(set);
-        });
+        if !push_descriptors {
+            gpu.create_descriptor_sets(&vec![
+                descriptor_set_layout;
+                ForwardRenderer::FRAMES_IN_FLIGHT.min(5) as usize
+            ])
+            .into_iter()
+            .enumerate()
+            .for_each(|(index, set)| {
+                descriptor_sets[index] = Some(set);
+            });
+        }
 
-        Self {
+        let pipeline = Self {
             descriptor_set_layout,
-            shader_module,
-            pipeline,
-            pipeline_layout,
+            shader_modules,
+            pipeline: VkPipeline {
+                pipeline: vk::Pipeline::null(),
+                layout,
+            },
+            depth_prepass: None,
+            push_constants,
+            object_payload,
+            indirect,
+            transparent: material.shading.transparent,
+            push_descriptors,
+            name: material.shading.name,
             descriptor_sets,
-        }
+        };
+        (pipeline, desc)
     }
 
+    // Not with `push_descriptors`.
     pub fn get_descriptor_set(&self, frame_index: usize) -> vk::DescriptorSet {
         self.descriptor_sets[frame_index].unwrap()
     }
 
-    fn create_pipeline(
+    fn create_pipeline_layout(
         gpu: &GPU,
         renderer: &ForwardRenderer,
-        shading: &Shading,
-        shader_module: vk::ShaderModule,
         descriptor_set_layout: vk::DescriptorSetLayout,
-    ) -> (vk::Pipeline, vk::PipelineLayout) {
+        push_constants: bool,
+        object_payload: bool,
+        indirect: bool,
+    ) -> vk::PipelineLayout {
+        unsafe {
+            let mut push_constant_ranges = vec![];
+            let mut descriptor_set_layouts = vec![
+                renderer.camera_uniforms.descriptor_set_layout,
+                descriptor_set_layout,
+            ];
+            if push_constants {
+                push_constant_ranges.push(
+                    vk::PushConstantRange::default()
+                        .stage_flags(vk::ShaderStageFlags::ALL_GRAPHICS)
+                        .offset(0)
+                        .size(size_of::<ObjectData>() as u32),
+                );
+            }
+            // sets are numbered by position, the object buffer set stays in front of the payload
+            // even when the push constants don't need it
+            if indirect {
+                descriptor_set_layouts.push(renderer.gpu_culling.descriptor_set_layout);
+            } else if !push_constants || object_payload {
+                descriptor_set_layouts.push(renderer.object_buffer.descriptor_set_layout);
+            }
+            if object_payload {
+                descriptor_set_layouts.push(renderer.payload_buffer.descriptor_set_layout);
+            }
+            let layout_create_info = vk::PipelineLayoutCreateInfo::default()
+                .set_layouts(&descriptor_set_layouts)
+                .push_constant_ranges(&push_constant_ranges);
+
+            gpu.device_context
+                .device
+                .create_pipeline_layout(&layout_create_info, None)
+                .expect("failed to create pipeline layout!")
+        }
+    }
+
+    pub fn drop(&mut self, gpu: &GPU) {
+        gpu.destroy_pipeline(self.pipeline);
+        if let Some(depth_prepass) = self.depth_prepass {
+            gpu.destroy_pipeline(depth_prepass);
+        }
+        unsafe {
+            let device = &gpu.device_context.device;
+            device.destroy_descriptor_set_layout(self.descriptor_set_layout, None);
+            self.shader_modules
+                .iter()
+                .flatten()
+                .for_each(|&shader_module| device.destroy_shader_module(shader_module, None));
+        }
+        let descriptor_sets = self
+            .descriptor_sets
+            .iter()
+            .flatten()
+            .copied()
+            .collect::<Vec<_>>();
+        gpu.free_descriptor_sets(&descriptor_sets);
+    }
+
+    // Like `drop`, through the deletion queue as frames in flight may still draw with it.
+    pub fn defer_drop(&mut self, gpu: &GPU) {
+        for pipeline in std::iter::once(self.pipeline).chain(self.depth_prepass) {
+            gpu.defer_destroy(RawResource::Pipeline(pipeline.pipeline));
+            gpu.defer_destroy(RawResource::PipelineLayout(pipeline.layout));
+        }
+        gpu.defer_destroy(RawResource::DescriptorSetLayout(self.descriptor_set_layout));
+        self.shader_modules
+            .iter()
+            .flatten()
+            .for_each(|&shader_module| gpu.defer_destroy(RawResource::ShaderModule(shader_module)));
+        let descriptor_sets = self
+            .descriptor_sets
+            .iter()
+            .flatten()
+            .copied()
+            .collect::<Vec<_>>();
+        gpu.free_descriptor_sets(&descriptor_sets);
+    }
+}
+
+// The inputs of vkCreateGraphicsPipelines, plain handles and values so it can be sent to a warm-up thread.
+pub struct PipelineDesc {
+    device: ash::Device,
+    pipeline_cache: vk::PipelineCache,
+    render_pass: vk::RenderPass,
+    subpass: u32,
+    layout: vk::PipelineLayout,
+    stages: Vec<(vk::ShaderStageFlags, vk::ShaderModule, CString)>,
+    topology: vk::PrimitiveTopology,
+    patch_control_points: u32,
+    samples: vk::SampleCountFlags,
+    sample_shading: bool,
+    depth_test: bool,
+    depth_write: bool,
+    depth_compare_op: vk::CompareOp,
+    alpha_to_coverage: bool,
+    transparent: bool,
+    // a depth only variant for subpass 0 is built too, the shading then only tests against it
+    depth_prepass: bool,
+}
+
+impl PipelineDesc {
+    fn new(
+        gpu: &GPU,
+        renderer: &ForwardRenderer,
+        shading: &Shading,
+        stages: Vec<(vk::ShaderStageFlags, vk::ShaderModule, CString)>,
+        layout: vk::PipelineLayout,
+    ) -> Self {
+        // MoltenVK: Metal has no triangle fans, the shading is drawn as a triangle list instead
+        // which only fits geometry indexed for one
+        let source = format!("topology of {}", shading.name);
+        let topology = if gpu.device_context.is_topology_supported(shading.topology) {
+            error_overlay::resolve(&source);
+            shading.topology
+        } else {
+            error_overlay::report(
+                source,
+                format!(
+                    "{:?} topology is not supported by the device, drawing a triangle list",
+                    shading.topology
+                ),
+            );
+            vk::PrimitiveTopology::TRIANGLE_LIST
+        };
+
+        // Alpha to coverage decides the coverage in the fragment shader, which a depth only pass
+        // doesn't run. Such shadings and ones not writing depth test against the prepass instead.
+        let mobile_friendly = renderer.is_mobile_friendly();
+        // Transparent ones never write depth, so they aren't in it either.
+        let depth_write = shading.depth_write && !shading.transparent;
+        let depth_prepass =
+            mobile_friendly && shading.depth_test && depth_write && !shading.alpha_to_coverage;
+
+        Self {
+            device: gpu.device_context.device.clone(),
+            pipeline_cache: gpu.pipeline_cache,
+            render_pass: renderer.render_pass,
+            subpass: renderer.shading_subpass(),
+            layout,
+            stages,
+            topology,
+            patch_control_points: shading.patch_control_points,
+            samples: renderer.msaa_samples(),
+            // nothing to shade per sample with MSAA off
+            sample_shading: gpu.device_context.is_sample_shading_supported()
+                && renderer.msaa_samples() != vk::SampleCountFlags::TYPE_1,
+            depth_test: shading.depth_test,
+            // the depth is read only in the shading subpass
+            depth_write: depth_write && !mobile_friendly,
+            depth_compare_op: if renderer.is_reverse_z() {
+                vk::CompareOp::GREATER
+            } else {
+                vk::CompareOp::LESS
+            },
+            // blending takes the alpha instead
+            alpha_to_coverage: shading.alpha_to_coverage && !shading.transparent,
+            transparent: shading.transparent,
+            depth_prepass,
+        }
+    }
+
+    // The shading pipeline, and the depth only one when the material is part of the prepass.
+    pub fn build(&self) -> (vk::Pipeline, Option<vk::Pipeline>) {
+        let pipeline = self.build_pipeline(false);
+        let depth_prepass = self.depth_prepass.then(|| self.build_pipeline(true));
+        (pipeline, depth_prepass)
+    }
+
+    // tolerates the shading rasterizing a depth a hair off the prepass one
+    fn or_equal(op: vk::CompareOp) -> vk::CompareOp {
+        match op {
+            vk::CompareOp::GREATER => vk::CompareOp::GREATER_OR_EQUAL,
+            vk::CompareOp::LESS => vk::CompareOp::LESS_OR_EQUAL,
+            op => op,
+        }
+    }
+
+    fn build_pipeline(&self, depth_only: bool) -> vk::Pipeline {
         unsafe {
-            let vert_shader_stage = vk::PipelineShaderStageCreateInfo::default()
-                .module(shader_module)
-                .stage(vk::ShaderStageFlags::VERTEX)
-                .name(CStr::from_bytes_with_nul_unchecked(b"vs\0"));
             // It allows you to specify values for shader constants. You can use a single shader module where its behavior can be configured
             // at pipeline creation by specifying different values for the constants used in it. This is more efficient than configuring
             // the shader using variables at render time, because the compiler can do optimizations like eliminating if statements that
             // depend on these values. If you don't have any constants like that, then you can set the member to nullptr,
             // which our struct initialization does automatically.
             // .specialization_info()
-
-            let frag_shader_stage = vk::PipelineShaderStageCreateInfo::default()
-                .module(shader_module)
-                .stage(vk::ShaderStageFlags::FRAGMENT)
-                .name(CStr::from_bytes_with_nul_unchecked(b"fs\0"));
-
-            let shader_stages = [vert_shader_stage, frag_shader_stage];
+            let shader_stages = self
+                .stages
+                .iter()
+                .filter(|(stage, _, _)| !depth_only || *stage != vk::ShaderStageFlags::FRAGMENT)
+                .map(|(stage, module, entry)| {
+                    vk::PipelineShaderStageCreateInfo::default()
+                        .module(*module)
+                        .stage(*stage)
+                        .name(entry.as_c_str())
+                })
+                .collect::<Vec<_>>();
+            let has_tessellation = self
+                .stages
+                .iter()
+                .any(|(stage, _, _)| *stage == vk::ShaderStageFlags::TESSELLATION_CONTROL);
 
             let input_bindings = [Vertex::get_binding_description()];
             let input_attributes = Vertex::get_attribute_descriptions();
@@ -102,7 +464,11 @@ impl GPUPipeline {
                 .vertex_attribute_descriptions(&input_attributes);
 
             let input_assembly_stage = vk::PipelineInputAssemblyStateCreateInfo::default()
-                .topology(vk::PrimitiveTopology::TRIANGLE_LIST)
+                .topology(if has_tessellation {
+                    vk::PrimitiveTopology::PATCH_LIST
+                } else {
+                    self.topology
+                })
                 // used with Indexed drawing + Triangle Fan/Strip topologies. This is more efficient than explicitly
                 // ending the current primitive and explicitly starting a new primitive of the same type.
                 // A special “index” indicates that the primitive should start over.
@@ -111,6 +477,9 @@ impl GPUPipeline {
                 // One Really Good use of Restart Enable is in Drawing Terrain Surfaces with Triangle Strips.
                 .primitive_restart_enable(false);
 
+            let tessellation_state = vk::PipelineTessellationStateCreateInfo::default()
+                .patch_control_points(self.patch_control_points);
+
             let dynamic_state = vk::PipelineDynamicStateCreateInfo::default()
                 .dynamic_states(&[vk::DynamicState::VIEWPORT, vk::DynamicState::SCISSOR]);
 
@@ -119,7 +488,12 @@ impl GPUPipeline {
                 .scissor_count(1);
 
             let rasterization_state = vk::PipelineRasterizationStateCreateInfo::default()
-                .cull_mode(vk::CullModeFlags::BACK)
+                .cull_mode(match self.topology {
+                    vk::PrimitiveTopology::POINT_LIST
+                    | vk::PrimitiveTopology::LINE_LIST
+                    | vk::PrimitiveTopology::LINE_STRIP => vk::CullModeFlags::NONE,
+                    _ => vk::CullModeFlags::BACK,
+                })
                 .front_face(vk::FrontFace::COUNTER_CLOCKWISE)
                 .polygon_mode(vk::PolygonMode::FILL)
                 .line_width(1.0)
@@ -131,38 +505,43 @@ impl GPUPipeline {
                 .depth_bias_constant_factor(0.0);
 
             let multisample = vk::PipelineMultisampleStateCreateInfo::default()
-                .sample_shading_enable(true)
+                // optional feature, and MoltenVK runs it without sampleRateInterpolationFunctions,
+                // so shaders must not rely on interpolateAtSample/interpolateAtOffset
+                .sample_shading_enable(self.sample_shading && !depth_only)
                 .min_sample_shading(0.2)
-                .rasterization_samples(gpu.device_context.msaa_samples)
+                .rasterization_samples(self.samples)
                 .sample_mask(&[])
-                .alpha_to_coverage_enable(false)
+                .alpha_to_coverage_enable(self.alpha_to_coverage)
                 .alpha_to_one_enable(false);
 
+            // straight alpha over what is behind, the scene alpha keeps the coverage so far
             let color_attachments = [vk::PipelineColorBlendAttachmentState {
-                blend_enable: false.into(),
+                blend_enable: self.transparent.into(),
                 src_color_blend_factor: vk::BlendFactor::SRC_ALPHA,
                 dst_color_blend_factor: vk::BlendFactor::ONE_MINUS_SRC_ALPHA,
                 color_blend_op: vk::BlendOp::ADD,
-                src_alpha_blend_factor: vk::BlendFactor::SRC_ALPHA,
+                src_alpha_blend_factor: vk::BlendFactor::ONE,
                 dst_alpha_blend_factor: vk::BlendFactor::ONE_MINUS_SRC_ALPHA,
                 alpha_blend_op: vk::BlendOp::ADD,
                 color_write_mask: vk::ColorComponentFlags::RGBA,
             }];
             let color_blend = vk::PipelineColorBlendStateCreateInfo::default()
                 // corresponding to renderPass subPass pColorAttachments
-                .attachments(&color_attachments)
+                .attachments(if depth_only { &[] } else { &color_attachments })
                 .blend_constants([0.0, 0.0, 0.0, 0.0])
                 .logic_op_enable(false)
                 .logic_op(vk::LogicOp::COPY);
 
+            // the shading of prepass materials only passes where the prepass left their own depth
+            let (depth_write, depth_compare_op, subpass) = match (depth_only, self.depth_prepass) {
+                (true, _) => (true, self.depth_compare_op, 0),
+                (false, true) => (false, Self::or_equal(self.depth_compare_op), self.subpass),
+                (false, false) => (self.depth_write, self.depth_compare_op, self.subpass),
+            };
             let depth_stencil = vk::PipelineDepthStencilStateCreateInfo::default()
-                .depth_write_enable(shading.depth_write)
-                .depth_test_enable(shading.depth_test)
-                .depth_compare_op(if renderer.depth_reverse_z {
-                    vk::CompareOp::GREATER
-                } else {
-                    vk::CompareOp::LESS
-                })
+                .depth_write_enable(depth_write)
+                .depth_test_enable(self.depth_test)
+                .depth_compare_op(depth_compare_op)
                 .stencil_test_enable(false)
                 .front(vk::StencilOpState::default())
                 .back(vk::StencilOpState::default())
@@ -171,23 +550,7 @@ impl GPUPipeline {
                 .min_depth_bounds(0.0)
                 .max_depth_bounds(1.0);
 
-            let push_constant_ranges = [vk::PushConstantRange::default()
-                .stage_flags(vk::ShaderStageFlags::ALL_GRAPHICS)
-                .offset(0)
-                .size(size_of::<ObjectData>() as u32)];
-            let descriptor_set_layouts =
-                vec![renderer.descriptor_set_layout, descriptor_set_layout];
-            let layout_create_info = vk::PipelineLayoutCreateInfo::default()
-                .set_layouts(&descriptor_set_layouts)
-                .push_constant_ranges(&push_constant_ranges);
-
-            let pipeline_layout = gpu
-                .device_context
-                .device
-                .create_pipeline_layout(&layout_create_info, None)
-                .expect("failed to create pipeline layout!");
-
-            let create_info = vk::GraphicsPipelineCreateInfo::default()
+            let mut create_info = vk::GraphicsPipelineCreateInfo::default()
                 .stages(&shader_stages)
                 .vertex_input_state(&vertex_input_state)
                 .input_assembly_state(&input_assembly_stage)
@@ -197,32 +560,18 @@ impl GPUPipeline {
                 .multisample_state(&multisample)
                 .color_blend_state(&color_blend)
                 .depth_stencil_state(&depth_stencil)
-                .layout(pipeline_layout)
-                .render_pass(renderer.render_pass)
-                .subpass(0)
+                .layout(self.layout)
+                .render_pass(self.render_pass)
+                .subpass(subpass)
                 .base_pipeline_handle(vk::Pipeline::null())
                 .base_pipeline_index(0);
+            if has_tessellation {
+                create_info = create_info.tessellation_state(&tessellation_state);
+            }
 
-            let pipeline = gpu
-                .device_context
-                .device
-                .create_graphics_pipelines(vk::PipelineCache::null(), &[create_info], None)
-                .expect("failed to create graphics pipeline!")[0];
-
-            (pipeline, pipeline_layout)
-        }
-    }
-
-    pub fn drop(&mut self, gpu: &GPU) {
-        unsafe {
-            let device = &gpu.device_context.device;
-            device.destroy_descriptor_set_layout(self.descriptor_set_layout, None);
-            device.destroy_shader_module(self.shader_module, None);
-            device.destroy_pipeline(self.pipeline, None);
-            device.destroy_pipeline_layout(self.pipeline_layout, None);
-            // device
-            //     .free_descriptor_sets(gpu.descriptor_pool, self.descriptor_sets.as_slice())
-            //     .expect("TODO: panic message");
+            self.device
+                .create_graphics_pipelines(self.pipeline_cache, &[create_info], None)
+                .expect("failed to create graphics pipeline!")[0]
         }
     }
 }
@@ -0,0 +1,500 @@
+use super::gpu_texture::GPUTexture;
+use crate::assets::{AssetHandle, Assets, Texture};
+use crate::gpu::{
+    ColorSpace, MipFilter, PassDesc, Rhi, SamplerDesc, TextureFormat, VkPipeline, GPU,
+};
+use ash::vk;
+use std::ffi::CString;
+use std::io;
+use std::mem::size_of;
+use std::rc::Rc;
+
+const FULLSCREEN_SHADER: &str = "fullscreen.spv";
+const IBL_SHADER: &str = "ibl.spv";
+// the environment is sRGB color, the LUT holds linear factors
+const CUBE_FORMAT: vk::Format = vk::Format::R8G8B8A8_SRGB;
+const LUT_FORMAT: vk::Format = vk::Format::R8G8B8A8_UNORM;
+
+const IRRADIANCE_SIZE: u32 = 32;
+const SPECULAR_SIZE: u32 = 128;
+// roughness 0 to 1 over the chain, the last level is 4x4
+pub const SPECULAR_MIPS: u32 = 6;
+const SPECULAR_SAMPLES: u32 = 256;
+// Keep in sync with BRDF_LUT_SIZE in the standard shader
+const BRDF_LUT_SIZE: u32 = 128;
+const BRDF_SAMPLES: u32 = 512;
+
+#[repr(C)]
+#[derive(Copy, Clone)]
+struct IblParams {
+    face: u32,
+    roughness: f32,
+    samples: u32,
+    environment_size: f32,
+}
+
+// The maps an environment lights the standard shading with, see `IblBaker`.
+#[derive(Debug, Clone)]
+pub struct IblTextures {
+    pub irradiance: AssetHandle<Texture>,
+    pub specular: AssetHandle<Texture>,
+}
+
+// Prefilters an environment cube map for image based lighting with fullscreen passes and reads
+// the results back into `Texture` assets: the diffuse irradiance cube, the specular cube with
+// one roughness per mip level and the BRDF LUT of the split sum approximation.
+pub struct IblBaker {
+    gpu: Rc<GPU>,
+
+    // cube faces and LUT
+    render_passes: [vk::RenderPass; 2],
+    descriptor_set_layout: vk::DescriptorSetLayout,
+    descriptor_set: vk::DescriptorSet,
+    pipeline_layout: vk::PipelineLayout,
+    // irradiance, specular and BRDF LUT
+    pipelines: [vk::Pipeline; 3],
+    shader_modules: [vk::ShaderModule; 2],
+}
+
+impl IblBaker {
+    pub fn new(gpu: &Rc<GPU>) -> Self {
+        unsafe {
+            let render_passes =
+                [CUBE_FORMAT, LUT_FORMAT].map(|format| Self::create_render_pass(gpu, format));
+
+            let descriptor_set_layout = gpu.create_descriptor_set_layout(&vec![
+                vk::DescriptorSetLayoutBinding {
+                    binding: 0,
+                    descriptor_type: vk::DescriptorType::SAMPLED_IMAGE,
+                    descriptor_count: 1,
+                    stage_flags: vk::ShaderStageFlags::FRAGMENT,
+                    ..Default::default()
+                },
+                vk::DescriptorSetLayoutBinding {
+                    binding: 1,
+                    descriptor_type: vk::DescriptorType::SAMPLER,
+                    descriptor_count: 1,
+                    stage_flags: vk::ShaderStageFlags::FRAGMENT,
+                    ..Default::default()
+                },
+            ]);
+            let descriptor_set = gpu.create_descriptor_sets(&[descriptor_set_layout])[0];
+
+            let push_constant_ranges = [vk::PushConstantRange::default()
+                .stage_flags(vk::ShaderStageFlags::ALL_GRAPHICS)
+                .offset(0)
+                .size(size_of::<IblParams>() as u32)];
+            let descriptor_set_layouts = [descriptor_set_layout];
+            let layout_create_info = vk::PipelineLayoutCreateInfo::default()
+                .set_layouts(&descriptor_set_layouts)
+                .push_constant_ranges(&push_constant_ranges);
+            let pipeline_layout = gpu
+                .device_context
+                .device
+                .create_pipeline_layout(&layout_create_info, None)
+                .expect("failed to create pipeline layout!");
+
+            let shader_modules = [FULLSCREEN_SHADER, IBL_SHADER].map(|path| {
+                let data = Assets::load_raw(path).unwrap();
+                let mut buffer = io::Cursor::new(&data);
+                let shader_code = ash::util::read_spv(&mut buffer).unwrap();
+                gpu.create_shader_module(&shader_code)
+            });
+            let pipelines = [
+                ("fs_irradiance", render_passes[0]),
+                ("fs_specular", render_passes[0]),
+                ("fs_brdf", render_passes[1]),
+            ]
+            .map(|(entry, render_pass)| {
+                Self::create_pipeline(gpu, &shader_modules, entry, pipeline_layout, render_pass)
+            });
+
+            Self {
+                gpu: Rc::clone(gpu),
+                render_passes,
+                descriptor_set_layout,
+                descriptor_set,
+                pipeline_layout,
+                pipelines,
+                shader_modules,
+            }
+        }
+    }
+
+    // Diffuse ambient light of every direction, `environment_size` is the face size of the cube.
+    pub fn irradiance(&self, environment: &GPUTexture, environment_size: u32) -> Texture {
+        self.bake_cube(
+            environment,
+            environment_size,
+            self.pipelines[0],
+            IRRADIANCE_SIZE,
+            1,
+        )
+    }
+
+    // Specular reflection with roughness 0 at level 0 up to 1 at the last of SPECULAR_MIPS levels.
+    pub fn specular(&self, environment: &GPUTexture, environment_size: u32) -> Texture {
+        self.bake_cube(
+            environment,
+            environment_size,
+            self.pipelines[1],
+            SPECULAR_SIZE,
+            SPECULAR_MIPS,
+        )
+    }
+
+    // Scale (r) and bias (g) of F0 by n.v (u) and roughness (v), the same for every environment.
+    pub fn brdf_lut(&self) -> Texture {
+        let params = IblParams {
+            face: 0,
+            roughness: 0.0,
+            samples: BRDF_SAMPLES,
+            environment_size: 0.0,
+        };
+        let (pixels, _) = self.bake(
+            self.pipelines[2],
+            (self.render_passes[1], LUT_FORMAT),
+            BRDF_LUT_SIZE,
+            1,
+            1,
+            |_, _| params,
+        );
+
+        Texture {
+            width: BRDF_LUT_SIZE,
+            height: BRDF_LUT_SIZE,
+            mip_levels: 1,
+            layers: 1,
+            format: TextureFormat::Rgba8Unorm,
+            color_space: ColorSpace::Linear,
+            pixels,
+            mips: vec![],
+            mip_filter: MipFilter::Linear,
+            // looked up by (n dot v, roughness), the edges must not wrap
+            sampler: SamplerDesc::clamped(),
+        }
+    }
+
+    fn bake_cube(
+        &self,
+        environment: &GPUTexture,
+        environment_size: u32,
+        pipeline: vk::Pipeline,
+        size: u32,
+        mip_levels: u32,
+    ) -> Texture {
+        self.gpu
+            .write_texture(self.descriptor_set, 0, &environment.texture);
+
+        let (pixels, mips) = self.bake(
+            pipeline,
+            (self.render_passes[0], CUBE_FORMAT),
+            size,
+            mip_levels,
+            6,
+            |level, face| IblParams {
+                face,
+                roughness: level as f32 / (mip_levels - 1).max(1) as f32,
+                samples: SPECULAR_SAMPLES,
+                environment_size: environment_size as f32,
+            },
+        );
+
+        // the chain is complete, nothing is generated on upload
+        Texture {
+            width: size,
+            height: size,
+            mip_levels,
+            layers: 6,
+            format: TextureFormat::Rgba8Srgb,
+            color_space: ColorSpace::Srgb,
+            pixels,
+            mips,
+            mip_filter: MipFilter::Linear,
+            sampler: SamplerDesc::default(),
+        }
+    }
+
+    // Renders every layer of every level with the params of (level, layer) and reads the image
+    // back, a level holds all layers back to back like `Texture` expects. The target is one of
+    // `render_passes` with the format it was created for.
+    fn bake(
+        &self,
+        pipeline: vk::Pipeline,
+        (render_pass, format): (vk::RenderPass, vk::Format),
+        size: u32,
+        mip_levels: u32,
+        layers: u32,
+        params: impl Fn(u32, u32) -> IblParams,
+    ) -> (Vec<u8>, Vec<std::ops::Range<usize>>) {
+        let gpu = &self.gpu;
+        let level_size = |level: u32| (size >> level).max(1);
+        let mut mips = vec![];
+        let mut byte_size = 0;
+        for level in 0..mip_levels {
+            let level_bytes = (level_size(level) * level_size(level) * 4 * layers) as usize;
+            mips.push(byte_size..byte_size + level_bytes);
+            byte_size += level_bytes;
+        }
+
+        unsafe {
+            let device_context = &gpu.device_context;
+            let (image, image_memory) = device_context.create_layered_image(
+                size,
+                size,
+                mip_levels,
+                layers,
+                vk::ImageCreateFlags::empty(),
+                vk::SampleCountFlags::TYPE_1,
+                format,
+                vk::ImageTiling::OPTIMAL,
+                vk::ImageUsageFlags::COLOR_ATTACHMENT | vk::ImageUsageFlags::TRANSFER_SRC,
+                vk::MemoryPropertyFlags::DEVICE_LOCAL,
+            );
+            let (readback_buffer, readback_memory) = device_context.create_buffer(
+                byte_size as vk::DeviceSize,
+                vk::BufferUsageFlags::TRANSFER_DST,
+                vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT,
+            );
+
+            // one 2D view and framebuffer per face of every level
+            let mut targets = vec![];
+            let vk_pipeline = VkPipeline {
+                pipeline,
+                layout: self.pipeline_layout,
+            };
+            let command_buffer = gpu.begin_single_time_command();
+            for level in 0..mip_levels {
+                for layer in 0..layers {
+                    let view_create_info = vk::ImageViewCreateInfo::default()
+                        .image(image)
+                        .view_type(vk::ImageViewType::TYPE_2D)
+                        .format(format)
+                        .subresource_range(vk::ImageSubresourceRange {
+                            aspect_mask: vk::ImageAspectFlags::COLOR,
+                            base_mip_level: level,
+                            level_count: 1,
+                            base_array_layer: layer,
+                            layer_count: 1,
+                        });
+                    let view = device_context
+                        .device
+                        .create_image_view(&view_create_info, None)
+                        .expect("failed to create image view!");
+                    let attachments = [view];
+                    let framebuffer_create_info = vk::FramebufferCreateInfo::default()
+                        .width(level_size(level))
+                        .height(level_size(level))
+                        .layers(1)
+                        .attachments(&attachments)
+                        .render_pass(render_pass);
+                    let framebuffer = device_context
+                        .device
+                        .create_framebuffer(&framebuffer_create_info, None)
+                        .expect("failed to create IBL framebuffer!");
+                    targets.push((view, framebuffer));
+
+                    let ibl_params = params(level, layer);
+                    gpu.begin_pass(
+                        command_buffer,
+                        &PassDesc {
+                            label: "ibl",
+                            render_pass,
+                            framebuffer,
+                            width: level_size(level),
+                            height: level_size(level),
+                            clear_color: [0.0; 4],
+                            clear_depth: 1.0,
+                        },
+                    );
+                    gpu.bind_pipeline(command_buffer, &vk_pipeline);
+                    if layers == 6 {
+                        gpu.bind_resource_sets(
+                            command_buffer,
+                            &vk_pipeline,
+                            0,
+                            &[self.descriptor_set],
+                        );
+                    }
+                    gpu.push_constants(
+                        command_buffer,
+                        &vk_pipeline,
+                        std::slice::from_raw_parts(
+                            (&ibl_params as *const IblParams) as *const u8,
+                            size_of::<IblParams>(),
+                        ),
+                    );
+                    gpu.draw(command_buffer, 3);
+                    gpu.end_pass(command_buffer);
+                }
+            }
+
+            // every pass leaves its face in TRANSFER_SRC_OPTIMAL
+            let regions = (0..mip_levels)
+                .map(|level| vk::BufferImageCopy {
+                    buffer_offset: mips[level as usize].start as vk::DeviceSize,
+                    buffer_row_length: 0,
+                    buffer_image_height: 0,
+                    image_subresource: vk::ImageSubresourceLayers {
+                        aspect_mask: vk::ImageAspectFlags::COLOR,
+                        mip_level: level,
+                        base_array_layer: 0,
+                        layer_count: layers,
+                    },
+                    image_offset: vk::Offset3D { x: 0, y: 0, z: 0 },
+                    image_extent: vk::Extent3D {
+                        width: level_size(level),
+                        height: level_size(level),
+                        depth: 1,
+                    },
+                })
+                .collect::<Vec<_>>();
+            device_context.device.cmd_copy_image_to_buffer(
+                command_buffer,
+                image,
+                vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                readback_buffer,
+                &regions,
+            );
+            gpu.end_single_time_command(command_buffer);
+
+            let pixels =
+                std::slice::from_raw_parts(readback_memory.mapped as *const u8, byte_size).to_vec();
+
+            device_context.destroy_buffer(readback_buffer, readback_memory);
+            for (view, framebuffer) in targets {
+                device_context.device.destroy_framebuffer(framebuffer, None);
+                device_context.device.destroy_image_view(view, None);
+            }
+            device_context.destroy_image(image, image_memory);
+
+            (pixels, mips)
+        }
+    }
+
+    unsafe fn create_render_pass(gpu: &GPU, format: vk::Format) -> vk::RenderPass {
+        let attachments = [vk::AttachmentDescription {
+            format,
+            samples: vk::SampleCountFlags::TYPE_1,
+            load_op: vk::AttachmentLoadOp::DONT_CARE,
+            store_op: vk::AttachmentStoreOp::STORE,
+            stencil_load_op: vk::AttachmentLoadOp::DONT_CARE,
+            stencil_store_op: vk::AttachmentStoreOp::DONT_CARE,
+            initial_layout: vk::ImageLayout::UNDEFINED,
+            final_layout: vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+            flags: Default::default(),
+        }];
+        let color_attachment_refs = [vk::AttachmentReference {
+            attachment: 0,
+            layout: vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+        }];
+        let sub_passes = [vk::SubpassDescription::default()
+            .pipeline_bind_point(vk::PipelineBindPoint::GRAPHICS)
+            .color_attachments(&color_attachment_refs)];
+
+        // the copy after the passes reads what they wrote
+        let dependencies = [vk::SubpassDependency {
+            src_subpass: 0,
+            src_stage_mask: vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
+            src_access_mask: vk::AccessFlags::COLOR_ATTACHMENT_WRITE,
+            dst_subpass: vk::SUBPASS_EXTERNAL,
+            dst_stage_mask: vk::PipelineStageFlags::TRANSFER,
+            dst_access_mask: vk::AccessFlags::TRANSFER_READ,
+            ..Default::default()
+        }];
+
+        let create_info = vk::RenderPassCreateInfo::default()
+            .attachments(&attachments)
+            .subpasses(&sub_passes)
+            .dependencies(&dependencies);
+
+        gpu.device_context
+            .device
+            .create_render_pass(&create_info, None)
+            .expect("failed to create IBL render pass!")
+    }
+
+    unsafe fn create_pipeline(
+        gpu: &GPU,
+        shader_modules: &[vk::ShaderModule; 2],
+        fragment_entry: &str,
+        pipeline_layout: vk::PipelineLayout,
+        render_pass: vk::RenderPass,
+    ) -> vk::Pipeline {
+        let vertex_entry = CString::new("vs").unwrap();
+        let fragment_entry = CString::new(fragment_entry).unwrap();
+        let shader_stages = [
+            vk::PipelineShaderStageCreateInfo::default()
+                .module(shader_modules[0])
+                .stage(vk::ShaderStageFlags::VERTEX)
+                .name(vertex_entry.as_c_str()),
+            vk::PipelineShaderStageCreateInfo::default()
+                .module(shader_modules[1])
+                .stage(vk::ShaderStageFlags::FRAGMENT)
+                .name(fragment_entry.as_c_str()),
+        ];
+
+        let vertex_input_state = vk::PipelineVertexInputStateCreateInfo::default();
+        let input_assembly_stage = vk::PipelineInputAssemblyStateCreateInfo::default()
+            .topology(vk::PrimitiveTopology::TRIANGLE_LIST);
+        let dynamic_state = vk::PipelineDynamicStateCreateInfo::default()
+            .dynamic_states(&[vk::DynamicState::VIEWPORT, vk::DynamicState::SCISSOR]);
+        let viewport_state = vk::PipelineViewportStateCreateInfo::default()
+            .viewport_count(1)
+            .scissor_count(1);
+        let rasterization_state = vk::PipelineRasterizationStateCreateInfo::default()
+            .cull_mode(vk::CullModeFlags::NONE)
+            .polygon_mode(vk::PolygonMode::FILL)
+            .line_width(1.0);
+        let multisample = vk::PipelineMultisampleStateCreateInfo::default()
+            .rasterization_samples(vk::SampleCountFlags::TYPE_1);
+        let color_attachments = [vk::PipelineColorBlendAttachmentState {
+            blend_enable: false.into(),
+            color_write_mask: vk::ColorComponentFlags::RGBA,
+            ..Default::default()
+        }];
+        let color_blend =
+            vk::PipelineColorBlendStateCreateInfo::default().attachments(&color_attachments);
+        let depth_stencil = vk::PipelineDepthStencilStateCreateInfo::default()
+            .depth_test_enable(false)
+            .depth_write_enable(false);
+
+        let create_info = vk::GraphicsPipelineCreateInfo::default()
+            .stages(&shader_stages)
+            .vertex_input_state(&vertex_input_state)
+            .input_assembly_state(&input_assembly_stage)
+            .dynamic_state(&dynamic_state)
+            .viewport_state(&viewport_state)
+            .rasterization_state(&rasterization_state)
+            .multisample_state(&multisample)
+            .color_blend_state(&color_blend)
+            .depth_stencil_state(&depth_stencil)
+            .layout(pipeline_layout)
+            .render_pass(render_pass)
+            .subpass(0);
+
+        gpu.device_context
+            .device
+            .create_graphics_pipelines(vk::PipelineCache::null(), &[create_info], None)
+            .expect("failed to create IBL pipeline!")[0]
+    }
+}
+
+impl Drop for IblBaker {
+    fn drop(&mut self) {
+        unsafe {
+            let device = &self.gpu.device_context.device;
+            self.pipelines
+                .iter()
+                .for_each(|&pipeline| device.destroy_pipeline(pipeline, None));
+            device.destroy_pipeline_layout(self.pipeline_layout, None);
+            device.destroy_descriptor_set_layout(self.descriptor_set_layout, None);
+            self.render_passes
+                .iter()
+                .for_each(|&render_pass| device.destroy_render_pass(render_pass, None));
+            self.shader_modules
+                .iter()
+                .for_each(|&shader_module| device.destroy_shader_module(shader_module, None));
+        }
+    }
+}
@@ -0,0 +1,217 @@
+use super::camera_uniforms::MAX_LIGHTS;
+use super::{CameraUniforms, GlobalsData, LightData};
+use crate::assets::Assets;
+use crate::gpu::{Allocation, ComputePipeline, ComputeReader, GPU};
+use crate::math::{Mat4, Vec3};
+use ash::vk;
+use std::cell::Cell;
+use std::io;
+use std::mem::size_of;
+use std::rc::Rc;
+
+const LIGHT_CLUSTER_SHADER: &str = "light_cluster.spv";
+// Keep in sync with @workgroup_size in light_cluster.wgsl
+const WORKGROUP_SIZE: u32 = 64;
+
+// Keep in sync with light_cluster.wgsl and the standard shader
+pub const CLUSTERS: [u32; 3] = [16, 9, 24];
+pub const MAX_LIGHTS_PER_CLUSTER: usize = 127;
+// Lights past it are dropped, the closest ones come first.
+pub const MAX_CLUSTERED_LIGHTS: usize = 1024;
+// Up to it the fragments loop over the lights of the uniform block, building the grid only pays
+// off past a handful of lights.
+pub const MIN_CLUSTERED_LIGHTS: usize = MAX_LIGHTS / 2;
+
+// Keep in sync with ClusterParams in light_cluster.wgsl
+#[repr(C)]
+#[derive(Copy, Clone)]
+struct ClusterParams {
+    projection: [f32; 4],
+    depth: [f32; 4],
+    count: u32,
+    _pad: [u32; 3],
+}
+
+struct FrameBuffers {
+    // `LightData` of every clustered light, read by the fragment shaders
+    lights: (vk::Buffer, Allocation),
+    // view space bounding sphere of every light
+    spheres: (vk::Buffer, Allocation),
+    // per cluster its light count followed by MAX_LIGHTS_PER_CLUSTER light indices
+    grid: (vk::Buffer, Allocation),
+}
+
+// Clustered forward lighting of the standard shading. The view frustum is split into a grid of
+// CLUSTERS tiles and depth slices, a compute pass lists the lights touching each cluster and the
+// fragment shader only evaluates the lights of its own, so a scene may have hundreds of lights
+// where the uniform block holds MAX_LIGHTS. With few lights the pass is skipped and the uniform
+// block is enough, see MIN_CLUSTERED_LIGHTS. The lights and the grid are bound on set 0 of the
+// camera uniforms, see CLUSTER_LIGHTS_BINDING.
+pub struct LightClusters {
+    gpu: Rc<GPU>,
+
+    pipeline: ComputePipeline,
+    cluster_sets: Vec<vk::DescriptorSet>,
+    frames: Vec<FrameBuffers>,
+    // of the frame being drawn
+    params: Cell<ClusterParams>,
+}
+
+impl LightClusters {
+    pub fn new(gpu: &Rc<GPU>, camera_uniforms: &CameraUniforms, frames_in_flight: u32) -> Self {
+        let data = Assets::load_raw(LIGHT_CLUSTER_SHADER).unwrap();
+        let mut buffer = io::Cursor::new(&data);
+        let shader_code = ash::util::read_spv(&mut buffer).unwrap();
+        let pipeline = ComputePipeline::new(
+            gpu,
+            &shader_code,
+            "cs",
+            &[vk::DescriptorType::STORAGE_BUFFER; 2],
+            size_of::<ClusterParams>() as u32,
+        );
+        gpu.debug_names.set_name(pipeline.pipeline, "light cluster");
+        let cluster_sets = pipeline.create_sets(gpu, frames_in_flight as usize);
+
+        let create_buffer = |size: usize, memory_properties: vk::MemoryPropertyFlags| unsafe {
+            // host visible allocations come persistently mapped
+            gpu.device_context.create_buffer(
+                size as vk::DeviceSize,
+                vk::BufferUsageFlags::STORAGE_BUFFER,
+                memory_properties,
+            )
+        };
+        let host_visible =
+            vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT;
+        let clusters = CLUSTERS.iter().product::<u32>() as usize;
+        let frames = (0..frames_in_flight as usize)
+            .map(|frame_index| {
+                let frame = FrameBuffers {
+                    lights: create_buffer(
+                        MAX_CLUSTERED_LIGHTS * size_of::<LightData>(),
+                        host_visible,
+                    ),
+                    spheres: create_buffer(
+                        MAX_CLUSTERED_LIGHTS * size_of::<[f32; 4]>(),
+                        host_visible,
+                    ),
+                    grid: create_buffer(
+                        clusters * (MAX_LIGHTS_PER_CLUSTER + 1) * size_of::<u32>(),
+                        vk::MemoryPropertyFlags::DEVICE_LOCAL,
+                    ),
+                };
+                let set = cluster_sets[frame_index];
+                pipeline.write_buffer(gpu, set, 0, frame.spheres.0, vk::WHOLE_SIZE);
+                pipeline.write_buffer(gpu, set, 1, frame.grid.0, vk::WHOLE_SIZE);
+                camera_uniforms.set_light_clusters(frame_index, frame.lights.0, frame.grid.0);
+                frame
+            })
+            .collect();
+
+        Self {
+            gpu: Rc::clone(gpu),
+            pipeline,
+            cluster_sets,
+            frames,
+            params: Cell::new(ClusterParams {
+                projection: [0.0; 4],
+                depth: [0.0; 4],
+                count: 0,
+                _pad: [0; 3],
+            }),
+        }
+    }
+
+    // Writes the lights of the frame, closest first, and returns what `LightsData::clusters`
+    // holds: the near and far depth of the slices, 1 for linear slices and the light count, all 0
+    // for MIN_CLUSTERED_LIGHTS or fewer. The frame's previous submission must be done.
+    pub fn update(
+        &self,
+        frame_index: usize,
+        lights: &[LightData],
+        view: Mat4,
+        globals: &GlobalsData,
+    ) -> [f32; 4] {
+        if lights.len() <= MIN_CLUSTERED_LIGHTS {
+            self.params.set(ClusterParams {
+                count: 0,
+                ..self.params.get()
+            });
+            return [0.0; 4];
+        }
+        let lights = &lights[..lights.len().min(MAX_CLUSTERED_LIGHTS)];
+        let [fov, aspect, ortho_height, _] = globals.camera_params;
+        let orthographic = ortho_height > 0.0;
+        let near = globals.camera_position[3];
+        let mut far = near;
+
+        let frame = &self.frames[frame_index];
+        unsafe {
+            let light_data = frame.lights.1.mapped as *mut LightData;
+            let spheres = frame.spheres.1.mapped as *mut [f32; 4];
+            for (index, light) in lights.iter().enumerate() {
+                let [x, y, z, range] = light.position_range;
+                // spot lights are clustered by the sphere of their range
+                let center = view.transform_point(Vec3::new(x, y, z));
+                far = far.max(-center.z + range);
+                light_data.add(index).write(*light);
+                spheres
+                    .add(index)
+                    .write([center.x, center.y, center.z, range]);
+            }
+        }
+
+        // the slices end behind the farthest light, fragments past it are out of every range
+        let far = far.max(near + 1.0);
+        let projection = match orthographic {
+            true => [0.0, aspect, ortho_height / 2.0, 0.0],
+            false => [(fov / 2.0).tan(), aspect, 0.0, 0.0],
+        };
+        let linear = if orthographic { 1.0 } else { 0.0 };
+        self.params.set(ClusterParams {
+            projection,
+            depth: [near, far, linear, 0.0],
+            count: lights.len() as u32,
+            _pad: [0; 3],
+        });
+        [near, far, linear, lights.len() as f32]
+    }
+
+    // Records the clustering of the lights `update` wrote, outside of a render pass and before
+    // the draws.
+    pub fn build(&self, command_buffer: vk::CommandBuffer, frame_index: usize) {
+        let params = self.params.get();
+        if params.count == 0 {
+            return;
+        }
+
+        let params = unsafe {
+            std::slice::from_raw_parts(
+                (&params as *const ClusterParams) as *const u8,
+                size_of::<ClusterParams>(),
+            )
+        };
+        let clusters = CLUSTERS.iter().product::<u32>();
+        self.pipeline.dispatch(
+            &self.gpu,
+            command_buffer,
+            self.cluster_sets[frame_index],
+            params,
+            [clusters.div_ceil(WORKGROUP_SIZE), 1, 1],
+        );
+        ComputePipeline::barrier(&self.gpu, command_buffer, ComputeReader::Fragment);
+    }
+}
+
+impl Drop for LightClusters {
+    fn drop(&mut self) {
+        self.pipeline.destroy(&self.gpu);
+        unsafe {
+            let device_context = &self.gpu.device_context;
+            for frame in self.frames.drain(..) {
+                for (buffer, memory) in [frame.lights, frame.spheres, frame.grid] {
+                    device_context.destroy_buffer(buffer, memory);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,107 @@
+use crate::assets::{AssetHandle, Assets, DynamicGeom, Material};
+use crate::math::{Mat4, Vec3};
+use crate::renderer::vertex::Vertex;
+use crate::renderer::{RenderObject, Shading};
+use crate::scene::{DebugDraw, Measurement, Query, World};
+
+const MEASUREMENT_COLOR: [f32; 3] = [1.0, 0.8, 0.0];
+
+// Draws `Measurement` entities as dimension lines: the measured segment plus a cap at each end,
+// labeled with its length.
+pub struct MeasurementRenderer {
+    material: AssetHandle<Material>,
+}
+
+impl MeasurementRenderer {
+    pub fn new(assets: &mut Assets) -> Self {
+        Self {
+            material: assets.handle(Material::new(Shading::load_debug_line())),
+        }
+    }
+
+    pub fn collect(&self, world: &mut World, assets: &mut Assets, objects: &mut Vec<RenderObject>) {
+        let query = Query::<&mut Measurement>::new(world);
+        for measurement in query {
+            let key = measurement.key();
+            // one geometry per measurement, rewritten in place when the points move
+            let lines = match &mut measurement.lines {
+                Some((lines, lines_key)) if *lines_key == key => lines.clone(),
+                Some((lines, lines_key)) => {
+                    let (vertices, indices) = Self::build_lines(measurement.start, measurement.end);
+                    if let Some(dynamic_geom) = assets.load_mut(lines) {
+                        dynamic_geom.set(vertices, indices);
+                    }
+                    *lines_key = key;
+                    lines.clone()
+                }
+                None => {
+                    let (vertices, indices) = Self::build_lines(measurement.start, measurement.end);
+                    let lines = assets.handle(DynamicGeom::new(vertices, indices));
+                    measurement.lines = Some((lines.clone(), key));
+                    lines
+                }
+            };
+
+            // points are in world space already
+            objects.push(RenderObject::new(
+                lines,
+                self.material.clone(),
+                Mat4::identity(),
+            ));
+        }
+    }
+
+    // The length of every measurement next to its middle, where the text renderer puts the labels,
+    // and a marker on the start the measure tool picked for the next one.
+    pub fn draw_labels(world: &mut World, picked_start: Option<Vec3>) {
+        let labels = Query::<&Measurement>::new(world)
+            .map(|measurement| {
+                let middle = (measurement.start + measurement.end) * 0.5;
+                (middle, format!("{:.3} m", measurement.distance()))
+            })
+            .collect::<Vec<_>>();
+        let Some(debug_draw) = world.get_resource_mut::<DebugDraw>() else {
+            return;
+        };
+        let color = Vec3::from(MEASUREMENT_COLOR);
+        for (position, label) in labels {
+            debug_draw.draw_text_3d(position, label, color);
+        }
+        if let Some(start) = picked_start {
+            debug_draw.draw_sphere(start, 0.05, color);
+        }
+    }
+
+    pub fn build_lines(start: Vec3, end: Vec3) -> (Vec<Vertex>, Vec<u32>) {
+        let direction = end - start;
+        let distance = direction.len();
+        let direction = if distance > 0.0 {
+            direction / distance
+        } else {
+            Vec3::new(1.0, 0.0, 0.0)
+        };
+
+        // caps stand perpendicular to the line, upright unless the line itself is vertical
+        let up = if direction.y.abs() > 0.99 {
+            Vec3::new(1.0, 0.0, 0.0)
+        } else {
+            Vec3::new(0.0, 1.0, 0.0)
+        };
+        let side = direction.cross(up).cross(direction).normalize();
+        let cap = side * (distance * 0.05).max(0.02);
+
+        let points = [start, end, start - cap, start + cap, end - cap, end + cap];
+        let vertices = points
+            .iter()
+            .map(|point| Vertex {
+                position: [point.x, point.y, point.z],
+                color: MEASUREMENT_COLOR,
+                uv: [0.0, 0.0],
+                normal: [0.0, 0.0, 0.0],
+            })
+            .collect::<Vec<_>>();
+        let indices = (0..vertices.len() as u32).collect();
+
+        (vertices, indices)
+    }
+}
@@ -0,0 +1,206 @@
+use super::gpu_texture::GPUTexture;
+use crate::assets::Assets;
+use crate::gpu::{ComputePipeline, MipFilter, GPU};
+use ash::vk;
+use std::io;
+use std::mem::size_of;
+use std::rc::Rc;
+
+const MIP_FILTER_SHADER: &str = "mip_filter.spv";
+// Keep in sync with @workgroup_size in mip_filter.wgsl
+const WORKGROUP_SIZE: u32 = 8;
+
+// Keep in sync with MipParams in mip_filter.wgsl
+#[repr(C)]
+#[derive(Copy, Clone)]
+struct MipParams {
+    mode: u32,
+    channel: u32,
+    from_base: u32,
+    _pad: u32,
+}
+
+impl MipParams {
+    fn new(mip_filter: MipFilter, level: u32) -> Self {
+        let (mode, channel) = match (mip_filter, mip_filter.roughness_channel()) {
+            (MipFilter::Normal, _) => (1, 0),
+            (_, Some(channel)) => (2, channel as u32),
+            _ => (0, 0),
+        };
+        Self {
+            mode,
+            channel,
+            from_base: (level == 1) as u32,
+            _pad: 0,
+        }
+    }
+}
+
+// Fills the mip chain of normal and roughness maps with a compute pass per level, the blit chain
+// averages their texels like color and the result sparkles in the distance. The texture was
+// uploaded with only level 0, see `TextureDesc::is_mip_filtered`.
+pub struct MipGenerator {
+    gpu: Rc<GPU>,
+
+    pipeline: ComputePipeline,
+}
+
+impl MipGenerator {
+    pub fn new(gpu: &Rc<GPU>) -> Self {
+        let data = Assets::load_raw(MIP_FILTER_SHADER).unwrap();
+        let mut buffer = io::Cursor::new(&data);
+        let shader_code = ash::util::read_spv(&mut buffer).unwrap();
+        let pipeline = ComputePipeline::new(
+            gpu,
+            &shader_code,
+            "cs",
+            &[
+                vk::DescriptorType::SAMPLED_IMAGE,
+                vk::DescriptorType::STORAGE_IMAGE,
+            ],
+            size_of::<MipParams>() as u32,
+        );
+        gpu.debug_names.set_name(pipeline.pipeline, "mip filter");
+
+        Self {
+            gpu: Rc::clone(gpu),
+            pipeline,
+        }
+    }
+
+    // Every level from the one above, waits for the GPU like the upload did. All levels are in
+    // SHADER_READ_ONLY_OPTIMAL before and after.
+    pub fn generate(&self, texture: &GPUTexture, width: u32, height: u32, mip_filter: MipFilter) {
+        let gpu = &self.gpu;
+        let texture = &texture.texture;
+        let device = &gpu.device_context.device;
+        let level_size = |level: u32| ((width >> level).max(1), (height >> level).max(1));
+
+        unsafe {
+            let descriptor_sets = self
+                .pipeline
+                .create_sets(gpu, texture.mip_levels as usize - 1);
+            // one view per level, read by the level below it and written from the one above
+            let views = (0..texture.mip_levels)
+                .map(|level| {
+                    let create_info = vk::ImageViewCreateInfo::default()
+                        .image(texture.image)
+                        .view_type(vk::ImageViewType::TYPE_2D)
+                        .format(texture.format)
+                        .subresource_range(Self::level_range(level));
+                    device
+                        .create_image_view(&create_info, None)
+                        .expect("failed to create image view!")
+                })
+                .collect::<Vec<_>>();
+
+            ComputePipeline::run(gpu, |command_buffer| {
+                for level in 1..texture.mip_levels {
+                    let descriptor_set = descriptor_sets[level as usize - 1];
+                    self.pipeline.write_image(
+                        gpu,
+                        descriptor_set,
+                        0,
+                        views[level as usize - 1],
+                        vk::Sampler::null(),
+                    );
+                    self.pipeline.write_image(
+                        gpu,
+                        descriptor_set,
+                        1,
+                        views[level as usize],
+                        vk::Sampler::null(),
+                    );
+
+                    // written in GENERAL, then read as the source of the next level
+                    self.barrier(
+                        command_buffer,
+                        texture.image,
+                        level,
+                        (
+                            vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+                            vk::AccessFlags::SHADER_READ,
+                        ),
+                        (vk::ImageLayout::GENERAL, vk::AccessFlags::SHADER_WRITE),
+                    );
+                    let params = MipParams::new(mip_filter, level);
+                    let (level_width, level_height) = level_size(level);
+                    self.pipeline.dispatch(
+                        gpu,
+                        command_buffer,
+                        descriptor_set,
+                        std::slice::from_raw_parts(
+                            (&params as *const MipParams) as *const u8,
+                            size_of::<MipParams>(),
+                        ),
+                        [
+                            level_width.div_ceil(WORKGROUP_SIZE),
+                            level_height.div_ceil(WORKGROUP_SIZE),
+                            1,
+                        ],
+                    );
+                    self.barrier(
+                        command_buffer,
+                        texture.image,
+                        level,
+                        (vk::ImageLayout::GENERAL, vk::AccessFlags::SHADER_WRITE),
+                        (
+                            vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+                            vk::AccessFlags::SHADER_READ,
+                        ),
+                    );
+                }
+            });
+
+            views
+                .into_iter()
+                .for_each(|view| device.destroy_image_view(view, None));
+            gpu.free_descriptor_sets(&descriptor_sets);
+        }
+    }
+
+    // Moves `level` from the old layout and access to the new ones.
+    unsafe fn barrier(
+        &self,
+        command_buffer: vk::CommandBuffer,
+        image: vk::Image,
+        level: u32,
+        (old_layout, src_access_mask): (vk::ImageLayout, vk::AccessFlags),
+        (new_layout, dst_access_mask): (vk::ImageLayout, vk::AccessFlags),
+    ) {
+        let barrier = vk::ImageMemoryBarrier::default()
+            .image(image)
+            .old_layout(old_layout)
+            .new_layout(new_layout)
+            .src_access_mask(src_access_mask)
+            .dst_access_mask(dst_access_mask)
+            .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+            .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+            .subresource_range(Self::level_range(level));
+        self.gpu.device_context.device.cmd_pipeline_barrier(
+            command_buffer,
+            vk::PipelineStageFlags::COMPUTE_SHADER,
+            vk::PipelineStageFlags::COMPUTE_SHADER | vk::PipelineStageFlags::FRAGMENT_SHADER,
+            vk::DependencyFlags::empty(),
+            &[],
+            &[],
+            &[barrier],
+        );
+    }
+
+    fn level_range(level: u32) -> vk::ImageSubresourceRange {
+        vk::ImageSubresourceRange {
+            aspect_mask: vk::ImageAspectFlags::COLOR,
+            base_mip_level: level,
+            level_count: 1,
+            base_array_layer: 0,
+            layer_count: 1,
+        }
+    }
+}
+
+impl Drop for MipGenerator {
+    fn drop(&mut self) {
+        self.pipeline.destroy(&self.gpu);
+    }
+}
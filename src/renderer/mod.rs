@@ -1,16 +1,78 @@
+mod block_layout;
+mod camera_uniforms;
+mod contact_shadow_renderer;
+mod culling_debugger;
+mod debug_draw_renderer;
+mod decal_renderer;
+mod egui_renderer;
 mod forward_renderer;
+mod geom_arena;
 mod gpu_assets;
+mod gpu_culling;
+mod gpu_dynamic_geom;
 mod gpu_geom;
+mod gpu_material_params;
 mod gpu_pipeline;
 mod gpu_texture;
+mod ibl_baker;
+mod light_clusters;
+mod measurement_renderer;
+mod mip_generator;
+mod noise_generator;
+mod normal_debugger;
+mod object_buffer;
+mod outline_renderer;
+mod payload_buffer;
+mod per_frame_buffer;
+mod post_chain;
 mod render_object;
+mod render_target;
+mod shader_compiler;
+mod shader_hooks;
 mod shader_node;
 mod shading;
+mod shadow_atlas;
+mod shadow_renderer;
+mod skeleton_debugger;
+mod skybox;
+mod text_renderer;
+mod texture_camera_renderer;
+mod trail_renderer;
 pub mod vertex;
+mod video_renderer;
 
-pub use forward_renderer::ForwardRenderer;
+pub use block_layout::BlockLayout;
+pub use camera_uniforms::{CameraUniforms, GlobalsData, LightData, LightsData, GLOBALS_BINDING};
+pub use contact_shadow_renderer::ContactShadowRenderer;
+pub use culling_debugger::CullingDebugger;
+pub use debug_draw_renderer::DebugDrawRenderer;
+pub use decal_renderer::{DecalObject, DecalRenderer};
+pub use egui_renderer::EguiRenderer;
+pub use forward_renderer::{ForwardRenderer, Msaa};
 pub use gpu_assets::GPUAssets;
-pub use render_object::RenderContext;
-pub use render_object::RenderObject;
+pub use gpu_culling::{GPUCulling, IndirectBatch};
+pub use ibl_baker::{IblBaker, IblTextures, SPECULAR_MIPS};
+pub use light_clusters::LightClusters;
+pub use measurement_renderer::MeasurementRenderer;
+pub use noise_generator::{NoiseDesc, NoiseGenerator, NoiseTextures};
+pub use normal_debugger::NormalDebugger;
+pub use object_buffer::{ObjectBuffer, OBJECT_SET};
+pub use outline_renderer::{OutlineRenderer, SelectedObject};
+pub use payload_buffer::{PayloadBuffer, MAX_PAYLOAD_SIZE, PAYLOAD_SET};
+pub use per_frame_buffer::PerFrameBuffer;
+pub use post_chain::{PostChain, PostEffect, Tonemapping};
+pub use render_object::{RenderContext, RenderExtract};
+pub use render_object::{RenderFlags, RenderGeom, RenderObject};
+pub use render_target::RenderTarget;
+pub use shader_compiler::ShaderCompiler;
+pub use shader_hooks::ShaderHooks;
 pub use shader_node::*;
-pub use shading::{Shading, ShadingMode};
+pub use shading::{ParamKind, ShaderStage, Shading};
+pub use shadow_atlas::{ShadowAtlas, ShadowTile};
+pub use shadow_renderer::{ShadowRenderer, ShadowView};
+pub use skeleton_debugger::SkeletonDebugger;
+pub use skybox::Skybox;
+pub use text_renderer::TextRenderer;
+pub use texture_camera_renderer::{TextureCameraRenderer, TextureCameraView};
+pub use trail_renderer::TrailRenderer;
+pub use video_renderer::VideoRenderer;
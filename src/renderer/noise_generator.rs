@@ -0,0 +1,368 @@
+use crate::assets::{AssetHandle, Assets, Texture};
+use crate::gpu::{ColorSpace, PassDesc, Rhi, VkPipeline, GPU};
+use ash::vk;
+use std::ffi::CString;
+use std::io;
+use std::mem::size_of;
+use std::rc::Rc;
+
+const FULLSCREEN_SHADER: &str = "fullscreen.spv";
+const NOISE_SHADER: &str = "noise.spv";
+// noise is data, read back and sampled as written
+const FORMAT: vk::Format = vk::Format::R8G8B8A8_UNORM;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum NoiseKind {
+    // smooth gradient noise, wind and volumetric density
+    Perlin,
+    // inverted cell distance, cloudy and cellular shapes
+    Worley,
+    // evenly spread values without clumps, jittering samples
+    Blue,
+}
+
+#[repr(C)]
+#[derive(Copy, Clone)]
+struct NoiseParams {
+    kind: u32,
+    period: u32,
+    octaves: u32,
+    seed: u32,
+    size: [f32; 2],
+}
+
+// Square tileable noise texture, every channel holds its own pattern.
+#[derive(Debug, Copy, Clone)]
+pub struct NoiseDesc {
+    pub kind: NoiseKind,
+    pub size: u32,
+    // lattice cells across the texture at the first octave, ignored by blue noise
+    pub period: u32,
+    // each one doubles the frequency and halves the amplitude
+    pub octaves: u32,
+    pub seed: u32,
+}
+
+impl NoiseDesc {
+    pub fn perlin(size: u32, period: u32) -> Self {
+        Self {
+            kind: NoiseKind::Perlin,
+            size,
+            period,
+            octaves: 4,
+            seed: 0,
+        }
+    }
+
+    pub fn worley(size: u32, period: u32) -> Self {
+        Self {
+            kind: NoiseKind::Worley,
+            size,
+            period,
+            octaves: 3,
+            seed: 0,
+        }
+    }
+
+    pub fn blue(size: u32) -> Self {
+        Self {
+            kind: NoiseKind::Blue,
+            size,
+            period: size,
+            octaves: 1,
+            seed: 0,
+        }
+    }
+}
+
+// The noise textures every app gets, generated at startup.
+#[derive(Debug, Clone)]
+pub struct NoiseTextures {
+    pub perlin: AssetHandle<Texture>,
+    pub worley: AssetHandle<Texture>,
+    pub blue: AssetHandle<Texture>,
+}
+
+// Renders noise with a fullscreen pass and reads it back into `Texture` assets, so they are
+// uploaded, sampled and released like any loaded image without shipping noise PNGs.
+pub struct NoiseGenerator {
+    gpu: Rc<GPU>,
+
+    render_pass: vk::RenderPass,
+    pipeline_layout: vk::PipelineLayout,
+    pipeline: vk::Pipeline,
+    shader_modules: [vk::ShaderModule; 2],
+}
+
+impl NoiseGenerator {
+    pub fn new(gpu: &Rc<GPU>) -> Self {
+        unsafe {
+            let render_pass = Self::create_render_pass(gpu);
+
+            let push_constant_ranges = [vk::PushConstantRange::default()
+                .stage_flags(vk::ShaderStageFlags::ALL_GRAPHICS)
+                .offset(0)
+                .size(size_of::<NoiseParams>() as u32)];
+            let layout_create_info =
+                vk::PipelineLayoutCreateInfo::default().push_constant_ranges(&push_constant_ranges);
+            let pipeline_layout = gpu
+                .device_context
+                .device
+                .create_pipeline_layout(&layout_create_info, None)
+                .expect("failed to create pipeline layout!");
+
+            let shader_modules = [FULLSCREEN_SHADER, NOISE_SHADER].map(|path| {
+                let data = Assets::load_raw(path).unwrap();
+                let mut buffer = io::Cursor::new(&data);
+                let shader_code = ash::util::read_spv(&mut buffer).unwrap();
+                gpu.create_shader_module(&shader_code)
+            });
+            let pipeline =
+                Self::create_pipeline(gpu, &shader_modules, pipeline_layout, render_pass);
+
+            Self {
+                gpu: Rc::clone(gpu),
+                render_pass,
+                pipeline_layout,
+                pipeline,
+                shader_modules,
+            }
+        }
+    }
+
+    pub fn generate(&self, desc: &NoiseDesc) -> Texture {
+        let gpu = &self.gpu;
+        let size = desc.size.max(1);
+        let byte_size = (size * size * 4) as vk::DeviceSize;
+
+        unsafe {
+            let device_context = &gpu.device_context;
+            let (image, image_memory) = device_context.create_image(
+                size,
+                size,
+                1,
+                vk::SampleCountFlags::TYPE_1,
+                FORMAT,
+                vk::ImageTiling::OPTIMAL,
+                vk::ImageUsageFlags::COLOR_ATTACHMENT | vk::ImageUsageFlags::TRANSFER_SRC,
+                vk::MemoryPropertyFlags::DEVICE_LOCAL,
+            );
+            let view =
+                device_context.create_image_view(image, FORMAT, vk::ImageAspectFlags::COLOR, 1);
+            let attachments = [view];
+            let framebuffer_create_info = vk::FramebufferCreateInfo::default()
+                .width(size)
+                .height(size)
+                .layers(1)
+                .attachments(&attachments)
+                .render_pass(self.render_pass);
+            let framebuffer = device_context
+                .device
+                .create_framebuffer(&framebuffer_create_info, None)
+                .expect("failed to create noise framebuffer!");
+            let (readback_buffer, readback_memory) = device_context.create_buffer(
+                byte_size,
+                vk::BufferUsageFlags::TRANSFER_DST,
+                vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT,
+            );
+
+            let pipeline = VkPipeline {
+                pipeline: self.pipeline,
+                layout: self.pipeline_layout,
+            };
+            let noise_params = NoiseParams {
+                kind: desc.kind as u32,
+                period: desc.period.max(1),
+                octaves: desc.octaves,
+                seed: desc.seed,
+                size: [size as f32; 2],
+            };
+
+            let command_buffer = gpu.begin_single_time_command();
+            gpu.begin_pass(
+                command_buffer,
+                &PassDesc {
+                    label: "noise",
+                    render_pass: self.render_pass,
+                    framebuffer,
+                    width: size,
+                    height: size,
+                    clear_color: [0.0; 4],
+                    clear_depth: 1.0,
+                },
+            );
+            gpu.bind_pipeline(command_buffer, &pipeline);
+            gpu.push_constants(
+                command_buffer,
+                &pipeline,
+                std::slice::from_raw_parts(
+                    (&noise_params as *const NoiseParams) as *const u8,
+                    size_of::<NoiseParams>(),
+                ),
+            );
+            gpu.draw(command_buffer, 3);
+            gpu.end_pass(command_buffer);
+
+            // the render pass leaves the image in TRANSFER_SRC_OPTIMAL
+            let region = vk::BufferImageCopy {
+                buffer_offset: 0,
+                buffer_row_length: 0,
+                buffer_image_height: 0,
+                image_subresource: vk::ImageSubresourceLayers {
+                    aspect_mask: vk::ImageAspectFlags::COLOR,
+                    mip_level: 0,
+                    base_array_layer: 0,
+                    layer_count: 1,
+                },
+                image_offset: vk::Offset3D { x: 0, y: 0, z: 0 },
+                image_extent: vk::Extent3D {
+                    width: size,
+                    height: size,
+                    depth: 1,
+                },
+            };
+            device_context.device.cmd_copy_image_to_buffer(
+                command_buffer,
+                image,
+                vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                readback_buffer,
+                &[region],
+            );
+            gpu.end_single_time_command(command_buffer);
+
+            let pixels =
+                std::slice::from_raw_parts(readback_memory.mapped as *const u8, byte_size as usize)
+                    .to_vec();
+
+            device_context.destroy_buffer(readback_buffer, readback_memory);
+            device_context.device.destroy_framebuffer(framebuffer, None);
+            device_context.device.destroy_image_view(view, None);
+            device_context.destroy_image(image, image_memory);
+
+            // filtering noise down averages it out, tiling is what matters
+            let mut texture = Texture::rgba8(size, size, 1, pixels);
+            texture.set_color_space(ColorSpace::Linear);
+            texture
+        }
+    }
+
+    unsafe fn create_render_pass(gpu: &GPU) -> vk::RenderPass {
+        let attachments = [vk::AttachmentDescription {
+            format: FORMAT,
+            samples: vk::SampleCountFlags::TYPE_1,
+            load_op: vk::AttachmentLoadOp::DONT_CARE,
+            store_op: vk::AttachmentStoreOp::STORE,
+            stencil_load_op: vk::AttachmentLoadOp::DONT_CARE,
+            stencil_store_op: vk::AttachmentStoreOp::DONT_CARE,
+            initial_layout: vk::ImageLayout::UNDEFINED,
+            final_layout: vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+            flags: Default::default(),
+        }];
+        let color_attachment_refs = [vk::AttachmentReference {
+            attachment: 0,
+            layout: vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+        }];
+        let sub_passes = [vk::SubpassDescription::default()
+            .pipeline_bind_point(vk::PipelineBindPoint::GRAPHICS)
+            .color_attachments(&color_attachment_refs)];
+
+        // the copy after the pass reads what it wrote
+        let dependencies = [vk::SubpassDependency {
+            src_subpass: 0,
+            src_stage_mask: vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
+            src_access_mask: vk::AccessFlags::COLOR_ATTACHMENT_WRITE,
+            dst_subpass: vk::SUBPASS_EXTERNAL,
+            dst_stage_mask: vk::PipelineStageFlags::TRANSFER,
+            dst_access_mask: vk::AccessFlags::TRANSFER_READ,
+            ..Default::default()
+        }];
+
+        let create_info = vk::RenderPassCreateInfo::default()
+            .attachments(&attachments)
+            .subpasses(&sub_passes)
+            .dependencies(&dependencies);
+
+        gpu.device_context
+            .device
+            .create_render_pass(&create_info, None)
+            .expect("failed to create noise render pass!")
+    }
+
+    unsafe fn create_pipeline(
+        gpu: &GPU,
+        shader_modules: &[vk::ShaderModule; 2],
+        pipeline_layout: vk::PipelineLayout,
+        render_pass: vk::RenderPass,
+    ) -> vk::Pipeline {
+        let vertex_entry = CString::new("vs").unwrap();
+        let fragment_entry = CString::new("fs").unwrap();
+        let shader_stages = [
+            vk::PipelineShaderStageCreateInfo::default()
+                .module(shader_modules[0])
+                .stage(vk::ShaderStageFlags::VERTEX)
+                .name(vertex_entry.as_c_str()),
+            vk::PipelineShaderStageCreateInfo::default()
+                .module(shader_modules[1])
+                .stage(vk::ShaderStageFlags::FRAGMENT)
+                .name(fragment_entry.as_c_str()),
+        ];
+
+        let vertex_input_state = vk::PipelineVertexInputStateCreateInfo::default();
+        let input_assembly_stage = vk::PipelineInputAssemblyStateCreateInfo::default()
+            .topology(vk::PrimitiveTopology::TRIANGLE_LIST);
+        let dynamic_state = vk::PipelineDynamicStateCreateInfo::default()
+            .dynamic_states(&[vk::DynamicState::VIEWPORT, vk::DynamicState::SCISSOR]);
+        let viewport_state = vk::PipelineViewportStateCreateInfo::default()
+            .viewport_count(1)
+            .scissor_count(1);
+        let rasterization_state = vk::PipelineRasterizationStateCreateInfo::default()
+            .cull_mode(vk::CullModeFlags::NONE)
+            .polygon_mode(vk::PolygonMode::FILL)
+            .line_width(1.0);
+        let multisample = vk::PipelineMultisampleStateCreateInfo::default()
+            .rasterization_samples(vk::SampleCountFlags::TYPE_1);
+        let color_attachments = [vk::PipelineColorBlendAttachmentState {
+            blend_enable: false.into(),
+            color_write_mask: vk::ColorComponentFlags::RGBA,
+            ..Default::default()
+        }];
+        let color_blend =
+            vk::PipelineColorBlendStateCreateInfo::default().attachments(&color_attachments);
+        let depth_stencil = vk::PipelineDepthStencilStateCreateInfo::default()
+            .depth_test_enable(false)
+            .depth_write_enable(false);
+
+        let create_info = vk::GraphicsPipelineCreateInfo::default()
+            .stages(&shader_stages)
+            .vertex_input_state(&vertex_input_state)
+            .input_assembly_state(&input_assembly_stage)
+            .dynamic_state(&dynamic_state)
+            .viewport_state(&viewport_state)
+            .rasterization_state(&rasterization_state)
+            .multisample_state(&multisample)
+            .color_blend_state(&color_blend)
+            .depth_stencil_state(&depth_stencil)
+            .layout(pipeline_layout)
+            .render_pass(render_pass)
+            .subpass(0);
+
+        gpu.device_context
+            .device
+            .create_graphics_pipelines(vk::PipelineCache::null(), &[create_info], None)
+            .expect("failed to create noise pipeline!")[0]
+    }
+}
+
+impl Drop for NoiseGenerator {
+    fn drop(&mut self) {
+        unsafe {
+            let device = &self.gpu.device_context.device;
+            device.destroy_pipeline(self.pipeline, None);
+            device.destroy_pipeline_layout(self.pipeline_layout, None);
+            device.destroy_render_pass(self.render_pass, None);
+            self.shader_modules
+                .iter()
+                .for_each(|&shader_module| device.destroy_shader_module(shader_module, None));
+        }
+    }
+}
@@ -0,0 +1,379 @@
+use crate::assets::{AssetHandle, AssetId, Assets, DynamicGeom, Material};
+use crate::gpu::{ComputePipeline, ComputeReader, RawResource, VkBuffer, GPU};
+use crate::math::{Aabb, Vec3};
+use crate::renderer::gpu_geom::GPUGeom;
+use crate::renderer::vertex::Vertex;
+use crate::renderer::{GPUAssets, RenderGeom, RenderObject, ShaderStage, Shading};
+use crate::scene::{DebugNormals, DynamicMesh, Query, StaticMesh, Transform, World};
+use ash::vk;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::io;
+use std::mem::size_of;
+use std::rc::Rc;
+
+// Keep in sync with NORMAL_LENGTH in normal_debug.geom
+pub const DEBUG_NORMAL_LENGTH: f32 = 0.1;
+
+const EXPAND_SHADER: &str = "normal_expand.spv";
+// Keep in sync with @workgroup_size in normal_expand.wgsl
+const WORKGROUP_SIZE: u32 = 64;
+// a normal and a tangent line per corner of a triangle
+const LINE_VERTICES: usize = 12;
+
+// Keep in sync with ExpandParams in normal_expand.wgsl
+#[repr(C)]
+#[derive(Copy, Clone)]
+struct ExpandParams {
+    first_index: u32,
+    vertex_offset: i32,
+    triangles: u32,
+    normal_length: f32,
+}
+
+// The expanded lines of one `DebugNormals`, device local and drawn in place of its line geom.
+struct LineBuffers {
+    vertex_buffer: VkBuffer,
+    index_buffer: VkBuffer,
+    // in vertices
+    capacity: usize,
+}
+
+// Draws per-vertex normals (green) and tangents (red) of meshes tagged with `DebugNormals`.
+// A geometry shader expands the triangles into lines where supported, otherwise a compute pass
+// expands them into a line buffer drawn with a line list pipeline. The buffer is expanded again
+// whenever the mesh changes: another geom, a reloaded one or new vertices of a dynamic one.
+pub struct NormalDebugger {
+    gpu: Rc<GPU>,
+    gpu_assets: Rc<RefCell<GPUAssets>>,
+
+    material: AssetHandle<Material>,
+    // None when the geometry shader draws the lines
+    expand_pipeline: Option<ComputePipeline>,
+    // by the id of the `DebugNormals::lines` handle they are drawn behind
+    lines: HashMap<AssetId, LineBuffers>,
+    // lines to expand by the next `render`, with the geom they come from
+    pending: Vec<(AssetHandle<DynamicGeom>, RenderGeom)>,
+}
+
+impl NormalDebugger {
+    pub fn new(gpu: &Rc<GPU>, gpu_assets: Rc<RefCell<GPUAssets>>, assets: &mut Assets) -> Self {
+        let use_geometry_shader = gpu
+            .device_context
+            .is_shader_stage_supported(vk::ShaderStageFlags::GEOMETRY);
+
+        let mut shading = Shading::load_stages(vec![
+            ShaderStage::new(
+                vk::ShaderStageFlags::VERTEX,
+                "normal_debug.vert.spv",
+                "main",
+            ),
+            ShaderStage::new(
+                vk::ShaderStageFlags::GEOMETRY,
+                "normal_debug.geom.spv",
+                "main",
+            ),
+            ShaderStage::new(
+                vk::ShaderStageFlags::FRAGMENT,
+                "normal_debug.frag.spv",
+                "main",
+            ),
+        ]);
+        shading.name = "NormalDebug";
+        // the GLSL stages are only built with glslc
+        let use_geometry_shader = use_geometry_shader && shading.is_available();
+        let mut expand_pipeline = None;
+        if !use_geometry_shader {
+            shading = Shading::load_debug_line();
+
+            let data = Assets::load_raw(EXPAND_SHADER).unwrap();
+            let mut buffer = io::Cursor::new(&data);
+            let shader_code = ash::util::read_spv(&mut buffer).unwrap();
+            let pipeline = ComputePipeline::new(
+                gpu,
+                &shader_code,
+                "cs",
+                &[vk::DescriptorType::STORAGE_BUFFER; 4],
+                size_of::<ExpandParams>() as u32,
+            );
+            gpu.debug_names.set_name(pipeline.pipeline, "normal expand");
+            expand_pipeline = Some(pipeline);
+        }
+
+        Self {
+            gpu: Rc::clone(gpu),
+            gpu_assets,
+
+            material: assets.handle(Material::new(shading)),
+            expand_pipeline,
+            lines: HashMap::new(),
+            pending: vec![],
+        }
+    }
+
+    pub fn collect(
+        &mut self,
+        world: &mut World,
+        assets: &mut Assets,
+        objects: &mut Vec<RenderObject>,
+    ) {
+        let query = Query::<(
+            &Transform,
+            Option<&StaticMesh>,
+            Option<&DynamicMesh>,
+            &mut DebugNormals,
+        )>::new(world);
+        for (transform, static_mesh, dynamic_mesh, debug_normals) in query {
+            let static_geom = static_mesh.and_then(|static_mesh| static_mesh.geom.clone());
+            let geom: RenderGeom = match (static_geom, dynamic_mesh) {
+                (Some(geom), _) => geom.into(),
+                (None, Some(dynamic_mesh)) => dynamic_mesh.geom.clone().into(),
+                (None, None) => continue,
+            };
+
+            let geom = if self.expand_pipeline.is_none() {
+                geom
+            } else {
+                let Some(lines) = self.update_lines(assets, geom, debug_normals) else {
+                    continue;
+                };
+                lines.into()
+            };
+
+            objects.push(RenderObject::new(
+                geom,
+                self.material.clone(),
+                transform.matrix(),
+            ));
+        }
+    }
+
+    // The line geom of `geom`, one per entity. When the mesh changed since the lines were
+    // expanded it is queued for `render`, the CPU side keeps only the bounds of the lines.
+    fn update_lines(
+        &mut self,
+        assets: &mut Assets,
+        geom: RenderGeom,
+        debug_normals: &mut DebugNormals,
+    ) -> Option<AssetHandle<DynamicGeom>> {
+        let (source, bounds) = match &geom {
+            RenderGeom::Static(handle) => ((handle.id, 0), assets.load(handle)?.bounds),
+            RenderGeom::Dynamic(handle) => {
+                let dynamic_geom = assets.load_mut(handle)?;
+                ((handle.id, dynamic_geom.revision()), dynamic_geom.bounds())
+            }
+        };
+        if let Some((lines, Some(built_from))) = &debug_normals.lines {
+            if *built_from == source {
+                return Some(lines.clone());
+            }
+        }
+
+        let bounds = Self::line_bounds(bounds);
+        let corners = [bounds.min, bounds.max].map(|position| Vertex {
+            position: [position.x, position.y, position.z],
+            color: [0.0, 0.0, 0.0],
+            uv: [0.0, 0.0],
+            normal: [0.0, 0.0, 0.0],
+        });
+        let lines = match &mut debug_normals.lines {
+            Some((lines, built_from)) => {
+                if let Some(dynamic_geom) = assets.load_mut(lines) {
+                    dynamic_geom.set(corners.to_vec(), vec![]);
+                }
+                *built_from = Some(source);
+                lines.clone()
+            }
+            None => {
+                let lines = assets.handle(DynamicGeom::new(corners.to_vec(), vec![]));
+                debug_normals.lines = Some((lines.clone(), Some(source)));
+                lines
+            }
+        };
+        self.pending.push((lines.clone(), geom));
+        Some(lines)
+    }
+
+    // The lines reach past the mesh by up to their length.
+    fn line_bounds(bounds: Aabb) -> Aabb {
+        if bounds.is_empty() {
+            return bounds;
+        }
+        let margin = Vec3::one() * DEBUG_NORMAL_LENGTH;
+        Aabb::new(bounds.min - margin, bounds.max + margin)
+    }
+
+    // Records the expansion of the lines queued by `collect`, outside of a render pass and
+    // before the draws.
+    pub fn render(&mut self, command_buffer: vk::CommandBuffer, frame_index: usize) {
+        let Some(pipeline) = &self.expand_pipeline else {
+            return;
+        };
+        if self.pending.is_empty() {
+            return;
+        }
+
+        // frames still in flight may draw the lines being written over
+        unsafe {
+            self.gpu.device_context.device.cmd_pipeline_barrier(
+                command_buffer,
+                vk::PipelineStageFlags::VERTEX_INPUT,
+                vk::PipelineStageFlags::COMPUTE_SHADER,
+                vk::DependencyFlags::empty(),
+                &[],
+                &[],
+                &[],
+            );
+        }
+
+        let mut gpu_assets = self.gpu_assets.borrow_mut();
+        for (lines, source) in self.pending.drain(..) {
+            let Some(source) = gpu_assets.get_render_geom(&source, frame_index) else {
+                continue;
+            };
+            let triangles = source.indices_length / 3;
+            let vertex_count = triangles * LINE_VERTICES;
+
+            let fits = self
+                .lines
+                .get(&lines.id)
+                .is_some_and(|buffers| vertex_count <= buffers.capacity);
+            if !fits {
+                let buffers = Self::create_buffers(&self.gpu, vertex_count);
+                if let Some(old) = self.lines.insert(lines.id, buffers) {
+                    Self::destroy_buffers(&self.gpu, old);
+                }
+            }
+            let buffers = &self.lines[&lines.id];
+            gpu_assets.replace_dynamic_geom(
+                &lines,
+                GPUGeom {
+                    vertex_buffer: buffers.vertex_buffer,
+                    index_buffer: buffers.index_buffer,
+                    first_index: 0,
+                    vertex_offset: 0,
+                    indices_length: vertex_count,
+                    bounds: Self::line_bounds(source.bounds),
+                },
+            );
+            if triangles == 0 {
+                continue;
+            }
+
+            let set = pipeline.create_sets(&self.gpu, 1)[0];
+            for (binding, buffer) in [
+                source.vertex_buffer,
+                source.index_buffer,
+                buffers.vertex_buffer,
+                buffers.index_buffer,
+            ]
+            .iter()
+            .enumerate()
+            {
+                pipeline.write_buffer(
+                    &self.gpu,
+                    set,
+                    binding as u32,
+                    buffer.buffer,
+                    vk::WHOLE_SIZE,
+                );
+            }
+            let params = ExpandParams {
+                first_index: source.first_index,
+                vertex_offset: source.vertex_offset,
+                triangles: triangles as u32,
+                normal_length: DEBUG_NORMAL_LENGTH,
+            };
+            let params = unsafe {
+                std::slice::from_raw_parts(
+                    (&params as *const ExpandParams) as *const u8,
+                    size_of::<ExpandParams>(),
+                )
+            };
+            pipeline.dispatch(
+                &self.gpu,
+                command_buffer,
+                set,
+                params,
+                [(triangles as u32).div_ceil(WORKGROUP_SIZE), 1, 1],
+            );
+            // reused once the frame is done
+            self.gpu.free_descriptor_sets(&[set]);
+        }
+        ComputePipeline::barrier(&self.gpu, command_buffer, ComputeReader::Vertex);
+    }
+
+    fn create_buffers(gpu: &GPU, vertex_count: usize) -> LineBuffers {
+        // grown by powers of two, a dynamic mesh growing every frame doesn't reallocate every frame
+        let capacity = vertex_count.max(1).next_power_of_two();
+        let create_buffer = |size: usize, usage: vk::BufferUsageFlags| unsafe {
+            let (buffer, memory) = gpu.device_context.create_buffer(
+                size as vk::DeviceSize,
+                vk::BufferUsageFlags::STORAGE_BUFFER | usage,
+                vk::MemoryPropertyFlags::DEVICE_LOCAL,
+            );
+            VkBuffer { buffer, memory }
+        };
+        LineBuffers {
+            vertex_buffer: create_buffer(
+                capacity * size_of::<Vertex>(),
+                vk::BufferUsageFlags::VERTEX_BUFFER,
+            ),
+            index_buffer: create_buffer(
+                capacity * size_of::<u32>(),
+                vk::BufferUsageFlags::INDEX_BUFFER,
+            ),
+            capacity,
+        }
+    }
+
+    // Frames in flight may still draw them.
+    fn destroy_buffers(gpu: &GPU, buffers: LineBuffers) {
+        for buffer in [buffers.vertex_buffer, buffers.index_buffer] {
+            gpu.defer_destroy(RawResource::Buffer(buffer.buffer));
+            gpu.defer_destroy(RawResource::Allocation(buffer.memory));
+        }
+    }
+}
+
+impl Drop for NormalDebugger {
+    fn drop(&mut self) {
+        if let Some(pipeline) = &mut self.expand_pipeline {
+            pipeline.destroy(&self.gpu);
+        }
+        unsafe {
+            let device_context = &self.gpu.device_context;
+            for (_, buffers) in self.lines.drain() {
+                for buffer in [buffers.vertex_buffer, buffers.index_buffer] {
+                    device_context.destroy_buffer(buffer.buffer, buffer.memory);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ExpandParams;
+    use std::mem::size_of;
+
+    #[test]
+    fn expand_params_match_the_shader() {
+        let module =
+            naga::front::wgsl::parse_str(include_str!("../shaders/normal_expand.wgsl")).unwrap();
+        naga::valid::Validator::new(
+            naga::valid::ValidationFlags::all(),
+            naga::valid::Capabilities::all(),
+        )
+        .validate(&module)
+        .unwrap();
+
+        let (_, params) = module
+            .global_variables
+            .iter()
+            .find(|(_, global)| global.space == naga::AddressSpace::PushConstant)
+            .unwrap();
+        let size = module.types[params.ty].inner.size(module.to_ctx());
+        assert_eq!(size as usize, size_of::<ExpandParams>());
+    }
+}
@@ -0,0 +1,142 @@
+use super::forward_renderer::ObjectData;
+use crate::gpu::{Allocation, GPU};
+use ash::vk;
+use std::cell::RefCell;
+use std::mem::size_of;
+use std::rc::Rc;
+
+// Set of the per draw data when it doesn't fit the push constant budget.
+pub const OBJECT_SET: u32 = 2;
+
+struct FrameBuffer {
+    buffer: vk::Buffer,
+    memory: Allocation,
+    // in draws
+    capacity: usize,
+}
+
+// Per draw `ObjectData` in a dynamic uniform buffer, the fallback for pipelines whose payload is
+// over the push constant budget. Every draw of a frame gets its own aligned slot, picked with
+// the dynamic offset when the set is bound.
+pub struct ObjectBuffer {
+    gpu: Rc<GPU>,
+
+    pub descriptor_set_layout: vk::DescriptorSetLayout,
+    descriptor_sets: Vec<vk::DescriptorSet>,
+    frames: RefCell<Vec<FrameBuffer>>,
+    stride: usize,
+}
+
+impl ObjectBuffer {
+    pub fn new(gpu: &Rc<GPU>, frames_in_flight: u32) -> Self {
+        let descriptor_set_layout =
+            gpu.create_descriptor_set_layout(&vec![vk::DescriptorSetLayoutBinding {
+                binding: 0,
+                descriptor_type: vk::DescriptorType::UNIFORM_BUFFER_DYNAMIC,
+                descriptor_count: 1,
+                stage_flags: vk::ShaderStageFlags::ALL_GRAPHICS,
+                ..Default::default()
+            }]);
+        let descriptor_sets =
+            gpu.create_descriptor_sets(&vec![descriptor_set_layout; frames_in_flight as usize]);
+
+        let alignment = gpu
+            .device_context
+            .physical_device_properties
+            .limits
+            .min_uniform_buffer_offset_alignment as usize;
+        let stride = size_of::<ObjectData>().next_multiple_of(alignment.max(1));
+
+        let object_buffer = Self {
+            gpu: Rc::clone(gpu),
+            descriptor_set_layout,
+            descriptor_sets,
+            frames: RefCell::new(vec![]),
+            stride,
+        };
+        let frames = (0..frames_in_flight as usize)
+            .map(|frame_index| object_buffer.create_frame(frame_index, 64))
+            .collect();
+        *object_buffer.frames.borrow_mut() = frames;
+        object_buffer
+    }
+
+    pub fn get_descriptor_set(&self, frame_index: usize) -> vk::DescriptorSet {
+        self.descriptor_sets[frame_index]
+    }
+
+    // Makes room for `count` draws, the frame's previous submission must be done.
+    pub fn reserve(&self, frame_index: usize, count: usize) {
+        let mut frames = self.frames.borrow_mut();
+        if count <= frames[frame_index].capacity {
+            return;
+        }
+
+        let frame = self.create_frame(frame_index, count.next_power_of_two());
+        let old = std::mem::replace(&mut frames[frame_index], frame);
+        unsafe {
+            self.gpu
+                .device_context
+                .destroy_buffer(old.buffer, old.memory);
+        }
+    }
+
+    // Dynamic offset of the slot.
+    pub fn write(&self, frame_index: usize, slot: usize, data: &ObjectData) -> u32 {
+        let frames = self.frames.borrow();
+        let frame = &frames[frame_index];
+        assert!(slot < frame.capacity, "object buffer slot out of range!");
+
+        let offset = slot * self.stride;
+        unsafe {
+            let target = (frame.memory.mapped as *mut u8).add(offset) as *mut ObjectData;
+            target.write_unaligned(*data);
+        }
+        offset as u32
+    }
+
+    fn create_frame(&self, frame_index: usize, capacity: usize) -> FrameBuffer {
+        let (buffer, memory) = self
+            .gpu
+            .create_mapped_buffers((capacity * self.stride) as vk::DeviceSize);
+
+        let buffer_infos = [vk::DescriptorBufferInfo {
+            buffer,
+            offset: 0,
+            range: size_of::<ObjectData>() as vk::DeviceSize,
+        }];
+        let write = vk::WriteDescriptorSet::default()
+            .descriptor_type(vk::DescriptorType::UNIFORM_BUFFER_DYNAMIC)
+            .buffer_info(&buffer_infos)
+            .dst_set(self.descriptor_sets[frame_index])
+            .dst_binding(0)
+            .dst_array_element(0);
+        unsafe {
+            self.gpu
+                .device_context
+                .device
+                .update_descriptor_sets(&[write], &[]);
+        }
+
+        FrameBuffer {
+            buffer,
+            memory,
+            capacity,
+        }
+    }
+}
+
+impl Drop for ObjectBuffer {
+    fn drop(&mut self) {
+        unsafe {
+            let device_context = &self.gpu.device_context;
+            self.frames
+                .borrow()
+                .iter()
+                .for_each(|frame| device_context.destroy_buffer(frame.buffer, frame.memory));
+            device_context
+                .device
+                .destroy_descriptor_set_layout(self.descriptor_set_layout, None);
+        }
+    }
+}
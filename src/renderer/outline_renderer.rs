@@ -0,0 +1,546 @@
+use super::vertex::Vertex;
+use super::{GPUAssets, RenderTarget};
+use crate::assets::{AssetHandle, Assets, Geom};
+use crate::gpu::{PassDesc, Rhi, VkPipeline, GPU};
+use crate::math::Mat4;
+use ash::vk;
+use std::cell::RefCell;
+use std::ffi::CString;
+use std::io;
+use std::mem::size_of;
+use std::rc::Rc;
+
+const MASK_FORMAT: vk::Format = vk::Format::R32_UINT;
+const MASK_SHADER: &str = "outline_mask.spv";
+const FULLSCREEN_SHADER: &str = "fullscreen.spv";
+const COMPOSITE_SHADER: &str = "outline_composite.spv";
+
+#[repr(C)]
+#[derive(Copy, Clone)]
+struct MaskPushConstants {
+    model_view_projection: Mat4,
+    id: u32,
+    _padding: [u32; 3],
+}
+
+#[repr(C)]
+#[derive(Copy, Clone)]
+struct OutlineParams {
+    color: [f32; 4],
+    width: f32,
+    _padding: [f32; 3],
+}
+
+// A mesh to outline, `id` tells touching selections apart and must not be 0.
+pub struct SelectedObject {
+    pub id: u32,
+    pub geom: AssetHandle<Geom>,
+    pub model: Mat4,
+}
+
+// Selection outline as a screen space pass, so it neither needs a stencil buffer nor cares about MSAA.
+// The selected meshes write their id into a single sampled mask at the output resolution, then a
+// fullscreen pass over the final image draws the outline wherever a different id is close by.
+// No depth test, the outline shows through whatever is in front of the selection.
+pub struct OutlineRenderer {
+    gpu: Rc<GPU>,
+
+    pub color: [f32; 4],
+    // in pixels, up to 8
+    pub width: f32,
+
+    output_format: vk::Format,
+    mask_render_pass: vk::RenderPass,
+    composite_render_pass: vk::RenderPass,
+    mask_pipeline_layout: vk::PipelineLayout,
+    composite_pipeline_layout: vk::PipelineLayout,
+    descriptor_set_layout: vk::DescriptorSetLayout,
+    shader_modules: Vec<vk::ShaderModule>,
+    mask_pipeline: vk::Pipeline,
+    composite_pipeline: vk::Pipeline,
+    descriptor_set: vk::DescriptorSet,
+
+    mask: RenderTarget,
+    mask_framebuffer: vk::Framebuffer,
+    composite_framebuffers: Vec<vk::Framebuffer>,
+}
+
+impl OutlineRenderer {
+    pub fn new(gpu: &Rc<GPU>) -> Self {
+        unsafe {
+            let output_format = gpu.swap_chain.borrow().format;
+            let mask_render_pass = Self::create_mask_render_pass(gpu);
+            let composite_render_pass = Self::create_composite_render_pass(gpu, output_format);
+
+            let descriptor_set_layout =
+                gpu.create_descriptor_set_layout(&vec![vk::DescriptorSetLayoutBinding {
+                    binding: 0,
+                    descriptor_type: vk::DescriptorType::SAMPLED_IMAGE,
+                    descriptor_count: 1,
+                    stage_flags: vk::ShaderStageFlags::FRAGMENT,
+                    ..Default::default()
+                }]);
+            let mask_pipeline_layout =
+                Self::create_pipeline_layout(gpu, &[], size_of::<MaskPushConstants>());
+            let composite_pipeline_layout = Self::create_pipeline_layout(
+                gpu,
+                &[descriptor_set_layout],
+                size_of::<OutlineParams>(),
+            );
+
+            let shader_modules = [MASK_SHADER, FULLSCREEN_SHADER, COMPOSITE_SHADER]
+                .iter()
+                .map(|path| {
+                    let data = Assets::load_raw(path).unwrap();
+                    let shader_code = ash::util::read_spv(&mut io::Cursor::new(&data)).unwrap();
+                    gpu.create_shader_module(&shader_code)
+                })
+                .collect::<Vec<_>>();
+            let mask_pipeline = Self::create_pipeline(
+                gpu,
+                (shader_modules[0], shader_modules[0]),
+                true,
+                mask_pipeline_layout,
+                mask_render_pass,
+            );
+            let composite_pipeline = Self::create_pipeline(
+                gpu,
+                (shader_modules[1], shader_modules[2]),
+                false,
+                composite_pipeline_layout,
+                composite_render_pass,
+            );
+            let descriptor_set = gpu.create_descriptor_sets(&[descriptor_set_layout])[0];
+            let (mask, mask_framebuffer, composite_framebuffers) =
+                Self::create_targets(gpu, mask_render_pass, composite_render_pass, descriptor_set);
+
+            Self {
+                gpu: Rc::clone(gpu),
+
+                color: [1.0, 0.6, 0.1, 1.0],
+                width: 2.0,
+
+                output_format,
+                mask_render_pass,
+                composite_render_pass,
+                mask_pipeline_layout,
+                composite_pipeline_layout,
+                descriptor_set_layout,
+                shader_modules,
+                mask_pipeline,
+                composite_pipeline,
+                descriptor_set,
+
+                mask,
+                mask_framebuffer,
+                composite_framebuffers,
+            }
+        }
+    }
+
+    pub fn resize(&mut self) {
+        unsafe {
+            self.destroy_targets();
+
+            let format = self.gpu.swap_chain.borrow().format;
+            if format != self.output_format {
+                let device = &self.gpu.device_context.device;
+                device.destroy_pipeline(self.composite_pipeline, None);
+                device.destroy_render_pass(self.composite_render_pass, None);
+
+                self.composite_render_pass = Self::create_composite_render_pass(&self.gpu, format);
+                self.composite_pipeline = Self::create_pipeline(
+                    &self.gpu,
+                    (self.shader_modules[1], self.shader_modules[2]),
+                    false,
+                    self.composite_pipeline_layout,
+                    self.composite_render_pass,
+                );
+                self.output_format = format;
+            }
+
+            (
+                self.mask,
+                self.mask_framebuffer,
+                self.composite_framebuffers,
+            ) = Self::create_targets(
+                &self.gpu,
+                self.mask_render_pass,
+                self.composite_render_pass,
+                self.descriptor_set,
+            );
+        }
+    }
+
+    // After the post chain, the outline goes on top of the presented image.
+    pub fn render(
+        &self,
+        command_buffer: vk::CommandBuffer,
+        image_index: usize,
+        gpu_assets: &RefCell<GPUAssets>,
+        view_projection: Mat4,
+        objects: &[SelectedObject],
+    ) {
+        if objects.is_empty() {
+            return;
+        }
+        let gpu = &self.gpu;
+        let mut gpu_assets = gpu_assets.borrow_mut();
+
+        let mask_pipeline = VkPipeline {
+            pipeline: self.mask_pipeline,
+            layout: self.mask_pipeline_layout,
+        };
+        gpu.begin_pass(
+            command_buffer,
+            &PassDesc {
+                label: "outline mask",
+                render_pass: self.mask_render_pass,
+                framebuffer: self.mask_framebuffer,
+                width: self.mask.width,
+                height: self.mask.height,
+                // all bits zero, no id
+                clear_color: [0.0; 4],
+                clear_depth: 1.0,
+            },
+        );
+        gpu.bind_pipeline(command_buffer, &mask_pipeline);
+        for object in objects {
+            let Some(geom) = gpu_assets.get_geom(&object.geom) else {
+                continue;
+            };
+            let push_constants = MaskPushConstants {
+                model_view_projection: view_projection * object.model,
+                id: object.id,
+                _padding: [0; 3],
+            };
+            gpu.push_constants(command_buffer, &mask_pipeline, unsafe {
+                std::slice::from_raw_parts(
+                    (&push_constants as *const MaskPushConstants) as *const u8,
+                    size_of::<MaskPushConstants>(),
+                )
+            });
+            gpu.draw_indexed(
+                command_buffer,
+                &geom.vertex_buffer,
+                &geom.index_buffer,
+                geom.first_index..geom.first_index + geom.indices_length as u32,
+                geom.vertex_offset,
+            );
+        }
+        gpu.end_pass(command_buffer);
+
+        let composite_pipeline = VkPipeline {
+            pipeline: self.composite_pipeline,
+            layout: self.composite_pipeline_layout,
+        };
+        let params = OutlineParams {
+            color: self.color,
+            width: self.width,
+            _padding: [0.0; 3],
+        };
+        gpu.begin_pass(
+            command_buffer,
+            &PassDesc {
+                label: "outline composite",
+                render_pass: self.composite_render_pass,
+                framebuffer: self.composite_framebuffers[image_index],
+                width: self.mask.width,
+                height: self.mask.height,
+                clear_color: [0.0; 4],
+                clear_depth: 1.0,
+            },
+        );
+        gpu.bind_pipeline(command_buffer, &composite_pipeline);
+        gpu.bind_resource_sets(
+            command_buffer,
+            &composite_pipeline,
+            0,
+            &[self.descriptor_set],
+        );
+        gpu.push_constants(command_buffer, &composite_pipeline, unsafe {
+            std::slice::from_raw_parts(
+                (&params as *const OutlineParams) as *const u8,
+                size_of::<OutlineParams>(),
+            )
+        });
+        gpu.draw(command_buffer, 3);
+        gpu.end_pass(command_buffer);
+    }
+
+    unsafe fn create_pipeline_layout(
+        gpu: &GPU,
+        set_layouts: &[vk::DescriptorSetLayout],
+        push_constant_size: usize,
+    ) -> vk::PipelineLayout {
+        let push_constant_ranges = [vk::PushConstantRange::default()
+            .stage_flags(vk::ShaderStageFlags::ALL_GRAPHICS)
+            .offset(0)
+            .size(push_constant_size as u32)];
+        let layout_create_info = vk::PipelineLayoutCreateInfo::default()
+            .set_layouts(set_layouts)
+            .push_constant_ranges(&push_constant_ranges);
+
+        gpu.device_context
+            .device
+            .create_pipeline_layout(&layout_create_info, None)
+            .expect("failed to create pipeline layout!")
+    }
+
+    // The mask pipeline draws meshes (positions only), the composite one a fullscreen triangle
+    // blended over the output.
+    unsafe fn create_pipeline(
+        gpu: &GPU,
+        (vertex_module, fragment_module): (vk::ShaderModule, vk::ShaderModule),
+        is_mask: bool,
+        layout: vk::PipelineLayout,
+        render_pass: vk::RenderPass,
+    ) -> vk::Pipeline {
+        let vertex_entry = CString::new("vs").unwrap();
+        let fragment_entry = CString::new("fs").unwrap();
+        let shader_stages = [
+            vk::PipelineShaderStageCreateInfo::default()
+                .module(vertex_module)
+                .stage(vk::ShaderStageFlags::VERTEX)
+                .name(vertex_entry.as_c_str()),
+            vk::PipelineShaderStageCreateInfo::default()
+                .module(fragment_module)
+                .stage(vk::ShaderStageFlags::FRAGMENT)
+                .name(fragment_entry.as_c_str()),
+        ];
+
+        let binding_descriptions = [Vertex::get_binding_description()];
+        let attribute_descriptions = [Vertex::get_attribute_descriptions()[0]];
+        let vertex_input_state = if is_mask {
+            vk::PipelineVertexInputStateCreateInfo::default()
+                .vertex_binding_descriptions(&binding_descriptions)
+                .vertex_attribute_descriptions(&attribute_descriptions)
+        } else {
+            vk::PipelineVertexInputStateCreateInfo::default()
+        };
+        let input_assembly_stage = vk::PipelineInputAssemblyStateCreateInfo::default()
+            .topology(vk::PrimitiveTopology::TRIANGLE_LIST);
+        let dynamic_state = vk::PipelineDynamicStateCreateInfo::default()
+            .dynamic_states(&[vk::DynamicState::VIEWPORT, vk::DynamicState::SCISSOR]);
+        let viewport_state = vk::PipelineViewportStateCreateInfo::default()
+            .viewport_count(1)
+            .scissor_count(1);
+        let rasterization_state = vk::PipelineRasterizationStateCreateInfo::default()
+            .cull_mode(vk::CullModeFlags::NONE)
+            .polygon_mode(vk::PolygonMode::FILL)
+            .line_width(1.0);
+        let multisample = vk::PipelineMultisampleStateCreateInfo::default()
+            .rasterization_samples(vk::SampleCountFlags::TYPE_1);
+        // integer attachments can't blend
+        let color_attachments = [if is_mask {
+            vk::PipelineColorBlendAttachmentState {
+                blend_enable: false.into(),
+                color_write_mask: vk::ColorComponentFlags::R,
+                ..Default::default()
+            }
+        } else {
+            vk::PipelineColorBlendAttachmentState {
+                blend_enable: true.into(),
+                src_color_blend_factor: vk::BlendFactor::SRC_ALPHA,
+                dst_color_blend_factor: vk::BlendFactor::ONE_MINUS_SRC_ALPHA,
+                color_blend_op: vk::BlendOp::ADD,
+                src_alpha_blend_factor: vk::BlendFactor::ZERO,
+                dst_alpha_blend_factor: vk::BlendFactor::ONE,
+                alpha_blend_op: vk::BlendOp::ADD,
+                color_write_mask: vk::ColorComponentFlags::RGBA,
+            }
+        }];
+        let color_blend =
+            vk::PipelineColorBlendStateCreateInfo::default().attachments(&color_attachments);
+        let depth_stencil = vk::PipelineDepthStencilStateCreateInfo::default()
+            .depth_test_enable(false)
+            .depth_write_enable(false);
+
+        let create_info = vk::GraphicsPipelineCreateInfo::default()
+            .stages(&shader_stages)
+            .vertex_input_state(&vertex_input_state)
+            .input_assembly_state(&input_assembly_stage)
+            .dynamic_state(&dynamic_state)
+            .viewport_state(&viewport_state)
+            .rasterization_state(&rasterization_state)
+            .multisample_state(&multisample)
+            .color_blend_state(&color_blend)
+            .depth_stencil_state(&depth_stencil)
+            .layout(layout)
+            .render_pass(render_pass)
+            .subpass(0);
+
+        gpu.device_context
+            .device
+            .create_graphics_pipelines(gpu.pipeline_cache, &[create_info], None)
+            .expect("failed to create outline pipeline!")[0]
+    }
+
+    unsafe fn create_mask_render_pass(gpu: &GPU) -> vk::RenderPass {
+        let attachments = [vk::AttachmentDescription {
+            format: MASK_FORMAT,
+            samples: vk::SampleCountFlags::TYPE_1,
+            load_op: vk::AttachmentLoadOp::CLEAR,
+            store_op: vk::AttachmentStoreOp::STORE,
+            stencil_load_op: vk::AttachmentLoadOp::DONT_CARE,
+            stencil_store_op: vk::AttachmentStoreOp::DONT_CARE,
+            initial_layout: vk::ImageLayout::UNDEFINED,
+            final_layout: vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+            flags: Default::default(),
+        }];
+
+        // the composite of the previous frame has to be done reading the mask
+        let dependencies = [vk::SubpassDependency {
+            src_subpass: vk::SUBPASS_EXTERNAL,
+            src_stage_mask: vk::PipelineStageFlags::FRAGMENT_SHADER,
+            src_access_mask: vk::AccessFlags::SHADER_READ,
+            dst_subpass: 0,
+            dst_stage_mask: vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
+            dst_access_mask: vk::AccessFlags::COLOR_ATTACHMENT_WRITE,
+            ..Default::default()
+        }];
+
+        Self::create_render_pass(gpu, &attachments, &dependencies)
+    }
+
+    unsafe fn create_composite_render_pass(gpu: &GPU, format: vk::Format) -> vk::RenderPass {
+        // drawn on top of what the post chain presented
+        let attachments = [vk::AttachmentDescription {
+            format,
+            samples: vk::SampleCountFlags::TYPE_1,
+            load_op: vk::AttachmentLoadOp::LOAD,
+            store_op: vk::AttachmentStoreOp::STORE,
+            stencil_load_op: vk::AttachmentLoadOp::DONT_CARE,
+            stencil_store_op: vk::AttachmentStoreOp::DONT_CARE,
+            initial_layout: vk::ImageLayout::PRESENT_SRC_KHR,
+            final_layout: vk::ImageLayout::PRESENT_SRC_KHR,
+            flags: Default::default(),
+        }];
+
+        // the mask pass wrote the mask, this one samples it and blends over the output
+        let dependencies = [vk::SubpassDependency {
+            src_subpass: vk::SUBPASS_EXTERNAL,
+            src_stage_mask: vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
+            src_access_mask: vk::AccessFlags::COLOR_ATTACHMENT_WRITE,
+            dst_subpass: 0,
+            dst_stage_mask: vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT
+                | vk::PipelineStageFlags::FRAGMENT_SHADER,
+            dst_access_mask: vk::AccessFlags::COLOR_ATTACHMENT_READ
+                | vk::AccessFlags::COLOR_ATTACHMENT_WRITE
+                | vk::AccessFlags::SHADER_READ,
+            ..Default::default()
+        }];
+
+        Self::create_render_pass(gpu, &attachments, &dependencies)
+    }
+
+    unsafe fn create_render_pass(
+        gpu: &GPU,
+        attachments: &[vk::AttachmentDescription],
+        dependencies: &[vk::SubpassDependency],
+    ) -> vk::RenderPass {
+        let color_attachment_refs = [vk::AttachmentReference {
+            attachment: 0,
+            layout: vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+        }];
+        let sub_passes = [vk::SubpassDescription::default()
+            .pipeline_bind_point(vk::PipelineBindPoint::GRAPHICS)
+            .color_attachments(&color_attachment_refs)];
+
+        let create_info = vk::RenderPassCreateInfo::default()
+            .attachments(attachments)
+            .subpasses(&sub_passes)
+            .dependencies(dependencies);
+
+        gpu.device_context
+            .device
+            .create_render_pass(&create_info, None)
+            .expect("failed to create outline render pass!")
+    }
+
+    unsafe fn create_targets(
+        gpu: &GPU,
+        mask_render_pass: vk::RenderPass,
+        composite_render_pass: vk::RenderPass,
+        descriptor_set: vk::DescriptorSet,
+    ) -> (RenderTarget, vk::Framebuffer, Vec<vk::Framebuffer>) {
+        let swap_chain = gpu.swap_chain.borrow();
+        let extent = swap_chain.extent;
+
+        let mask = RenderTarget::new(gpu, extent.width, extent.height, MASK_FORMAT);
+        let mask_framebuffer = Self::create_framebuffer(gpu, mask_render_pass, mask.view, extent);
+        let composite_framebuffers = swap_chain
+            .image_views
+            .iter()
+            .map(|&image_view| {
+                Self::create_framebuffer(gpu, composite_render_pass, image_view, extent)
+            })
+            .collect();
+
+        // integer textures are read with textureLoad, no sampler
+        let image_infos = [vk::DescriptorImageInfo {
+            image_view: mask.view,
+            image_layout: vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+            sampler: vk::Sampler::null(),
+        }];
+        let mask_write = vk::WriteDescriptorSet::default()
+            .descriptor_type(vk::DescriptorType::SAMPLED_IMAGE)
+            .image_info(&image_infos)
+            .dst_set(descriptor_set)
+            .dst_binding(0);
+        gpu.device_context
+            .device
+            .update_descriptor_sets(&[mask_write], &[]);
+
+        (mask, mask_framebuffer, composite_framebuffers)
+    }
+
+    unsafe fn create_framebuffer(
+        gpu: &GPU,
+        render_pass: vk::RenderPass,
+        image_view: vk::ImageView,
+        extent: vk::Extent2D,
+    ) -> vk::Framebuffer {
+        let attachments = [image_view];
+        let create_info = vk::FramebufferCreateInfo::default()
+            .width(extent.width)
+            .height(extent.height)
+            .layers(1)
+            .attachments(&attachments)
+            .render_pass(render_pass);
+
+        gpu.device_context
+            .device
+            .create_framebuffer(&create_info, None)
+            .expect("failed to create framebuffer!")
+    }
+
+    unsafe fn destroy_targets(&mut self) {
+        let device = &self.gpu.device_context.device;
+        device.destroy_framebuffer(self.mask_framebuffer, None);
+        self.composite_framebuffers
+            .drain(..)
+            .for_each(|framebuffer| device.destroy_framebuffer(framebuffer, None));
+        self.mask.drop(&self.gpu);
+    }
+}
+
+impl Drop for OutlineRenderer {
+    fn drop(&mut self) {
+        unsafe {
+            self.destroy_targets();
+
+            let device = &self.gpu.device_context.device;
+            device.destroy_pipeline(self.mask_pipeline, None);
+            device.destroy_pipeline(self.composite_pipeline, None);
+            self.shader_modules
+                .iter()
+                .for_each(|&shader_module| device.destroy_shader_module(shader_module, None));
+            device.destroy_pipeline_layout(self.mask_pipeline_layout, None);
+            device.destroy_pipeline_layout(self.composite_pipeline_layout, None);
+            device.destroy_descriptor_set_layout(self.descriptor_set_layout, None);
+            device.destroy_render_pass(self.mask_render_pass, None);
+            device.destroy_render_pass(self.composite_render_pass, None);
+        }
+    }
+}
@@ -0,0 +1,179 @@
+use crate::gpu::{Allocation, GPU};
+use ash::vk;
+use std::cell::{Cell, RefCell};
+use std::rc::Rc;
+
+// Set of the per object payload, after the object buffer.
+pub const PAYLOAD_SET: u32 = 3;
+// What a shader may read past the dynamic offset, payloads are cut off there.
+pub const MAX_PAYLOAD_SIZE: usize = 4096;
+
+struct FrameBuffer {
+    buffer: vk::Buffer,
+    memory: Allocation,
+    // in bytes, without the tail of MAX_PAYLOAD_SIZE every buffer has for the last payload
+    capacity: usize,
+}
+
+// The `RenderObject::payload` of every draw of a frame, sub-allocated back to back from a ring
+// of storage buffers (one per frame in flight) at the device's offset alignment. The shader reads
+// it from a dynamic storage buffer on `PAYLOAD_SET`, the offset is picked when the set is bound.
+// Unlike the push constants, whose `ObjectData` is fixed, its size is up to the shading, e.g.
+// material overrides or skinning indices.
+pub struct PayloadBuffer {
+    gpu: Rc<GPU>,
+
+    pub descriptor_set_layout: vk::DescriptorSetLayout,
+    descriptor_sets: Vec<vk::DescriptorSet>,
+    frames: RefCell<Vec<FrameBuffer>>,
+    // next free byte of the frame being drawn
+    cursor: Cell<usize>,
+    alignment: usize,
+}
+
+impl PayloadBuffer {
+    pub fn new(gpu: &Rc<GPU>, frames_in_flight: u32) -> Self {
+        let descriptor_set_layout =
+            gpu.create_descriptor_set_layout(&vec![vk::DescriptorSetLayoutBinding {
+                binding: 0,
+                descriptor_type: vk::DescriptorType::STORAGE_BUFFER_DYNAMIC,
+                descriptor_count: 1,
+                stage_flags: vk::ShaderStageFlags::ALL_GRAPHICS,
+                ..Default::default()
+            }]);
+        let descriptor_sets =
+            gpu.create_descriptor_sets(&vec![descriptor_set_layout; frames_in_flight as usize]);
+
+        let alignment = gpu
+            .device_context
+            .physical_device_properties
+            .limits
+            .min_storage_buffer_offset_alignment as usize;
+
+        let payload_buffer = Self {
+            gpu: Rc::clone(gpu),
+            descriptor_set_layout,
+            descriptor_sets,
+            frames: RefCell::new(vec![]),
+            cursor: Cell::new(0),
+            alignment: alignment.max(1),
+        };
+        let frames = (0..frames_in_flight as usize)
+            .map(|frame_index| payload_buffer.create_frame(frame_index, 4096))
+            .collect();
+        *payload_buffer.frames.borrow_mut() = frames;
+        payload_buffer
+    }
+
+    pub fn get_descriptor_set(&self, frame_index: usize) -> vk::DescriptorSet {
+        self.descriptor_sets[frame_index]
+    }
+
+    // Space the payloads take once aligned, what `begin_frame` has to make room for.
+    pub fn aligned_size<'a>(&self, payloads: impl Iterator<Item = &'a [u8]>) -> usize {
+        payloads
+            .filter(|payload| !payload.is_empty())
+            .map(|payload| {
+                payload
+                    .len()
+                    .min(MAX_PAYLOAD_SIZE)
+                    .next_multiple_of(self.alignment)
+            })
+            .sum()
+    }
+
+    // Starts the frame over from the front of its buffer, growing it to `size` bytes. The frame's
+    // previous submission must be done.
+    pub fn begin_frame(&self, frame_index: usize, size: usize) {
+        self.cursor.set(0);
+        let mut frames = self.frames.borrow_mut();
+        if size <= frames[frame_index].capacity {
+            return;
+        }
+
+        let frame = self.create_frame(frame_index, size.next_power_of_two());
+        let old = std::mem::replace(&mut frames[frame_index], frame);
+        unsafe {
+            self.gpu
+                .device_context
+                .destroy_buffer(old.buffer, old.memory);
+        }
+    }
+
+    // Dynamic offset of the copied payload, past MAX_PAYLOAD_SIZE it is cut off.
+    pub fn push(&self, frame_index: usize, payload: &[u8]) -> u32 {
+        let frames = self.frames.borrow();
+        let frame = &frames[frame_index];
+        let payload = if payload.len() > MAX_PAYLOAD_SIZE {
+            log::warn!(
+                "object payload of {} bytes is over {}, cut off",
+                payload.len(),
+                MAX_PAYLOAD_SIZE
+            );
+            &payload[..MAX_PAYLOAD_SIZE]
+        } else {
+            payload
+        };
+
+        let offset = self.cursor.get();
+        let end = offset + payload.len().next_multiple_of(self.alignment);
+        assert!(end <= frame.capacity, "payload buffer out of space!");
+        unsafe {
+            let target = (frame.memory.mapped as *mut u8).add(offset);
+            std::ptr::copy_nonoverlapping(payload.as_ptr(), target, payload.len());
+        }
+        self.cursor.set(end);
+        offset as u32
+    }
+
+    fn create_frame(&self, frame_index: usize, capacity: usize) -> FrameBuffer {
+        let (buffer, memory) = unsafe {
+            // host visible allocations come persistently mapped
+            self.gpu.device_context.create_buffer(
+                (capacity + MAX_PAYLOAD_SIZE) as vk::DeviceSize,
+                vk::BufferUsageFlags::STORAGE_BUFFER,
+                vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT,
+            )
+        };
+
+        // the range is fixed, the tail keeps the last offset plus it inside the buffer
+        let buffer_infos = [vk::DescriptorBufferInfo {
+            buffer,
+            offset: 0,
+            range: MAX_PAYLOAD_SIZE as vk::DeviceSize,
+        }];
+        let write = vk::WriteDescriptorSet::default()
+            .descriptor_type(vk::DescriptorType::STORAGE_BUFFER_DYNAMIC)
+            .buffer_info(&buffer_infos)
+            .dst_set(self.descriptor_sets[frame_index])
+            .dst_binding(0)
+            .dst_array_element(0);
+        unsafe {
+            self.gpu
+                .device_context
+                .device
+                .update_descriptor_sets(&[write], &[]);
+        }
+
+        FrameBuffer {
+            buffer,
+            memory,
+            capacity,
+        }
+    }
+}
+
+impl Drop for PayloadBuffer {
+    fn drop(&mut self) {
+        unsafe {
+            let device_context = &self.gpu.device_context;
+            self.frames
+                .borrow()
+                .iter()
+                .for_each(|frame| device_context.destroy_buffer(frame.buffer, frame.memory));
+            device_context
+                .device
+                .destroy_descriptor_set_layout(self.descriptor_set_layout, None);
+        }
+    }
+}
@@ -0,0 +1,89 @@
+use crate::gpu::{Allocation, GPU};
+use ash::vk;
+use std::marker::PhantomData;
+use std::mem::{align_of, size_of};
+use std::rc::Rc;
+
+// One persistently mapped uniform buffer per frame in flight holding a `T`.
+// A frame only writes its own copy, so the GPU can still read the others.
+pub struct PerFrameBuffer<T: Copy> {
+    gpu: Rc<GPU>,
+
+    buffers: Vec<vk::Buffer>,
+    memories: Vec<Allocation>,
+
+    _marker: PhantomData<T>,
+}
+
+impl<T: Copy> PerFrameBuffer<T> {
+    pub fn new(gpu: &Rc<GPU>, frames_in_flight: u32) -> Self {
+        let mut buffers = Vec::new();
+        let mut memories = Vec::new();
+
+        for _ in 0..frames_in_flight {
+            let (buffer, memory) = gpu.create_mapped_buffers(Self::size());
+
+            buffers.push(buffer);
+            memories.push(memory);
+        }
+
+        Self {
+            gpu: Rc::clone(gpu),
+
+            buffers,
+            memories,
+
+            _marker: PhantomData,
+        }
+    }
+
+    pub fn size() -> vk::DeviceSize {
+        size_of::<T>() as vk::DeviceSize
+    }
+
+    pub fn write(&self, frame_index: usize, value: &T) {
+        unsafe {
+            let mut align = ash::util::Align::new(
+                self.memories[frame_index].mapped,
+                align_of::<T>() as vk::DeviceSize,
+                Self::size(),
+            );
+            align.copy_from_slice(std::slice::from_ref(value));
+        }
+    }
+
+    // Points `binding` of the descriptor set at this frame's buffer.
+    pub fn bind(&self, frame_index: usize, descriptor_set: vk::DescriptorSet, binding: u32) {
+        let buffer_infos = [vk::DescriptorBufferInfo {
+            buffer: self.buffers[frame_index],
+            offset: 0,
+            range: Self::size(),
+        }];
+        let ubo_write = vk::WriteDescriptorSet::default()
+            .descriptor_type(vk::DescriptorType::UNIFORM_BUFFER)
+            .buffer_info(&buffer_infos)
+            .dst_set(descriptor_set)
+            .dst_binding(binding)
+            // starting element in that array
+            .dst_array_element(0);
+
+        unsafe {
+            self.gpu
+                .device_context
+                .device
+                .update_descriptor_sets(&[ubo_write], &[]);
+        }
+    }
+}
+
+impl<T: Copy> Drop for PerFrameBuffer<T> {
+    fn drop(&mut self) {
+        unsafe {
+            let device_context = &self.gpu.device_context;
+            self.buffers
+                .iter()
+                .zip(&self.memories)
+                .for_each(|(buffer, memory)| device_context.destroy_buffer(*buffer, *memory));
+        }
+    }
+}
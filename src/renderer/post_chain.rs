@@ -0,0 +1,614 @@
+use super::RenderTarget;
+use crate::assets::Assets;
+use crate::gpu::{PassDesc, Rhi, SurfaceFormatMode, VkPipeline, GPU};
+use crate::scene::{PostOverride, PostOverrides};
+use ash::vk;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::ffi::CString;
+use std::io;
+use std::mem::size_of;
+use std::rc::Rc;
+
+const FULLSCREEN_SHADER: &str = "fullscreen.spv";
+const COPY_SHADER: &str = "post_copy.spv";
+const UPSCALE_SHADER: &str = "post_upscale.spv";
+const TONEMAP_SHADER: &str = "post_tonemap.spv";
+const ENCODE_SHADER: &str = "post_encode.spv";
+
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub struct PostParams {
+    pub texel_size: [f32; 2],
+    _padding: [f32; 2],
+    pub params: [f32; 4],
+}
+
+// How `PostEffect::tonemap` squeezes the HDR scene color into the display range.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Tonemapping {
+    // clamps, everything above 1 is lost
+    None,
+    Reinhard,
+    Aces,
+}
+
+// A fullscreen fragment shader reading the previous pass at binding 0 (texture) and 1 (sampler).
+#[derive(Debug, Clone)]
+pub struct PostEffect {
+    pub name: &'static str,
+    pub shader: &'static str,
+    pub enabled: bool,
+    // runs at the output resolution, after the scene color got upscaled
+    pub after_upscale: bool,
+    // effect specific, pushed as `params`
+    pub params: [f32; 4],
+}
+
+impl PostEffect {
+    pub fn new(name: &'static str, shader: &'static str) -> Self {
+        Self {
+            name,
+            shader,
+            enabled: true,
+            after_upscale: false,
+            params: [0.0; 4],
+        }
+    }
+
+    // RCAS-style contrast adaptive sharpening, sharpness in 0..1
+    pub fn sharpen(sharpness: f32) -> Self {
+        let mut effect = Self::new("sharpen", "post_sharpen.spv");
+        effect.after_upscale = true;
+        effect.params[0] = sharpness.clamp(0.0, 1.0);
+        effect
+    }
+
+    // Exposure in stops, scales the scene color by 2^exposure before the operator.
+    pub fn tonemap(tonemapping: Tonemapping, exposure: f32) -> Self {
+        let mut effect = Self::new("tonemap", TONEMAP_SHADER);
+        effect.params[0] = exposure;
+        effect.params[1] = tonemapping as u32 as f32;
+        effect
+    }
+}
+
+// Runs the enabled effects in order, ping-ponging between two targets, the last one writes the swap chain image.
+// The targets have the HDR format of the scene color, the tone mapping effect the chain starts with
+// brings it into the display range for the effects after it.
+// A scene color smaller than the swap chain (dynamic resolution) gets a bicubic upscale step,
+// effects before it run at the internal resolution, `after_upscale` ones at the output resolution.
+// Without any pass to run the scene color is copied as is. A UNORM swap chain that should show
+// sRGB output gets a last pass doing the encoding the hardware would.
+pub struct PostChain {
+    gpu: Rc<GPU>,
+
+    pub effects: Vec<PostEffect>,
+
+    // of the swap chain, the targets have the source's
+    format: vk::Format,
+    offscreen_render_pass: vk::RenderPass,
+    present_render_pass: vk::RenderPass,
+    descriptor_set_layout: vk::DescriptorSetLayout,
+    pipeline_layout: vk::PipelineLayout,
+    sampler: vk::Sampler,
+    shader_modules: RefCell<HashMap<&'static str, vk::ShaderModule>>,
+    // per render pass, the offscreen and present passes differ in format
+    pipelines: RefCell<HashMap<(&'static str, vk::RenderPass), vk::Pipeline>>,
+
+    // source, then one per target
+    descriptor_sets: Vec<vk::DescriptorSet>,
+    source_size: (u32, u32),
+    output_size: (u32, u32),
+    // ping-pong pair at the source size, a second pair at the output size when upscaling
+    targets: Vec<(RenderTarget, vk::Framebuffer)>,
+    present_framebuffers: Vec<vk::Framebuffer>,
+}
+
+impl PostChain {
+    pub fn new(gpu: &Rc<GPU>, source: &RenderTarget) -> Self {
+        unsafe {
+            let format = gpu.swap_chain.borrow().format;
+            let offscreen_render_pass = Self::create_render_pass(
+                gpu,
+                source.format,
+                vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+            );
+            let present_render_pass =
+                Self::create_render_pass(gpu, format, vk::ImageLayout::PRESENT_SRC_KHR);
+
+            let descriptor_set_layout = gpu.create_descriptor_set_layout(&vec![
+                vk::DescriptorSetLayoutBinding {
+                    binding: 0,
+                    descriptor_type: vk::DescriptorType::SAMPLED_IMAGE,
+                    descriptor_count: 1,
+                    stage_flags: vk::ShaderStageFlags::FRAGMENT,
+                    ..Default::default()
+                },
+                vk::DescriptorSetLayoutBinding {
+                    binding: 1,
+                    descriptor_type: vk::DescriptorType::SAMPLER,
+                    descriptor_count: 1,
+                    stage_flags: vk::ShaderStageFlags::FRAGMENT,
+                    ..Default::default()
+                },
+            ]);
+            let push_constant_ranges = [vk::PushConstantRange::default()
+                .stage_flags(vk::ShaderStageFlags::ALL_GRAPHICS)
+                .offset(0)
+                .size(size_of::<PostParams>() as u32)];
+            let descriptor_set_layouts = [descriptor_set_layout];
+            let layout_create_info = vk::PipelineLayoutCreateInfo::default()
+                .set_layouts(&descriptor_set_layouts)
+                .push_constant_ranges(&push_constant_ranges);
+            let pipeline_layout = gpu
+                .device_context
+                .device
+                .create_pipeline_layout(&layout_create_info, None)
+                .expect("failed to create pipeline layout!");
+
+            let sampler_create_info = vk::SamplerCreateInfo::default()
+                .min_filter(vk::Filter::LINEAR)
+                .mag_filter(vk::Filter::LINEAR)
+                .mipmap_mode(vk::SamplerMipmapMode::NEAREST)
+                .address_mode_u(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+                .address_mode_v(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+                .address_mode_w(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+                .max_lod(0.0);
+            let sampler = gpu
+                .device_context
+                .device
+                .create_sampler(&sampler_create_info, None)
+                .expect("failed to create post sampler!");
+
+            let descriptor_sets = gpu.create_descriptor_sets(&[descriptor_set_layout; 5]);
+
+            let mut post_chain = Self {
+                gpu: Rc::clone(gpu),
+
+                effects: vec![PostEffect::tonemap(Tonemapping::Aces, 0.0)],
+
+                format,
+                offscreen_render_pass,
+                present_render_pass,
+                descriptor_set_layout,
+                pipeline_layout,
+                sampler,
+                shader_modules: RefCell::new(HashMap::new()),
+                pipelines: RefCell::new(HashMap::new()),
+
+                descriptor_sets,
+                source_size: (source.width, source.height),
+                output_size: (0, 0),
+                targets: vec![],
+                present_framebuffers: vec![],
+            };
+            post_chain.create_targets(source);
+            post_chain
+        }
+    }
+
+    pub fn get_effect_mut(&mut self, name: &str) -> Option<&mut PostEffect> {
+        self.effects.iter_mut().find(|effect| effect.name == name)
+    }
+
+    // Adds the effect or replaces the one with the same name.
+    pub fn set_effect(&mut self, effect: PostEffect) {
+        match self.get_effect_mut(effect.name) {
+            Some(existing) => *existing = effect,
+            None => self.effects.push(effect),
+        }
+    }
+
+    // The chain's effects with the camera's overrides applied on top, what `render` runs for it.
+    pub fn resolve(&self, overrides: &PostOverrides) -> Vec<PostEffect> {
+        let mut effects = self.effects.clone();
+        for entry in &overrides.stack {
+            let (PostOverride::Enabled(name, _) | PostOverride::Params(name, _)) = entry;
+            let Some(effect) = effects.iter_mut().find(|effect| effect.name == *name) else {
+                continue;
+            };
+            match *entry {
+                PostOverride::Enabled(_, enabled) => effect.enabled = enabled,
+                PostOverride::Params(_, params) => effect.params = params,
+            }
+        }
+        effects
+    }
+
+    // Follows the swap chain, the source is the renderer's (re)created scene color.
+    pub fn resize(&mut self, source: &RenderTarget) {
+        unsafe {
+            self.destroy_targets();
+
+            let format = self.gpu.swap_chain.borrow().format;
+            if format != self.format {
+                let device = &self.gpu.device_context.device;
+                self.pipelines
+                    .borrow_mut()
+                    .drain()
+                    .for_each(|(_, pipeline)| device.destroy_pipeline(pipeline, None));
+                device.destroy_render_pass(self.present_render_pass, None);
+
+                self.present_render_pass =
+                    Self::create_render_pass(&self.gpu, format, vk::ImageLayout::PRESENT_SRC_KHR);
+                self.format = format;
+            }
+
+            self.source_size = (source.width, source.height);
+            self.create_targets(source);
+        }
+    }
+
+    pub fn is_upscaling(&self) -> bool {
+        self.source_size != self.output_size
+    }
+
+    // The surface had no sRGB format, Linear mode leaves the encoding to whoever reads the image.
+    fn is_encoding(&self) -> bool {
+        let swap_chain = self.gpu.swap_chain.borrow();
+        swap_chain.format_mode == SurfaceFormatMode::Srgb
+            && !matches!(
+                swap_chain.format,
+                vk::Format::B8G8R8A8_SRGB
+                    | vk::Format::R8G8B8A8_SRGB
+                    | vk::Format::A8B8G8R8_SRGB_PACK32
+            )
+    }
+
+    pub fn render(
+        &self,
+        command_buffer: vk::CommandBuffer,
+        image_index: usize,
+        effects: &[PostEffect],
+    ) {
+        let gpu = &self.gpu;
+
+        let is_upscaling = self.is_upscaling();
+        let upscale = [PostEffect::new("upscale", UPSCALE_SHADER)];
+        let copy = [PostEffect::new("copy", COPY_SHADER)];
+        let encode = [PostEffect::new("encode", ENCODE_SHADER)];
+        let enabled_effects = effects.iter().filter(|effect| effect.enabled);
+        let mut passes = enabled_effects
+            .clone()
+            .filter(|effect| !effect.after_upscale)
+            .collect::<Vec<_>>();
+        if is_upscaling {
+            passes.push(&upscale[0]);
+        }
+        passes.extend(enabled_effects.filter(|effect| effect.after_upscale));
+        if self.is_encoding() {
+            passes.push(&encode[0]);
+        } else if passes.is_empty() {
+            passes.push(&copy[0]);
+        }
+
+        // 0 is the scene color, 1 + n the target n
+        let mut source_set_index = 0;
+        let mut source_size = self.source_size;
+        let mut upscaled = !is_upscaling;
+        for (index, effect) in passes.iter().enumerate() {
+            upscaled |= effect.shader == UPSCALE_SHADER;
+            let source_set = self.descriptor_sets[source_set_index];
+
+            let is_last = index + 1 == passes.len();
+            let (render_pass, framebuffer, width, height) = if is_last {
+                (
+                    self.present_render_pass,
+                    self.present_framebuffers[image_index],
+                    self.output_size.0,
+                    self.output_size.1,
+                )
+            } else {
+                // never write the target that is being read
+                let first = if upscaled && is_upscaling { 2 } else { 0 };
+                let target_index = if source_set_index == 1 + first {
+                    first + 1
+                } else {
+                    first
+                };
+                source_set_index = 1 + target_index;
+
+                let (target, framebuffer) = &self.targets[target_index];
+                (
+                    self.offscreen_render_pass,
+                    *framebuffer,
+                    target.width,
+                    target.height,
+                )
+            };
+
+            let pipeline = VkPipeline {
+                pipeline: self.get_pipeline(effect.shader, render_pass),
+                layout: self.pipeline_layout,
+            };
+            let post_params = PostParams {
+                texel_size: [1.0 / source_size.0 as f32, 1.0 / source_size.1 as f32],
+                _padding: [0.0; 2],
+                params: effect.params,
+            };
+
+            gpu.begin_pass(
+                command_buffer,
+                &PassDesc {
+                    label: effect.name,
+                    render_pass,
+                    framebuffer,
+                    width,
+                    height,
+                    clear_color: [0.0, 0.0, 0.0, 1.0],
+                    clear_depth: 1.0,
+                },
+            );
+            gpu.bind_pipeline(command_buffer, &pipeline);
+            gpu.bind_resource_sets(command_buffer, &pipeline, 0, &[source_set]);
+            gpu.push_constants(command_buffer, &pipeline, unsafe {
+                std::slice::from_raw_parts(
+                    (&post_params as *const PostParams) as *const u8,
+                    size_of::<PostParams>(),
+                )
+            });
+            gpu.draw(command_buffer, 3);
+            gpu.end_pass(command_buffer);
+
+            source_size = (width, height);
+        }
+    }
+
+    fn get_pipeline(&self, shader: &'static str, render_pass: vk::RenderPass) -> vk::Pipeline {
+        let key = (shader, render_pass);
+        if let Some(pipeline) = self.pipelines.borrow().get(&key) {
+            return *pipeline;
+        }
+
+        let vertex_module = self.get_shader_module(FULLSCREEN_SHADER);
+        let fragment_module = self.get_shader_module(shader);
+        let pipeline = unsafe { self.create_pipeline(vertex_module, fragment_module, render_pass) };
+        self.pipelines.borrow_mut().insert(key, pipeline);
+        pipeline
+    }
+
+    fn get_shader_module(&self, path: &'static str) -> vk::ShaderModule {
+        *self
+            .shader_modules
+            .borrow_mut()
+            .entry(path)
+            .or_insert_with(|| {
+                let data = Assets::load_raw(path).unwrap();
+                let mut buffer = io::Cursor::new(&data);
+                let shader_code = ash::util::read_spv(&mut buffer).unwrap();
+                self.gpu.create_shader_module(&shader_code)
+            })
+    }
+
+    unsafe fn create_pipeline(
+        &self,
+        vertex_module: vk::ShaderModule,
+        fragment_module: vk::ShaderModule,
+        render_pass: vk::RenderPass,
+    ) -> vk::Pipeline {
+        let vertex_entry = CString::new("vs").unwrap();
+        let fragment_entry = CString::new("fs").unwrap();
+        let shader_stages = [
+            vk::PipelineShaderStageCreateInfo::default()
+                .module(vertex_module)
+                .stage(vk::ShaderStageFlags::VERTEX)
+                .name(vertex_entry.as_c_str()),
+            vk::PipelineShaderStageCreateInfo::default()
+                .module(fragment_module)
+                .stage(vk::ShaderStageFlags::FRAGMENT)
+                .name(fragment_entry.as_c_str()),
+        ];
+
+        // the triangle is generated from the vertex index
+        let vertex_input_state = vk::PipelineVertexInputStateCreateInfo::default();
+        let input_assembly_stage = vk::PipelineInputAssemblyStateCreateInfo::default()
+            .topology(vk::PrimitiveTopology::TRIANGLE_LIST);
+        let dynamic_state = vk::PipelineDynamicStateCreateInfo::default()
+            .dynamic_states(&[vk::DynamicState::VIEWPORT, vk::DynamicState::SCISSOR]);
+        let viewport_state = vk::PipelineViewportStateCreateInfo::default()
+            .viewport_count(1)
+            .scissor_count(1);
+        let rasterization_state = vk::PipelineRasterizationStateCreateInfo::default()
+            .cull_mode(vk::CullModeFlags::NONE)
+            .polygon_mode(vk::PolygonMode::FILL)
+            .line_width(1.0);
+        let multisample = vk::PipelineMultisampleStateCreateInfo::default()
+            .rasterization_samples(vk::SampleCountFlags::TYPE_1);
+        let color_attachments = [vk::PipelineColorBlendAttachmentState {
+            blend_enable: false.into(),
+            color_write_mask: vk::ColorComponentFlags::RGBA,
+            ..Default::default()
+        }];
+        let color_blend =
+            vk::PipelineColorBlendStateCreateInfo::default().attachments(&color_attachments);
+        let depth_stencil = vk::PipelineDepthStencilStateCreateInfo::default()
+            .depth_test_enable(false)
+            .depth_write_enable(false);
+
+        let create_info = vk::GraphicsPipelineCreateInfo::default()
+            .stages(&shader_stages)
+            .vertex_input_state(&vertex_input_state)
+            .input_assembly_state(&input_assembly_stage)
+            .dynamic_state(&dynamic_state)
+            .viewport_state(&viewport_state)
+            .rasterization_state(&rasterization_state)
+            .multisample_state(&multisample)
+            .color_blend_state(&color_blend)
+            .depth_stencil_state(&depth_stencil)
+            .layout(self.pipeline_layout)
+            .render_pass(render_pass)
+            .subpass(0);
+
+        self.gpu
+            .device_context
+            .device
+            .create_graphics_pipelines(vk::PipelineCache::null(), &[create_info], None)
+            .expect("failed to create post pipeline!")[0]
+    }
+
+    unsafe fn create_render_pass(
+        gpu: &GPU,
+        format: vk::Format,
+        final_layout: vk::ImageLayout,
+    ) -> vk::RenderPass {
+        // every pixel gets overwritten, nothing to load
+        let attachments = [vk::AttachmentDescription {
+            format,
+            samples: vk::SampleCountFlags::TYPE_1,
+            load_op: vk::AttachmentLoadOp::DONT_CARE,
+            store_op: vk::AttachmentStoreOp::STORE,
+            stencil_load_op: vk::AttachmentLoadOp::DONT_CARE,
+            stencil_store_op: vk::AttachmentStoreOp::DONT_CARE,
+            initial_layout: vk::ImageLayout::UNDEFINED,
+            final_layout,
+            flags: Default::default(),
+        }];
+        let color_attachment_refs = [vk::AttachmentReference {
+            attachment: 0,
+            layout: vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+        }];
+        let sub_passes = [vk::SubpassDescription::default()
+            .pipeline_bind_point(vk::PipelineBindPoint::GRAPHICS)
+            .color_attachments(&color_attachment_refs)];
+
+        // the previous pass wrote its target as an attachment, this one samples it
+        let dependencies = [vk::SubpassDependency {
+            src_subpass: vk::SUBPASS_EXTERNAL,
+            src_stage_mask: vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT
+                | vk::PipelineStageFlags::FRAGMENT_SHADER,
+            src_access_mask: vk::AccessFlags::COLOR_ATTACHMENT_WRITE,
+            dst_subpass: 0,
+            dst_stage_mask: vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT
+                | vk::PipelineStageFlags::FRAGMENT_SHADER,
+            dst_access_mask: vk::AccessFlags::COLOR_ATTACHMENT_WRITE | vk::AccessFlags::SHADER_READ,
+            ..Default::default()
+        }];
+
+        let create_info = vk::RenderPassCreateInfo::default()
+            .attachments(&attachments)
+            .subpasses(&sub_passes)
+            .dependencies(&dependencies);
+
+        gpu.device_context
+            .device
+            .create_render_pass(&create_info, None)
+            .expect("failed to create post render pass!")
+    }
+
+    unsafe fn create_targets(&mut self, source: &RenderTarget) {
+        let swap_chain = self.gpu.swap_chain.borrow();
+        let extent = swap_chain.extent;
+        self.output_size = (extent.width, extent.height);
+
+        let mut sizes = vec![self.source_size; 2];
+        if self.source_size != self.output_size {
+            sizes.extend([self.output_size; 2]);
+        }
+        self.targets = sizes
+            .into_iter()
+            .map(|(width, height)| {
+                let target = RenderTarget::new(&self.gpu, width, height, source.format);
+                let framebuffer =
+                    self.create_framebuffer(self.offscreen_render_pass, target.view, width, height);
+                (target, framebuffer)
+            })
+            .collect();
+        self.present_framebuffers = swap_chain
+            .image_views
+            .iter()
+            .map(|&image_view| {
+                self.create_framebuffer(
+                    self.present_render_pass,
+                    image_view,
+                    extent.width,
+                    extent.height,
+                )
+            })
+            .collect();
+
+        self.write_descriptor_set(self.descriptor_sets[0], source.view);
+        for (index, (target, _)) in self.targets.iter().enumerate() {
+            self.write_descriptor_set(self.descriptor_sets[1 + index], target.view);
+        }
+    }
+
+    unsafe fn create_framebuffer(
+        &self,
+        render_pass: vk::RenderPass,
+        image_view: vk::ImageView,
+        width: u32,
+        height: u32,
+    ) -> vk::Framebuffer {
+        let attachments = [image_view];
+        let create_info = vk::FramebufferCreateInfo::default()
+            .width(width)
+            .height(height)
+            .layers(1)
+            .attachments(&attachments)
+            .render_pass(render_pass);
+
+        self.gpu
+            .device_context
+            .device
+            .create_framebuffer(&create_info, None)
+            .expect("failed to create framebuffer!")
+    }
+
+    unsafe fn write_descriptor_set(&self, set: vk::DescriptorSet, image_view: vk::ImageView) {
+        let image_infos = [vk::DescriptorImageInfo {
+            image_view,
+            image_layout: vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+            sampler: self.sampler,
+        }];
+        let texture_write = vk::WriteDescriptorSet::default()
+            .descriptor_type(vk::DescriptorType::SAMPLED_IMAGE)
+            .image_info(&image_infos)
+            .dst_set(set)
+            .dst_binding(0);
+        let sampler_write = vk::WriteDescriptorSet::default()
+            .descriptor_type(vk::DescriptorType::SAMPLER)
+            .image_info(&image_infos)
+            .dst_set(set)
+            .dst_binding(1);
+
+        self.gpu
+            .device_context
+            .device
+            .update_descriptor_sets(&[texture_write, sampler_write], &[]);
+    }
+
+    unsafe fn destroy_targets(&mut self) {
+        let device = &self.gpu.device_context.device;
+        for (mut target, framebuffer) in self.targets.drain(..) {
+            device.destroy_framebuffer(framebuffer, None);
+            target.drop(&self.gpu);
+        }
+        self.present_framebuffers
+            .drain(..)
+            .for_each(|framebuffer| device.destroy_framebuffer(framebuffer, None));
+    }
+}
+
+impl Drop for PostChain {
+    fn drop(&mut self) {
+        unsafe {
+            self.destroy_targets();
+
+            let device = &self.gpu.device_context.device;
+            self.pipelines
+                .borrow_mut()
+                .drain()
+                .for_each(|(_, pipeline)| device.destroy_pipeline(pipeline, None));
+            self.shader_modules
+                .borrow_mut()
+                .drain()
+                .for_each(|(_, shader_module)| device.destroy_shader_module(shader_module, None));
+            device.destroy_sampler(self.sampler, None);
+            device.destroy_pipeline_layout(self.pipeline_layout, None);
+            device.destroy_descriptor_set_layout(self.descriptor_set_layout, None);
+            device.destroy_render_pass(self.offscreen_render_pass, None);
+            device.destroy_render_pass(self.present_render_pass, None);
+        }
+    }
+}
@@ -1,28 +1,164 @@
 use crate::assets::*;
 use crate::math::Mat4;
-use crate::renderer::GPUAssets;
+use crate::renderer::{
+    DecalObject, GPUAssets, GlobalsData, LightData, ShadowView, TextureCameraView, MAX_PAYLOAD_SIZE,
+};
+use crate::scene::{Dissolve, Entity, Motion, PostOverrides};
 use std::cell::RefCell;
+use std::mem::size_of;
+use std::ops::{BitOr, BitOrAssign};
 use std::rc::Rc;
 
+// Where the triangles of a render object come from.
+#[derive(Debug, Clone)]
+pub enum RenderGeom {
+    Static(AssetHandle<Geom>),
+    // rewritten at runtime, see `DynamicGeom`
+    Dynamic(AssetHandle<DynamicGeom>),
+}
+
+impl From<AssetHandle<Geom>> for RenderGeom {
+    fn from(handle: AssetHandle<Geom>) -> Self {
+        RenderGeom::Static(handle)
+    }
+}
+
+impl From<AssetHandle<DynamicGeom>> for RenderGeom {
+    fn from(handle: AssetHandle<DynamicGeom>) -> Self {
+        RenderGeom::Dynamic(handle)
+    }
+}
+
+// What the passes past the forward one do with a render object.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub struct RenderFlags(u32);
+
+impl RenderFlags {
+    pub const NONE: Self = Self(0);
+    // of a `Selected` entity, drawn into the outline mask
+    pub const SELECTED: Self = Self(1);
+
+    pub fn contains(self, flags: Self) -> bool {
+        self.0 & flags.0 == flags.0
+    }
+}
+
+impl BitOr for RenderFlags {
+    type Output = Self;
+
+    fn bitor(self, rhs: Self) -> Self {
+        Self(self.0 | rhs.0)
+    }
+}
+
+impl BitOrAssign for RenderFlags {
+    fn bitor_assign(&mut self, rhs: Self) {
+        self.0 |= rhs.0;
+    }
+}
+
+#[derive(Clone)]
 pub struct RenderObject {
-    pub geom: AssetHandle<Geom>,
+    pub geom: RenderGeom,
     pub material: AssetHandle<Material>,
     pub model: Mat4,
+    // of the last frame, the same as `model` unless the entity has a `Motion`
+    pub previous_model: Mat4,
+    pub dissolve: Option<Dissolve>,
+    // data of any size past the model and dissolve, for shadings with `object_payload`, which
+    // read whatever follows when it is left empty
+    pub payload: Vec<u8>,
+    // None for the objects of the debug helpers
+    pub entity: Option<Entity>,
+    pub flags: RenderFlags,
 }
 
 impl RenderObject {
-    pub fn new(geom: AssetHandle<Geom>, material: AssetHandle<Material>, model: Mat4) -> Self {
+    pub fn new(geom: impl Into<RenderGeom>, material: AssetHandle<Material>, model: Mat4) -> Self {
         Self {
-            geom,
+            geom: geom.into(),
             material,
             model,
+            previous_model: model,
+            dissolve: None,
+            payload: vec![],
+            entity: None,
+            flags: RenderFlags::NONE,
         }
     }
+
+    // The bytes of `value` as the payload, laid out the way the shader declares it.
+    pub fn set_payload<T: Copy>(&mut self, value: &T) {
+        let bytes =
+            unsafe { std::slice::from_raw_parts((value as *const T) as *const u8, size_of::<T>()) };
+        self.payload = bytes.to_vec();
+    }
+
+    // The previous model matrix and the skinning palettes of this and the last frame as the
+    // payload, laid out the way object_motion.glsl reads it: the previous model, the joint count
+    // in a uvec4, then the current palette followed by the previous one. Replaces any other
+    // payload, palettes past MAX_MOTION_JOINTS are cut off.
+    pub fn set_motion(&mut self, motion: &Motion) {
+        self.previous_model = motion.previous_model();
+
+        let mut joints = motion.palette().len();
+        if joints > MAX_MOTION_JOINTS {
+            log::warn!(
+                "{} joints don't fit the object payload, only the first {} are skinned",
+                joints,
+                MAX_MOTION_JOINTS
+            );
+            joints = MAX_MOTION_JOINTS;
+        }
+        let previous_palette = motion.previous_palette();
+
+        let mut matrices = vec![self.previous_model];
+        matrices.extend_from_slice(&motion.palette()[..joints]);
+        matrices.extend_from_slice(&previous_palette[..joints.min(previous_palette.len())]);
+        let mut payload = Vec::with_capacity(MAX_PAYLOAD_SIZE);
+        for (index, matrix) in matrices.iter().enumerate() {
+            // column major, like a GLSL mat4
+            for col in 0..4 {
+                for row in 0..4 {
+                    payload.extend(matrix[col][row].to_ne_bytes());
+                }
+            }
+            if index == 0 {
+                payload.extend((joints as u32).to_ne_bytes());
+                payload.extend([0; 12]);
+            }
+        }
+        self.payload = payload;
+    }
 }
 
+// What fits the payload next to the previous model, with the palettes of two frames.
+pub const MAX_MOTION_JOINTS: usize = (MAX_PAYLOAD_SIZE - 80) / 128;
+
 pub struct RenderContext {
     pub gpu_assets: Rc<RefCell<GPUAssets>>,
     pub view: Mat4,
     pub projection: Mat4,
+    // what the objects were culled with, the camera's view projection unless culling is frozen
+    pub culling: Mat4,
+    // the screen size is filled in by the forward renderer
+    pub globals: GlobalsData,
+    // inside the culling frustum, with their geoms loaded. Those of indirect shadings are culled
+    // later, on the GPU
     pub objects: Vec<RenderObject>,
+    pub lights: Vec<LightData>,
+    // of the camera the context is rendered from
+    pub post_overrides: PostOverrides,
+}
+
+// Everything a frame renders, read from the world before its first command is recorded, see
+// `Mirage::extract_render`. The passes only get this, none of them borrows the ECS storage while
+// recording, so the world is free to change once extraction is done.
+pub struct RenderExtract {
+    pub context: RenderContext,
+    // culled against their own frustums
+    pub texture_cameras: Vec<TextureCameraView>,
+    // the tiles of the shadow casting lights, drawn into the shadow atlas first
+    pub shadows: Vec<ShadowView>,
+    pub decals: Vec<DecalObject>,
 }
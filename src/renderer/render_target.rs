@@ -0,0 +1,51 @@
+use crate::gpu::{Allocation, GPU};
+use ash::vk;
+
+// Single sampled color image a pass renders into and a later pass reads from.
+#[derive(Debug, Copy, Clone)]
+pub struct RenderTarget {
+    pub image: vk::Image,
+    pub memory: Allocation,
+    pub view: vk::ImageView,
+    pub format: vk::Format,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl RenderTarget {
+    pub fn new(gpu: &GPU, width: u32, height: u32, format: vk::Format) -> Self {
+        unsafe {
+            let (image, memory) = gpu.device_context.create_image(
+                width,
+                height,
+                1,
+                vk::SampleCountFlags::TYPE_1,
+                format,
+                vk::ImageTiling::OPTIMAL,
+                vk::ImageUsageFlags::COLOR_ATTACHMENT | vk::ImageUsageFlags::SAMPLED,
+                vk::MemoryPropertyFlags::DEVICE_LOCAL,
+            );
+            let view =
+                gpu.device_context
+                    .create_image_view(image, format, vk::ImageAspectFlags::COLOR, 1);
+
+            Self {
+                image,
+                memory,
+                view,
+                format,
+                width,
+                height,
+            }
+        }
+    }
+
+    pub fn drop(&mut self, gpu: &GPU) {
+        unsafe {
+            gpu.device_context
+                .device
+                .destroy_image_view(self.view, None);
+            gpu.device_context.destroy_image(self.image, self.memory);
+        }
+    }
+}
@@ -0,0 +1,209 @@
+use crate::renderer::ShaderHooks;
+use ash::vk;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime};
+
+// Checking every file each frame is wasteful, edits are picked up within this interval.
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+// Compiles the shader sources of src/shaders at runtime, the same way build.rs does ahead of time,
+// and reports the ones changed on disk so their pipelines can be rebuilt while the app runs.
+// Only available where the sources are, in debug builds run from the repository.
+pub struct ShaderCompiler {
+    dir: Option<PathBuf>,
+    // modification time of each source at the last poll, by compiled name
+    sources: HashMap<String, SystemTime>,
+    last_poll: Instant,
+}
+
+impl ShaderCompiler {
+    pub fn new() -> Self {
+        let dir = Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("src")
+            .join("shaders");
+        let dir = (cfg!(debug_assertions) && dir.is_dir()).then_some(dir);
+
+        let mut compiler = Self {
+            dir,
+            sources: HashMap::new(),
+            last_poll: Instant::now(),
+        };
+        compiler.sources = compiler.scan();
+        compiler
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.dir.is_some()
+    }
+
+    // Names of the compiled shaders ("simple.spv", "terrain.vert.spv") whose sources changed.
+    pub fn poll(&mut self) -> Vec<String> {
+        if !self.is_enabled() || self.last_poll.elapsed() < POLL_INTERVAL {
+            return vec![];
+        }
+        self.last_poll = Instant::now();
+
+        let sources = self.scan();
+        let changed = sources
+            .iter()
+            .filter(|(name, modified)| self.sources.get(*name) != Some(modified))
+            .map(|(name, _)| name.clone())
+            .collect();
+        self.sources = sources;
+        changed
+    }
+
+    // SPIR-V of the shader from its current source, the error message is ready to be logged.
+    pub fn compile(&self, name: &str) -> Result<Vec<u32>, String> {
+        let path = self
+            .source_path(name)
+            .ok_or_else(|| format!("no source found for shader {}", name))?;
+        Self::compile_file(&path)
+    }
+
+    // SPIR-V of a WGSL or GLSL source file anywhere on disk, the stage of GLSL comes from the extension.
+    pub fn compile_file(path: &Path) -> Result<Vec<u32>, String> {
+        let source = fs::read_to_string(path)
+            .map_err(|error| format!("failed to read {}: {}", path.display(), error))?;
+
+        if path
+            .extension()
+            .is_some_and(|extension| extension == "wgsl")
+        {
+            Self::compile_wgsl(&source)
+        } else {
+            let stage = match path.extension().and_then(|extension| extension.to_str()) {
+                Some("vert") => vk::ShaderStageFlags::VERTEX,
+                Some("frag") => vk::ShaderStageFlags::FRAGMENT,
+                Some("comp") => vk::ShaderStageFlags::COMPUTE,
+                // tessellation and geometry stages need glslc, see build.rs
+                _ => {
+                    return Err(format!(
+                        "shader {} can't be compiled at runtime",
+                        path.display()
+                    ))
+                }
+            };
+            ShaderHooks::compile(&source, stage)
+        }
+    }
+
+    fn compile_wgsl(source: &str) -> Result<Vec<u32>, String> {
+        let module =
+            naga::front::wgsl::parse_str(source).map_err(|error| error.emit_to_string(source))?;
+        let info = naga::valid::Validator::new(
+            naga::valid::ValidationFlags::all(),
+            naga::valid::Capabilities::all(),
+        )
+        .validate(&module)
+        .map_err(|error| error.emit_to_string(source))?;
+
+        // all entry points in one module, same as `naga --keep-coordinate-space` in build.rs
+        let mut options = naga::back::spv::Options::default();
+        options
+            .flags
+            .remove(naga::back::spv::WriterFlags::ADJUST_COORDINATE_SPACE);
+        naga::back::spv::write_vec(&module, &info, &options, None)
+            .map_err(|error| error.to_string())
+    }
+
+    // Moves the push constant block of compiled SPIR-V into a uniform buffer at the binding,
+    // same members and layout. Modules without push constants come back unchanged.
+    pub fn push_constants_to_uniform(
+        code: &[u32],
+        group: u32,
+        binding: u32,
+    ) -> Result<Vec<u32>, String> {
+        if !Self::has_push_constants(code) {
+            return Ok(code.to_vec());
+        }
+
+        let bytes = code
+            .iter()
+            .flat_map(|word| word.to_le_bytes())
+            .collect::<Vec<_>>();
+        let options = naga::front::spv::Options {
+            adjust_coordinate_space: false,
+            ..Default::default()
+        };
+        let mut module = naga::front::spv::parse_u8_slice(&bytes, &options)
+            .map_err(|error| error.to_string())?;
+        for (_, variable) in module.global_variables.iter_mut() {
+            if variable.space == naga::AddressSpace::PushConstant {
+                variable.space = naga::AddressSpace::Uniform;
+                variable.binding = Some(naga::ResourceBinding { group, binding });
+            }
+        }
+
+        let info = naga::valid::Validator::new(
+            naga::valid::ValidationFlags::all(),
+            naga::valid::Capabilities::all(),
+        )
+        .validate(&module)
+        .map_err(|error| format!("{:?}", error))?;
+        let mut options = naga::back::spv::Options::default();
+        options
+            .flags
+            .remove(naga::back::spv::WriterFlags::ADJUST_COORDINATE_SPACE);
+        naga::back::spv::write_vec(&module, &info, &options, None)
+            .map_err(|error| error.to_string())
+    }
+
+    // Scans for an OpVariable in the PushConstant storage class, without parsing the module.
+    fn has_push_constants(code: &[u32]) -> bool {
+        const OP_VARIABLE: u32 = 59;
+        const STORAGE_CLASS_PUSH_CONSTANT: u32 = 9;
+
+        // the header is 5 words
+        let mut index = 5;
+        while index < code.len() {
+            let word_count = (code[index] >> 16) as usize;
+            let opcode = code[index] & 0xffff;
+            // result type, result id, storage class
+            if opcode == OP_VARIABLE && code.get(index + 3) == Some(&STORAGE_CLASS_PUSH_CONSTANT) {
+                return true;
+            }
+            index += word_count.max(1);
+        }
+        false
+    }
+
+    // simple.spv <- simple.wgsl, terrain.vert.spv <- terrain.vert
+    fn source_path(&self, name: &str) -> Option<PathBuf> {
+        let dir = self.dir.as_ref()?;
+        let stem = name.strip_suffix(".spv")?;
+        let path = if Path::new(stem).extension().is_some() {
+            dir.join(stem)
+        } else {
+            dir.join(format!("{}.wgsl", stem))
+        };
+        path.is_file().then_some(path)
+    }
+
+    fn scan(&self) -> HashMap<String, SystemTime> {
+        let Some(entries) = self.dir.as_ref().and_then(|dir| fs::read_dir(dir).ok()) else {
+            return HashMap::new();
+        };
+
+        entries
+            .flatten()
+            .filter_map(|entry| {
+                let file_name = entry.file_name().into_string().ok()?;
+                let name = match file_name.strip_suffix(".wgsl") {
+                    Some(stem) => format!("{}.spv", stem),
+                    None => {
+                        let extension = Path::new(&file_name).extension()?.to_str()?;
+                        if !["vert", "tesc", "tese", "geom", "frag", "comp"].contains(&extension) {
+                            return None;
+                        }
+                        format!("{}.spv", file_name)
+                    }
+                };
+                let modified = entry.metadata().ok()?.modified().ok()?;
+                Some((name, modified))
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,136 @@
+use ash::vk;
+
+const STANDARD_VERTEX_TEMPLATE: &str = include_str!("../shaders/standard.vert.glsl");
+const STANDARD_FRAGMENT_TEMPLATE: &str = include_str!("../shaders/standard.frag.glsl");
+// headers runtime compiled GLSL can `#include`, naga has no include support of its own
+const INCLUDES: [(&str, &str); 2] = [
+    (
+        "engine_globals.glsl",
+        include_str!("../shaders/engine_globals.glsl"),
+    ),
+    (
+        "object_motion.glsl",
+        include_str!("../shaders/object_motion.glsl"),
+    ),
+];
+
+// GLSL function bodies injected into the standard shader, a lightweight alternative to the node graph.
+//   vertex_offset: vec3 vertex_offset(vec3 position, vec3 normal, vec2 uv), object space offset
+//   albedo_modify: vec3 albedo_modify(vec3 albedo, vec2 uv), sampled texture color in, final albedo out
+//   emissive_add:  vec3 emissive_add(vec2 uv), added on top of the albedo
+// e.g. `return normal * sin(uv.x * 20.0) * 0.05;` as vertex offset, or with the engine globals
+// `return normal * sin(globals.time.x + uv.x * 20.0) * 0.05;` to animate it
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ShaderHooks {
+    pub vertex_offset: Option<String>,
+    pub albedo_modify: Option<String>,
+    pub emissive_add: Option<String>,
+}
+
+impl ShaderHooks {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn vertex_source(&self) -> String {
+        Self::stitch(
+            STANDARD_VERTEX_TEMPLATE,
+            &[("vertex_offset", &self.vertex_offset)],
+        )
+    }
+
+    pub fn fragment_source(&self) -> String {
+        Self::stitch(
+            STANDARD_FRAGMENT_TEMPLATE,
+            &[
+                ("albedo_modify", &self.albedo_modify),
+                ("emissive_add", &self.emissive_add),
+            ],
+        )
+    }
+
+    // Replaces the default body between `//#hook <name>` and `//#end` with the snippet.
+    fn stitch(template: &str, hooks: &[(&str, &Option<String>)]) -> String {
+        let mut source = String::with_capacity(template.len());
+        let mut skipping = false;
+
+        for line in template.lines() {
+            let trimmed = line.trim();
+            if let Some(name) = trimmed.strip_prefix("//#hook ") {
+                let snippet = hooks
+                    .iter()
+                    .find(|(hook, _)| *hook == name.trim())
+                    .and_then(|(_, snippet)| snippet.as_ref());
+                if let Some(snippet) = snippet {
+                    source.push_str(snippet);
+                    source.push('\n');
+                    skipping = true;
+                }
+                continue;
+            }
+            if trimmed == "//#end" {
+                skipping = false;
+                continue;
+            }
+            if !skipping {
+                source.push_str(line);
+                source.push('\n');
+            }
+        }
+
+        source
+    }
+
+    // Pastes the `INCLUDES` in place of their `#include "<name>"` lines.
+    fn expand_includes(source: &str) -> Result<String, String> {
+        let mut expanded = String::with_capacity(source.len());
+        for line in source.lines() {
+            match line.trim().strip_prefix("#include") {
+                Some(name) => {
+                    let name = name.trim().trim_matches('"');
+                    let (_, header) = INCLUDES
+                        .iter()
+                        .find(|(include, _)| *include == name)
+                        .ok_or_else(|| format!("unknown include {}", name))?;
+                    expanded.push_str(header);
+                }
+                None => expanded.push_str(line),
+            }
+            expanded.push('\n');
+        }
+        Ok(expanded)
+    }
+
+    pub fn compile(source: &str, stage: vk::ShaderStageFlags) -> Result<Vec<u32>, String> {
+        let source = &Self::expand_includes(source)?;
+        let naga_stage = match stage {
+            vk::ShaderStageFlags::VERTEX => naga::ShaderStage::Vertex,
+            vk::ShaderStageFlags::FRAGMENT => naga::ShaderStage::Fragment,
+            vk::ShaderStageFlags::COMPUTE => naga::ShaderStage::Compute,
+            _ => return Err(format!("stage {:?} can't be compiled at runtime", stage)),
+        };
+
+        let module = naga::front::glsl::Frontend::default()
+            .parse(&naga::front::glsl::Options::from(naga_stage), source)
+            .map_err(|error| error.emit_to_string(source))?;
+        let info = naga::valid::Validator::new(
+            naga::valid::ValidationFlags::all(),
+            naga::valid::Capabilities::all(),
+        )
+        .validate(&module)
+        .map_err(|error| error.emit_to_string(source))?;
+
+        // same as `naga --keep-coordinate-space` in build.rs
+        let mut options = naga::back::spv::Options::default();
+        options
+            .flags
+            .remove(naga::back::spv::WriterFlags::ADJUST_COORDINATE_SPACE);
+        let pipeline_options = naga::back::spv::PipelineOptions {
+            shader_stage: naga_stage,
+            entry_point: "main".to_string(),
+        };
+
+        naga::back::spv::write_vec(&module, &info, &options, Some(&pipeline_options))
+            .map_err(|error| error.to_string())
+    }
+}
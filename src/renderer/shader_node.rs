@@ -1,5 +1,8 @@
 use ash::vk;
 
+// A node of the shading graph. Only the bindings are read so far, they lay out the material set,
+// the ids and the links between the nodes are there for when the graph gets evaluated.
+#[allow(dead_code)]
 pub enum ShaderNode<'a> {
     Texture {
         id: &'a str,
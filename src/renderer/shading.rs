@@ -1,27 +1,213 @@
 use super::*;
+use crate::assets::Assets;
+use crate::error_overlay;
 use ash::vk;
-use egui::ahash::HashMap;
+use std::rc::Rc;
 
 #[derive(Debug, Copy, Clone, PartialEq)]
 pub enum ShadingMode {
     Unlit,
 }
 
+// A field of the material parameter block, laid out by std140 rules.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum ParamKind {
+    Float,
+    Vec2,
+    Vec3,
+    Vec4,
+    // a vec4, rgba
+    Color,
+}
+
+impl ParamKind {
+    fn align(self) -> u32 {
+        match self {
+            ParamKind::Float => 4,
+            ParamKind::Vec2 => 8,
+            ParamKind::Vec3 | ParamKind::Vec4 | ParamKind::Color => 16,
+        }
+    }
+
+    pub fn size(self) -> u32 {
+        match self {
+            ParamKind::Float => 4,
+            ParamKind::Vec2 => 8,
+            ParamKind::Vec3 => 12,
+            ParamKind::Vec4 | ParamKind::Color => 16,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ShaderStage {
+    pub stage: vk::ShaderStageFlags,
+    pub path: &'static str,
+    pub entry: &'static str,
+    // SPIR-V compiled at runtime, `path` is only used to tell modules apart then
+    pub code: Option<Rc<Vec<u32>>>,
+}
+
+impl ShaderStage {
+    pub fn new(stage: vk::ShaderStageFlags, path: &'static str, entry: &'static str) -> Self {
+        Self {
+            stage,
+            path,
+            entry,
+            code: None,
+        }
+    }
+
+    pub fn with_code(stage: vk::ShaderStageFlags, path: &'static str, code: Vec<u32>) -> Self {
+        Self {
+            stage,
+            path,
+            entry: "main",
+            code: Some(Rc::new(code)),
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct Shading {
     pub id: u32,
     pub name: &'static str,
     pub path: &'static str,
+    // Stages can live in a single module (wgsl with several entry points)
+    // or be split across files (one spv per stage).
+    pub stages: Vec<ShaderStage>,
     pub mode: ShadingMode,
     pub depth_test: bool,
     pub depth_write: bool,
+    // the fragment alpha becomes the MSAA sample coverage, smooth cutout edges without sorting
+    pub alpha_to_coverage: bool,
+    // alpha blended after the opaque objects, back to front, and without writing depth
+    pub transparent: bool,
+    // reads `RenderObject::payload` from a storage buffer on PAYLOAD_SET
+    pub object_payload: bool,
+    // reads its `ObjectData` by instance from the storage buffers of `GPUCulling` on OBJECT_SET,
+    // drawn in GPU culled batches without push constants or an object payload
+    pub indirect: bool,
+    pub topology: vk::PrimitiveTopology,
+    // control points per patch when tessellation stages are present
+    pub patch_control_points: u32,
     pub bindings: Vec<vk::DescriptorSetLayoutBinding<'static>>,
+    // material texture slot n is bound at 2n (texture) and 2n + 1 (sampler)
+    pub texture_slots: Vec<&'static str>,
+    // fields of the uniform block at PARAMS_BINDING in declaration order, see `set_params`
+    pub params: Vec<(&'static str, ParamKind)>,
+    // the snippets `load_standard` stitched in, none for the other shadings
+    pub hooks: ShaderHooks,
     // pub inputs: HashMap<&str, ?>
 }
 
 impl Shading {
+    // past the bindings of any number of texture slots a shading would have
+    pub const PARAMS_BINDING: u32 = 32;
+
     pub fn load(path: &'static str) -> Self {
+        Self::load_stages(vec![
+            ShaderStage::new(vk::ShaderStageFlags::VERTEX, path, "vs"),
+            ShaderStage::new(vk::ShaderStageFlags::FRAGMENT, path, "fs"),
+        ])
+    }
+
+    // False when a stage's SPIR-V isn't in the assets, e.g. GLSL stages of a build without glslc.
+    pub fn is_available(&self) -> bool {
+        self.stages
+            .iter()
+            .all(|stage| stage.code.is_some() || Assets::load_raw(stage.path).is_some())
+    }
+
+    // Vertex -> tessellation control -> tessellation evaluation -> fragment, the evaluation
+    // stage displaces the tessellated surface by the material texture.
+    pub fn load_terrain() -> Self {
+        let mut shading = Self::load_stages(vec![
+            ShaderStage::new(vk::ShaderStageFlags::VERTEX, "terrain.vert.spv", "main"),
+            ShaderStage::new(
+                vk::ShaderStageFlags::TESSELLATION_CONTROL,
+                "terrain.tesc.spv",
+                "main",
+            ),
+            ShaderStage::new(
+                vk::ShaderStageFlags::TESSELLATION_EVALUATION,
+                "terrain.tese.spv",
+                "main",
+            ),
+            ShaderStage::new(vk::ShaderStageFlags::FRAGMENT, "terrain.frag.spv", "main"),
+        ]);
+        shading.name = "Terrain";
+        shading
+    }
+
+    // The standard shader with the material's GLSL hooks stitched in. A snippet that fails to
+    // compile is reported and the material falls back to the default hook bodies.
+    pub fn load_standard(hooks: &ShaderHooks) -> Self {
+        let compile = |hooks: &ShaderHooks| -> Result<(Vec<u32>, Vec<u32>), String> {
+            let vertex =
+                ShaderHooks::compile(&hooks.vertex_source(), vk::ShaderStageFlags::VERTEX)?;
+            let fragment =
+                ShaderHooks::compile(&hooks.fragment_source(), vk::ShaderStageFlags::FRAGMENT)?;
+            Ok((vertex, fragment))
+        };
+
+        let (vertex, fragment) = compile(hooks).unwrap_or_else(|error| {
+            let message = format!("failed to compile, using defaults!\n{}", error);
+            error_overlay::report("shader hooks", message);
+            compile(&ShaderHooks::default()).expect("failed to compile standard shader!")
+        });
+
+        let mut shading = Self::load_stages(vec![
+            ShaderStage::with_code(vk::ShaderStageFlags::VERTEX, "standard.vert.glsl", vertex),
+            ShaderStage::with_code(
+                vk::ShaderStageFlags::FRAGMENT,
+                "standard.frag.glsl",
+                fragment,
+            ),
+        ]);
+        shading.name = "Standard";
+        shading.hooks = hooks.clone();
+        // dissolve and alpha cutouts
+        shading.alpha_to_coverage = true;
+        shading
+    }
+
+    // Unlit colored line list, position + vertex color only.
+    pub fn load_debug_line() -> Self {
+        let mut shading = Self::load("debug_line.spv");
+        shading.name = "DebugLine";
+        shading.topology = vk::PrimitiveTopology::LINE_LIST;
+        shading
+    }
+
+    // Unlit textured ribbons, the alpha comes in with the vertices and becomes coverage.
+    pub fn load_trail() -> Self {
+        let mut shading = Self::load("trail.spv");
+        shading.name = "Trail";
+        shading.alpha_to_coverage = true;
+        shading
+    }
+
+    // The simple shader drawn with GPU culled indirect draws, for scenes of many objects
+    // sharing a few materials and meshes.
+    pub fn load_indirect() -> Self {
+        let mut shading = Self::load("simple_indirect.spv");
+        shading.name = "SimpleIndirect";
+        shading.indirect = true;
+        shading
+    }
+
+    pub fn load_stages(stages: Vec<ShaderStage>) -> Self {
         let mut bindings: Vec<vk::DescriptorSetLayoutBinding> = vec![];
+        // textures are sampled for displacement too when an evaluation stage exists
+        let extra_stage = if stages
+            .iter()
+            .any(|stage| stage.stage == vk::ShaderStageFlags::TESSELLATION_EVALUATION)
+        {
+            vk::ShaderStageFlags::TESSELLATION_EVALUATION
+        } else {
+            vk::ShaderStageFlags::empty()
+        };
 
         SIMPLE_SHADER_NODES.iter().for_each(|node| match node {
             ShaderNode::Texture { binding, stage, .. } => {
@@ -29,7 +215,21 @@ impl Shading {
                     binding: *binding,
                     descriptor_type: vk::DescriptorType::SAMPLED_IMAGE,
                     descriptor_count: 1,
-                    stage_flags: *stage,
+                    stage_flags: *stage | extra_stage,
+                    ..Default::default()
+                });
+            }
+            ShaderNode::TextureArray {
+                binding,
+                paths,
+                stage,
+                ..
+            } => {
+                bindings.push(vk::DescriptorSetLayoutBinding {
+                    binding: *binding,
+                    descriptor_type: vk::DescriptorType::SAMPLED_IMAGE,
+                    descriptor_count: paths.len() as u32,
+                    stage_flags: *stage | extra_stage,
                     ..Default::default()
                 });
             }
@@ -38,23 +238,109 @@ impl Shading {
                     binding: *binding,
                     descriptor_type: vk::DescriptorType::SAMPLER,
                     descriptor_count: 1,
-                    stage_flags: *stage,
+                    stage_flags: *stage | extra_stage,
                     ..Default::default()
                 });
             }
-            // ShaderNode::Shading { .. } => {}
-            // ShaderNode::TextureArray { .. } => {}
-            _ => {}
+            ShaderNode::UniformBuffer { binding, stage, .. } => {
+                bindings.push(vk::DescriptorSetLayoutBinding {
+                    binding: *binding,
+                    descriptor_type: vk::DescriptorType::UNIFORM_BUFFER,
+                    descriptor_count: 1,
+                    stage_flags: *stage | extra_stage,
+                    ..Default::default()
+                });
+            }
+            ShaderNode::Shading { .. } => {}
         });
 
         Shading {
             id: 0,
             name: "Simple",
-            path,
+            path: stages.first().map_or("", |stage| stage.path),
+            stages,
             mode: ShadingMode::Unlit,
             depth_test: true,
             depth_write: true,
+            alpha_to_coverage: false,
+            transparent: false,
+            object_payload: false,
+            indirect: false,
+            topology: vk::PrimitiveTopology::TRIANGLE_LIST,
+            patch_control_points: 3,
             bindings,
+            texture_slots: vec!["texture"],
+            params: vec![],
+            hooks: ShaderHooks::default(),
         }
     }
+
+    // Adds the bindings of the slots past the first, with the stages of the first one.
+    pub fn set_texture_slots(&mut self, texture_slots: Vec<&'static str>) {
+        let stage_flags = self
+            .bindings
+            .first()
+            .map_or(vk::ShaderStageFlags::FRAGMENT, |binding| {
+                binding.stage_flags
+            });
+        self.bindings.retain(|binding| binding.binding < 2);
+
+        for slot in 1..texture_slots.len() as u32 {
+            for (binding, descriptor_type) in [
+                (slot * 2, vk::DescriptorType::SAMPLED_IMAGE),
+                (slot * 2 + 1, vk::DescriptorType::SAMPLER),
+            ] {
+                self.bindings.push(vk::DescriptorSetLayoutBinding {
+                    binding,
+                    descriptor_type,
+                    descriptor_count: 1,
+                    stage_flags,
+                    ..Default::default()
+                });
+            }
+        }
+        self.texture_slots = texture_slots;
+    }
+
+    // Declares the material parameter block the shaders read at PARAMS_BINDING of the material
+    // set, the fields in the same order, e.g.
+    // `layout(set = 1, binding = 32) uniform Params { vec4 tint; float roughness; };`
+    // Materials fill it with `Material::set_param`.
+    pub fn set_params(&mut self, params: Vec<(&'static str, ParamKind)>) {
+        self.bindings
+            .retain(|binding| binding.binding != Self::PARAMS_BINDING);
+        if !params.is_empty() {
+            self.bindings.push(vk::DescriptorSetLayoutBinding {
+                binding: Self::PARAMS_BINDING,
+                descriptor_type: vk::DescriptorType::UNIFORM_BUFFER,
+                descriptor_count: 1,
+                stage_flags: vk::ShaderStageFlags::ALL_GRAPHICS,
+                ..Default::default()
+            });
+        }
+        self.params = params;
+    }
+
+    // std140 offsets of the params and the size of the block, rounded up to a vec4.
+    pub fn param_layout(&self) -> (Vec<u32>, u32) {
+        let mut size = 0u32;
+        let offsets = self
+            .params
+            .iter()
+            .map(|(_, kind)| {
+                let offset = size.next_multiple_of(kind.align());
+                size = offset + kind.size();
+                offset
+            })
+            .collect();
+        (offsets, size.next_multiple_of(16))
+    }
+
+    pub fn get_stage(&self, stage: vk::ShaderStageFlags) -> Option<&ShaderStage> {
+        self.stages.iter().find(|item| item.stage == stage)
+    }
+
+    pub fn has_stage(&self, stage: vk::ShaderStageFlags) -> bool {
+        self.get_stage(stage).is_some()
+    }
 }
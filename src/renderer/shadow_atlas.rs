@@ -0,0 +1,403 @@
+use crate::gpu::{Allocation, GPU};
+use crate::scene::Entity;
+use ash::vk;
+use std::cmp::Reverse;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct ShadowTile {
+    pub x: u32,
+    pub y: u32,
+    pub size: u32,
+}
+
+impl ShadowTile {
+    // [offset_u, offset_v, scale_u, scale_v], maps a light's shadow uv into the atlas
+    pub fn uv_rect(&self, atlas_size: u32) -> [f32; 4] {
+        let atlas_size = atlas_size as f32;
+        [
+            self.x as f32 / atlas_size,
+            self.y as f32 / atlas_size,
+            self.size as f32 / atlas_size,
+            self.size as f32 / atlas_size,
+        ]
+    }
+}
+
+// Where the tiles of the atlas go, apart from the image so the packing can be tested without a
+// device. Tiles are power of two squares placed biggest first along a Z-order curve, which packs
+// them without gaps.
+#[derive(Debug)]
+pub struct ShadowTiles {
+    pub size: u32,
+    // the floor over budget tiles shrink to, below it lights are left without tiles
+    pub min_tile_size: u32,
+    pub max_tile_size: u32,
+
+    // one per face of the light, in the order the request's faces go
+    tiles: HashMap<Entity, Vec<ShadowTile>>,
+}
+
+impl ShadowTiles {
+    pub fn new(size: u32) -> Self {
+        let size = size.next_power_of_two();
+        Self {
+            size,
+            min_tile_size: (size / 32).max(1),
+            max_tile_size: (size / 2).max(1),
+            tiles: HashMap::new(),
+        }
+    }
+
+    // Replaces the previous allocation, lights missing from `requests` give their tiles back.
+    // A request is the light, its importance and how many faces it needs tiles for, all of the
+    // same size: 1 for a spot light, 6 for the cube of a point light.
+    // Over budget the lights take turns halving their tiles, the least important first, down to
+    // `min_tile_size`, then the least important lights are left without any. Whatever the last
+    // halvings freed beyond the budget goes back to the most important shrunk lights.
+    pub fn allocate(&mut self, requests: &[(Entity, f32, u32)]) {
+        self.tiles.clear();
+
+        let mut requests = requests.to_vec();
+        requests.sort_by(|a, b| b.1.total_cmp(&a.1));
+        let requested = requests
+            .iter()
+            .map(|&(_, importance, _)| self.tile_size(importance))
+            .collect::<Vec<_>>();
+        let mut sizes = requested.clone();
+
+        // in min tiles
+        let cells = |size: u32| (size / self.min_tile_size).pow(2);
+        let capacity = cells(self.size);
+        let mut total = sizes
+            .iter()
+            .zip(&requests)
+            .map(|(&size, &(_, _, faces))| cells(size) * faces)
+            .sum::<u32>();
+        // round-robin from the least important light up, wrapping around
+        let mut cursor = sizes.len();
+        while total > capacity {
+            let above_floor = |index: &usize| sizes[*index] > self.min_tile_size;
+            let next = (0..cursor)
+                .rev()
+                .find(above_floor)
+                .or_else(|| (cursor..sizes.len()).rev().find(above_floor));
+            match next {
+                Some(index) => {
+                    let freed = cells(sizes[index]) - cells(sizes[index] / 2);
+                    total -= freed * requests[index].2;
+                    sizes[index] /= 2;
+                    cursor = index;
+                }
+                None => {
+                    let (_, _, faces) = requests.pop().unwrap();
+                    total -= cells(sizes.pop().unwrap()) * faces;
+                    cursor = sizes.len();
+                }
+            }
+        }
+        // what the last halvings freed beyond the budget, most important first
+        for index in 0..sizes.len() {
+            let faces = requests[index].2;
+            while sizes[index] < requested[index] {
+                let grown = cells(sizes[index] * 2) - cells(sizes[index]);
+                if total + grown * faces > capacity {
+                    break;
+                }
+                total += grown * faces;
+                sizes[index] *= 2;
+            }
+        }
+
+        // biggest first whatever the light, every tile starts aligned to its own size
+        let mut faces = requests
+            .into_iter()
+            .zip(sizes)
+            .flat_map(|((light, _, faces), size)| (0..faces).map(move |_| (light, size)))
+            .collect::<Vec<_>>();
+        faces.sort_by_key(|&(_, size)| Reverse(size));
+        let mut used = 0;
+        for (light, size) in faces {
+            let (x, y) = Self::morton_decode(used);
+            self.tiles.entry(light).or_default().push(ShadowTile {
+                x: x * self.min_tile_size,
+                y: y * self.min_tile_size,
+                size,
+            });
+            used += cells(size);
+        }
+    }
+
+    // Empty for the lights without tiles.
+    pub fn get_tiles(&self, light: Entity) -> &[ShadowTile] {
+        self.tiles.get(&light).map_or(&[], Vec::as_slice)
+    }
+
+    fn tile_size(&self, importance: f32) -> u32 {
+        // the tile area follows the screen area the light covers
+        let size = self.max_tile_size as f32 * importance.clamp(0.0, 1.0).sqrt();
+        (size as u32)
+            .next_power_of_two()
+            .clamp(self.min_tile_size, self.max_tile_size)
+    }
+
+    fn morton_decode(code: u32) -> (u32, u32) {
+        let compact = |mut v: u32| {
+            v &= 0x5555_5555;
+            v = (v | (v >> 1)) & 0x3333_3333;
+            v = (v | (v >> 2)) & 0x0f0f_0f0f;
+            v = (v | (v >> 4)) & 0x00ff_00ff;
+            v = (v | (v >> 8)) & 0x0000_ffff;
+            v
+        };
+        (compact(code), compact(code >> 1))
+    }
+}
+
+// One depth texture shared by every shadow casting light, so the memory spent on shadows stays
+// fixed no matter how many lights there are. Each frame the lights ask for tiles with their
+// importance (screen coverage 0..1), a spot light for one and a point light for one per cube face,
+// important lights get bigger tiles and the least important ones none once it is full, see
+// ShadowRenderer.
+pub struct ShadowAtlas {
+    gpu: Rc<GPU>,
+
+    pub tiles: ShadowTiles,
+
+    pub format: vk::Format,
+    pub image: vk::Image,
+    pub image_memory: Allocation,
+    pub image_view: vk::ImageView,
+}
+
+impl ShadowAtlas {
+    pub fn new(gpu: &Rc<GPU>, size: u32) -> Self {
+        let tiles = ShadowTiles::new(size);
+
+        unsafe {
+            let format = gpu.find_supported_format(
+                vec![vk::Format::D32_SFLOAT, vk::Format::D16_UNORM],
+                vk::ImageTiling::OPTIMAL,
+                vk::FormatFeatureFlags::DEPTH_STENCIL_ATTACHMENT
+                    | vk::FormatFeatureFlags::SAMPLED_IMAGE,
+            );
+            let (image, image_memory) = gpu.device_context.create_image(
+                tiles.size,
+                tiles.size,
+                1,
+                vk::SampleCountFlags::TYPE_1,
+                format,
+                vk::ImageTiling::OPTIMAL,
+                vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT | vk::ImageUsageFlags::SAMPLED,
+                vk::MemoryPropertyFlags::DEVICE_LOCAL,
+            );
+            let image_view =
+                gpu.device_context
+                    .create_image_view(image, format, vk::ImageAspectFlags::DEPTH, 1);
+            gpu.debug_names.set_name(image, "shadow atlas");
+
+            Self {
+                gpu: Rc::clone(gpu),
+
+                tiles,
+
+                format,
+                image,
+                image_memory,
+                image_view,
+            }
+        }
+    }
+
+    pub fn size(&self) -> u32 {
+        self.tiles.size
+    }
+
+    pub fn allocate(&mut self, requests: &[(Entity, f32, u32)]) {
+        self.tiles.allocate(requests);
+    }
+
+    pub fn get_tiles(&self, light: Entity) -> &[ShadowTile] {
+        self.tiles.get_tiles(light)
+    }
+}
+
+impl Drop for ShadowAtlas {
+    fn drop(&mut self) {
+        unsafe {
+            let device_context = &self.gpu.device_context;
+            device_context
+                .device
+                .destroy_image_view(self.image_view, None);
+            device_context.destroy_image(self.image, self.image_memory);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ShadowTile, ShadowTiles};
+    use crate::scene::Entity;
+
+    fn lights(importances: &[f32]) -> Vec<(Entity, f32, u32)> {
+        importances
+            .iter()
+            .enumerate()
+            .map(|(id, &importance)| (Entity::new(id as u32), importance, 1))
+            .collect()
+    }
+
+    fn overlap(a: &ShadowTile, b: &ShadowTile) -> bool {
+        a.x < b.x + b.size && b.x < a.x + a.size && a.y < b.y + b.size && b.y < a.y + a.size
+    }
+
+    fn tile(tiles: &ShadowTiles, id: u32) -> Option<ShadowTile> {
+        tiles.get_tiles(Entity::new(id)).first().copied()
+    }
+
+    fn allocated(tiles: &ShadowTiles, count: usize) -> Vec<ShadowTile> {
+        (0..count as u32)
+            .flat_map(|id| tiles.get_tiles(Entity::new(id)).to_vec())
+            .collect()
+    }
+
+    fn sizes(tiles: &ShadowTiles, count: usize) -> Vec<u32> {
+        allocated(tiles, count)
+            .iter()
+            .map(|tile| tile.size)
+            .collect()
+    }
+
+    fn assert_packed(placed: &[ShadowTile], atlas_size: u32) {
+        for (index, a) in placed.iter().enumerate() {
+            assert!(a.size.is_power_of_two());
+            assert!(a.x + a.size <= atlas_size && a.y + a.size <= atlas_size);
+            // aligned to its own size along the Z-order curve
+            assert_eq!((a.x % a.size, a.y % a.size), (0, 0));
+            for b in &placed[index + 1..] {
+                assert!(!overlap(a, b), "{:?} overlaps {:?}", a, b);
+            }
+        }
+    }
+
+    #[test]
+    fn tiles_follow_importance() {
+        let mut tiles = ShadowTiles::new(1024);
+        tiles.allocate(&lights(&[0.01, 1.0, 0.25, 0.0]));
+
+        assert_eq!(tile(&tiles, 1).unwrap().size, 512);
+        assert_eq!(tile(&tiles, 2).unwrap().size, 256);
+        assert_eq!(tile(&tiles, 0).unwrap().size, 64);
+        // no coverage still gets the smallest tile
+        assert_eq!(tile(&tiles, 3).unwrap().size, 32);
+        assert_eq!(tile(&tiles, 4), None);
+    }
+
+    #[test]
+    fn tiles_pack_without_overlap() {
+        let mut tiles = ShadowTiles::new(512);
+        let importances = (0..40)
+            .map(|i| ((i * 37) % 11) as f32 / 10.0)
+            .collect::<Vec<_>>();
+        tiles.allocate(&lights(&importances));
+
+        let placed = allocated(&tiles, importances.len());
+        assert_eq!(placed.len(), importances.len());
+        assert_packed(&placed, 512);
+    }
+
+    #[test]
+    fn biggest_first_fills_the_atlas_in_z_order() {
+        let mut tiles = ShadowTiles::new(256);
+        tiles.allocate(&lights(&[1.0; 4]));
+
+        let placed = allocated(&tiles, 4);
+        let corners = placed.iter().map(|t| (t.x, t.y)).collect::<Vec<_>>();
+        assert_eq!(corners, vec![(0, 0), (128, 0), (0, 128), (128, 128)]);
+        assert!(placed.iter().all(|tile| tile.size == 128));
+    }
+
+    #[test]
+    fn reallocating_frees_the_tiles_of_missing_lights() {
+        let mut tiles = ShadowTiles::new(256);
+        tiles.allocate(&lights(&[1.0; 4]));
+        assert_eq!(allocated(&tiles, 4).len(), 4);
+
+        // the lights that went away give their corners to the next ones
+        tiles.allocate(&[(Entity::new(4), 1.0, 1), (Entity::new(2), 0.0, 1)]);
+        assert_eq!(allocated(&tiles, 5).len(), 2);
+        assert_eq!(tile(&tiles, 0), None);
+        let first = tile(&tiles, 4).unwrap();
+        assert_eq!((first.x, first.y, first.size), (0, 0, 128));
+        let second = tile(&tiles, 2).unwrap();
+        assert_eq!((second.x, second.y, second.size), (128, 0, 8));
+
+        tiles.allocate(&[]);
+        assert!(allocated(&tiles, 5).is_empty());
+    }
+
+    #[test]
+    fn over_budget_the_lights_take_turns_shrinking() {
+        let mut tiles = ShadowTiles::new(256);
+        tiles.allocate(&lights(&[1.0, 0.9, 0.8, 0.7, 0.6, 0.5]));
+
+        // six max tiles would take an atlas and a half, halving the three least important is
+        // enough
+        assert_eq!(sizes(&tiles, 6), vec![128, 128, 128, 64, 64, 64]);
+
+        // the most important light is the last to give up a halving
+        tiles.allocate(&lights(&[1.0; 12]));
+        assert_eq!(sizes(&tiles, 12)[..2], [128, 64]);
+        // and a second round starts from the least important again
+        tiles.allocate(&lights(&[1.0; 17]));
+        let sizes = sizes(&tiles, 17);
+        assert!(sizes[..15].iter().all(|&size| size == 64));
+        assert_eq!(sizes[15..], [32, 32]);
+    }
+
+    #[test]
+    fn over_budget_at_the_smallest_size_the_least_important_get_none() {
+        let mut tiles = ShadowTiles::new(256);
+        // 32 by 32 of the smallest tiles fit
+        tiles.allocate(&lights(&[0.0; 1100]));
+
+        assert_eq!(allocated(&tiles, 1100).len(), 1024);
+        assert!(tile(&tiles, 1023).is_some());
+        assert_eq!(tile(&tiles, 1024), None);
+        let last = tile(&tiles, 1023).unwrap();
+        assert_eq!((last.x, last.y, last.size), (248, 248, 8));
+    }
+
+    #[test]
+    fn point_lights_get_a_tile_per_cube_face() {
+        let mut tiles = ShadowTiles::new(1024);
+        tiles.allocate(&[(Entity::new(0), 1.0, 6), (Entity::new(1), 0.25, 1)]);
+
+        // six 512 faces take an atlas and a half, halving the spot light alone isn't enough
+        let faces = tiles.get_tiles(Entity::new(0));
+        assert_eq!(faces.len(), 6);
+        assert!(faces.iter().all(|face| face.size == 256));
+        // the space the faces gave up beyond the budget goes back to the spot light
+        assert_eq!(tile(&tiles, 1).unwrap().size, 256);
+        assert_packed(&allocated(&tiles, 2), 1024);
+    }
+
+    #[test]
+    fn shrunk_tiles_pack_into_the_space_they_freed() {
+        let mut tiles = ShadowTiles::new(256);
+        tiles.allocate(&[
+            (Entity::new(0), 1.0, 6),
+            (Entity::new(1), 0.5, 1),
+            (Entity::new(2), 0.0, 1),
+        ]);
+
+        // the point light's faces shrink below the spot light, which still packs biggest first
+        let faces = tiles.get_tiles(Entity::new(0));
+        assert!(faces.iter().all(|face| face.size == 64));
+        let spot = tile(&tiles, 1).unwrap();
+        assert_eq!((spot.x, spot.y, spot.size), (0, 0, 128));
+        assert_eq!(tile(&tiles, 2).unwrap().size, 8);
+        assert_packed(&allocated(&tiles, 3), 256);
+    }
+}
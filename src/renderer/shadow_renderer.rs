@@ -0,0 +1,518 @@
+use super::vertex::Vertex;
+use super::{CameraUniforms, GPUAssets, RenderObject, ShadowAtlas, ShadowTile};
+use crate::assets::Assets;
+use crate::gpu::{PassDesc, Rhi, VkPipeline, GPU};
+use crate::math::{Mat4, Vec3};
+use crate::scene::{Light, LightKind};
+use ash::vk;
+use std::cell::{Cell, RefCell};
+use std::ffi::CString;
+use std::io;
+use std::mem::size_of;
+use std::rc::Rc;
+
+const SHADOW_DEPTH_SHADER: &str = "shadow_depth.spv";
+const ATLAS_SIZE: u32 = 2048;
+// of the light's range, where its depth starts
+const NEAR_RATIO: f32 = 0.01;
+// Forward and up of the faces of a point light's cube: +X, -X, +Y, -Y, +Z, -Z, with 90 degree
+// frustums. Keep in sync with cubeShadowFactor in standard.frag.glsl
+const CUBE_FACES: [([f32; 3], [f32; 3]); 6] = [
+    ([1.0, 0.0, 0.0], [0.0, 1.0, 0.0]),
+    ([-1.0, 0.0, 0.0], [0.0, 1.0, 0.0]),
+    ([0.0, 1.0, 0.0], [1.0, 0.0, 0.0]),
+    ([0.0, -1.0, 0.0], [1.0, 0.0, 0.0]),
+    ([0.0, 0.0, 1.0], [0.0, 1.0, 0.0]),
+    ([0.0, 0.0, -1.0], [0.0, 1.0, 0.0]),
+];
+
+#[repr(C)]
+#[derive(Copy, Clone)]
+struct ShadowPushConstants {
+    model_view_projection: Mat4,
+}
+
+// A tile of the shadow atlas, a spot light's or a point light's cube face, and the casters it
+// sees, culled against its frustum.
+pub struct ShadowView {
+    pub tile: ShadowTile,
+    pub view_projection: Mat4,
+    pub objects: Vec<RenderObject>,
+}
+
+// Shadow maps of the lights with `Light::cast_shadows`, all in one ShadowAtlas. Each frame the
+// lights in view get tiles sized by their screen coverage, see `coverage`, and this depth only
+// pass draws the casters into it before anything samples the atlas. The standard shading compares
+// against it through the shadow matrix of `LightData`, the atlas is bound on set 0 of the camera
+// uniforms, see SHADOW_ATLAS_BINDING.
+pub struct ShadowRenderer {
+    gpu: Rc<GPU>,
+
+    pub atlas: ShadowAtlas,
+
+    render_pass: vk::RenderPass,
+    framebuffer: vk::Framebuffer,
+    pipeline_layout: vk::PipelineLayout,
+    shader_module: vk::ShaderModule,
+    pipeline: vk::Pipeline,
+    // compares the depth instead of returning it, owned since the cached samplers go away with
+    // the mip bias
+    sampler: vk::Sampler,
+    // the atlas was cleared into the layout the shading reads it in
+    cleared: Cell<bool>,
+}
+
+impl ShadowRenderer {
+    pub fn new(gpu: &Rc<GPU>, camera_uniforms: &CameraUniforms) -> Self {
+        let atlas = ShadowAtlas::new(gpu, ATLAS_SIZE);
+
+        unsafe {
+            let render_pass = Self::create_render_pass(gpu, atlas.format);
+            let framebuffer = Self::create_framebuffer(gpu, render_pass, &atlas);
+            let pipeline_layout = Self::create_pipeline_layout(gpu);
+
+            let data = Assets::load_raw(SHADOW_DEPTH_SHADER).unwrap();
+            let shader_code = ash::util::read_spv(&mut io::Cursor::new(&data)).unwrap();
+            let shader_module = gpu.create_shader_module(&shader_code);
+            let pipeline = Self::create_pipeline(gpu, shader_module, pipeline_layout, render_pass);
+            gpu.debug_names.set_name(pipeline, "shadow depth");
+
+            let sampler = Self::create_sampler(gpu);
+            camera_uniforms.set_shadow_atlas(atlas.image_view, sampler);
+
+            Self {
+                gpu: Rc::clone(gpu),
+
+                atlas,
+
+                render_pass,
+                framebuffer,
+                pipeline_layout,
+                shader_module,
+                pipeline,
+                sampler,
+                cleared: Cell::new(false),
+            }
+        }
+    }
+
+    // How much of the screen the bounding sphere of a light covers, 0..1, 1 from inside it.
+    // `camera_params` like `GlobalsData::camera_params`.
+    pub fn coverage(
+        center: Vec3,
+        radius: f32,
+        camera_location: Vec3,
+        camera_params: [f32; 4],
+    ) -> f32 {
+        let [fov, aspect, ortho_height, _] = camera_params;
+        let distance = (center - camera_location).len();
+        if distance <= radius {
+            return 1.0;
+        }
+        let half_height = match ortho_height > 0.0 {
+            true => ortho_height / 2.0,
+            false => distance * (fov / 2.0).tan(),
+        };
+        // in half screen heights, the screen is 2 by 2 * aspect of them
+        let projected = radius / half_height.max(1e-4);
+        (std::f32::consts::PI * projected * projected / (4.0 * aspect)).min(1.0)
+    }
+
+    // How many tiles of the atlas the light's shadow map takes, 0 for the lights that cast none.
+    pub fn shadow_faces(light: &Light) -> u32 {
+        match light.kind {
+            LightKind::Point => CUBE_FACES.len() as u32,
+            LightKind::Spot { .. } => 1,
+        }
+    }
+
+    // Near and far plane of the light's shadow map.
+    pub fn depth_range(light: &Light) -> (f32, f32) {
+        (light.range * NEAR_RATIO, light.range)
+    }
+
+    // Clip spaces of the light's shadow map, one per tile: the cone of a spot light, or the faces
+    // of a point light's cube in CUBE_FACES order.
+    pub fn light_view_projections(light: &Light, location: Vec3, direction: Vec3) -> Vec<Mat4> {
+        let (near, far) = Self::depth_range(light);
+        match light.kind {
+            LightKind::Point => {
+                let projection = Mat4::perspective_rh(std::f32::consts::FRAC_PI_2, 1.0, near, far);
+                CUBE_FACES
+                    .iter()
+                    .map(|&(forward, up)| {
+                        let target = location + Vec3::from(forward);
+                        projection * Mat4::look_at_rh(location, target, Vec3::from(up))
+                    })
+                    .collect()
+            }
+            LightKind::Spot { outer_angle, .. } => {
+                let up = match direction.y.abs() > 0.99 {
+                    true => Vec3::new(1.0, 0.0, 0.0),
+                    false => Vec3::new(0.0, 1.0, 0.0),
+                };
+                let view = Mat4::look_at_rh(location, location + direction, up);
+                let fov = (outer_angle * 2.0).clamp(0.01, std::f32::consts::PI - 0.01);
+                vec![Mat4::perspective_rh(fov, 1.0, near, far) * view]
+            }
+        }
+    }
+
+    // Before the passes that shade with the lights, texture cameras included. Without any view the
+    // atlas is only cleared once so the layout the shading expects is there.
+    pub fn render(
+        &self,
+        command_buffer: vk::CommandBuffer,
+        frame_index: usize,
+        gpu_assets: &RefCell<GPUAssets>,
+        views: &[ShadowView],
+    ) {
+        if views.is_empty() && self.cleared.get() {
+            return;
+        }
+        self.cleared.set(true);
+        let gpu = &self.gpu;
+        let mut gpu_assets = gpu_assets.borrow_mut();
+
+        let pipeline = VkPipeline {
+            pipeline: self.pipeline,
+            layout: self.pipeline_layout,
+        };
+        gpu.begin_pass(
+            command_buffer,
+            &PassDesc {
+                label: "shadow atlas",
+                render_pass: self.render_pass,
+                framebuffer: self.framebuffer,
+                width: self.atlas.size(),
+                height: self.atlas.size(),
+                clear_color: [0.0; 4],
+                clear_depth: 1.0,
+            },
+        );
+        gpu.bind_pipeline(command_buffer, &pipeline);
+        for view in views {
+            let tile = &view.tile;
+            unsafe {
+                let device = &gpu.device_context.device;
+                device.cmd_set_viewport(
+                    command_buffer,
+                    0,
+                    &[vk::Viewport {
+                        x: tile.x as f32,
+                        y: tile.y as f32,
+                        width: tile.size as f32,
+                        height: tile.size as f32,
+                        min_depth: 0.0,
+                        max_depth: 1.0,
+                    }],
+                );
+                device.cmd_set_scissor(
+                    command_buffer,
+                    0,
+                    &[vk::Rect2D {
+                        offset: vk::Offset2D {
+                            x: tile.x as i32,
+                            y: tile.y as i32,
+                        },
+                        extent: vk::Extent2D {
+                            width: tile.size,
+                            height: tile.size,
+                        },
+                    }],
+                );
+            }
+            for object in &view.objects {
+                let Some(geom) = gpu_assets.get_render_geom(&object.geom, frame_index) else {
+                    continue;
+                };
+                let push_constants = ShadowPushConstants {
+                    model_view_projection: view.view_projection * object.model,
+                };
+                gpu.push_constants(command_buffer, &pipeline, unsafe {
+                    std::slice::from_raw_parts(
+                        (&push_constants as *const ShadowPushConstants) as *const u8,
+                        size_of::<ShadowPushConstants>(),
+                    )
+                });
+                gpu.draw_indexed(
+                    command_buffer,
+                    &geom.vertex_buffer,
+                    &geom.index_buffer,
+                    geom.first_index..geom.first_index + geom.indices_length as u32,
+                    geom.vertex_offset,
+                );
+            }
+        }
+        gpu.end_pass(command_buffer);
+    }
+
+    unsafe fn create_render_pass(gpu: &GPU, format: vk::Format) -> vk::RenderPass {
+        let attachments = [vk::AttachmentDescription {
+            format,
+            samples: vk::SampleCountFlags::TYPE_1,
+            load_op: vk::AttachmentLoadOp::CLEAR,
+            store_op: vk::AttachmentStoreOp::STORE,
+            stencil_load_op: vk::AttachmentLoadOp::DONT_CARE,
+            stencil_store_op: vk::AttachmentStoreOp::DONT_CARE,
+            initial_layout: vk::ImageLayout::UNDEFINED,
+            final_layout: vk::ImageLayout::DEPTH_STENCIL_READ_ONLY_OPTIMAL,
+            flags: Default::default(),
+        }];
+        let depth_attachment_ref = vk::AttachmentReference {
+            attachment: 0,
+            layout: vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL,
+        };
+        let sub_passes = [vk::SubpassDescription::default()
+            .pipeline_bind_point(vk::PipelineBindPoint::GRAPHICS)
+            .depth_stencil_attachment(&depth_attachment_ref)];
+
+        let dependencies = [
+            // the shading of the previous frame has to be done reading the atlas
+            vk::SubpassDependency {
+                src_subpass: vk::SUBPASS_EXTERNAL,
+                src_stage_mask: vk::PipelineStageFlags::FRAGMENT_SHADER,
+                src_access_mask: vk::AccessFlags::SHADER_READ,
+                dst_subpass: 0,
+                dst_stage_mask: vk::PipelineStageFlags::EARLY_FRAGMENT_TESTS
+                    | vk::PipelineStageFlags::LATE_FRAGMENT_TESTS,
+                dst_access_mask: vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE,
+                ..Default::default()
+            },
+            // and this frame's shading waits for the depth written here
+            vk::SubpassDependency {
+                src_subpass: 0,
+                src_stage_mask: vk::PipelineStageFlags::LATE_FRAGMENT_TESTS,
+                src_access_mask: vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE,
+                dst_subpass: vk::SUBPASS_EXTERNAL,
+                dst_stage_mask: vk::PipelineStageFlags::FRAGMENT_SHADER,
+                dst_access_mask: vk::AccessFlags::SHADER_READ,
+                ..Default::default()
+            },
+        ];
+
+        let create_info = vk::RenderPassCreateInfo::default()
+            .attachments(&attachments)
+            .subpasses(&sub_passes)
+            .dependencies(&dependencies);
+
+        gpu.device_context
+            .device
+            .create_render_pass(&create_info, None)
+            .expect("failed to create shadow render pass!")
+    }
+
+    unsafe fn create_framebuffer(
+        gpu: &GPU,
+        render_pass: vk::RenderPass,
+        atlas: &ShadowAtlas,
+    ) -> vk::Framebuffer {
+        let attachments = [atlas.image_view];
+        let create_info = vk::FramebufferCreateInfo::default()
+            .width(atlas.size())
+            .height(atlas.size())
+            .layers(1)
+            .attachments(&attachments)
+            .render_pass(render_pass);
+
+        gpu.device_context
+            .device
+            .create_framebuffer(&create_info, None)
+            .expect("failed to create framebuffer!")
+    }
+
+    unsafe fn create_pipeline_layout(gpu: &GPU) -> vk::PipelineLayout {
+        let push_constant_ranges = [vk::PushConstantRange::default()
+            .stage_flags(vk::ShaderStageFlags::ALL_GRAPHICS)
+            .offset(0)
+            .size(size_of::<ShadowPushConstants>() as u32)];
+        let layout_create_info =
+            vk::PipelineLayoutCreateInfo::default().push_constant_ranges(&push_constant_ranges);
+
+        gpu.device_context
+            .device
+            .create_pipeline_layout(&layout_create_info, None)
+            .expect("failed to create pipeline layout!")
+    }
+
+    // Positions only and no fragment stage, the slope scaled bias keeps lit surfaces from
+    // shadowing themselves.
+    unsafe fn create_pipeline(
+        gpu: &GPU,
+        shader_module: vk::ShaderModule,
+        layout: vk::PipelineLayout,
+        render_pass: vk::RenderPass,
+    ) -> vk::Pipeline {
+        let vertex_entry = CString::new("vs").unwrap();
+        let shader_stages = [vk::PipelineShaderStageCreateInfo::default()
+            .module(shader_module)
+            .stage(vk::ShaderStageFlags::VERTEX)
+            .name(vertex_entry.as_c_str())];
+
+        let binding_descriptions = [Vertex::get_binding_description()];
+        let attribute_descriptions = [Vertex::get_attribute_descriptions()[0]];
+        let vertex_input_state = vk::PipelineVertexInputStateCreateInfo::default()
+            .vertex_binding_descriptions(&binding_descriptions)
+            .vertex_attribute_descriptions(&attribute_descriptions);
+        let input_assembly_stage = vk::PipelineInputAssemblyStateCreateInfo::default()
+            .topology(vk::PrimitiveTopology::TRIANGLE_LIST);
+        let dynamic_state = vk::PipelineDynamicStateCreateInfo::default()
+            .dynamic_states(&[vk::DynamicState::VIEWPORT, vk::DynamicState::SCISSOR]);
+        let viewport_state = vk::PipelineViewportStateCreateInfo::default()
+            .viewport_count(1)
+            .scissor_count(1);
+        let rasterization_state = vk::PipelineRasterizationStateCreateInfo::default()
+            .cull_mode(vk::CullModeFlags::NONE)
+            .polygon_mode(vk::PolygonMode::FILL)
+            .depth_bias_enable(true)
+            .depth_bias_constant_factor(1.25)
+            .depth_bias_slope_factor(1.75)
+            .line_width(1.0);
+        let multisample = vk::PipelineMultisampleStateCreateInfo::default()
+            .rasterization_samples(vk::SampleCountFlags::TYPE_1);
+        let color_blend = vk::PipelineColorBlendStateCreateInfo::default();
+        let depth_stencil = vk::PipelineDepthStencilStateCreateInfo::default()
+            .depth_test_enable(true)
+            .depth_write_enable(true)
+            .depth_compare_op(vk::CompareOp::LESS_OR_EQUAL);
+
+        let create_info = vk::GraphicsPipelineCreateInfo::default()
+            .stages(&shader_stages)
+            .vertex_input_state(&vertex_input_state)
+            .input_assembly_state(&input_assembly_stage)
+            .dynamic_state(&dynamic_state)
+            .viewport_state(&viewport_state)
+            .rasterization_state(&rasterization_state)
+            .multisample_state(&multisample)
+            .color_blend_state(&color_blend)
+            .depth_stencil_state(&depth_stencil)
+            .layout(layout)
+            .render_pass(render_pass)
+            .subpass(0);
+
+        gpu.device_context
+            .device
+            .create_graphics_pipelines(gpu.pipeline_cache, &[create_info], None)
+            .expect("failed to create shadow pipeline!")[0]
+    }
+
+    // Linear filtering of the comparisons gives a 2x2 PCF for free.
+    unsafe fn create_sampler(gpu: &GPU) -> vk::Sampler {
+        let create_info = vk::SamplerCreateInfo::default()
+            .mag_filter(vk::Filter::LINEAR)
+            .min_filter(vk::Filter::LINEAR)
+            .mipmap_mode(vk::SamplerMipmapMode::NEAREST)
+            .address_mode_u(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+            .address_mode_v(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+            .address_mode_w(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+            .compare_enable(true)
+            .compare_op(vk::CompareOp::LESS_OR_EQUAL)
+            .max_lod(0.0);
+
+        gpu.device_context
+            .device
+            .create_sampler(&create_info, None)
+            .expect("failed to create shadow sampler!")
+    }
+}
+
+impl Drop for ShadowRenderer {
+    fn drop(&mut self) {
+        unsafe {
+            let device = &self.gpu.device_context.device;
+            device.destroy_pipeline(self.pipeline, None);
+            device.destroy_shader_module(self.shader_module, None);
+            device.destroy_pipeline_layout(self.pipeline_layout, None);
+            device.destroy_framebuffer(self.framebuffer, None);
+            device.destroy_render_pass(self.render_pass, None);
+            device.destroy_sampler(self.sampler, None);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ShadowRenderer, CUBE_FACES};
+    use crate::math::Vec3;
+    use crate::scene::Light;
+
+    // cubeShadowFactor in standard.frag.glsl, the clip position in the face of the major axis
+    fn shader_cube_clip(to_fragment: Vec3, near: f32, far: f32) -> (usize, [f32; 4]) {
+        let axis = Vec3::new(
+            to_fragment.x.abs(),
+            to_fragment.y.abs(),
+            to_fragment.z.abs(),
+        );
+        let sign = |value: f32| if value > 0.0 { 1.0 } else { -1.0 };
+        let (face, forward, up) = if axis.x >= axis.y && axis.x >= axis.z {
+            let face = if to_fragment.x > 0.0 { 0 } else { 1 };
+            (
+                face,
+                Vec3::new(sign(to_fragment.x), 0.0, 0.0),
+                Vec3::new(0.0, 1.0, 0.0),
+            )
+        } else if axis.y >= axis.z {
+            let face = if to_fragment.y > 0.0 { 2 } else { 3 };
+            (
+                face,
+                Vec3::new(0.0, sign(to_fragment.y), 0.0),
+                Vec3::new(1.0, 0.0, 0.0),
+            )
+        } else {
+            let face = if to_fragment.z > 0.0 { 4 } else { 5 };
+            (
+                face,
+                Vec3::new(0.0, 0.0, sign(to_fragment.z)),
+                Vec3::new(0.0, 1.0, 0.0),
+            )
+        };
+        let z = -forward;
+        let x = up.cross(z).normalize();
+        let y = z.cross(x);
+        let view = Vec3::new(x.dot(to_fragment), y.dot(to_fragment), z.dot(to_fragment));
+        let clip = [
+            view.x,
+            -view.y,
+            (view.z * far + near * far) / (near - far),
+            -view.z,
+        ];
+        (face, clip)
+    }
+
+    #[test]
+    fn the_shading_rebuilds_the_cube_face_projections() {
+        let light = Light::point(Vec3::one(), 1.0, 10.0);
+        let location = Vec3::new(1.0, 2.0, -3.0);
+        let (near, far) = ShadowRenderer::depth_range(&light);
+        let faces = ShadowRenderer::light_view_projections(&light, location, Vec3::zero());
+        assert_eq!(faces.len(), CUBE_FACES.len());
+
+        let offsets = [
+            Vec3::new(4.0, 1.0, -2.0),
+            Vec3::new(-3.0, 0.5, 1.0),
+            Vec3::new(0.5, 5.0, 2.0),
+            Vec3::new(-1.0, -2.5, 0.5),
+            Vec3::new(1.0, -2.0, 6.0),
+            Vec3::new(-0.5, 1.5, -2.0),
+        ];
+        for (expected_face, offset) in offsets.into_iter().enumerate() {
+            let (face, clip) = shader_cube_clip(offset, near, far);
+            assert_eq!(face, expected_face);
+            let projected = faces[face].project_point(location + offset);
+            let projected = [projected.x, projected.y, projected.z, projected.w];
+            for (a, b) in clip.iter().zip(projected) {
+                assert!(
+                    (a - b).abs() < 1e-4,
+                    "face {}: {:?} != {:?}",
+                    face,
+                    clip,
+                    projected
+                );
+            }
+            // inside the face's frustum
+            let ndc = clip.map(|value| value / clip[3]);
+            assert!(ndc[0].abs() <= 1.0 && ndc[1].abs() <= 1.0);
+            assert!((0.0..=1.0).contains(&ndc[2]));
+        }
+    }
+}
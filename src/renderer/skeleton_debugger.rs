@@ -0,0 +1,135 @@
+use crate::assets::{AssetHandle, Assets, Geom, Material};
+use crate::math::{Mat4, Vec3};
+use crate::renderer::vertex::Vertex;
+use crate::renderer::{RenderObject, Shading};
+use crate::scene::{DebugSkeleton, Query, Skeleton, Transform, World};
+
+const BONE_COLOR: [f32; 3] = [0.2, 0.6, 1.0];
+const LEAF_COLOR: [f32; 3] = [1.0, 0.4, 0.8];
+
+// Draws the bones of skeletons tagged with `DebugSkeleton` as wire octahedrons from each joint
+// to its child, leaf joints get a small cross. Handy to spot retargeting and import scale issues,
+// a wrong scale shows up as bones far off the mesh.
+pub struct SkeletonDebugger {
+    material: AssetHandle<Material>,
+}
+
+impl SkeletonDebugger {
+    pub fn new(assets: &mut Assets) -> Self {
+        Self {
+            material: assets.handle(Material::new(Shading::load_debug_line())),
+        }
+    }
+
+    pub fn collect(&self, world: &mut World, assets: &mut Assets, objects: &mut Vec<RenderObject>) {
+        let query = Query::<(&Transform, &Skeleton, &mut DebugSkeleton)>::new(world);
+        for (transform, skeleton, debug_skeleton) in query {
+            if skeleton.joints.is_empty() {
+                continue;
+            }
+            let matrices = skeleton.model_matrices();
+            let lines = match &debug_skeleton.lines {
+                Some((lines, key)) if *key == matrices => lines.clone(),
+                _ => {
+                    let lines = assets.handle(Self::build_lines(skeleton, &matrices));
+                    debug_skeleton.lines = Some((lines.clone(), matrices));
+                    lines
+                }
+            };
+
+            objects.push(RenderObject::new(
+                lines,
+                self.material.clone(),
+                transform.matrix(),
+            ));
+        }
+    }
+
+    // World space position and name of every joint, where the text renderer puts the labels.
+    pub fn joint_labels(world: &mut World) -> Vec<(String, Vec3)> {
+        let mut labels = vec![];
+        let query = Query::<(&Transform, &Skeleton, &DebugSkeleton)>::new(world);
+        for (transform, skeleton, _) in query {
+            let model = transform.matrix();
+            for (joint, matrix) in skeleton.joints.iter().zip(skeleton.model_matrices()) {
+                labels.push((joint.name.clone(), Self::origin(&(model * matrix))));
+            }
+        }
+        labels
+    }
+
+    pub fn build_lines(skeleton: &Skeleton, matrices: &[Mat4]) -> Geom {
+        let mut vertices = vec![];
+        let mut push_line = |from: Vec3, to: Vec3, color: [f32; 3]| {
+            for position in [from, to] {
+                vertices.push(Vertex {
+                    position: [position.x, position.y, position.z],
+                    color,
+                    uv: [0.0, 0.0],
+                    normal: [0.0, 0.0, 0.0],
+                });
+            }
+        };
+
+        let mut has_children = vec![false; skeleton.joints.len()];
+        for (index, joint) in skeleton.joints.iter().enumerate() {
+            let Some(parent) = joint.parent else {
+                continue;
+            };
+            has_children[parent] = true;
+
+            let head = Self::origin(&matrices[parent]);
+            let tail = Self::origin(&matrices[index]);
+            let axis = tail - head;
+            let length = axis.len();
+            if length <= f32::EPSILON {
+                continue;
+            }
+            let axis = axis / length;
+
+            // octahedron: head, a square ring at 10% of the bone, tail
+            let up = if axis.y.abs() > 0.99 {
+                Vec3::new(1.0, 0.0, 0.0)
+            } else {
+                Vec3::new(0.0, 1.0, 0.0)
+            };
+            let side = axis.cross(up).normalize() * (length * 0.1);
+            let normal = axis.cross(side).normalize() * (length * 0.1);
+            let center = head + axis * (length * 0.1);
+            let ring = [
+                center + side,
+                center + normal,
+                center - side,
+                center - normal,
+            ];
+            for i in 0..4 {
+                push_line(head, ring[i], BONE_COLOR);
+                push_line(ring[i], ring[(i + 1) % 4], BONE_COLOR);
+                push_line(ring[i], tail, BONE_COLOR);
+            }
+        }
+
+        // end joints have no bone to show their position, mark them with a cross
+        for (index, matrix) in matrices.iter().enumerate() {
+            if has_children[index] {
+                continue;
+            }
+            let origin = Self::origin(matrix);
+            let size = 0.02;
+            for axis in [
+                Vec3::new(size, 0.0, 0.0),
+                Vec3::new(0.0, size, 0.0),
+                Vec3::new(0.0, 0.0, size),
+            ] {
+                push_line(origin - axis, origin + axis, LEAF_COLOR);
+            }
+        }
+
+        let indices = (0..vertices.len() as u32).collect();
+        Geom::new(vertices, indices)
+    }
+
+    fn origin(matrix: &Mat4) -> Vec3 {
+        Vec3::new(matrix[3][0], matrix[3][1], matrix[3][2])
+    }
+}
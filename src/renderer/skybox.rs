@@ -0,0 +1,240 @@
+use super::{CameraUniforms, GPUAssets};
+use crate::assets::{AssetHandle, Assets, Texture};
+use crate::gpu::{Rhi, VkPipeline, GPU};
+use ash::vk;
+use std::cell::Cell;
+use std::ffi::CString;
+use std::io;
+use std::mem::size_of;
+use std::rc::Rc;
+
+const SKYBOX_SHADER: &str = "skybox.spv";
+
+#[repr(C)]
+#[derive(Copy, Clone)]
+struct SkyboxParams {
+    depth: f32,
+    intensity: f32,
+}
+
+// Draws the environment cube map behind everything inside the forward pass, after the opaque
+// geometry. Set 0 is the camera, set 1 the cube map.
+pub struct Skybox {
+    gpu: Rc<GPU>,
+
+    // a cube map texture, nothing is drawn without one
+    pub environment: Option<AssetHandle<Texture>>,
+    pub intensity: f32,
+
+    descriptor_set_layout: vk::DescriptorSetLayout,
+    descriptor_sets: Vec<vk::DescriptorSet>,
+    pipeline_layout: vk::PipelineLayout,
+    shader_module: vk::ShaderModule,
+    // built for the render pass and subpass it was last drawn in, the forward pass is recreated on
+    // format and sample count changes and when switching to the mobile friendly one
+    pipeline: Cell<Option<(vk::RenderPass, u32, vk::Pipeline)>>,
+}
+
+impl Skybox {
+    pub fn new(gpu: &Rc<GPU>, camera_uniforms: &CameraUniforms, frames_in_flight: u32) -> Self {
+        let descriptor_set_layout = gpu.create_descriptor_set_layout(&vec![
+            vk::DescriptorSetLayoutBinding {
+                binding: 0,
+                descriptor_type: vk::DescriptorType::SAMPLED_IMAGE,
+                descriptor_count: 1,
+                stage_flags: vk::ShaderStageFlags::FRAGMENT,
+                ..Default::default()
+            },
+            vk::DescriptorSetLayoutBinding {
+                binding: 1,
+                descriptor_type: vk::DescriptorType::SAMPLER,
+                descriptor_count: 1,
+                stage_flags: vk::ShaderStageFlags::FRAGMENT,
+                ..Default::default()
+            },
+        ]);
+        let descriptor_sets =
+            gpu.create_descriptor_sets(&vec![descriptor_set_layout; frames_in_flight as usize]);
+
+        let push_constant_ranges = [vk::PushConstantRange::default()
+            .stage_flags(vk::ShaderStageFlags::ALL_GRAPHICS)
+            .offset(0)
+            .size(size_of::<SkyboxParams>() as u32)];
+        let descriptor_set_layouts = [camera_uniforms.descriptor_set_layout, descriptor_set_layout];
+        let layout_create_info = vk::PipelineLayoutCreateInfo::default()
+            .set_layouts(&descriptor_set_layouts)
+            .push_constant_ranges(&push_constant_ranges);
+        let pipeline_layout = unsafe {
+            gpu.device_context
+                .device
+                .create_pipeline_layout(&layout_create_info, None)
+                .expect("failed to create pipeline layout!")
+        };
+
+        let data = Assets::load_raw(SKYBOX_SHADER).unwrap();
+        let mut buffer = io::Cursor::new(&data);
+        let shader_code = ash::util::read_spv(&mut buffer).unwrap();
+        let shader_module = gpu.create_shader_module(&shader_code);
+
+        Self {
+            gpu: Rc::clone(gpu),
+
+            environment: None,
+            intensity: 1.0,
+
+            descriptor_set_layout,
+            descriptor_sets,
+            pipeline_layout,
+            shader_module,
+            pipeline: Cell::new(None),
+        }
+    }
+
+    // Inside the forward pass, drawn into the subpass of the render pass with the sample count of
+    // `target`. `clear_depth` is what the depth buffer was cleared to.
+    pub fn render(
+        &self,
+        command_buffer: vk::CommandBuffer,
+        frame_index: usize,
+        (render_pass, subpass, samples): (vk::RenderPass, u32, vk::SampleCountFlags),
+        camera_set: vk::DescriptorSet,
+        gpu_assets: &GPUAssets,
+        clear_depth: f32,
+    ) {
+        let Some(environment) = &self.environment else {
+            return;
+        };
+        let Some(texture) = gpu_assets.get_texture(environment.clone()) else {
+            return;
+        };
+
+        let gpu = &self.gpu;
+        let descriptor_set = self.descriptor_sets[frame_index];
+        gpu.write_texture(descriptor_set, 0, &texture.texture);
+
+        let pipeline = VkPipeline {
+            pipeline: self.get_pipeline(render_pass, subpass, samples),
+            layout: self.pipeline_layout,
+        };
+        let skybox_params = SkyboxParams {
+            depth: clear_depth,
+            intensity: self.intensity,
+        };
+
+        gpu.bind_pipeline(command_buffer, &pipeline);
+        gpu.bind_resource_sets(command_buffer, &pipeline, 0, &[camera_set, descriptor_set]);
+        gpu.push_constants(command_buffer, &pipeline, unsafe {
+            std::slice::from_raw_parts(
+                (&skybox_params as *const SkyboxParams) as *const u8,
+                size_of::<SkyboxParams>(),
+            )
+        });
+        gpu.draw(command_buffer, 3);
+    }
+
+    // A new sample count always comes with a new render pass.
+    fn get_pipeline(
+        &self,
+        render_pass: vk::RenderPass,
+        subpass: u32,
+        samples: vk::SampleCountFlags,
+    ) -> vk::Pipeline {
+        match self.pipeline.get() {
+            Some((pass, index, pipeline)) if pass == render_pass && index == subpass => pipeline,
+            previous => unsafe {
+                // the old render pass is only replaced while the device is idle
+                if let Some((_, _, pipeline)) = previous {
+                    self.gpu
+                        .device_context
+                        .device
+                        .destroy_pipeline(pipeline, None);
+                }
+                let pipeline = self.create_pipeline(render_pass, subpass, samples);
+                self.pipeline.set(Some((render_pass, subpass, pipeline)));
+                pipeline
+            },
+        }
+    }
+
+    unsafe fn create_pipeline(
+        &self,
+        render_pass: vk::RenderPass,
+        subpass: u32,
+        samples: vk::SampleCountFlags,
+    ) -> vk::Pipeline {
+        let vertex_entry = CString::new("vs").unwrap();
+        let fragment_entry = CString::new("fs").unwrap();
+        let shader_stages = [
+            vk::PipelineShaderStageCreateInfo::default()
+                .module(self.shader_module)
+                .stage(vk::ShaderStageFlags::VERTEX)
+                .name(vertex_entry.as_c_str()),
+            vk::PipelineShaderStageCreateInfo::default()
+                .module(self.shader_module)
+                .stage(vk::ShaderStageFlags::FRAGMENT)
+                .name(fragment_entry.as_c_str()),
+        ];
+
+        // the triangle is generated from the vertex index
+        let vertex_input_state = vk::PipelineVertexInputStateCreateInfo::default();
+        let input_assembly_stage = vk::PipelineInputAssemblyStateCreateInfo::default()
+            .topology(vk::PrimitiveTopology::TRIANGLE_LIST);
+        let dynamic_state = vk::PipelineDynamicStateCreateInfo::default()
+            .dynamic_states(&[vk::DynamicState::VIEWPORT, vk::DynamicState::SCISSOR]);
+        let viewport_state = vk::PipelineViewportStateCreateInfo::default()
+            .viewport_count(1)
+            .scissor_count(1);
+        let rasterization_state = vk::PipelineRasterizationStateCreateInfo::default()
+            .cull_mode(vk::CullModeFlags::NONE)
+            .polygon_mode(vk::PolygonMode::FILL)
+            .line_width(1.0);
+        let multisample =
+            vk::PipelineMultisampleStateCreateInfo::default().rasterization_samples(samples);
+        let color_attachments = [vk::PipelineColorBlendAttachmentState {
+            blend_enable: false.into(),
+            color_write_mask: vk::ColorComponentFlags::RGBA,
+            ..Default::default()
+        }];
+        let color_blend =
+            vk::PipelineColorBlendStateCreateInfo::default().attachments(&color_attachments);
+        // the triangle sits exactly at the clear depth, whatever got drawn is in front of it
+        let depth_stencil = vk::PipelineDepthStencilStateCreateInfo::default()
+            .depth_test_enable(true)
+            .depth_write_enable(false)
+            .depth_compare_op(vk::CompareOp::EQUAL);
+
+        let create_info = vk::GraphicsPipelineCreateInfo::default()
+            .stages(&shader_stages)
+            .vertex_input_state(&vertex_input_state)
+            .input_assembly_state(&input_assembly_stage)
+            .dynamic_state(&dynamic_state)
+            .viewport_state(&viewport_state)
+            .rasterization_state(&rasterization_state)
+            .multisample_state(&multisample)
+            .color_blend_state(&color_blend)
+            .depth_stencil_state(&depth_stencil)
+            .layout(self.pipeline_layout)
+            .render_pass(render_pass)
+            .subpass(subpass);
+
+        self.gpu
+            .device_context
+            .device
+            .create_graphics_pipelines(vk::PipelineCache::null(), &[create_info], None)
+            .expect("failed to create skybox pipeline!")[0]
+    }
+}
+
+impl Drop for Skybox {
+    fn drop(&mut self) {
+        unsafe {
+            let device = &self.gpu.device_context.device;
+            if let Some((_, _, pipeline)) = self.pipeline.get() {
+                device.destroy_pipeline(pipeline, None);
+            }
+            device.destroy_pipeline_layout(self.pipeline_layout, None);
+            device.destroy_shader_module(self.shader_module, None);
+            device.destroy_descriptor_set_layout(self.descriptor_set_layout, None);
+        }
+    }
+}
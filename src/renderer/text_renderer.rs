@@ -0,0 +1,817 @@
+use super::GPUAssets;
+use crate::assets::{AssetHandle, AssetId, Assets, Font, Texture};
+use crate::gpu::{Allocation, PassDesc, Rhi, SamplerDesc, VkPipeline, GPU};
+use crate::math::{Mat4, Vec3, Vec4};
+use crate::scene::{DebugDraw, DebugTextAnchor, Query, Text, TextSpace, Transform, World};
+use crate::ui::{UiNode, Viewport};
+use ab_glyph::{point, Font as _, GlyphId, PxScale, ScaleFont};
+use ash::vk;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::ffi::CString;
+use std::io;
+use std::mem::{offset_of, size_of};
+use std::rc::Rc;
+
+const TEXT_SHADER: &str = "text.spv";
+const ATLAS_SIZE: u32 = 1024;
+// pixel height glyphs are rasterized at, text of other sizes scales the quads
+const BAKE_SIZE: f32 = 48.0;
+// the generated mips average 2 x 2 texels per level, the gap keeps neighbours from bleeding in
+const GLYPH_PADDING: u32 = 4;
+const ATLAS_MIPS: u32 = 3;
+// line height of the `DebugDraw` strings in logical pixels
+const DEBUG_TEXT_SIZE: f32 = 16.0;
+// grown to the next power of two when a frame needs more
+const INITIAL_VERTEX_CAPACITY: usize = 6 * 1024;
+
+#[repr(C)]
+#[derive(Copy, Clone)]
+struct TextVertex {
+    // clip space
+    position: [f32; 4],
+    uv: [f32; 2],
+    color: [f32; 4],
+}
+
+#[repr(C)]
+#[derive(Copy, Clone)]
+struct TextParams {
+    srgb_target: u32,
+    _padding: [u32; 3],
+}
+
+// In baked pixels relative to the pen on the baseline, y pointing down.
+#[derive(Debug, Copy, Clone)]
+struct GlyphQuad {
+    min: [f32; 2],
+    max: [f32; 2],
+    uv_min: [f32; 2],
+    uv_max: [f32; 2],
+}
+
+#[derive(Debug, Copy, Clone)]
+struct Glyph {
+    id: GlyphId,
+    advance: f32,
+    // None for glyphs without an outline like spaces, or once the atlas is full
+    quad: Option<GlyphQuad>,
+}
+
+// The glyphs of one font rasterized at `BAKE_SIZE` into a texture asset, added as strings need
+// them. The coverage is in alpha, the color is white. Printable ASCII is there from the start.
+struct GlyphAtlas {
+    font: Font,
+    texture: AssetHandle<Texture>,
+    // one per frame in flight, rewritten before use like the egui sets
+    descriptor_sets: Vec<vk::DescriptorSet>,
+    glyphs: HashMap<char, Glyph>,
+    // shelf packing, the top left of the next glyph and the height of its row
+    cursor: [u32; 2],
+    row_height: u32,
+    // glyphs were added since the texture was uploaded
+    dirty: bool,
+    full: bool,
+}
+
+impl GlyphAtlas {
+    fn new(font: Font, assets: &mut Assets, descriptor_sets: Vec<vk::DescriptorSet>) -> Self {
+        let pixels = [255, 255, 255, 0].repeat((ATLAS_SIZE * ATLAS_SIZE) as usize);
+        let mut texture = Texture::rgba8(ATLAS_SIZE, ATLAS_SIZE, ATLAS_MIPS, pixels);
+        // glyphs at the edge of the atlas would bleed in from the opposite one
+        texture.set_sampler(SamplerDesc::clamped());
+        let texture = assets.handle(texture);
+        let mut atlas = Self {
+            font,
+            texture,
+            descriptor_sets,
+            glyphs: HashMap::new(),
+            cursor: [0, 0],
+            row_height: 0,
+            dirty: true,
+            full: false,
+        };
+        for c in ' '..='~' {
+            atlas.glyph(c, assets);
+        }
+        atlas
+    }
+
+    fn line_height(&self) -> f32 {
+        let font = self.font.font.as_scaled(PxScale::from(BAKE_SIZE));
+        font.height() + font.line_gap()
+    }
+
+    fn glyph(&mut self, c: char, assets: &mut Assets) -> Glyph {
+        if let Some(glyph) = self.glyphs.get(&c) {
+            return *glyph;
+        }
+
+        let font = &self.font.font;
+        let id = font.glyph_id(c);
+        let advance = font.as_scaled(PxScale::from(BAKE_SIZE)).h_advance(id);
+        let quad = font
+            .outline_glyph(id.with_scale_and_position(BAKE_SIZE, point(0.0, 0.0)))
+            .and_then(|outlined| {
+                let bounds = outlined.px_bounds();
+                let width = bounds.width().ceil() as u32;
+                let height = bounds.height().ceil() as u32;
+                if self.cursor[0] + width + GLYPH_PADDING * 2 > ATLAS_SIZE {
+                    self.cursor = [0, self.cursor[1] + self.row_height];
+                    self.row_height = 0;
+                }
+                if self.cursor[1] + height + GLYPH_PADDING * 2 > ATLAS_SIZE {
+                    if !self.full {
+                        log::warn!("glyph atlas is full, '{}' and later glyphs are skipped", c);
+                        self.full = true;
+                    }
+                    return None;
+                }
+
+                let [x, y] = self.cursor.map(|value| value + GLYPH_PADDING);
+                let texture = assets.load_mut(&self.texture).unwrap();
+                outlined.draw(|glyph_x, glyph_y, coverage| {
+                    let index = ((y + glyph_y) * ATLAS_SIZE + x + glyph_x) as usize * 4;
+                    texture.pixels[index + 3] = (coverage.clamp(0.0, 1.0) * 255.0).round() as u8;
+                });
+                self.cursor[0] += width + GLYPH_PADDING * 2;
+                self.row_height = self.row_height.max(height + GLYPH_PADDING * 2);
+                self.dirty = true;
+
+                let size = ATLAS_SIZE as f32;
+                Some(GlyphQuad {
+                    min: [bounds.min.x, bounds.min.y],
+                    max: [bounds.min.x + width as f32, bounds.min.y + height as f32],
+                    uv_min: [x as f32 / size, y as f32 / size],
+                    uv_max: [(x + width) as f32 / size, (y + height) as f32 / size],
+                })
+            });
+
+        let glyph = Glyph { id, advance, quad };
+        self.glyphs.insert(c, glyph);
+        glyph
+    }
+
+    // The glyph quads in baked pixels from the top left of the first line, and the size of the
+    // whole block.
+    fn layout(&mut self, text: &str, assets: &mut Assets) -> (Vec<GlyphQuad>, [f32; 2]) {
+        let scaled = self.font.font.as_scaled(PxScale::from(BAKE_SIZE));
+        let ascent = scaled.ascent();
+        let line_height = self.line_height();
+
+        let mut quads = vec![];
+        let mut width: f32 = 0.0;
+        let mut lines = 0;
+        for line in text.split('\n') {
+            let baseline = ascent + lines as f32 * line_height;
+            let mut pen = 0.0;
+            let mut previous: Option<GlyphId> = None;
+            for c in line.chars() {
+                let glyph = self.glyph(c, assets);
+                if let Some(previous) = previous {
+                    pen += self
+                        .font
+                        .font
+                        .as_scaled(PxScale::from(BAKE_SIZE))
+                        .kern(previous, glyph.id);
+                }
+                if let Some(quad) = glyph.quad {
+                    quads.push(GlyphQuad {
+                        min: [pen + quad.min[0], baseline + quad.min[1]],
+                        max: [pen + quad.max[0], baseline + quad.max[1]],
+                        ..quad
+                    });
+                }
+                pen += glyph.advance;
+                previous = Some(glyph.id);
+            }
+            width = width.max(pen);
+            lines += 1;
+        }
+        (quads, [width, lines as f32 * line_height])
+    }
+}
+
+// Host visible vertex buffer of one frame in flight, rewritten every frame.
+struct FrameBuffer {
+    buffer: vk::Buffer,
+    memory: Allocation,
+    capacity: usize,
+}
+
+// Draws the `Text` comps and the strings of the world's `DebugDraw` over the swap chain image,
+// after the outlines and before egui. Every string becomes quads textured from the glyph atlas of
+// its font, rebuilt every frame, so there is nothing to keep in sync when a text changes. World
+// space text is projected on the CPU with the unjittered view projection, it doesn't test depth.
+pub struct TextRenderer {
+    gpu: Rc<GPU>,
+    assets: Rc<RefCell<Assets>>,
+    gpu_assets: Rc<RefCell<GPUAssets>>,
+
+    format: vk::Format,
+    render_pass: vk::RenderPass,
+    descriptor_set_layout: vk::DescriptorSetLayout,
+    pipeline_layout: vk::PipelineLayout,
+    shader_module: vk::ShaderModule,
+    pipeline: vk::Pipeline,
+    framebuffers: Vec<vk::Framebuffer>,
+
+    builtin_font: AssetHandle<Font>,
+    // by font asset
+    atlases: HashMap<AssetId, GlyphAtlas>,
+    // the quads `collect` built, by font asset
+    batches: Vec<(AssetId, Vec<TextVertex>)>,
+    frames: Vec<FrameBuffer>,
+}
+
+impl TextRenderer {
+    pub fn new(
+        gpu: &Rc<GPU>,
+        assets: Rc<RefCell<Assets>>,
+        gpu_assets: Rc<RefCell<GPUAssets>>,
+        frames_in_flight: u32,
+    ) -> Self {
+        unsafe {
+            let format = gpu.swap_chain.borrow().format;
+            let render_pass = Self::create_render_pass(gpu, format);
+
+            let descriptor_set_layout = gpu.create_descriptor_set_layout(&vec![
+                vk::DescriptorSetLayoutBinding {
+                    binding: 0,
+                    descriptor_type: vk::DescriptorType::SAMPLED_IMAGE,
+                    descriptor_count: 1,
+                    stage_flags: vk::ShaderStageFlags::FRAGMENT,
+                    ..Default::default()
+                },
+                vk::DescriptorSetLayoutBinding {
+                    binding: 1,
+                    descriptor_type: vk::DescriptorType::SAMPLER,
+                    descriptor_count: 1,
+                    stage_flags: vk::ShaderStageFlags::FRAGMENT,
+                    ..Default::default()
+                },
+            ]);
+            let push_constant_ranges = [vk::PushConstantRange::default()
+                .stage_flags(vk::ShaderStageFlags::ALL_GRAPHICS)
+                .offset(0)
+                .size(size_of::<TextParams>() as u32)];
+            let descriptor_set_layouts = [descriptor_set_layout];
+            let layout_create_info = vk::PipelineLayoutCreateInfo::default()
+                .set_layouts(&descriptor_set_layouts)
+                .push_constant_ranges(&push_constant_ranges);
+            let pipeline_layout = gpu
+                .device_context
+                .device
+                .create_pipeline_layout(&layout_create_info, None)
+                .expect("failed to create pipeline layout!");
+
+            let data = Assets::load_raw(TEXT_SHADER).unwrap();
+            let shader_code = ash::util::read_spv(&mut io::Cursor::new(&data)).unwrap();
+            let shader_module = gpu.create_shader_module(&shader_code);
+            let pipeline = Self::create_pipeline(gpu, shader_module, pipeline_layout, render_pass);
+
+            let frames = (0..frames_in_flight)
+                .map(|_| Self::create_frame_buffer(gpu, INITIAL_VERTEX_CAPACITY))
+                .collect();
+            let builtin_font = assets.borrow_mut().handle(Font::builtin());
+
+            let mut text_renderer = Self {
+                gpu: Rc::clone(gpu),
+                assets,
+                gpu_assets,
+
+                format,
+                render_pass,
+                descriptor_set_layout,
+                pipeline_layout,
+                shader_module,
+                pipeline,
+                framebuffers: vec![],
+
+                builtin_font,
+                atlases: HashMap::new(),
+                batches: vec![],
+                frames,
+            };
+            text_renderer.create_framebuffers();
+            text_renderer
+        }
+    }
+
+    // Follows the swap chain, the pipeline only has to be rebuilt when its format changed.
+    pub fn resize(&mut self) {
+        unsafe {
+            self.destroy_framebuffers();
+
+            let format = self.gpu.swap_chain.borrow().format;
+            if format != self.format {
+                let device = &self.gpu.device_context.device;
+                device.destroy_pipeline(self.pipeline, None);
+                device.destroy_render_pass(self.render_pass, None);
+
+                self.render_pass = Self::create_render_pass(&self.gpu, format);
+                self.pipeline = Self::create_pipeline(
+                    &self.gpu,
+                    self.shader_module,
+                    self.pipeline_layout,
+                    self.render_pass,
+                );
+                self.format = format;
+            }
+
+            self.create_framebuffers();
+        }
+    }
+
+    // Lays the texts of the frame out, `view_projection` unjittered.
+    pub fn collect(
+        &mut self,
+        world: &mut World,
+        view: &Mat4,
+        view_projection: &Mat4,
+        viewport: Viewport,
+    ) {
+        self.batches.clear();
+        let extent = self.gpu.swap_chain.borrow().extent;
+        let extent = [extent.width as f32, extent.height as f32];
+        // physical pixels from the top left into clip space
+        let to_clip = |x: f32, y: f32| {
+            Vec4::new(
+                x / extent[0] * 2.0 - 1.0,
+                y / extent[1] * 2.0 - 1.0,
+                0.0,
+                1.0,
+            )
+        };
+        let camera = view.invert();
+        let camera_right = camera.transform_vector(Vec3::new(1.0, 0.0, 0.0));
+        let camera_up = camera.transform_vector(Vec3::new(0.0, 1.0, 0.0));
+
+        let query = Query::<(&Text, Option<&UiNode>, Option<&Transform>)>::new(world);
+        for (text, node, transform) in query {
+            let font = text.font.clone().unwrap_or(self.builtin_font.clone());
+            let color = [text.color.x, text.color.y, text.color.z, text.color.w];
+            let Some((quads, size, line_height)) = self.layout(&font, &text.text) else {
+                continue;
+            };
+            let vertices = match text.space {
+                TextSpace::Screen => {
+                    let origin = node.map_or([0.0, 0.0], |node| [node.rect.x, node.rect.y]);
+                    let scale = text.size * viewport.scale_factor / line_height;
+                    Self::quad_vertices(&quads, color, |[x, y]| {
+                        to_clip(origin[0] + x * scale, origin[1] + y * scale)
+                    })
+                }
+                TextSpace::World => {
+                    let Some(transform) = transform else {
+                        continue;
+                    };
+                    let model_view_projection = *view_projection * transform.matrix();
+                    let scale = text.size / line_height;
+                    Self::quad_vertices(&quads, color, |[x, y]| {
+                        let local = Vec3::new(
+                            (x - size[0] * 0.5) * scale,
+                            (size[1] * 0.5 - y) * scale,
+                            0.0,
+                        );
+                        model_view_projection.project_point(local)
+                    })
+                }
+                TextSpace::Billboard => {
+                    let Some(transform) = transform else {
+                        continue;
+                    };
+                    let center = transform.matrix().transform_point(Vec3::new(0.0, 0.0, 0.0));
+                    let scale = text.size / line_height;
+                    Self::quad_vertices(&quads, color, |[x, y]| {
+                        let position = center
+                            + camera_right * ((x - size[0] * 0.5) * scale)
+                            + camera_up * ((size[1] * 0.5 - y) * scale);
+                        view_projection.project_point(position)
+                    })
+                }
+            };
+            self.push_batch(font.id, vertices);
+        }
+
+        let Some(debug_draw) = world.get_resource::<DebugDraw>() else {
+            return;
+        };
+        let font = self.builtin_font.clone();
+        for (anchor, text, color) in debug_draw.texts() {
+            let origin = match *anchor {
+                DebugTextAnchor::Screen(position) => [position.x, position.y],
+                DebugTextAnchor::World(position) => {
+                    let clip = view_projection.project_point(position);
+                    if clip.w <= 0.0 {
+                        continue;
+                    }
+                    [
+                        (clip.x / clip.w + 1.0) * 0.5 * extent[0],
+                        (clip.y / clip.w + 1.0) * 0.5 * extent[1],
+                    ]
+                }
+            };
+            let Some((quads, _, line_height)) = self.layout(&font, text) else {
+                continue;
+            };
+            let scale = DEBUG_TEXT_SIZE * viewport.scale_factor / line_height;
+            let vertices =
+                Self::quad_vertices(&quads, [color.x, color.y, color.z, 1.0], |[x, y]| {
+                    to_clip(origin[0] + x * scale, origin[1] + y * scale)
+                });
+            self.push_batch(font.id, vertices);
+        }
+    }
+
+    pub fn render(
+        &mut self,
+        command_buffer: vk::CommandBuffer,
+        image_index: usize,
+        frame_index: usize,
+    ) {
+        if self.batches.is_empty() {
+            return;
+        }
+        let vertex_count = self
+            .batches
+            .iter()
+            .map(|(_, vertices)| vertices.len())
+            .sum();
+        self.reserve(frame_index, vertex_count);
+
+        let gpu = &self.gpu;
+        let device = &gpu.device_context.device;
+        let frame = &self.frames[frame_index];
+        let extent = gpu.swap_chain.borrow().extent;
+        let pipeline = VkPipeline {
+            pipeline: self.pipeline,
+            layout: self.pipeline_layout,
+        };
+        let params = TextParams {
+            srgb_target: Self::is_srgb(self.format) as u32,
+            _padding: [0; 3],
+        };
+
+        // new glyphs showed up, uploaded again like a patched egui texture
+        let gpu_assets = self.gpu_assets.borrow();
+        for atlas in self.atlases.values_mut() {
+            if atlas.dirty {
+                gpu_assets.release_texture(&atlas.texture);
+                atlas.dirty = false;
+            }
+        }
+
+        gpu.begin_pass(
+            command_buffer,
+            &PassDesc {
+                label: "text",
+                render_pass: self.render_pass,
+                framebuffer: self.framebuffers[image_index],
+                width: extent.width,
+                height: extent.height,
+                clear_color: [0.0, 0.0, 0.0, 0.0],
+                clear_depth: 1.0,
+            },
+        );
+        gpu.bind_pipeline(command_buffer, &pipeline);
+        gpu.push_constants(command_buffer, &pipeline, unsafe {
+            std::slice::from_raw_parts(
+                (&params as *const TextParams) as *const u8,
+                size_of::<TextParams>(),
+            )
+        });
+
+        let mut first_vertex = 0;
+        unsafe {
+            device.cmd_bind_vertex_buffers(command_buffer, 0, &[frame.buffer], &[0]);
+            let mut written = vec![];
+            for (font, vertices) in &self.batches {
+                std::ptr::copy_nonoverlapping(
+                    vertices.as_ptr(),
+                    (frame.memory.mapped as *mut TextVertex).add(first_vertex),
+                    vertices.len(),
+                );
+                let atlas = &self.atlases[font];
+                let descriptor_set = atlas.descriptor_sets[frame_index];
+                if !written.contains(font) {
+                    let Some(texture) = gpu_assets.get_texture(atlas.texture.clone()) else {
+                        first_vertex += vertices.len();
+                        continue;
+                    };
+                    gpu.write_texture(descriptor_set, 0, &texture.texture);
+                    written.push(*font);
+                }
+                gpu.bind_resource_sets(command_buffer, &pipeline, 0, &[descriptor_set]);
+                device.cmd_draw(
+                    command_buffer,
+                    vertices.len() as u32,
+                    1,
+                    first_vertex as u32,
+                    0,
+                );
+                first_vertex += vertices.len();
+            }
+        }
+
+        gpu.end_pass(command_buffer);
+    }
+
+    // The quads of the string in the font's atlas, with the size of the block and the height of a
+    // line in baked pixels. None when the font asset is gone.
+    fn layout(
+        &mut self,
+        font: &AssetHandle<Font>,
+        text: &str,
+    ) -> Option<(Vec<GlyphQuad>, [f32; 2], f32)> {
+        let mut assets = self.assets.borrow_mut();
+        if !self.atlases.contains_key(&font.id) {
+            let loaded = assets.load(font)?.clone();
+            let descriptor_sets = self
+                .gpu
+                .create_descriptor_sets(&vec![self.descriptor_set_layout; self.frames.len()]);
+            self.atlases.insert(
+                font.id,
+                GlyphAtlas::new(loaded, &mut assets, descriptor_sets),
+            );
+        }
+        let atlas = self.atlases.get_mut(&font.id).unwrap();
+        let (quads, size) = atlas.layout(text, &mut assets);
+        Some((quads, size, atlas.line_height()))
+    }
+
+    // Two triangles per glyph, `place` maps baked pixels of the block to clip space.
+    fn quad_vertices(
+        quads: &[GlyphQuad],
+        color: [f32; 4],
+        place: impl Fn([f32; 2]) -> Vec4,
+    ) -> Vec<TextVertex> {
+        quads
+            .iter()
+            .flat_map(|quad| {
+                let corner = |x: usize, y: usize| {
+                    let position = place([[quad.min, quad.max][x][0], [quad.min, quad.max][y][1]]);
+                    TextVertex {
+                        position: [position.x, position.y, position.z, position.w],
+                        uv: [
+                            [quad.uv_min, quad.uv_max][x][0],
+                            [quad.uv_min, quad.uv_max][y][1],
+                        ],
+                        color,
+                    }
+                };
+                [
+                    corner(0, 0),
+                    corner(1, 0),
+                    corner(1, 1),
+                    corner(0, 0),
+                    corner(1, 1),
+                    corner(0, 1),
+                ]
+            })
+            .collect()
+    }
+
+    // Consecutive strings of a font share a draw.
+    fn push_batch(&mut self, font: AssetId, vertices: Vec<TextVertex>) {
+        match self.batches.last_mut() {
+            Some((last, batch)) if *last == font => batch.extend(vertices),
+            _ => self.batches.push((font, vertices)),
+        }
+    }
+
+    // The frame's fence was waited on, its old buffer is no longer read.
+    fn reserve(&mut self, frame_index: usize, vertex_count: usize) {
+        let frame = &self.frames[frame_index];
+        if vertex_count <= frame.capacity {
+            return;
+        }
+
+        let capacity = frame.capacity.max(vertex_count.next_power_of_two());
+        let frame = std::mem::replace(
+            &mut self.frames[frame_index],
+            Self::create_frame_buffer(&self.gpu, capacity),
+        );
+        unsafe {
+            self.gpu
+                .device_context
+                .destroy_buffer(frame.buffer, frame.memory);
+        }
+    }
+
+    fn create_frame_buffer(gpu: &GPU, capacity: usize) -> FrameBuffer {
+        unsafe {
+            let (buffer, memory) = gpu.device_context.create_buffer(
+                (capacity * size_of::<TextVertex>()) as vk::DeviceSize,
+                vk::BufferUsageFlags::VERTEX_BUFFER,
+                vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT,
+            );
+            FrameBuffer {
+                buffer,
+                memory,
+                capacity,
+            }
+        }
+    }
+
+    fn is_srgb(format: vk::Format) -> bool {
+        matches!(
+            format,
+            vk::Format::B8G8R8A8_SRGB
+                | vk::Format::R8G8B8A8_SRGB
+                | vk::Format::A8B8G8R8_SRGB_PACK32
+        )
+    }
+
+    unsafe fn create_pipeline(
+        gpu: &GPU,
+        shader_module: vk::ShaderModule,
+        pipeline_layout: vk::PipelineLayout,
+        render_pass: vk::RenderPass,
+    ) -> vk::Pipeline {
+        let vertex_entry = CString::new("vs").unwrap();
+        let fragment_entry = CString::new("fs").unwrap();
+        let shader_stages = [
+            vk::PipelineShaderStageCreateInfo::default()
+                .module(shader_module)
+                .stage(vk::ShaderStageFlags::VERTEX)
+                .name(vertex_entry.as_c_str()),
+            vk::PipelineShaderStageCreateInfo::default()
+                .module(shader_module)
+                .stage(vk::ShaderStageFlags::FRAGMENT)
+                .name(fragment_entry.as_c_str()),
+        ];
+
+        let binding_descriptions = [vk::VertexInputBindingDescription {
+            binding: 0,
+            stride: size_of::<TextVertex>() as u32,
+            input_rate: vk::VertexInputRate::VERTEX,
+        }];
+        let attribute_descriptions = [
+            vk::VertexInputAttributeDescription {
+                location: 0,
+                binding: 0,
+                format: vk::Format::R32G32B32A32_SFLOAT,
+                offset: offset_of!(TextVertex, position) as u32,
+            },
+            vk::VertexInputAttributeDescription {
+                location: 1,
+                binding: 0,
+                format: vk::Format::R32G32_SFLOAT,
+                offset: offset_of!(TextVertex, uv) as u32,
+            },
+            vk::VertexInputAttributeDescription {
+                location: 2,
+                binding: 0,
+                format: vk::Format::R32G32B32A32_SFLOAT,
+                offset: offset_of!(TextVertex, color) as u32,
+            },
+        ];
+        let vertex_input_state = vk::PipelineVertexInputStateCreateInfo::default()
+            .vertex_binding_descriptions(&binding_descriptions)
+            .vertex_attribute_descriptions(&attribute_descriptions);
+        let input_assembly_stage = vk::PipelineInputAssemblyStateCreateInfo::default()
+            .topology(vk::PrimitiveTopology::TRIANGLE_LIST);
+        let dynamic_state = vk::PipelineDynamicStateCreateInfo::default()
+            .dynamic_states(&[vk::DynamicState::VIEWPORT, vk::DynamicState::SCISSOR]);
+        let viewport_state = vk::PipelineViewportStateCreateInfo::default()
+            .viewport_count(1)
+            .scissor_count(1);
+        // world space text is readable from behind as well
+        let rasterization_state = vk::PipelineRasterizationStateCreateInfo::default()
+            .cull_mode(vk::CullModeFlags::NONE)
+            .polygon_mode(vk::PolygonMode::FILL)
+            .line_width(1.0);
+        let multisample = vk::PipelineMultisampleStateCreateInfo::default()
+            .rasterization_samples(vk::SampleCountFlags::TYPE_1);
+        // premultiplied alpha
+        let color_attachments = [vk::PipelineColorBlendAttachmentState {
+            blend_enable: true.into(),
+            src_color_blend_factor: vk::BlendFactor::ONE,
+            dst_color_blend_factor: vk::BlendFactor::ONE_MINUS_SRC_ALPHA,
+            color_blend_op: vk::BlendOp::ADD,
+            src_alpha_blend_factor: vk::BlendFactor::ONE_MINUS_DST_ALPHA,
+            dst_alpha_blend_factor: vk::BlendFactor::ONE,
+            alpha_blend_op: vk::BlendOp::ADD,
+            color_write_mask: vk::ColorComponentFlags::RGBA,
+        }];
+        let color_blend =
+            vk::PipelineColorBlendStateCreateInfo::default().attachments(&color_attachments);
+        let depth_stencil = vk::PipelineDepthStencilStateCreateInfo::default()
+            .depth_test_enable(false)
+            .depth_write_enable(false);
+
+        let create_info = vk::GraphicsPipelineCreateInfo::default()
+            .stages(&shader_stages)
+            .vertex_input_state(&vertex_input_state)
+            .input_assembly_state(&input_assembly_stage)
+            .dynamic_state(&dynamic_state)
+            .viewport_state(&viewport_state)
+            .rasterization_state(&rasterization_state)
+            .multisample_state(&multisample)
+            .color_blend_state(&color_blend)
+            .depth_stencil_state(&depth_stencil)
+            .layout(pipeline_layout)
+            .render_pass(render_pass)
+            .subpass(0);
+
+        gpu.device_context
+            .device
+            .create_graphics_pipelines(gpu.pipeline_cache, &[create_info], None)
+            .expect("failed to create text pipeline!")[0]
+    }
+
+    unsafe fn create_render_pass(gpu: &GPU, format: vk::Format) -> vk::RenderPass {
+        // drawn on top of what the post chain and the outlines left
+        let attachments = [vk::AttachmentDescription {
+            format,
+            samples: vk::SampleCountFlags::TYPE_1,
+            load_op: vk::AttachmentLoadOp::LOAD,
+            store_op: vk::AttachmentStoreOp::STORE,
+            stencil_load_op: vk::AttachmentLoadOp::DONT_CARE,
+            stencil_store_op: vk::AttachmentStoreOp::DONT_CARE,
+            initial_layout: vk::ImageLayout::PRESENT_SRC_KHR,
+            final_layout: vk::ImageLayout::PRESENT_SRC_KHR,
+            flags: Default::default(),
+        }];
+        let color_attachment_refs = [vk::AttachmentReference {
+            attachment: 0,
+            layout: vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+        }];
+        let sub_passes = [vk::SubpassDescription::default()
+            .pipeline_bind_point(vk::PipelineBindPoint::GRAPHICS)
+            .color_attachments(&color_attachment_refs)];
+
+        let dependencies = [vk::SubpassDependency {
+            src_subpass: vk::SUBPASS_EXTERNAL,
+            src_stage_mask: vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
+            src_access_mask: vk::AccessFlags::COLOR_ATTACHMENT_WRITE,
+            dst_subpass: 0,
+            dst_stage_mask: vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
+            dst_access_mask: vk::AccessFlags::COLOR_ATTACHMENT_READ
+                | vk::AccessFlags::COLOR_ATTACHMENT_WRITE,
+            ..Default::default()
+        }];
+
+        let create_info = vk::RenderPassCreateInfo::default()
+            .attachments(&attachments)
+            .subpasses(&sub_passes)
+            .dependencies(&dependencies);
+
+        gpu.device_context
+            .device
+            .create_render_pass(&create_info, None)
+            .expect("failed to create text render pass!")
+    }
+
+    unsafe fn create_framebuffers(&mut self) {
+        let swap_chain = self.gpu.swap_chain.borrow();
+        let extent = swap_chain.extent;
+        self.framebuffers = swap_chain
+            .image_views
+            .iter()
+            .map(|&image_view| {
+                let attachments = [image_view];
+                let create_info = vk::FramebufferCreateInfo::default()
+                    .width(extent.width)
+                    .height(extent.height)
+                    .layers(1)
+                    .attachments(&attachments)
+                    .render_pass(self.render_pass);
+
+                self.gpu
+                    .device_context
+                    .device
+                    .create_framebuffer(&create_info, None)
+                    .expect("failed to create framebuffer!")
+            })
+            .collect();
+    }
+
+    unsafe fn destroy_framebuffers(&mut self) {
+        let device = &self.gpu.device_context.device;
+        self.framebuffers
+            .drain(..)
+            .for_each(|framebuffer| device.destroy_framebuffer(framebuffer, None));
+    }
+}
+
+impl Drop for TextRenderer {
+    fn drop(&mut self) {
+        unsafe {
+            self.destroy_framebuffers();
+            let device = &self.gpu.device_context.device;
+            for frame in self.frames.drain(..) {
+                self.gpu
+                    .device_context
+                    .destroy_buffer(frame.buffer, frame.memory);
+            }
+
+            device.destroy_pipeline(self.pipeline, None);
+            device.destroy_shader_module(self.shader_module, None);
+            device.destroy_pipeline_layout(self.pipeline_layout, None);
+            device.destroy_descriptor_set_layout(self.descriptor_set_layout, None);
+            device.destroy_render_pass(self.render_pass, None);
+        }
+    }
+}
@@ -0,0 +1,244 @@
+use super::gpu_texture::GPUTexture;
+use super::{CameraUniforms, ForwardRenderer, GPUAssets, RenderContext, RenderTarget};
+use crate::assets::{AssetHandle, AssetId, Texture};
+use crate::gpu::{SamplerDesc, VkTexture, GPU};
+use ash::vk;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+// What a camera with a `CameraTarget::Texture` sees this frame.
+pub struct TextureCameraView {
+    pub texture: AssetHandle<Texture>,
+    pub width: u32,
+    pub height: u32,
+    pub context: RenderContext,
+}
+
+// The GPU side of one texture camera, its draws don't disturb the window camera's.
+struct TextureCamera {
+    texture: AssetHandle<Texture>,
+    renderer: ForwardRenderer,
+}
+
+// Renders the cameras with a texture target into the image behind their texture handle, before
+// the window camera so the scene samples them the same frame. Each has its own camera uniforms and
+// forward pass, and so its own pipelines. Only the forward pass runs, there are no decals,
+// contact shadows or post processing, the texture holds the linear HDR scene color.
+pub struct TextureCameraRenderer {
+    gpu: Rc<GPU>,
+    gpu_assets: Rc<RefCell<GPUAssets>>,
+
+    cameras: HashMap<AssetId, TextureCamera>,
+}
+
+impl TextureCameraRenderer {
+    pub fn new(gpu: &Rc<GPU>, gpu_assets: Rc<RefCell<GPUAssets>>) -> Self {
+        Self {
+            gpu: Rc::clone(gpu),
+            gpu_assets,
+
+            cameras: HashMap::new(),
+        }
+    }
+
+    // Cameras that are gone, changed size or no longer match the window camera's forward pass are
+    // dropped before anything is recorded, a target may be sampled by another camera's pass.
+    pub fn render(
+        &mut self,
+        command_buffer: vk::CommandBuffer,
+        frame_index: usize,
+        main: &ForwardRenderer,
+        views: Vec<TextureCameraView>,
+    ) {
+        let stale = self
+            .cameras
+            .iter()
+            .filter(|(id, camera)| {
+                let renderer = &camera.renderer;
+                let wanted = views.iter().any(|view| {
+                    view.texture.id == **id
+                        && view.width == renderer.scene_color.width
+                        && view.height == renderer.scene_color.height
+                });
+                !wanted
+                    || renderer.is_mobile_friendly() != main.is_mobile_friendly()
+                    || renderer.get_msaa() != main.get_msaa()
+                    || renderer.is_reverse_z() != main.is_reverse_z()
+                    || renderer.get_recording_threads() != main.get_recording_threads()
+            })
+            .map(|(id, _)| *id)
+            .collect::<Vec<_>>();
+        for id in stale {
+            let camera = self.cameras.remove(&id).unwrap();
+            self.destroy_camera(camera);
+        }
+
+        for view in views {
+            let TextureCameraView {
+                texture,
+                width,
+                height,
+                mut context,
+            } = view;
+            if !self.cameras.contains_key(&texture.id) {
+                let camera = self.create_camera(main, &texture, width, height);
+                self.cameras.insert(texture.id, camera);
+            }
+            let renderer = &mut self.cameras.get_mut(&texture.id).unwrap().renderer;
+
+            // a mirror doesn't see itself, its target can't be sampled while rendered into
+            let gpu_assets = self.gpu_assets.borrow();
+            context
+                .objects
+                .retain(|object| !gpu_assets.material_samples(&object.material, texture.id));
+            drop(gpu_assets);
+
+            renderer
+                .camera_uniforms
+                .inherit_environment(frame_index, &main.camera_uniforms);
+            renderer.skybox.environment = main.skybox.environment.clone();
+            renderer.skybox.intensity = main.skybox.intensity;
+            renderer.render(command_buffer, context, frame_index);
+
+            // sampled by the passes after it, the window camera's or another texture camera's
+            let barrier = vk::MemoryBarrier::default()
+                .src_access_mask(vk::AccessFlags::COLOR_ATTACHMENT_WRITE)
+                .dst_access_mask(vk::AccessFlags::SHADER_READ);
+            unsafe {
+                self.gpu.device_context.device.cmd_pipeline_barrier(
+                    command_buffer,
+                    vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
+                    vk::PipelineStageFlags::VERTEX_SHADER | vk::PipelineStageFlags::FRAGMENT_SHADER,
+                    vk::DependencyFlags::empty(),
+                    &[barrier],
+                    &[],
+                    &[],
+                );
+            }
+        }
+    }
+
+    fn create_camera(
+        &self,
+        main: &ForwardRenderer,
+        texture: &AssetHandle<Texture>,
+        width: u32,
+        height: u32,
+    ) -> TextureCamera {
+        let gpu = &self.gpu;
+        let format = ForwardRenderer::SCENE_FORMAT;
+        unsafe {
+            let (image, memory) = gpu.device_context.create_image(
+                width,
+                height,
+                1,
+                vk::SampleCountFlags::TYPE_1,
+                format,
+                vk::ImageTiling::OPTIMAL,
+                vk::ImageUsageFlags::COLOR_ATTACHMENT
+                    | vk::ImageUsageFlags::SAMPLED
+                    | vk::ImageUsageFlags::TRANSFER_DST,
+                vk::MemoryPropertyFlags::DEVICE_LOCAL,
+            );
+            let view =
+                gpu.device_context
+                    .create_image_view(image, format, vk::ImageAspectFlags::COLOR, 1);
+            gpu.debug_names.set_name(image, "camera target");
+            Self::clear_target(gpu, image);
+
+            // the texture pool owns the image, the renderer only resolves into it
+            self.gpu_assets.borrow().replace_texture(
+                texture,
+                GPUTexture {
+                    texture: VkTexture {
+                        image,
+                        image_memory: memory,
+                        image_view: view,
+                        image_sampler: gpu.get_sampler(&SamplerDesc::default()),
+                        format,
+                        mip_levels: 1,
+                    },
+                },
+            );
+            let target = RenderTarget {
+                image,
+                memory,
+                view,
+                format,
+                width,
+                height,
+            };
+
+            let camera_uniforms =
+                Rc::new(CameraUniforms::new(gpu, ForwardRenderer::FRAMES_IN_FLIGHT));
+            let mut renderer = ForwardRenderer::with_target(gpu, camera_uniforms, target);
+            let gpu_assets = self.gpu_assets.borrow();
+            renderer.set_mobile_friendly(main.is_mobile_friendly(), &gpu_assets);
+            renderer.set_msaa(main.get_msaa(), &gpu_assets);
+            renderer.set_reverse_z(main.is_reverse_z());
+            renderer.set_recording_threads(main.get_recording_threads());
+
+            TextureCamera {
+                texture: texture.clone(),
+                renderer,
+            }
+        }
+    }
+
+    // Black until the camera first renders, materials may sample it before that.
+    unsafe fn clear_target(gpu: &GPU, image: vk::Image) {
+        let format = ForwardRenderer::SCENE_FORMAT;
+        gpu.transition_image_layout(
+            image,
+            format,
+            1,
+            1,
+            vk::ImageLayout::UNDEFINED,
+            vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+        );
+        let command_buffer = gpu.begin_single_time_command();
+        gpu.device_context.device.cmd_clear_color_image(
+            command_buffer,
+            image,
+            vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+            &vk::ClearColorValue {
+                float32: [0.0, 0.0, 0.0, 1.0],
+            },
+            &[vk::ImageSubresourceRange {
+                aspect_mask: vk::ImageAspectFlags::COLOR,
+                base_mip_level: 0,
+                level_count: 1,
+                base_array_layer: 0,
+                layer_count: 1,
+            }],
+        );
+        gpu.end_single_time_command(command_buffer);
+        gpu.transition_image_layout(
+            image,
+            format,
+            1,
+            1,
+            vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+            vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+        );
+    }
+
+    // Waits for the device, its forward pass and attachments are destroyed right away and the
+    // pipelines built for it go with it. The texture handle shows the asset's own pixels again.
+    fn destroy_camera(&self, camera: TextureCamera) {
+        self.gpu.wait_idle();
+        let gpu_assets = self.gpu_assets.borrow();
+        gpu_assets.release_render_pass(camera.renderer.render_pass);
+        drop(camera.renderer);
+        gpu_assets.release_texture(&camera.texture);
+    }
+}
+
+impl Drop for TextureCameraRenderer {
+    fn drop(&mut self) {
+        for (_, camera) in std::mem::take(&mut self.cameras) {
+            self.destroy_camera(camera);
+        }
+    }
+}
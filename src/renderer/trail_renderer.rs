@@ -0,0 +1,126 @@
+use crate::assets::{AssetHandle, Assets, DynamicGeom, Material, Texture};
+use crate::gpu::{ColorSpace, MipFilter, SamplerDesc, TextureFormat};
+use crate::math::{Mat4, Vec3};
+use crate::renderer::vertex::Vertex;
+use crate::renderer::{RenderObject, Shading};
+use crate::scene::{Pooled, Query, Trail, Transform, World};
+
+// Builds the ribbons of `Trail` entities into their dynamic geometry, facing the camera.
+pub struct TrailRenderer {
+    // untextured, for trails without a material of their own
+    material: AssetHandle<Material>,
+}
+
+impl TrailRenderer {
+    pub fn new(assets: &mut Assets) -> Self {
+        let white = assets.handle(Texture {
+            width: 1,
+            height: 1,
+            mip_levels: 1,
+            layers: 1,
+            format: TextureFormat::Rgba8Srgb,
+            color_space: ColorSpace::Srgb,
+            pixels: vec![255; 4],
+            mips: vec![],
+            mip_filter: MipFilter::Linear,
+            sampler: SamplerDesc::default(),
+        });
+        let mut material = Material::new(Shading::load_trail());
+        material.set_texture("texture", Some(white));
+        Self {
+            material: assets.handle(material),
+        }
+    }
+
+    // `eye` is where the camera is, the ribbons turn their face to it.
+    pub fn collect(
+        &self,
+        world: &mut World,
+        assets: &mut Assets,
+        eye: Vec3,
+        objects: &mut Vec<RenderObject>,
+    ) {
+        let query = Query::<(&Transform, &mut Trail, Option<&Pooled>)>::new(world);
+        for (transform, trail, pooled) in query {
+            if pooled.is_some_and(|pooled| !pooled.active) {
+                continue;
+            }
+            let (vertices, indices) = Self::build_ribbon(trail, transform.location, eye);
+            if indices.is_empty() {
+                continue;
+            }
+
+            let geom = match &trail.geom {
+                Some(geom) => {
+                    if let Some(dynamic_geom) = assets.load_mut(geom) {
+                        dynamic_geom.set(vertices, indices);
+                    }
+                    geom.clone()
+                }
+                None => {
+                    let geom = assets.handle(DynamicGeom::new(vertices, indices));
+                    trail.geom = Some(geom.clone());
+                    geom
+                }
+            };
+            let material = trail.material.clone().unwrap_or(self.material.clone());
+            // points are in world space already
+            objects.push(RenderObject::new(geom, material, Mat4::identity()));
+        }
+    }
+
+    // A quad per segment from the entity back to the oldest point. The ribbon runs across the
+    // direction of the trail and the way to the camera, so it is seen flat from anywhere.
+    fn build_ribbon(trail: &Trail, head: Vec3, eye: Vec3) -> (Vec<Vertex>, Vec<u32>) {
+        // newest first, the entity itself leads so the ribbon never lags behind it
+        let points = std::iter::once((head, 0.0))
+            .chain(
+                trail
+                    .points()
+                    .iter()
+                    .rev()
+                    .map(|point| (point.position, point.age)),
+            )
+            .collect::<Vec<_>>();
+        if points.len() < 2 {
+            return (vec![], vec![]);
+        }
+
+        let mut vertices = Vec::with_capacity(points.len() * 2);
+        for (i, &(position, age)) in points.iter().enumerate() {
+            let previous = points[i.saturating_sub(1)].0;
+            let next = points[(i + 1).min(points.len() - 1)].0;
+            let along = next - previous;
+            let side = along.cross(eye - position);
+            let side = match side.len() > f32::EPSILON {
+                true => side.normalize(),
+                // seen straight down the trail, any side is as good
+                false => Vec3::new(0.0, 0.0, 0.0),
+            };
+
+            let fade = 1.0 - (age / trail.lifetime.max(f32::EPSILON)).clamp(0.0, 1.0);
+            let half_width = trail.width * fade * 0.5;
+            let u = 1.0 - fade + trail.uv_offset();
+            let color = [trail.color.x, trail.color.y, trail.color.z];
+            for (offset, v) in [(-half_width, 0.0), (half_width, 1.0)] {
+                let corner = position + side * offset;
+                vertices.push(Vertex {
+                    position: [corner.x, corner.y, corner.z],
+                    color,
+                    uv: [u, v],
+                    // the trail shading reads its alpha from x
+                    normal: [fade, 0.0, 0.0],
+                });
+            }
+        }
+
+        // counter clockwise seen from the camera
+        let mut indices = Vec::with_capacity((points.len() - 1) * 6);
+        for segment in 0..points.len() as u32 - 1 {
+            let (a, b) = (segment * 2, segment * 2 + 1);
+            let (c, d) = (a + 2, b + 2);
+            indices.extend_from_slice(&[a, b, c, b, d, c]);
+        }
+        (vertices, indices)
+    }
+}
@@ -8,6 +8,7 @@ pub struct Vertex {
     pub position: [f32; 3],
     pub color: [f32; 3],
     pub uv: [f32; 2],
+    pub normal: [f32; 3],
 }
 
 impl Vertex {
@@ -19,7 +20,7 @@ impl Vertex {
         }
     }
 
-    pub fn get_attribute_descriptions() -> [vk::VertexInputAttributeDescription; 3] {
+    pub fn get_attribute_descriptions() -> [vk::VertexInputAttributeDescription; 4] {
         [
             vk::VertexInputAttributeDescription {
                 location: 0,
@@ -39,6 +40,12 @@ impl Vertex {
                 format: vk::Format::R32G32_SFLOAT,
                 offset: size_of::<[f32; 3]>() as u32 * 2,
             },
+            vk::VertexInputAttributeDescription {
+                location: 3,
+                binding: 0,
+                format: vk::Format::R32G32B32_SFLOAT,
+                offset: size_of::<[f32; 3]>() as u32 * 2 + size_of::<[f32; 2]>() as u32,
+            },
         ]
     }
 }
@@ -0,0 +1,616 @@
+use super::gpu_texture::GPUTexture;
+use super::{ForwardRenderer, GPUAssets};
+use crate::assets::{AssetHandle, AssetId, Assets, Texture, VideoFrame, VideoTexture};
+use crate::gpu::{Allocation, PassDesc, RawResource, Rhi, SamplerDesc, VkPipeline, VkTexture, GPU};
+use ash::vk;
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::ffi::CString;
+use std::io;
+use std::rc::Rc;
+
+const FULLSCREEN_SHADER: &str = "fullscreen.spv";
+const VIDEO_SHADER: &str = "video_yuv.spv";
+const OUTPUT_FORMAT: vk::Format = vk::Format::R8G8B8A8_SRGB;
+
+// The GPU side of one `VideoTexture`.
+struct VideoTarget {
+    texture: AssetHandle<Texture>,
+    width: u32,
+    height: u32,
+    // Y, U and V, sampled by the conversion
+    planes: [(vk::Image, Allocation, vk::ImageView); 3],
+    // one per frame in flight, a frame's planes back to back
+    staging: Vec<(vk::Buffer, Allocation)>,
+    // renders into the image behind `texture`, owned by the texture pool of `GPUAssets`
+    framebuffer: vk::Framebuffer,
+    descriptor_set: vk::DescriptorSet,
+}
+
+impl VideoTarget {
+    fn plane_sizes(&self) -> [(u32, u32); 3] {
+        let chroma = VideoFrame::chroma_size(self.width, self.height);
+        [(self.width, self.height), chroma, chroma]
+    }
+}
+
+// Converts the I420 frames of the `VideoTexture` assets to RGB, rendering into the image behind
+// each video's texture handle. The planes are uploaded from a staging buffer in the frame's
+// command buffer, so the conversion is ordered before the scene sampling it.
+pub struct VideoRenderer {
+    gpu: Rc<GPU>,
+    gpu_assets: Rc<RefCell<GPUAssets>>,
+
+    render_pass: vk::RenderPass,
+    descriptor_set_layout: vk::DescriptorSetLayout,
+    pipeline_layout: vk::PipelineLayout,
+    pipeline: vk::Pipeline,
+    sampler: vk::Sampler,
+
+    targets: HashMap<AssetId, VideoTarget>,
+    // the newest frame per video that wasn't converted yet
+    pending: HashMap<AssetId, VideoFrame>,
+}
+
+impl VideoRenderer {
+    pub fn new(gpu: &Rc<GPU>, gpu_assets: Rc<RefCell<GPUAssets>>) -> Self {
+        unsafe {
+            let render_pass = Self::create_render_pass(gpu);
+
+            let plane_binding = |binding| vk::DescriptorSetLayoutBinding {
+                binding,
+                descriptor_type: vk::DescriptorType::SAMPLED_IMAGE,
+                descriptor_count: 1,
+                stage_flags: vk::ShaderStageFlags::FRAGMENT,
+                ..Default::default()
+            };
+            let descriptor_set_layout = gpu.create_descriptor_set_layout(&vec![
+                plane_binding(0),
+                plane_binding(1),
+                plane_binding(2),
+                vk::DescriptorSetLayoutBinding {
+                    binding: 3,
+                    descriptor_type: vk::DescriptorType::SAMPLER,
+                    descriptor_count: 1,
+                    stage_flags: vk::ShaderStageFlags::FRAGMENT,
+                    ..Default::default()
+                },
+            ]);
+            let descriptor_set_layouts = [descriptor_set_layout];
+            let layout_create_info =
+                vk::PipelineLayoutCreateInfo::default().set_layouts(&descriptor_set_layouts);
+            let pipeline_layout = gpu
+                .device_context
+                .device
+                .create_pipeline_layout(&layout_create_info, None)
+                .expect("failed to create pipeline layout!");
+            let pipeline = Self::create_pipeline(gpu, render_pass, pipeline_layout);
+
+            // chroma is half the resolution, filtered up to the luma
+            let sampler_create_info = vk::SamplerCreateInfo::default()
+                .min_filter(vk::Filter::LINEAR)
+                .mag_filter(vk::Filter::LINEAR)
+                .mipmap_mode(vk::SamplerMipmapMode::NEAREST)
+                .address_mode_u(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+                .address_mode_v(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+                .address_mode_w(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+                .max_lod(0.0);
+            let sampler = gpu
+                .device_context
+                .device
+                .create_sampler(&sampler_create_info, None)
+                .expect("failed to create video sampler!");
+
+            Self {
+                gpu: Rc::clone(gpu),
+                gpu_assets,
+
+                render_pass,
+                descriptor_set_layout,
+                pipeline_layout,
+                pipeline,
+                sampler,
+
+                targets: HashMap::new(),
+                pending: HashMap::new(),
+            }
+        }
+    }
+
+    // Advances every video, keeping the frames that became due for `render`. Videos removed from
+    // the assets get their GPU side released.
+    pub fn update(&mut self, assets: &mut Assets, delta_time: f32) {
+        let mut live = HashSet::new();
+        for (id, video) in assets.iter_mut::<VideoTexture>() {
+            live.insert(id);
+            if !self.targets.contains_key(&id) {
+                let target = self.create_target(video.texture.clone(), video.width, video.height);
+                self.targets.insert(id, target);
+            }
+            if let Some(frame) = video.tick(delta_time) {
+                self.pending.insert(id, frame);
+            }
+        }
+
+        let removed = self
+            .targets
+            .keys()
+            .filter(|id| !live.contains(id))
+            .copied()
+            .collect::<Vec<_>>();
+        for id in removed {
+            self.pending.remove(&id);
+            let target = self.targets.remove(&id).unwrap();
+            self.destroy_target(target);
+        }
+    }
+
+    pub fn render(&mut self, command_buffer: vk::CommandBuffer, frame_index: usize) {
+        for (id, frame) in self.pending.drain() {
+            let Some(target) = self.targets.get(&id) else {
+                continue;
+            };
+            if (frame.width, frame.height) != (target.width, target.height) {
+                log::warn!(
+                    "video frame of {}x{} doesn't fit the texture of {}x{}!",
+                    frame.width,
+                    frame.height,
+                    target.width,
+                    target.height
+                );
+                continue;
+            }
+            unsafe {
+                Self::upload_planes(&self.gpu, command_buffer, target, frame_index, &frame);
+            }
+
+            let pipeline = VkPipeline {
+                pipeline: self.pipeline,
+                layout: self.pipeline_layout,
+            };
+            self.gpu.begin_pass(
+                command_buffer,
+                &PassDesc {
+                    label: "video",
+                    render_pass: self.render_pass,
+                    framebuffer: target.framebuffer,
+                    width: target.width,
+                    height: target.height,
+                    clear_color: [0.0, 0.0, 0.0, 1.0],
+                    clear_depth: 1.0,
+                },
+            );
+            self.gpu.bind_pipeline(command_buffer, &pipeline);
+            self.gpu
+                .bind_resource_sets(command_buffer, &pipeline, 0, &[target.descriptor_set]);
+            self.gpu.draw(command_buffer, 3);
+            self.gpu.end_pass(command_buffer);
+        }
+    }
+
+    unsafe fn upload_planes(
+        gpu: &GPU,
+        command_buffer: vk::CommandBuffer,
+        target: &VideoTarget,
+        frame_index: usize,
+        frame: &VideoFrame,
+    ) {
+        let device = &gpu.device_context.device;
+        let (staging_buffer, staging_memory) = target.staging[frame_index];
+
+        let mut offset = 0;
+        let mut regions = vec![];
+        for (plane, (width, height)) in frame.planes.iter().zip(target.plane_sizes()) {
+            let size = (width * height) as usize;
+            std::ptr::copy_nonoverlapping(
+                plane.as_ptr(),
+                (staging_memory.mapped as *mut u8).add(offset),
+                size.min(plane.len()),
+            );
+            regions.push(vk::BufferImageCopy {
+                buffer_offset: offset as vk::DeviceSize,
+                buffer_row_length: 0,
+                buffer_image_height: 0,
+                image_subresource: vk::ImageSubresourceLayers {
+                    aspect_mask: vk::ImageAspectFlags::COLOR,
+                    mip_level: 0,
+                    base_array_layer: 0,
+                    layer_count: 1,
+                },
+                image_offset: vk::Offset3D::default(),
+                image_extent: vk::Extent3D {
+                    width,
+                    height,
+                    depth: 1,
+                },
+            });
+            offset += size;
+        }
+
+        // the previous conversion may still sample the planes, their content is replaced
+        let barriers = |old_layout, new_layout, src_access_mask, dst_access_mask| {
+            target.planes.map(|(image, _, _)| {
+                vk::ImageMemoryBarrier::default()
+                    .image(image)
+                    .old_layout(old_layout)
+                    .new_layout(new_layout)
+                    .src_access_mask(src_access_mask)
+                    .dst_access_mask(dst_access_mask)
+                    .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+                    .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+                    .subresource_range(vk::ImageSubresourceRange {
+                        aspect_mask: vk::ImageAspectFlags::COLOR,
+                        base_mip_level: 0,
+                        level_count: 1,
+                        base_array_layer: 0,
+                        layer_count: 1,
+                    })
+            })
+        };
+        device.cmd_pipeline_barrier(
+            command_buffer,
+            vk::PipelineStageFlags::FRAGMENT_SHADER,
+            vk::PipelineStageFlags::TRANSFER,
+            vk::DependencyFlags::empty(),
+            &[],
+            &[],
+            &barriers(
+                vk::ImageLayout::UNDEFINED,
+                vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                vk::AccessFlags::NONE,
+                vk::AccessFlags::TRANSFER_WRITE,
+            ),
+        );
+        for ((image, _, _), region) in target.planes.iter().zip(regions) {
+            device.cmd_copy_buffer_to_image(
+                command_buffer,
+                staging_buffer,
+                *image,
+                vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                &[region],
+            );
+        }
+        device.cmd_pipeline_barrier(
+            command_buffer,
+            vk::PipelineStageFlags::TRANSFER,
+            vk::PipelineStageFlags::FRAGMENT_SHADER,
+            vk::DependencyFlags::empty(),
+            &[],
+            &[],
+            &barriers(
+                vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+                vk::AccessFlags::TRANSFER_WRITE,
+                vk::AccessFlags::SHADER_READ,
+            ),
+        );
+    }
+
+    fn create_target(&self, texture: AssetHandle<Texture>, width: u32, height: u32) -> VideoTarget {
+        unsafe {
+            let gpu = &self.gpu;
+            let device_context = &gpu.device_context;
+
+            let chroma = VideoFrame::chroma_size(width, height);
+            let planes = [(width, height), chroma, chroma].map(|(width, height)| {
+                let (image, memory) = device_context.create_image(
+                    width,
+                    height,
+                    1,
+                    vk::SampleCountFlags::TYPE_1,
+                    vk::Format::R8_UNORM,
+                    vk::ImageTiling::OPTIMAL,
+                    vk::ImageUsageFlags::TRANSFER_DST | vk::ImageUsageFlags::SAMPLED,
+                    vk::MemoryPropertyFlags::DEVICE_LOCAL,
+                );
+                let view = device_context.create_image_view(
+                    image,
+                    vk::Format::R8_UNORM,
+                    vk::ImageAspectFlags::COLOR,
+                    1,
+                );
+                (image, memory, view)
+            });
+            let staging = (0..ForwardRenderer::FRAMES_IN_FLIGHT)
+                .map(|_| {
+                    device_context.create_buffer(
+                        VideoFrame::byte_size(width, height) as vk::DeviceSize,
+                        vk::BufferUsageFlags::TRANSFER_SRC,
+                        vk::MemoryPropertyFlags::HOST_VISIBLE
+                            | vk::MemoryPropertyFlags::HOST_COHERENT,
+                    )
+                })
+                .collect();
+
+            let (image, image_memory) = device_context.create_image(
+                width,
+                height,
+                1,
+                vk::SampleCountFlags::TYPE_1,
+                OUTPUT_FORMAT,
+                vk::ImageTiling::OPTIMAL,
+                vk::ImageUsageFlags::COLOR_ATTACHMENT
+                    | vk::ImageUsageFlags::SAMPLED
+                    | vk::ImageUsageFlags::TRANSFER_DST,
+                vk::MemoryPropertyFlags::DEVICE_LOCAL,
+            );
+            let image_view = device_context.create_image_view(
+                image,
+                OUTPUT_FORMAT,
+                vk::ImageAspectFlags::COLOR,
+                1,
+            );
+            Self::clear_output(gpu, image);
+            self.gpu_assets.borrow().replace_texture(
+                &texture,
+                GPUTexture {
+                    texture: VkTexture {
+                        image,
+                        image_memory,
+                        image_view,
+                        image_sampler: gpu.get_sampler(&SamplerDesc::default()),
+                        format: OUTPUT_FORMAT,
+                        mip_levels: 1,
+                    },
+                },
+            );
+
+            let attachments = [image_view];
+            let framebuffer_create_info = vk::FramebufferCreateInfo::default()
+                .width(width)
+                .height(height)
+                .layers(1)
+                .attachments(&attachments)
+                .render_pass(self.render_pass);
+            let framebuffer = device_context
+                .device
+                .create_framebuffer(&framebuffer_create_info, None)
+                .expect("failed to create framebuffer!");
+
+            let descriptor_set = gpu.create_descriptor_sets(&[self.descriptor_set_layout])[0];
+            let image_infos = planes.map(|(_, _, image_view)| {
+                [vk::DescriptorImageInfo {
+                    image_view,
+                    image_layout: vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+                    sampler: self.sampler,
+                }]
+            });
+            let mut writes = image_infos
+                .iter()
+                .enumerate()
+                .map(|(binding, image_info)| {
+                    vk::WriteDescriptorSet::default()
+                        .descriptor_type(vk::DescriptorType::SAMPLED_IMAGE)
+                        .image_info(image_info)
+                        .dst_set(descriptor_set)
+                        .dst_binding(binding as u32)
+                })
+                .collect::<Vec<_>>();
+            writes.push(
+                vk::WriteDescriptorSet::default()
+                    .descriptor_type(vk::DescriptorType::SAMPLER)
+                    .image_info(&image_infos[0])
+                    .dst_set(descriptor_set)
+                    .dst_binding(3),
+            );
+            device_context.device.update_descriptor_sets(&writes, &[]);
+
+            VideoTarget {
+                texture,
+                width,
+                height,
+                planes,
+                staging,
+                framebuffer,
+                descriptor_set,
+            }
+        }
+    }
+
+    // Black until the first frame is converted, materials may sample it right away.
+    unsafe fn clear_output(gpu: &GPU, image: vk::Image) {
+        gpu.transition_image_layout(
+            image,
+            OUTPUT_FORMAT,
+            1,
+            1,
+            vk::ImageLayout::UNDEFINED,
+            vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+        );
+        let command_buffer = gpu.begin_single_time_command();
+        gpu.device_context.device.cmd_clear_color_image(
+            command_buffer,
+            image,
+            vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+            &vk::ClearColorValue {
+                float32: [0.0, 0.0, 0.0, 1.0],
+            },
+            &[vk::ImageSubresourceRange {
+                aspect_mask: vk::ImageAspectFlags::COLOR,
+                base_mip_level: 0,
+                level_count: 1,
+                base_array_layer: 0,
+                layer_count: 1,
+            }],
+        );
+        gpu.end_single_time_command(command_buffer);
+        gpu.transition_image_layout(
+            image,
+            OUTPUT_FORMAT,
+            1,
+            1,
+            vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+            vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+        );
+    }
+
+    fn destroy_target(&self, target: VideoTarget) {
+        // the output goes with the texture pool entry
+        self.gpu_assets.borrow().release_texture(&target.texture);
+
+        let gpu = &self.gpu;
+        gpu.free_descriptor_sets(&[target.descriptor_set]);
+        gpu.defer_destroy(RawResource::Framebuffer(target.framebuffer));
+        for (image, memory, view) in target.planes {
+            gpu.defer_destroy(RawResource::ImageView(view));
+            gpu.defer_destroy(RawResource::Image(image));
+            gpu.defer_destroy(RawResource::Allocation(memory));
+        }
+        for (buffer, memory) in target.staging {
+            gpu.defer_destroy(RawResource::Buffer(buffer));
+            gpu.defer_destroy(RawResource::Allocation(memory));
+        }
+    }
+
+    unsafe fn create_pipeline(
+        gpu: &GPU,
+        render_pass: vk::RenderPass,
+        pipeline_layout: vk::PipelineLayout,
+    ) -> vk::Pipeline {
+        let device = &gpu.device_context.device;
+        let [vertex_module, fragment_module] = [FULLSCREEN_SHADER, VIDEO_SHADER].map(|path| {
+            let data = Assets::load_raw(path).unwrap();
+            let mut buffer = io::Cursor::new(&data);
+            let shader_code = ash::util::read_spv(&mut buffer).unwrap();
+            gpu.create_shader_module(&shader_code)
+        });
+
+        let vertex_entry = CString::new("vs").unwrap();
+        let fragment_entry = CString::new("fs").unwrap();
+        let shader_stages = [
+            vk::PipelineShaderStageCreateInfo::default()
+                .module(vertex_module)
+                .stage(vk::ShaderStageFlags::VERTEX)
+                .name(vertex_entry.as_c_str()),
+            vk::PipelineShaderStageCreateInfo::default()
+                .module(fragment_module)
+                .stage(vk::ShaderStageFlags::FRAGMENT)
+                .name(fragment_entry.as_c_str()),
+        ];
+
+        // the triangle is generated from the vertex index
+        let vertex_input_state = vk::PipelineVertexInputStateCreateInfo::default();
+        let input_assembly_stage = vk::PipelineInputAssemblyStateCreateInfo::default()
+            .topology(vk::PrimitiveTopology::TRIANGLE_LIST);
+        let dynamic_state = vk::PipelineDynamicStateCreateInfo::default()
+            .dynamic_states(&[vk::DynamicState::VIEWPORT, vk::DynamicState::SCISSOR]);
+        let viewport_state = vk::PipelineViewportStateCreateInfo::default()
+            .viewport_count(1)
+            .scissor_count(1);
+        let rasterization_state = vk::PipelineRasterizationStateCreateInfo::default()
+            .cull_mode(vk::CullModeFlags::NONE)
+            .polygon_mode(vk::PolygonMode::FILL)
+            .line_width(1.0);
+        let multisample = vk::PipelineMultisampleStateCreateInfo::default()
+            .rasterization_samples(vk::SampleCountFlags::TYPE_1);
+        let color_attachments = [vk::PipelineColorBlendAttachmentState {
+            blend_enable: false.into(),
+            color_write_mask: vk::ColorComponentFlags::RGBA,
+            ..Default::default()
+        }];
+        let color_blend =
+            vk::PipelineColorBlendStateCreateInfo::default().attachments(&color_attachments);
+        let depth_stencil = vk::PipelineDepthStencilStateCreateInfo::default()
+            .depth_test_enable(false)
+            .depth_write_enable(false);
+
+        let create_info = vk::GraphicsPipelineCreateInfo::default()
+            .stages(&shader_stages)
+            .vertex_input_state(&vertex_input_state)
+            .input_assembly_state(&input_assembly_stage)
+            .dynamic_state(&dynamic_state)
+            .viewport_state(&viewport_state)
+            .rasterization_state(&rasterization_state)
+            .multisample_state(&multisample)
+            .color_blend_state(&color_blend)
+            .depth_stencil_state(&depth_stencil)
+            .layout(pipeline_layout)
+            .render_pass(render_pass)
+            .subpass(0);
+
+        let pipeline = device
+            .create_graphics_pipelines(vk::PipelineCache::null(), &[create_info], None)
+            .expect("failed to create video pipeline!")[0];
+        device.destroy_shader_module(vertex_module, None);
+        device.destroy_shader_module(fragment_module, None);
+        pipeline
+    }
+
+    unsafe fn create_render_pass(gpu: &GPU) -> vk::RenderPass {
+        // every pixel gets overwritten, nothing to load
+        let attachments = [vk::AttachmentDescription {
+            format: OUTPUT_FORMAT,
+            samples: vk::SampleCountFlags::TYPE_1,
+            load_op: vk::AttachmentLoadOp::DONT_CARE,
+            store_op: vk::AttachmentStoreOp::STORE,
+            stencil_load_op: vk::AttachmentLoadOp::DONT_CARE,
+            stencil_store_op: vk::AttachmentStoreOp::DONT_CARE,
+            initial_layout: vk::ImageLayout::UNDEFINED,
+            final_layout: vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+            flags: Default::default(),
+        }];
+        let color_attachment_refs = [vk::AttachmentReference {
+            attachment: 0,
+            layout: vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+        }];
+        let sub_passes = [vk::SubpassDescription::default()
+            .pipeline_bind_point(vk::PipelineBindPoint::GRAPHICS)
+            .color_attachments(&color_attachment_refs)];
+
+        // the previous frame may still sample the output, the scene samples it after this pass
+        let dependencies = [
+            vk::SubpassDependency {
+                src_subpass: vk::SUBPASS_EXTERNAL,
+                src_stage_mask: vk::PipelineStageFlags::FRAGMENT_SHADER,
+                src_access_mask: vk::AccessFlags::NONE,
+                dst_subpass: 0,
+                dst_stage_mask: vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
+                dst_access_mask: vk::AccessFlags::COLOR_ATTACHMENT_WRITE,
+                ..Default::default()
+            },
+            vk::SubpassDependency {
+                src_subpass: 0,
+                src_stage_mask: vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
+                src_access_mask: vk::AccessFlags::COLOR_ATTACHMENT_WRITE,
+                dst_subpass: vk::SUBPASS_EXTERNAL,
+                dst_stage_mask: vk::PipelineStageFlags::VERTEX_SHADER
+                    | vk::PipelineStageFlags::FRAGMENT_SHADER,
+                dst_access_mask: vk::AccessFlags::SHADER_READ,
+                ..Default::default()
+            },
+        ];
+
+        let create_info = vk::RenderPassCreateInfo::default()
+            .attachments(&attachments)
+            .subpasses(&sub_passes)
+            .dependencies(&dependencies);
+
+        gpu.device_context
+            .device
+            .create_render_pass(&create_info, None)
+            .expect("failed to create video render pass!")
+    }
+}
+
+impl Drop for VideoRenderer {
+    fn drop(&mut self) {
+        unsafe {
+            self.gpu.wait_idle();
+            let targets = self
+                .targets
+                .drain()
+                .map(|(_, target)| target)
+                .collect::<Vec<_>>();
+            for target in targets {
+                self.destroy_target(target);
+            }
+
+            let device = &self.gpu.device_context.device;
+            device.destroy_pipeline(self.pipeline, None);
+            device.destroy_sampler(self.sampler, None);
+            device.destroy_pipeline_layout(self.pipeline_layout, None);
+            device.destroy_descriptor_set_layout(self.descriptor_set_layout, None);
+            device.destroy_render_pass(self.render_pass, None);
+        }
+    }
+}
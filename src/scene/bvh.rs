@@ -0,0 +1,388 @@
+use crate::math::{Aabb, Ray};
+use crate::scene::Entity;
+use std::collections::HashMap;
+
+// Once refits have made the tree this much more expensive to traverse than right after
+// the last build, it is rebuilt from scratch.
+const REBUILD_COST_RATIO: f32 = 1.5;
+
+#[derive(Debug, Copy, Clone)]
+struct BvhNode {
+    aabb: Aabb,
+    // children of internal nodes, leaves hold an entity instead
+    left: usize,
+    right: usize,
+    entity: Option<Entity>,
+}
+
+// Scene bounding volume hierarchy for picking, raycasts and overlap queries.
+// Moving entities only refit the boxes of the existing tree, which is much cheaper than a build
+// but slowly loosens it. Rebuilds happen when entities come or go, or the tree got too loose.
+pub struct Bvh {
+    // parents always come before their children, so one reverse pass refits the whole tree
+    nodes: Vec<BvhNode>,
+    leaves: HashMap<Entity, usize>,
+    build_cost: f32,
+}
+
+impl Bvh {
+    pub fn new() -> Self {
+        Self {
+            nodes: vec![],
+            leaves: HashMap::new(),
+            build_cost: 0.0,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.leaves.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.leaves.is_empty()
+    }
+
+    pub fn build(&mut self, items: &[(Entity, Aabb)]) {
+        self.nodes.clear();
+        self.leaves.clear();
+
+        if !items.is_empty() {
+            let mut items = items.to_vec();
+            self.build_node(&mut items);
+        }
+        self.build_cost = self.cost();
+    }
+
+    // Called every frame with the current boxes of all entities.
+    pub fn update(&mut self, items: &[(Entity, Aabb)]) {
+        let same_entities = items.len() == self.leaves.len()
+            && items
+                .iter()
+                .all(|(entity, _)| self.leaves.contains_key(entity));
+        if !same_entities {
+            self.build(items);
+            return;
+        }
+
+        let mut changed = false;
+        for (entity, aabb) in items {
+            let node = &mut self.nodes[self.leaves[entity]];
+            if node.aabb != *aabb {
+                node.aabb = *aabb;
+                changed = true;
+            }
+        }
+        if !changed {
+            return;
+        }
+
+        self.refit();
+        if self.cost() > self.build_cost * REBUILD_COST_RATIO {
+            self.build(items);
+        }
+    }
+
+    // Closest entity whose box the ray hits, with the distance to it.
+    pub fn raycast(&self, ray: &Ray, max_distance: f32) -> Option<(Entity, f32)> {
+        self.raycast_with(ray, max_distance, |_, distance| Some(distance))
+    }
+
+    // Closest entity by the distance `hit` gives for the entities whose box the ray hits, None
+    // when the ray misses the entity after all, e.g. between the triangles of its mesh.
+    pub fn raycast_with<F>(&self, ray: &Ray, max_distance: f32, mut hit: F) -> Option<(Entity, f32)>
+    where
+        F: FnMut(Entity, f32) -> Option<f32>,
+    {
+        let mut closest: Option<(Entity, f32)> = None;
+        let mut stack = vec![];
+        if !self.nodes.is_empty() {
+            stack.push(0);
+        }
+
+        while let Some(index) = stack.pop() {
+            let node = &self.nodes[index];
+            let max_distance = closest.map_or(max_distance, |(_, distance)| distance);
+            let Some(distance) = node.aabb.intersect_ray(ray, max_distance) else {
+                continue;
+            };
+            match node.entity {
+                Some(entity) => match hit(entity, distance) {
+                    Some(distance) if distance <= max_distance => {
+                        closest = Some((entity, distance))
+                    }
+                    _ => {}
+                },
+                None => {
+                    stack.push(node.left);
+                    stack.push(node.right);
+                }
+            }
+        }
+
+        closest
+    }
+
+    pub fn query(&self, aabb: &Aabb) -> Vec<Entity> {
+        let mut entities = vec![];
+        let mut stack = vec![];
+        if !self.nodes.is_empty() {
+            stack.push(0);
+        }
+
+        while let Some(index) = stack.pop() {
+            let node = &self.nodes[index];
+            if !node.aabb.intersects(aabb) {
+                continue;
+            }
+            match node.entity {
+                Some(entity) => entities.push(entity),
+                None => {
+                    stack.push(node.left);
+                    stack.push(node.right);
+                }
+            }
+        }
+
+        entities
+    }
+
+    // Top down, split at the median centroid along the longest axis.
+    fn build_node(&mut self, items: &mut [(Entity, Aabb)]) -> usize {
+        let index = self.nodes.len();
+        let aabb = items
+            .iter()
+            .fold(Aabb::empty(), |aabb, (_, item)| aabb.union(*item));
+        self.nodes.push(BvhNode {
+            aabb,
+            left: 0,
+            right: 0,
+            entity: None,
+        });
+
+        if let [(entity, _)] = items {
+            self.nodes[index].entity = Some(*entity);
+            self.leaves.insert(*entity, index);
+            return index;
+        }
+
+        let centroids = items.iter().fold(Aabb::empty(), |aabb, (_, item)| {
+            aabb.union(Aabb::new(item.center(), item.center()))
+        });
+        let size = centroids.size();
+        let axis = |aabb: &Aabb| {
+            let center = aabb.center();
+            if size.x >= size.y && size.x >= size.z {
+                center.x
+            } else if size.y >= size.z {
+                center.y
+            } else {
+                center.z
+            }
+        };
+        items.sort_by(|(_, a), (_, b)| axis(a).total_cmp(&axis(b)));
+
+        let (left, right) = items.split_at_mut(items.len() / 2);
+        self.nodes[index].left = self.build_node(left);
+        self.nodes[index].right = self.build_node(right);
+        index
+    }
+
+    fn refit(&mut self) {
+        for index in (0..self.nodes.len()).rev() {
+            let node = self.nodes[index];
+            if node.entity.is_none() {
+                self.nodes[index].aabb = self.nodes[node.left]
+                    .aabb
+                    .union(self.nodes[node.right].aabb);
+            }
+        }
+    }
+
+    // Surface area heuristic, expected number of internal nodes a random ray visits.
+    fn cost(&self) -> f32 {
+        let Some(root) = self.nodes.first() else {
+            return 0.0;
+        };
+        let root_area = root.aabb.surface_area().max(f32::EPSILON);
+        self.nodes
+            .iter()
+            .filter(|node| node.entity.is_none())
+            .map(|node| node.aabb.surface_area() / root_area)
+            .sum()
+    }
+}
+
+impl Default for Bvh {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Bvh;
+    use crate::math::{Aabb, Ray, Vec3};
+    use crate::scene::Entity;
+
+    fn unit_box(center: Vec3) -> Aabb {
+        Aabb::new(center - Vec3::one() * 0.5, center + Vec3::one() * 0.5)
+    }
+
+    // boxes of pseudo random size spread over a 20 unit cube
+    fn scattered(count: u32) -> Vec<(Entity, Aabb)> {
+        let mut seed = 12345u32;
+        let mut next = || {
+            seed = seed.wrapping_mul(1664525).wrapping_add(1013904223);
+            (seed >> 8) as f32 / (1 << 24) as f32
+        };
+        (0..count)
+            .map(|id| {
+                let center = Vec3::new(next(), next(), next()) * 20.0;
+                let half = Vec3::new(next(), next(), next()) + Vec3::one() * 0.1;
+                (Entity::new(id), Aabb::new(center - half, center + half))
+            })
+            .collect()
+    }
+
+    fn sorted(mut entities: Vec<Entity>) -> Vec<u32> {
+        entities.sort_by_key(|entity| entity.id);
+        entities.into_iter().map(|entity| entity.id).collect()
+    }
+
+    // every entity in one leaf, parents before and around their children
+    fn assert_well_formed(bvh: &Bvh, count: usize) {
+        assert_eq!(bvh.len(), count);
+        let leaves = bvh
+            .nodes
+            .iter()
+            .filter(|node| node.entity.is_some())
+            .count();
+        assert_eq!(leaves, count);
+        for (index, node) in bvh.nodes.iter().enumerate() {
+            if node.entity.is_some() {
+                continue;
+            }
+            for child in [node.left, node.right] {
+                assert!(child > index);
+                let aabb = bvh.nodes[child].aabb;
+                assert_eq!(node.aabb.union(aabb), node.aabb);
+            }
+        }
+    }
+
+    #[test]
+    fn empty_tree_finds_nothing() {
+        let mut bvh = Bvh::new();
+        bvh.build(&[]);
+        let ray = Ray::new(Vec3::zero(), Vec3::new(1.0, 0.0, 0.0));
+        assert_eq!(bvh.raycast(&ray, f32::MAX), None);
+        assert!(bvh.query(&unit_box(Vec3::zero())).is_empty());
+        assert_eq!(bvh.len(), 0);
+    }
+
+    #[test]
+    fn build_holds_every_entity() {
+        let items = scattered(100);
+        let mut bvh = Bvh::new();
+        bvh.build(&items);
+        assert_well_formed(&bvh, items.len());
+        assert_eq!(bvh.nodes.len(), items.len() * 2 - 1);
+    }
+
+    #[test]
+    fn raycast_finds_the_closest_box() {
+        let items = (0..8)
+            .map(|i| {
+                (
+                    Entity::new(i),
+                    unit_box(Vec3::new(i as f32 * 3.0, 0.0, 0.0)),
+                )
+            })
+            .collect::<Vec<_>>();
+        let mut bvh = Bvh::new();
+        bvh.build(&items);
+
+        let forward = Ray::new(Vec3::new(-5.0, 0.0, 0.0), Vec3::new(1.0, 0.0, 0.0));
+        assert_eq!(bvh.raycast(&forward, f32::MAX), Some((Entity::new(0), 4.5)));
+        let backward = Ray::new(Vec3::new(30.0, 0.0, 0.0), Vec3::new(-1.0, 0.0, 0.0));
+        assert_eq!(
+            bvh.raycast(&backward, f32::MAX),
+            Some((Entity::new(7), 8.5))
+        );
+
+        // too short to reach the first box, or passing above all of them
+        assert_eq!(bvh.raycast(&forward, 4.0), None);
+        let above = Ray::new(Vec3::new(-5.0, 1.0, 0.0), Vec3::new(1.0, 0.0, 0.0));
+        assert_eq!(bvh.raycast(&above, f32::MAX), None);
+
+        // the closest box is missed on a closer look, the one behind it is hit
+        let hit = bvh.raycast_with(&forward, f32::MAX, |entity, distance| {
+            (entity != Entity::new(0)).then_some(distance)
+        });
+        assert_eq!(hit, Some((Entity::new(1), 7.5)));
+    }
+
+    #[test]
+    fn query_matches_a_brute_force_search() {
+        let items = scattered(200);
+        let mut bvh = Bvh::new();
+        bvh.build(&items);
+
+        for center in [Vec3::zero(), Vec3::one() * 10.0, Vec3::new(5.0, 15.0, 2.0)] {
+            let area = Aabb::new(center - Vec3::one() * 3.0, center + Vec3::one() * 3.0);
+            let expected = items
+                .iter()
+                .filter(|(_, aabb)| aabb.intersects(&area))
+                .map(|(entity, _)| *entity)
+                .collect();
+            assert_eq!(sorted(bvh.query(&area)), sorted(expected));
+        }
+        let far = unit_box(Vec3::one() * 100.0);
+        assert!(bvh.query(&far).is_empty());
+    }
+
+    #[test]
+    fn moving_entities_refit_the_tree() {
+        let mut items = scattered(50);
+        let mut bvh = Bvh::new();
+        bvh.build(&items);
+        let build_cost = bvh.build_cost;
+
+        // a small move keeps the tree, only its boxes follow
+        let (entity, aabb) = items[0];
+        items[0].1 = Aabb::new(aabb.min + 0.25, aabb.max + 0.25);
+        bvh.update(&items);
+        assert_eq!(bvh.build_cost, build_cost);
+        assert_well_formed(&bvh, items.len());
+        assert!(bvh.query(&items[0].1).contains(&entity));
+        let leaf = bvh.leaves[&entity];
+        assert_eq!(bvh.nodes[leaf].aabb, items[0].1);
+    }
+
+    #[test]
+    fn loose_trees_and_new_entities_rebuild() {
+        let mut items = scattered(50);
+        let mut bvh = Bvh::new();
+        bvh.build(&items);
+
+        // half the entities jump far away, refitting would leave huge overlapping boxes
+        for (_, aabb) in items.iter_mut().step_by(2) {
+            *aabb = Aabb::new(aabb.min + 100.0, aabb.max + 100.0);
+        }
+        bvh.update(&items);
+        assert_well_formed(&bvh, items.len());
+        assert_eq!(bvh.cost(), bvh.build_cost);
+
+        items.push((Entity::new(1000), unit_box(Vec3::one() * -10.0)));
+        bvh.update(&items);
+        assert_well_formed(&bvh, items.len());
+        let ray = Ray::new(Vec3::new(-10.0, -10.0, -15.0), Vec3::new(0.0, 0.0, 1.0));
+        let (entity, _) = bvh.raycast(&ray, f32::MAX).unwrap();
+        assert_eq!(entity, Entity::new(1000));
+
+        items.remove(0);
+        bvh.update(&items);
+        assert_well_formed(&bvh, items.len());
+        assert!(!bvh.leaves.contains_key(&Entity::new(0)));
+    }
+}
@@ -1,14 +1,285 @@
-use crate::scene::Comp;
+use crate::assets::{AssetHandle, Assets, Texture};
+use crate::math::{ndc_to_screen, screen_to_ndc, Mat4, Ray, Vec2, Vec3, Vec4};
+use crate::scene::serialize::{Fields, SerializeComp, Value};
+use crate::scene::{Comp, PostOverrides, Query, Storage, Transform, World};
+use std::cell::RefCell;
+
+// Where a camera renders to.
+#[derive(Debug, Clone)]
+pub enum CameraTarget {
+    // the swap chain, with post processing and UI on top, the last window camera wins
+    Window,
+    // the image behind `texture`, materials sampling it show what the camera sees, e.g. mirrors
+    // and security camera screens. Linear HDR color without post processing, `aspect` should be
+    // `width / height`.
+    Texture {
+        texture: AssetHandle<Texture>,
+        width: u32,
+        height: u32,
+    },
+}
+
+impl CameraTarget {
+    // A texture for the camera to render into, black until it does.
+    pub fn texture(assets: &mut Assets, width: u32, height: u32) -> Self {
+        CameraTarget::Texture {
+            texture: assets.handle(Texture::rgba8(1, 1, 1, vec![0, 0, 0, 255])),
+            width,
+            height,
+        }
+    }
+}
+
+// How view space maps to clip space.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum ProjectionMode {
+    // `fov` vertically, the far plane at infinity
+    Perspective,
+    // `height` world units fit the viewport vertically, depth runs from `near` to `far`, e.g. for
+    // 2D, isometric views and top down maps
+    Orthographic { height: f32, far: f32 },
+}
 
 pub struct Camera {
     pub fov: f32,
+    // window cameras follow the swap chain, see `window_camera`
     pub aspect: f32,
     pub near: f32,
+    pub projection_mode: ProjectionMode,
+    pub target: CameraTarget,
+    // inactive cameras render nothing
+    pub active: bool,
+    // the depth convention of the renderer, which keeps it in sync, see `Mirage::set_reverse_z`
+    pub(crate) reverse_z: bool,
+    view_key: RefCell<Option<Mat4>>,
+    view_cache: RefCell<Mat4>,
+    projection_key: RefCell<Option<([f32; 3], ProjectionMode, bool)>>,
+    projection_cache: RefCell<Mat4>,
 }
 
-impl Comp for Camera {}
+impl Comp for Camera {
+    fn storage() -> Storage {
+        Storage::Sparse
+    }
+}
 impl Camera {
     pub fn new(fov: f32, aspect: f32, near: f32) -> Camera {
-        Self { fov, aspect, near }
+        Self {
+            fov,
+            aspect,
+            near,
+            projection_mode: ProjectionMode::Perspective,
+            target: CameraTarget::Window,
+            active: true,
+            reverse_z: true,
+            view_key: RefCell::new(None),
+            view_cache: RefCell::new(Mat4::identity()),
+            projection_key: RefCell::new(None),
+            projection_cache: RefCell::new(Mat4::identity()),
+        }
+    }
+
+    pub fn orthographic(height: f32, aspect: f32, near: f32, far: f32) -> Camera {
+        let mut camera = Self::new(std::f32::consts::FRAC_PI_2, aspect, near);
+        camera.projection_mode = ProjectionMode::Orthographic { height, far };
+        camera
     }
+
+    pub fn is_orthographic(&self) -> bool {
+        matches!(self.projection_mode, ProjectionMode::Orthographic { .. })
+    }
+
+    // The inverse is only recomputed when the camera transform actually moved.
+    pub fn view(&self, transform: &Transform) -> Mat4 {
+        let matrix = transform.matrix();
+        let mut maybe_key = self.view_key.borrow_mut();
+        match *maybe_key {
+            Some(key) if key.eq(&matrix) => {}
+            _ => {
+                *maybe_key = Some(matrix);
+                *self.view_cache.borrow_mut() = matrix.invert();
+            }
+        }
+        *self.view_cache.borrow()
+    }
+
+    // With reversed or regular depth like the renderer. The far plane of perspective cameras is
+    // at infinity.
+    pub fn projection(&self) -> Mat4 {
+        let curr_key = (
+            [self.fov, self.aspect, self.near],
+            self.projection_mode,
+            self.reverse_z,
+        );
+        let mut maybe_key = self.projection_key.borrow_mut();
+        match *maybe_key {
+            Some(key) if key.eq(&curr_key) => {}
+            _ => {
+                *maybe_key = Some(curr_key);
+                *self.projection_cache.borrow_mut() = match (self.projection_mode, self.reverse_z) {
+                    (ProjectionMode::Perspective, true) => {
+                        Mat4::perspective_reversed_z_infinite_rh(self.fov, self.aspect, self.near)
+                    }
+                    (ProjectionMode::Perspective, false) => {
+                        Mat4::perspective_infinite_rh(self.fov, self.aspect, self.near)
+                    }
+                    (ProjectionMode::Orthographic { height, far }, reverse_z) => {
+                        let (half_width, half_height) = (height * self.aspect / 2.0, height / 2.0);
+                        // swapping the planes maps the near one to 1
+                        let (near, far) = match reverse_z {
+                            true => (far, self.near),
+                            false => (self.near, far),
+                        };
+                        Mat4::orthographic_rh(
+                            -half_width,
+                            half_width,
+                            -half_height,
+                            half_height,
+                            near,
+                            far,
+                        )
+                    }
+                };
+            }
+        }
+        *self.projection_cache.borrow()
+    }
+
+    // What the shaders get as `camera_params`: x vertical field of view, y aspect, z the height
+    // of an orthographic camera, 0 for perspective ones.
+    pub fn params(&self) -> [f32; 4] {
+        let height = match self.projection_mode {
+            ProjectionMode::Perspective => 0.0,
+            ProjectionMode::Orthographic { height, .. } => height,
+        };
+        [self.fov, self.aspect, height, 0.0]
+    }
+
+    // The camera looks down -Z of view space.
+    pub fn world_to_view(&self, transform: &Transform, point: Vec3) -> Vec3 {
+        self.view(transform).transform_point(point)
+    }
+
+    // Before the divide by w, which is the distance in front of the camera.
+    pub fn world_to_clip(&self, transform: &Transform, point: Vec3) -> Vec4 {
+        (self.projection() * self.view(transform)).project_point(point)
+    }
+
+    // None behind the camera, where the divide would mirror the point onto the screen. z is the
+    // depth, 1 at the near plane going to 0 far away when reversed, the other way around if not.
+    pub fn world_to_ndc(&self, transform: &Transform, point: Vec3) -> Option<Vec3> {
+        let clip = self.world_to_clip(transform, point);
+        (clip.w > 0.0).then(|| Vec3::new(clip.x, clip.y, clip.z) / clip.w)
+    }
+
+    // Pixels from the top left of a viewport of `size`, e.g. for labels and health bars. Points off
+    // screen are returned as well, None is only for points behind the camera.
+    pub fn world_to_screen(&self, transform: &Transform, point: Vec3, size: Vec2) -> Option<Vec2> {
+        let ndc = self.world_to_ndc(transform, point)?;
+        Some(ndc_to_screen(Vec2::new(ndc.x, ndc.y), size))
+    }
+
+    // From the near plane through a pixel, for picking.
+    pub fn screen_to_world_ray(&self, transform: &Transform, screen: Vec2, size: Vec2) -> Ray {
+        Ray::from_screen(
+            screen,
+            size,
+            &self.view(transform),
+            &self.projection(),
+            self.near_depth(),
+        )
+    }
+
+    // The world position of a pixel with the depth buffer value `depth`.
+    pub fn screen_to_world(
+        &self,
+        transform: &Transform,
+        screen: Vec2,
+        size: Vec2,
+        depth: f32,
+    ) -> Vec3 {
+        let ndc = screen_to_ndc(screen, size);
+        let inverse = (self.projection() * self.view(transform)).invert();
+        let world = inverse.project_point(Vec3::new(ndc.x, ndc.y, depth));
+        Vec3::new(world.x, world.y, world.z) / world.w
+    }
+
+    // Distance in front of the camera of a depth buffer value. The far plane of perspective
+    // cameras is at infinity, so reversed depth falls off with 1 / distance from 1 at the near
+    // plane. Orthographic depth is linear between the planes.
+    pub fn linear_depth(&self, depth: f32) -> f32 {
+        let depth = match self.reverse_z {
+            true => depth,
+            false => 1.0 - depth,
+        };
+        match self.projection_mode {
+            ProjectionMode::Perspective => self.near / depth.max(f32::MIN_POSITIVE),
+            ProjectionMode::Orthographic { far, .. } => far - depth * (far - self.near),
+        }
+    }
+
+    // The depth buffer value at the near plane.
+    pub fn near_depth(&self) -> f32 {
+        match self.reverse_z {
+            true => 1.0,
+            false => 0.0,
+        }
+    }
+}
+
+impl SerializeComp for Camera {
+    const TYPE_NAME: &'static str = "Camera";
+    const VERSION: u32 = 1;
+
+    // the aspect of window cameras follows the window again, texture targets aren't saved
+    fn serialize(&self) -> Fields {
+        let mut fields = Fields::from([
+            ("fov".to_string(), Value::Float(self.fov)),
+            ("aspect".to_string(), Value::Float(self.aspect)),
+            ("near".to_string(), Value::Float(self.near)),
+            ("active".to_string(), Value::Bool(self.active)),
+        ]);
+        if let ProjectionMode::Orthographic { height, far } = self.projection_mode {
+            fields.insert("orthographic_height".to_string(), Value::Float(height));
+            fields.insert("far".to_string(), Value::Float(far));
+        }
+        fields
+    }
+
+    fn deserialize(fields: &Fields) -> Self {
+        let get =
+            |name: &str, default: f32| fields.get(name).and_then(Value::as_f32).unwrap_or(default);
+        let mut camera = Self::new(
+            get("fov", std::f32::consts::FRAC_PI_2),
+            get("aspect", 1.0),
+            get("near", 0.01),
+        );
+        if let Some(height) = fields.get("orthographic_height").and_then(Value::as_f32) {
+            camera.projection_mode = ProjectionMode::Orthographic {
+                height,
+                far: get("far", 1000.0),
+            };
+        }
+        camera.active = fields.get("active") != Some(&Value::Bool(false));
+        camera
+    }
+}
+
+// The camera system, run when a frame is extracted: window cameras take the aspect of the swap
+// chain's `extent`, the last active one is what the frame renders from.
+pub fn window_camera(
+    world: &mut World,
+    extent: (u32, u32),
+) -> Option<(&Transform, &Camera, Option<&PostOverrides>)> {
+    // a minimized window has no size, the aspect stays what it was
+    let aspect = (extent.0 > 0 && extent.1 > 0).then(|| extent.0 as f32 / extent.1 as f32);
+    Query::<(&Transform, &mut Camera, Option<&PostOverrides>)>::new(world)
+        .filter(|(_, camera, _)| camera.active && matches!(camera.target, CameraTarget::Window))
+        .map(|(transform, camera, overrides)| {
+            if let Some(aspect) = aspect {
+                camera.aspect = aspect;
+            }
+            (transform, &*camera, overrides)
+        })
+        .last()
 }
@@ -0,0 +1,28 @@
+use crate::assets::{AssetHandle, AssetId, DynamicGeom};
+use crate::scene::ecs::Comp;
+
+// The geom and the revision of its vertices debug lines were expanded from.
+pub type LinesSource = (AssetId, u64);
+
+// Marks a mesh whose normals and tangents are visualized by the debug pass.
+#[derive(Debug, Clone, Default)]
+pub struct DebugNormals {
+    // the handle the compute expanded lines are drawn behind, only made when geometry shaders are
+    // unavailable. Next to it what they were expanded from, None once stale
+    pub lines: Option<(AssetHandle<DynamicGeom>, Option<LinesSource>)>,
+}
+
+impl Comp for DebugNormals {}
+
+impl DebugNormals {
+    pub fn new() -> Self {
+        Self { lines: None }
+    }
+
+    // The lines are expanded again on the next frame, e.g. after the geom was reloaded.
+    pub fn invalidate(&mut self) {
+        if let Some((_, built_from)) = &mut self.lines {
+            *built_from = None;
+        }
+    }
+}
@@ -0,0 +1,48 @@
+use crate::assets::{AssetHandle, Texture};
+use crate::math::Vec3;
+use crate::scene::ecs::{Comp, Storage};
+
+// How a decal lands on the surface it is projected onto.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+#[repr(u8)]
+pub enum DecalBlend {
+    // covers the surface by the texture alpha
+    #[default]
+    Alpha,
+    // darkens the surface, e.g. dirt or scorch marks
+    Multiply,
+    // adds light on top, e.g. glowing runes
+    Additive,
+}
+
+// Projects the texture onto whatever surfaces lie inside the unit box of its entity's Transform,
+// down local -Z, u along X and v down Y. Scale the Transform to size the box, the depth of the box
+// decides how far the decal reaches. Drawn after the opaque pass, so transparent objects and
+// mobile friendly rendering are not decaled.
+#[derive(Debug, Clone)]
+pub struct Decal {
+    pub texture: AssetHandle<Texture>,
+    pub color: Vec3,
+    pub opacity: f32,
+    pub blend: DecalBlend,
+    // overlapping decals draw from low to high
+    pub sort_order: i32,
+}
+
+impl Comp for Decal {
+    fn storage() -> Storage {
+        Storage::Sparse
+    }
+}
+
+impl Decal {
+    pub fn new(texture: AssetHandle<Texture>) -> Self {
+        Self {
+            texture,
+            color: Vec3::new(1.0, 1.0, 1.0),
+            opacity: 1.0,
+            blend: DecalBlend::default(),
+            sort_order: 0,
+        }
+    }
+}
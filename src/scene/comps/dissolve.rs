@@ -0,0 +1,38 @@
+use crate::math::Vec3;
+use crate::scene::ecs::{Comp, Storage};
+
+// Burns the mesh away along the engine Perlin noise, `amount` 0 is intact and 1 fully gone.
+// The standard shading cuts the surface below the threshold and lights up the edge, animate
+// `amount` from a system to fade entities in or out.
+#[derive(Debug, Copy, Clone)]
+pub struct Dissolve {
+    pub amount: f32,
+    // in noise units, how far behind the cut the glow fades out
+    pub edge_width: f32,
+    pub edge_color: Vec3,
+    // noise repeats per uv unit
+    pub noise_scale: f32,
+}
+
+impl Comp for Dissolve {
+    fn storage() -> Storage {
+        Storage::Sparse
+    }
+}
+
+impl Dissolve {
+    pub fn new(amount: f32) -> Self {
+        Self {
+            amount,
+            edge_width: 0.05,
+            edge_color: Vec3::new(4.0, 1.5, 0.3),
+            noise_scale: 1.0,
+        }
+    }
+}
+
+impl Default for Dissolve {
+    fn default() -> Self {
+        Self::new(0.0)
+    }
+}
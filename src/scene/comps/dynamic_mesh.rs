@@ -0,0 +1,18 @@
+use crate::assets::{AssetHandle, DynamicGeom, Material};
+use crate::scene::ecs::Comp;
+
+// Draws a `DynamicGeom` like a `StaticMesh`, whatever was written to the geometry during the
+// update shows up in that frame. Not picked, the BVH only knows static meshes.
+#[derive(Debug, Clone)]
+pub struct DynamicMesh {
+    pub geom: AssetHandle<DynamicGeom>,
+    pub material: AssetHandle<Material>,
+}
+
+impl Comp for DynamicMesh {}
+
+impl DynamicMesh {
+    pub fn new(geom: AssetHandle<DynamicGeom>, material: AssetHandle<Material>) -> Self {
+        Self { geom, material }
+    }
+}
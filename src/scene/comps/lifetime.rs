@@ -0,0 +1,47 @@
+use crate::scene::ecs::{Comp, SystemState, World};
+use crate::scene::Pooled;
+
+// Seconds the entity has left, `expire_lifetimes` despawns it once they run out, or hands it
+// back to its `EntityPool` when it came from one.
+#[derive(Debug, Copy, Clone)]
+pub struct Lifetime {
+    pub remaining: f32,
+}
+
+impl Comp for Lifetime {}
+
+impl Lifetime {
+    pub fn new(seconds: f32) -> Self {
+        Self { remaining: seconds }
+    }
+
+    pub fn is_expired(&self) -> bool {
+        self.remaining <= 0.0
+    }
+}
+
+// Writes `Lifetime` and `Pooled`, despawns through the system's commands.
+pub fn expire_lifetimes(world: &mut World, state: &SystemState) {
+    // in slot order, the despawns decide which slots are reused first
+    for entity in world.ordered_entities() {
+        // released pooled entities are on hold until acquired again
+        if world
+            .get_entity_comp::<Pooled>(entity)
+            .is_some_and(|pooled| !pooled.active)
+        {
+            continue;
+        }
+        let Some(lifetime) = world.get_entity_comp_mut::<Lifetime>(entity) else {
+            continue;
+        };
+        lifetime.remaining -= state.delta_time;
+        if !lifetime.is_expired() {
+            continue;
+        }
+
+        match world.get_entity_comp_mut::<Pooled>(entity) {
+            Some(pooled) => pooled.active = false,
+            None => state.commands.despawn(entity),
+        }
+    }
+}
@@ -0,0 +1,182 @@
+use crate::math::Vec3;
+use crate::scene::ecs::{Comp, Storage};
+use crate::scene::serialize::{Fields, SerializeComp, Value};
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum LightKind {
+    Point,
+    // cone angles in radians from the light direction, full intensity inside `inner_angle`
+    // fading out to zero at `outer_angle`
+    Spot { inner_angle: f32, outer_angle: f32 },
+}
+
+// Positioned by the Transform of its entity, spot lights shine down the local -Z axis.
+#[derive(Debug, Copy, Clone)]
+pub struct Light {
+    pub kind: LightKind,
+    pub color: Vec3,
+    pub intensity: f32,
+    // the contribution smoothly reaches zero at this distance
+    pub range: f32,
+    // into the shadow atlas, six tiles for a point light, see `ShadowRenderer`
+    pub cast_shadows: bool,
+    // short screen space shadows where small details touch, see `ContactShadowRenderer`
+    pub contact_shadows: bool,
+}
+
+impl Comp for Light {
+    fn storage() -> Storage {
+        Storage::Sparse
+    }
+}
+
+impl Light {
+    pub fn point(color: Vec3, intensity: f32, range: f32) -> Self {
+        Self {
+            kind: LightKind::Point,
+            color,
+            intensity,
+            range,
+            cast_shadows: false,
+            contact_shadows: false,
+        }
+    }
+
+    pub fn spot(
+        color: Vec3,
+        intensity: f32,
+        range: f32,
+        inner_angle: f32,
+        outer_angle: f32,
+    ) -> Self {
+        Self {
+            kind: LightKind::Spot {
+                inner_angle: inner_angle.min(outer_angle),
+                outer_angle,
+            },
+            ..Self::point(color, intensity, range)
+        }
+    }
+
+    // The smallest sphere around what the light reaches, its range around the location for a
+    // point light, the cone down `direction` for a spot light.
+    pub fn bounding_sphere(&self, location: Vec3, direction: Vec3) -> (Vec3, f32) {
+        match self.kind {
+            LightKind::Point => (location, self.range),
+            LightKind::Spot { outer_angle, .. } => {
+                let (sin, cos) = outer_angle.min(std::f32::consts::PI).sin_cos();
+                if cos <= 0.0 {
+                    // a hemisphere or more, only the whole range bounds it
+                    (location, self.range)
+                } else if cos < std::f32::consts::FRAC_1_SQRT_2 {
+                    // wide, the cap circle at the far end bounds it
+                    (location + direction * (self.range * cos), self.range * sin)
+                } else {
+                    // narrow, the sphere through the apex and the rim of the cap
+                    let radius = self.range / (2.0 * cos);
+                    (location + direction * radius, radius)
+                }
+            }
+        }
+    }
+
+    // Windowed inverse square falloff, matches `attenuation` in the standard shader.
+    pub fn attenuation(&self, distance: f32) -> f32 {
+        let ratio = (distance / self.range).powi(4);
+        let window = (1.0 - ratio).clamp(0.0, 1.0).powi(2);
+        window / (distance * distance + 1.0)
+    }
+}
+
+impl SerializeComp for Light {
+    const TYPE_NAME: &'static str = "Light";
+    const VERSION: u32 = 1;
+
+    fn serialize(&self) -> Fields {
+        let mut fields = Fields::from([
+            ("color".to_string(), Value::from(self.color)),
+            ("intensity".to_string(), Value::Float(self.intensity)),
+            ("range".to_string(), Value::Float(self.range)),
+            ("cast_shadows".to_string(), Value::Bool(self.cast_shadows)),
+            (
+                "contact_shadows".to_string(),
+                Value::Bool(self.contact_shadows),
+            ),
+        ]);
+        match self.kind {
+            LightKind::Point => {
+                fields.insert("kind".to_string(), Value::String("point".to_string()));
+            }
+            LightKind::Spot {
+                inner_angle,
+                outer_angle,
+            } => {
+                fields.insert("kind".to_string(), Value::String("spot".to_string()));
+                fields.insert("inner_angle".to_string(), Value::Float(inner_angle));
+                fields.insert("outer_angle".to_string(), Value::Float(outer_angle));
+            }
+        }
+        fields
+    }
+
+    fn deserialize(fields: &Fields) -> Self {
+        let get =
+            |name: &str, default: f32| fields.get(name).and_then(Value::as_f32).unwrap_or(default);
+        let color = fields
+            .get("color")
+            .and_then(Value::as_vec3)
+            .unwrap_or(Vec3::one());
+
+        let mut light = Self::point(color, get("intensity", 1.0), get("range", 10.0));
+        if fields.get("kind") == Some(&Value::String("spot".to_string())) {
+            let outer_angle = get("outer_angle", std::f32::consts::FRAC_PI_4);
+            light.kind = LightKind::Spot {
+                inner_angle: get("inner_angle", outer_angle * 0.8),
+                outer_angle,
+            };
+        }
+        light.cast_shadows = fields.get("cast_shadows") == Some(&Value::Bool(true));
+        light.contact_shadows = fields.get("contact_shadows") == Some(&Value::Bool(true));
+        light
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Light;
+    use crate::math::Vec3;
+
+    // the apex and points around the rim of the cap at the end of the range, down -Z
+    fn cone_points(outer_angle: f32, range: f32) -> Vec<Vec3> {
+        let (sin, cos) = outer_angle.sin_cos();
+        let mut points = vec![Vec3::zero(), Vec3::new(0.0, 0.0, -range)];
+        for step in 0..16 {
+            let around = step as f32 / 16.0 * std::f32::consts::TAU;
+            let rim = Vec3::new(around.cos() * sin, around.sin() * sin, -cos);
+            points.push(rim * range);
+        }
+        points
+    }
+
+    #[test]
+    fn bounding_spheres_contain_the_spot_cones() {
+        let direction = Vec3::new(0.0, 0.0, -1.0);
+        for degrees in [10.0_f32, 45.0, 60.0, 89.0, 90.0, 120.0, 180.0] {
+            let outer_angle = degrees.to_radians();
+            let light = Light::spot(Vec3::one(), 1.0, 5.0, outer_angle * 0.8, outer_angle);
+            let (center, radius) = light.bounding_sphere(Vec3::zero(), direction);
+            for point in cone_points(outer_angle, 5.0) {
+                let distance = (point - center).len();
+                assert!(distance <= radius + 1e-4, "{} degrees", degrees);
+            }
+        }
+    }
+
+    #[test]
+    fn spot_lights_past_a_hemisphere_are_bounded_by_their_range() {
+        let light = Light::spot(Vec3::one(), 1.0, 5.0, 1.5, 2.0);
+        let location = Vec3::new(1.0, 2.0, 3.0);
+        let (center, radius) = light.bounding_sphere(location, Vec3::new(0.0, 0.0, -1.0));
+        assert_eq!((center, radius), (location, 5.0));
+    }
+}
@@ -0,0 +1,39 @@
+use crate::assets::{AssetHandle, DynamicGeom};
+use crate::math::Vec3;
+use crate::scene::ecs::Comp;
+
+// A dimension line between two picked points, drawn by the measurement renderer.
+#[derive(Debug, Clone)]
+pub struct Measurement {
+    pub start: Vec3,
+    pub end: Vec3,
+    // line geometry and the points it was built for
+    pub lines: Option<(AssetHandle<DynamicGeom>, [f32; 6])>,
+}
+
+impl Comp for Measurement {}
+
+impl Measurement {
+    pub fn new(start: Vec3, end: Vec3) -> Self {
+        Self {
+            start,
+            end,
+            lines: None,
+        }
+    }
+
+    pub fn distance(&self) -> f32 {
+        (self.end - self.start).len()
+    }
+
+    pub fn key(&self) -> [f32; 6] {
+        [
+            self.start.x,
+            self.start.y,
+            self.start.z,
+            self.end.x,
+            self.end.y,
+            self.end.z,
+        ]
+    }
+}
@@ -1,10 +1,37 @@
 pub mod camera;
+mod debug_normals;
+mod decal;
+mod dissolve;
+mod dynamic_mesh;
+mod lifetime;
 pub mod light;
+mod measurement;
+mod motion;
+mod pooled;
+mod post_overrides;
 pub mod relation;
+mod selected;
+mod skeleton;
+mod static_mesh;
 pub mod tag;
+mod text;
+mod trail;
 pub mod transform;
-mod static_mesh;
 
-pub use transform::Transform;
+pub use debug_normals::DebugNormals;
+pub use decal::{Decal, DecalBlend};
+pub use dissolve::Dissolve;
+pub use dynamic_mesh::DynamicMesh;
+pub use lifetime::{expire_lifetimes, Lifetime};
+pub use light::{Light, LightKind};
+pub use measurement::Measurement;
+pub use motion::Motion;
+pub use pooled::Pooled;
+pub use post_overrides::{PostOverride, PostOverrides};
 pub use relation::Relation;
-pub use static_mesh::StaticMesh;
\ No newline at end of file
+pub use selected::Selected;
+pub use skeleton::{DebugSkeleton, Joint, Skeleton};
+pub use static_mesh::StaticMesh;
+pub use text::{Text, TextSpace};
+pub use trail::{record_trails, Trail, TrailPoint};
+pub use transform::Transform;
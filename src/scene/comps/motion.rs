@@ -0,0 +1,46 @@
+use crate::math::Mat4;
+use crate::scene::ecs::Comp;
+
+// Keeps what an entity looked like in the last rendered frame, so its draw can get motion vectors
+// that follow the object and its bones instead of only the camera. Entities without it are drawn
+// as if they stood still. See `RenderObject::set_motion`.
+#[derive(Debug, Clone, Default)]
+pub struct Motion {
+    model: Option<Mat4>,
+    palette: Vec<Mat4>,
+    previous_model: Mat4,
+    previous_palette: Vec<Mat4>,
+}
+
+impl Comp for Motion {}
+
+impl Motion {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Once per rendered frame with its model matrix and skinning palette, the ones of the frame
+    // before become the previous ones. The first frame is its own previous one.
+    pub fn advance(&mut self, model: Mat4, palette: Vec<Mat4>) {
+        self.previous_model = self.model.unwrap_or(model);
+        self.previous_palette = match self.model {
+            // a skeleton added or changed shape since has no matching previous pose
+            Some(_) if self.palette.len() == palette.len() => std::mem::take(&mut self.palette),
+            _ => palette.clone(),
+        };
+        self.model = Some(model);
+        self.palette = palette;
+    }
+
+    pub fn palette(&self) -> &[Mat4] {
+        &self.palette
+    }
+
+    pub fn previous_model(&self) -> Mat4 {
+        self.previous_model
+    }
+
+    pub fn previous_palette(&self) -> &[Mat4] {
+        &self.previous_palette
+    }
+}
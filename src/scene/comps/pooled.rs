@@ -0,0 +1,22 @@
+use crate::scene::ecs::Comp;
+
+// Entity owned by an `EntityPool`, inactive ones wait in the world for reuse and are skipped
+// by rendering and picking. Gameplay systems should skip them too.
+#[derive(Debug, Copy, Clone)]
+pub struct Pooled {
+    pub active: bool,
+}
+
+impl Comp for Pooled {}
+
+impl Pooled {
+    pub fn new() -> Self {
+        Self { active: true }
+    }
+}
+
+impl Default for Pooled {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,38 @@
+use crate::scene::ecs::Comp;
+
+#[derive(Debug, Copy, Clone)]
+pub enum PostOverride {
+    Enabled(&'static str, bool),
+    Params(&'static str, [f32; 4]),
+}
+
+// Post processing settings of one camera on top of the post chain's, e.g. the editor viewport
+// without bloom and depth of field while the game camera keeps them. Stacked in order, a later
+// entry for the same effect wins. Effects are matched by name, unknown ones are ignored.
+#[derive(Debug, Clone, Default)]
+pub struct PostOverrides {
+    pub stack: Vec<PostOverride>,
+}
+
+impl Comp for PostOverrides {}
+
+impl PostOverrides {
+    pub fn new() -> Self {
+        Self { stack: vec![] }
+    }
+
+    pub fn enable(mut self, name: &'static str) -> Self {
+        self.stack.push(PostOverride::Enabled(name, true));
+        self
+    }
+
+    pub fn disable(mut self, name: &'static str) -> Self {
+        self.stack.push(PostOverride::Enabled(name, false));
+        self
+    }
+
+    pub fn params(mut self, name: &'static str, params: [f32; 4]) -> Self {
+        self.stack.push(PostOverride::Params(name, params));
+        self
+    }
+}
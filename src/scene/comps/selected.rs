@@ -0,0 +1,13 @@
+use crate::scene::ecs::Comp;
+
+// Marks an entity picked in the editor, its mesh gets the selection outline.
+#[derive(Debug, Clone, Default)]
+pub struct Selected {}
+
+impl Comp for Selected {}
+
+impl Selected {
+    pub fn new() -> Self {
+        Self {}
+    }
+}
@@ -0,0 +1,71 @@
+use crate::assets::{AssetHandle, Geom};
+use crate::math::Mat4;
+use crate::scene::ecs::Comp;
+
+#[derive(Debug, Clone)]
+pub struct Joint {
+    pub name: String,
+    // index into the skeleton joints, always smaller than the joint's own index
+    pub parent: Option<usize>,
+    // relative to the parent joint, or the mesh for roots
+    pub local: Mat4,
+}
+
+// Joint hierarchy of a skinned mesh, in the model space of its entity.
+#[derive(Debug, Clone, Default)]
+pub struct Skeleton {
+    pub joints: Vec<Joint>,
+    // model to joint space of the bind pose per joint, identity for joints past the end
+    pub inverse_binds: Vec<Mat4>,
+}
+
+impl Comp for Skeleton {}
+
+impl Skeleton {
+    pub fn new(joints: Vec<Joint>) -> Self {
+        Self {
+            joints,
+            inverse_binds: vec![],
+        }
+    }
+
+    // Joint to model space, parents come first so one pass resolves the whole chain.
+    pub fn model_matrices(&self) -> Vec<Mat4> {
+        let mut matrices: Vec<Mat4> = Vec::with_capacity(self.joints.len());
+        for joint in &self.joints {
+            let matrix = match joint.parent {
+                Some(parent) => matrices[parent] * joint.local,
+                None => joint.local,
+            };
+            matrices.push(matrix);
+        }
+        matrices
+    }
+
+    // The skinning matrices, bind pose to the current pose in model space.
+    pub fn palette(&self) -> Vec<Mat4> {
+        self.model_matrices()
+            .into_iter()
+            .enumerate()
+            .map(|(index, matrix)| match self.inverse_binds.get(index) {
+                Some(inverse_bind) => matrix * *inverse_bind,
+                None => matrix,
+            })
+            .collect()
+    }
+}
+
+// Marks a skeleton whose bones are drawn by the debug pass.
+#[derive(Debug, Clone, Default)]
+pub struct DebugSkeleton {
+    // bone geometry and the joint matrices it was built for
+    pub lines: Option<(AssetHandle<Geom>, Vec<Mat4>)>,
+}
+
+impl Comp for DebugSkeleton {}
+
+impl DebugSkeleton {
+    pub fn new() -> Self {
+        Self { lines: None }
+    }
+}
@@ -0,0 +1,58 @@
+use crate::assets::{AssetHandle, Font};
+use crate::math::Vec4;
+use crate::scene::ecs::Comp;
+
+// Where a `Text` is drawn, always over the scene, it isn't hidden behind geometry.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum TextSpace {
+    // on the HUD from the top left of the entity's `UiNode` rect, or of the window without one,
+    // `size` in logical pixels
+    #[default]
+    Screen,
+    // in the XY plane of the entity's transform and centered on it, `size` in world units
+    World,
+    // centered on the entity's location and facing the camera, `size` in world units
+    Billboard,
+}
+
+// A string drawn by the text renderer, lines are broken at '\n'.
+#[derive(Debug, Clone)]
+pub struct Text {
+    pub text: String,
+    // the builtin font when None, see `Font::builtin`
+    pub font: Option<AssetHandle<Font>>,
+    // height of a line
+    pub size: f32,
+    // linear, alpha is the opacity
+    pub color: Vec4,
+    pub space: TextSpace,
+}
+
+impl Comp for Text {}
+
+impl Text {
+    pub fn new(text: impl Into<String>, size: f32) -> Self {
+        Self {
+            text: text.into(),
+            font: None,
+            size,
+            color: Vec4::new(1.0, 1.0, 1.0, 1.0),
+            space: TextSpace::Screen,
+        }
+    }
+
+    pub fn with_font(mut self, font: AssetHandle<Font>) -> Self {
+        self.font = Some(font);
+        self
+    }
+
+    pub fn with_color(mut self, color: Vec4) -> Self {
+        self.color = color;
+        self
+    }
+
+    pub fn with_space(mut self, space: TextSpace) -> Self {
+        self.space = space;
+        self
+    }
+}
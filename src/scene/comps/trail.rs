@@ -0,0 +1,101 @@
+use crate::assets::{AssetHandle, DynamicGeom, Material};
+use crate::math::Vec3;
+use crate::scene::ecs::{Comp, Query, SystemState, World};
+use crate::scene::{Pooled, Transform};
+use std::collections::VecDeque;
+
+#[derive(Debug, Copy, Clone)]
+pub struct TrailPoint {
+    pub position: Vec3,
+    // seconds since it was recorded
+    pub age: f32,
+}
+
+// Leaves a ribbon behind the entity, e.g. for projectiles or to emphasize motion. Positions are
+// recorded by `record_trails` and drawn by the trail renderer facing the camera, narrowing and
+// fading out with their age. u runs from the entity (0) to the end of the ribbon (1) and
+// scrolls with `uv_scroll`, v goes across.
+#[derive(Debug, Clone)]
+pub struct Trail {
+    // seconds a recorded position stays on the ribbon
+    pub lifetime: f32,
+    // world units at the entity, down to 0 at the end
+    pub width: f32,
+    pub color: Vec3,
+    // how far the entity moves before another position is recorded
+    pub min_distance: f32,
+    // u per second the texture moves along the ribbon
+    pub uv_scroll: f32,
+    // None draws the renderer's untextured one, see `Shading::load_trail`
+    pub material: Option<AssetHandle<Material>>,
+
+    points: VecDeque<TrailPoint>,
+    uv_offset: f32,
+    pub(crate) geom: Option<AssetHandle<DynamicGeom>>,
+}
+
+impl Comp for Trail {}
+
+impl Trail {
+    pub fn new(lifetime: f32, width: f32) -> Self {
+        Self {
+            lifetime,
+            width,
+            color: Vec3::new(1.0, 1.0, 1.0),
+            min_distance: 0.1,
+            uv_scroll: 0.0,
+            material: None,
+            points: VecDeque::new(),
+            uv_offset: 0.0,
+            geom: None,
+        }
+    }
+
+    // Oldest first.
+    pub fn points(&self) -> &VecDeque<TrailPoint> {
+        &self.points
+    }
+
+    pub fn uv_offset(&self) -> f32 {
+        self.uv_offset
+    }
+
+    // Starts over, e.g. when a pooled projectile is fired again.
+    pub fn clear(&mut self) {
+        self.points.clear();
+    }
+
+    fn record(&mut self, position: Vec3, delta_time: f32) {
+        for point in &mut self.points {
+            point.age += delta_time;
+        }
+        while self
+            .points
+            .front()
+            .is_some_and(|point| point.age >= self.lifetime)
+        {
+            self.points.pop_front();
+        }
+
+        let moved = self
+            .points
+            .back()
+            .is_none_or(|point| (position - point.position).len() >= self.min_distance);
+        if moved {
+            self.points.push_back(TrailPoint { position, age: 0.0 });
+        }
+        self.uv_offset = (self.uv_offset + self.uv_scroll * delta_time).fract();
+    }
+}
+
+// Reads `Transform` and `Pooled`, writes `Trail`.
+pub fn record_trails(world: &mut World, state: &SystemState) {
+    let query = Query::<(&Transform, &mut Trail, Option<&Pooled>)>::new(world);
+    for (transform, trail, pooled) in query {
+        // released pooled entities are on hold until acquired again
+        if pooled.is_some_and(|pooled| !pooled.active) {
+            continue;
+        }
+        trail.record(transform.location, state.delta_time);
+    }
+}
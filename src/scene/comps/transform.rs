@@ -1,12 +1,12 @@
-use crate::math::{Euler, Mat4, Vec3};
+use crate::math::{Euler, Mat4, Quat, Vec3};
 use crate::scene::ecs::*;
-use egui::ahash::HashMapExt;
+use crate::scene::serialize::{Fields, SerializeComp, Value};
 use std::cell::RefCell;
 
 #[derive(Debug)]
 pub struct Transform {
     pub location: Vec3,
-    pub rotation: Euler,
+    pub rotation: Quat,
     pub scale: Vec3,
     matrix_key: RefCell<Option<[f32; 10]>>,
     matrix_cache: RefCell<Mat4>,
@@ -14,7 +14,7 @@ pub struct Transform {
 impl Comp for Transform {}
 
 impl Transform {
-    pub fn new(location: Vec3, rotation: Euler, scale: Vec3) -> Self {
+    pub fn new(location: Vec3, rotation: Quat, scale: Vec3) -> Self {
         Self {
             location,
             rotation,
@@ -24,11 +24,20 @@ impl Transform {
         }
     }
 
+    // Euler angles are easier to author, the rotation is kept as a quaternion for interpolation.
+    pub fn euler(&self) -> Euler {
+        self.rotation.to_euler()
+    }
+
+    pub fn set_euler(&mut self, euler: Euler) {
+        self.rotation = Quat::from_euler(euler);
+    }
+
     pub fn matrix(&self) -> Mat4 {
         if self.update_matrix_key() {
             *self.matrix_cache.borrow_mut() = Mat4::compose(self.location, self.rotation, self.scale);
         }
-        self.matrix_cache.borrow().clone()
+        *self.matrix_cache.borrow()
     }
 
     pub fn matrix_mut(&mut self, mat4: Mat4) {
@@ -49,7 +58,7 @@ impl Transform {
             self.rotation.x,
             self.rotation.y,
             self.rotation.z,
-            self.rotation.order as u8 as f32,
+            self.rotation.s,
             self.scale.x,
             self.scale.y,
             self.scale.z,
@@ -66,8 +75,35 @@ impl Transform {
     }
 }
 
+impl SerializeComp for Transform {
+    const TYPE_NAME: &'static str = "Transform";
+    const VERSION: u32 = 1;
+
+    fn serialize(&self) -> Fields {
+        // euler angles in the file, readable and without the unit length constraint
+        let euler = self.euler();
+        let rotation = Vec3::new(euler.x, euler.y, euler.z);
+        Fields::from([
+            ("location".to_string(), Value::from(self.location)),
+            ("rotation".to_string(), Value::from(rotation)),
+            ("scale".to_string(), Value::from(self.scale)),
+        ])
+    }
+
+    fn deserialize(fields: &Fields) -> Self {
+        let get = |name: &str| fields.get(name).and_then(Value::as_vec3);
+        let rotation = get("rotation").unwrap_or(Vec3::zero());
+
+        Self::new(
+            get("location").unwrap_or(Vec3::zero()),
+            Quat::from_euler(Euler::new(rotation.x, rotation.y, rotation.z)),
+            get("scale").unwrap_or(Vec3::one()),
+        )
+    }
+}
+
 impl Default for Transform {
     fn default() -> Self {
-        Self::new(Vec3::zero(), Euler::default(), Vec3::one())
+        Self::new(Vec3::zero(), Quat::identity(), Vec3::one())
     }
 }
@@ -0,0 +1,128 @@
+use crate::math::{Aabb, Mat4, Vec2, Vec3};
+
+// segments of each circle of a sphere
+const SPHERE_SEGMENTS: usize = 24;
+
+// Where a debug string starts.
+#[derive(Debug, Copy, Clone)]
+pub enum DebugTextAnchor {
+    // physical pixels from the top left of the window
+    Screen(Vec2),
+    // where the point lands on screen, hidden while it is behind the camera
+    World(Vec3),
+}
+
+// Immediate mode lines in world space, e.g. bounding boxes, rays or light positions, and HUD
+// strings. Kept as a world resource, systems write it with
+// `SystemAccess::write_resource::<DebugDraw>()` and what is drawn during an update shows up in
+// that frame's render, then it is cleared.
+#[derive(Debug, Clone, Default)]
+pub struct DebugDraw {
+    // from, to, color
+    lines: Vec<(Vec3, Vec3, Vec3)>,
+    // anchor, text, color
+    texts: Vec<(DebugTextAnchor, String, Vec3)>,
+}
+
+impl DebugDraw {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn lines(&self) -> &[(Vec3, Vec3, Vec3)] {
+        &self.lines
+    }
+
+    pub fn texts(&self) -> &[(DebugTextAnchor, String, Vec3)] {
+        &self.texts
+    }
+
+    // At the start of every update, by the engine.
+    pub fn clear(&mut self) {
+        self.lines.clear();
+        self.texts.clear();
+    }
+
+    // A string on the HUD in the builtin font, e.g. a frame counter.
+    pub fn draw_text(&mut self, position: Vec2, text: impl Into<String>, color: Vec3) {
+        self.texts
+            .push((DebugTextAnchor::Screen(position), text.into(), color));
+    }
+
+    // A label next to a point of the scene, the same size at any distance.
+    pub fn draw_text_3d(&mut self, position: Vec3, text: impl Into<String>, color: Vec3) {
+        self.texts
+            .push((DebugTextAnchor::World(position), text.into(), color));
+    }
+
+    pub fn draw_line(&mut self, from: Vec3, to: Vec3, color: Vec3) {
+        self.lines.push((from, to, color));
+    }
+
+    // The 12 edges of the box.
+    pub fn draw_aabb(&mut self, aabb: &Aabb, color: Vec3) {
+        if aabb.is_empty() {
+            return;
+        }
+        let corner = |index: usize| {
+            Vec3::new(
+                if index & 1 == 0 {
+                    aabb.min.x
+                } else {
+                    aabb.max.x
+                },
+                if index & 2 == 0 {
+                    aabb.min.y
+                } else {
+                    aabb.max.y
+                },
+                if index & 4 == 0 {
+                    aabb.min.z
+                } else {
+                    aabb.max.z
+                },
+            )
+        };
+        // corners one bit apart share an edge
+        for index in 0..8 {
+            for bit in [1, 2, 4] {
+                if index & bit == 0 {
+                    self.draw_line(corner(index), corner(index | bit), color);
+                }
+            }
+        }
+    }
+
+    // A circle around each axis.
+    pub fn draw_sphere(&mut self, center: Vec3, radius: f32, color: Vec3) {
+        let axes = [
+            (Vec3::new(1.0, 0.0, 0.0), Vec3::new(0.0, 1.0, 0.0)),
+            (Vec3::new(0.0, 1.0, 0.0), Vec3::new(0.0, 0.0, 1.0)),
+            (Vec3::new(0.0, 0.0, 1.0), Vec3::new(1.0, 0.0, 0.0)),
+        ];
+        for (u, v) in axes {
+            let point = |segment: usize| {
+                let angle = segment as f32 / SPHERE_SEGMENTS as f32 * std::f32::consts::TAU;
+                let (sin, cos) = angle.sin_cos();
+                center + u * (cos * radius) + v * (sin * radius)
+            };
+            for segment in 0..SPHERE_SEGMENTS {
+                self.draw_line(point(segment), point(segment + 1), color);
+            }
+        }
+    }
+
+    // The X, Y and Z axes of the matrix in red, green and blue, `size` long.
+    pub fn draw_axes(&mut self, matrix: &Mat4, size: f32) {
+        let origin = matrix.transform_point(Vec3::new(0.0, 0.0, 0.0));
+        // each unit axis doubles as its color
+        for axis in [
+            Vec3::new(1.0, 0.0, 0.0),
+            Vec3::new(0.0, 1.0, 0.0),
+            Vec3::new(0.0, 0.0, 1.0),
+        ] {
+            let to = matrix.transform_point(axis * size);
+            self.draw_line(origin, to, axis);
+        }
+    }
+}
@@ -1,13 +0,0 @@
-mod comp;
-mod entity;
-mod system;
-mod world;
-mod query;
-mod scheduler;
-
-pub use comp::Comp;
-pub use entity::Entity;
-pub use system::SystemState;
-pub use query::Query;
-pub use world::World;
-pub use scheduler::Scheduler;
\ No newline at end of file
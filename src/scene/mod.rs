@@ -1,5 +1,13 @@
-pub mod ecs;
+pub mod bvh;
 pub mod comps;
+pub mod debug_draw;
+pub mod pool;
+pub mod serialize;
 
+pub use mirage_core::ecs;
+
+pub use bvh::Bvh;
+pub use comps::*;
+pub use debug_draw::{DebugDraw, DebugTextAnchor};
 pub use ecs::*;
-pub use comps::*;
\ No newline at end of file
+pub use pool::EntityPool;
@@ -0,0 +1,95 @@
+use crate::scene::{Entity, Pooled, World};
+
+// Recycles entities that come and go all the time, projectiles or the CPU side of particles.
+// A released entity stays in the world, with its slot and comps, until `acquire` hands it out
+// again, so the caller resets its comps in place with `World::get_entity_comp_mut` instead of
+// allocating new ones.
+pub struct EntityPool {
+    entities: Vec<Entity>,
+    // where the search for an inactive entity continues
+    cursor: usize,
+}
+
+impl EntityPool {
+    pub fn new() -> Self {
+        Self {
+            entities: vec![],
+            cursor: 0,
+        }
+    }
+
+    // Spawns inactive entities up front, e.g. while loading.
+    pub fn prewarm(&mut self, world: &mut World, count: usize) {
+        for _ in 0..count {
+            let entity = self.spawn(world);
+            self.release(world, entity);
+        }
+    }
+
+    // A released entity with the comps it had, or a new one with only `Pooled` when all are in use.
+    pub fn acquire(&mut self, world: &mut World) -> Entity {
+        for _ in 0..self.entities.len() {
+            let entity = self.entities[self.cursor];
+            self.cursor = (self.cursor + 1) % self.entities.len();
+            if let Some(pooled) = world.get_entity_comp_mut::<Pooled>(entity) {
+                if !pooled.active {
+                    pooled.active = true;
+                    return entity;
+                }
+            }
+        }
+
+        self.spawn(world)
+    }
+
+    pub fn release(&self, world: &mut World, entity: Entity) {
+        if let Some(pooled) = world.get_entity_comp_mut::<Pooled>(entity) {
+            pooled.active = false;
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.entities.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entities.is_empty()
+    }
+
+    pub fn active_count(&self, world: &World) -> usize {
+        self.entities
+            .iter()
+            .filter(|entity| {
+                world
+                    .get_entity_comp::<Pooled>(**entity)
+                    .is_some_and(|pooled| pooled.active)
+            })
+            .count()
+    }
+
+    // Despawns every entity of the pool, in use or not.
+    pub fn clear(&mut self, world: &mut World) {
+        self.entities
+            .drain(..)
+            .for_each(|entity| world.remove_entity(entity));
+        self.cursor = 0;
+    }
+
+    fn spawn(&mut self, world: &mut World) -> Entity {
+        // entities despawned behind the pool's back are forgotten
+        self.entities
+            .retain(|entity| world.contains_entity(*entity));
+        self.cursor = 0;
+
+        let entity = world.add_entity();
+        world.add_entity_comp(entity, Pooled::new());
+        self.entities.push(entity);
+        entity
+    }
+}
+
+impl Default for EntityPool {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,4 @@
+mod scene_file;
+
+pub use mirage_core::serialize::*;
+pub use scene_file::{load_scene, save_scene};
@@ -0,0 +1,551 @@
+use super::{Fields, Json, Migrations, SerializeComp, SerializedComp, Value};
+use crate::assets::{AssetHandle, Assets, Environment, Geom, Material, Texture};
+use crate::gpu::ColorSpace;
+use crate::renderer::{ShaderHooks, Shading};
+use crate::scene::camera::Camera;
+use crate::scene::{Light, Relation, StaticMesh, Transform, World};
+use egui::ahash::{HashMap, HashMapExt, HashSet};
+use std::cell::RefCell;
+
+// bumped when the layout of the file around the comps changes, comps carry versions of their own
+const FORMAT_VERSION: i64 = 1;
+
+// A scene as JSON, loaded by `Mirage::load_scene` from paths ending in `.scene`:
+//
+// {
+//   "format": 1,
+//   "environment": "simple.environment",
+//   "materials": [{"shading": "Simple", "shader": "simple.spv", "textures": {"texture": "texture.jpg"}}],
+//   (the standard shading keeps its GLSL snippets, "hooks": {"albedo_modify": "return albedo;"})
+//   "entities": [
+//     {"comps": [
+//       {"type": "Transform", "version": 1, "fields": {"location": [0.0, 1.0, 0.0]}},
+//       {"type": "StaticMesh", "version": 1, "fields": {"geom": "viking_room.obj", "material": 0}},
+//       {"type": "Relation", "version": 1, "fields": {"target": 0}}
+//     ]}
+//   ]
+// }
+//
+// Assets are referred to by the path they were loaded from, meshes with a geometry made in code
+// keep only their material. A relation's target is the index of the entity in "entities".
+pub fn save_scene(world: &World, assets: &Assets, environment: Option<&str>) -> String {
+    let entities = world
+        .ordered_entities()
+        .into_iter()
+        .filter(|&entity| {
+            world.has_entity_comp::<Transform>(entity)
+                || world.has_entity_comp::<StaticMesh>(entity)
+                || world.has_entity_comp::<Camera>(entity)
+                || world.has_entity_comp::<Light>(entity)
+        })
+        .collect::<Vec<_>>();
+    let file_indices = entities
+        .iter()
+        .enumerate()
+        .map(|(index, entity)| (entity.id, index as i64))
+        .collect::<HashMap<_, _>>();
+
+    // shared materials are written once
+    let mut materials = vec![];
+    let mut material_indices = HashMap::new();
+
+    let mut entities_json = vec![];
+    for &entity in &entities {
+        let mut comps = vec![];
+        if let Some(transform) = world.get_entity_comp::<Transform>(entity) {
+            comps.push(transform.to_serialized());
+        }
+        if let Some(camera) = world.get_entity_comp::<Camera>(entity) {
+            comps.push(camera.to_serialized());
+        }
+        if let Some(light) = world.get_entity_comp::<Light>(entity) {
+            comps.push(light.to_serialized());
+        }
+        if let Some(static_mesh) = world.get_entity_comp::<StaticMesh>(entity) {
+            let mut fields = Fields::new();
+            let geom_path = static_mesh.geom.as_ref().and_then(|geom| assets.path(geom));
+            if let Some(path) = geom_path {
+                fields.insert("geom".to_string(), Value::String(path.to_string()));
+            }
+            if let Some(material) = &static_mesh.material {
+                let index = *material_indices.entry(material.id).or_insert_with(|| {
+                    materials.push(material_json(assets, material));
+                    materials.len() as i64 - 1
+                });
+                fields.insert("material".to_string(), Value::Int(index));
+            }
+            comps.push(SerializedComp {
+                type_name: "StaticMesh".to_string(),
+                version: 1,
+                fields,
+            });
+        }
+        let relation = world.get_entity_comp::<Relation>(entity);
+        if let Some(&target) = relation
+            .and_then(|relation| relation.target)
+            .and_then(|target| file_indices.get(&target.id))
+        {
+            comps.push(SerializedComp {
+                type_name: "Relation".to_string(),
+                version: 1,
+                fields: Fields::from([("target".to_string(), Value::Int(target))]),
+            });
+        }
+
+        let comps = comps.iter().map(comp_json).collect();
+        entities_json.push(Json::Object(vec![(
+            "comps".to_string(),
+            Json::Array(comps),
+        )]));
+    }
+
+    let mut file = vec![("format".to_string(), Json::Int(FORMAT_VERSION))];
+    if let Some(environment) = environment {
+        file.push((
+            "environment".to_string(),
+            Json::String(environment.to_string()),
+        ));
+    }
+    file.push(("materials".to_string(), Json::Array(materials)));
+    file.push(("entities".to_string(), Json::Array(entities_json)));
+    Json::Object(file).to_string_pretty()
+}
+
+// Adds the entities of the file to the world, returns the environment the scene refers to.
+// Comps of unknown types and assets that fail to load are skipped with a warning.
+pub fn load_scene(
+    world: &mut World,
+    assets: &mut Assets,
+    migrations: &Migrations,
+    text: &str,
+) -> Result<Option<AssetHandle<Environment>>, String> {
+    let file = Json::parse(text)?;
+    let format = file.get("format").and_then(Json::as_i64).unwrap_or(0);
+    if format > FORMAT_VERSION {
+        return Err(format!(
+            "format {} is newer than the supported {}",
+            format, FORMAT_VERSION
+        ));
+    }
+
+    // assets shared by several entities are loaded once
+    let mut textures = HashMap::new();
+    let mut load_texture = |assets: &mut Assets, path: &str| {
+        textures
+            .entry(path.to_string())
+            .or_insert_with(|| {
+                let texture = assets.handle_path::<Texture>(path);
+                if texture.is_none() {
+                    log::warn!("failed to load scene texture {}!", path);
+                }
+                texture
+            })
+            .clone()
+    };
+    let materials = file
+        .get("materials")
+        .and_then(Json::as_array)
+        .unwrap_or_default()
+        .iter()
+        .map(|material| load_material(assets, material, &mut load_texture))
+        .collect::<Vec<_>>();
+    let mut geoms = HashMap::new();
+
+    let entities_json = file
+        .get("entities")
+        .and_then(Json::as_array)
+        .unwrap_or_default();
+    let entities = entities_json
+        .iter()
+        .map(|_| world.add_entity())
+        .collect::<Vec<_>>();
+
+    for (&entity, entity_json) in entities.iter().zip(entities_json) {
+        let comps = entity_json
+            .get("comps")
+            .and_then(Json::as_array)
+            .unwrap_or_default();
+        for comp in comps.iter().filter_map(parse_comp) {
+            match comp.type_name.as_str() {
+                Transform::TYPE_NAME => {
+                    if let Some(transform) = migrations.deserialize::<Transform>(comp) {
+                        world.add_entity_comp(entity, transform);
+                    }
+                }
+                Camera::TYPE_NAME => {
+                    if let Some(camera) = migrations.deserialize::<Camera>(comp) {
+                        world.add_entity_comp(entity, camera);
+                    }
+                }
+                Light::TYPE_NAME => {
+                    if let Some(light) = migrations.deserialize::<Light>(comp) {
+                        world.add_entity_comp(entity, light);
+                    }
+                }
+                "StaticMesh" => {
+                    let geom = match comp.fields.get("geom") {
+                        Some(Value::String(path)) => geoms
+                            .entry(path.clone())
+                            .or_insert_with(|| {
+                                let geom = assets.handle_path::<Geom>(path);
+                                if geom.is_none() {
+                                    log::warn!("failed to load scene geometry {}!", path);
+                                }
+                                geom
+                            })
+                            .clone(),
+                        _ => None,
+                    };
+                    let material = match comp.fields.get("material") {
+                        Some(&Value::Int(index)) => materials.get(index as usize).cloned(),
+                        _ => None,
+                    };
+                    world.add_entity_comp(entity, StaticMesh::new(geom, material));
+                }
+                "Relation" => {
+                    let target = match comp.fields.get("target") {
+                        Some(&Value::Int(index)) => entities.get(index as usize),
+                        _ => None,
+                    };
+                    match target {
+                        Some(&target) => {
+                            world.add_entity_comp(entity, Relation::new(entity, target))
+                        }
+                        None => log::warn!("relation without a valid target, skipped!"),
+                    }
+                }
+                type_name => log::warn!("unknown scene comp {}, skipped!", type_name),
+            }
+        }
+    }
+
+    let environment = file.get("environment").and_then(Json::as_str);
+    Ok(environment.and_then(|path| {
+        let environment = assets.handle_path::<Environment>(path);
+        if environment.is_none() {
+            log::warn!("failed to load scene environment {}!", path);
+        }
+        environment
+    }))
+}
+
+fn comp_json(comp: &SerializedComp) -> Json {
+    let fields = comp
+        .fields
+        .iter()
+        .map(|(name, value)| (name.clone(), Json::from(value)))
+        .collect();
+    Json::Object(vec![
+        ("type".to_string(), Json::String(comp.type_name.clone())),
+        ("version".to_string(), Json::Int(comp.version as i64)),
+        ("fields".to_string(), Json::Object(fields)),
+    ])
+}
+
+fn parse_comp(json: &Json) -> Option<SerializedComp> {
+    let Some(type_name) = json.get("type").and_then(Json::as_str) else {
+        log::warn!("scene comp without a type, skipped!");
+        return None;
+    };
+    let fields = json
+        .get("fields")
+        .and_then(Json::as_object)
+        .unwrap_or_default()
+        .iter()
+        .filter_map(|(name, value)| Some((name.clone(), value.to_value()?)))
+        .collect();
+    Some(SerializedComp {
+        type_name: type_name.to_string(),
+        // files written before comps were versioned hold version 0
+        version: json.get("version").and_then(Json::as_i64).unwrap_or(0) as u32,
+        fields,
+    })
+}
+
+// The shading by name and shader path, plus the hooks of the standard shading that are set.
+fn material_json(assets: &Assets, material: &AssetHandle<Material>) -> Json {
+    let Some(material) = assets.load(material) else {
+        return Json::Object(vec![]);
+    };
+    let mut textures = material
+        .textures()
+        .filter_map(|(slot, texture)| {
+            let path = assets.path(texture)?;
+            Some((slot.to_string(), Json::String(path.to_string())))
+        })
+        .collect::<Vec<_>>();
+    textures.sort_by(|a, b| a.0.cmp(&b.0));
+    let mut json = vec![
+        (
+            "shading".to_string(),
+            Json::String(material.shading.name.to_string()),
+        ),
+        (
+            "shader".to_string(),
+            Json::String(material.shading.path.to_string()),
+        ),
+        (
+            "transparent".to_string(),
+            Json::Bool(material.shading.transparent),
+        ),
+        ("textures".to_string(), Json::Object(textures)),
+    ];
+
+    let hooks = &material.shading.hooks;
+    let hooks = [
+        ("vertex_offset", &hooks.vertex_offset),
+        ("albedo_modify", &hooks.albedo_modify),
+        ("emissive_add", &hooks.emissive_add),
+    ]
+    .into_iter()
+    .filter_map(|(name, snippet)| Some((name.to_string(), Json::String(snippet.clone()?))))
+    .collect::<Vec<_>>();
+    if !hooks.is_empty() {
+        json.push(("hooks".to_string(), Json::Object(hooks)));
+    }
+    Json::Object(json)
+}
+
+// Snippets of a material's "hooks", the default body stays for the ones left out.
+fn load_hooks(json: &Json) -> ShaderHooks {
+    let hook = |name: &str| {
+        json.get("hooks")
+            .and_then(|hooks| hooks.get(name))
+            .and_then(Json::as_str)
+            .map(str::to_string)
+    };
+    ShaderHooks {
+        vertex_offset: hook("vertex_offset"),
+        albedo_modify: hook("albedo_modify"),
+        emissive_add: hook("emissive_add"),
+    }
+}
+
+fn load_material(
+    assets: &mut Assets,
+    json: &Json,
+    load_texture: &mut impl FnMut(&mut Assets, &str) -> Option<AssetHandle<Texture>>,
+) -> AssetHandle<Material> {
+    let shader = json
+        .get("shader")
+        .and_then(Json::as_str)
+        .unwrap_or("simple.spv");
+    let mut shading = match json.get("shading").and_then(Json::as_str) {
+        Some("Terrain") => Shading::load_terrain(),
+        Some("Standard") => Shading::load_standard(&load_hooks(json)),
+        Some("DebugLine") => Shading::load_debug_line(),
+        Some("Trail") => Shading::load_trail(),
+        Some("SimpleIndirect") => Shading::load_indirect(),
+        _ => Shading::load(intern(shader)),
+    };
+    if let Some(Json::Bool(transparent)) = json.get("transparent") {
+        shading.transparent = *transparent;
+    }
+
+    let mut material = Material::new(shading);
+    let textures = json
+        .get("textures")
+        .and_then(Json::as_object)
+        .unwrap_or_default();
+    for (slot, path) in textures {
+        let Some(path) = path.as_str() else {
+            continue;
+        };
+        // the shading's own slot name when it has one
+        let slot = material
+            .shading
+            .texture_slots
+            .iter()
+            .copied()
+            .find(|known| known == slot)
+            .unwrap_or_else(|| intern(slot));
+        let texture = load_texture(assets, path);
+        // normal maps and the like, the file is data rather than color
+        let linear = texture
+            .as_ref()
+            .filter(|_| Material::LINEAR_SLOTS.contains(&slot));
+        if let Some(loaded) = linear.and_then(|texture| assets.load_mut(texture)) {
+            loaded.set_color_space(ColorSpace::Linear);
+        }
+        material.set_texture(slot, texture);
+    }
+    assets.handle(material)
+}
+
+// Shader paths and texture slots are static strings across the renderer, each distinct one read
+// from a scene file is leaked once.
+fn intern(text: &str) -> &'static str {
+    thread_local! {
+        static INTERNED: RefCell<HashSet<&'static str>> = RefCell::new(HashSet::default());
+    }
+    INTERNED.with_borrow_mut(|interned| match interned.get(text) {
+        Some(text) => *text,
+        None => {
+            let text: &'static str = Box::leak(text.to_string().into_boxed_str());
+            interned.insert(text);
+            text
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{load_scene, save_scene};
+    use crate::assets::{Assets, Material};
+    use crate::math::{Euler, Quat, Vec3};
+    use crate::renderer::{ShaderHooks, Shading};
+    use crate::scene::serialize::{Json, Migrations, SerializeComp};
+    use crate::scene::{Light, LightKind, Relation, StaticMesh, Transform, World};
+
+    fn load(text: &str) -> (World, Result<(), String>) {
+        let mut world = World::new();
+        let mut assets = Assets::new();
+        let loaded = load_scene(&mut world, &mut assets, &Migrations::new(), text);
+        (
+            world,
+            loaded.map(|environment| assert!(environment.is_none())),
+        )
+    }
+
+    #[test]
+    fn entities_round_trip() {
+        let mut world = World::new();
+        let parent = world.add_entity();
+        let rotation = Quat::from_euler(Euler::new(0.0, 0.5, 0.0));
+        world.add_entity_comp(
+            parent,
+            Transform::new(Vec3::new(1.0, 2.0, 3.0), rotation, Vec3::one() * 2.0),
+        );
+        world.add_entity_comp(parent, StaticMesh::new(None, None));
+        let child = world.add_entity();
+        world.add_entity_comp(child, Transform::default());
+        let mut light = Light::spot(Vec3::new(1.0, 0.5, 0.25), 4.0, 12.0, 0.3, 0.6);
+        light.cast_shadows = true;
+        world.add_entity_comp(child, light);
+        world.add_entity_comp(child, Relation::new(child, parent));
+        // nothing of it is written
+        world.add_entity();
+
+        let text = save_scene(&world, &Assets::new(), None);
+        assert!(Json::parse(&text).is_ok());
+        let (loaded, result) = load(&text);
+        assert_eq!(result, Ok(()));
+
+        let entities = loaded.ordered_entities();
+        assert_eq!(entities.len(), 2);
+        let transform = loaded.get_entity_comp::<Transform>(entities[0]).unwrap();
+        assert_eq!(transform.location, Vec3::new(1.0, 2.0, 3.0));
+        assert_eq!(transform.scale, Vec3::one() * 2.0);
+        assert!((transform.rotation.dot(rotation).abs() - 1.0).abs() < 1e-5);
+        let static_mesh = loaded.get_entity_comp::<StaticMesh>(entities[0]).unwrap();
+        assert!(static_mesh.geom.is_none() && static_mesh.material.is_none());
+
+        let light = loaded.get_entity_comp::<Light>(entities[1]).unwrap();
+        assert_eq!(
+            light.kind,
+            LightKind::Spot {
+                inner_angle: 0.3,
+                outer_angle: 0.6
+            }
+        );
+        assert_eq!(light.color, Vec3::new(1.0, 0.5, 0.25));
+        assert_eq!((light.intensity, light.range), (4.0, 12.0));
+        assert!(light.cast_shadows && !light.contact_shadows);
+        let relation = loaded.get_entity_comp::<Relation>(entities[1]).unwrap();
+        assert_eq!(relation.owner, entities[1]);
+        assert_eq!(relation.target, Some(entities[0]));
+
+        // what was loaded saves to the same text
+        assert_eq!(save_scene(&loaded, &Assets::new(), None), text);
+    }
+
+    #[test]
+    fn shader_hooks_round_trip() {
+        let mut world = World::new();
+        let mut assets = Assets::new();
+        let hooks = ShaderHooks {
+            albedo_modify: Some("return albedo * 0.5;".to_string()),
+            ..ShaderHooks::default()
+        };
+        let material = assets.handle(Material::new(Shading::load_standard(&hooks)));
+        let entity = world.add_entity();
+        world.add_entity_comp(entity, StaticMesh::new(None, Some(material)));
+
+        let text = save_scene(&world, &assets, None);
+        let mut loaded = World::new();
+        let mut loaded_assets = Assets::new();
+        let result = load_scene(&mut loaded, &mut loaded_assets, &Migrations::new(), &text);
+        assert!(result.is_ok());
+
+        let entities = loaded.ordered_entities();
+        let static_mesh = loaded.get_entity_comp::<StaticMesh>(entities[0]).unwrap();
+        let material = loaded_assets
+            .load(static_mesh.material.as_ref().unwrap())
+            .unwrap();
+        assert_eq!(material.shading.name, "Standard");
+        assert_eq!(material.shading.hooks, hooks);
+    }
+
+    #[test]
+    fn unknown_comps_and_missing_fields_are_skipped() {
+        let text = r#"{
+            "format": 1,
+            "entities": [
+                {"comps": [
+                    {"type": "Transform", "fields": {"location": [1, 2, 3]}},
+                    {"type": "Wobble", "version": 1, "fields": {}},
+                    {"fields": {}},
+                    {"type": "Relation", "version": 1, "fields": {"target": 5}}
+                ]},
+                {}
+            ]
+        }"#;
+        let (world, result) = load(text);
+        assert_eq!(result, Ok(()));
+        let entities = world.ordered_entities();
+        assert_eq!(entities.len(), 2);
+        // version 0 goes through the migrations, missing fields take their default
+        let transform = world.get_entity_comp::<Transform>(entities[0]).unwrap();
+        assert_eq!(transform.location, Vec3::new(1.0, 2.0, 3.0));
+        assert_eq!(transform.scale, Vec3::one());
+        assert!(!world.has_entity_comp::<Relation>(entities[0]));
+    }
+
+    #[test]
+    fn newer_or_broken_files_fail() {
+        let (world, result) = load(r#"{"format": 2, "entities": [{}]}"#);
+        assert_eq!(
+            result,
+            Err("format 2 is newer than the supported 1".to_string())
+        );
+        assert_eq!(world.entity_count(), 0);
+
+        let (_, result) = load(r#"{"format": 1, "entities": [}"#);
+        assert_eq!(result, Err("unexpected character on line 1".to_string()));
+    }
+
+    #[test]
+    fn comps_written_by_a_newer_build_are_skipped() {
+        let comp = Light::point(Vec3::one(), 1.0, 1.0).to_serialized();
+        let fields = comp
+            .fields
+            .iter()
+            .map(|(name, value)| (name.clone(), Json::from(value)))
+            .collect();
+        let text = Json::Object(vec![(
+            "entities".to_string(),
+            Json::Array(vec![Json::Object(vec![(
+                "comps".to_string(),
+                Json::Array(vec![Json::Object(vec![
+                    ("type".to_string(), Json::String("Light".to_string())),
+                    ("version".to_string(), Json::Int(Light::VERSION as i64 + 1)),
+                    ("fields".to_string(), Json::Object(fields)),
+                ])]),
+            )])]),
+        )])
+        .to_string_pretty();
+        let (world, result) = load(&text);
+        assert_eq!(result, Ok(()));
+        assert_eq!(world.comp_count::<Light>(), 0);
+    }
+}
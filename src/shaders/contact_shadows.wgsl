@@ -0,0 +1,166 @@
+// Contact shadows, short screen space ray marches from every opaque pixel toward the lights that
+// ask for them. They fill in where the shadow maps lose small details to their bias, e.g. where
+// objects touch the ground. The result multiplies the scene color: a light's share of the lighting
+// of the pixel is taken away as far as the march finds it occluded.
+
+const MAX_LIGHTS: u32 = 16u;
+const LIGHT_KIND_SPOT: f32 = 1.0;
+
+struct SceneUBO {
+    view: mat4x4<f32>,
+    projection: mat4x4<f32>,
+    view_projection: mat4x4<f32>,
+    inverse_view_projection: mat4x4<f32>,
+}
+
+struct Light {
+    position_range: vec4<f32>,
+    color_intensity: vec4<f32>,
+    direction_kind: vec4<f32>,
+    // z 1 with contact shadows
+    cone: vec4<f32>,
+    // the tile in the shadow atlas and the shadow matrix, unused here
+    shadow: vec4<f32>,
+    shadow_matrix: mat4x4<f32>,
+}
+
+struct LightsUBO {
+    count: vec4<u32>,
+    lights: array<Light, MAX_LIGHTS>,
+    ambient: vec4<f32>,
+    fog: vec4<f32>,
+    // w 1 with contact shadows
+    sun_direction: vec4<f32>,
+    sun_color_intensity: vec4<f32>,
+}
+
+struct ContactParams {
+    // world units the rays travel
+    length: f32,
+    // how far behind a surface the ray still counts as occluded
+    thickness: f32,
+    steps: u32,
+    // what the depth buffer is cleared to, 0 with reversed depth
+    far_depth: f32,
+}
+
+var<push_constant> params: ContactParams;
+
+@group(0) @binding(0)
+var<uniform> scene: SceneUBO;
+@group(0) @binding(1)
+var<uniform> scene_lights: LightsUBO;
+
+@group(1) @binding(0)
+var depth_texture: texture_depth_multisampled_2d;
+
+struct VertexOutput {
+    @builtin(position) position: vec4<f32>,
+}
+
+@vertex
+fn vs(@builtin(vertex_index) index: u32) -> VertexOutput {
+    var output = VertexOutput();
+    let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+    output.position = vec4<f32>(uv * 2.0 - 1.0, 0.0, 1.0);
+    return output;
+}
+
+fn world_position(pixel: vec2<i32>, size: vec2<f32>) -> vec3<f32> {
+    // the first sample stands for the pixel, like the decals
+    let depth = textureLoad(depth_texture, pixel, 0);
+    let ndc = (vec2<f32>(pixel) + 0.5) / size * 2.0 - 1.0;
+    let clip = scene.inverse_view_projection * vec4<f32>(ndc, depth, 1.0);
+    return clip.xyz / clip.w;
+}
+
+fn view_depth(world: vec3<f32>) -> f32 {
+    return -(scene.view * vec4<f32>(world, 1.0)).z;
+}
+
+// Windowed inverse square falloff, same as Light::attenuation
+fn attenuation(distance: f32, range: f32) -> f32 {
+    let ratio = pow(distance / range, 4.0);
+    let window = clamp(1.0 - ratio, 0.0, 1.0);
+    return window * window / (distance * distance + 1.0);
+}
+
+// 1 when something in the depth buffer sits between the position and `to_light` within reach
+fn march(position: vec3<f32>, to_light: vec3<f32>, size: vec2<f32>, jitter: f32) -> f32 {
+    let step = to_light * (params.length / f32(params.steps));
+    for (var i = 0u; i < params.steps; i++) {
+        let sample = position + step * (f32(i) + jitter);
+        let clip = scene.view_projection * vec4<f32>(sample, 1.0);
+        if clip.w <= 0.0 {
+            return 0.0;
+        }
+        let ndc = clip.xy / clip.w;
+        if any(abs(ndc) > vec2<f32>(1.0)) {
+            return 0.0;
+        }
+        let pixel = vec2<i32>((ndc * 0.5 + 0.5) * size);
+        let scene_depth = view_depth(world_position(pixel, size));
+        let ray_depth = view_depth(sample);
+        let behind = ray_depth - scene_depth;
+        // a small bias so the surface the ray starts from doesn't shadow itself
+        if behind > 0.001 * ray_depth && behind < params.thickness {
+            // fades out toward the end of the ray so the cut off isn't visible
+            return 1.0 - f32(i) / f32(params.steps);
+        }
+    }
+    return 0.0;
+}
+
+@fragment
+fn fs(@builtin(position) position: vec4<f32>) -> @location(0) vec4<f32> {
+    let size = vec2<f32>(textureDimensions(depth_texture));
+    let pixel = vec2<i32>(position.xy);
+    let world = world_position(pixel, size);
+    // derivatives are taken before the sky returns, the normal is turned toward the camera
+    let view_rotation = mat3x3<f32>(scene.view[0].xyz, scene.view[1].xyz, scene.view[2].xyz);
+    let camera = -(transpose(view_rotation) * scene.view[3].xyz);
+    var normal = normalize(cross(dpdy(world), dpdx(world)));
+    normal *= sign(dot(normal, camera - world) + 1e-6);
+    if textureLoad(depth_texture, pixel, 0) == params.far_depth {
+        return vec4<f32>(1.0);
+    }
+    // starts a little off the surface, the pixel hash spreads the steps between neighbours
+    let origin = world + normal * 0.01;
+    let jitter = fract(sin(dot(position.xy, vec2<f32>(12.9898, 78.233))) * 43758.5453);
+
+    var total = scene_lights.ambient.rgb * scene_lights.ambient.a;
+    var occluded = vec3<f32>(0.0);
+
+    let sun_intensity = scene_lights.sun_color_intensity.a;
+    if sun_intensity > 0.0 {
+        let to_sun = -normalize(scene_lights.sun_direction.xyz);
+        let light = scene_lights.sun_color_intensity.rgb * sun_intensity
+            * max(dot(normal, to_sun), 0.0);
+        total += light;
+        if scene_lights.sun_direction.w > 0.0 {
+            occluded += light * march(origin, to_sun, size, jitter);
+        }
+    }
+
+    let count = min(scene_lights.count.x, MAX_LIGHTS);
+    for (var i = 0u; i < count; i++) {
+        let light = scene_lights.lights[i];
+        let to_light = light.position_range.xyz - world;
+        let distance = length(to_light);
+        let l = to_light / max(distance, 1e-4);
+
+        var intensity = light.color_intensity.a * attenuation(distance, light.position_range.w);
+        if light.direction_kind.w == LIGHT_KIND_SPOT {
+            intensity *= smoothstep(light.cone.y, light.cone.x, dot(-l, light.direction_kind.xyz));
+        }
+        let contribution = light.color_intensity.rgb * intensity * max(dot(normal, l), 0.0);
+        total += contribution;
+        if light.cone.z > 0.0 && intensity > 0.0 {
+            occluded += contribution * march(origin, l, size, jitter);
+        }
+    }
+
+    // the environment lighting isn't known here, the shares are of the direct and ambient light
+    let remaining = 1.0 - occluded / max(total, vec3<f32>(1e-4));
+    return vec4<f32>(clamp(remaining, vec3<f32>(0.0), vec3<f32>(1.0)), 1.0);
+}
@@ -0,0 +1,86 @@
+// Projects a texture onto the opaque surfaces inside a box. The back faces of the unit box are
+// rasterized, every covered pixel reconstructs its world position from the depth buffer and keeps
+// the decal where that position falls inside the box. Premultiplied output, the pipeline picks
+// how it blends over the scene color.
+
+struct SceneUBO {
+    view: mat4x4<f32>,
+    projection: mat4x4<f32>,
+    view_projection: mat4x4<f32>,
+    inverse_view_projection: mat4x4<f32>,
+}
+
+struct DecalParams {
+    model: mat4x4<f32>,
+    // rows of the inverse model, world space into the unit box
+    world_to_decal_x: vec4<f32>,
+    world_to_decal_y: vec4<f32>,
+    world_to_decal_z: vec4<f32>,
+    // rgb tint, a opacity
+    color: vec4<f32>,
+}
+
+var<push_constant> decal: DecalParams;
+
+@group(0) @binding(0)
+var<uniform> scene: SceneUBO;
+
+@group(1) @binding(0)
+var depth_texture: texture_depth_multisampled_2d;
+@group(1) @binding(1)
+var decal_texture: texture_2d<f32>;
+@group(1) @binding(2)
+var decal_sampler: sampler;
+
+// corners of the unit box, bit 0 is x, bit 1 y and bit 2 z
+fn corner(index: u32) -> vec3<f32> {
+    return vec3<f32>(f32(index & 1u), f32((index >> 1u) & 1u), f32((index >> 2u) & 1u)) - 0.5;
+}
+
+@vertex
+fn vs(@builtin(vertex_index) index: u32) -> @builtin(position) vec4<f32> {
+    // counter clockwise seen from outside, -x, +x, -y, +y, -z, +z
+    var indices = array<u32, 36>(
+        0u, 4u, 6u, 0u, 6u, 2u,
+        1u, 3u, 7u, 1u, 7u, 5u,
+        0u, 1u, 5u, 0u, 5u, 4u,
+        2u, 6u, 7u, 2u, 7u, 3u,
+        0u, 2u, 3u, 0u, 3u, 1u,
+        4u, 5u, 7u, 4u, 7u, 6u,
+    );
+    let world = decal.model * vec4<f32>(corner(indices[index]), 1.0);
+    return scene.view_projection * world;
+}
+
+@fragment
+fn fs(@builtin(position) position: vec4<f32>) -> @location(0) vec4<f32> {
+    // the first sample stands for the pixel, edges of the surfaces stay as sharp as the resolve
+    let pixel = vec2<i32>(position.xy);
+    let depth = textureLoad(depth_texture, pixel, 0);
+    let size = vec2<f32>(textureDimensions(depth_texture));
+    let ndc = position.xy / size * 2.0 - 1.0;
+    let clip = scene.inverse_view_projection * vec4<f32>(ndc, depth, 1.0);
+    let world = vec4<f32>(clip.xyz / clip.w, 1.0);
+
+    let local = vec3<f32>(
+        dot(decal.world_to_decal_x, world),
+        dot(decal.world_to_decal_y, world),
+        dot(decal.world_to_decal_z, world),
+    );
+    // projected down -z, u along x and v down y
+    let uv = vec2<f32>(local.x + 0.5, 0.5 - local.y);
+
+    // fades out on surfaces running along the projection instead of facing it
+    let normal = normalize(cross(dpdy(world.xyz), dpdx(world.xyz)));
+    let axis = normalize(decal.world_to_decal_z.xyz);
+    let facing = smoothstep(0.2, 0.5, abs(dot(normal, axis)));
+
+    // derivatives are taken before anything is discarded
+    let color = textureSampleGrad(decal_texture, decal_sampler, uv, dpdx(uv), dpdy(uv));
+    if any(abs(local) > vec3<f32>(0.5)) {
+        discard;
+    }
+
+    let alpha = color.a * decal.color.a * facing;
+    return vec4<f32>(color.rgb * decal.color.rgb * alpha, alpha);
+}
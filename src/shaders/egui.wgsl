@@ -0,0 +1,62 @@
+// egui meshes over the final image. Positions are in points, colors are premultiplied sRGB.
+
+struct EguiParams {
+    screen_size: vec2<f32>,
+    // 1 when the target is an sRGB format and does the encoding itself
+    srgb_target: u32,
+}
+
+var<push_constant> params: EguiParams;
+
+struct VertexInput {
+    @location(0) position: vec2<f32>,
+    @location(1) uv: vec2<f32>,
+    @location(2) color: vec4<f32>,
+}
+
+struct VertexOutput {
+    @builtin(position) position: vec4<f32>,
+    @location(0) uv: vec2<f32>,
+    @location(1) color: vec4<f32>,
+}
+
+@group(0) @binding(0)
+var egui_texture: texture_2d<f32>;
+@group(0) @binding(1)
+var egui_sampler: sampler;
+
+fn linear_from_srgb(srgb: vec3<f32>) -> vec3<f32> {
+    let cutoff = srgb < vec3<f32>(0.04045);
+    let lower = srgb / vec3<f32>(12.92);
+    let higher = pow((srgb + vec3<f32>(0.055)) / vec3<f32>(1.055), vec3<f32>(2.4));
+    return select(higher, lower, cutoff);
+}
+
+fn srgb_from_linear(linear: vec3<f32>) -> vec3<f32> {
+    let cutoff = linear < vec3<f32>(0.0031308);
+    let lower = linear * vec3<f32>(12.92);
+    let higher = vec3<f32>(1.055) * pow(linear, vec3<f32>(1.0 / 2.4)) - vec3<f32>(0.055);
+    return select(higher, lower, cutoff);
+}
+
+@vertex
+fn vs(in: VertexInput) -> VertexOutput {
+    var output = VertexOutput();
+
+    // top left origin, Vulkan clip space has y pointing down as well
+    output.position = vec4<f32>(in.position / params.screen_size * 2.0 - 1.0, 0.0, 1.0);
+    output.uv = in.uv;
+    output.color = vec4<f32>(linear_from_srgb(in.color.rgb), in.color.a);
+
+    return output;
+}
+
+@fragment
+fn fs(in: VertexOutput) -> @location(0) vec4<f32> {
+    // textures are uploaded as sRGB, sampling already returns linear values
+    let color = in.color * textureSample(egui_texture, egui_sampler, in.uv);
+    if params.srgb_target == 1u {
+        return color;
+    }
+    return vec4<f32>(srgb_from_linear(color.rgb), color.a);
+}
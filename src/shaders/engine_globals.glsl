@@ -0,0 +1,23 @@
+// Engine globals on set 0 of every material, keep in sync with GlobalsData and GLOBALS_BINDING in
+// camera_uniforms.rs. `#include "engine_globals.glsl"` in GLSL compiled at runtime, see
+// ShaderHooks::compile.
+
+layout(set = 0, binding = 11) uniform EngineGlobals {
+    // x seconds since start, y seconds since the last frame, z frames rendered
+    vec4 time;
+    // xy scene color size in pixels, zw one over it
+    vec4 screen;
+    // xy subpixel offset of the projection in NDC, zw the previous frame's
+    vec4 jitter;
+    // xyz world position, w near plane
+    vec4 cameraPosition;
+    // x vertical field of view in radians, y aspect, z height of an orthographic camera, 0 for
+    // perspective ones
+    vec4 cameraParams;
+    // of the last frame without its jitter, for motion vectors
+    mat4 previousViewProjection;
+} globals;
+
+// tileable, 64 texels square
+layout(set = 0, binding = 12) uniform texture2D blueNoiseTexture;
+layout(set = 0, binding = 13) uniform sampler blueNoiseSampler;
@@ -0,0 +1,19 @@
+// One triangle covering the screen, no vertex buffer needed.
+// Shared by every post pass, uv (0, 0) is the top left corner.
+
+struct VertexOutput {
+    @builtin(position) position: vec4<f32>,
+
+    @location(0) uv: vec2<f32>,
+}
+
+@vertex
+fn vs(@builtin(vertex_index) index: u32) -> VertexOutput {
+    var output = VertexOutput();
+
+    let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+    output.position = vec4<f32>(uv * 2.0 - 1.0, 0.0, 1.0);
+    output.uv = uv;
+
+    return output;
+}
@@ -0,0 +1,149 @@
+// Prefiltering of the environment cube map for image based lighting, one cube face or the BRDF
+// LUT per fullscreen pass:
+//   fs_irradiance: cosine weighted hemisphere around every direction, the diffuse ambient
+//   fs_specular:   GGX importance sampled at `roughness`, one mip level of the specular chain
+//   fs_brdf:       split sum scale (r) and bias (g) of F0, by n.v (u) and roughness (v)
+
+struct IblParams {
+    face: u32,
+    roughness: f32,
+    samples: u32,
+    // of the environment, picks the mip a sample reads
+    environment_size: f32,
+}
+
+var<push_constant> ibl: IblParams;
+
+@group(0) @binding(0)
+var environment: texture_cube<f32>;
+@group(0) @binding(1)
+var environment_sampler: sampler;
+
+struct FragmentInput {
+    @location(0) uv: vec2<f32>,
+}
+
+const PI: f32 = 3.14159265359;
+
+// Inverse of the Vulkan cube face selection, uv (0, 0) is the top left of the face.
+fn face_direction(face: u32, uv: vec2<f32>) -> vec3<f32> {
+    let st = uv * 2.0 - 1.0;
+    switch face {
+        case 0u: { return normalize(vec3<f32>(1.0, -st.y, -st.x)); }
+        case 1u: { return normalize(vec3<f32>(-1.0, -st.y, st.x)); }
+        case 2u: { return normalize(vec3<f32>(st.x, 1.0, st.y)); }
+        case 3u: { return normalize(vec3<f32>(st.x, -1.0, -st.y)); }
+        case 4u: { return normalize(vec3<f32>(st.x, -st.y, 1.0)); }
+        default: { return normalize(vec3<f32>(-st.x, -st.y, -1.0)); }
+    }
+}
+
+fn tangent_to_world(v: vec3<f32>, n: vec3<f32>) -> vec3<f32> {
+    let up = select(vec3<f32>(1.0, 0.0, 0.0), vec3<f32>(0.0, 0.0, 1.0), abs(n.z) < 0.999);
+    let tangent = normalize(cross(up, n));
+    let bitangent = cross(n, tangent);
+    return tangent * v.x + bitangent * v.y + n * v.z;
+}
+
+fn hammersley(index: u32, count: u32) -> vec2<f32> {
+    return vec2<f32>(f32(index) / f32(count), f32(reverseBits(index)) * 2.3283064365386963e-10);
+}
+
+// Half vector around n, distributed like the GGX lobe.
+fn importance_sample_ggx(xi: vec2<f32>, n: vec3<f32>, roughness: f32) -> vec3<f32> {
+    let a = roughness * roughness;
+    let phi = 2.0 * PI * xi.x;
+    let cos_theta = sqrt((1.0 - xi.y) / (1.0 + (a * a - 1.0) * xi.y));
+    let sin_theta = sqrt(1.0 - cos_theta * cos_theta);
+    return tangent_to_world(vec3<f32>(cos(phi) * sin_theta, sin(phi) * sin_theta, cos_theta), n);
+}
+
+fn distribution_ggx(n_dot_h: f32, roughness: f32) -> f32 {
+    let a2 = roughness * roughness * roughness * roughness;
+    let d = n_dot_h * n_dot_h * (a2 - 1.0) + 1.0;
+    return a2 / (PI * d * d);
+}
+
+fn geometry_schlick_ggx(n_dot_x: f32, roughness: f32) -> f32 {
+    // k of image based lighting
+    let k = roughness * roughness / 2.0;
+    return n_dot_x / (n_dot_x * (1.0 - k) + k);
+}
+
+@fragment
+fn fs_irradiance(in: FragmentInput) -> @location(0) vec4<f32> {
+    let n = face_direction(ibl.face, in.uv);
+    // the integral is smooth, a small mip keeps the fixed sample grid from aliasing
+    let mip = max(log2(ibl.environment_size / 32.0), 0.0);
+
+    let phi_steps = 64u;
+    let theta_steps = 16u;
+    var irradiance = vec3<f32>(0.0);
+    for (var i = 0u; i < phi_steps; i++) {
+        for (var j = 0u; j < theta_steps; j++) {
+            let phi = (f32(i) + 0.5) / f32(phi_steps) * 2.0 * PI;
+            let theta = (f32(j) + 0.5) / f32(theta_steps) * 0.5 * PI;
+            let sample = vec3<f32>(sin(theta) * cos(phi), sin(theta) * sin(phi), cos(theta));
+            let direction = tangent_to_world(sample, n);
+            let radiance = textureSampleLevel(environment, environment_sampler, direction, mip).rgb;
+            irradiance += radiance * cos(theta) * sin(theta);
+        }
+    }
+    irradiance = PI * irradiance / f32(phi_steps * theta_steps);
+    return vec4<f32>(irradiance, 1.0);
+}
+
+@fragment
+fn fs_specular(in: FragmentInput) -> @location(0) vec4<f32> {
+    // view and reflection along the normal, the usual prefiltering assumption
+    let n = face_direction(ibl.face, in.uv);
+    let texel_solid_angle = 4.0 * PI / (6.0 * ibl.environment_size * ibl.environment_size);
+
+    var color = vec3<f32>(0.0);
+    var weight = 0.0;
+    for (var i = 0u; i < ibl.samples; i++) {
+        let h = importance_sample_ggx(hammersley(i, ibl.samples), n, ibl.roughness);
+        let l = normalize(2.0 * dot(n, h) * h - n);
+        let n_dot_l = dot(n, l);
+        if n_dot_l > 0.0 {
+            // a mip matching the solid angle the sample stands for, no bright dots of single texels
+            let n_dot_h = max(dot(n, h), 0.0);
+            let pdf = distribution_ggx(n_dot_h, ibl.roughness) / 4.0 + 0.0001;
+            let sample_solid_angle = 1.0 / (f32(ibl.samples) * pdf + 0.0001);
+            let mip = select(
+                max(0.5 * log2(sample_solid_angle / texel_solid_angle), 0.0),
+                0.0,
+                ibl.roughness == 0.0,
+            );
+            color += textureSampleLevel(environment, environment_sampler, l, mip).rgb * n_dot_l;
+            weight += n_dot_l;
+        }
+    }
+    return vec4<f32>(color / max(weight, 0.0001), 1.0);
+}
+
+@fragment
+fn fs_brdf(in: FragmentInput) -> @location(0) vec4<f32> {
+    let n_dot_v = max(in.uv.x, 0.0001);
+    let roughness = in.uv.y;
+    let v = vec3<f32>(sqrt(1.0 - n_dot_v * n_dot_v), 0.0, n_dot_v);
+    let n = vec3<f32>(0.0, 0.0, 1.0);
+
+    var scale = 0.0;
+    var bias = 0.0;
+    for (var i = 0u; i < ibl.samples; i++) {
+        let h = importance_sample_ggx(hammersley(i, ibl.samples), n, roughness);
+        let l = normalize(2.0 * dot(v, h) * h - v);
+        let n_dot_l = max(l.z, 0.0);
+        if n_dot_l > 0.0 {
+            let n_dot_h = max(h.z, 0.0);
+            let v_dot_h = max(dot(v, h), 0.0);
+            let g = geometry_schlick_ggx(n_dot_v, roughness) * geometry_schlick_ggx(n_dot_l, roughness);
+            let g_vis = g * v_dot_h / max(n_dot_h * n_dot_v, 0.0001);
+            let fresnel = pow(1.0 - v_dot_h, 5.0);
+            scale += (1.0 - fresnel) * g_vis;
+            bias += fresnel * g_vis;
+        }
+    }
+    return vec4<f32>(scale, bias, 0.0, 1.0) / vec4<f32>(f32(ibl.samples), f32(ibl.samples), 1.0, 1.0);
+}
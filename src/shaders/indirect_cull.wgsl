@@ -0,0 +1,59 @@
+// Frustum culling of the objects drawn indirect, see GPUCulling in gpu_culling.rs. Every visible
+// object is appended to the visible list of its batch and counted in the instances of the batch's
+// draw command, which the CPU wrote with none.
+
+// Keep in sync with CullObject in gpu_culling.rs
+struct CullObject {
+    // xyz world space center, w radius of the bounding sphere
+    sphere: vec4<f32>,
+    // of the object's data in the objects buffer the vertex shaders read
+    slot: u32,
+    // draw command of the batch
+    batch: u32,
+    // where the batch's visible list starts
+    first: u32,
+    _pad: u32,
+}
+
+// VkDrawIndexedIndirectCommand
+struct DrawCommand {
+    index_count: u32,
+    instance_count: atomic<u32>,
+    first_index: u32,
+    vertex_offset: i32,
+    first_instance: u32,
+}
+
+// Keep in sync with CullParams in gpu_culling.rs
+struct CullParams {
+    // xyz normal pointing inside, w distance, see Frustum::from_matrix
+    planes: array<vec4<f32>, 6>,
+    count: u32,
+}
+
+var<push_constant> params: CullParams;
+
+@group(0) @binding(0)
+var<storage, read> objects: array<CullObject>;
+@group(0) @binding(1)
+var<storage, read_write> commands: array<DrawCommand>;
+@group(0) @binding(2)
+var<storage, read_write> visible: array<u32>;
+
+@compute @workgroup_size(64, 1, 1)
+fn cs(@builtin(global_invocation_id) id: vec3<u32>) {
+    if (id.x >= params.count) {
+        return;
+    }
+
+    let object = objects[id.x];
+    for (var i = 0u; i < 6u; i++) {
+        let plane = params.planes[i];
+        if (dot(plane.xyz, object.sphere.xyz) + plane.w < -object.sphere.w) {
+            return;
+        }
+    }
+
+    let index = atomicAdd(&commands[object.batch].instance_count, 1u);
+    visible[object.first + index] = object.slot;
+}
@@ -0,0 +1,90 @@
+// Light clustering of the standard shading, see LightClusters in light_clusters.rs. The view
+// frustum is split into CLUSTERS_X by CLUSTERS_Y tiles of the framebuffer and CLUSTERS_Z depth
+// slices, every cluster lists the lights whose sphere touches its view space bounds.
+
+// Keep in sync with CLUSTERS and MAX_LIGHTS_PER_CLUSTER in light_clusters.rs
+const CLUSTERS_X: u32 = 16u;
+const CLUSTERS_Y: u32 = 9u;
+const CLUSTERS_Z: u32 = 24u;
+const MAX_LIGHTS_PER_CLUSTER: u32 = 127u;
+
+// Keep in sync with ClusterParams in light_clusters.rs
+struct ClusterParams {
+    // x tan of half the vertical field of view, 0 for orthographic cameras, y aspect, z half the
+    // height of an orthographic camera
+    projection: vec4<f32>,
+    // x near, y far depth of the slices, z 1 for linear slices, exponential ones otherwise
+    depth: vec4<f32>,
+    count: u32,
+}
+
+var<push_constant> params: ClusterParams;
+
+// xyz view space center, w radius
+@group(0) @binding(0)
+var<storage, read> spheres: array<vec4<f32>>;
+// per cluster its light count followed by MAX_LIGHTS_PER_CLUSTER light indices
+@group(0) @binding(1)
+var<storage, read_write> grid: array<u32>;
+
+// Depth where `slice` starts, the standard shader inverts it
+fn slice_depth(slice: u32) -> f32 {
+    let t = f32(slice) / f32(CLUSTERS_Z);
+    if (params.depth.z > 0.0) {
+        return mix(params.depth.x, params.depth.y, t);
+    }
+    return params.depth.x * pow(params.depth.y / params.depth.x, t);
+}
+
+// Half the width and height the view covers at `depth`
+fn half_size(depth: f32) -> vec2<f32> {
+    var half_height = params.projection.z;
+    if (params.projection.x > 0.0) {
+        half_height = depth * params.projection.x;
+    }
+    return vec2<f32>(half_height * params.projection.y, half_height);
+}
+
+@compute @workgroup_size(64, 1, 1)
+fn cs(@builtin(global_invocation_id) id: vec3<u32>) {
+    let cluster = id.x;
+    if (cluster >= CLUSTERS_X * CLUSTERS_Y * CLUSTERS_Z) {
+        return;
+    }
+    let x = cluster % CLUSTERS_X;
+    let y = (cluster / CLUSTERS_X) % CLUSTERS_Y;
+    let z = cluster / (CLUSTERS_X * CLUSTERS_Y);
+
+    // the projections flip y, the top row of tiles is up in view space
+    let tiles = vec2<f32>(f32(CLUSTERS_X), f32(CLUSTERS_Y));
+    let flip = vec2<f32>(1.0, -1.0);
+    let corner_a = (vec2<f32>(f32(x), f32(y)) / tiles * 2.0 - 1.0) * flip;
+    let corner_b = (vec2<f32>(f32(x + 1u), f32(y + 1u)) / tiles * 2.0 - 1.0) * flip;
+    let near = slice_depth(z);
+    let far = slice_depth(z + 1u);
+    let near_size = half_size(near);
+    let far_size = half_size(far);
+    let corners_min = min(
+        min(corner_a * near_size, corner_b * near_size),
+        min(corner_a * far_size, corner_b * far_size)
+    );
+    let corners_max = max(
+        max(corner_a * near_size, corner_b * near_size),
+        max(corner_a * far_size, corner_b * far_size)
+    );
+    // the camera looks down -z
+    let bounds_min = vec3<f32>(corners_min, -far);
+    let bounds_max = vec3<f32>(corners_max, -near);
+
+    let first = cluster * (MAX_LIGHTS_PER_CLUSTER + 1u);
+    var count = 0u;
+    for (var i = 0u; i < params.count && count < MAX_LIGHTS_PER_CLUSTER; i++) {
+        let sphere = spheres[i];
+        let offset = sphere.xyz - clamp(sphere.xyz, bounds_min, bounds_max);
+        if (dot(offset, offset) <= sphere.w * sphere.w) {
+            grid[first + 1u + count] = i;
+            count++;
+        }
+    }
+    grid[first] = count;
+}
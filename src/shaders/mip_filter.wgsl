@@ -0,0 +1,63 @@
+// One level of a mip chain from the level above, for data the linear blit chain gets wrong.
+// Keep in sync with MipFilter in rhi.rs and MipGenerator in mip_generator.rs.
+
+const MODE_NORMAL: u32 = 1u;
+const MODE_ROUGHNESS: u32 = 2u;
+
+struct MipParams {
+    mode: u32,
+    // roughness channel, 0 to 3 for r to a
+    channel: u32,
+    // the source is level 0, its normals have unit length whatever its alpha says
+    from_base: u32,
+    _pad: u32,
+}
+
+var<push_constant> params: MipParams;
+
+@group(0) @binding(0)
+var source: texture_2d<f32>;
+@group(0) @binding(1)
+var destination: texture_storage_2d<rgba8unorm, write>;
+
+// xyz the tangent space direction scaled by the length the averaged normals kept
+fn load_normal(texel: vec4<f32>) -> vec3<f32> {
+    let direction = normalize(texel.xyz * 2.0 - 1.0);
+    let length = select(texel.w, 1.0, params.from_base == 1u);
+    return direction * length;
+}
+
+@compute @workgroup_size(8, 8, 1)
+fn cs(@builtin(global_invocation_id) id: vec3<u32>) {
+    let size = textureDimensions(destination);
+    if id.x >= size.x || id.y >= size.y {
+        return;
+    }
+
+    // the 2x2 texels above, odd sizes clamp to the last row or column
+    let last = vec2<i32>(textureDimensions(source)) - 1;
+    let base = vec2<i32>(id.xy) * 2;
+    var texels: array<vec4<f32>, 4>;
+    texels[0] = textureLoad(source, min(base, last), 0);
+    texels[1] = textureLoad(source, min(base + vec2<i32>(1, 0), last), 0);
+    texels[2] = textureLoad(source, min(base + vec2<i32>(0, 1), last), 0);
+    texels[3] = textureLoad(source, min(base + vec2<i32>(1, 1), last), 0);
+
+    var result = (texels[0] + texels[1] + texels[2] + texels[3]) * 0.25;
+    if params.mode == MODE_NORMAL {
+        // the average of the level 0 normals, shorter the more they diverge (Toksvig)
+        let average = (load_normal(texels[0]) + load_normal(texels[1]) + load_normal(texels[2])
+            + load_normal(texels[3])) * 0.25;
+        let length = max(length(average), 1e-4);
+        result = vec4<f32>(average / length * 0.5 + 0.5, length);
+    } else if params.mode == MODE_ROUGHNESS {
+        // averaged as GGX alpha squared, the variance of the microfacets adds up (LEAN)
+        var alpha2 = 0.0;
+        for (var i = 0; i < 4; i++) {
+            let roughness = texels[i][params.channel];
+            alpha2 += roughness * roughness * roughness * roughness * 0.25;
+        }
+        result[params.channel] = sqrt(sqrt(alpha2));
+    }
+    textureStore(destination, vec2<i32>(id.xy), result);
+}
@@ -0,0 +1,123 @@
+// Tileable noise for the noise textures, one independent pattern per channel.
+// The lattice of every octave wraps at a multiple of the period, so the texture repeats seamlessly.
+
+struct NoiseParams {
+    // 0: perlin, 1: worley, 2: blue
+    kind: u32,
+    // lattice cells across the texture at the first octave
+    period: u32,
+    octaves: u32,
+    seed: u32,
+    size: vec2<f32>,
+}
+
+var<push_constant> noise: NoiseParams;
+
+struct FragmentInput {
+    @location(0) uv: vec2<f32>,
+}
+
+// https://www.pcg-random.org/, as in "Hash Functions for GPU Rendering" (Jarzynski, Olano)
+fn pcg(v: u32) -> u32 {
+    let state = v * 747796405u + 2891336453u;
+    let word = ((state >> ((state >> 28u) + 4u)) ^ state) * 277803737u;
+    return (word >> 22u) ^ word;
+}
+
+fn hash(cell: vec2<i32>, channel: u32) -> u32 {
+    let c = bitcast<vec2<u32>>(cell);
+    return pcg(pcg(pcg(c.x + noise.seed * 4u + channel) + c.y));
+}
+
+fn hash_unit(cell: vec2<i32>, channel: u32) -> f32 {
+    return f32(hash(cell, channel)) / 4294967295.0;
+}
+
+fn wrap(cell: vec2<i32>, period: i32) -> vec2<i32> {
+    return ((cell % period) + period) % period;
+}
+
+fn gradient(cell: vec2<i32>, channel: u32) -> vec2<f32> {
+    let angle = hash_unit(cell, channel) * 6.28318530718;
+    return vec2<f32>(cos(angle), sin(angle));
+}
+
+// Gradient noise in -1..1, quintic fade.
+fn perlin(p: vec2<f32>, period: i32, channel: u32) -> f32 {
+    let cell = vec2<i32>(floor(p));
+    let f = fract(p);
+    let u = f * f * f * (f * (f * 6.0 - 15.0) + 10.0);
+
+    let g00 = dot(gradient(wrap(cell, period), channel), f);
+    let g10 = dot(gradient(wrap(cell + vec2<i32>(1, 0), period), channel), f - vec2<f32>(1.0, 0.0));
+    let g01 = dot(gradient(wrap(cell + vec2<i32>(0, 1), period), channel), f - vec2<f32>(0.0, 1.0));
+    let g11 = dot(gradient(wrap(cell + vec2<i32>(1, 1), period), channel), f - vec2<f32>(1.0, 1.0));
+    return mix(mix(g00, g10, u.x), mix(g01, g11, u.x), u.y) * 1.41421356;
+}
+
+// Distance to the closest feature point, one per cell, in 0..1.
+fn worley(p: vec2<f32>, period: i32, channel: u32) -> f32 {
+    let cell = vec2<i32>(floor(p));
+    let f = fract(p);
+
+    var closest = 1.0;
+    for (var y = -1; y <= 1; y++) {
+        for (var x = -1; x <= 1; x++) {
+            let offset = vec2<i32>(x, y);
+            let wrapped = wrap(cell + offset, period);
+            let feature = vec2<f32>(hash_unit(wrapped, channel), hash_unit(wrapped, channel + 4u));
+            closest = min(closest, length(vec2<f32>(offset) + feature - f));
+        }
+    }
+    return closest;
+}
+
+// White noise minus its local average, which removes the low frequencies that make clumps.
+// Cheaper than a void and cluster pattern and close enough for jittering samples.
+fn blue(pixel: vec2<i32>, size: i32, channel: u32) -> f32 {
+    var sum = 0.0;
+    for (var y = -2; y <= 2; y++) {
+        for (var x = -2; x <= 2; x++) {
+            sum += hash_unit(wrap(pixel + vec2<i32>(x, y), size), channel);
+        }
+    }
+    let center = hash_unit(pixel, channel);
+    // the high pass spreads roughly over -0.5..0.5
+    return clamp((center - sum / 25.0) * 1.2 + 0.5, 0.0, 1.0);
+}
+
+fn octaves(uv: vec2<f32>, channel: u32) -> f32 {
+    var value = 0.0;
+    var amplitude = 0.5;
+    var total = 0.0;
+    var period = i32(noise.period);
+    for (var octave = 0u; octave < max(noise.octaves, 1u); octave++) {
+        let p = uv * f32(period);
+        if noise.kind == 0u {
+            value += (perlin(p, period, channel) * 0.5 + 0.5) * amplitude;
+        } else {
+            value += (1.0 - worley(p, period, channel)) * amplitude;
+        }
+        total += amplitude;
+        amplitude *= 0.5;
+        period *= 2;
+    }
+    return clamp(value / total, 0.0, 1.0);
+}
+
+@fragment
+fn fs(in: FragmentInput) -> @location(0) vec4<f32> {
+    var color = vec4<f32>(0.0);
+    if noise.kind == 2u {
+        let pixel = vec2<i32>(in.uv * noise.size);
+        let size = i32(noise.size.x);
+        for (var channel = 0u; channel < 4u; channel++) {
+            color[channel] = blue(pixel, size, channel);
+        }
+    } else {
+        for (var channel = 0u; channel < 4u; channel++) {
+            color[channel] = octaves(in.uv, channel);
+        }
+    }
+    return color;
+}
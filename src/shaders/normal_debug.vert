@@ -0,0 +1,21 @@
+#version 450
+
+layout(push_constant) uniform ObjectPushConstants {
+    mat4 model;
+} object;
+
+layout(location = 0) in vec3 inPosition;
+layout(location = 1) in vec3 inColor;
+layout(location = 2) in vec2 inUV;
+layout(location = 3) in vec3 inNormal;
+
+layout(location = 0) out vec3 outWorldNormal;
+layout(location = 1) out vec2 outUV;
+
+void main() {
+    // normalize() of a zero vector is NaN, such a normal stays a point instead of a broken line
+    vec3 worldNormal = mat3(object.model) * inNormal;
+    outWorldNormal = dot(worldNormal, worldNormal) > 1e-12 ? normalize(worldNormal) : vec3(0.0);
+    outUV = inUV;
+    gl_Position = object.model * vec4(inPosition, 1.0);
+}
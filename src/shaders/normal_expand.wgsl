@@ -0,0 +1,93 @@
+// The normal (green) and tangent (red) lines of a mesh as a line list, where geometry shaders
+// are unsupported, see NormalDebugger in normal_debugger.rs. One invocation per triangle writes
+// the same 12 vertices normal_debug.geom emits for it, in object space, and their indices.
+
+// Keep in sync with ExpandParams in normal_debugger.rs
+struct ExpandParams {
+    // of the geom in the buffers it shares with others, see GeomArena
+    first_index: u32,
+    vertex_offset: i32,
+    triangles: u32,
+    normal_length: f32,
+}
+
+var<push_constant> params: ExpandParams;
+
+// Vertex in vertex.rs without padding: position, color, uv, normal
+const VERTEX_FLOATS: u32 = 11u;
+const LINE_VERTICES: u32 = 12u;
+
+@group(0) @binding(0)
+var<storage, read> vertices: array<f32>;
+@group(0) @binding(1)
+var<storage, read> indices: array<u32>;
+@group(0) @binding(2)
+var<storage, read_write> lines: array<f32>;
+@group(0) @binding(3)
+var<storage, read_write> line_indices: array<u32>;
+
+fn read_vec3(base: u32) -> vec3<f32> {
+    return vec3<f32>(vertices[base], vertices[base + 1u], vertices[base + 2u]);
+}
+
+// normalize() of a zero vector is NaN, a degenerate direction becomes a point instead
+fn safe_normalize(direction: vec3<f32>) -> vec3<f32> {
+    let length_sq = dot(direction, direction);
+    if (length_sq > 1e-12) {
+        return direction * inverseSqrt(length_sq);
+    }
+    return vec3<f32>(0.0);
+}
+
+fn write_vertex(index: u32, position: vec3<f32>, color: vec3<f32>) {
+    let base = index * VERTEX_FLOATS;
+    lines[base] = position.x;
+    lines[base + 1u] = position.y;
+    lines[base + 2u] = position.z;
+    lines[base + 3u] = color.x;
+    lines[base + 4u] = color.y;
+    lines[base + 5u] = color.z;
+    for (var i = 6u; i < VERTEX_FLOATS; i++) {
+        lines[base + i] = 0.0;
+    }
+    line_indices[index] = index;
+}
+
+fn write_line(first: u32, start: vec3<f32>, direction: vec3<f32>, color: vec3<f32>) {
+    write_vertex(first, start, color);
+    write_vertex(first + 1u, start + direction * params.normal_length, color);
+}
+
+@compute @workgroup_size(64, 1, 1)
+fn cs(@builtin(global_invocation_id) id: vec3<u32>) {
+    if (id.x >= params.triangles) {
+        return;
+    }
+
+    var corners: array<u32, 3>;
+    for (var i = 0u; i < 3u; i++) {
+        let index = indices[params.first_index + id.x * 3u + i];
+        corners[i] = u32(i32(index) + params.vertex_offset) * VERTEX_FLOATS;
+    }
+
+    // Tangent of the triangle derived from its uv gradient.
+    let p0 = read_vec3(corners[0]);
+    let edge1 = read_vec3(corners[1]) - p0;
+    let edge2 = read_vec3(corners[2]) - p0;
+    let uv0 = vec2<f32>(vertices[corners[0] + 6u], vertices[corners[0] + 7u]);
+    let delta_uv1 = vec2<f32>(vertices[corners[1] + 6u], vertices[corners[1] + 7u]) - uv0;
+    let delta_uv2 = vec2<f32>(vertices[corners[2] + 6u], vertices[corners[2] + 7u]) - uv0;
+    let det = delta_uv1.x * delta_uv2.y - delta_uv2.x * delta_uv1.y;
+    var tangent = vec3<f32>(0.0);
+    if (abs(det) > 1e-8) {
+        tangent = safe_normalize((edge1 * delta_uv2.y - edge2 * delta_uv1.y) / det);
+    }
+
+    for (var i = 0u; i < 3u; i++) {
+        let position = read_vec3(corners[i]);
+        let normal = safe_normalize(read_vec3(corners[i] + 8u));
+        let first = id.x * LINE_VERTICES + i * 4u;
+        write_line(first, position, normal, vec3<f32>(0.0, 1.0, 0.0));
+        write_line(first + 2u, position, tangent, vec3<f32>(1.0, 0.0, 0.0));
+    }
+}
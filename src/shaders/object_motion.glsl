@@ -0,0 +1,39 @@
+// Last frame's model matrix and skinning palettes of the object, for motion vectors of moving and
+// skinned objects. Keep in sync with RenderObject::set_motion in render_object.rs, the shading
+// needs `object_payload`. `#include "object_motion.glsl"` after engine_globals.glsl.
+
+// without an instance name, naga can't load from a named block ending in a runtime array
+layout(std430, set = 3, binding = 0) readonly buffer ObjectMotion {
+    mat4 motionPreviousModel;
+    // x joints per palette, 0 for objects without a skeleton
+    uvec4 motionJointCount;
+    // the current palette, then the previous one
+    mat4 motionPalettes[];
+};
+
+// Blended skinning matrix of the vertex, of the last frame when `previous` is set.
+mat4 skin_matrix(uvec4 joints, vec4 weights, bool previous) {
+    uint count = motionJointCount.x;
+    if (count == 0u) {
+        return mat4(1.0);
+    }
+    uint base = previous ? count : 0u;
+    return weights.x * motionPalettes[base + min(joints.x, count - 1u)]
+        + weights.y * motionPalettes[base + min(joints.y, count - 1u)]
+        + weights.z * motionPalettes[base + min(joints.z, count - 1u)]
+        + weights.w * motionPalettes[base + min(joints.w, count - 1u)];
+}
+
+// Where the vertex was on screen in the last frame, in clip space and without the jitter.
+vec4 previous_clip_position(vec3 position, uvec4 joints, vec4 weights) {
+    vec4 skinned = skin_matrix(joints, weights, true) * vec4(position, 1.0);
+    return globals.previousViewProjection * motionPreviousModel * skinned;
+}
+
+// Screen space motion from the last frame to this one in UV units, the jitter of both frames
+// taken out so a still scene has none.
+vec2 motion_vector(vec4 clipPosition, vec4 previousClipPosition) {
+    vec2 current = clipPosition.xy / clipPosition.w - globals.jitter.xy;
+    vec2 previous = previousClipPosition.xy / previousClipPosition.w;
+    return (current - previous) * 0.5;
+}
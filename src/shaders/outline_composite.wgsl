@@ -0,0 +1,51 @@
+// Outlines the shapes of the selection mask over the final image.
+// A pixel is on the outline when another id is within `width` pixels of it, which draws outside
+// of each selected entity and also between two touching ones.
+
+struct OutlineParams {
+    color: vec4<f32>,
+    width: f32,
+}
+
+var<push_constant> outline: OutlineParams;
+
+struct FragmentInput {
+    @builtin(position) position: vec4<f32>,
+    @location(0) uv: vec2<f32>,
+}
+
+@group(0) @binding(0)
+var mask: texture_2d<u32>;
+
+const MAX_WIDTH: i32 = 8;
+
+@fragment
+fn fs(in: FragmentInput) -> @location(0) vec4<f32> {
+    let size = vec2<i32>(textureDimensions(mask));
+    let pixel = vec2<i32>(in.position.xy);
+    let center = textureLoad(mask, pixel, 0).r;
+
+    let width = min(outline.width, f32(MAX_WIDTH));
+    let radius = i32(ceil(width));
+    var coverage = 0.0;
+    for (var y = -MAX_WIDTH; y <= MAX_WIDTH; y++) {
+        for (var x = -MAX_WIDTH; x <= MAX_WIDTH; x++) {
+            if abs(x) > radius || abs(y) > radius {
+                continue;
+            }
+            let neighbour = clamp(pixel + vec2<i32>(x, y), vec2<i32>(0), size - 1);
+            let id = textureLoad(mask, neighbour, 0).r;
+            if id == 0u || id == center {
+                continue;
+            }
+            // soft last pixel so the outline doesn't look jagged
+            let distance = length(vec2<f32>(f32(x), f32(y)));
+            coverage = max(coverage, clamp(width + 1.0 - distance, 0.0, 1.0));
+        }
+    }
+
+    if coverage <= 0.0 {
+        discard;
+    }
+    return vec4<f32>(outline.color.rgb, outline.color.a * coverage);
+}
@@ -0,0 +1,22 @@
+// Writes the id of the selected entity into the mask, 0 is left for nothing selected.
+
+struct MaskPushConstants {
+    model_view_projection: mat4x4<f32>,
+    id: u32,
+}
+
+var<push_constant> object: MaskPushConstants;
+
+struct VertexInput {
+    @location(0) position: vec3<f32>,
+}
+
+@vertex
+fn vs(in: VertexInput) -> @builtin(position) vec4<f32> {
+    return object.model_view_projection * vec4<f32>(in.position, 1.0);
+}
+
+@fragment
+fn fs() -> @location(0) u32 {
+    return object.id;
+}
@@ -0,0 +1,13 @@
+struct FragmentInput {
+    @location(0) uv: vec2<f32>,
+}
+
+@group(0) @binding(0)
+var source: texture_2d<f32>;
+@group(0) @binding(1)
+var source_sampler: sampler;
+
+@fragment
+fn fs(in: FragmentInput) -> @location(0) vec4<f32> {
+    return textureSample(source, source_sampler, in.uv);
+}
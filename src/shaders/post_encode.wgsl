@@ -0,0 +1,24 @@
+// The last pass of the post chain when the swap chain is UNORM but sRGB output is wanted, the
+// surface had no sRGB format. Encodes what an sRGB attachment would on write.
+
+struct FragmentInput {
+    @location(0) uv: vec2<f32>,
+}
+
+@group(0) @binding(0)
+var source: texture_2d<f32>;
+@group(0) @binding(1)
+var source_sampler: sampler;
+
+fn srgb_from_linear(linear: vec3<f32>) -> vec3<f32> {
+    let cutoff = linear < vec3<f32>(0.0031308);
+    let lower = linear * vec3<f32>(12.92);
+    let higher = vec3<f32>(1.055) * pow(linear, vec3<f32>(1.0 / 2.4)) - vec3<f32>(0.055);
+    return select(higher, lower, cutoff);
+}
+
+@fragment
+fn fs(in: FragmentInput) -> @location(0) vec4<f32> {
+    let color = textureSample(source, source_sampler, in.uv);
+    return vec4<f32>(srgb_from_linear(max(color.rgb, vec3<f32>(0.0))), color.a);
+}
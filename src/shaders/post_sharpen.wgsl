@@ -0,0 +1,48 @@
+// Robust contrast adaptive sharpening, after AMD FidelityFX RCAS.
+// The lobe is limited so the cross neighbourhood can't push the center out of its min/max range,
+// which keeps edges from ringing.
+
+struct PostParams {
+    texel_size: vec2<f32>,
+    // x: sharpness 0..1
+    params: vec4<f32>,
+}
+
+var<push_constant> post: PostParams;
+
+struct FragmentInput {
+    @location(0) uv: vec2<f32>,
+}
+
+@group(0) @binding(0)
+var source: texture_2d<f32>;
+@group(0) @binding(1)
+var source_sampler: sampler;
+
+const RCAS_LIMIT: f32 = 0.1875;
+
+@fragment
+fn fs(in: FragmentInput) -> @location(0) vec4<f32> {
+    let texel = post.texel_size;
+    //    b
+    //  d e f
+    //    h
+    let b = textureSample(source, source_sampler, in.uv + vec2<f32>(0.0, -texel.y)).rgb;
+    let d = textureSample(source, source_sampler, in.uv + vec2<f32>(-texel.x, 0.0)).rgb;
+    let center = textureSample(source, source_sampler, in.uv);
+    let e = center.rgb;
+    let f = textureSample(source, source_sampler, in.uv + vec2<f32>(texel.x, 0.0)).rgb;
+    let h = textureSample(source, source_sampler, in.uv + vec2<f32>(0.0, texel.y)).rgb;
+
+    let min4 = min(min(b, d), min(f, h));
+    let max4 = max(max(b, d), max(f, h));
+
+    let hit_min = min(min4, e) / (4.0 * max4 + 1e-5);
+    let hit_max = (1.0 - max(max4, e)) / (4.0 * min4 - 4.0 - 1e-5);
+    let lobe_rgb = max(-hit_min, hit_max);
+    let lobe = max(-RCAS_LIMIT, min(max(lobe_rgb.r, max(lobe_rgb.g, lobe_rgb.b)), 0.0))
+        * post.params.x;
+
+    let color = (lobe * (b + d + f + h) + e) / (4.0 * lobe + 1.0);
+    return vec4<f32>(color, center.a);
+}
@@ -0,0 +1,52 @@
+// HDR scene color to the display range, the first pass of the post chain.
+
+struct PostParams {
+    texel_size: vec2<f32>,
+    // x: exposure in stops, y: operator, 0 clamp, 1 Reinhard, 2 ACES
+    params: vec4<f32>,
+}
+
+var<push_constant> post: PostParams;
+
+struct FragmentInput {
+    @location(0) uv: vec2<f32>,
+}
+
+@group(0) @binding(0)
+var source: texture_2d<f32>;
+@group(0) @binding(1)
+var source_sampler: sampler;
+
+fn reinhard(color: vec3<f32>) -> vec3<f32> {
+    return color / (1.0 + color);
+}
+
+// Narkowicz's fit of the ACES reference rendering transform
+fn aces(color: vec3<f32>) -> vec3<f32> {
+    let a = 2.51;
+    let b = 0.03;
+    let c = 2.43;
+    let d = 0.59;
+    let e = 0.14;
+    return clamp((color * (a * color + b)) / (color * (c * color + d) + e), vec3<f32>(0.0), vec3<f32>(1.0));
+}
+
+@fragment
+fn fs(in: FragmentInput) -> @location(0) vec4<f32> {
+    let hdr = textureSample(source, source_sampler, in.uv);
+    let color = max(hdr.rgb * exp2(post.params.x), vec3<f32>(0.0));
+
+    var mapped: vec3<f32>;
+    switch u32(post.params.y) {
+        case 1u: {
+            mapped = reinhard(color);
+        }
+        case 2u: {
+            mapped = aces(color);
+        }
+        default: {
+            mapped = clamp(color, vec3<f32>(0.0), vec3<f32>(1.0));
+        }
+    }
+    return vec4<f32>(mapped, hdr.a);
+}
@@ -0,0 +1,48 @@
+// Catmull-Rom bicubic upscale of the internal resolution scene color to the output resolution.
+// The 4x4 kernel is folded into 9 bilinear taps by merging the two middle weights of each axis.
+
+struct PostParams {
+    // of the source
+    texel_size: vec2<f32>,
+    params: vec4<f32>,
+}
+
+var<push_constant> post: PostParams;
+
+struct FragmentInput {
+    @location(0) uv: vec2<f32>,
+}
+
+@group(0) @binding(0)
+var source: texture_2d<f32>;
+@group(0) @binding(1)
+var source_sampler: sampler;
+
+fn tap(x: f32, y: f32) -> vec4<f32> {
+    return textureSampleLevel(source, source_sampler, vec2<f32>(x, y), 0.0);
+}
+
+@fragment
+fn fs(in: FragmentInput) -> @location(0) vec4<f32> {
+    let sample_position = in.uv / post.texel_size;
+    let center = floor(sample_position - 0.5) + 0.5;
+    let f = sample_position - center;
+
+    let w0 = f * (-0.5 + f * (1.0 - 0.5 * f));
+    let w1 = 1.0 + f * f * (-2.5 + 1.5 * f);
+    let w2 = f * (0.5 + f * (2.0 - 1.5 * f));
+    let w3 = f * f * (-0.5 + 0.5 * f);
+
+    let w12 = w1 + w2;
+    let p0 = (center - 1.0) * post.texel_size;
+    let p12 = (center + w2 / w12) * post.texel_size;
+    let p3 = (center + 2.0) * post.texel_size;
+
+    var color = vec4<f32>(0.0);
+    color += (tap(p0.x, p0.y) * w0.x + tap(p12.x, p0.y) * w12.x + tap(p3.x, p0.y) * w3.x) * w0.y;
+    color += (tap(p0.x, p12.y) * w0.x + tap(p12.x, p12.y) * w12.x + tap(p3.x, p12.y) * w3.x) * w12.y;
+    color += (tap(p0.x, p3.y) * w0.x + tap(p12.x, p3.y) * w12.x + tap(p3.x, p3.y) * w3.x) * w3.y;
+
+    // the negative lobes can overshoot
+    return max(color, vec4<f32>(0.0));
+}
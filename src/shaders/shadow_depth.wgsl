@@ -0,0 +1,17 @@
+// Depth of the shadow casters seen from a spot light or a point light's cube face, into its
+// tile of the shadow atlas. See ShadowRenderer, there is no fragment stage.
+
+struct ShadowPushConstants {
+    model_view_projection: mat4x4<f32>,
+}
+
+var<push_constant> object: ShadowPushConstants;
+
+struct VertexInput {
+    @location(0) position: vec3<f32>,
+}
+
+@vertex
+fn vs(in: VertexInput) -> @builtin(position) vec4<f32> {
+    return object.model_view_projection * vec4<f32>(in.position, 1.0);
+}
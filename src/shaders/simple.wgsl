@@ -10,10 +10,37 @@ struct ObjectPushConstants {
 
 var<push_constant> object: ObjectPushConstants;
 
+// Keep in sync with MAX_LIGHTS in camera_uniforms.rs
+const MAX_LIGHTS: u32 = 16u;
+const LIGHT_KIND_SPOT: f32 = 1.0;
+
+struct Light {
+    position_range: vec4<f32>,
+    color_intensity: vec4<f32>,
+    direction_kind: vec4<f32>,
+    cone: vec4<f32>,
+    // the tile in the shadow atlas and the shadow matrix, unused here
+    shadow: vec4<f32>,
+    shadow_matrix: mat4x4<f32>,
+}
+
+struct LightsUBO {
+    count: vec4<u32>,
+    lights: array<Light, MAX_LIGHTS>,
+    // rgb color, a intensity
+    ambient: vec4<f32>,
+    // rgb color, a exponential density, 0 without fog
+    fog: vec4<f32>,
+    // xyz the way the sunlight travels
+    sunDirection: vec4<f32>,
+    // rgb color, a intensity, 0 without sun
+    sunColorIntensity: vec4<f32>,
+}
+
 @group(0) @binding(0)
 var<uniform> scene: SceneUBO;
-//@group(0) @binding(1)
-//var<uniform> scene: CameraData;
+@group(0) @binding(1)
+var<uniform> lights: LightsUBO;
 
 @group(1) @binding(0)
 var colorTexture: texture_2d<f32>;
@@ -24,6 +51,7 @@ struct VertexInput {
     @location(0) position: vec3<f32>,
     @location(1) color: vec3<f32>,
     @location(2) uv: vec2<f32>,
+    @location(3) normal: vec3<f32>,
 }
 
 struct VertexOutput {
@@ -31,13 +59,18 @@ struct VertexOutput {
 
     @location(0) fragColor: vec3<f32>,
     @location(1) fragCoord: vec2<f32>,
+    @location(2) worldPosition: vec3<f32>,
+    @location(3) worldNormal: vec3<f32>,
 }
 
 @vertex
 fn vs(in: VertexInput) -> VertexOutput {
     var output = VertexOutput();
 
-    output.position = scene.view_projection * object.model * vec4<f32>(in.position, 1.0);
+    let worldPosition = object.model * vec4<f32>(in.position, 1.0);
+    output.position = scene.view_projection * worldPosition;
+    output.worldPosition = worldPosition.xyz;
+    output.worldNormal = mat3x3<f32>(object.model[0].xyz, object.model[1].xyz, object.model[2].xyz) * in.normal;
 
     output.fragColor = in.color;
     output.fragCoord = in.uv;
@@ -45,7 +78,56 @@ fn vs(in: VertexInput) -> VertexOutput {
     return output;
 }
 
+// Windowed inverse square falloff, same as Light::attenuation
+fn attenuation(distance: f32, range: f32) -> f32 {
+    let ratio = pow(distance / range, 4.0);
+    let window = clamp(1.0 - ratio, 0.0, 1.0);
+    return window * window / (distance * distance + 1.0);
+}
+
+// Lambert diffuse of every light and the sun plus the flat ambient light, scenes without any
+// light stay unlit
+fn lighting(position: vec3<f32>, normal: vec3<f32>) -> vec3<f32> {
+    let count = min(lights.count.x, MAX_LIGHTS);
+    let sunIntensity = lights.sunColorIntensity.a;
+    if (count == 0u && sunIntensity == 0.0 && lights.ambient.a == 0.0) {
+        return vec3<f32>(1.0);
+    }
+
+    var radiance = lights.ambient.rgb * lights.ambient.a;
+    let toSun = -lights.sunDirection.xyz;
+    radiance += lights.sunColorIntensity.rgb * sunIntensity * max(dot(normal, toSun), 0.0);
+    for (var i = 0u; i < count; i++) {
+        let light = lights.lights[i];
+        let toLight = light.position_range.xyz - position;
+        let distance = length(toLight);
+        let L = toLight / max(distance, 1e-4);
+
+        var intensity = light.color_intensity.a * attenuation(distance, light.position_range.w);
+        if (light.direction_kind.w == LIGHT_KIND_SPOT) {
+            let cosAngle = dot(-L, light.direction_kind.xyz);
+            intensity *= smoothstep(light.cone.y, light.cone.x, cosAngle);
+        }
+        radiance += light.color_intensity.rgb * intensity * max(dot(normal, L), 0.0);
+    }
+    return radiance;
+}
+
+// Exponential fog over the distance to the camera
+fn fog(color: vec3<f32>, position: vec3<f32>) -> vec3<f32> {
+    let density = lights.fog.a;
+    if (density <= 0.0) {
+        return color;
+    }
+    let view = mat3x3<f32>(scene.view[0].xyz, scene.view[1].xyz, scene.view[2].xyz);
+    let cameraPosition = -(transpose(view) * scene.view[3].xyz);
+    let visibility = exp(-density * distance(cameraPosition, position));
+    return mix(lights.fog.rgb, color, visibility);
+}
+
 @fragment
 fn fs(in: VertexOutput) -> @location(0) vec4<f32> {
-    return textureSample(colorTexture, colorTextureSampler, in.fragCoord);
+    let albedo = textureSample(colorTexture, colorTextureSampler, in.fragCoord);
+    let color = albedo.rgb * lighting(in.worldPosition, normalize(in.worldNormal));
+    return vec4<f32>(fog(color, in.worldPosition), albedo.a);
 }
\ No newline at end of file
@@ -0,0 +1,57 @@
+// The environment cube map behind everything, one triangle covering the screen at the depth
+// the buffer was cleared to, so only pixels no geometry covered pass the EQUAL test.
+
+struct SceneUBO {
+    view: mat4x4<f32>,
+    projection: mat4x4<f32>,
+    view_projection: mat4x4<f32>,
+}
+
+struct SkyboxParams {
+    // the clear depth, 0 with reversed z
+    depth: f32,
+    intensity: f32,
+}
+
+var<push_constant> sky: SkyboxParams;
+
+@group(0) @binding(0)
+var<uniform> scene: SceneUBO;
+
+@group(1) @binding(0)
+var environment: texture_cube<f32>;
+@group(1) @binding(1)
+var environment_sampler: sampler;
+
+struct VertexOutput {
+    @builtin(position) position: vec4<f32>,
+
+    @location(0) ndc: vec2<f32>,
+}
+
+@vertex
+fn vs(@builtin(vertex_index) index: u32) -> VertexOutput {
+    var output = VertexOutput();
+
+    let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+    output.ndc = uv * 2.0 - 1.0;
+    output.position = vec4<f32>(output.ndc, sky.depth, 1.0);
+
+    return output;
+}
+
+@fragment
+fn fs(in: VertexOutput) -> @location(0) vec4<f32> {
+    // view space ray through the pixel, undoing the perspective scale and its y flip
+    let view_ray = vec3<f32>(
+        in.ndc.x / scene.projection[0][0],
+        in.ndc.y / scene.projection[1][1],
+        -1.0,
+    );
+    // the view rotation is orthonormal, its inverse is the transpose
+    let rotation = mat3x3<f32>(scene.view[0].xyz, scene.view[1].xyz, scene.view[2].xyz);
+    let direction = transpose(rotation) * view_ray;
+
+    let color = textureSample(environment, environment_sampler, direction).rgb;
+    return vec4<f32>(color * sky.intensity, 1.0);
+}
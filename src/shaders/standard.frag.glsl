@@ -0,0 +1,313 @@
+#version 450
+
+// Template of the standard shader, see standard.vert.glsl for the hook markers.
+
+// Keep in sync with MAX_LIGHTS in camera_uniforms.rs
+#define MAX_LIGHTS 16
+#define LIGHT_KIND_POINT 0.0
+#define LIGHT_KIND_SPOT 1.0
+// Keep in sync with CLUSTERS and MAX_LIGHTS_PER_CLUSTER in light_clusters.rs
+#define CLUSTERS_X 16u
+#define CLUSTERS_Y 9u
+#define CLUSTERS_Z 24u
+#define MAX_LIGHTS_PER_CLUSTER 127u
+// Keep in sync with BRDF_LUT_SIZE in ibl_baker.rs
+#define BRDF_LUT_SIZE 128.0
+// materials have no roughness or metalness yet, every surface is a fairly rough dielectric
+#define ENVIRONMENT_ROUGHNESS 0.5
+#define ENVIRONMENT_F0 vec3(0.04)
+
+layout(set = 0, binding = 0) uniform SceneUBO {
+    mat4 view;
+    mat4 projection;
+    mat4 view_projection;
+} scene;
+
+struct Light {
+    vec4 position_range;
+    vec4 color_intensity;
+    vec4 direction_kind;
+    vec4 cone;
+    // xy offset, zw scale of the light's tile in the shadow atlas, 0 without shadows
+    vec4 shadow;
+    // world to the light's shadow clip space, a point light's cube faces, see cubeShadowFactor
+    mat4 shadowMatrix;
+};
+
+layout(set = 0, binding = 1) uniform LightsUBO {
+    // x lights, y mip levels of the specular environment, 0 without environment lighting
+    uvec4 count;
+    Light lights[MAX_LIGHTS];
+    // rgb color, a intensity
+    vec4 ambient;
+    // rgb color, a exponential density, 0 without fog
+    vec4 fog;
+    // xyz the way the sunlight travels
+    vec4 sunDirection;
+    // rgb color, a intensity, 0 without sun
+    vec4 sunColorIntensity;
+    // x near, y far depth of the cluster slices, z 1 for linear slices, w clustered lights, 0
+    // without clustering
+    vec4 clusters;
+} sceneLights;
+
+layout(set = 0, binding = 2) uniform texture2D noiseTexture;
+layout(set = 0, binding = 3) uniform sampler noiseSampler;
+
+layout(set = 0, binding = 4) uniform textureCube irradianceTexture;
+layout(set = 0, binding = 5) uniform sampler irradianceSampler;
+layout(set = 0, binding = 6) uniform textureCube specularTexture;
+layout(set = 0, binding = 7) uniform sampler specularSampler;
+layout(set = 0, binding = 8) uniform texture2D brdfLutTexture;
+layout(set = 0, binding = 9) uniform sampler brdfLutSampler;
+
+// every light of the view, see LightClusters
+layout(std430, set = 0, binding = 14) readonly buffer ClusterLights {
+    Light clusterLights[];
+};
+// per cluster its light count followed by MAX_LIGHTS_PER_CLUSTER indices into clusterLights
+layout(std430, set = 0, binding = 15) readonly buffer ClusterGrid {
+    uint clusterGrid[];
+};
+
+// depth of the shadow casting lights, see ShadowRenderer
+layout(set = 0, binding = 16) uniform texture2D shadowAtlas;
+layout(set = 0, binding = 17) uniform samplerShadow shadowSampler;
+
+// time, screen size, jitter, camera params and blue noise for the hooks
+#include "engine_globals.glsl"
+
+// Keep in sync with ObjectData in forward_renderer.rs
+layout(push_constant) uniform ObjectPushConstants {
+    mat4 model;
+    // x amount, y edge width, z noise scale
+    vec4 dissolve;
+    // rgb edge glow
+    vec4 dissolveEdge;
+} object;
+
+layout(set = 1, binding = 0) uniform texture2D colorTexture;
+layout(set = 1, binding = 1) uniform sampler colorTextureSampler;
+
+layout(location = 0) in vec3 fragColor;
+layout(location = 1) in vec2 fragCoord;
+layout(location = 2) in vec3 fragWorldPosition;
+layout(location = 3) in vec3 fragWorldNormal;
+
+layout(location = 0) out vec4 outColor;
+
+vec3 albedo_modify(vec3 albedo, vec2 uv) {
+//#hook albedo_modify
+    return albedo;
+//#end
+}
+
+vec3 emissive_add(vec2 uv) {
+//#hook emissive_add
+    return vec3(0.0);
+//#end
+}
+
+// Windowed inverse square falloff, same as Light::attenuation
+float attenuation(float distance, float range) {
+    float ratio = pow(distance / range, 4.0);
+    float window = clamp(1.0 - ratio, 0.0, 1.0);
+    return window * window / (distance * distance + 1.0);
+}
+
+// Compares the fragment's depth in a tile of the shadow atlas, `tile` xy offset, zw scale
+float sampleShadowTile(vec4 tile, vec3 ndc) {
+    // half a texel in, the filtering must not reach into the neighbouring tiles
+    vec2 atlasSize = vec2(textureSize(sampler2DShadow(shadowAtlas, shadowSampler), 0));
+    vec2 margin = 0.5 / (atlasSize * tile.zw);
+    vec2 uv = clamp(ndc.xy * 0.5 + 0.5, margin, 1.0 - margin);
+    vec2 atlasUv = tile.xy + uv * tile.zw;
+    return texture(sampler2DShadow(shadowAtlas, shadowSampler), vec3(atlasUv, ndc.z));
+}
+
+// The cube face of a point light the fragment falls in, the one of its major axis. Its projection
+// is rebuilt like ShadowRenderer::light_view_projections does for CUBE_FACES, the shadow matrix
+// holds the face offsets and the depth range, see LightData::set_cube_shadow.
+float cubeShadowFactor(Light light, vec3 position) {
+    vec3 toFragment = position - light.position_range.xyz;
+    vec3 axis = abs(toFragment);
+    int face;
+    vec3 forward;
+    vec3 up = vec3(0.0, 1.0, 0.0);
+    if (axis.x >= axis.y && axis.x >= axis.z) {
+        face = toFragment.x > 0.0 ? 0 : 1;
+        forward = vec3(toFragment.x > 0.0 ? 1.0 : -1.0, 0.0, 0.0);
+    } else if (axis.y >= axis.z) {
+        face = toFragment.y > 0.0 ? 2 : 3;
+        forward = vec3(0.0, toFragment.y > 0.0 ? 1.0 : -1.0, 0.0);
+        up = vec3(1.0, 0.0, 0.0);
+    } else {
+        face = toFragment.z > 0.0 ? 4 : 5;
+        forward = vec3(0.0, 0.0, toFragment.z > 0.0 ? 1.0 : -1.0);
+    }
+
+    // Mat4::look_at_rh, then Mat4::perspective_rh with a 90 degree field of view
+    vec3 z = -forward;
+    vec3 x = normalize(cross(up, z));
+    vec3 y = cross(z, x);
+    vec3 view = vec3(dot(x, toFragment), dot(y, toFragment), dot(z, toFragment));
+    float near = light.shadowMatrix[3].x;
+    float far = light.shadowMatrix[3].y;
+    vec4 clip = vec4(view.x, -view.y, (view.z * far + near * far) / (near - far), -view.z);
+    if (clip.w <= 0.0) {
+        return 1.0;
+    }
+
+    vec4 offsets = face < 2 ? light.shadowMatrix[0]
+        : (face < 4 ? light.shadowMatrix[1] : light.shadowMatrix[2]);
+    vec2 offset = (face & 1) == 0 ? offsets.xy : offsets.zw;
+    return sampleShadowTile(vec4(offset, light.shadow.zw), clip.xyz / clip.w);
+}
+
+// 1 where the light reaches the fragment, 0 where a caster in its tile of the shadow atlas is
+// closer. Lights without a tile cast no shadow
+float shadowFactor(Light light, vec3 position) {
+    if (light.shadow.z == 0.0) {
+        return 1.0;
+    }
+    if (light.direction_kind.w == LIGHT_KIND_POINT) {
+        return cubeShadowFactor(light, position);
+    }
+    vec4 clip = light.shadowMatrix * vec4(position, 1.0);
+    if (clip.w <= 0.0) {
+        return 1.0;
+    }
+    return sampleShadowTile(light.shadow, clip.xyz / clip.w);
+}
+
+// Lambert diffuse of a point or spot light
+vec3 lightRadiance(Light light, vec3 position, vec3 normal) {
+    vec3 toLight = light.position_range.xyz - position;
+    float distance = length(toLight);
+    vec3 L = toLight / max(distance, 1e-4);
+
+    float intensity = light.color_intensity.a * attenuation(distance, light.position_range.w);
+    if (light.direction_kind.w == LIGHT_KIND_SPOT) {
+        float cosAngle = dot(-L, light.direction_kind.xyz);
+        intensity *= smoothstep(light.cone.y, light.cone.x, cosAngle);
+    }
+    if (intensity > 0.0) {
+        intensity *= shadowFactor(light, position);
+    }
+    return light.color_intensity.rgb * intensity * max(dot(normal, L), 0.0);
+}
+
+// Where the fragment's light list starts in clusterGrid, the tile of the framebuffer and the
+// depth slice, see light_cluster.wgsl
+uint clusterStart(vec3 position) {
+    float depth = -(scene.view * vec4(position, 1.0)).z;
+    float near = sceneLights.clusters.x;
+    float far = sceneLights.clusters.y;
+    float slice = sceneLights.clusters.z > 0.0
+        ? (depth - near) / (far - near)
+        : log(max(depth, near) / near) / log(far / near);
+    uint z = uint(clamp(slice * float(CLUSTERS_Z), 0.0, float(CLUSTERS_Z - 1u)));
+    vec2 tile = gl_FragCoord.xy * globals.screen.zw * vec2(CLUSTERS_X, CLUSTERS_Y);
+    uint x = min(uint(tile.x), CLUSTERS_X - 1u);
+    uint y = min(uint(tile.y), CLUSTERS_Y - 1u);
+    return ((z * CLUSTERS_Y + y) * CLUSTERS_X + x) * (MAX_LIGHTS_PER_CLUSTER + 1u);
+}
+
+// Lambert diffuse of the lights of the fragment's cluster, or of every uniform light without
+// clustering, and the sun plus the flat ambient light. Scenes without any light or environment
+// stay unlit
+vec3 lighting(vec3 position, vec3 normal) {
+    uint count = min(sceneLights.count.x, uint(MAX_LIGHTS));
+    uint clustered = uint(sceneLights.clusters.w);
+    float sunIntensity = sceneLights.sunColorIntensity.a;
+    if (count == 0u && clustered == 0u && sceneLights.count.y == 0u && sunIntensity == 0.0
+        && sceneLights.ambient.a == 0.0) {
+        return vec3(1.0);
+    }
+
+    vec3 radiance = sceneLights.ambient.rgb * sceneLights.ambient.a;
+    vec3 toSun = -sceneLights.sunDirection.xyz;
+    radiance += sceneLights.sunColorIntensity.rgb * sunIntensity * max(dot(normal, toSun), 0.0);
+    if (clustered > 0u) {
+        uint start = clusterStart(position);
+        uint clusterCount = clusterGrid[start];
+        for (uint i = 0u; i < clusterCount; i++) {
+            radiance += lightRadiance(clusterLights[clusterGrid[start + 1u + i]], position, normal);
+        }
+        return radiance;
+    }
+    for (uint i = 0u; i < count; i++) {
+        radiance += lightRadiance(sceneLights.lights[i], position, normal);
+    }
+    return radiance;
+}
+
+// Ambient light of the environment, diffuse irradiance plus the split sum specular of the
+// prefiltered cube and the BRDF LUT, see IblBaker
+vec3 environment(vec3 albedo, vec3 position, vec3 normal) {
+    uint specularMips = sceneLights.count.y;
+    if (specularMips == 0u) {
+        return vec3(0.0);
+    }
+
+    vec3 cameraPosition = -transpose(mat3(scene.view)) * scene.view[3].xyz;
+    vec3 V = normalize(cameraPosition - position);
+    vec3 R = reflect(-V, normal);
+    float NdotV = max(dot(normal, V), 0.0);
+
+    // Schlick with roughness, rough surfaces reflect less at grazing angles
+    vec3 F0 = ENVIRONMENT_F0;
+    vec3 F = F0 + (max(vec3(1.0 - ENVIRONMENT_ROUGHNESS), F0) - F0) * pow(1.0 - NdotV, 5.0);
+
+    vec3 irradiance = texture(samplerCube(irradianceTexture, irradianceSampler), normal).rgb;
+    float lod = ENVIRONMENT_ROUGHNESS * float(specularMips - 1u);
+    vec3 prefiltered = textureLod(samplerCube(specularTexture, specularSampler), R, lod).rgb;
+    // the sampler repeats, stay half a texel inside the LUT
+    float halfTexel = 0.5 / BRDF_LUT_SIZE;
+    vec2 lutCoord = clamp(vec2(NdotV, ENVIRONMENT_ROUGHNESS), halfTexel, 1.0 - halfTexel);
+    vec2 brdf = texture(sampler2D(brdfLutTexture, brdfLutSampler), lutCoord).rg;
+
+    return (1.0 - F) * albedo * irradiance + prefiltered * (F * brdf.x + brdf.y);
+}
+
+// Exponential fog over the distance to the camera
+vec3 fog(vec3 color, vec3 position) {
+    float density = sceneLights.fog.a;
+    if (density <= 0.0) {
+        return color;
+    }
+    vec3 cameraPosition = -transpose(mat3(scene.view)) * scene.view[3].xyz;
+    float visibility = exp(-density * distance(cameraPosition, position));
+    return mix(sceneLights.fog.rgb, color, visibility);
+}
+
+// Coverage left by the dissolve, the cut is antialiased over about a pixel so alpha to coverage
+// smooths it under MSAA. Surfaces just behind the cut glow.
+float dissolve(vec2 uv, out vec3 glow) {
+    glow = vec3(0.0);
+    if (object.dissolve.x <= 0.0) {
+        return 1.0;
+    }
+
+    float noise = texture(sampler2D(noiseTexture, noiseSampler), uv * object.dissolve.z).r;
+    // the noise never quite reaches 0 or 1, amount 1 still has to remove everything
+    float edge = noise - mix(-0.01, 1.01, object.dissolve.x);
+    glow = object.dissolveEdge.rgb * (1.0 - smoothstep(0.0, max(object.dissolve.y, 1e-4), edge));
+    return clamp(edge / max(fwidth(edge), 1e-4) + 0.5, 0.0, 1.0);
+}
+
+void main() {
+    vec3 glow;
+    float coverage = dissolve(fragCoord, glow);
+    if (coverage <= 0.0) {
+        discard;
+    }
+
+    vec4 albedo = texture(sampler2D(colorTexture, colorTextureSampler), fragCoord);
+    vec3 normal = normalize(fragWorldNormal);
+    vec3 baseColor = albedo_modify(albedo.rgb, fragCoord);
+    vec3 color = baseColor * lighting(fragWorldPosition, normal)
+        + environment(baseColor, fragWorldPosition, normal);
+    color = fog(color + emissive_add(fragCoord) + glow, fragWorldPosition);
+    outColor = vec4(color, albedo.a * coverage);
+}
@@ -0,0 +1,48 @@
+#version 450
+
+// Template of the standard shader, compiled at runtime after material hooks are stitched in.
+// Code between `//#hook <name>` and `//#end` is the default body, replaced by the material snippet.
+
+layout(set = 0, binding = 0) uniform SceneUBO {
+    mat4 view;
+    mat4 projection;
+    mat4 view_projection;
+} scene;
+
+// time, screen size, jitter, camera params and blue noise for the hooks
+#include "engine_globals.glsl"
+
+// Keep in sync with ObjectData in forward_renderer.rs
+layout(push_constant) uniform ObjectPushConstants {
+    mat4 model;
+    vec4 dissolve;
+    vec4 dissolveEdge;
+} object;
+
+layout(location = 0) in vec3 inPosition;
+layout(location = 1) in vec3 inColor;
+layout(location = 2) in vec2 inUV;
+layout(location = 3) in vec3 inNormal;
+
+layout(location = 0) out vec3 fragColor;
+layout(location = 1) out vec2 fragCoord;
+layout(location = 2) out vec3 fragWorldPosition;
+layout(location = 3) out vec3 fragWorldNormal;
+
+// object space offset added to the vertex position
+vec3 vertex_offset(vec3 position, vec3 normal, vec2 uv) {
+//#hook vertex_offset
+    return vec3(0.0);
+//#end
+}
+
+void main() {
+    vec3 position = inPosition + vertex_offset(inPosition, inNormal, inUV);
+    vec4 worldPosition = object.model * vec4(position, 1.0);
+    gl_Position = scene.view_projection * worldPosition;
+
+    fragColor = inColor;
+    fragCoord = inUV;
+    fragWorldPosition = worldPosition.xyz;
+    fragWorldNormal = mat3(object.model) * inNormal;
+}
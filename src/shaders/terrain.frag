@@ -0,0 +1,12 @@
+#version 450
+
+layout(set = 1, binding = 0) uniform texture2D colorTexture;
+layout(set = 1, binding = 1) uniform sampler colorTextureSampler;
+
+layout(location = 0) in vec2 inUV;
+
+layout(location = 0) out vec4 outColor;
+
+void main() {
+    outColor = texture(sampler2D(colorTexture, colorTextureSampler), inUV);
+}
@@ -0,0 +1,27 @@
+#version 450
+
+layout(set = 0, binding = 0) uniform SceneUBO {
+    mat4 view;
+    mat4 projection;
+    mat4 view_projection;
+} scene;
+
+layout(push_constant) uniform ObjectPushConstants {
+    mat4 model;
+} object;
+
+layout(location = 0) in vec3 inPosition;
+layout(location = 1) in vec3 inColor;
+layout(location = 2) in vec2 inUV;
+
+layout(location = 0) out vec2 outUV;
+layout(location = 1) out vec3 outWorldPosition;
+
+void main() {
+    vec4 worldPosition = object.model * vec4(inPosition, 1.0);
+
+    outUV = inUV;
+    outWorldPosition = worldPosition.xyz;
+    // Only used when the tessellation stages are unavailable and the pipeline falls back to vert/frag.
+    gl_Position = scene.view_projection * worldPosition;
+}
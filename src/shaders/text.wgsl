@@ -0,0 +1,56 @@
+// Glyph quads over the final image. Positions are in clip space already, screen and world space
+// text are both placed on the CPU. Colors are linear, the atlas holds the glyph coverage in alpha.
+
+struct TextParams {
+    // 1 when the target is an sRGB format and does the encoding itself
+    srgb_target: u32,
+}
+
+var<push_constant> params: TextParams;
+
+struct VertexInput {
+    @location(0) position: vec4<f32>,
+    @location(1) uv: vec2<f32>,
+    @location(2) color: vec4<f32>,
+}
+
+struct VertexOutput {
+    @builtin(position) position: vec4<f32>,
+    @location(0) uv: vec2<f32>,
+    @location(1) color: vec4<f32>,
+}
+
+@group(0) @binding(0)
+var atlas_texture: texture_2d<f32>;
+@group(0) @binding(1)
+var atlas_sampler: sampler;
+
+fn srgb_from_linear(linear: vec3<f32>) -> vec3<f32> {
+    let cutoff = linear < vec3<f32>(0.0031308);
+    let lower = linear * vec3<f32>(12.92);
+    let higher = vec3<f32>(1.055) * pow(linear, vec3<f32>(1.0 / 2.4)) - vec3<f32>(0.055);
+    return select(higher, lower, cutoff);
+}
+
+@vertex
+fn vs(in: VertexInput) -> VertexOutput {
+    var output = VertexOutput();
+
+    // w of world space text keeps the atlas lookup perspective correct
+    output.position = in.position;
+    output.uv = in.uv;
+    output.color = in.color;
+
+    return output;
+}
+
+@fragment
+fn fs(in: VertexOutput) -> @location(0) vec4<f32> {
+    let alpha = in.color.a * textureSample(atlas_texture, atlas_sampler, in.uv).a;
+    var color = in.color.rgb;
+    if params.srgb_target == 0u {
+        color = srgb_from_linear(color);
+    }
+    // premultiplied, blended like the egui pass
+    return vec4<f32>(color * alpha, alpha);
+}
@@ -0,0 +1,37 @@
+// I420 planes of a video frame to RGB, rendered into the texture of a `VideoTexture`.
+
+struct FragmentInput {
+    @location(0) uv: vec2<f32>,
+}
+
+@group(0) @binding(0)
+var luma: texture_2d<f32>;
+@group(0) @binding(1)
+var chroma_u: texture_2d<f32>;
+@group(0) @binding(2)
+var chroma_v: texture_2d<f32>;
+@group(0) @binding(3)
+var plane_sampler: sampler;
+
+fn srgb_to_linear(color: vec3<f32>) -> vec3<f32> {
+    let low = color / 12.92;
+    let high = pow((color + 0.055) / 1.055, vec3<f32>(2.4));
+    return select(high, low, color <= vec3<f32>(0.04045));
+}
+
+@fragment
+fn fs(in: FragmentInput) -> @location(0) vec4<f32> {
+    // BT.709 limited range, luma in 16..235 and chroma in 16..240
+    let y = (textureSample(luma, plane_sampler, in.uv).r - 16.0 / 255.0) * (255.0 / 219.0);
+    let u = (textureSample(chroma_u, plane_sampler, in.uv).r - 128.0 / 255.0) * (255.0 / 224.0);
+    let v = (textureSample(chroma_v, plane_sampler, in.uv).r - 128.0 / 255.0) * (255.0 / 224.0);
+
+    let rgb = vec3<f32>(
+        y + 1.5748 * v,
+        y - 0.1873 * u - 0.4681 * v,
+        y + 1.8556 * u,
+    );
+    // the target is sRGB, which encodes it back to the video's gamma on store
+    let color = srgb_to_linear(clamp(rgb, vec3<f32>(0.0), vec3<f32>(1.0)));
+    return vec4<f32>(color, 1.0);
+}
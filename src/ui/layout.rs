@@ -0,0 +1,159 @@
+use crate::math::Vec2;
+use winit::window::Window;
+
+// Where a node sits along one axis of the viewport. Stretch fills the axis minus the margins.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Align {
+    Start,
+    Center,
+    End,
+    Stretch,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Anchor {
+    pub x: Align,
+    pub y: Align,
+}
+
+impl Anchor {
+    pub const TOP_LEFT: Self = Self::new(Align::Start, Align::Start);
+    pub const TOP: Self = Self::new(Align::Center, Align::Start);
+    pub const TOP_RIGHT: Self = Self::new(Align::End, Align::Start);
+    pub const LEFT: Self = Self::new(Align::Start, Align::Center);
+    pub const CENTER: Self = Self::new(Align::Center, Align::Center);
+    pub const RIGHT: Self = Self::new(Align::End, Align::Center);
+    pub const BOTTOM_LEFT: Self = Self::new(Align::Start, Align::End);
+    pub const BOTTOM: Self = Self::new(Align::Center, Align::End);
+    pub const BOTTOM_RIGHT: Self = Self::new(Align::End, Align::End);
+    pub const STRETCH: Self = Self::new(Align::Stretch, Align::Stretch);
+
+    pub const fn new(x: Align, y: Align) -> Self {
+        Self { x, y }
+    }
+}
+
+impl Default for Anchor {
+    fn default() -> Self {
+        Self::TOP_LEFT
+    }
+}
+
+// Distance to the viewport edges in logical pixels, only the ones on the anchored side(s) are used.
+#[derive(Debug, Copy, Clone, Default, PartialEq)]
+pub struct Margins {
+    pub left: f32,
+    pub top: f32,
+    pub right: f32,
+    pub bottom: f32,
+}
+
+impl Margins {
+    pub fn new(left: f32, top: f32, right: f32, bottom: f32) -> Self {
+        Self {
+            left,
+            top,
+            right,
+            bottom,
+        }
+    }
+
+    pub fn all(margin: f32) -> Self {
+        Self::new(margin, margin, margin, margin)
+    }
+}
+
+// In physical pixels, origin at the top left of the window.
+#[derive(Debug, Copy, Clone, Default, PartialEq)]
+pub struct UiRect {
+    pub x: f32,
+    pub y: f32,
+    pub width: f32,
+    pub height: f32,
+}
+
+impl UiRect {
+    pub fn contains(&self, point: Vec2) -> bool {
+        point.x >= self.x
+            && point.y >= self.y
+            && point.x < self.x + self.width
+            && point.y < self.y + self.height
+    }
+
+    pub fn area(&self) -> f32 {
+        self.width * self.height
+    }
+}
+
+// Physical size of the window and the DPI scale between logical and physical pixels.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Viewport {
+    pub width: u32,
+    pub height: u32,
+    pub scale_factor: f32,
+}
+
+impl Viewport {
+    pub fn from_window(window: &Window) -> Self {
+        let size = window.inner_size();
+        Self {
+            width: size.width,
+            height: size.height,
+            scale_factor: window.scale_factor() as f32,
+        }
+    }
+
+    // Logical size, what layouts are written against.
+    pub fn logical_size(&self) -> (f32, f32) {
+        (
+            self.width as f32 / self.scale_factor,
+            self.height as f32 / self.scale_factor,
+        )
+    }
+
+    // Position and extent along one axis, in logical pixels.
+    fn resolve_axis(
+        align: Align,
+        available: f32,
+        size: f32,
+        margin_start: f32,
+        margin_end: f32,
+    ) -> (f32, f32) {
+        match align {
+            Align::Start => (margin_start, size),
+            Align::Center => ((available - size + margin_start - margin_end) * 0.5, size),
+            Align::End => (available - size - margin_end, size),
+            Align::Stretch => (
+                margin_start,
+                (available - margin_start - margin_end).max(0.0),
+            ),
+        }
+    }
+
+    // Snapped to whole physical pixels so sprites and glyphs stay crisp at any scale factor.
+    pub fn resolve(&self, anchor: Anchor, width: f32, height: f32, margins: Margins) -> UiRect {
+        let (available_width, available_height) = self.logical_size();
+        let (x, width) = Self::resolve_axis(
+            anchor.x,
+            available_width,
+            width,
+            margins.left,
+            margins.right,
+        );
+        let (y, height) = Self::resolve_axis(
+            anchor.y,
+            available_height,
+            height,
+            margins.top,
+            margins.bottom,
+        );
+
+        let scale = self.scale_factor;
+        UiRect {
+            x: (x * scale).round(),
+            y: (y * scale).round(),
+            width: (width * scale).round(),
+            height: (height * scale).round(),
+        }
+    }
+}
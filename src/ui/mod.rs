@@ -0,0 +1,5 @@
+mod layout;
+mod ui_node;
+
+pub use layout::{Align, Anchor, Margins, UiRect, Viewport};
+pub use ui_node::{hit_test_ui, layout_ui, UiNode};
@@ -0,0 +1,60 @@
+use super::{Anchor, Margins, UiRect, Viewport};
+use crate::math::Vec2;
+use crate::scene::{Comp, Entity, Query, World};
+
+// A 2D element (sprite, text) of the HUD layer, placed relative to the viewport instead of
+// at a fixed pixel position. `rect` is recomputed by `layout_ui`, sizes are in logical pixels.
+#[derive(Debug, Copy, Clone, Default)]
+pub struct UiNode {
+    pub anchor: Anchor,
+    pub margins: Margins,
+    // ignored along stretched axes
+    pub width: f32,
+    pub height: f32,
+    // decorations like crosshairs let the pointer through to whatever is below
+    pub pass_through: bool,
+
+    pub rect: UiRect,
+}
+
+impl Comp for UiNode {}
+
+impl UiNode {
+    pub fn new(anchor: Anchor, width: f32, height: f32) -> Self {
+        Self {
+            anchor,
+            width,
+            height,
+            ..Default::default()
+        }
+    }
+
+    pub fn with_margins(mut self, margins: Margins) -> Self {
+        self.margins = margins;
+        self
+    }
+
+    pub fn with_pass_through(mut self) -> Self {
+        self.pass_through = true;
+        self
+    }
+}
+
+// Cheap enough to run every frame, so resizes and DPI changes are picked up without any bookkeeping.
+pub fn layout_ui(world: &mut World, viewport: Viewport) {
+    let query = Query::<&mut UiNode>::new(world);
+    for node in query {
+        node.rect = viewport.resolve(node.anchor, node.width, node.height, node.margins);
+    }
+}
+
+// Node under the pointer, the smallest one when they overlap so a button wins over the panel it sits on.
+pub fn hit_test_ui(world: &World, point: Vec2) -> Option<Entity> {
+    world
+        .ordered_entities()
+        .into_iter()
+        .filter_map(|entity| Some((entity, world.get_entity_comp::<UiNode>(entity)?)))
+        .filter(|(_, node)| !node.pass_through && node.rect.contains(point))
+        .min_by(|(_, a), (_, b)| a.rect.area().total_cmp(&b.rect.area()))
+        .map(|(entity, _)| entity)
+}
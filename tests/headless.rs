@@ -0,0 +1,86 @@
+// Renders offscreen with a real device, run with `cargo test -- --ignored` where Vulkan is
+// available.
+use mirage::ash::vk;
+use mirage::{Mirage, MirageConfig, RawResource};
+use std::cell::Cell;
+use std::rc::Rc;
+
+const WIDTH: u32 = 64;
+const HEIGHT: u32 = 48;
+
+#[test]
+#[ignore]
+fn read_back_frame_has_the_offscreen_size() {
+    let mut mirage = Mirage::new_headless(WIDTH, HEIGHT);
+    assert!(mirage.read_back_frame().is_none());
+
+    mirage.render();
+    let (pixels, width, height) = mirage
+        .read_back_frame()
+        .expect("failed to read back frame!");
+    assert_eq!((width, height), (WIDTH, HEIGHT));
+    assert_eq!(pixels.len(), (WIDTH * HEIGHT * 4) as usize);
+
+    mirage.resize_offscreen(WIDTH * 2, HEIGHT);
+    mirage.render();
+    let (pixels, width, _) = mirage
+        .read_back_frame()
+        .expect("failed to read back frame!");
+    assert_eq!(width, WIDTH * 2);
+    assert_eq!(pixels.len(), (WIDTH * 2 * HEIGHT * 4) as usize);
+}
+
+#[test]
+#[ignore]
+fn setup_registers_hooks_and_ui_run_every_frame() {
+    let updates = Rc::new(Cell::new(0));
+    let renders = Rc::new(Cell::new(0));
+    let uis = Rc::new(Cell::new(0));
+    let config = MirageConfig::default().with_setup({
+        let (updates, renders, uis) = (updates.clone(), renders.clone(), uis.clone());
+        move |mirage| {
+            let updates = updates.clone();
+            mirage.on_pre_update(move |_| updates.set(updates.get() + 1));
+            let renders = renders.clone();
+            mirage.on_post_render(move |_| renders.set(renders.get() + 1));
+            let uis = uis.clone();
+            mirage.ui(move |_| uis.set(uis.get() + 1));
+        }
+    });
+
+    let mut mirage = Mirage::headless_with_config(WIDTH, HEIGHT, &config);
+    mirage.render();
+    mirage.render();
+    assert_eq!(updates.get(), 2);
+    assert_eq!(renders.get(), 2);
+    // egui may run a pass again when a layout asks for it
+    assert!(uis.get() >= 2);
+}
+
+#[test]
+#[ignore]
+fn raw_objects_are_released_through_the_deletion_queue() {
+    let mut mirage = Mirage::new_headless(WIDTH, HEIGHT);
+    let gpu = mirage.get_gpu();
+    let raw = gpu.raw();
+    let create_info = vk::BufferCreateInfo::default()
+        .size(256)
+        .usage(vk::BufferUsageFlags::STORAGE_BUFFER);
+    let buffer =
+        unsafe { raw.device.create_buffer(&create_info, None) }.expect("failed to create buffer!");
+    gpu.defer_destroy(RawResource::Buffer(buffer));
+
+    // the frames in flight go by and flush the queue, dropping the GPU destroys the rest
+    mirage.render();
+    mirage.render();
+    mirage.render();
+}
+
+#[test]
+#[ignore]
+fn run_headless_reads_back_the_last_frame() {
+    let (pixels, width, height) = mirage::run_headless(WIDTH, HEIGHT, 2, MirageConfig::default())
+        .expect("failed to read back frame!");
+    assert_eq!((width, height), (WIDTH, HEIGHT));
+    assert_eq!(pixels.len(), (WIDTH * HEIGHT * 4) as usize);
+}